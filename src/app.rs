@@ -1,4 +1,5 @@
 use std::sync::Arc;
+use anyhow::Context;
 use winit::{
     application::ApplicationHandler,
     event::*,
@@ -8,20 +9,51 @@ use winit::{
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
+use crate::simulation_config::SimulationConfig;
 use crate::state::State;
+use crate::utils::input_log::{InputLog, InputSession};
+
+/// Command line option with the path of a config file, see `SimulationConfig::from_toml_str`.
+const CONFIG_ARG: &str = "--config";
+/// Command line option starting the embedded demo scene instead, see `scenes::demo`.
+const DEMO_ARG: &str = "--demo";
+/// Command line option measuring the kernel workgroup sizes on the adapter, or loading the ones measured before,
+/// see `kernel_autotune::load_or_autotune`.
+const AUTOTUNE_ARG: &str = "--autotune";
+/// Command line option waiting for the queue to drain after every refresh of a spawn, to log how long it took,
+/// see `WgpuContext::set_measure_queue_drain`.
+const MEASURE_QUEUE_DRAIN_ARG: &str = "--measure-queue-drain";
+/// Command line option with the path the input of the run is logged to, see `InputLog`.
+const RECORD_INPUT_ARG: &str = "--record-input";
+/// Command line option with the path of an input log to replay.
+const REPLAY_ARG: &str = "--replay";
 
 pub struct App {
     #[cfg(target_arch = "wasm32")]
     proxy: Option<winit::event_loop::EventLoopProxy<State>>,
     state: Option<State>,
+    config: SimulationConfig,
+    /// Replaces `config` with the demo scene once the adapter is known
+    demo: bool,
+    /// Tunes the kernels before creating the simulation
+    autotune: bool,
+    /// Stalls after every spawn refresh to time the queue drain
+    measure_queue_drain: bool,
+    /// Taken by the state once the window exists
+    input_session: Option<InputSession>,
 }
 
 impl App {
-    pub fn new(#[cfg(target_arch = "wasm32")] event_loop: &EventLoop<State>) -> Self {
+    pub fn new(#[cfg(target_arch = "wasm32")] event_loop: &EventLoop<State>, config: SimulationConfig, demo: bool, autotune: bool, measure_queue_drain: bool, input_session: InputSession) -> Self {
         #[cfg(target_arch = "wasm32")]
         let proxy = Some(event_loop.create_proxy());
         Self {
             state: None,
+            config,
+            demo,
+            autotune,
+            measure_queue_drain,
+            input_session: Some(input_session),
             #[cfg(target_arch = "wasm32")]
             proxy,
         }
@@ -51,10 +83,11 @@ impl ApplicationHandler<State> for App {
         }
 
         let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+        let input_session = self.input_session.take().unwrap_or_default();
 
         #[cfg(not(target_arch = "wasm32"))]
         {
-            self.state = Some(pollster::block_on(State::new(window)).unwrap());
+            self.state = Some(pollster::block_on(State::new(window, self.config, self.demo, self.autotune, self.measure_queue_drain, input_session)).unwrap());
         }
 
         #[cfg(target_arch = "wasm32")]
@@ -62,10 +95,11 @@ impl ApplicationHandler<State> for App {
             // Run the future asynchronously and use the
             // proxy to send the results to the event loop
             if let Some(proxy) = self.proxy.take() {
+                let (config, demo, autotune, measure_queue_drain) = (self.config, self.demo, self.autotune, self.measure_queue_drain);
                 wasm_bindgen_futures::spawn_local(async move {
                     assert!(proxy
                         .send_event(
-                            State::new(window)
+                            State::new(window, config, demo, autotune, measure_queue_drain, input_session)
                                 .await
                                 .expect("Unable to create canvas!!!")
                         )
@@ -98,6 +132,19 @@ impl ApplicationHandler<State> for App {
         state.render_loop(&event, &event_loop);
 
     }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if let Some(state) = &mut self.state {
+            state.about_to_wait(event_loop);
+        }
+    }
+
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        // Shut down while the event loop, and so the window, still exist
+        if let Some(mut state) = self.state.take() {
+            state.shutdown();
+        }
+    }
 }
 
 
@@ -111,10 +158,20 @@ pub fn run() -> anyhow::Result<()>{
         console_log::init_with_level(log::Level::Info).unwrap_throw();
     }
 
+    let demo = std::env::args().any(|arg| arg == DEMO_ARG);
+    let autotune = std::env::args().any(|arg| arg == AUTOTUNE_ARG);
+    let measure_queue_drain = std::env::args().any(|arg| arg == MEASURE_QUEUE_DRAIN_ARG);
+    let config = if demo { SimulationConfig::default() } else { startup_config()? };
+    let input_session = startup_input_session()?;
     let event_loop = EventLoop::with_user_event().build()?;
     let mut app = App::new(
         #[cfg(target_arch = "wasm32")]
         &event_loop,
+        config,
+        demo,
+        autotune,
+        measure_queue_drain,
+        input_session,
     );
 
     event_loop.run_app(&mut app)?;
@@ -122,6 +179,41 @@ pub fn run() -> anyhow::Result<()>{
     Ok(())
 }
 
+/// The config file given with `--config <path>`, or the default config. The web build has no files.
+fn startup_config() -> anyhow::Result<SimulationConfig> {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(index) = args.iter().position(|arg| arg == CONFIG_ARG) else {
+        return Ok(SimulationConfig::default());
+    };
+    let path = args.get(index + 1).ok_or_else(|| anyhow::anyhow!("{} needs the path of a config file", CONFIG_ARG))?;
+    let config = SimulationConfig::load(path).with_context(|| format!("Loading the config {}", path))?;
+    log::info!("Loaded the config {}: {} particles in a {} m world", path, config.num_particles, config.world_size);
+    Ok(config)
+}
+
+/// Logs the input with `--record-input <path>`, or replays a log with `--replay <path>`. The replay needs the
+/// config of the recorded run.
+fn startup_input_session() -> anyhow::Result<InputSession> {
+    let args: Vec<String> = std::env::args().collect();
+    let path_of = |option: &str| -> anyhow::Result<Option<String>> {
+        let Some(index) = args.iter().position(|arg| arg == option) else {
+            return Ok(None);
+        };
+        let path = args.get(index + 1).ok_or_else(|| anyhow::anyhow!("{} needs the path of an input log", option))?;
+        Ok(Some(path.clone()))
+    };
+    match (path_of(RECORD_INPUT_ARG)?, path_of(REPLAY_ARG)?) {
+        (Some(_), Some(_)) => Err(anyhow::anyhow!("{} and {} can not be combined", RECORD_INPUT_ARG, REPLAY_ARG)),
+        (Some(path), None) => Ok(InputSession::recording(path.into())),
+        (None, Some(path)) => {
+            let log = InputLog::load(std::path::Path::new(&path)).with_context(|| format!("Loading the input log {}", path))?;
+            log::info!("Replaying {} frames of {}", log.frames().len(), path);
+            Ok(InputSession::replaying(log))
+        }
+        (None, None) => Ok(InputSession::Live),
+    }
+}
+
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen(start)]
 pub fn run_web() -> Result<(), wasm_bindgen::JsValue> {
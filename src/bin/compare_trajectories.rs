@@ -0,0 +1,35 @@
+//! Compares two trajectory recordings of the same scene, saved with `Trajectory::save`,
+//! and reports the first frame where they diverge and the divergence of every frame.
+//!
+//! Usage: compare-trajectories <recording a> <recording b> [tolerance]
+
+use std::path::Path;
+use std::process::ExitCode;
+use game_engine::physics::trajectory::{compare_trajectories, Trajectory};
+
+const DEFAULT_TOLERANCE: f32 = 0.0;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3 || args.len() > 4 {
+        eprintln!("Usage: {} <recording a> <recording b> [tolerance]", args[0]);
+        return ExitCode::from(2);
+    }
+    let tolerance = match args.get(3).map(|tolerance| tolerance.parse::<f32>()) {
+        None => DEFAULT_TOLERANCE,
+        Some(Ok(tolerance)) => tolerance,
+        Some(Err(e)) => {
+            eprintln!("Invalid tolerance {}: {}", args[3], e);
+            return ExitCode::from(2);
+        }
+    };
+
+    let load = |path: &str| Trajectory::load(Path::new(path)).inspect_err(|e| eprintln!("Unable to load {}: {}", path, e));
+    let (Ok(a), Ok(b)) = (load(&args[1]), load(&args[2])) else {
+        return ExitCode::from(2);
+    };
+
+    let divergence = compare_trajectories(&a, &b, tolerance);
+    println!("{}", divergence);
+    if divergence.is_identical() { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
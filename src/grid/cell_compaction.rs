@@ -0,0 +1,203 @@
+use bytemuck::bytes_of;
+use wgpu::{BindGroupLayout, CommandEncoder, PushConstantRange};
+use crate::grid::grid::UNUSED_CELL_ID;
+use crate::grid::live_count::{LiveParticleCount, BUILD_CELL_IDS_ARGS_OFFSET};
+use crate::physics::collision_cell_builder::{COUNTING_CHUNK_SIZE, WORKGROUP_SIZE as CHUNK_WORKGROUP_SIZE};
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::bind_resources::BindResources;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::prefix_sum::prefix_sum::PrefixSum;
+use crate::utils::radix_sort::GPUSorter;
+
+/// num_particles of the push constants when the dispatch is driven by the live particle count.
+const INDIRECT_COUNT: u32 = u32::MAX;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstants {
+    num_particles: u32,
+}
+
+/// Buffers of the grid the compaction writes: the map and the indirect args of the kernels after it, the sort's
+/// through its sorter.
+pub struct CompactionTargets<'a> {
+    pub cell_ids: &'a GpuBuffer<u32>,
+    pub object_ids: &'a GpuBuffer<u32>,
+    pub live_count: &'a LiveParticleCount,
+    pub sorter: &'a GPUSorter,
+}
+
+/// Moves the used cell ids of the grid map to its front before the sort, see cell_compaction.wgsl.
+/// The cell id build writes `MAX_CELLS_PER_OBJECT` slots per particle into the sparse buffers, most of them
+/// unused. The cells of every particle are counted, prefix summed and scattered to the map, and a single
+/// thread writes the used cell count into the indirect args of the sort and the collision cell builder,
+/// so neither works on the unused slots and the CPU never reads the count back.
+pub struct CellCompaction {
+    sparse_cell_ids: GpuBuffer<u32>,
+    sparse_object_ids: GpuBuffer<u32>,
+    cell_counts: GpuBuffer<u32>,
+    prefix_sum: PrefixSum,
+    count_shader: ComputeShader,
+    scatter_shader: ComputeShader,
+    prepare_shader: ComputeShader,
+    bind_resources: BindResources,
+}
+
+impl CellCompaction {
+    pub fn new(wgpu_context: &WgpuContext, capacity: usize, max_cells_per_object: u32, grid_workgroup_size: u32, targets: &CompactionTargets) -> Self {
+        let buffer_len = capacity * max_cells_per_object as usize;
+        let sparse_cell_ids = GpuBuffer::new(wgpu_context, vec![UNUSED_CELL_ID; buffer_len], wgpu::BufferUsages::STORAGE);
+        let sparse_object_ids = GpuBuffer::new(wgpu_context, vec![0; buffer_len], wgpu::BufferUsages::STORAGE);
+        let cell_counts = GpuBuffer::new(wgpu_context, vec![0; capacity], wgpu::BufferUsages::STORAGE);
+        let prefix_sum = PrefixSum::new(wgpu_context, &cell_counts);
+
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let bind_group = Self::create_bind_group(wgpu_context, &bind_group_layout, &sparse_cell_ids, &sparse_object_ids, &cell_counts, targets);
+        let bind_resources = BindResources::new(bind_group_layout, bind_group);
+
+        let constants = vec![
+            ("WORKGROUP_SIZE", grid_workgroup_size as f64),
+            ("MAX_CELLS_PER_OBJECT", max_cells_per_object as f64),
+            ("CHUNK_SIZE", COUNTING_CHUNK_SIZE as f64),
+            ("CHUNK_WORKGROUP_SIZE", CHUNK_WORKGROUP_SIZE.0 as f64),
+            ("SORT_WORKGROUP_SIZE", targets.sorter.workgroup_size() as f64),
+            ("SORT_BLOCKS_PER_WORKGROUP", targets.sorter.blocks_per_workgroup() as f64),
+        ];
+        let push_constants = vec![
+            PushConstantRange {
+                stages: wgpu::ShaderStages::COMPUTE,
+                range: 0..size_of::<PushConstants>() as u32,
+            }
+        ];
+        let shader = |entry_point: &str, workgroup_size: (u32, u32, u32)| ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("cell_compaction.wgsl"),
+            entry_point,
+            &bind_resources.bind_group_layout,
+            workgroup_size,
+            &constants,
+            &push_constants,
+        );
+        let count_shader = shader("count_used_cells", (grid_workgroup_size, 1, 1));
+        let scatter_shader = shader("scatter_used_cells", (grid_workgroup_size, 1, 1));
+        let prepare_shader = shader("prepare_used_cells_dispatch", (1, 1, 1));
+
+        Self {
+            sparse_cell_ids,
+            sparse_object_ids,
+            cell_counts,
+            prefix_sum,
+            count_shader,
+            scatter_shader,
+            prepare_shader,
+            bind_resources,
+        }
+    }
+
+    /// Cell ids written by the cell id build, before the compaction.
+    pub fn sparse_cell_ids(&self) -> &GpuBuffer<u32> {
+        &self.sparse_cell_ids
+    }
+
+    pub fn sparse_object_ids(&self) -> &GpuBuffer<u32> {
+        &self.sparse_object_ids
+    }
+
+    /// Grows the buffers with the grid, and rebinds the map since growing may have replaced it.
+    pub fn refresh(&mut self, wgpu_context: &WgpuContext, capacity: usize, max_cells_per_object: u32, targets: &CompactionTargets) {
+        let added_slots = (capacity * max_cells_per_object as usize).saturating_sub(self.sparse_cell_ids.len());
+        self.sparse_cell_ids.push_all(&vec![UNUSED_CELL_ID; added_slots], wgpu_context);
+        self.sparse_object_ids.push_all(&vec![0; added_slots], wgpu_context);
+        self.cell_counts.push_all(&vec![0; capacity.saturating_sub(self.cell_counts.len())], wgpu_context);
+        self.prefix_sum.update_buffers(wgpu_context, &self.cell_counts);
+
+        self.bind_resources.bind_group = Self::create_bind_group(
+            wgpu_context,
+            &self.bind_resources.bind_group_layout,
+            &self.sparse_cell_ids,
+            &self.sparse_object_ids,
+            &self.cell_counts,
+            targets
+        );
+    }
+
+    /// Records the compaction of the cells of the first `num_particles` particles, or of the particles covered
+    /// by the live count when `live_count_args` is its buffer. The prefix sum runs over the whole capacity:
+    /// its dispatch size is recorded by the CPU, which does not know the count.
+    pub fn compact(&self, encoder: &mut CommandEncoder, num_particles: u32, live_count_args: Option<&wgpu::Buffer>) {
+        let push_constants = PushConstants {
+            num_particles: if live_count_args.is_some() { INDIRECT_COUNT } else { num_particles },
+        };
+        let dispatch = |shader: &ComputeShader, encoder: &mut CommandEncoder| match live_count_args {
+            Some(args) => shader.indirect_dispatch(encoder, args, BUILD_CELL_IDS_ARGS_OFFSET, Some(vec![(0, bytes_of(&push_constants))]), &self.bind_resources.bind_group),
+            None => shader.dispatch_by_items(encoder, (num_particles, 1, 1), Some(vec![(0, bytes_of(&push_constants))]), &self.bind_resources.bind_group),
+        };
+
+        dispatch(&self.count_shader, encoder);
+        self.prefix_sum.record(encoder, self.cell_counts.len() as u32);
+        dispatch(&self.scatter_shader, encoder);
+        self.prepare_shader.dispatch(encoder, (1, 1, 1), Some(vec![(0, bytes_of(&push_constants))]), &self.bind_resources.bind_group);
+    }
+
+    fn create_bind_group(
+        wgpu_context: &WgpuContext,
+        bind_group_layout: &BindGroupLayout,
+        sparse_cell_ids: &GpuBuffer<u32>,
+        sparse_object_ids: &GpuBuffer<u32>,
+        cell_counts: &GpuBuffer<u32>,
+        targets: &CompactionTargets,
+    ) -> wgpu::BindGroup {
+        let buffers = [
+            sparse_cell_ids.buffer(),
+            sparse_object_ids.buffer(),
+            cell_counts.buffer(),
+            targets.cell_ids.buffer(),
+            targets.object_ids.buffer(),
+            targets.live_count.args().buffer(),
+            targets.sorter.indirect_args().buffer(),
+        ];
+        let entries: Vec<wgpu::BindGroupEntry> = buffers.iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+        wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Cell compaction bind group"),
+            layout: bind_group_layout,
+            entries: &entries,
+        })
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Cell compaction bind group layout"),
+            entries: &[
+                // Sparse cell ids and object ids
+                storage_entry(0, true),
+                storage_entry(1, true),
+                // Cell counts
+                storage_entry(2, false),
+                // Map of the grid
+                storage_entry(3, false),
+                storage_entry(4, false),
+                // Live count and sort indirect args
+                storage_entry(5, false),
+                storage_entry(6, false),
+            ],
+        })
+    }
+}
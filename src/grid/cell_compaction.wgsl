@@ -0,0 +1,127 @@
+// Workgroup size of the grid kernels, the compaction reuses the dispatch size of build_cell_ids_array
+override WORKGROUP_SIZE = 64u;
+override MAX_CELLS_PER_OBJECT = 4u;
+// Chunks and workgroup size of the collision cell builder
+override CHUNK_SIZE = 4u;
+override CHUNK_WORKGROUP_SIZE = 64u;
+// Radix sort, see SortIndirectArgs::new
+override SORT_WORKGROUP_SIZE = 256u;
+override SORT_BLOCKS_PER_WORKGROUP = 45u;
+
+const UNUSED_CELL_ID = 0xffffffffu;
+// num_particles value of a dispatch driven by the live particle count
+const INDIRECT_COUNT = 0xffffffffu;
+
+struct PushConstants {
+    num_particles: u32,
+}
+
+struct LiveCount {
+    build_args: array<u32, 3>,
+    chunks_x: u32,
+    chunks_y: u32,
+    chunks_z: u32,
+    live_particles: u32,
+    previous_live_particles: u32,
+    covered_particles: u32,
+    capacity: u32,
+    used_cells: u32,
+    previous_used_cells: u32,
+};
+
+struct SortIndirectArgs {
+    x: u32,
+    y: u32,
+    z: u32,
+    num_elements: u32,
+    num_workgroups: u32,
+};
+
+var<push_constant> push_constants: PushConstants;
+
+// Written by build_cell_ids_array, the cells of particle i are in [i * MAX_CELLS_PER_OBJECT, (i + 1) * MAX_CELLS_PER_OBJECT)
+@group(0) @binding(0) var<storage, read> sparse_cell_ids: array<u32>;
+@group(0) @binding(1) var<storage, read> sparse_object_ids: array<u32>;
+// Cells used by each particle, an inclusive prefix sum of them after the prefix sum pass
+@group(0) @binding(2) var<storage, read_write> cell_counts: array<u32>;
+// The map the sort works on, the used cells first
+@group(0) @binding(3) var<storage, read_write> cell_ids: array<u32>;
+@group(0) @binding(4) var<storage, read_write> object_ids: array<u32>;
+@group(0) @binding(5) var<storage, read_write> live_count: LiveCount;
+@group(0) @binding(6) var<storage, read_write> sort_args: SortIndirectArgs;
+
+fn div_ceil(a: u32, b: u32) -> u32 {
+    return (a + b - 1u) / b;
+}
+
+/// Particles whose cells are compacted: the ones covered by build_cell_ids_array.
+fn get_num_particles() -> u32 {
+    return select(push_constants.num_particles, live_count.covered_particles, push_constants.num_particles == INDIRECT_COUNT);
+}
+
+/// Used cells of the first num_particles particles, once the counts are prefix summed.
+fn get_used_cells(num_particles: u32) -> u32 {
+    return select(0u, cell_counts[num_particles - 1u], num_particles > 0u);
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn count_used_cells(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let obj_id = global_id.x;
+    if obj_id >= get_num_particles() {
+        return;
+    }
+
+    var count = 0u;
+    for (var i = 0u; i < MAX_CELLS_PER_OBJECT; i++) {
+        count += select(0u, 1u, sparse_cell_ids[obj_id * MAX_CELLS_PER_OBJECT + i] != UNUSED_CELL_ID);
+    }
+    cell_counts[obj_id] = count;
+}
+
+/// Moves the used cells of every particle to the front of the map and clears the slots past them,
+/// so the cells left by earlier steps are not sorted again.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn scatter_used_cells(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let obj_id = global_id.x;
+    let num_particles = get_num_particles();
+    if obj_id >= num_particles {
+        return;
+    }
+
+    let used_cells = get_used_cells(num_particles);
+    let end = cell_counts[obj_id];
+    let start = select(0u, cell_counts[obj_id - 1u], obj_id > 0u);
+    for (var i = 0u; i < MAX_CELLS_PER_OBJECT; i++) {
+        let slot = obj_id * MAX_CELLS_PER_OBJECT + i;
+        // build_cell_ids_array writes the cells of a particle first, then the unused slots
+        if start + i < end {
+            cell_ids[start + i] = sparse_cell_ids[slot];
+            object_ids[start + i] = sparse_object_ids[slot];
+        }
+        if slot >= used_cells {
+            cell_ids[slot] = UNUSED_CELL_ID;
+        }
+    }
+}
+
+/// Turns the used cell count into the dispatch sizes of the sort and the collision cell builder.
+/// The collision cell builder covers the cells of the last step once more, so their chunk counts are cleared.
+@compute @workgroup_size(1)
+fn prepare_used_cells_dispatch() {
+    let used_cells = get_used_cells(get_num_particles());
+    let covered_cells = max(used_cells, live_count.previous_used_cells);
+    live_count.used_cells = used_cells;
+    live_count.previous_used_cells = used_cells;
+
+    // At least one workgroup: the collision cell builder also writes the solver dispatch
+    live_count.chunks_x = max(div_ceil(div_ceil(covered_cells, CHUNK_SIZE), CHUNK_WORKGROUP_SIZE), 1u);
+    live_count.chunks_y = 1u;
+    live_count.chunks_z = 1u;
+
+    let num_workgroups = div_ceil(div_ceil(used_cells, SORT_BLOCKS_PER_WORKGROUP), SORT_WORKGROUP_SIZE);
+    sort_args.x = num_workgroups;
+    sort_args.y = 1u;
+    sort_args.z = 1u;
+    sort_args.num_elements = used_cells;
+    sort_args.num_workgroups = num_workgroups;
+}
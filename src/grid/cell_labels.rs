@@ -0,0 +1,97 @@
+//! Morton id labels of the grid cells, drawn with seven segment digits when the camera is zoomed in enough
+//! for them to be readable. Only builds the strokes, `GridDrawer` draws them as lines.
+use glam::{UVec2, Vec2};
+use crate::grid::morton;
+
+/// Cells smaller than this on screen are not labelled.
+pub const MIN_LABEL_CELL_PIXELS: f32 = 48.0;
+
+/// Bounds the strokes uploaded per frame, a larger view is not labelled.
+pub const MAX_LABELLED_CELLS: usize = 4096;
+
+/// Width of a digit relative to its height.
+const DIGIT_WIDTH: f32 = 0.5;
+/// Space between two digits relative to the height.
+const DIGIT_SPACING: f32 = 0.25;
+
+// Segments: top, top right, bottom right, bottom, bottom left, top left, middle
+const SEGMENTS: [(Vec2, Vec2); 7] = [
+    (Vec2::new(0.0, 1.0), Vec2::new(1.0, 1.0)),
+    (Vec2::new(1.0, 1.0), Vec2::new(1.0, 0.5)),
+    (Vec2::new(1.0, 0.5), Vec2::new(1.0, 0.0)),
+    (Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0)),
+    (Vec2::new(0.0, 0.5), Vec2::new(0.0, 0.0)),
+    (Vec2::new(0.0, 1.0), Vec2::new(0.0, 0.5)),
+    (Vec2::new(0.0, 0.5), Vec2::new(1.0, 0.5)),
+];
+
+/// Lit segments of each digit, bit i is `SEGMENTS[i]`.
+const DIGIT_SEGMENTS: [u8; 10] = [
+    0b0111111, 0b0000110, 0b1011011, 0b1001111, 0b1100110,
+    0b1101101, 0b1111101, 0b0000111, 0b1111111, 0b1101111,
+];
+
+/// Lit segments of `digit`, see `SEGMENTS`. Panics if `digit` is not below 10.
+pub fn digit_segments(digit: u32) -> u8 {
+    DIGIT_SEGMENTS[digit as usize]
+}
+
+/// Strokes of the decimal digits of `value`, centered on `center`. Each stroke is a (start, end) pair.
+pub fn number_strokes(value: u32, center: Vec2, digit_height: f32) -> Vec<(Vec2, Vec2)> {
+    let digits = value.to_string();
+    let num_digits = digits.len() as f32;
+    let total_width = (num_digits * (DIGIT_WIDTH + DIGIT_SPACING) - DIGIT_SPACING) * digit_height;
+    let bottom_left = center - Vec2::new(total_width, digit_height) * 0.5;
+    let digit_size = Vec2::new(DIGIT_WIDTH, 1.0) * digit_height;
+
+    let mut strokes = Vec::new();
+    for (i, digit) in digits.chars().enumerate() {
+        let corner = bottom_left + Vec2::new(i as f32 * (DIGIT_WIDTH + DIGIT_SPACING) * digit_height, 0.0);
+        let lit = digit_segments(digit.to_digit(10).unwrap());
+        for (segment, (start, end)) in SEGMENTS.iter().enumerate() {
+            if lit & (1 << segment) != 0 {
+                strokes.push((corner + *start * digit_size, corner + *end * digit_size));
+            }
+        }
+    }
+    strokes
+}
+
+/// Height of the digits of a label of `num_digits`, so it fits in 80% of the cell width.
+fn label_height(num_digits: usize, cell_size: f32) -> f32 {
+    let width_per_height = num_digits as f32 * (DIGIT_WIDTH + DIGIT_SPACING) - DIGIT_SPACING;
+    (cell_size * 0.25).min(cell_size * 0.8 / width_per_height)
+}
+
+/// Strokes of the morton id labels of the cells of the world that intersect the view rectangle
+/// `view_min..view_max`. Empty if a cell covers fewer than `MIN_LABEL_CELL_PIXELS` at `pixels_per_unit`
+/// (the camera zoom), or if more than `MAX_LABELLED_CELLS` cells are visible.
+pub fn cell_label_strokes(view_min: Vec2, view_max: Vec2, origin: Vec2, world_size: Vec2, cell_size: f32, pixels_per_unit: f32) -> Vec<(Vec2, Vec2)> {
+    if cell_size <= 0.0 || cell_size * pixels_per_unit < MIN_LABEL_CELL_PIXELS {
+        return Vec::new();
+    }
+    let num_cells = (world_size / cell_size).ceil().as_uvec2();
+    if num_cells.x == 0 || num_cells.y == 0 {
+        return Vec::new();
+    }
+    let first_cell = ((view_min - origin) / cell_size).floor().max(Vec2::ZERO).as_uvec2();
+    let last_cell = ((view_max - origin) / cell_size).floor().max(Vec2::ZERO).as_uvec2().min(num_cells - UVec2::ONE);
+    if view_max.x < origin.x || view_max.y < origin.y || first_cell.x > last_cell.x || first_cell.y > last_cell.y {
+        return Vec::new();
+    }
+    let visible = (last_cell - first_cell + UVec2::ONE).as_u64vec2();
+    if visible.x * visible.y > MAX_LABELLED_CELLS as u64 {
+        return Vec::new();
+    }
+
+    let mut strokes = Vec::new();
+    for y in first_cell.y..=last_cell.y {
+        for x in first_cell.x..=last_cell.x {
+            let cell = UVec2::new(x, y);
+            let id = morton::encode(cell);
+            let center = origin + (cell.as_vec2() + Vec2::splat(0.5)) * cell_size;
+            strokes.extend(number_strokes(id, center, label_height(id.to_string().len(), cell_size)));
+        }
+    }
+    strokes
+}
@@ -0,0 +1,180 @@
+use glam::UVec2;
+use wgpu::{BindGroupLayout, PushConstantRange};
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::profiler::GpuProfiler;
+
+pub const WORKGROUP_SIZE: u32 = 64;
+
+/// What the debug view of the grid colors the cells by, see `Grid::cycle_debug_view`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GridDebugView {
+    /// Objects touching the cell, home and phantom: what the broadphase put in it.
+    Occupancy,
+    /// Object pairs of the cell, n (n - 1) / 2 for n objects: the narrow phase work of the cell.
+    PairTests,
+}
+
+impl GridDebugView {
+    /// Next view of the debug view key: occupancy, pair tests, and off.
+    pub fn cycle(current: Option<Self>) -> Option<Self> {
+        match current {
+            None => Some(Self::Occupancy),
+            Some(Self::Occupancy) => Some(Self::PairTests),
+            Some(Self::PairTests) => None,
+        }
+    }
+
+    /// Value of a cell touched by `objects` objects. Must match occupancy_drawer.wgsl
+    pub fn value(&self, objects: u32) -> u32 {
+        match self {
+            Self::Occupancy => objects,
+            Self::PairTests => objects * objects.saturating_sub(1) / 2,
+        }
+    }
+
+    /// Value at the end of the color map, the larger ones saturate: 8 objects, and their pairs.
+    pub fn max_value(&self) -> u32 {
+        self.value(8)
+    }
+
+    /// Title of the legend.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Occupancy => "OBJECTS PER CELL",
+            Self::PairTests => "PAIR TESTS PER CELL",
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstants {
+    total_cell_ids: u32,
+    table_width: u32,
+    table_height: u32,
+}
+
+/// Number of objects touching every cell of the grid, counted on the GPU from the cell id map of the last grid
+/// update. The table covers the cells from the grid origin, cells past it are not counted. Nothing is read back
+/// unless asked for, the debug view of `GridDrawer` draws the counts directly.
+pub struct CellOccupancyMap {
+    count_shader: ComputeShader,
+    bind_group_layout: BindGroupLayout,
+    counts: GpuBuffer<u32>,
+    table_size: UVec2,
+}
+
+impl CellOccupancyMap {
+    pub fn new(wgpu_context: &WgpuContext) -> Self {
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let count_shader = ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("cell_occupancy_map.wgsl"),
+            "count_cell_objects",
+            &bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange {
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstants>() as u32,
+                }
+            ]
+        );
+
+        Self {
+            count_shader,
+            bind_group_layout,
+            counts: GpuBuffer::new(wgpu_context, vec![0u32], wgpu::BufferUsages::STORAGE),
+            table_size: UVec2::ONE,
+        }
+    }
+
+    /// Cells of the last `update` along x and y.
+    pub fn table_size(&self) -> UVec2 {
+        self.table_size
+    }
+
+    /// Objects per cell, row by row from the cell at the grid origin.
+    pub fn counts(&self) -> &GpuBuffer<u32> {
+        &self.counts
+    }
+
+    /// Submits the count of the objects in the `table_size` cells from the origin, from `cell_ids`, see
+    /// `Grid::cell_ids`. Call it after the grid update was submitted. Returns true if `counts` was recreated:
+    /// the bind groups that read it must be recreated too.
+    pub fn update(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, cell_ids: &GpuBuffer<u32>, table_size: UVec2) -> bool {
+        self.table_size = table_size.max(UVec2::ONE);
+        let num_cells = (self.table_size.x * self.table_size.y) as usize;
+        let recreated = self.counts.reserve(wgpu_context, num_cells);
+        if self.counts.len() < num_cells {
+            self.counts.push_all(&vec![0u32; num_cells - self.counts.len()], wgpu_context);
+        }
+        self.counts.truncate(num_cells);
+
+        let bind_group = wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Cell occupancy map bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: cell_ids.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: self.counts.buffer().as_entire_binding() },
+            ],
+        });
+        let total_cell_ids = cell_ids.len() as u32;
+        let push_constants = PushConstants {
+            total_cell_ids,
+            table_width: self.table_size.x,
+            table_height: self.table_size.y,
+        };
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Cell occupancy map encoder") }
+        );
+        encoder.clear_buffer(self.counts.buffer(), 0, None);
+        {
+            let mut scope = gpu_profiler.scope("Cell occupancy map", &mut encoder);
+            if total_cell_ids > 0 {
+                self.count_shader.dispatch_by_items(
+                    &mut scope,
+                    (total_cell_ids, 1, 1),
+                    Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                    &bind_group
+                );
+            }
+        }
+        gpu_profiler.resolve_queries(&mut encoder);
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+        recreated
+    }
+
+    /// Reads the counts of the last `update` back. Stalls until the GPU is done.
+    pub fn download_counts(&self, wgpu_context: &WgpuContext) -> Vec<u32> {
+        self.counts.read_back(wgpu_context).unwrap()
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Cell occupancy map bind group layout"),
+            entries: &[
+                // Cell IDs
+                storage_entry(0, true),
+                // Counts
+                storage_entry(1, false),
+            ],
+        })
+    }
+}
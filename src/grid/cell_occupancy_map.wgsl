@@ -0,0 +1,49 @@
+override WORKGROUP_SIZE = 64u;
+
+const UNUSED_CELL_ID = 0xffffffffu;
+
+struct PushConstants {
+    total_cell_ids: u32,
+    // Cells of the map along x and y
+    table_width: u32,
+    table_height: u32,
+}
+
+@group(0) @binding(0) var<storage, read> cell_ids: array<u32>;
+// Objects touching every cell, home and phantom, row by row from the cell at the grid origin
+@group(0) @binding(1) var<storage, read_write> counts: array<atomic<u32>>;
+
+var<push_constant> push_constants: PushConstants;
+
+// One thread per slot of the cell id map, in any order: the map may be sorted or not
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn count_cell_objects(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let idx = global_id.x;
+    if idx >= push_constants.total_cell_ids {
+        return;
+    }
+    let cell_id = cell_ids[idx];
+    if cell_id == UNUSED_CELL_ID {
+        return;
+    }
+    let cell = morton_decode(cell_id);
+    if cell.x >= push_constants.table_width || cell.y >= push_constants.table_height {
+        return;
+    }
+    atomicAdd(&counts[cell.y * push_constants.table_width + cell.x], 1u);
+}
+
+/// Inverse of split_by_bits in grid.wgsl.
+fn unsplit_by_bits(n: u32) -> u32 {
+    var x = n & 0x55555555;
+    x = (x | (x >> 1)) & 0x33333333;
+    x = (x | (x >> 2)) & 0x0F0F0F0F;
+    x = (x | (x >> 4)) & 0x00FF00FF;
+    x = (x | (x >> 8)) & 0x0000FFFF;
+    return x;
+}
+
+/// Inverse of morton_encode in grid.wgsl.
+fn morton_decode(cell_id: u32) -> vec2<u32> {
+    return vec2<u32>(unsplit_by_bits(cell_id), unsplit_by_bits(cell_id >> 1));
+}
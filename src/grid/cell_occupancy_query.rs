@@ -0,0 +1,197 @@
+use std::sync::mpsc::{Receiver, TryRecvError};
+use wgpu::{BindGroupLayout, BufferAsyncError, PushConstantRange};
+use wgpu::wgt::PollType;
+use crate::grid::grid::Grid;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::bind_resources::BindResources;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstants {
+    target_cell_id: u32,
+    total_cell_ids: u32,
+    key_mask: u32,
+}
+
+/// Number of objects touching a cell, as seen by the last grid update.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CellOccupancy {
+    pub cell_id: u32,
+    pub objects: u32,
+}
+
+struct PendingQuery {
+    cell_id: u32,
+    receiver: Receiver<Result<(), BufferAsyncError>>,
+}
+
+/// Counts the objects in one cell of the grid without stalling the frame.
+/// `record_request` records the count and a copy to a mappable buffer, `after_submit` maps it and `poll`
+/// picks the result up once the GPU is done, usually one or two frames later. Only one query is in flight at a time.
+pub struct CellOccupancyQuery {
+    count_shader: ComputeShader,
+    bind_resources: BindResources,
+    occupancy: GpuBuffer<u32>,
+    staging_buffer: wgpu::Buffer,
+    pending: Option<PendingQuery>,
+    /// Cell of the query recorded into an encoder that was not submitted yet
+    recorded: Option<u32>,
+    last_result: Option<CellOccupancy>,
+}
+
+impl CellOccupancyQuery {
+    pub fn new(wgpu_context: &WgpuContext, grid: &Grid) -> Self {
+        let occupancy = GpuBuffer::new(wgpu_context, vec![0u32], wgpu::BufferUsages::STORAGE);
+        let staging_buffer = wgpu_context.get_device().create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cell occupancy staging buffer"),
+            size: size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let bind_group = Self::create_bind_group(wgpu_context, &bind_group_layout, grid, &occupancy);
+        let bind_resources = BindResources::new(bind_group_layout, bind_group);
+
+        let count_shader = ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("cell_occupancy_query.wgsl"),
+            "count_cell_occupancy",
+            &bind_resources.bind_group_layout,
+            (1, 1, 1),
+            &vec![],
+            &vec![
+                PushConstantRange{
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstants>() as u32,
+                }
+            ]
+        );
+
+        Self {
+            count_shader,
+            bind_resources,
+            occupancy,
+            staging_buffer,
+            pending: None,
+            recorded: None,
+            last_result: None,
+        }
+    }
+
+    /// Must be called after the grid buffers are refreshed.
+    pub fn refresh(&mut self, wgpu_context: &WgpuContext, grid: &Grid) {
+        self.bind_resources.bind_group = Self::create_bind_group(wgpu_context, &self.bind_resources.bind_group_layout, grid, &self.occupancy);
+    }
+
+    fn create_bind_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, grid: &Grid, occupancy: &GpuBuffer<u32>) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                label: Some("Cell occupancy query bind group"),
+                layout: bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: grid.cell_ids().buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: occupancy.buffer().as_entire_binding(),
+                    },
+                ],
+            }
+        )
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Cell occupancy query bind group layout"),
+            entries: &[
+                // Cell IDs
+                storage_entry(0, true),
+                // Occupancy
+                storage_entry(1, false),
+            ],
+        })
+    }
+
+    /// Starts counting the objects in `cell_id`. Ignored while the previous query is still in flight.
+    /// Submits its own work, so call it after the grid update of the frame was submitted.
+    pub fn request(&mut self, wgpu_context: &WgpuContext, grid: &Grid, cell_id: u32) {
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Cell occupancy query encoder") }
+        );
+        self.record_request(wgpu_context, &mut encoder, grid, cell_id);
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+        self.after_submit();
+    }
+
+    /// `request` recorded into `encoder`, after the grid update. `after_submit` must follow the submit of the encoder.
+    pub fn record_request(&mut self, wgpu_context: &WgpuContext, encoder: &mut wgpu::CommandEncoder, grid: &Grid, cell_id: u32) {
+        if self.pending.is_some() || self.recorded.is_some() {
+            return;
+        }
+
+        // Binary search of the cell in the map sorted by the last grid update, in a single thread
+        self.count_shader.dispatch(
+            encoder,
+            (1, 1, 1),
+            Some(vec![(0u32, bytemuck::bytes_of(&PushConstants {
+                target_cell_id: cell_id,
+                total_cell_ids: grid.cell_ids().len() as u32,
+                key_mask: u32::MAX >> (u32::BITS - grid.sort_key_bits()),
+            }))]),
+            &self.bind_resources.bind_group
+        );
+        encoder.copy_buffer_to_buffer(self.occupancy.buffer(), 0, &self.staging_buffer, 0, size_of::<u32>() as u64);
+        wgpu_context.transfers().record_readback(size_of::<u32>() as u64);
+        self.recorded = Some(cell_id);
+    }
+
+    /// Maps the result of the query recorded since the last call, once its encoder was submitted.
+    pub fn after_submit(&mut self) {
+        let Some(cell_id) = self.recorded.take() else {
+            return;
+        };
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.staging_buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.pending = Some(PendingQuery { cell_id, receiver });
+    }
+
+    /// Checks, without blocking, if the query in flight finished.
+    /// Returns the latest available result, which may belong to an older request.
+    pub fn poll(&mut self, wgpu_context: &WgpuContext) -> Option<CellOccupancy> {
+        if let Some(pending) = self.pending.take() {
+            let _ = wgpu_context.get_device().poll(PollType::Poll);
+            match pending.receiver.try_recv() {
+                Ok(Ok(())) => {
+                    let objects = {
+                        let mapped_range = self.staging_buffer.slice(..).get_mapped_range();
+                        bytemuck::cast_slice::<u8, u32>(&mapped_range)[0]
+                    };
+                    self.staging_buffer.unmap();
+                    self.last_result = Some(CellOccupancy { cell_id: pending.cell_id, objects });
+                }
+                Ok(Err(e)) => log::error!("Cell occupancy query failed: {:?}", e),
+                Err(TryRecvError::Empty) => self.pending = Some(pending),
+                Err(TryRecvError::Disconnected) => {}
+            }
+        }
+        self.last_result
+    }
+}
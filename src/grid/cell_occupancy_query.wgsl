@@ -0,0 +1,48 @@
+struct PushConstants {
+    target_cell_id: u32,
+    total_cell_ids: u32,
+    // Bits the map was sorted on, see `GPUSorter::set_key_bits`
+    key_mask: u32,
+}
+
+// Sorted by the sort key, the unused slots last
+@group(0) @binding(0) var<storage, read> cell_ids: array<u32>;
+// Number of objects touching the target cell, home and phantom
+@group(0) @binding(1) var<storage, read_write> occupancy: u32;
+
+var<push_constant> push_constants: PushConstants;
+
+fn sort_key(cell_id: u32) -> u32 {
+    return cell_id & push_constants.key_mask;
+}
+
+// First index whose sort key is not below `key` (or above it, with `inclusive`)
+fn lower_bound(key: u32, inclusive: bool) -> u32 {
+    var low = 0u;
+    var high = push_constants.total_cell_ids;
+    while low < high {
+        let middle = low + (high - low) / 2u;
+        let middle_key = sort_key(cell_ids[middle]);
+        if middle_key < key || (inclusive && middle_key == key) {
+            low = middle + 1u;
+        }
+        else {
+            high = middle;
+        }
+    }
+    return low;
+}
+
+// One thread: the objects of a cell are contiguous in the sorted map
+@compute @workgroup_size(1)
+fn count_cell_occupancy() {
+    let key = sort_key(push_constants.target_cell_id);
+    let start = lower_bound(key, false);
+    let end = lower_bound(key, true);
+    // Cells outside the sorted bits share their key with the target, only the exact matches count
+    var count = 0u;
+    for (var i = start; i < end; i++) {
+        count += select(0u, 1u, cell_ids[i] == push_constants.target_cell_id);
+    }
+    occupancy = count;
+}
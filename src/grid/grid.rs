@@ -1,36 +1,67 @@
-use glam::{Vec2};
+use glam::{UVec2, Vec2};
 use crate::particles::particle_system::ParticleSystem;
+use crate::particles::particle_volume::ParticleVolume;
+#[cfg(feature = "windowing")]
 use crate::renderer::camera::Camera;
+#[cfg(feature = "windowing")]
 use crate::renderer::renderable::Renderable;
 use crate::renderer::wgpu_context::WgpuContext;
 use crate::utils::compute_shader::ComputeShader;
-use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::gpu_buffer::{BufferHandle, GpuBuffer};
 use std::num::NonZeroU32;
-use wgpu::{BindGroupLayout, BufferAsyncError, CommandEncoder, PushConstantRange};
-use wgpu_profiler::GpuProfiler;
+use wgpu::{BufferAsyncError, CommandEncoder, PushConstantRange};
+use crate::utils::profiler::GpuProfiler;
+#[cfg(feature = "windowing")]
 use crate::grid::grid_drawer::GridDrawer;
-use crate::utils::bind_resources::BindResources;
-use crate::utils::radix_sort::radix_sort::{GPUSorter};
+#[cfg(feature = "windowing")]
+use crate::grid::cell_occupancy_map::GridDebugView;
+use crate::utils::bind_resources::{Bindings, BindingsBuilder};
+use crate::utils::radix_sort::{GPUSorter, BITS_PER_ELEMENT};
+use crate::grid::morton;
+use crate::grid::spatial_hash;
+use crate::grid::live_count::{LiveParticleCount, BUILD_CELL_IDS_ARGS_OFFSET};
+use crate::grid::cell_compaction::{CellCompaction, CompactionTargets};
+use crate::physics::frame_graph::FrameGraph;
+use crate::physics::pass_validation::PhysicsPass;
 
 /// The value must match in the compute shader.
-const WORKGROUP_SIZE: (u32, u32, u32) = (64, 1, 1);
+pub const WORKGROUP_SIZE: (u32, u32, u32) = (64, 1, 1);
 
 pub const MAX_CELLS_PER_OBJECT: u32 = 4;
+/// An object of a 3D grid can touch 2^3 cells.
+pub const MAX_CELLS_PER_OBJECT_3D: u32 = 8;
 
 const CELL_SIZE_MULTIPLIER: f32 = 2.2f32;
 
 pub const UNUSED_CELL_ID: u32 = u32::MAX;
 
+/// num_particles of the build push constants when the dispatch is driven by the live particle count.
+const INDIRECT_COUNT: u32 = u32::MAX;
+
 
 pub struct Grid {
+    #[cfg(feature = "windowing")]
     grid_drawer: Option<GridDrawer>,
+    #[cfg(feature = "windowing")]
     should_draw_grid: bool,
+    #[cfg(feature = "windowing")]
+    debug_view: Option<GridDebugView>, // Set by cycle_debug_view
     dim: u32,
     grid_buffers: GridBuffers,
     grid_kernels: GridKernels,
-    grid_binding_group: BindResources,
+    bindings: Bindings,
     cell_size: f32,
+    origin: Vec2,
     num_elements: usize,
+    live_count: LiveParticleCount,
+    indirect_dispatch: bool,
+    cell_compaction: Option<CellCompaction>, // Set by set_cell_compaction
+    key_compression_world: Option<Vec2>, // Set by set_key_compression
+    spatial_hash_bits: Option<u32>, // Set by set_spatial_hash
+    period: Option<Vec2>, // Set by set_period
+    // Particle buffers bound by the cell id build
+    positions: BufferHandle,
+    radii: BufferHandle,
 }
 
 struct GridBuffers{
@@ -60,21 +91,35 @@ struct UniformData {
 struct PushConstantsBuildGrid {
     cell_size: f32,
     num_particles: u32,
+    origin: Vec2,
+    table_bits: u32,
+    _padding: u32,
+    /// Size of the periodic world, zero if the grid does not wrap
+    period: Vec2,
 }
 
 impl Grid {
+    #[cfg(feature = "windowing")]
     pub fn new(wgpu_context: &WgpuContext, camera: &Camera, world_dimensions: Vec2, particle_system: &ParticleSystem) -> Grid {
         let max_obj_radius = particle_system.get_max_radius();
         let mut grid = Self::new_without_camera(wgpu_context, max_obj_radius, particle_system);
-        grid.grid_drawer = Some(GridDrawer::new(wgpu_context, camera, &world_dimensions, grid.cell_size));
+        grid.refresh_drawer(wgpu_context, camera, world_dimensions);
         grid
     }
 
     // No camera needed for tests
     pub fn new_without_camera(wgpu_context: &WgpuContext, max_obj_radius: f32, particle_system: &ParticleSystem) -> Grid{
-        let total_particles: usize = particle_system.len();
-        let dim: u32 = 2;
-        let buffer_len = total_particles * 2usize.pow(dim); // A particle can be in 2**dim different cells
+        Self::new_with_dim(wgpu_context, 2, max_obj_radius, particle_system.len(), particle_system.positions().handle(), particle_system.radius().handle())
+    }
+
+    /// Grid of a volumetric particle system: cell ids come from a 3D morton encoding
+    /// (10 bits per axis) and every particle can touch up to `MAX_CELLS_PER_OBJECT_3D` cells.
+    pub fn new_3d(wgpu_context: &WgpuContext, max_obj_radius: f32, particle_volume: &ParticleVolume) -> Grid{
+        Self::new_with_dim(wgpu_context, 3, max_obj_radius, particle_volume.len(), particle_volume.positions().handle(), particle_volume.radii().handle())
+    }
+
+    fn new_with_dim(wgpu_context: &WgpuContext, dim: u32, max_obj_radius: f32, total_particles: usize, positions: BufferHandle, radii: BufferHandle) -> Grid{
+        let buffer_len = total_particles * Self::max_cells_per_object_of(dim) as usize; // A particle can be in 2**dim different cells
         let cell_size = Self::compute_cell_size(max_obj_radius);
         
         let cell_ids = GpuBuffer::new(
@@ -105,25 +150,25 @@ impl Grid {
         };
 
 
-        let bind_group_layout = Grid::create_binding_group_layout(wgpu_context);
+        let sorter: GPUSorter = GPUSorter::new(wgpu_context, NonZeroU32::new(buffer_len as u32).unwrap(), &grid_buffers.cell_ids, &grid_buffers.object_ids);
+        let live_count = LiveParticleCount::new(wgpu_context, total_particles, Self::max_cells_per_object_of(dim), WORKGROUP_SIZE.0, &sorter);
 
-        // Create bind group
-        let bind_group = Self::create_binding_group(wgpu_context, &bind_group_layout, &grid_buffers, particle_system);
+        let bindings = Self::bindings_builder(&grid_buffers, None, &live_count, &positions, &radii).build(wgpu_context);
         
-        let grid_binding_group = BindResources{
-            bind_group,
-            bind_group_layout,
+        let shader_source = match dim {
+            2 => wgpu::include_wgsl!("grid.wgsl"),
+            3 => wgpu::include_wgsl!("grid_3d.wgsl"),
+            _ => panic!("Unsupported grid dimension {}", dim),
         };
-        
         let build_grid_shader = ComputeShader::new(
             wgpu_context,
-            wgpu::include_wgsl!("grid.wgsl"),
+            shader_source,
             "build_cell_ids_array",
-            &grid_binding_group.bind_group_layout,
+            bindings.layout(),
             WORKGROUP_SIZE,
             &vec![
                 ("WORKGROUP_SIZE", WORKGROUP_SIZE.0 as f64),
-                ("MAX_CELLS_PER_OBJECT", MAX_CELLS_PER_OBJECT as f64)
+                ("MAX_CELLS_PER_OBJECT", Self::max_cells_per_object_of(dim) as f64)
             ],
             &vec![
                 PushConstantRange{
@@ -132,30 +177,66 @@ impl Grid {
             }]
         );
 
-        
-
-        let sorter: GPUSorter = GPUSorter::new(wgpu_context, NonZeroU32::new(buffer_len as u32).unwrap(), &grid_buffers.cell_ids, &grid_buffers.object_ids);
-
         Grid {
             dim,
+            #[cfg(feature = "windowing")]
             should_draw_grid: false,
+            #[cfg(feature = "windowing")]
+            debug_view: None,
+            #[cfg(feature = "windowing")]
             grid_drawer: None,
             grid_buffers,
             grid_kernels: GridKernels{build_cell_ids_shader: build_grid_shader, gpu_sorter: sorter},
-            grid_binding_group,
+            bindings,
             cell_size,
-            num_elements: total_particles
+            origin: Vec2::ZERO,
+            num_elements: total_particles,
+            live_count,
+            indirect_dispatch: false,
+            cell_compaction: None,
+            key_compression_world: None,
+            spatial_hash_bits: None,
+            period: None,
+            positions,
+            radii,
         }
     }
     
+    /// Cells an object of a grid with `dim` dimensions overlaps at most.
+    pub fn max_cells_per_object_of(dim: u32) -> u32 {
+        2u32.pow(dim)
+    }
+
+    /// 2 or 3.
+    pub fn dim(&self) -> u32 {
+        self.dim
+    }
+
+    /// Slots of the cell id array used by each object: `MAX_CELLS_PER_OBJECT` or `MAX_CELLS_PER_OBJECT_3D`.
+    pub fn max_cells_per_object(&self) -> u32 {
+        Self::max_cells_per_object_of(self.dim)
+    }
+
     pub fn get_total_cells(cell_size: f32, world_dim: &Vec2) -> usize{
         (world_dim.x / cell_size) as usize * (world_dim.y / cell_size) as usize
     }
     
+    #[cfg(feature = "windowing")]
     pub fn toggle_grid_drawing(&mut self){
         self.should_draw_grid = !self.should_draw_grid;
     }
 
+    /// Colors the cells by their objects, then by their pair tests, then stops, see `GridDebugView`.
+    #[cfg(feature = "windowing")]
+    pub fn cycle_debug_view(&mut self){
+        self.debug_view = GridDebugView::cycle(self.debug_view);
+    }
+
+    #[cfg(feature = "windowing")]
+    pub fn debug_view(&self) -> Option<GridDebugView> {
+        self.debug_view
+    }
+
     pub fn compute_cell_size(max_obj_radius: f32) -> f32 {
         max_obj_radius * CELL_SIZE_MULTIPLIER
     }
@@ -163,109 +244,85 @@ impl Grid {
     pub fn cell_size(&self) -> f32 {
         self.cell_size
     }
+
+    /// World position of the corner of cell (0, 0). Cell coordinates are computed relative to it,
+    /// so it must be the minimum corner of the world. Only used by 2D grids.
+    pub fn origin(&self) -> Vec2 {
+        self.origin
+    }
+
+    /// Moves the grid, takes effect on the next `update`. The drawn grid lines only move with `refresh_drawer`.
+    pub fn set_origin(&mut self, origin: Vec2) {
+        self.origin = origin;
+    }
     
-    fn create_binding_group_layout(wgpu_context: &WgpuContext) -> wgpu::BindGroupLayout{
-        let compute_bind_group_layout = wgpu::BindGroupLayoutDescriptor {
-            label: Some("Grid compute Bind Group Layout"),
-            entries: &[
-                // Positions
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // Uniform data
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // Cell IDs
-                wgpu::BindGroupLayoutEntry {
-                    binding: 2,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false }, // false means read-write
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // Object IDs
-                wgpu::BindGroupLayoutEntry {
-                    binding: 3,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // Radius
-                wgpu::BindGroupLayoutEntry {
-                    binding: 4,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
+    /// The cell id build writes the map of `grid_buffers`, or the sparse buffers of `cell_compaction` if it is set.
+    fn bindings_builder(grid_buffers: &GridBuffers, cell_compaction: Option<&CellCompaction>, live_count: &LiveParticleCount, positions: &BufferHandle, radii: &BufferHandle) -> BindingsBuilder {
+        let (cell_ids, object_ids) = match cell_compaction {
+            Some(cell_compaction) => (cell_compaction.sparse_cell_ids(), cell_compaction.sparse_object_ids()),
+            None => (&grid_buffers.cell_ids, &grid_buffers.object_ids),
         };
-
-        wgpu_context.get_device().create_bind_group_layout(&compute_bind_group_layout)
-    }
-    fn create_binding_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, grid_buffers: &GridBuffers, particle_system: &ParticleSystem) -> wgpu::BindGroup{
-        wgpu_context.get_device().create_bind_group(
-            &wgpu::BindGroupDescriptor {
-                label: None,
-                layout: bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: particle_system.positions().buffer().as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: grid_buffers.uniform_buffer.buffer().as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 2,
-                        resource: grid_buffers.cell_ids.buffer().as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 3,
-                        resource: grid_buffers.object_ids.buffer().as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 4,
-                        resource: particle_system.radius().buffer().as_entire_binding(),
-                    },
-                ],
-            }
-        )
+        BindingsBuilder::new("Grid compute")
+            .storage_rw(positions)
+            .uniform(&grid_buffers.uniform_buffer)
+            .storage_rw(cell_ids)
+            .storage_rw(object_ids)
+            .storage_ro(radii)
+            .storage_ro(live_count.args())
     }
 
 
     /// Refreshes the grid when elements have been added or removed.
-    /// This function is called when the particles system is updated.
-    pub fn refresh_grid(&mut self, wgpu_context: &WgpuContext, camera: &Camera, world_dimensions: Vec2, particle_system: &ParticleSystem, prev_total_particles: usize){
-        self.cell_size = Grid::compute_cell_size(particle_system.get_max_radius());
-        self.num_elements = particle_system.len();
-        let particles_added = self.num_elements - prev_total_particles;
+    /// This function is called when the particles system is updated. The cell size may change,
+    /// call `refresh_drawer` afterwards if the grid is drawn.
+    pub fn refresh_grid(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem){
+        self.refresh_buffers(wgpu_context, particle_system.get_max_radius(), particle_system.len(), particle_system.positions().handle(), particle_system.radius().handle());
+    }
+
+    /// Recreates the grid lines if the cell size, the origin or the world size changed since they were built,
+    /// e.g. after a spawn brought a larger particle. Returns true if they were rebuilt.
+    #[cfg(feature = "windowing")]
+    pub fn refresh_drawer(&mut self, wgpu_context: &WgpuContext, camera: &Camera, world_dimensions: Vec2) -> bool {
+        let up_to_date = self.grid_drawer.as_ref()
+            .is_some_and(|grid_drawer| grid_drawer.is_built_for(self.origin, world_dimensions, self.cell_size));
+        if up_to_date {
+            return false;
+        }
+        self.grid_drawer = Some(GridDrawer::new(wgpu_context, camera, self.origin, &world_dimensions, self.cell_size));
+        true
+    }
+
+    /// Labels the visible cells with their morton id when zoomed in, see `GridDrawer::update_labels`.
+    /// Does nothing while the grid is not drawn.
+    #[cfg(feature = "windowing")]
+    pub fn update_cell_labels(&mut self, wgpu_context: &WgpuContext, camera: &Camera) {
+        if !self.should_draw_grid {
+            return;
+        }
+        if let Some(grid_drawer) = self.grid_drawer.as_mut() {
+            grid_drawer.update_labels(wgpu_context, camera, &wgpu_context.window_size());
+        }
+    }
+
+    /// Recounts the objects of the cells drawn by the debug view from the last grid update, once per frame after
+    /// the steps. Does nothing else without a debug view.
+    #[cfg(feature = "windowing")]
+    pub fn update_debug_view(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, camera: &Camera) {
+        if let Some(grid_drawer) = self.grid_drawer.as_mut() {
+            grid_drawer.update_debug_view(wgpu_context, gpu_profiler, &self.grid_buffers.cell_ids, self.debug_view, camera, &wgpu_context.window_size());
+        }
+    }
+
+    /// `refresh_grid` of a 3D grid, after particles were added to the volume.
+    pub fn refresh_grid_3d(&mut self, wgpu_context: &WgpuContext, particle_volume: &ParticleVolume){
+        self.refresh_buffers(wgpu_context, particle_volume.get_max_radius(), particle_volume.len(), particle_volume.positions().handle(), particle_volume.radii().handle());
+    }
+
+    fn refresh_buffers(&mut self, wgpu_context: &WgpuContext, max_obj_radius: f32, num_elements: usize, positions: BufferHandle, radii: BufferHandle){
+        self.cell_size = Grid::compute_cell_size(max_obj_radius);
+        self.num_elements = num_elements;
+        // A smaller cell size means more cells in the world
+        self.set_key_compression(self.key_compression_world);
 
         // Update the uniform
 
@@ -275,40 +332,247 @@ impl Grid {
             cell_size: self.cell_size,
         };
         self.grid_buffers.uniform_buffer.replace_elem(new_uniform, 0, wgpu_context);
-        
-        
-        // Recreate the grid drawer
-        self.grid_drawer = Some(GridDrawer::new(wgpu_context, camera, &world_dimensions, self.cell_size));
 
-        let buffer_size = particles_added * 4;
+        // Only grows past the slots freed by remove_particles
+        let buffer_size = (self.num_elements * self.max_cells_per_object() as usize).saturating_sub(self.grid_buffers.cell_ids.len());
         self.grid_buffers.cell_ids.push_all(&vec![UNUSED_CELL_ID; buffer_size], wgpu_context);
         self.grid_buffers.object_ids.push_all(&vec![0; buffer_size], wgpu_context);
         
         
-        // Update the binding group
-        self.grid_binding_group.bind_group = Self::create_binding_group(wgpu_context, &self.grid_binding_group.bind_group_layout, &self.grid_buffers, particle_system);
+        self.positions = positions;
+        self.radii = radii;
         self.grid_kernels.gpu_sorter.update_sorting_buffers(wgpu_context, NonZeroU32::new(self.grid_buffers.object_ids.len() as u32).unwrap(), &self.grid_buffers.cell_ids, &self.grid_buffers.object_ids);
+        if let Some(mut cell_compaction) = self.cell_compaction.take() {
+            cell_compaction.refresh(wgpu_context, self.capacity(), self.max_cells_per_object(), &self.compaction_targets());
+            self.cell_compaction = Some(cell_compaction);
+        }
+        // The grown buffers are followed by the bindings, the particle buffers may be other ones
+        self.rebind_cell_id_build();
+
+        // Particles added from the CPU are all live
+        self.live_count.set_capacity(wgpu_context, self.capacity() as u32);
+        self.live_count.set_live_particles(wgpu_context, self.num_elements as u32);
+    }
+
+    /// Forgets the particles past `num_elements`, after they were removed from the particle system.
+    /// Their cell ids are cleared, the buffers keep their size for the next refresh.
+    pub fn remove_particles(&mut self, wgpu_context: &WgpuContext, num_elements: usize){
+        if num_elements >= self.num_elements {
+            return;
+        }
+        let max_cells = self.max_cells_per_object() as usize;
+        let cleared = vec![UNUSED_CELL_ID; (self.num_elements - num_elements) * max_cells];
+        // The compacted map can hold cells of the removed particles anywhere past the used cells of the remaining ones
+        let cleared_buffers = [Some(&self.grid_buffers.cell_ids), self.cell_compaction.as_ref().map(CellCompaction::sparse_cell_ids)];
+        for cell_ids in cleared_buffers.into_iter().flatten() {
+            wgpu_context.get_queue().write_buffer(cell_ids.buffer(), (num_elements * max_cells * size_of::<u32>()) as u64, bytemuck::cast_slice(&cleared));
+            wgpu_context.transfers().record_upload(size_of_val(cleared.as_slice()) as u64);
+        }
+        self.num_elements = num_elements;
+
+        let new_uniform: UniformData = UniformData {
+            num_particles: self.num_elements as u32,
+            num_collision_cells: self.num_elements as u32 * 2u32.pow(self.dim),
+            cell_size: self.cell_size,
+        };
+        self.grid_buffers.uniform_buffer.replace_elem(new_uniform, 0, wgpu_context);
+        self.live_count.set_live_particles(wgpu_context, self.num_elements as u32);
+    }
+
+    /// Particles the grid is built for.
+    pub fn num_elements(&self) -> usize {
+        self.num_elements
+    }
+
+    /// Particles the grid buffers can hold.
+    pub fn capacity(&self) -> usize {
+        self.grid_buffers.cell_ids.len() / self.max_cells_per_object() as usize
     }
 
     /// Step 1: Constructs the map of cell ids to objects.
     /// Key: cell id; Value: Object id
-    /// Each particle has a max of 4 cell ids (in 2D space)
+    /// Each particle has a max of 4 cell ids in 2D space, 8 in 3D space
+    /// With indirect dispatch, only the live particles are processed; `prepare_indirect_dispatch` must run first.
+    /// With the cell compaction, the used cell ids are then moved to the front of the map, see `set_cell_compaction`.
     pub fn build_cell_ids(&self, encoder: &mut CommandEncoder){
+        self.dispatch_cell_id_build(encoder);
+        if let Some(cell_compaction) = &self.cell_compaction {
+            let live_count_args = self.indirect_dispatch.then(|| self.live_count.args().buffer());
+            cell_compaction.compact(encoder, self.num_elements as u32, live_count_args);
+        }
+    }
+
+    fn dispatch_cell_id_build(&self, encoder: &mut CommandEncoder){
+        if self.indirect_dispatch {
+            self.grid_kernels.build_cell_ids_shader.indirect_dispatch(
+                encoder,
+                self.live_count.args().buffer(),
+                BUILD_CELL_IDS_ARGS_OFFSET,
+                Some(vec![(0u32, bytemuck::bytes_of(&PushConstantsBuildGrid{
+                    cell_size: self.cell_size,
+                    num_particles: INDIRECT_COUNT,
+                    origin: self.origin,
+                    table_bits: self.spatial_hash_bits.unwrap_or(0),
+                    _padding: 0,
+                    period: self.period.unwrap_or(Vec2::ZERO),
+                }))]),
+                &self.bindings.bind_group()
+            );
+            return;
+        }
         self.grid_kernels.build_cell_ids_shader.dispatch_by_items(
             encoder,
             (self.num_elements as u32, 1, 1),
             Some(vec![(0u32, bytemuck::bytes_of(&PushConstantsBuildGrid{
                 cell_size: self.cell_size,
                 num_particles: self.num_elements as u32,
+                origin: self.origin,
+                table_bits: self.spatial_hash_bits.unwrap_or(0),
+                _padding: 0,
+                period: self.period.unwrap_or(Vec2::ZERO),
             }))]),
-            &self.grid_binding_group.bind_group
+            &self.bindings.bind_group()
         );
     }
 
     /// Step 2: Sorts the map of cell ids to objects by cell id.
     /// Key: cell id; Value: Object id
+    /// With the cell compaction, only the used cell ids counted on the GPU are sorted.
     pub fn sort_map(&mut self, encoder: &mut CommandEncoder){
-        self.grid_kernels.gpu_sorter.sort(encoder, None);
+        if self.indirect_dispatch || self.cell_compaction.is_some() {
+            self.grid_kernels.gpu_sorter.sort_indirect(encoder);
+        }
+        else {
+            self.grid_kernels.gpu_sorter.sort(encoder, None);
+        }
+    }
+
+    /// Computes the dispatch sizes of the grid and collision cell kernels from the live particle count.
+    pub fn prepare_indirect_dispatch(&self, encoder: &mut CommandEncoder){
+        self.live_count.prepare(encoder);
+    }
+
+    /// Drives the cell id build, the sort and the collision cell builder from the live particle count
+    /// on the GPU (see `LiveParticleCount`) instead of the particle count known by the CPU.
+    /// Only the first `live_particles` particles take part in the broad phase.
+    pub fn set_indirect_dispatch(&mut self, enabled: bool){
+        self.indirect_dispatch = enabled;
+    }
+
+    pub fn is_indirect_dispatch(&self) -> bool {
+        self.indirect_dispatch
+    }
+
+    /// Sorts the cell ids on the bits the cells of a `world_size` world need, so small worlds take fewer
+    /// radix passes (2 instead of 4 when every cell id fits in 16 bits), or on all 32 bits with `None`.
+    /// The bits follow the cell size when the grid is refreshed. One cell of margin is kept past the world;
+    /// particles further out than that are sorted on the low bits of their cell ids, and may miss collisions
+    /// until they are back. Only used by 2D grids, the spatial hash sorts on the bits of its table instead.
+    pub fn set_key_compression(&mut self, world_size: Option<Vec2>){
+        self.key_compression_world = world_size;
+        let key_bits = match (world_size, self.spatial_hash_bits) {
+            (_, Some(table_bits)) => spatial_hash::key_bits(table_bits),
+            (Some(world_size), None) if self.dim == 2 => {
+                let max_cell = morton::cell_coord(world_size, self.cell_size) + UVec2::ONE;
+                morton::key_bits(max_cell)
+            }
+            _ => BITS_PER_ELEMENT,
+        };
+        self.grid_kernels.gpu_sorter.set_key_bits(key_bits);
+    }
+
+    /// Hashes the cell coordinates into a table of `2^table_bits` blocks of four cells instead of morton
+    /// encoding them, or goes back to the morton ids with `None`, see `spatial_hash`. The coordinates are
+    /// no longer clamped to the origin and the sort only looks at the bits of the table. The collision solver
+    /// must follow, see `BroadphaseMode::SpatialHash`; the debug views, the cell labels and the occupancy and
+    /// region queries still decode morton ids. Only used by 2D grids, `table_bits` is capped at
+    /// `spatial_hash::MAX_TABLE_BITS`.
+    pub fn set_spatial_hash(&mut self, table_bits: Option<u32>){
+        self.spatial_hash_bits = table_bits.filter(|_| self.dim == 2).map(|table_bits| table_bits.clamp(1, spatial_hash::MAX_TABLE_BITS));
+        self.set_key_compression(self.key_compression_world);
+    }
+
+    /// Bits of the spatial hash table, `None` with morton cell ids.
+    pub fn spatial_hash_bits(&self) -> Option<u32> {
+        self.spatial_hash_bits
+    }
+
+    /// Wraps the grid around a periodic world of size `period` starting at the origin, or stops wrapping with
+    /// `None`. The positions are wrapped into the world and the neighbour cells past an edge are the cells of
+    /// the opposite edge, so the particles on both sides of the seam share collision cells. The world is split
+    /// into `periodic_cells` cells per axis, slightly larger than `cell_size`; the debug views, the cell labels and
+    /// the occupancy and region queries still assume cells of `cell_size`. Only used by 2D grids.
+    pub fn set_period(&mut self, period: Option<Vec2>){
+        self.period = period.filter(|period| self.dim == 2 && period.min_element() > 0.0);
+    }
+
+    /// Size of the periodic world, `None` if the grid does not wrap.
+    pub fn period(&self) -> Option<Vec2> {
+        self.period
+    }
+
+    /// Cells per axis of a periodic world of size `period`: as many cells of at least `cell_size` as fit, rounded
+    /// down to an even number so the colors of the collision cells keep alternating across the seam, and at
+    /// least 2. Must match `periodic_cells` in grid.wgsl and collision_solver.wgsl.
+    pub fn periodic_cells(period: Vec2, cell_size: f32) -> UVec2 {
+        ((period / (2.0 * cell_size)).floor() * 2.0).max(Vec2::splat(2.0)).as_uvec2()
+    }
+
+    /// Low bits of the cell ids the map is sorted on, see `set_key_compression`.
+    pub fn sort_key_bits(&self) -> u32 {
+        self.grid_kernels.gpu_sorter.key_bits()
+    }
+
+    /// Radix passes of the sort of the map, see `set_key_compression`.
+    pub fn sort_passes(&self) -> u32 {
+        self.grid_kernels.gpu_sorter.num_passes()
+    }
+
+    /// Compacts the map after the cell id build, so the sort and the collision cell builder are dispatched
+    /// for the used cell ids only instead of `MAX_CELLS_PER_OBJECT` slots per particle, see `CellCompaction`.
+    /// The used cell count stays on the GPU, in `LiveCountArgs::used_cells`. Works with both dispatch modes.
+    pub fn set_cell_compaction(&mut self, wgpu_context: &WgpuContext, enabled: bool){
+        if enabled == self.cell_compaction.is_some() {
+            return;
+        }
+        self.cell_compaction = enabled.then(|| CellCompaction::new(wgpu_context, self.capacity(), self.max_cells_per_object(), WORKGROUP_SIZE.0, &self.compaction_targets()));
+        if enabled {
+            // The first compaction covers every chunk the collision cell builder may have counted before
+            self.live_count.set_previous_used_cells(wgpu_context, self.grid_buffers.cell_ids.len() as u32);
+        }
+        self.rebind_cell_id_build();
+    }
+
+    pub fn is_cell_compaction(&self) -> bool {
+        self.cell_compaction.is_some()
+    }
+
+    fn compaction_targets(&self) -> CompactionTargets<'_> {
+        CompactionTargets {
+            cell_ids: &self.grid_buffers.cell_ids,
+            object_ids: &self.grid_buffers.object_ids,
+            live_count: &self.live_count,
+            sorter: &self.grid_kernels.gpu_sorter,
+        }
+    }
+
+    fn rebind_cell_id_build(&mut self){
+        self.bindings.rebind(Self::bindings_builder(
+            &self.grid_buffers,
+            self.cell_compaction.as_ref(),
+            &self.live_count,
+            &self.positions,
+            &self.radii
+        ));
+    }
+
+    pub fn live_count(&self) -> &LiveParticleCount {
+        &self.live_count
+    }
+
+    /// Sets the live particle count from the CPU, see `LiveParticleCount::set_live_particles`.
+    pub fn set_live_particles(&self, wgpu_context: &WgpuContext, live_particles: u32){
+        self.live_count.set_live_particles(wgpu_context, live_particles);
     }
     
     pub fn download_cell_ids(&mut self, wgpu_context: &WgpuContext) ->  Result<Vec<u32>, BufferAsyncError>{
@@ -319,7 +583,12 @@ impl Grid {
         Ok(self.grid_buffers.object_ids.download(wgpu_context)?.clone())
     }
     
-    pub fn update(&mut self, encoder: &mut CommandEncoder, gpu_profiler: &mut GpuProfiler){
+    pub fn update(&mut self, encoder: &mut CommandEncoder, gpu_profiler: &GpuProfiler){
+        if self.indirect_dispatch {
+            let mut scope = gpu_profiler.scope("Prepare grid dispatch", encoder);
+            self.prepare_indirect_dispatch(&mut scope);
+        }
+
         {
             let mut scope = gpu_profiler.scope("Build cell ids", encoder);
             self.build_cell_ids(&mut scope);
@@ -331,6 +600,21 @@ impl Grid {
         }
     }
     
+    /// Adds the passes of `update` to `frame_graph`, one per pass of the step.
+    pub fn register_passes(&mut self, frame_graph: &mut FrameGraph, gpu_profiler: &GpuProfiler){
+        if self.indirect_dispatch {
+            frame_graph.add_pass(PhysicsPass::PrepareGridDispatch, gpu_profiler, |encoder, _| self.prepare_indirect_dispatch(encoder));
+        }
+        frame_graph.add_pass(PhysicsPass::BuildCellIds, gpu_profiler, |encoder, _| self.build_cell_ids(encoder));
+        frame_graph.add_pass(PhysicsPass::SortMap, gpu_profiler, |encoder, _| self.sort_map(encoder));
+    }
+
+    /// Replaces the cell id / object id map, e.g. with captured data to replay the sort in isolation.
+    pub fn load_cell_ids(&mut self, wgpu_context: &WgpuContext, cell_ids: &[u32], object_ids: &[u32]){
+        self.grid_buffers.cell_ids.overwrite(cell_ids, wgpu_context);
+        self.grid_buffers.object_ids.overwrite(object_ids, wgpu_context);
+    }
+
     pub fn object_ids(&self) -> &GpuBuffer<u32>{
         &self.grid_buffers.object_ids
     }
@@ -341,11 +625,11 @@ impl Grid {
 
 }
 
-
+#[cfg(feature = "windowing")]
 impl Renderable for Grid {
     fn draw(&self, render_pass: &mut wgpu::RenderPass, camera: &Camera){
-        if self.should_draw_grid {
-            self.grid_drawer.as_ref().expect("Not drawing grid lines").draw(render_pass, camera);
+        if self.should_draw_grid || self.debug_view.is_some() {
+            self.grid_drawer.as_ref().expect("Not drawing grid lines").draw(render_pass, camera, self.should_draw_grid);
         }
     }
 }
\ No newline at end of file
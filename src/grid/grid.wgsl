@@ -25,31 +25,87 @@ struct DispatchArgs {
 @group(0) @binding(2) var<storage, read_write> cell_ids: array<u32>;
 @group(0) @binding(3) var<storage, read_write> object_ids: array<u32>;
 @group(0) @binding(4) var<storage, read> radius: array<f32>;
+// Only read when num_particles is INDIRECT_COUNT, see live_count.wgsl
+@group(0) @binding(5) var<storage, read> live_count: LiveCount;
 
 
 struct PushConstantsBuildGrid {
     cell_size: f32,
     num_particles: u32,
+    // World position of the corner of cell (0, 0)
+    origin: vec2<f32>,
+    // Bits of the spatial hash table, 0 for morton cell ids, see spatial_hash.rs
+    table_bits: u32,
+    _padding: u32,
+    // Size of the periodic world, zero if the grid does not wrap, see Grid::set_period
+    period: vec2<f32>,
 }
 
 var<push_constant> push_constants_build_grid: PushConstantsBuildGrid;
 
+// num_particles value of a dispatch driven by the live particle count
+const INDIRECT_COUNT = 0xffffffffu;
+
+struct LiveCount {
+    build_args: array<u32, 3>,
+    chunks_args: array<u32, 3>,
+    live_particles: u32,
+    previous_live_particles: u32,
+    covered_particles: u32,
+    capacity: u32,
+    used_cells: u32,
+    previous_used_cells: u32,
+};
+
+/// Clears the cell ids of the particles removed since the last step.
+/// Returns false if obj_id is not a live particle.
+fn is_live_particle(obj_id: u32) -> bool {
+    if push_constants_build_grid.num_particles != INDIRECT_COUNT {
+        return obj_id < push_constants_build_grid.num_particles;
+    }
+    if obj_id >= min(live_count.live_particles, live_count.capacity) {
+        if obj_id < live_count.covered_particles {
+            for (var i = 0u; i < MAX_CELLS_PER_OBJECT; i++) {
+                cell_ids[obj_id * MAX_CELLS_PER_OBJECT + i] = UNUSED_CELL_ID;
+            }
+        }
+        return false;
+    }
+    return true;
+}
+
 
 @compute @workgroup_size(WORKGROUP_SIZE)
 fn build_cell_ids_array(@builtin(global_invocation_id) global_id: vec3<u32>){
 
     let obj_id = global_id.x;
 
-    if obj_id >= push_constants_build_grid.num_particles {
+    if !is_live_particle(obj_id) {
         return;
     }
 
-    let pos = positions[obj_id];
+    // Relative to the grid origin, so worlds centered on (0, 0) get the same cells
+    var pos = positions[obj_id] - push_constants_build_grid.origin;
     let radius = radius[obj_id];
     let sq_radius = radius*radius;
 
+    // A periodic world is split into an even number of cells per axis, a bit larger than cell_size
+    let period = push_constants_build_grid.period;
+    let periodic = period.x > 0.0;
+    var cell_extent = vec2<f32>(push_constants_build_grid.cell_size);
+    if periodic {
+        // The collision solver can push particles slightly past the seam
+        pos -= period * floor(pos / period);
+        cell_extent = period / vec2<f32>(periodic_cells(period));
+    }
+
     // Convert to grid coordinates.
-    let home_cell_coord = vec2<i32>(floor(pos / push_constants_build_grid.cell_size));
+    // The collision solver can push particles slightly below the origin. Negative coordinates would wrap
+    // in the morton code, and cell (-1, -1) would even hash to UNUSED_CELL_ID, so they are clamped.
+    // The spatial hash takes any coordinates, the periodic cells are wrapped instead.
+    let hashed = push_constants_build_grid.table_bits != 0u;
+    let cell_coord = vec2<i32>(floor(pos / cell_extent));
+    let home_cell_coord = select(max(cell_coord, vec2<i32>(0)), cell_coord, hashed || periodic);
 
     // This is the base index for the cell ids and object ids array, for this object.
     let output_base_idx: u32 = obj_id * MAX_CELLS_PER_OBJECT;
@@ -58,7 +114,7 @@ fn build_cell_ids_array(@builtin(global_invocation_id) global_id: vec3<u32>){
     // Step 1:
     // Always store the home (H) cell
     // The H cell is where the object center is located
-    let home_cell_hash = morton_encode(home_cell_coord);
+    let home_cell_hash = cell_id(wrap_cell(home_cell_coord));
     cell_ids[output_base_idx] = home_cell_hash;
     object_ids[output_base_idx] = obj_id;
 
@@ -75,13 +131,16 @@ fn build_cell_ids_array(@builtin(global_invocation_id) global_id: vec3<u32>){
 
             let offset = vec2<i32>(x, y);
             let neighbour_coord = home_cell_coord + offset;
+            if !hashed && !periodic && any(neighbour_coord < vec2<i32>(0)) {
+                continue;
+            }
 
-            if is_obj_in_cell(pos, sq_radius, neighbour_coord) {
+            if is_obj_in_cell(pos, sq_radius, neighbour_coord, cell_extent) {
                 // The object was found in the neighbour cell
                 // Thus, this is a phantom (P) cell
                 p_cell_count++;
                 let output_idx = output_base_idx + p_cell_count;
-                cell_ids[output_idx] = morton_encode(neighbour_coord);
+                cell_ids[output_idx] = cell_id(wrap_cell(neighbour_coord));
                 object_ids[output_idx] = obj_id;
             }
         }
@@ -96,6 +155,38 @@ fn build_cell_ids_array(@builtin(global_invocation_id) global_id: vec3<u32>){
 
 }
 
+/// Must match Grid::periodic_cells: the cells of at least cell_size that fit, an even number and at least 2.
+fn periodic_cells(period: vec2<f32>) -> vec2<i32> {
+    return vec2<i32>(max(floor(period / (2.0 * push_constants_build_grid.cell_size)) * 2.0, vec2<f32>(2.0)));
+}
+
+/// The cell of a periodic world a cell past its edges stands for, the cell itself otherwise.
+fn wrap_cell(cell_coord: vec2<i32>) -> vec2<i32> {
+    let period = push_constants_build_grid.period;
+    if period.x <= 0.0 {
+        return cell_coord;
+    }
+    let num_cells = periodic_cells(period);
+    return ((cell_coord % num_cells) + num_cells) % num_cells;
+}
+
+/// Morton or hashed id of a cell, depending on the table bits.
+fn cell_id(cell_coord: vec2<i32>) -> u32 {
+    if push_constants_build_grid.table_bits != 0u {
+        return spatial_hash_encode(cell_coord, push_constants_build_grid.table_bits);
+    }
+    return morton_encode(cell_coord);
+}
+
+/// Must match spatial_hash::encode: the hashed 2x2 block of the cell, then its position in the block (its color).
+fn spatial_hash_encode(cell_coord: vec2<i32>, table_bits: u32) -> u32 {
+    let block = vec2<u32>(cell_coord >> vec2<u32>(1u));
+    let hash = (block.x * 73856093u) ^ (block.y * 19349663u);
+    let slot = hash & ((1u << table_bits) - 1u);
+    let parity = vec2<u32>(cell_coord & vec2<i32>(1));
+    return slot * 4u + parity.x + parity.y * 2u;
+}
+
 /// Spreads the lower 16 bits of an integer to every other bit.
 /// Example (2-bit): n = 3 (binary 11) becomes 5 (binary 0101).
 fn split_by_bits(n: u32) -> u32 {
@@ -114,9 +205,9 @@ fn morton_encode(v: vec2<i32>) -> u32 {
 }
 
 
-fn is_obj_in_cell(particle_pos: vec2<f32>, particle_sq_radius: f32, cell_coord: vec2<i32>) -> bool {
-    let cell_bottom_left_corner: vec2<f32> = vec2<f32>(cell_coord) * push_constants_build_grid.cell_size;
-    let cell_top_right_corner: vec2<f32> = cell_bottom_left_corner + vec2<f32>(push_constants_build_grid.cell_size);
+fn is_obj_in_cell(particle_pos: vec2<f32>, particle_sq_radius: f32, cell_coord: vec2<i32>, cell_extent: vec2<f32>) -> bool {
+    let cell_bottom_left_corner: vec2<f32> = vec2<f32>(cell_coord) * cell_extent;
+    let cell_top_right_corner: vec2<f32> = cell_bottom_left_corner + cell_extent;
 
     // Closest point to the object center
     let closest_point = clamp(particle_pos, cell_bottom_left_corner, cell_top_right_corner);
@@ -0,0 +1,139 @@
+override WORKGROUP_SIZE = 64u;
+// For 3D, an object can touch at most 2^3 = 8 cells.
+override MAX_CELLS_PER_OBJECT = 8u;
+
+const UNUSED_CELL_ID = 0xffffffffu;
+// 10 bits per axis in the morton code
+const MAX_CELL_COORD = 1023;
+
+// Bindings for the Compute Shader
+// xyz = position, w unused
+@group(0) @binding(0) var<storage, read_write> positions: array<vec4<f32>>;
+@group(0) @binding(2) var<storage, read_write> cell_ids: array<u32>;
+@group(0) @binding(3) var<storage, read_write> object_ids: array<u32>;
+@group(0) @binding(4) var<storage, read> radius: array<f32>;
+// Only read when num_particles is INDIRECT_COUNT, see live_count.wgsl
+@group(0) @binding(5) var<storage, read> live_count: LiveCount;
+
+
+struct PushConstantsBuildGrid {
+    cell_size: f32,
+    num_particles: u32,
+}
+
+var<push_constant> push_constants_build_grid: PushConstantsBuildGrid;
+
+// num_particles value of a dispatch driven by the live particle count
+const INDIRECT_COUNT = 0xffffffffu;
+
+struct LiveCount {
+    build_args: array<u32, 3>,
+    chunks_args: array<u32, 3>,
+    live_particles: u32,
+    previous_live_particles: u32,
+    covered_particles: u32,
+    capacity: u32,
+    used_cells: u32,
+    previous_used_cells: u32,
+};
+
+/// Clears the cell ids of the particles removed since the last step.
+/// Returns false if obj_id is not a live particle.
+fn is_live_particle(obj_id: u32) -> bool {
+    if push_constants_build_grid.num_particles != INDIRECT_COUNT {
+        return obj_id < push_constants_build_grid.num_particles;
+    }
+    if obj_id >= min(live_count.live_particles, live_count.capacity) {
+        if obj_id < live_count.covered_particles {
+            for (var i = 0u; i < MAX_CELLS_PER_OBJECT; i++) {
+                cell_ids[obj_id * MAX_CELLS_PER_OBJECT + i] = UNUSED_CELL_ID;
+            }
+        }
+        return false;
+    }
+    return true;
+}
+
+
+// Same as build_cell_ids_array of grid.wgsl, with the 26 neighbours of the home cell.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn build_cell_ids_array(@builtin(global_invocation_id) global_id: vec3<u32>){
+
+    let obj_id = global_id.x;
+
+    if !is_live_particle(obj_id) {
+        return;
+    }
+
+    let pos = positions[obj_id].xyz;
+    let radius = radius[obj_id];
+    let sq_radius = radius*radius;
+
+    // Clamped to the cells the morton code can address, see grid.wgsl
+    let home_cell_coord = clamp(vec3<i32>(floor(pos / push_constants_build_grid.cell_size)), vec3<i32>(0), vec3<i32>(MAX_CELL_COORD));
+
+    let output_base_idx: u32 = obj_id * MAX_CELLS_PER_OBJECT;
+
+    // Step 1: the home (H) cell
+    cell_ids[output_base_idx] = morton_encode(home_cell_coord);
+    object_ids[output_base_idx] = obj_id;
+
+    // Step 2: the phantom (P) cells among the 26 neighbours
+    var p_cell_count = 0u;
+    for (var z = -1; z <= 1; z++ ){
+        for (var y = -1; y <= 1; y++ ){
+            for (var x = -1; x <= 1; x++){
+                if x == 0 && y == 0 && z == 0 {
+                    continue;
+                }
+
+                let neighbour_coord = home_cell_coord + vec3<i32>(x, y, z);
+                if any(neighbour_coord < vec3<i32>(0)) || any(neighbour_coord > vec3<i32>(MAX_CELL_COORD)) {
+                    continue;
+                }
+
+                if p_cell_count + 1u < MAX_CELLS_PER_OBJECT && is_obj_in_cell(pos, sq_radius, neighbour_coord) {
+                    p_cell_count++;
+                    let output_idx = output_base_idx + p_cell_count;
+                    cell_ids[output_idx] = morton_encode(neighbour_coord);
+                    object_ids[output_idx] = obj_id;
+                }
+            }
+        }
+    }
+
+    // Step 3: mark the unused slots
+    for(var i = p_cell_count+1; i < MAX_CELLS_PER_OBJECT; i++){
+        cell_ids[output_base_idx + i] = UNUSED_CELL_ID;
+    }
+}
+
+/// Spreads the lower 10 bits of an integer to every third bit.
+/// Example: n = 3 (binary 11) becomes 9 (binary 001001).
+fn split_by_3_bits(n: u32) -> u32 {
+    var x = n & 0x000003FF;
+    x = (x | (x << 16)) & 0x030000FF;
+    x = (x | (x << 8)) & 0x0300F00F;
+    x = (x | (x << 4)) & 0x030C30C3;
+    x = (x | (x << 2)) & 0x09249249;
+    return x;
+}
+
+/// Encodes 3D coordinates (10-bit max) into a 1D Morton index. Must match morton::encode_3d.
+fn morton_encode(v: vec3<i32>) -> u32 {
+    return split_by_3_bits(u32(v.x)) | (split_by_3_bits(u32(v.y)) << 1) | (split_by_3_bits(u32(v.z)) << 2);
+}
+
+
+fn is_obj_in_cell(particle_pos: vec3<f32>, particle_sq_radius: f32, cell_coord: vec3<i32>) -> bool {
+    let cell_min_corner: vec3<f32> = vec3<f32>(cell_coord) * push_constants_build_grid.cell_size;
+    let cell_max_corner: vec3<f32> = cell_min_corner + vec3<f32>(push_constants_build_grid.cell_size);
+
+    // Closest point to the object center
+    let closest_point = clamp(particle_pos, cell_min_corner, cell_max_corner);
+
+    let distance_vec: vec3<f32> = particle_pos - closest_point;
+    let dist_sq: f32 = dot(distance_vec, distance_vec);
+
+    return dist_sq < particle_sq_radius;
+}
@@ -1,27 +1,277 @@
 use glam::{Vec2, Vec4};
+use wgpu::{BindGroup, BindGroupLayout};
+use crate::grid::cell_labels;
+use crate::grid::cell_occupancy_map::{CellOccupancyMap, GridDebugView};
 use crate::lines::lines::Lines;
+use crate::particles::particle_color_kernel::ColorMap;
 use crate::renderer::camera::Camera;
 use crate::renderer::renderable::Renderable;
+use crate::renderer::stroke_font;
 use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::profiler::GpuProfiler;
+
+const LABEL_COLOR: Vec4 = Vec4::new(1.0, 0.85, 0.2, 1.0);
+const LEGEND_TEXT_COLOR: Vec4 = Vec4::new(0.9, 0.9, 0.9, 1.0);
+const LEGEND_BACKGROUND_COLOR: Vec4 = Vec4::new(0.0, 0.0, 0.0, 0.7);
+/// Sizes of the legend in pixels, like the profiler overlay: the text height, the color bar and the margin.
+const LEGEND_TEXT_HEIGHT: f32 = 10.0;
+const LEGEND_BAR_WIDTH: f32 = 160.0;
+const LEGEND_BAR_HEIGHT: f32 = 10.0;
+const LEGEND_MARGIN: f32 = 10.0;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct OccupancyPushConstants {
+    origin: Vec2,
+    cell_size: f32,
+    table_width: u32,
+    view: u32,
+    max_value: f32,
+}
 
 pub struct GridDrawer {
     lines: Lines,
-
+    /// Morton ids of the visible cells, see `update_labels`
+    labels: Lines,
+    origin: Vec2,
+    world_dimensions: Vec2,
+    cell_size: f32,
+    /// View rectangle and zoom of the current labels
+    labelled_view: Option<(Vec2, Vec2, f32)>,
+    /// Cells colored by their objects, see `update_debug_view`
+    occupancy_map: CellOccupancyMap,
+    occupancy_pipeline: wgpu::RenderPipeline,
+    occupancy_bind_group_layout: BindGroupLayout,
+    occupancy_bind_group: BindGroup,
+    legend: Lines,
+    debug_view: Option<GridDebugView>,
 }
 
 impl GridDrawer {
-    pub fn new(wgpu_context: &WgpuContext, camera: &Camera, world_dimensions: &Vec2, cell_size: f32) -> Self {
-        let lines = Self::create_grid_lines(wgpu_context, camera, world_dimensions.clone(), cell_size);
+    /// Lines of the cells covering the world, which starts at `origin`.
+    pub fn new(wgpu_context: &WgpuContext, camera: &Camera, origin: Vec2, world_dimensions: &Vec2, cell_size: f32) -> Self {
+        let lines = Self::create_grid_lines(wgpu_context, camera, origin, *world_dimensions, cell_size);
+        let occupancy_map = CellOccupancyMap::new(wgpu_context);
+        let occupancy_bind_group_layout = Self::create_occupancy_bind_group_layout(wgpu_context);
+        let occupancy_bind_group = Self::create_occupancy_bind_group(wgpu_context, &occupancy_bind_group_layout, occupancy_map.counts());
+        let occupancy_pipeline = Self::create_occupancy_pipeline(wgpu_context, camera, &occupancy_bind_group_layout);
         Self {
             lines,
+            labels: Lines::new(wgpu_context, camera),
+            origin,
+            world_dimensions: *world_dimensions,
+            cell_size,
+            labelled_view: None,
+            occupancy_map,
+            occupancy_pipeline,
+            occupancy_bind_group_layout,
+            occupancy_bind_group,
+            legend: Lines::new(wgpu_context, camera),
+            debug_view: None,
         }
     }
     
-    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, camera: &Camera) {
-        self.lines.draw(render_pass, camera);       
+    /// True if the lines were built for this world and cell size, so they need no rebuild.
+    pub fn is_built_for(&self, origin: Vec2, world_dimensions: Vec2, cell_size: f32) -> bool {
+        self.origin == origin && self.world_dimensions == world_dimensions && self.cell_size == cell_size
+    }
+
+    /// Draws the cells of the debug view, if any, then the grid lines if `draw_lines`, then the legend.
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, camera: &Camera, draw_lines: bool) {
+        if let Some(view) = self.debug_view {
+            let table_size = self.occupancy_map.table_size();
+            let push_constants = OccupancyPushConstants {
+                origin: self.origin,
+                cell_size: self.cell_size,
+                table_width: table_size.x,
+                view: match view {
+                    GridDebugView::Occupancy => 0,
+                    GridDebugView::PairTests => 1,
+                },
+                max_value: view.max_value() as f32,
+            };
+            render_pass.set_pipeline(&self.occupancy_pipeline);
+            render_pass.set_bind_group(0, &self.occupancy_bind_group, &[]);
+            render_pass.set_bind_group(1, camera.binding_group(), &[]);
+            render_pass.set_push_constants(wgpu::ShaderStages::VERTEX, 0, bytemuck::bytes_of(&push_constants));
+            render_pass.draw(0..6, 0..table_size.x * table_size.y);
+        }
+        if draw_lines {
+            self.lines.draw(render_pass, camera);
+            self.labels.draw(render_pass, camera);
+        }
+        if self.debug_view.is_some() {
+            self.legend.draw(render_pass, camera);
+        }
+    }
+
+    /// Counts the objects of every cell from `cell_ids`, see `Grid::cell_ids`, and lays the legend out for the
+    /// current view of the camera, once per frame after the steps. `None` stops drawing the cells.
+    pub fn update_debug_view(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, cell_ids: &GpuBuffer<u32>, view: Option<GridDebugView>, camera: &Camera, screen_size: &Vec2) {
+        self.debug_view = view;
+        self.legend.clear();
+        let Some(view) = view else {
+            return;
+        };
+        let table_size = (self.world_dimensions / self.cell_size).ceil().as_uvec2();
+        if self.occupancy_map.update(wgpu_context, gpu_profiler, cell_ids, table_size) {
+            self.occupancy_bind_group = Self::create_occupancy_bind_group(wgpu_context, &self.occupancy_bind_group_layout, self.occupancy_map.counts());
+        }
+        self.update_legend(wgpu_context, view, camera, screen_size);
+    }
+
+    /// Color bar of the values from 1 to the saturated one in the bottom left corner of the window, under its title.
+    fn update_legend(&mut self, wgpu_context: &WgpuContext, view: GridDebugView, camera: &Camera, screen_size: &Vec2) {
+        // Screen pixels to world: the legend is laid out from the bottom left corner, with y up
+        let bottom_left = camera.screen_to_world(screen_size, &Vec2::new(0.0, screen_size.y));
+        let pixel = 1.0 / camera.zoom;
+        let to_world = |point: Vec2| bottom_left + point * pixel;
+
+        let max_value = view.max_value();
+        let numbers_baseline = LEGEND_MARGIN;
+        let bar_bottom = numbers_baseline + LEGEND_TEXT_HEIGHT + 4.0;
+        let title_baseline = bar_bottom + LEGEND_BAR_HEIGHT + 4.0;
+        let width = LEGEND_BAR_WIDTH.max(stroke_font::text_width(view.label(), LEGEND_TEXT_HEIGHT));
+        let height = title_baseline + LEGEND_TEXT_HEIGHT + LEGEND_MARGIN;
+
+        let mut positions = Vec::new();
+        let mut colors = Vec::new();
+        // Filled with one line per pixel row, `Lines` has no quads
+        for y in 0..height as u32 {
+            let y = y as f32;
+            positions.extend([to_world(Vec2::new(0.0, y)), to_world(Vec2::new(width + 2.0 * LEGEND_MARGIN, y))]);
+            colors.extend([LEGEND_BACKGROUND_COLOR; 2]);
+        }
+        // One column per pixel, from a value of 1 to max_value, like occupancy_drawer.wgsl colors the cells
+        for x in 0..LEGEND_BAR_WIDTH as u32 {
+            let value = 1.0 + (max_value - 1) as f32 * x as f32 / (LEGEND_BAR_WIDTH - 1.0);
+            let color = ColorMap::Heat.sample(value / max_value as f32);
+            let x = LEGEND_MARGIN + x as f32;
+            positions.extend([to_world(Vec2::new(x, bar_bottom)), to_world(Vec2::new(x, bar_bottom + LEGEND_BAR_HEIGHT))]);
+            colors.extend([color; 2]);
+        }
+        let max_label = format!("{}+", max_value);
+        let texts = [
+            (view.label().to_string(), Vec2::new(LEGEND_MARGIN, title_baseline)),
+            ("1".to_string(), Vec2::new(LEGEND_MARGIN, numbers_baseline)),
+            (max_label.clone(), Vec2::new(LEGEND_MARGIN + LEGEND_BAR_WIDTH - stroke_font::text_width(&max_label, LEGEND_TEXT_HEIGHT), numbers_baseline)),
+        ];
+        for (text, bottom_left) in texts {
+            for (start, end) in stroke_font::text_strokes(&text, bottom_left, LEGEND_TEXT_HEIGHT) {
+                positions.extend([to_world(start), to_world(end)]);
+                colors.extend([LEGEND_TEXT_COLOR; 2]);
+            }
+        }
+        let thicknesses = vec![1.0; positions.len()];
+        self.legend.push_all(wgpu_context, &positions, &colors, &thicknesses);
+    }
+
+    /// Labels the cells in the view of the camera with their morton id, when the camera is zoomed in
+    /// enough, see `cell_labels::MIN_LABEL_CELL_PIXELS`. The labels are only rebuilt when the view moves.
+    pub fn update_labels(&mut self, wgpu_context: &WgpuContext, camera: &Camera, screen_size: &Vec2) {
+        let view_min = camera.screen_to_world(screen_size, &Vec2::new(0.0, screen_size.y));
+        let view_max = camera.screen_to_world(screen_size, &Vec2::new(screen_size.x, 0.0));
+        let view = (view_min, view_max, camera.zoom);
+        if self.labelled_view == Some(view) {
+            return;
+        }
+        self.labelled_view = Some(view);
+
+        let strokes = cell_labels::cell_label_strokes(view_min, view_max, self.origin, self.world_dimensions, self.cell_size, camera.zoom);
+        self.labels.clear();
+        if strokes.is_empty() {
+            return;
+        }
+        let positions: Vec<Vec2> = strokes.iter().flat_map(|(start, end)| [*start, *end]).collect();
+        let colors = vec![LABEL_COLOR; positions.len()];
+        let thicknesses = vec![1.0; positions.len()];
+        self.labels.push_all(wgpu_context, &positions, &colors, &thicknesses);
     }
     
-    fn create_grid_lines(wgpu_context: &WgpuContext, camera: &Camera, world_dimensions: Vec2, cell_size: f32) -> Lines {
+    fn create_occupancy_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Occupancy drawer bind group layout"),
+            entries: &[
+                // Counts
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    fn create_occupancy_bind_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, counts: &GpuBuffer<u32>) -> BindGroup {
+        wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Occupancy drawer bind group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: counts.buffer().as_entire_binding() },
+            ],
+        })
+    }
+
+    fn create_occupancy_pipeline(wgpu_context: &WgpuContext, camera: &Camera, bind_group_layout: &BindGroupLayout) -> wgpu::RenderPipeline {
+        let shader = wgpu_context.get_device().create_shader_module(wgpu::include_wgsl!("occupancy_drawer.wgsl"));
+        let render_pipeline_layout = wgpu_context.get_device().create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Occupancy render pipeline layout"),
+            bind_group_layouts: &[bind_group_layout, camera.camera_bind_group_layout()],
+            push_constant_ranges: &[
+                wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStages::VERTEX,
+                    range: 0..size_of::<OccupancyPushConstants>() as u32,
+                }
+            ],
+        });
+
+        wgpu_context.get_device().create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Occupancy render pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                // The quads come from the vertex and instance indices
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: wgpu_context.surface_format(),
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: wgpu_context.pipeline_cache(),
+        })
+    }
+
+    fn create_grid_lines(wgpu_context: &WgpuContext, camera: &Camera, origin: Vec2, world_dimensions: Vec2, cell_size: f32) -> Lines {
         let mut lines = Lines::new(wgpu_context, camera);
 
         let num_vertical_lines = world_dimensions.x / cell_size;
@@ -33,8 +283,8 @@ impl GridDrawer {
         let mut thicknesses: Vec<f32> = Vec::new();
 
         for i in 0..num_vertical_lines.ceil() as u32{
-            start = Vec2::new(i as f32 * cell_size, 0.0);
-            end = Vec2::new(i as f32 * cell_size, world_dimensions.y);
+            start = origin + Vec2::new(i as f32 * cell_size, 0.0);
+            end = origin + Vec2::new(i as f32 * cell_size, world_dimensions.y);
             positions.push(start);
             positions.push(end);
             colors.push(Vec4::new(1.0, 1.0, 1.0, 1.0)); // Color for start point
@@ -45,8 +295,8 @@ impl GridDrawer {
 
         let num_horizontal_lines = world_dimensions.y / cell_size;
         for i in 0..num_horizontal_lines.ceil() as u32 {
-            start = Vec2::new(0.0, i as f32 * cell_size);
-            end = Vec2::new(world_dimensions.x, i as f32 * cell_size);
+            start = origin + Vec2::new(0.0, i as f32 * cell_size);
+            end = origin + Vec2::new(world_dimensions.x, i as f32 * cell_size);
             positions.push(start);
             positions.push(end);
             colors.push(Vec4::new(1.0, 1.0, 1.0, 1.0)); // Color for start point
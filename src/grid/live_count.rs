@@ -0,0 +1,165 @@
+use wgpu::{BindGroupLayout, CommandEncoder};
+use crate::physics::collision_cell_builder::{COUNTING_CHUNK_SIZE, WORKGROUP_SIZE as CHUNK_WORKGROUP_SIZE};
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::bind_resources::BindResources;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::radix_sort::{GPUSorter, SortIndirectArgs};
+
+/// Byte offset of the indirect args of the cell id build kernel.
+pub const BUILD_CELL_IDS_ARGS_OFFSET: u64 = 0;
+/// Byte offset of the indirect args of the collision cell builder kernels.
+pub const COLLISION_CHUNKS_ARGS_OFFSET: u64 = 3 * size_of::<u32>() as u64;
+const LIVE_PARTICLES_OFFSET: u64 = 6 * size_of::<u32>() as u64;
+const CAPACITY_OFFSET: u64 = 9 * size_of::<u32>() as u64;
+const PREVIOUS_USED_CELLS_OFFSET: u64 = 11 * size_of::<u32>() as u64;
+
+/// Contents of the live count buffer, see live_count.wgsl.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LiveCountArgs {
+    pub build_cell_ids: [u32; 3],
+    pub collision_chunks: [u32; 3],
+    /// Particles the next step works on, the first `live_particles` of the particle buffers
+    pub live_particles: u32,
+    pub previous_live_particles: u32,
+    /// Particles whose cell ids are rebuilt by the next step, includes the ones removed since the last step
+    pub covered_particles: u32,
+    /// Particles the grid buffers can hold
+    pub capacity: u32,
+    /// Cell ids left by the last cell id compaction, see `CellCompaction`
+    pub used_cells: u32,
+    pub previous_used_cells: u32,
+}
+
+/// Number of live particles on the GPU, and the dispatch sizes of the grid kernels derived from it.
+/// `prepare` runs a single thread that writes the indirect args of the cell id build, the sort and the
+/// collision cell builder, so GPU kernels can change `live_particles` without a CPU round trip.
+pub struct LiveParticleCount {
+    args: GpuBuffer<LiveCountArgs>,
+    prepare_shader: ComputeShader,
+    bind_resources: BindResources,
+}
+
+impl LiveParticleCount {
+    pub fn new(wgpu_context: &WgpuContext, num_particles: usize, max_cells_per_object: u32, grid_workgroup_size: u32, sorter: &GPUSorter) -> Self {
+        let num_particles = num_particles as u32;
+        let args = GpuBuffer::new(
+            wgpu_context,
+            vec![LiveCountArgs {
+                build_cell_ids: [0, 1, 1],
+                collision_chunks: [1, 1, 1],
+                live_particles: num_particles,
+                previous_live_particles: num_particles,
+                covered_particles: num_particles,
+                capacity: num_particles,
+                used_cells: num_particles * max_cells_per_object,
+                previous_used_cells: num_particles * max_cells_per_object,
+            }],
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::COPY_DST
+        );
+
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let bind_group = Self::create_bind_group(wgpu_context, &bind_group_layout, &args, sorter.indirect_args());
+        let bind_resources = BindResources::new(bind_group_layout, bind_group);
+
+        let prepare_shader = ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("live_count.wgsl"),
+            "prepare_grid_dispatch",
+            &bind_resources.bind_group_layout,
+            (1, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", grid_workgroup_size as f64),
+                ("MAX_CELLS_PER_OBJECT", max_cells_per_object as f64),
+                ("CHUNK_SIZE", COUNTING_CHUNK_SIZE as f64),
+                ("CHUNK_WORKGROUP_SIZE", CHUNK_WORKGROUP_SIZE.0 as f64),
+                ("SORT_WORKGROUP_SIZE", sorter.workgroup_size() as f64),
+                ("SORT_BLOCKS_PER_WORKGROUP", sorter.blocks_per_workgroup() as f64),
+            ],
+            &vec![]
+        );
+
+        Self {
+            args,
+            prepare_shader,
+            bind_resources,
+        }
+    }
+
+    /// Sets the live particle count from the CPU. Only writes the count, the dispatch sizes are
+    /// computed by the next `prepare`.
+    pub fn set_live_particles(&self, wgpu_context: &WgpuContext, live_particles: u32) {
+        wgpu_context.get_queue().write_buffer(self.args.buffer(), LIVE_PARTICLES_OFFSET, bytemuck::bytes_of(&live_particles));
+        wgpu_context.transfers().record_upload(size_of::<u32>() as u64);
+    }
+
+    /// Must be called when the grid buffers grow.
+    pub fn set_capacity(&self, wgpu_context: &WgpuContext, capacity: u32) {
+        wgpu_context.get_queue().write_buffer(self.args.buffer(), CAPACITY_OFFSET, bytemuck::bytes_of(&capacity));
+        wgpu_context.transfers().record_upload(size_of::<u32>() as u64);
+    }
+
+    /// Makes the next cell id compaction dispatch the collision cell builder over `cells` cell ids at least,
+    /// so chunks counted before the compaction took over are rewritten once.
+    pub fn set_previous_used_cells(&self, wgpu_context: &WgpuContext, cells: u32) {
+        wgpu_context.get_queue().write_buffer(self.args.buffer(), PREVIOUS_USED_CELLS_OFFSET, bytemuck::bytes_of(&cells));
+        wgpu_context.transfers().record_upload(size_of::<u32>() as u64);
+    }
+
+    /// Writes the dispatch sizes of this step.
+    pub fn prepare(&self, encoder: &mut CommandEncoder) {
+        self.prepare_shader.dispatch(encoder, (1, 1, 1), None, &self.bind_resources.bind_group);
+    }
+
+    /// The live count buffer. Kernels that spawn or remove particles write `live_particles` in it.
+    pub fn args(&self) -> &GpuBuffer<LiveCountArgs> {
+        &self.args
+    }
+
+    pub fn download(&self, wgpu_context: &WgpuContext) -> LiveCountArgs {
+        self.args.read_back(wgpu_context).unwrap()[0]
+    }
+
+    fn create_bind_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, args: &GpuBuffer<LiveCountArgs>, sort_args: &GpuBuffer<SortIndirectArgs>) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                label: Some("Live count bind group"),
+                layout: bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: args.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: sort_args.buffer().as_entire_binding(),
+                    },
+                ],
+            }
+        )
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Live count bind group layout"),
+            entries: &[
+                // Live count
+                storage_entry(0, false),
+                // Sort indirect args
+                storage_entry(1, false),
+            ],
+        })
+    }
+}
@@ -0,0 +1,70 @@
+// Workgroup size of build_cell_ids_array
+override WORKGROUP_SIZE = 64u;
+override MAX_CELLS_PER_OBJECT = 4u;
+// Chunks and workgroup size of the collision cell builder
+override CHUNK_SIZE = 4u;
+override CHUNK_WORKGROUP_SIZE = 64u;
+// Radix sort, see SortIndirectArgs::new
+override SORT_WORKGROUP_SIZE = 256u;
+override SORT_BLOCKS_PER_WORKGROUP = 45u;
+
+struct LiveCount {
+    // Indirect args of build_cell_ids_array
+    build_x: u32,
+    build_y: u32,
+    build_z: u32,
+    // Indirect args of the collision cell builder kernels
+    chunks_x: u32,
+    chunks_y: u32,
+    chunks_z: u32,
+    // Written by the CPU or by the kernels that spawn/remove particles
+    live_particles: u32,
+    previous_live_particles: u32,
+    covered_particles: u32,
+    capacity: u32,
+    // Written by the cell id compaction, see cell_compaction.wgsl
+    used_cells: u32,
+    previous_used_cells: u32,
+};
+
+struct SortIndirectArgs {
+    x: u32,
+    y: u32,
+    z: u32,
+    num_elements: u32,
+    num_workgroups: u32,
+};
+
+@group(0) @binding(0) var<storage, read_write> live_count: LiveCount;
+@group(0) @binding(1) var<storage, read_write> sort_args: SortIndirectArgs;
+
+fn div_ceil(a: u32, b: u32) -> u32 {
+    return (a + b - 1u) / b;
+}
+
+/// Turns the live particle count into the dispatch sizes of the grid kernels.
+/// The particles removed since the last step are covered once more, so their cell ids are cleared.
+@compute @workgroup_size(1)
+fn prepare_grid_dispatch() {
+    let live = min(live_count.live_particles, live_count.capacity);
+    let covered = min(max(live, live_count.previous_live_particles), live_count.capacity);
+    live_count.previous_live_particles = live;
+    live_count.covered_particles = covered;
+
+    live_count.build_x = div_ceil(covered, WORKGROUP_SIZE);
+    live_count.build_y = 1u;
+    live_count.build_z = 1u;
+
+    // At least one workgroup: the collision cell builder also writes the solver dispatch
+    live_count.chunks_x = max(div_ceil(div_ceil(covered * MAX_CELLS_PER_OBJECT, CHUNK_SIZE), CHUNK_WORKGROUP_SIZE), 1u);
+    live_count.chunks_y = 1u;
+    live_count.chunks_z = 1u;
+
+    let num_elements = live * MAX_CELLS_PER_OBJECT;
+    let num_workgroups = div_ceil(div_ceil(num_elements, SORT_BLOCKS_PER_WORKGROUP), SORT_WORKGROUP_SIZE);
+    sort_args.x = num_workgroups;
+    sort_args.y = 1u;
+    sort_args.z = 1u;
+    sort_args.num_elements = num_elements;
+    sort_args.num_workgroups = num_workgroups;
+}
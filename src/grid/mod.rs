@@ -1,2 +1,13 @@
 pub mod grid;
-mod grid_drawer;
\ No newline at end of file
+pub mod live_count;
+pub mod cell_compaction;
+pub mod morton;
+pub mod spatial_hash;
+pub mod cell_occupancy_query;
+pub mod cell_occupancy_map;
+pub mod cell_labels;
+pub mod velocity_field;
+#[cfg(feature = "windowing")]
+mod grid_drawer;
+#[cfg(feature = "windowing")]
+pub mod velocity_field_drawer;
@@ -0,0 +1,101 @@
+//! CPU side of the morton encoding used by the GPU kernels (`grid.wgsl`, `grid_3d.wgsl`, `home_cell_ids.wgsl`,
+//! `collision_solver.wgsl`, `cell_occupancy_map.wgsl`). The results must match the shaders bit for bit.
+use glam::{UVec2, UVec3, Vec2, Vec3};
+
+/// Largest cell coordinate of the 3D encoding: 10 bits per axis.
+pub const MAX_CELL_COORD_3D: u32 = 0x3FF;
+
+/// Spreads the lower 16 bits of `n` to the even bit positions.
+fn split_by_bits(n: u32) -> u32 {
+    let mut x = n & 0x0000FFFF;
+    x = (x | (x << 8)) & 0x00FF00FF;
+    x = (x | (x << 4)) & 0x0F0F0F0F;
+    x = (x | (x << 2)) & 0x33333333;
+    x = (x | (x << 1)) & 0x55555555;
+    x
+}
+
+/// Inverse of `split_by_bits`.
+fn unsplit_by_bits(n: u32) -> u32 {
+    let mut x = n & 0x55555555;
+    x = (x | (x >> 1)) & 0x33333333;
+    x = (x | (x >> 2)) & 0x0F0F0F0F;
+    x = (x | (x >> 4)) & 0x00FF00FF;
+    x = (x | (x >> 8)) & 0x0000FFFF;
+    x
+}
+
+/// Spreads the lower 10 bits of `n` to every third bit position.
+fn split_by_3_bits(n: u32) -> u32 {
+    let mut x = n & 0x000003FF;
+    x = (x | (x << 16)) & 0x030000FF;
+    x = (x | (x << 8)) & 0x0300F00F;
+    x = (x | (x << 4)) & 0x030C30C3;
+    x = (x | (x << 2)) & 0x09249249;
+    x
+}
+
+/// Inverse of `split_by_3_bits`.
+fn unsplit_by_3_bits(n: u32) -> u32 {
+    let mut x = n & 0x09249249;
+    x = (x | (x >> 2)) & 0x030C30C3;
+    x = (x | (x >> 4)) & 0x0300F00F;
+    x = (x | (x >> 8)) & 0x030000FF;
+    x = (x | (x >> 16)) & 0x000003FF;
+    x
+}
+
+/// Encodes 2D cell coordinates (16-bit max) into a morton cell id.
+pub fn encode(cell: UVec2) -> u32 {
+    split_by_bits(cell.x) | (split_by_bits(cell.y) << 1)
+}
+
+/// Bits a radix sort of the cell ids of cells up to `max_cell` must look at: every cell id stays below the
+/// all-ones key of that width, so `UNUSED_CELL_ID` still sorts after them. See `GPUSorter::set_key_bits`.
+pub fn key_bits(max_cell: UVec2) -> u32 {
+    match encode(max_cell).checked_add(1) {
+        Some(keys) => (u32::BITS - keys.leading_zeros()).max(1),
+        None => u32::BITS,
+    }
+}
+
+/// Decodes a morton cell id back into 2D cell coordinates.
+pub fn decode(cell_id: u32) -> UVec2 {
+    UVec2::new(unsplit_by_bits(cell_id), unsplit_by_bits(cell_id >> 1))
+}
+
+/// Encodes 3D cell coordinates (10-bit max) into a morton cell id. The result never reaches `UNUSED_CELL_ID`.
+pub fn encode_3d(cell: UVec3) -> u32 {
+    split_by_3_bits(cell.x) | (split_by_3_bits(cell.y) << 1) | (split_by_3_bits(cell.z) << 2)
+}
+
+/// Decodes a 3D morton cell id back into cell coordinates.
+pub fn decode_3d(cell_id: u32) -> UVec3 {
+    UVec3::new(unsplit_by_3_bits(cell_id), unsplit_by_3_bits(cell_id >> 1), unsplit_by_3_bits(cell_id >> 2))
+}
+
+/// Cell containing `position` in a 3D grid. Coordinates are clamped to [0, MAX_CELL_COORD_3D], like `grid_3d.wgsl` does.
+pub fn cell_coord_3d(position: Vec3, cell_size: f32) -> UVec3 {
+    (position / cell_size).floor().clamp(Vec3::ZERO, Vec3::splat(MAX_CELL_COORD_3D as f32)).as_uvec3()
+}
+
+/// Number of color groups the collision solver splits the cells into.
+pub const NUM_CELL_COLORS: u32 = 4;
+
+/// Color group (1..=4) of a cell, as computed by the collision solver (`get_cell_color`).
+/// The solver runs one pass per color and cells of the same color are solved in parallel, so
+/// the coloring must give different colors to any two neighbouring cells (8-neighbourhood);
+/// the parity of both coordinates does. `CollisionSystem::set_color_validation` checks it on the GPU.
+pub fn cell_color(cell: UVec2) -> u32 {
+    1 + cell.x % 2 + (cell.y % 2) * 2
+}
+
+/// Color group of a morton cell id, see `cell_color`.
+pub fn cell_id_color(cell_id: u32) -> u32 {
+    cell_color(decode(cell_id))
+}
+
+/// Cell containing `position`. Negative coordinates are clamped to 0, like `build_cell_ids_array` does.
+pub fn cell_coord(position: Vec2, cell_size: f32) -> UVec2 {
+    (position / cell_size).floor().max(Vec2::ZERO).as_uvec2()
+}
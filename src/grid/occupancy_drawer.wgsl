@@ -0,0 +1,68 @@
+struct Camera {
+    view_proj: mat4x4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> u_camera: Camera;
+// Objects per cell, written by cell_occupancy_map.wgsl
+@group(0) @binding(0) var<storage, read> counts: array<u32>;
+
+// Must match GridDebugView
+const VIEW_OCCUPANCY = 0u;
+const VIEW_PAIR_TESTS = 1u;
+// Opacity of the cells, the particles stay visible under them
+const CELL_ALPHA = 0.55;
+
+struct PushConstantsData {
+    // World position of the corner of cell (0, 0)
+    origin: vec2<f32>,
+    cell_size: f32,
+    table_width: u32,
+    view: u32,
+    // Value at the end of the color map
+    max_value: f32,
+}
+
+var<push_constant> push_constants: PushConstantsData;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+// One instance per cell, two triangles per quad
+@vertex
+fn vs_main(@builtin(instance_index) cell_index: u32, @builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 0.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 1.0), vec2<f32>(0.0, 1.0),
+    );
+    let cell = vec2<f32>(f32(cell_index % push_constants.table_width), f32(cell_index / push_constants.table_width));
+    let position = push_constants.origin + (cell + corners[vertex_index]) * push_constants.cell_size;
+
+    var out: VertexOutput;
+    out.clip_position = u_camera.view_proj * vec4<f32>(position, 0.0, 1.0);
+    let objects = counts[cell_index];
+    var value = objects;
+    // Must match GridDebugView::value
+    if push_constants.view == VIEW_PAIR_TESTS {
+        value = objects * (max(objects, 1u) - 1u) / 2u;
+    }
+    // Empty cells are not drawn
+    let alpha = select(0.0, CELL_ALPHA, value > 0u);
+    out.color = vec4<f32>(heat(f32(value) / push_constants.max_value), alpha);
+    return out;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    if input.color.a == 0.0 {
+        discard;
+    }
+    return input.color;
+}
+
+// Black, red, yellow, white, like ColorMap::Heat
+fn heat(t: f32) -> vec3<f32> {
+    let x = clamp(t, 0.0, 1.0);
+    return clamp(vec3<f32>(3.0 * x, 3.0 * x - 1.0, 3.0 * x - 2.0), vec3<f32>(0.0), vec3<f32>(1.0));
+}
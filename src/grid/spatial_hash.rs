@@ -0,0 +1,42 @@
+//! CPU side of the hashed cell ids of `BroadphaseMode::SpatialHash` (`grid.wgsl`, `collision_solver.wgsl`).
+//! The results must match the shaders bit for bit.
+//!
+//! The cells are grouped in 2x2 blocks: the block coordinates are hashed into a table of `2^table_bits` slots and
+//! the position of the cell in its block, which is its color (see `morton::cell_color`), fills the two low bits.
+//! Unlike the morton ids the coordinates are not bounded nor clamped, so sparse and unbounded worlds keep a small
+//! key space. Cells whose blocks hash to the same slot share an id: the sort puts them in one collision cell,
+//! whose far apart pairs fail the distance test. Cells of different colors never share an id.
+use glam::{IVec2, Vec2};
+
+/// Table slots of the grid, `2^DEFAULT_TABLE_BITS` blocks of four cells.
+pub const DEFAULT_TABLE_BITS: u32 = 18;
+/// Largest table, the ids stay below `UNUSED_CELL_ID` with a bit to spare for the sort.
+pub const MAX_TABLE_BITS: u32 = 28;
+
+// Primes of the hash of Teschner et al., "Optimized Spatial Hashing for Collision Detection of Deformable Objects"
+const PRIME_X: u32 = 73856093;
+const PRIME_Y: u32 = 19349663;
+
+/// Cell containing `position`, negative coordinates included.
+pub fn cell_coord(position: Vec2, cell_size: f32) -> IVec2 {
+    (position / cell_size).floor().as_ivec2()
+}
+
+/// Hashed id of `cell` in a table of `2^table_bits` slots.
+pub fn encode(cell: IVec2, table_bits: u32) -> u32 {
+    let block = cell >> 1i32;
+    let hash = (block.x as u32).wrapping_mul(PRIME_X) ^ (block.y as u32).wrapping_mul(PRIME_Y);
+    let slot = hash & ((1u32 << table_bits) - 1);
+    slot * 4 + (cell.x & 1) as u32 + (cell.y & 1) as u32 * 2
+}
+
+/// Color group (1..=4) of a hashed id, the same as the one of its cell, see `morton::cell_color`.
+pub fn cell_id_color(cell_id: u32) -> u32 {
+    1 + (cell_id & 3)
+}
+
+/// Bits the radix sort looks at: every id stays below the all-ones key of that width, so `UNUSED_CELL_ID`
+/// still sorts after them. See `morton::key_bits`.
+pub fn key_bits(table_bits: u32) -> u32 {
+    table_bits + 3
+}
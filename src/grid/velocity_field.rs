@@ -0,0 +1,238 @@
+use glam::{UVec2, Vec2, Vec4};
+use wgpu::{BindGroupLayout, PushConstantRange};
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::profiler::GpuProfiler;
+
+pub const WORKGROUP_SIZE: u32 = 64;
+/// Line list vertices of an arrow, see `velocity_field.wgsl`.
+pub const ARROW_VERTICES: usize = 6;
+/// Arrows along the longest side of the world at most, the cells of the field are merged grid cells.
+pub const MAX_ARROWS_PER_SIDE: f32 = 64.0;
+/// Speed of the longest arrow, in world units per second, like `ParticleColorSettings::speed`.
+pub const DEFAULT_MAX_SPEED: f32 = 30.0;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstants {
+    num_particles: u32,
+    table_width: u32,
+    table_height: u32,
+    cell_size: f32,
+    origin: Vec2,
+    inv_delta_time: f32,
+    max_speed: f32,
+}
+
+/// What a velocity field update covers: the first `num_particles` particles, in the cells of `cell_size` covering
+/// the world from `origin`. `delta_time` is the one of the last step.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VelocityFieldParams {
+    pub num_particles: usize,
+    pub delta_time: f32,
+    pub origin: Vec2,
+    pub world_size: Vec2,
+    pub cell_size: f32,
+}
+
+/// Average velocity of the particles per cell of a coarse table covering the world, to show the bulk flow.
+/// A compute pass sums the displacement of the last step of every particle into its cell, then a second pass
+/// averages every cell and writes an arrow for it: two vertices per line, pointing along the velocity, as long
+/// and as bright as the speed relative to `max_speed`. Nothing is read back, the arrows are drawn from the GPU,
+/// see `VelocityFieldDrawer`.
+pub struct VelocityField {
+    accumulate_shader: ComputeShader,
+    build_shader: ComputeShader,
+    bind_group_layout: BindGroupLayout,
+    /// Fixed point displacement sums and particle count of every cell
+    cell_sums: GpuBuffer<i32>,
+    cell_velocities: GpuBuffer<Vec2>,
+    arrow_vertices: GpuBuffer<Vec2>,
+    arrow_colors: GpuBuffer<Vec4>,
+    table_size: UVec2,
+    max_speed: f32,
+}
+
+impl VelocityField {
+    pub fn new(wgpu_context: &WgpuContext) -> Self {
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let create_shader = |entry_point: &str| ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("velocity_field.wgsl"),
+            entry_point,
+            &bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange {
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstants>() as u32,
+                }
+            ]
+        );
+        let accumulate_shader = create_shader("accumulate_velocities");
+        let build_shader = create_shader("build_arrows");
+
+        Self {
+            accumulate_shader,
+            build_shader,
+            bind_group_layout,
+            cell_sums: GpuBuffer::new(wgpu_context, vec![0i32; 3], wgpu::BufferUsages::STORAGE),
+            cell_velocities: GpuBuffer::new(wgpu_context, vec![Vec2::ZERO], wgpu::BufferUsages::STORAGE),
+            arrow_vertices: GpuBuffer::new(wgpu_context, vec![Vec2::ZERO; ARROW_VERTICES], wgpu::BufferUsages::STORAGE),
+            arrow_colors: GpuBuffer::new(wgpu_context, vec![Vec4::ZERO; ARROW_VERTICES], wgpu::BufferUsages::STORAGE),
+            table_size: UVec2::ONE,
+            max_speed: DEFAULT_MAX_SPEED,
+        }
+    }
+
+    /// Cell size of the field: the grid cells are merged so that at most `MAX_ARROWS_PER_SIDE` arrows cover
+    /// the longest side of the world.
+    pub fn cell_size_for(grid_cell_size: f32, world_size: Vec2) -> f32 {
+        let merged = (world_size.max_element() / (MAX_ARROWS_PER_SIDE * grid_cell_size)).ceil().max(1.0);
+        grid_cell_size * merged
+    }
+
+    pub fn max_speed(&self) -> f32 {
+        self.max_speed
+    }
+
+    pub fn set_max_speed(&mut self, max_speed: f32) {
+        self.max_speed = max_speed.max(f32::EPSILON);
+    }
+
+    /// Cells of the last `update` along x and y, row by row from the cell at the origin.
+    pub fn table_size(&self) -> UVec2 {
+        self.table_size
+    }
+
+    /// `ARROW_VERTICES` vertices per cell, a line list.
+    pub fn arrow_vertices(&self) -> &GpuBuffer<Vec2> {
+        &self.arrow_vertices
+    }
+
+    /// Color of every arrow vertex, transparent for the empty and still cells.
+    pub fn arrow_colors(&self) -> &GpuBuffer<Vec4> {
+        &self.arrow_colors
+    }
+
+    /// Submits the passes that average the velocities of the particles in the cells of `params`, see
+    /// `cell_size_for`. The velocity of a particle is its displacement from `previous_positions` over the
+    /// `delta_time` of the params.
+    pub fn update(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, positions: &GpuBuffer<Vec2>, previous_positions: &GpuBuffer<Vec2>, params: VelocityFieldParams) {
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Velocity field encoder") }
+        );
+        self.record_update(wgpu_context, &mut encoder, gpu_profiler, positions, previous_positions, params);
+        gpu_profiler.resolve_queries(&mut encoder);
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+    }
+
+    /// `update` recorded into `encoder`, after the steps. Leaves the profiler queries to the owner of the encoder.
+    pub fn record_update(&mut self, wgpu_context: &WgpuContext, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler, positions: &GpuBuffer<Vec2>, previous_positions: &GpuBuffer<Vec2>, params: VelocityFieldParams) {
+        let VelocityFieldParams { num_particles, delta_time, origin, world_size, cell_size } = params;
+        self.table_size = (world_size / cell_size).ceil().as_uvec2().max(UVec2::ONE);
+        let num_cells = (self.table_size.x * self.table_size.y) as usize;
+        self.resize(wgpu_context, num_cells);
+        let bind_group = self.create_bind_group(wgpu_context, positions, previous_positions);
+
+        let push_constants = PushConstants {
+            num_particles: num_particles as u32,
+            table_width: self.table_size.x,
+            table_height: self.table_size.y,
+            cell_size,
+            origin,
+            inv_delta_time: if delta_time > 0.0 { 1.0 / delta_time } else { 0.0 },
+            max_speed: self.max_speed,
+        };
+        {
+            let mut scope = gpu_profiler.scope("Velocity field", encoder);
+            if num_particles > 0 {
+                self.accumulate_shader.dispatch_by_items(
+                    &mut scope,
+                    (num_particles as u32, 1, 1),
+                    Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                    &bind_group
+                );
+            }
+            self.build_shader.dispatch_by_items(
+                &mut scope,
+                (num_cells as u32, 1, 1),
+                Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                &bind_group
+            );
+        }
+    }
+
+    /// Reads back the average velocity of every cell of the last `update`, in world units per second.
+    /// Stalls until the GPU is done.
+    pub fn download_velocities(&self, wgpu_context: &WgpuContext) -> Vec<Vec2> {
+        self.cell_velocities.read_back(wgpu_context).unwrap()
+    }
+
+    /// Gives the buffers exactly `num_cells` cells, the arrows are drawn from their whole length.
+    fn resize(&mut self, wgpu_context: &WgpuContext, num_cells: usize) {
+        fn resize_to<T: bytemuck::Pod + Default>(buffer: &mut GpuBuffer<T>, wgpu_context: &WgpuContext, len: usize) {
+            if buffer.len() < len {
+                buffer.push_all(&vec![T::default(); len - buffer.len()], wgpu_context);
+            }
+            buffer.truncate(len);
+        }
+        // The sums past the table are left cleared by the last build, the buffer only grows
+        if self.cell_sums.len() < num_cells * 3 {
+            self.cell_sums.push_all(&vec![0; num_cells * 3 - self.cell_sums.len()], wgpu_context);
+        }
+        resize_to(&mut self.cell_velocities, wgpu_context, num_cells);
+        resize_to(&mut self.arrow_vertices, wgpu_context, num_cells * ARROW_VERTICES);
+        resize_to(&mut self.arrow_colors, wgpu_context, num_cells * ARROW_VERTICES);
+    }
+
+    fn create_bind_group(&self, wgpu_context: &WgpuContext, positions: &GpuBuffer<Vec2>, previous_positions: &GpuBuffer<Vec2>) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Velocity field bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: positions.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: previous_positions.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: self.cell_sums.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 3, resource: self.cell_velocities.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 4, resource: self.arrow_vertices.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 5, resource: self.arrow_colors.buffer().as_entire_binding() },
+            ],
+        })
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Velocity field bind group layout"),
+            entries: &[
+                // Positions
+                storage_entry(0, true),
+                // Previous positions
+                storage_entry(1, true),
+                // Cell sums
+                storage_entry(2, false),
+                // Cell velocities
+                storage_entry(3, false),
+                // Arrow vertices
+                storage_entry(4, false),
+                // Arrow colors
+                storage_entry(5, false),
+            ],
+        })
+    }
+}
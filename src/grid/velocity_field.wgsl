@@ -0,0 +1,121 @@
+override WORKGROUP_SIZE = 64u;
+
+// Displacements are summed as integers, in 1 / FIXED_POINT_SCALE world units, so the sums are atomic
+const FIXED_POINT_SCALE = 4096.0;
+// Vertices of an arrow: the shaft and the two strokes of the head, as a line list
+const ARROW_VERTICES = 6u;
+// Longest arrow, relative to the cell size
+const MAX_ARROW_LENGTH = 0.9;
+// Head strokes, relative to the arrow length, and their angle to the shaft
+const HEAD_LENGTH = 0.3;
+const HEAD_ANGLE = 0.5;
+
+struct PushConstants {
+    num_particles: u32,
+    // Cells of the field along x and y
+    table_width: u32,
+    table_height: u32,
+    cell_size: f32,
+    // World position of the corner of cell (0, 0)
+    origin: vec2<f32>,
+    inv_delta_time: f32,
+    // Speed of the longest arrow and of the end of the color map
+    max_speed: f32,
+}
+
+@group(0) @binding(0) var<storage, read> positions: array<vec2<f32>>;
+@group(0) @binding(1) var<storage, read> previous_positions: array<vec2<f32>>;
+// Per cell: the summed displacement along x and y, then the number of particles. Cleared by build_arrows
+@group(0) @binding(2) var<storage, read_write> cell_sums: array<atomic<i32>>;
+// Average velocity of every cell, in world units per second
+@group(0) @binding(3) var<storage, read_write> cell_velocities: array<vec2<f32>>;
+@group(0) @binding(4) var<storage, read_write> arrow_vertices: array<vec2<f32>>;
+@group(0) @binding(5) var<storage, read_write> arrow_colors: array<vec4<f32>>;
+
+var<push_constant> push_constants: PushConstants;
+
+// Every particle adds its displacement of the last step to the cell holding its center
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn accumulate_velocities(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= push_constants.num_particles {
+        return;
+    }
+    let position = positions[index];
+    let displacement = vec2<i32>(round((position - previous_positions[index]) * FIXED_POINT_SCALE));
+    let cell = cell_index(position) * 3u;
+    atomicAdd(&cell_sums[cell], displacement.x);
+    atomicAdd(&cell_sums[cell + 1u], displacement.y);
+    atomicAdd(&cell_sums[cell + 2u], 1);
+}
+
+// One thread per cell: averages the velocity and writes the arrow showing it, from the center of the cell.
+// The arrows of the empty and still cells collapse to a transparent point
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn build_arrows(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= push_constants.table_width * push_constants.table_height {
+        return;
+    }
+    let sum = vec2<f32>(f32(atomicExchange(&cell_sums[index * 3u], 0)), f32(atomicExchange(&cell_sums[index * 3u + 1u], 0)));
+    let count = atomicExchange(&cell_sums[index * 3u + 2u], 0);
+    var velocity = vec2<f32>(0.0);
+    if count > 0 {
+        velocity = sum / (FIXED_POINT_SCALE * f32(count)) * push_constants.inv_delta_time;
+    }
+    cell_velocities[index] = velocity;
+
+    let cell = vec2<f32>(f32(index % push_constants.table_width), f32(index / push_constants.table_width));
+    let center = push_constants.origin + (cell + 0.5) * push_constants.cell_size;
+    let speed = length(velocity);
+    let first = index * ARROW_VERTICES;
+    if speed == 0.0 {
+        for (var i = 0u; i < ARROW_VERTICES; i++) {
+            arrow_vertices[first + i] = center;
+            arrow_colors[first + i] = vec4<f32>(0.0);
+        }
+        return;
+    }
+    let t = min(speed / push_constants.max_speed, 1.0);
+    let direction = velocity / speed;
+    let arrow = direction * t * MAX_ARROW_LENGTH * push_constants.cell_size;
+    let tip = center + arrow * 0.5;
+    let head = -arrow * HEAD_LENGTH;
+    arrow_vertices[first] = center - arrow * 0.5;
+    arrow_vertices[first + 1u] = tip;
+    arrow_vertices[first + 2u] = tip;
+    arrow_vertices[first + 3u] = tip + rotate(head, HEAD_ANGLE);
+    arrow_vertices[first + 4u] = tip;
+    arrow_vertices[first + 5u] = tip + rotate(head, -HEAD_ANGLE);
+    let color = vec4<f32>(viridis(t), 1.0);
+    for (var i = 0u; i < ARROW_VERTICES; i++) {
+        arrow_colors[first + i] = color;
+    }
+}
+
+// Particles outside of the world count in the closest border cell
+fn cell_index(world_position: vec2<f32>) -> u32 {
+    let cell = vec2<i32>(floor((world_position - push_constants.origin) / push_constants.cell_size));
+    let max_cell = vec2<i32>(i32(push_constants.table_width) - 1, i32(push_constants.table_height) - 1);
+    let clamped = vec2<u32>(clamp(cell, vec2<i32>(0), max_cell));
+    return clamped.y * push_constants.table_width + clamped.x;
+}
+
+fn rotate(v: vec2<f32>, angle: f32) -> vec2<f32> {
+    let c = cos(angle);
+    let s = sin(angle);
+    return vec2<f32>(c * v.x - s * v.y, s * v.x + c * v.y);
+}
+
+// Polynomial fit of viridis, like sample_color_map in particle_color_kernel.wgsl
+fn viridis(t: f32) -> vec3<f32> {
+    let c0 = vec3<f32>(0.2777273272234177, 0.005407344544966578, 0.3340998053353061);
+    let c1 = vec3<f32>(0.1050930431085774, 1.404613529898575, 1.384590162594685);
+    let c2 = vec3<f32>(-0.3308618287255563, 0.214847559468213, 0.09509516302823659);
+    let c3 = vec3<f32>(-4.634230498983486, -5.799100973351585, -19.33244095627987);
+    let c4 = vec3<f32>(6.228269936347081, 14.17993336680509, 56.69055260068105);
+    let c5 = vec3<f32>(4.776384997670288, -13.74514537774601, -65.35303263337234);
+    let c6 = vec3<f32>(-5.435455855934631, 4.645852612178535, 26.3124352495832);
+    let color = c0 + t * (c1 + t * (c2 + t * (c3 + t * (c4 + t * (c5 + t * c6)))));
+    return clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+}
@@ -0,0 +1,61 @@
+use crate::grid::velocity_field::{VelocityField, VelocityFieldParams};
+use crate::lines::lines::Lines;
+use crate::particles::particle_system::ParticleSystem;
+use crate::renderer::camera::Camera;
+use crate::renderer::renderable::Renderable;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::profiler::GpuProfiler;
+
+/// Arrows of the average particle velocity over the world, drawn over the particles, see `VelocityField`.
+pub struct VelocityFieldDrawer {
+    field: VelocityField,
+    arrows: Lines,
+    visible: bool,
+}
+
+impl VelocityFieldDrawer {
+    pub fn new(wgpu_context: &WgpuContext, camera: &Camera) -> Self {
+        Self {
+            field: VelocityField::new(wgpu_context),
+            arrows: Lines::new(wgpu_context, camera),
+            visible: false,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    /// Records the rebuild of the arrows from the particles into `encoder`, once per frame after the steps.
+    /// `delta_time` is the one of the last step, the cells of the field are merged cells of `grid_cell_size`.
+    /// Does nothing while hidden.
+    pub fn record_update(&mut self, wgpu_context: &WgpuContext, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler, particles: &ParticleSystem, delta_time: f32, grid_cell_size: f32) {
+        if !self.visible {
+            return;
+        }
+        let world_size = particles.world_size();
+        let cell_size = VelocityField::cell_size_for(grid_cell_size, world_size);
+        let buffers = particles.buffers();
+        let params = VelocityFieldParams {
+            num_particles: particles.len(),
+            delta_time,
+            origin: particles.get_world_origin(),
+            world_size,
+            cell_size,
+        };
+        self.field.record_update(wgpu_context, encoder, gpu_profiler, &buffers.current_positions, &buffers.previous_positions, params);
+        self.arrows.copy_from(wgpu_context, encoder, self.field.arrow_vertices(), self.field.arrow_colors());
+    }
+}
+
+impl Renderable for VelocityFieldDrawer {
+    fn draw(&self, render_pass: &mut wgpu::RenderPass, camera: &Camera) {
+        if self.visible {
+            self.arrows.draw(render_pass, camera);
+        }
+    }
+}
@@ -1,8 +1,16 @@
 pub mod renderer;
 pub mod utils;
+#[cfg(feature = "windowing")]
 pub mod lines;
 pub mod particles;
+#[cfg(feature = "windowing")]
 pub mod state;
 pub mod grid;
+#[cfg(feature = "windowing")]
 pub mod app;
 pub mod physics;
+pub mod simulation;
+pub mod simulation_config;
+pub mod simulation_layers;
+pub mod physics_backend;
+pub mod scenes;
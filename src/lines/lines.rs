@@ -59,7 +59,7 @@ impl Lines {
                 module: &shader,
                 entry_point: Some("fs_main"),
                 targets: &[Some(wgpu::ColorTargetState{
-                    format: wgpu_context.get_surface_config().format,
+                    format: wgpu_context.surface_format(),
                     blend: Some(wgpu::BlendState::ALPHA_BLENDING),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
@@ -81,7 +81,7 @@ impl Lines {
                 alpha_to_coverage_enabled: false,
             },
             multiview: None,
-            cache: None,
+            cache: wgpu_context.pipeline_cache(),
         });
 
         Self {
@@ -121,6 +121,33 @@ impl Lines {
         self.vertices.push_all(positions, wgpu_context);
     }
 
+    /// Removes every line, the buffers keep their space for the next pushes.
+    pub fn clear(&mut self) {
+        self.vertices.truncate(0);
+        self.colors.truncate(0);
+        self.thicknesses.truncate(0);
+    }
+
+    /// Replaces the lines by the ones a compute pass wrote to `vertices` and `colors`, two vertices per line,
+    /// with a copy recorded into `encoder` after that pass. Only the lengths of the buffers are used on the CPU.
+    pub fn copy_from(&mut self, wgpu_context: &WgpuContext, encoder: &mut wgpu::CommandEncoder, vertices: &GpuBuffer<Vec2>, colors: &GpuBuffer<Vec4>) {
+        let num_vertices = vertices.len();
+        if self.vertices.len() != num_vertices {
+            self.clear();
+            self.vertices.push_all(&vec![Vec2::ZERO; num_vertices], wgpu_context);
+            self.colors.push_all(&vec![Vec4::ZERO; num_vertices], wgpu_context);
+            self.thicknesses.push_all(&vec![1.0; num_vertices], wgpu_context);
+        }
+        if num_vertices == 0 {
+            return;
+        }
+        let vertices_size = (num_vertices * size_of::<Vec2>()) as u64;
+        let colors_size = (num_vertices * size_of::<Vec4>()) as u64;
+        encoder.copy_buffer_to_buffer(vertices.buffer(), 0, self.vertices.buffer(), 0, vertices_size);
+        encoder.copy_buffer_to_buffer(colors.buffer(), 0, self.colors.buffer(), 0, colors_size);
+        wgpu_context.transfers().record_copy(vertices_size + colors_size);
+    }
+
     }
 
 impl Renderable for Lines {
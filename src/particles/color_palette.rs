@@ -0,0 +1,144 @@
+use glam::{Vec3, Vec4};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::utils::rng;
+
+/// Lightness shifts of the shades generated for every hue of a palette.
+const SHADES: [f32; 3] = [-0.12, 0.0, 0.12];
+/// More shades for the single hue of `ColorHarmony::Monochromatic`.
+const MONOCHROMATIC_SHADES: [f32; 5] = [-0.2, -0.1, 0.0, 0.1, 0.2];
+/// Largest brightness change of a particle from the palette color of its batch, so a batch is not one flat color.
+const PARTICLE_VARIATION: f32 = 0.08;
+
+/// How the hues of a `ColorPalette` are spread around its base hue.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ColorHarmony {
+    /// Neighbouring hues, 30° apart.
+    #[default]
+    Analogous,
+    /// The base hue and the opposite one.
+    Complementary,
+    /// The base hue and the two opposite of its neighbours.
+    SplitComplementary,
+    /// Three hues 120° apart.
+    Triadic,
+    /// Only the base hue, in more shades.
+    Monochromatic,
+}
+
+impl ColorHarmony {
+    pub const ALL: [ColorHarmony; 5] = [
+        ColorHarmony::Analogous,
+        ColorHarmony::Complementary,
+        ColorHarmony::SplitComplementary,
+        ColorHarmony::Triadic,
+        ColorHarmony::Monochromatic,
+    ];
+
+    /// Name of the harmony in a config file, e.g. `"split_complementary"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorHarmony::Analogous => "analogous",
+            ColorHarmony::Complementary => "complementary",
+            ColorHarmony::SplitComplementary => "split_complementary",
+            ColorHarmony::Triadic => "triadic",
+            ColorHarmony::Monochromatic => "monochromatic",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|harmony| harmony.name() == name)
+    }
+
+    /// Offsets of the hues from the base hue, in turns.
+    fn hue_offsets(self) -> &'static [f32] {
+        match self {
+            ColorHarmony::Analogous => &[-1.0 / 12.0, 0.0, 1.0 / 12.0],
+            ColorHarmony::Complementary => &[0.0, 0.5],
+            ColorHarmony::SplitComplementary => &[0.0, 5.0 / 12.0, 7.0 / 12.0],
+            ColorHarmony::Triadic => &[0.0, 1.0 / 3.0, 2.0 / 3.0],
+            ColorHarmony::Monochromatic => &[0.0],
+        }
+    }
+}
+
+/// Seed and harmony a `ColorPalette` is generated from, e.g. the `[palette]` of a config file.
+/// The same theme always gives the same palette.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ColorTheme {
+    pub seed: u64,
+    pub harmony: ColorHarmony,
+}
+
+impl ColorTheme {
+    /// A theme with a random seed and harmony, for a run that looks different every time.
+    pub fn random() -> Self {
+        let mut rng = rng::rng();
+        Self {
+            seed: rng.random(),
+            harmony: ColorHarmony::ALL[rng.random_range(0..ColorHarmony::ALL.len())],
+        }
+    }
+}
+
+/// A few colors of harmonious hues, with the same saturation, generated from a `ColorTheme`.
+/// `ParticleSystem::set_palette` paints every spawn batch with one of them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColorPalette {
+    theme: ColorTheme,
+    colors: Vec<Vec4>,
+}
+
+impl ColorPalette {
+    pub fn generate(theme: ColorTheme) -> Self {
+        let mut rng = StdRng::seed_from_u64(theme.seed);
+        let base_hue: f32 = rng.random();
+        let saturation = rng.random_range(0.45..0.75);
+        let lightness = rng.random_range(0.45..0.6);
+
+        let offsets = theme.harmony.hue_offsets();
+        let shades: &[f32] = if theme.harmony == ColorHarmony::Monochromatic { &MONOCHROMATIC_SHADES } else { &SHADES };
+        let colors = offsets.iter()
+            .flat_map(|offset| shades.iter().map(move |shade| (offset, shade)))
+            .map(|(offset, shade)| {
+                let hue = (base_hue + offset).rem_euclid(1.0);
+                hsl_to_rgb(hue, saturation, (lightness + shade).clamp(0.15, 0.85)).extend(1.0)
+            })
+            .collect();
+        Self { theme, colors }
+    }
+
+    pub fn theme(&self) -> ColorTheme {
+        self.theme
+    }
+
+    pub fn colors(&self) -> &[Vec4] {
+        &self.colors
+    }
+
+    /// Color `index` of the palette, wrapping around. Also usable with `GroupOperation::Recolor`.
+    pub fn color(&self, index: usize) -> Vec4 {
+        self.colors[index % self.colors.len()]
+    }
+
+    /// `color` made slightly brighter or darker at random, for the particles of one batch.
+    pub fn vary(color: Vec4, rng: &mut impl Rng) -> Vec4 {
+        let brightness = 1.0 + rng.random_range(-PARTICLE_VARIATION..=PARTICLE_VARIATION);
+        (color.truncate() * brightness).clamp(Vec3::ZERO, Vec3::ONE).extend(color.w)
+    }
+
+    /// A color of the palette at random, varied like `vary`.
+    pub fn random_color(&self, rng: &mut impl Rng) -> Vec4 {
+        Self::vary(self.colors[rng.random_range(0..self.colors.len())], rng)
+    }
+}
+
+/// Hue in turns, saturation and lightness in [0, 1].
+fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> Vec3 {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let channel = |n: f32| {
+        let k = (n + hue * 12.0) % 12.0;
+        lightness - chroma / 2.0 * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+    };
+    Vec3::new(channel(0.0), channel(8.0), channel(4.0))
+}
@@ -0,0 +1,153 @@
+use std::io;
+use std::path::Path;
+use glam::Vec2;
+use crate::particles::particle_system::ParticleSystem;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::png;
+
+/// What the pixels of a `Heightmap` measure.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HeightmapMode {
+    /// Fraction of the pixel covered by particles: the area of the particles centered in it
+    /// divided by the pixel area, capped at 1. Shows the packing density of a pile.
+    Occupancy,
+    /// Silhouette of the pile: every pixel below the highest particle of its column is filled.
+    Height,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HeightmapSettings {
+    /// Image width in pixels. The height follows the aspect ratio of the world.
+    pub width: u32,
+    pub mode: HeightmapMode,
+}
+
+impl Default for HeightmapSettings {
+    fn default() -> Self {
+        Self {
+            width: 512,
+            mode: HeightmapMode::Occupancy,
+        }
+    }
+}
+
+/// The particle distribution gridded into an image, to compare the final pile shapes of different solver settings.
+/// Row 0 is the top of the world (highest y). Values are in [0, 1].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heightmap {
+    width: u32,
+    height: u32,
+    world_size: Vec2,
+    mode: HeightmapMode,
+    values: Vec<f32>,
+    column_heights: Vec<f32>,
+}
+
+impl Heightmap {
+    /// Downloads the particles and grids them. Stalls until the GPU is done.
+    pub fn from_particle_system(wgpu_context: &WgpuContext, particle_system: &mut ParticleSystem, settings: HeightmapSettings) -> Self {
+        let world_size = particle_system.world_size();
+        let world_origin = particle_system.get_world_origin();
+        let buffers = particle_system.download_particle_buffers(wgpu_context);
+        // from_particles expects a world starting at (0, 0)
+        let positions: Vec<Vec2> = buffers.current_positions.data().iter().map(|position| *position - world_origin).collect();
+        Self::from_particles(&positions, buffers.radii.data(), world_size, settings)
+    }
+
+    pub fn from_particles(positions: &[Vec2], radii: &[f32], world_size: Vec2, settings: HeightmapSettings) -> Self {
+        let width = settings.width.max(1);
+        let height = ((width as f32 * world_size.y / world_size.x).round() as u32).max(1);
+        let pixel_size = world_size / Vec2::new(width as f32, height as f32);
+
+        let mut covered_area = vec![0.0f32; (width * height) as usize];
+        let mut column_heights = vec![0.0f32; width as usize];
+        let column = |x: f32| ((x / pixel_size.x).floor().max(0.0) as u32).min(width - 1);
+        let row = |y: f32| height - 1 - ((y / pixel_size.y).floor().max(0.0) as u32).min(height - 1);
+
+        for (position, radius) in positions.iter().zip(radii) {
+            let pixel = (row(position.y) * width + column(position.x)) as usize;
+            covered_area[pixel] += std::f32::consts::PI * radius * radius;
+
+            let top = position.y + radius;
+            for x in column(position.x - radius)..=column(position.x + radius) {
+                column_heights[x as usize] = column_heights[x as usize].max(top);
+            }
+        }
+
+        let values = match settings.mode {
+            HeightmapMode::Occupancy => {
+                let pixel_area = pixel_size.x * pixel_size.y;
+                covered_area.iter().map(|area| (area / pixel_area).min(1.0)).collect()
+            }
+            HeightmapMode::Height => (0..height).flat_map(|y| {
+                let pixel_center = (height - y) as f32 * pixel_size.y - pixel_size.y * 0.5;
+                column_heights.iter().map(move |&column_height| if pixel_center < column_height { 1.0 } else { 0.0 })
+            }).collect(),
+        };
+
+        Self {
+            width,
+            height,
+            world_size,
+            mode: settings.mode,
+            values,
+            column_heights,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn world_size(&self) -> Vec2 {
+        self.world_size
+    }
+
+    pub fn mode(&self) -> HeightmapMode {
+        self.mode
+    }
+
+    /// Row-major values, starting at the top left pixel.
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    pub fn value(&self, x: u32, y: u32) -> f32 {
+        self.values[(y * self.width + x) as usize]
+    }
+
+    /// World height of the highest particle top of each pixel column, 0 for empty columns.
+    pub fn column_heights(&self) -> &[f32] {
+        &self.column_heights
+    }
+
+    /// Colors the values with a black, blue, orange, white ramp.
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        self.values.iter().flat_map(|&value| {
+            let [r, g, b] = ramp(value);
+            [r, g, b, 255]
+        }).collect()
+    }
+
+    pub fn save_png(&self, path: &Path) -> io::Result<()> {
+        png::save_rgba8(path, self.width, self.height, &self.to_rgba8())
+    }
+}
+
+fn ramp(value: f32) -> [u8; 3] {
+    const STOPS: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [0.1, 0.2, 0.8],
+        [1.0, 0.6, 0.1],
+        [1.0, 1.0, 1.0],
+    ];
+    let scaled = value.clamp(0.0, 1.0) * (STOPS.len() - 1) as f32;
+    let index = (scaled as usize).min(STOPS.len() - 2);
+    let t = scaled - index as f32;
+    let (from, to) = (STOPS[index], STOPS[index + 1]);
+    std::array::from_fn(|channel| ((from[channel] + (to[channel] - from[channel]) * t) * 255.0).round() as u8)
+}
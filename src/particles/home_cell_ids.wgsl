@@ -4,6 +4,8 @@ override WORKGROUP_SIZE = 64u;
 struct PushConstantsData{
     num_particles: u32, 
     cell_size: f32,
+    // World origin, the corner of cell (0, 0)
+    origin: vec2<f32>,
 }
 
 @group(0) @binding(0) var<storage, read_write> positions: array<vec2<f32>>;
@@ -21,10 +23,10 @@ fn create_home_cell_ids(@builtin(global_invocation_id) global_id: vec3<u32>){
         return;
     }
 
-    let pos = positions[obj_id];
+    let pos = positions[obj_id] - push_constant_data.origin;
 
-    // Convert to grid coordinates.
-    let home_cell_coord = vec2<i32>(floor(pos / push_constant_data.cell_size));
+    // Convert to grid coordinates, clamped like grid.wgsl does
+    let home_cell_coord = max(vec2<i32>(floor(pos / push_constant_data.cell_size)), vec2<i32>(0));
     let home_cell_hash = morton_encode(home_cell_coord);
     
     // Store home cell id
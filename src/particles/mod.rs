@@ -1,8 +1,31 @@
 
 pub mod particle_system;
+pub mod particle_channels;
+pub mod particle_initializer;
+pub mod nearest_particle_query;
+pub mod particle_selection;
+pub mod particle_group;
+pub mod particle_interaction;
+pub mod heightmap;
+pub mod particle_volume;
+pub mod particle_color_kernel;
+pub mod particle_shape;
+pub mod sprite_animation;
+pub mod particle_emitter;
+pub mod region_energy;
+pub mod region_query;
+pub mod color_palette;
+pub mod particle_trails;
+pub mod particle_culling;
 mod particle_integration;
 mod particle_buffers;
+mod particle_buffer_swapchain;
+#[cfg(feature = "windowing")]
 mod particle_drawer;
-mod particle_sort;
+#[cfg(feature = "windowing")]
+mod trail_drawer;
+pub mod particle_sort;
 mod particle_rearrange;
-mod particle_home_cell_ids_kernel;
\ No newline at end of file
+mod particle_home_cell_ids_kernel;
+mod sort_disorder;
+pub mod particle_compaction;
\ No newline at end of file
@@ -0,0 +1,272 @@
+use std::sync::mpsc::{Receiver, TryRecvError};
+use glam::Vec2;
+use wgpu::{BindGroupLayout, BufferAsyncError, PushConstantRange};
+use wgpu::wgt::PollType;
+use crate::particles::particle_system::ParticleSystem;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::bind_resources::BindResources;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+
+pub const WORKGROUP_SIZE: u32 = 64;
+const NO_PARTICLE: u32 = u32::MAX;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstants {
+    position: Vec2,
+    pick_tolerance: f32,
+    num_particles: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct QueryResult {
+    distance_sq_bits: u32,
+    particle: u32,
+}
+
+/// Particle picked by a query.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NearestParticle {
+    /// Index of the particle at the time of the query. Indices change when the particles are sorted.
+    pub particle: u32,
+    /// Distance from the query position to the particle center.
+    pub distance: f32,
+}
+
+/// Result of a query at `position`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NearestParticleResult {
+    pub position: Vec2,
+    /// `None` if no particle was close enough.
+    pub nearest: Option<NearestParticle>,
+}
+
+struct PendingQuery {
+    position: Vec2,
+    receiver: Receiver<Result<(), BufferAsyncError>>,
+}
+
+/// Finds the particle closest to a world position, among the particles that contain it
+/// (or are within `pick_tolerance` of it), and flags it in the particle highlight flags.
+/// The flags are written on the GPU by every `request`, so the drawer sees them the same frame;
+/// the result is read back like `CellOccupancyQuery`, one query in flight at a time.
+pub struct NearestParticleQuery {
+    find_distance_shader: ComputeShader,
+    find_particle_shader: ComputeShader,
+    mark_highlight_shader: ComputeShader,
+    bind_resources: BindResources,
+    result: GpuBuffer<QueryResult>,
+    staging_buffer: wgpu::Buffer,
+    pending: Option<PendingQuery>,
+    /// Position of the readback recorded into an encoder that was not submitted yet
+    recorded: Option<Vec2>,
+    last_result: Option<NearestParticleResult>,
+    pick_tolerance: f32,
+}
+
+impl NearestParticleQuery {
+    pub fn new(wgpu_context: &WgpuContext, particle_system: &ParticleSystem) -> Self {
+        let result = GpuBuffer::new(wgpu_context, vec![Self::cleared_result()], wgpu::BufferUsages::STORAGE);
+        let staging_buffer = wgpu_context.get_device().create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Nearest particle staging buffer"),
+            size: size_of::<QueryResult>() as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let bind_group = Self::create_bind_group(wgpu_context, &bind_group_layout, particle_system, &result);
+        let bind_resources = BindResources::new(bind_group_layout, bind_group);
+
+        let create_shader = |entry_point: &str| ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("nearest_particle_query.wgsl"),
+            entry_point,
+            &bind_resources.bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange{
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstants>() as u32,
+                }
+            ]
+        );
+        let find_distance_shader = create_shader("find_nearest_distance");
+        let find_particle_shader = create_shader("find_nearest_particle");
+        let mark_highlight_shader = create_shader("mark_highlight");
+
+        Self {
+            find_distance_shader,
+            find_particle_shader,
+            mark_highlight_shader,
+            bind_resources,
+            result,
+            staging_buffer,
+            pending: None,
+            recorded: None,
+            last_result: None,
+            pick_tolerance: 0.0,
+        }
+    }
+
+    fn cleared_result() -> QueryResult {
+        QueryResult {
+            distance_sq_bits: NO_PARTICLE,
+            particle: NO_PARTICLE,
+        }
+    }
+
+    /// Must be called after the particle buffers are refreshed.
+    pub fn refresh(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem) {
+        self.bind_resources.bind_group = Self::create_bind_group(wgpu_context, &self.bind_resources.bind_group_layout, particle_system, &self.result);
+    }
+
+    /// Extra distance, beyond the particle radius, at which a particle can still be picked. Zero by default.
+    pub fn set_pick_tolerance(&mut self, pick_tolerance: f32) {
+        self.pick_tolerance = pick_tolerance.max(0.0);
+    }
+
+    fn create_bind_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, particle_system: &ParticleSystem, result: &GpuBuffer<QueryResult>) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                label: Some("Nearest particle query bind group"),
+                layout: bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: particle_system.buffers().current_positions.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: particle_system.buffers().radii.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: result.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: particle_system.highlight_flags().buffer().as_entire_binding(),
+                    },
+                ],
+            }
+        )
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Nearest particle query bind group layout"),
+            entries: &[
+                // Positions
+                storage_entry(0, true),
+                // Radii
+                storage_entry(1, true),
+                // Result
+                storage_entry(2, false),
+                // Highlight flags
+                storage_entry(3, false),
+            ],
+        })
+    }
+
+    /// Picks the particle at `position` and highlights it. Submits its own work, so call it after the
+    /// physics step of the frame was submitted. The highlight is always updated; the readback is
+    /// skipped while the previous one is still in flight.
+    pub fn request(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem, position: Vec2) {
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Nearest particle query encoder") }
+        );
+        self.record_request(wgpu_context, &mut encoder, particle_system, position);
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+        self.after_submit();
+    }
+
+    /// `request` recorded into `encoder`, after the physics step. `after_submit` must follow the submit of the encoder.
+    pub fn record_request(&mut self, wgpu_context: &WgpuContext, encoder: &mut wgpu::CommandEncoder, particle_system: &ParticleSystem, position: Vec2) {
+        self.result.replace_elem(Self::cleared_result(), 0, wgpu_context);
+        let num_particles = particle_system.len() as u32;
+        let push_constants = PushConstants {
+            position,
+            pick_tolerance: self.pick_tolerance,
+            num_particles,
+        };
+        for shader in [&self.find_distance_shader, &self.find_particle_shader, &self.mark_highlight_shader] {
+            shader.dispatch_by_items(
+                encoder,
+                (num_particles, 1, 1),
+                Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                &self.bind_resources.bind_group
+            );
+        }
+
+        if self.pending.is_none() && self.recorded.is_none() {
+            encoder.copy_buffer_to_buffer(self.result.buffer(), 0, &self.staging_buffer, 0, size_of::<QueryResult>() as u64);
+            wgpu_context.transfers().record_readback(size_of::<QueryResult>() as u64);
+            self.recorded = Some(position);
+        }
+    }
+
+    /// Maps the result of the readback recorded since the last call, once its encoder was submitted.
+    pub fn after_submit(&mut self) {
+        let Some(position) = self.recorded.take() else {
+            return;
+        };
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.staging_buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.pending = Some(PendingQuery { position, receiver });
+    }
+
+    /// Removes the highlight, e.g. when the cursor leaves the window.
+    pub fn clear_highlight(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem) {
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Clear highlight encoder") }
+        );
+        encoder.clear_buffer(particle_system.highlight_flags().buffer(), 0, None);
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+        self.last_result = None;
+    }
+
+    /// Checks, without blocking, if the query in flight finished.
+    /// Returns the latest available result, which may belong to an older request.
+    pub fn poll(&mut self, wgpu_context: &WgpuContext) -> Option<NearestParticleResult> {
+        if let Some(pending) = self.pending.take() {
+            let _ = wgpu_context.get_device().poll(PollType::Poll);
+            match pending.receiver.try_recv() {
+                Ok(Ok(())) => {
+                    let result = {
+                        let mapped_range = self.staging_buffer.slice(..).get_mapped_range();
+                        bytemuck::pod_read_unaligned::<QueryResult>(&mapped_range)
+                    };
+                    self.staging_buffer.unmap();
+                    let nearest = (result.particle != NO_PARTICLE).then(|| NearestParticle {
+                        particle: result.particle,
+                        distance: f32::from_bits(result.distance_sq_bits).sqrt(),
+                    });
+                    self.last_result = Some(NearestParticleResult { position: pending.position, nearest });
+                }
+                Ok(Err(e)) => log::error!("Nearest particle query failed: {:?}", e),
+                Err(TryRecvError::Empty) => self.pending = Some(pending),
+                Err(TryRecvError::Disconnected) => {}
+            }
+        }
+        self.last_result
+    }
+}
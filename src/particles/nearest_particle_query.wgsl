@@ -0,0 +1,69 @@
+override WORKGROUP_SIZE = 64u;
+
+const NO_PARTICLE = 0xffffffffu;
+
+struct PushConstants {
+    position: vec2<f32>,
+    // Extra distance, beyond the particle radius, at which a particle still counts as picked
+    pick_tolerance: f32,
+    num_particles: u32,
+}
+
+struct QueryResult {
+    // Squared distance to the nearest picked particle, as bits. Positive floats keep their order as u32
+    distance_sq_bits: atomic<u32>,
+    particle: atomic<u32>,
+}
+
+@group(0) @binding(0) var<storage, read> positions: array<vec2<f32>>;
+@group(0) @binding(1) var<storage, read> radii: array<f32>;
+@group(0) @binding(2) var<storage, read_write> result: QueryResult;
+@group(0) @binding(3) var<storage, read_write> highlight_flags: array<u32>;
+
+var<push_constant> push_constants: PushConstants;
+
+// Squared distance from the query position to the particle, or NO_PARTICLE if it is too far to be picked
+fn picked_distance_sq_bits(idx: u32) -> u32 {
+    let offset = positions[idx] - push_constants.position;
+    let distance_sq = dot(offset, offset);
+    let max_distance = radii[idx] + push_constants.pick_tolerance;
+    if distance_sq > max_distance * max_distance {
+        return NO_PARTICLE;
+    }
+    return bitcast<u32>(distance_sq);
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn find_nearest_distance(@builtin(global_invocation_id) global_id: vec3<u32>){
+    let idx = global_id.x;
+    if idx >= push_constants.num_particles {
+        return;
+    }
+    let distance_sq_bits = picked_distance_sq_bits(idx);
+    if distance_sq_bits != NO_PARTICLE {
+        atomicMin(&result.distance_sq_bits, distance_sq_bits);
+    }
+}
+
+// Runs after find_nearest_distance. Ties are broken by the lowest index, so the result is deterministic
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn find_nearest_particle(@builtin(global_invocation_id) global_id: vec3<u32>){
+    let idx = global_id.x;
+    if idx >= push_constants.num_particles {
+        return;
+    }
+    let distance_sq_bits = picked_distance_sq_bits(idx);
+    if distance_sq_bits != NO_PARTICLE && distance_sq_bits == atomicLoad(&result.distance_sq_bits) {
+        atomicMin(&result.particle, idx);
+    }
+}
+
+// Flags the picked particle for the drawer and clears every other flag
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn mark_highlight(@builtin(global_invocation_id) global_id: vec3<u32>){
+    let idx = global_id.x;
+    if idx >= push_constants.num_particles {
+        return;
+    }
+    highlight_flags[idx] = select(0u, 1u, idx == atomicLoad(&result.particle));
+}
@@ -0,0 +1,91 @@
+use glam::{Vec2, Vec4};
+use crate::particles::particle_buffers::ParticleBuffers;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::gpu_buffer::GpuBuffer;
+
+/// Two copies of the particle buffers the drawer reads, so the physics of the next frame can be recorded
+/// and submitted while the current one is still drawn: the kernels only write the live buffers, and
+/// `publish` copies them into the set that is not displayed before making it the displayed one.
+pub struct ParticleBufferSwapchain {
+    sets: [ParticleBuffers; 2],
+    displayed: usize,
+    displayed_len: usize, // Particles of the displayed set
+}
+
+impl ParticleBufferSwapchain {
+    /// Both sets are sized like `live`, nothing is displayed until the first `publish`.
+    pub fn new(wgpu_context: &WgpuContext, live: &ParticleBuffers) -> Self {
+        Self {
+            sets: [Self::create_set(wgpu_context, live), Self::create_set(wgpu_context, live)],
+            displayed: 0,
+            displayed_len: 0,
+        }
+    }
+
+    /// Records the copy of the first `num_particles` of `live` into the set that is not displayed and
+    /// displays it. Returns true if the sets were recreated, because `live` grew or its channels changed:
+    /// the bind groups of the drawer must be refreshed.
+    pub fn publish(&mut self, wgpu_context: &WgpuContext, encoder: &mut wgpu::CommandEncoder, live: &ParticleBuffers, num_particles: usize, extras_stride: u32) -> bool {
+        let recreated = !Self::fits(&self.sets[0], live);
+        if recreated {
+            self.sets = [Self::create_set(wgpu_context, live), Self::create_set(wgpu_context, live)];
+        }
+        let back = 1 - self.displayed;
+        let set = &self.sets[back];
+        copy_prefix(encoder, &live.current_positions, &set.current_positions, num_particles);
+        copy_prefix(encoder, &live.previous_positions, &set.previous_positions, num_particles);
+        copy_prefix(encoder, &live.radii, &set.radii, num_particles);
+        copy_prefix(encoder, &live.colors, &set.colors, num_particles.min(live.colors.len()));
+        copy_prefix(encoder, &live.extras, &set.extras, num_particles * extras_stride as usize);
+        self.displayed = back;
+        self.displayed_len = num_particles;
+        recreated
+    }
+
+    /// The set the drawer shows.
+    pub fn displayed(&self) -> &ParticleBuffers {
+        &self.sets[self.displayed]
+    }
+
+    pub fn displayed_index(&self) -> usize {
+        self.displayed
+    }
+
+    /// Particles copied into the displayed set by the last `publish`.
+    pub fn displayed_len(&self) -> usize {
+        self.displayed_len
+    }
+
+    pub fn sets(&self) -> [&ParticleBuffers; 2] {
+        [&self.sets[0], &self.sets[1]]
+    }
+
+    /// Whether `set` can hold every particle of `live`, with the same extras layout.
+    fn fits(set: &ParticleBuffers, live: &ParticleBuffers) -> bool {
+        set.current_positions.capacity() == live.current_positions.capacity()
+            && set.colors.capacity() == live.colors.capacity()
+            && set.extras.capacity() == live.extras.capacity()
+    }
+
+    fn create_set(wgpu_context: &WgpuContext, live: &ParticleBuffers) -> ParticleBuffers {
+        let usage = wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE;
+        let capacity = live.current_positions.capacity();
+        ParticleBuffers {
+            current_positions: GpuBuffer::new(wgpu_context, vec![Vec2::ZERO; capacity], usage),
+            previous_positions: GpuBuffer::new(wgpu_context, vec![Vec2::ZERO; capacity], usage),
+            radii: GpuBuffer::new(wgpu_context, vec![0.0; capacity], usage),
+            colors: GpuBuffer::new(wgpu_context, vec![Vec4::ZERO; live.colors.capacity()], usage),
+            // Not drawn
+            home_cell_ids: GpuBuffer::new(wgpu_context, vec![0], wgpu::BufferUsages::STORAGE),
+            extras: GpuBuffer::new(wgpu_context, vec![0; live.extras.capacity()], wgpu::BufferUsages::STORAGE),
+        }
+    }
+}
+
+/// Records the copy of the first `len` elements of `source` into `destination`.
+fn copy_prefix<T: bytemuck::Pod>(encoder: &mut wgpu::CommandEncoder, source: &GpuBuffer<T>, destination: &GpuBuffer<T>, len: usize) {
+    let size = (len * size_of::<T>()) as u64;
+    if size > 0 {
+        encoder.copy_buffer_to_buffer(source.buffer(), 0, destination.buffer(), 0, size);
+    }
+}
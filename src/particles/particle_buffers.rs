@@ -1,4 +1,7 @@
 use glam::{Vec2, Vec4};
+use crate::particles::particle_channels::ParticleChannels;
+use crate::particles::particle_system::MASS_CHANNEL;
+use crate::renderer::wgpu_context::WgpuContext;
 use crate::utils::gpu_buffer::GpuBuffer;
 
 pub struct ParticleBuffers {
@@ -7,4 +10,42 @@ pub struct ParticleBuffers {
     pub radii: GpuBuffer<f32>,
     pub colors: GpuBuffer<Vec4>,
     pub home_cell_ids: GpuBuffer<u32>, // Need this to sort objects by home cell
-}
\ No newline at end of file
+    pub extras: GpuBuffer<u32>, // Interleaved per-particle channels, see ParticleChannels
+}
+
+impl ParticleBuffers {
+    /// Particles every buffer can hold without growing, with `extras_stride` channel words each.
+    pub fn capacity(&self, extras_stride: usize) -> usize {
+        let extras_capacity = match extras_stride {
+            0 => usize::MAX,
+            stride => self.extras.capacity() / stride,
+        };
+        self.current_positions.capacity()
+            .min(self.previous_positions.capacity())
+            .min(self.radii.capacity())
+            .min(self.colors.capacity())
+            .min(self.home_cell_ids.capacity())
+            .min(extras_capacity)
+    }
+
+    /// Mass of every particle, from the CPU copy of the extras (e.g. after `ParticleSystem::download_particle_buffers`).
+    /// `None` if the particles have no mass channel, see `ParticleSystem::enable_mass`.
+    pub fn masses(&self, channels: &ParticleChannels) -> Option<Vec<f32>> {
+        let id = channels.find(MASS_CHANNEL)?;
+        let stride = channels.stride() as usize;
+        let offset = channels.offset(id) as usize;
+        Some(self.extras.data().chunks_exact(stride).map(|words| f32::from_bits(words[offset])).collect())
+    }
+
+    /// Grows every buffer so it holds `capacity` particles, see `GpuBuffer::reserve`.
+    /// Returns true if any buffer was recreated.
+    pub fn reserve(&mut self, wgpu_context: &WgpuContext, capacity: usize, extras_stride: usize) -> bool {
+        let mut recreated = self.current_positions.reserve(wgpu_context, capacity);
+        recreated |= self.previous_positions.reserve(wgpu_context, capacity);
+        recreated |= self.radii.reserve(wgpu_context, capacity);
+        recreated |= self.colors.reserve(wgpu_context, capacity);
+        recreated |= self.home_cell_ids.reserve(wgpu_context, capacity);
+        recreated |= self.extras.reserve(wgpu_context, capacity * extras_stride);
+        recreated
+    }
+}
@@ -0,0 +1,163 @@
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::gpu_buffer::GpuBuffer;
+
+/// Maximum number of channels addressable from the layout uniform.
+/// Must match MAX_PARTICLE_CHANNELS in the shaders that read channels.
+pub const MAX_PARTICLE_CHANNELS: usize = 16;
+
+/// Handle of a registered per-particle channel.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChannelId(pub u32);
+
+struct ChannelDescriptor {
+    name: String,
+    offset: u32,
+    default: Vec<u32>,
+}
+
+/// Uniform describing where each channel lives inside the interleaved extras buffer.
+/// In WGSL: `extras[particle * stride + offsets[channel / 4][channel % 4] + component]`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ChannelLayoutUniform {
+    stride: u32,
+    num_channels: u32,
+    num_particles: u32,
+    _padding: u32,
+    offsets: [[u32; 4]; MAX_PARTICLE_CHANNELS / 4],
+}
+
+/// Registry of the optional per-particle channels (mass, lifetime, flags...).
+/// All channels are interleaved in a single `u32` storage buffer (`ParticleBuffers::extras`),
+/// so kernels bind one buffer plus this layout uniform, and registering a new channel
+/// never changes a bind group layout. Float channels are stored with `f32::to_bits`.
+pub struct ParticleChannels {
+    channels: Vec<ChannelDescriptor>,
+    stride: u32,
+    layout: GpuBuffer<ChannelLayoutUniform>,
+}
+
+impl ParticleChannels {
+    pub fn new(wgpu_context: &WgpuContext, num_particles: usize) -> Self {
+        let layout = GpuBuffer::new(
+            wgpu_context,
+            vec![ChannelLayoutUniform {
+                stride: 0,
+                num_channels: 0,
+                num_particles: num_particles as u32,
+                _padding: 0,
+                offsets: [[0; 4]; MAX_PARTICLE_CHANNELS / 4],
+            }],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+
+        Self {
+            channels: Vec::new(),
+            stride: 0,
+            layout,
+        }
+    }
+
+    /// Creates the extras buffer contents for `num_particles` particles using the channel defaults.
+    /// The buffer always holds at least one word, since empty storage bindings are not allowed.
+    pub fn create_extras_data(&self, num_particles: usize) -> Vec<u32> {
+        let mut data = self.default_words().repeat(num_particles);
+        if data.is_empty() {
+            data.push(0);
+        }
+        data
+    }
+
+    /// Adds a channel and returns the re-laid out extras data for the existing particles.
+    /// `old_extras` is the current (downloaded) content of the extras buffer.
+    pub fn register(&mut self, wgpu_context: &WgpuContext, name: &str, default: &[u32], old_extras: &[u32], num_particles: usize) -> (ChannelId, Vec<u32>) {
+        assert!(self.channels.len() < MAX_PARTICLE_CHANNELS, "Too many particle channels");
+        assert!(!default.is_empty(), "A channel needs at least one component");
+        assert!(self.find(name).is_none(), "Channel {} already registered", name);
+
+        let old_stride = self.stride as usize;
+        let mut new_extras = Vec::with_capacity(num_particles * (old_stride + default.len()));
+        for particle in 0..num_particles {
+            new_extras.extend_from_slice(&old_extras[particle * old_stride..(particle + 1) * old_stride]);
+            new_extras.extend_from_slice(default);
+        }
+
+        let id = ChannelId(self.channels.len() as u32);
+        self.channels.push(ChannelDescriptor {
+            name: name.to_string(),
+            offset: self.stride,
+            default: default.to_vec(),
+        });
+        self.stride += default.len() as u32;
+        self.update_layout(wgpu_context, num_particles);
+
+        if new_extras.is_empty() {
+            new_extras.push(0);
+        }
+        (id, new_extras)
+    }
+
+    /// Returns the words of one particle filled with every channel's default value.
+    /// `overrides` replaces the default of the given channels.
+    pub fn particle_words(&self, overrides: &[(ChannelId, &[u32])]) -> Vec<u32> {
+        let mut words = self.default_words();
+        for (id, values) in overrides {
+            let channel = &self.channels[id.0 as usize];
+            assert_eq!(values.len(), channel.default.len(), "Wrong number of components for channel {}", channel.name);
+            let offset = channel.offset as usize;
+            words[offset..offset + values.len()].copy_from_slice(values);
+        }
+        words
+    }
+
+    fn default_words(&self) -> Vec<u32> {
+        self.channels.iter().flat_map(|channel| channel.default.iter().copied()).collect()
+    }
+
+    /// Updates the particle count in the layout uniform.
+    pub fn update_layout(&mut self, wgpu_context: &WgpuContext, num_particles: usize) {
+        let mut offsets = [[0u32; 4]; MAX_PARTICLE_CHANNELS / 4];
+        for (i, channel) in self.channels.iter().enumerate() {
+            offsets[i / 4][i % 4] = channel.offset;
+        }
+        let uniform = ChannelLayoutUniform {
+            stride: self.stride,
+            num_channels: self.channels.len() as u32,
+            num_particles: num_particles as u32,
+            _padding: 0,
+            offsets,
+        };
+        self.layout.replace_elem(uniform, 0, wgpu_context);
+    }
+
+    pub fn find(&self, name: &str) -> Option<ChannelId> {
+        self.channels.iter().position(|channel| channel.name == name).map(|i| ChannelId(i as u32))
+    }
+
+    /// Word offset of the channel inside a particle's record.
+    pub fn offset(&self, id: ChannelId) -> u32 {
+        self.channels[id.0 as usize].offset
+    }
+
+    /// Number of `u32` words of the channel in a particle's record.
+    pub fn num_components(&self, id: ChannelId) -> usize {
+        self.channels[id.0 as usize].default.len()
+    }
+
+    /// Number of `u32` words stored per particle.
+    pub fn stride(&self) -> u32 {
+        self.stride
+    }
+
+    pub fn len(&self) -> usize {
+        self.channels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    pub fn layout_buffer(&self) -> &GpuBuffer<ChannelLayoutUniform> {
+        &self.layout
+    }
+}
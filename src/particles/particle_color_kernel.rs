@@ -0,0 +1,271 @@
+use glam::{Vec2, Vec3, Vec4};
+use wgpu::{BindGroupLayout, CommandEncoder, PushConstantRange};
+use crate::particles::particle_buffers::ParticleBuffers;
+use crate::particles::particle_channels::ParticleChannels;
+use crate::physics::heat_diffusion::TEMPERATURE_CHANNEL;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::bind_resources::BindResources;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::profiler::GpuProfiler;
+
+pub const WORKGROUP_SIZE: u32 = 64;
+const NO_CHANNEL: u32 = u32::MAX;
+
+/// What the particle colors show.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColorSource {
+    /// |current - previous position| / delta time, in world units per second.
+    Speed,
+    /// Particles whose center is in the same grid cell.
+    Density,
+    /// The `TEMPERATURE_CHANNEL` channel, see `Simulation::enable_heat`. Particles without it are at 0.
+    Temperature,
+}
+
+/// Gradient from the low to the high values.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColorMap {
+    /// Dark purple, blue, green, yellow.
+    Viridis,
+    /// Black, red, yellow, white.
+    Heat,
+}
+
+impl ColorMap {
+    /// Color at `t`, clamped to [0, 1]. Must match particle_color_kernel.wgsl
+    pub fn sample(&self, t: f32) -> Vec4 {
+        let t = t.clamp(0.0, 1.0);
+        let color = match self {
+            ColorMap::Heat => Vec3::new(3.0 * t, 3.0 * t - 1.0, 3.0 * t - 2.0),
+            ColorMap::Viridis => {
+                // Polynomial fit of viridis
+                let c0 = Vec3::new(0.27772734, 0.0054073445, 0.3340998);
+                let c1 = Vec3::new(0.10509304, 1.4046135, 1.3845901);
+                let c2 = Vec3::new(-0.33086184, 0.21484756, 0.095095165);
+                let c3 = Vec3::new(-4.6342306, -5.799101, -19.332441);
+                let c4 = Vec3::new(6.22827, 14.179934, 56.69055);
+                let c5 = Vec3::new(4.776385, -13.745146, -65.353035);
+                let c6 = Vec3::new(-5.435456, 4.6458526, 26.312435);
+                c0 + t * (c1 + t * (c2 + t * (c3 + t * (c4 + t * (c5 + t * c6)))))
+            }
+        };
+        color.clamp(Vec3::ZERO, Vec3::ONE).extend(1.0)
+    }
+}
+
+/// Colors the particles from their state every frame, see `Simulation::set_particle_colors`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ParticleColorSettings {
+    pub source: ColorSource,
+    pub color_map: ColorMap,
+    /// Speed, density or temperature at the end of the color map, lower values are spread over it linearly.
+    pub max_value: f32,
+}
+
+impl ParticleColorSettings {
+    pub fn speed(color_map: ColorMap) -> Self {
+        Self { source: ColorSource::Speed, color_map, max_value: 30.0 }
+    }
+
+    pub fn density(color_map: ColorMap) -> Self {
+        Self { source: ColorSource::Density, color_map, max_value: 4.0 }
+    }
+
+    pub fn temperature(color_map: ColorMap) -> Self {
+        Self { source: ColorSource::Temperature, color_map, max_value: 100.0 }
+    }
+
+    /// Next mode of the color toggle: the drawer's velocity shading, speed, density, and back.
+    pub fn cycle(current: Option<Self>) -> Option<Self> {
+        match current.map(|settings| settings.source) {
+            None => Some(Self::speed(ColorMap::Viridis)),
+            Some(ColorSource::Speed) => Some(Self::density(ColorMap::Heat)),
+            Some(ColorSource::Density) | Some(ColorSource::Temperature) => None,
+        }
+    }
+}
+
+/// What a color pass covers. `delta_time` is the one of the last step, `origin`, `world_size` and `cell_size`
+/// give the cells of the density.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct ColorParams {
+    pub num_particles: u32,
+    pub delta_time: f32,
+    pub origin: Vec2,
+    pub world_size: Vec2,
+    pub cell_size: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstants {
+    num_particles: u32,
+    source: u32,
+    color_map: u32,
+    table_width: u32,
+    table_height: u32,
+    cell_size: f32,
+    max_value: f32,
+    inv_delta_time: f32,
+    origin: Vec2,
+    extras_stride: u32,
+    temperature_offset: u32,
+}
+
+/// Recomputes the color of every particle from its speed, the number of particles of its grid cell or its temperature.
+/// The density counts the particle centers per cell of a table covering the world, with the cell size of the grid.
+pub(crate) struct ParticleColorKernel {
+    count_shader: ComputeShader,
+    color_shader: ComputeShader,
+    bind_resources: BindResources,
+    cell_counts: GpuBuffer<u32>,
+    settings: ParticleColorSettings,
+}
+
+impl ParticleColorKernel {
+    pub fn new(wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers, settings: ParticleColorSettings) -> Self {
+        let cell_counts = GpuBuffer::new(wgpu_context, vec![0u32], wgpu::BufferUsages::STORAGE);
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let bind_group = Self::create_bind_group(wgpu_context, &bind_group_layout, particle_buffers, &cell_counts);
+        let bind_resources = BindResources::new(bind_group_layout, bind_group);
+
+        let create_shader = |entry_point: &str| ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("particle_color_kernel.wgsl"),
+            entry_point,
+            &bind_resources.bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange {
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstants>() as u32,
+                }
+            ]
+        );
+        let count_shader = create_shader("count_particles");
+        let color_shader = create_shader("color_particles");
+
+        Self {
+            count_shader,
+            color_shader,
+            bind_resources,
+            cell_counts,
+            settings,
+        }
+    }
+
+    pub fn settings(&self) -> ParticleColorSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: ParticleColorSettings) {
+        self.settings = settings;
+    }
+
+    /// Follows the particle buffers after they grew or got a new channel.
+    pub fn refresh(&mut self, wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers) {
+        self.bind_resources.bind_group = Self::create_bind_group(wgpu_context, &self.bind_resources.bind_group_layout, particle_buffers, &self.cell_counts);
+    }
+
+    /// Records the color passes.
+    pub fn record(&mut self, wgpu_context: &WgpuContext, encoder: &mut CommandEncoder, gpu_profiler: &GpuProfiler, particle_buffers: &ParticleBuffers, channels: &ParticleChannels, params: ColorParams) {
+        let ColorParams { num_particles, delta_time, origin, world_size, cell_size } = params;
+        if num_particles == 0 {
+            return;
+        }
+        let table_size = (world_size / cell_size).ceil().as_uvec2().max(glam::UVec2::ONE);
+        let num_cells = (table_size.x * table_size.y) as usize;
+        let density = self.settings.source == ColorSource::Density;
+        if density && self.cell_counts.len() < num_cells {
+            self.cell_counts.push_all(&vec![0u32; num_cells - self.cell_counts.len()], wgpu_context);
+            self.refresh(wgpu_context, particle_buffers);
+        }
+
+        let push_constants = PushConstants {
+            num_particles,
+            source: match self.settings.source {
+                ColorSource::Speed => 0,
+                ColorSource::Density => 1,
+                ColorSource::Temperature => 2,
+            },
+            color_map: match self.settings.color_map {
+                ColorMap::Viridis => 0,
+                ColorMap::Heat => 1,
+            },
+            table_width: table_size.x,
+            table_height: table_size.y,
+            cell_size,
+            max_value: self.settings.max_value.max(f32::EPSILON),
+            inv_delta_time: if delta_time > 0.0 { 1.0 / delta_time } else { 0.0 },
+            origin,
+            extras_stride: channels.stride(),
+            temperature_offset: channels.find(TEMPERATURE_CHANNEL).map_or(NO_CHANNEL, |id| channels.offset(id)),
+        };
+
+        if density {
+            encoder.clear_buffer(self.cell_counts.buffer(), 0, None);
+        }
+        let mut scope = gpu_profiler.scope("Particle colors", encoder);
+        if density {
+            self.count_shader.dispatch_by_items(
+                &mut scope,
+                (num_particles, 1, 1),
+                Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                &self.bind_resources.bind_group
+            );
+        }
+        self.color_shader.dispatch_by_items(
+            &mut scope,
+            (num_particles, 1, 1),
+            Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+            &self.bind_resources.bind_group
+        );
+    }
+
+    fn create_bind_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, particle_buffers: &ParticleBuffers, cell_counts: &GpuBuffer<u32>) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Particle color bind group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: particle_buffers.current_positions.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: particle_buffers.previous_positions.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: particle_buffers.colors.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 3, resource: cell_counts.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 4, resource: particle_buffers.extras.buffer().as_entire_binding() },
+            ],
+        })
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Particle color bind group layout"),
+            entries: &[
+                // Positions
+                storage_entry(0, true),
+                // Previous positions
+                storage_entry(1, true),
+                // Colors
+                storage_entry(2, false),
+                // Cell counts
+                storage_entry(3, false),
+                // Extras, for the temperature channel
+                storage_entry(4, true),
+            ],
+        })
+    }
+}
@@ -0,0 +1,96 @@
+override WORKGROUP_SIZE = 64u;
+
+// Must match ColorSource
+const SOURCE_SPEED = 0u;
+const SOURCE_DENSITY = 1u;
+const SOURCE_TEMPERATURE = 2u;
+const NO_CHANNEL = 0xffffffffu;
+// Must match ColorMap
+const MAP_VIRIDIS = 0u;
+const MAP_HEAT = 1u;
+
+struct PushConstantsData {
+    num_particles: u32,
+    source: u32,
+    color_map: u32,
+    // Cells of the density table along x, the table has table_size.x * table_size.y cells
+    table_width: u32,
+    table_height: u32,
+    cell_size: f32,
+    // Speed, density or temperature mapped to the end of the color map
+    max_value: f32,
+    inv_delta_time: f32,
+    // World position of the corner of cell (0, 0)
+    origin: vec2<f32>,
+    extras_stride: u32,
+    // Word offset of the temperature channel, NO_CHANNEL if the particles have none
+    temperature_offset: u32,
+}
+
+@group(0) @binding(0) var<storage, read> positions: array<vec2<f32>>;
+@group(0) @binding(1) var<storage, read> previous_positions: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read_write> colors: array<vec4<f32>>;
+// Particles per cell, cleared before every count
+@group(0) @binding(3) var<storage, read_write> cell_counts: array<atomic<u32>>;
+@group(0) @binding(4) var<storage, read> extras: array<u32>;
+
+var<push_constant> push_constants: PushConstantsData;
+
+// Density source only: every particle adds itself to the cell holding its center
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn count_particles(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= push_constants.num_particles {
+        return;
+    }
+    atomicAdd(&cell_counts[cell_index(positions[index])], 1u);
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn color_particles(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= push_constants.num_particles || index >= arrayLength(&colors) {
+        return;
+    }
+    let position = positions[index];
+    var value: f32;
+    if push_constants.source == SOURCE_DENSITY {
+        value = f32(atomicLoad(&cell_counts[cell_index(position)]));
+    }
+    else if push_constants.source == SOURCE_TEMPERATURE {
+        value = 0.0;
+        if push_constants.temperature_offset != NO_CHANNEL {
+            value = bitcast<f32>(extras[index * push_constants.extras_stride + push_constants.temperature_offset]);
+        }
+    }
+    else {
+        value = length(position - previous_positions[index]) * push_constants.inv_delta_time;
+    }
+    let t = clamp(value / push_constants.max_value, 0.0, 1.0);
+    colors[index] = vec4<f32>(sample_color_map(t), 1.0);
+}
+
+// Particles outside of the world count in the closest border cell
+fn cell_index(world_position: vec2<f32>) -> u32 {
+    let cell = vec2<i32>(floor((world_position - push_constants.origin) / push_constants.cell_size));
+    let max_cell = vec2<i32>(i32(push_constants.table_width) - 1, i32(push_constants.table_height) - 1);
+    let clamped = vec2<u32>(clamp(cell, vec2<i32>(0), max_cell));
+    return clamped.y * push_constants.table_width + clamped.x;
+}
+
+// Must match ColorMap::sample
+fn sample_color_map(t: f32) -> vec3<f32> {
+    if push_constants.color_map == MAP_HEAT {
+        return clamp(vec3<f32>(3.0 * t, 3.0 * t - 1.0, 3.0 * t - 2.0), vec3<f32>(0.0), vec3<f32>(1.0));
+    }
+    // Polynomial fit of viridis
+    let c0 = vec3<f32>(0.2777273272234177, 0.005407344544966578, 0.3340998053353061);
+    let c1 = vec3<f32>(0.1050930431085774, 1.404613529898575, 1.384590162594685);
+    let c2 = vec3<f32>(-0.3308618287255563, 0.214847559468213, 0.09509516302823659);
+    let c3 = vec3<f32>(-4.634230498983486, -5.799100973351585, -19.33244095627987);
+    let c4 = vec3<f32>(6.228269936347081, 14.17993336680509, 56.69055260068105);
+    let c5 = vec3<f32>(4.776384997670288, -13.74514537774601, -65.35303263337234);
+    let c6 = vec3<f32>(-5.435455855934631, 4.645852612178535, 26.3124352495832);
+    let color = c0 + t * (c1 + t * (c2 + t * (c3 + t * (c4 + t * (c5 + t * c6)))));
+    return clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+}
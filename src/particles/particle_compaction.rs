@@ -0,0 +1,220 @@
+use glam::{Vec2, Vec4};
+use wgpu::{BindGroup, BindGroupLayout, CommandEncoder, PushConstantRange};
+use crate::particles::particle_buffers::ParticleBuffers;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::bind_resources::BindResources;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::prefix_sum::prefix_sum::PrefixSum;
+use crate::utils::profiler::GpuProfiler;
+
+pub const WORKGROUP_SIZE: (u32, u32, u32) = (64, 1, 1);
+/// lifetime_offset of particles without a lifetime channel, see particle_compaction.wgsl.
+const NO_LIFETIME: u32 = u32::MAX;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstantData {
+    num_particles: u32,
+    extras_stride: u32,
+    lifetime_offset: u32,
+    elapsed_time: f32,
+    bounds_min: Vec2,
+    bounds_max: Vec2,
+}
+
+/// What a compaction pass removes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CompactionParams {
+    pub num_particles: u32,
+    pub extras_stride: u32,
+    /// Word of the lifetime channel in the extras of a particle, `None` if particles never expire.
+    pub lifetime_offset: Option<u32>,
+    /// Seconds simulated since the last compaction, subtracted from the lifetimes.
+    pub elapsed_time: f32,
+    /// Particles whose center leaves these bounds (or is not finite) are removed.
+    pub bounds_min: Vec2,
+    pub bounds_max: Vec2,
+}
+
+/// Removes the expired and out-of-bounds particles on the GPU (stream compaction): the particles are
+/// flagged, the flags are prefix summed with `PrefixSum`, and the survivors are scattered to the front
+/// of the copy buffers, then copied back, keeping their order. Only the surviving count has to be read
+/// back, by `read_live_particles`, to shrink the buffers logically.
+pub struct ParticleCompaction {
+    mark_alive_pass: ComputeShader,
+    scatter_pass: ComputeShader,
+    bind_resources: BindResources,
+    alive: GpuBuffer<u32>,
+    prefix_sum: PrefixSum,
+    result: GpuBuffer<u32>,
+}
+
+impl ParticleCompaction {
+    pub fn new(wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers, particle_copy_buffers: &ParticleBuffers) -> Self {
+        let alive = GpuBuffer::new(wgpu_context, vec![0u32; particle_buffers.current_positions.len().max(1)], wgpu::BufferUsages::STORAGE);
+        let prefix_sum = PrefixSum::new(wgpu_context, &alive);
+        let result = GpuBuffer::new(wgpu_context, vec![0u32], wgpu::BufferUsages::STORAGE);
+
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let bind_group = Self::create_bind_group(wgpu_context, &bind_group_layout, particle_buffers, particle_copy_buffers, &alive, &result);
+        let bind_resources = BindResources::new(bind_group_layout, bind_group);
+
+        let push_constants = vec![
+            PushConstantRange {
+                stages: wgpu::ShaderStages::COMPUTE,
+                range: 0..size_of::<PushConstantData>() as u32,
+            }
+        ];
+        let constants = vec![("WORKGROUP_SIZE", WORKGROUP_SIZE.0 as f64)];
+        let mark_alive_pass = ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("particle_compaction.wgsl"),
+            "mark_alive",
+            &bind_resources.bind_group_layout,
+            WORKGROUP_SIZE,
+            &constants,
+            &push_constants,
+        );
+        let scatter_pass = ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("particle_compaction.wgsl"),
+            "scatter_alive",
+            &bind_resources.bind_group_layout,
+            WORKGROUP_SIZE,
+            &constants,
+            &push_constants,
+        );
+
+        Self {
+            mark_alive_pass,
+            scatter_pass,
+            bind_resources,
+            alive,
+            prefix_sum,
+            result,
+        }
+    }
+
+    /// Must be called after the particle buffers grew or were replaced.
+    pub fn refresh(&mut self, wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers, particle_copy_buffers: &ParticleBuffers) {
+        let num_particles = particle_buffers.current_positions.len();
+        if num_particles > self.alive.len() {
+            self.alive.push_all(&vec![0u32; num_particles - self.alive.len()], wgpu_context);
+            self.prefix_sum.update_buffers(wgpu_context, &self.alive);
+        }
+        self.bind_resources.bind_group = Self::create_bind_group(wgpu_context, &self.bind_resources.bind_group_layout, particle_buffers, particle_copy_buffers, &self.alive, &self.result);
+    }
+
+    /// Records the compaction. The first `read_live_particles` particles are the survivors once it ran.
+    pub fn compact(&self, wgpu_context: &WgpuContext, encoder: &mut CommandEncoder, gpu_profiler: &mut GpuProfiler, particle_buffers: &ParticleBuffers, particle_copy_buffers: &ParticleBuffers, params: &CompactionParams) {
+        let num_particles = params.num_particles;
+        if num_particles == 0 {
+            return;
+        }
+        let push_constants = PushConstantData {
+            num_particles,
+            extras_stride: params.extras_stride,
+            lifetime_offset: params.lifetime_offset.unwrap_or(NO_LIFETIME),
+            elapsed_time: params.elapsed_time,
+            bounds_min: params.bounds_min,
+            bounds_max: params.bounds_max,
+        };
+
+        {
+            let mut scope = gpu_profiler.scope("Particle compaction mark alive", encoder);
+            self.mark_alive_pass.dispatch_by_items(&mut scope, (num_particles, 1, 1), Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]), &self.bind_resources.bind_group);
+        }
+        {
+            let mut scope = gpu_profiler.scope("Particle compaction prefix sum", encoder);
+            self.prefix_sum.execute(wgpu_context, &mut scope, num_particles);
+        }
+        {
+            let mut scope = gpu_profiler.scope("Particle compaction scatter", encoder);
+            self.scatter_pass.dispatch_by_items(&mut scope, (num_particles, 1, 1), Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]), &self.bind_resources.bind_group);
+        }
+
+        // Copy the survivors back, like the particle rearrange does
+        let mut scope = gpu_profiler.scope("Particle compaction copy", encoder);
+        let num_particles = num_particles as u64;
+        scope.copy_buffer_to_buffer(particle_copy_buffers.current_positions.buffer(), 0, particle_buffers.current_positions.buffer(), 0, num_particles * size_of::<Vec2>() as u64);
+        scope.copy_buffer_to_buffer(particle_copy_buffers.previous_positions.buffer(), 0, particle_buffers.previous_positions.buffer(), 0, num_particles * size_of::<Vec2>() as u64);
+        scope.copy_buffer_to_buffer(particle_copy_buffers.radii.buffer(), 0, particle_buffers.radii.buffer(), 0, num_particles * size_of::<f32>() as u64);
+        // Particle systems built from test buffers have a single color
+        let colors_size = (num_particles * size_of::<Vec4>() as u64).min(particle_buffers.colors.buffer().size()).min(particle_copy_buffers.colors.buffer().size());
+        scope.copy_buffer_to_buffer(particle_copy_buffers.colors.buffer(), 0, particle_buffers.colors.buffer(), 0, colors_size);
+        if params.extras_stride > 0 {
+            scope.copy_buffer_to_buffer(particle_copy_buffers.extras.buffer(), 0, particle_buffers.extras.buffer(), 0, num_particles * params.extras_stride as u64 * size_of::<u32>() as u64);
+        }
+        let copied = num_particles * (2 * size_of::<Vec2>() + size_of::<f32>() + params.extras_stride as usize * size_of::<u32>()) as u64 + colors_size;
+        wgpu_context.transfers().record_copy(copied);
+    }
+
+    /// Number of particles that survived the last compaction. Stalls until the GPU is done.
+    pub fn read_live_particles(&self, wgpu_context: &WgpuContext) -> u32 {
+        self.result.read_back(wgpu_context).unwrap()[0]
+    }
+
+    fn create_bind_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, particle_buffers: &ParticleBuffers, particle_copy_buffers: &ParticleBuffers, alive: &GpuBuffer<u32>, result: &GpuBuffer<u32>) -> BindGroup {
+        let buffers = [
+            particle_buffers.current_positions.buffer(),
+            particle_buffers.previous_positions.buffer(),
+            particle_buffers.radii.buffer(),
+            particle_buffers.colors.buffer(),
+            particle_buffers.extras.buffer(),
+            alive.buffer(),
+            particle_copy_buffers.current_positions.buffer(),
+            particle_copy_buffers.previous_positions.buffer(),
+            particle_copy_buffers.radii.buffer(),
+            particle_copy_buffers.colors.buffer(),
+            particle_copy_buffers.extras.buffer(),
+            result.buffer(),
+        ];
+        let entries: Vec<wgpu::BindGroupEntry> = buffers.iter().enumerate().map(|(binding, buffer)| wgpu::BindGroupEntry {
+            binding: binding as u32,
+            resource: buffer.as_entire_binding(),
+        }).collect();
+
+        wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Particle compaction bind group"),
+            layout: bind_group_layout,
+            entries: &entries,
+        })
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Particle compaction bind group layout"),
+            entries: &[
+                // Positions, previous positions, radii and colors read
+                storage_entry(0, true),
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, true),
+                // Extras, the lifetimes are aged in place
+                storage_entry(4, false),
+                // Alive flags, prefix summed
+                storage_entry(5, false),
+                // Positions, previous positions, radii, colors and extras write
+                storage_entry(6, false),
+                storage_entry(7, false),
+                storage_entry(8, false),
+                storage_entry(9, false),
+                storage_entry(10, false),
+                // Surviving count
+                storage_entry(11, false),
+            ],
+        })
+    }
+}
@@ -0,0 +1,99 @@
+override WORKGROUP_SIZE = 64u;
+
+// lifetime_offset of particles without a lifetime channel
+const NO_LIFETIME = 0xffffffffu;
+
+struct PushConstantsData {
+    num_particles: u32,
+    extras_stride: u32,
+    // Word of the lifetime channel in the extras of a particle, or NO_LIFETIME
+    lifetime_offset: u32,
+    // Seconds simulated since the last compaction, subtracted from the lifetimes
+    elapsed_time: f32,
+    // Particles whose center leaves [bounds_min, bounds_max] are removed
+    bounds_min: vec2<f32>,
+    bounds_max: vec2<f32>,
+}
+
+struct CompactionResult {
+    live_particles: u32,
+}
+
+@group(0) @binding(0) var<storage, read> positions_read: array<vec2<f32>>;
+@group(0) @binding(1) var<storage, read> previous_positions_read: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read> radius_read: array<f32>;
+@group(0) @binding(3) var<storage, read> colors_read: array<vec4<f32>>;
+// Read-write, mark_alive ages the lifetimes
+@group(0) @binding(4) var<storage, read_write> extras_read: array<u32>;
+// 1 if the particle survives, then the inclusive prefix sum of the flags: the new index + 1
+@group(0) @binding(5) var<storage, read_write> alive: array<u32>;
+@group(0) @binding(6) var<storage, read_write> positions_write: array<vec2<f32>>;
+@group(0) @binding(7) var<storage, read_write> previous_positions_write: array<vec2<f32>>;
+@group(0) @binding(8) var<storage, read_write> radius_write: array<f32>;
+@group(0) @binding(9) var<storage, read_write> colors_write: array<vec4<f32>>;
+@group(0) @binding(10) var<storage, read_write> extras_write: array<u32>;
+@group(0) @binding(11) var<storage, read_write> result: CompactionResult;
+
+var<push_constant> push_constant_data: PushConstantsData;
+
+fn is_finite(v: vec2<f32>) -> bool {
+    // NaN is the only value different from itself
+    return all(v == v) && all(abs(v) <= vec2<f32>(3.40282347e38));
+}
+
+/// Step 1: ages the lifetimes and flags the particles that survive.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn mark_alive(@builtin(global_invocation_id) global_id: vec3<u32>){
+    let obj_id = global_id.x;
+    if obj_id >= push_constant_data.num_particles {
+        return;
+    }
+
+    let position = positions_read[obj_id];
+    var is_alive = is_finite(position)
+        && all(position >= push_constant_data.bounds_min)
+        && all(position <= push_constant_data.bounds_max);
+
+    if push_constant_data.lifetime_offset != NO_LIFETIME {
+        let lifetime_idx = obj_id * push_constant_data.extras_stride + push_constant_data.lifetime_offset;
+        let lifetime = bitcast<f32>(extras_read[lifetime_idx]) - push_constant_data.elapsed_time;
+        extras_read[lifetime_idx] = bitcast<u32>(lifetime);
+        is_alive = is_alive && lifetime > 0.0;
+    }
+
+    alive[obj_id] = select(0u, 1u, is_alive);
+}
+
+/// Step 2, after the prefix sum of the flags: moves the surviving particles to the front, keeping their order.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn scatter_alive(@builtin(global_invocation_id) global_id: vec3<u32>){
+    let obj_id = global_id.x;
+    let num_particles = push_constant_data.num_particles;
+    if obj_id >= num_particles {
+        return;
+    }
+
+    if obj_id == num_particles - 1u {
+        result.live_particles = alive[obj_id];
+    }
+
+    var alive_before = 0u;
+    if obj_id > 0u {
+        alive_before = alive[obj_id - 1u];
+    }
+    if alive[obj_id] == alive_before {
+        // Removed
+        return;
+    }
+
+    let new_idx = alive_before;
+    positions_write[new_idx] = positions_read[obj_id];
+    previous_positions_write[new_idx] = previous_positions_read[obj_id];
+    radius_write[new_idx] = radius_read[obj_id];
+    colors_write[new_idx] = colors_read[obj_id];
+
+    let stride = push_constant_data.extras_stride;
+    for (var i = 0u; i < stride; i++) {
+        extras_write[new_idx * stride + i] = extras_read[obj_id * stride + i];
+    }
+}
@@ -0,0 +1,170 @@
+use glam::{Mat4, Vec2};
+use wgpu::{BindGroupLayout, PushConstantRange};
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::profiler::GpuProfiler;
+
+pub const WORKGROUP_SIZE: u32 = 64;
+/// Indices of the quad the drawer instances, see `ParticleDrawer`.
+const QUAD_INDEX_COUNT: u32 = 6;
+/// Drawn size of a particle over its radius: a highlighted particle is 1.3 times larger, see `particle_drawer.wgsl`.
+pub const CULLING_RADIUS_SCALE: f32 = 1.3;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstants {
+    view_proj: [[f32; 4]; 4],
+    num_particles: u32,
+    radius_scale: f32,
+    _padding: [u32; 2],
+}
+
+/// Finds the particles inside the view of the camera before drawing: a GPU pass tests the quad of every
+/// particle against the view and appends the visible ones to `visible_ids`, counting them in the instance
+/// count of `draw_args`. The drawer then draws indirectly, one instance per visible particle, so zoomed in
+/// views of large simulations only shade what is on screen. Nothing is read back.
+pub struct ParticleCulling {
+    cull_shader: ComputeShader,
+    bind_group_layout: BindGroupLayout,
+    /// `wgpu::util::DrawIndexedIndirectArgs` of the particle quads
+    draw_args: GpuBuffer<u32>,
+    visible_ids: GpuBuffer<u32>,
+    /// The bind group and the buffers it binds, created again when one of them is reallocated
+    bind_group: Option<(Vec<wgpu::Buffer>, wgpu::BindGroup)>,
+}
+
+impl ParticleCulling {
+    pub fn new(wgpu_context: &WgpuContext) -> Self {
+        let draw_args = GpuBuffer::new(wgpu_context, vec![QUAD_INDEX_COUNT, 0, 0, 0, 0], wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT);
+        let visible_ids = GpuBuffer::new(wgpu_context, vec![0u32], wgpu::BufferUsages::STORAGE);
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let cull_shader = ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("particle_culling.wgsl"),
+            "cull_particles",
+            &bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange {
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstants>() as u32,
+                }
+            ]
+        );
+
+        Self {
+            cull_shader,
+            bind_group_layout,
+            draw_args,
+            visible_ids,
+            bind_group: None,
+        }
+    }
+
+    /// Indices of the visible particles, read by the drawer through the instance index.
+    pub fn visible_ids(&self) -> &GpuBuffer<u32> {
+        &self.visible_ids
+    }
+
+    /// Indirect arguments of `draw_indexed_indirect`, at offset 0.
+    pub fn draw_args(&self) -> &GpuBuffer<u32> {
+        &self.draw_args
+    }
+
+    /// Submits the culling of the first `num_particles` particles against `view_proj`, see `Camera::view_projection`.
+    /// Returns true if `visible_ids` was recreated: the bind groups that read it must be recreated too.
+    pub fn cull(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, positions: &GpuBuffer<Vec2>, radii: &GpuBuffer<f32>, num_particles: usize, view_proj: Mat4) -> bool {
+        let recreated = self.visible_ids.reserve(wgpu_context, num_particles);
+        if self.visible_ids.len() < num_particles {
+            self.visible_ids.push_all(&vec![0u32; num_particles - self.visible_ids.len()], wgpu_context);
+        }
+        self.draw_args.replace_elem(0, 1, wgpu_context);
+        if num_particles == 0 {
+            return recreated;
+        }
+        let buffers = vec![positions.buffer().clone(), radii.buffer().clone(), self.visible_ids.buffer().clone()];
+        if self.bind_group.as_ref().is_none_or(|(bound_buffers, _)| *bound_buffers != buffers) {
+            self.bind_group = Some((buffers, self.create_bind_group(wgpu_context, positions, radii)));
+        }
+        let (_, bind_group) = self.bind_group.as_ref().unwrap();
+
+        let push_constants = PushConstants {
+            view_proj: view_proj.to_cols_array_2d(),
+            num_particles: num_particles as u32,
+            radius_scale: CULLING_RADIUS_SCALE,
+            _padding: [0; 2],
+        };
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Particle culling encoder") }
+        );
+        {
+            let mut scope = gpu_profiler.scope("Particle culling", &mut encoder);
+            self.cull_shader.dispatch_by_items(
+                &mut scope,
+                (num_particles as u32, 1, 1),
+                Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                bind_group
+            );
+        }
+        gpu_profiler.resolve_queries(&mut encoder);
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+        recreated
+    }
+
+    /// Indices of the particles the last `cull` found visible, in increasing order. Stalls until the GPU is done.
+    pub fn download_visible_ids(&self, wgpu_context: &WgpuContext) -> Vec<u32> {
+        let num_visible = self.draw_args.read_back(wgpu_context).unwrap()[1] as usize;
+        if num_visible == 0 {
+            return Vec::new();
+        }
+        let mut visible_ids = self.visible_ids.read_back(wgpu_context).unwrap();
+        visible_ids.truncate(num_visible);
+        // Appended in no particular order
+        visible_ids.sort_unstable();
+        visible_ids
+    }
+
+    fn create_bind_group(&self, wgpu_context: &WgpuContext, positions: &GpuBuffer<Vec2>, radii: &GpuBuffer<f32>) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Particle culling bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: positions.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: radii.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: self.draw_args.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 3, resource: self.visible_ids.buffer().as_entire_binding() },
+            ],
+        })
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Particle culling bind group layout"),
+            entries: &[
+                // Positions
+                storage_entry(0, true),
+                // Radius
+                storage_entry(1, true),
+                // Draw arguments
+                storage_entry(2, false),
+                // Visible ids
+                storage_entry(3, false),
+            ],
+        })
+    }
+}
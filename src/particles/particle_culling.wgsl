@@ -0,0 +1,45 @@
+override WORKGROUP_SIZE = 64u;
+
+struct PushConstants {
+    // Camera view projection, world to clip space
+    view_proj: mat4x4<f32>,
+    num_particles: u32,
+    // Drawn size of a particle over its radius, with room for the highlight outline
+    radius_scale: f32,
+}
+
+// Same layout as wgpu::util::DrawIndexedIndirectArgs
+struct DrawIndexedIndirectArgs {
+    index_count: u32,
+    instance_count: atomic<u32>,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0) var<storage, read> positions: array<vec2<f32>>;
+@group(0) @binding(1) var<storage, read> radius: array<f32>;
+@group(0) @binding(2) var<storage, read_write> draw_args: DrawIndexedIndirectArgs;
+// One instance of the drawer per visible particle
+@group(0) @binding(3) var<storage, read_write> visible_ids: array<u32>;
+
+var<push_constant> push_constants: PushConstants;
+
+// Appends the particles whose quad overlaps the view to visible_ids, in no particular order, and counts
+// them in the instance count of the draw
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn cull_particles(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let idx = global_id.x;
+    if idx >= push_constants.num_particles {
+        return;
+    }
+    let clip = push_constants.view_proj * vec4<f32>(positions[idx], 0.0, 1.0);
+    let ndc = clip.xy / clip.w;
+    // Half extent of the quad in clip space, the camera has no rotation
+    let half_size = radius[idx] * push_constants.radius_scale;
+    let extent = abs((push_constants.view_proj * vec4<f32>(half_size, half_size, 0.0, 0.0)).xy) / clip.w;
+    if all(abs(ndc) <= vec2<f32>(1.0) + extent) {
+        let slot = atomicAdd(&draw_args.instance_count, 1u);
+        visible_ids[slot] = idx;
+    }
+}
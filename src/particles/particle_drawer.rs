@@ -1,26 +1,67 @@
-use glam::Vec2;
+use glam::{Mat4, Vec2};
 use wgpu::{BindGroup, BindGroupLayout};
 use crate::particles::particle_buffers::ParticleBuffers;
+use crate::particles::particle_culling::ParticleCulling;
+use crate::particles::particle_shape::{ParticleShape, SpriteAtlas};
 use crate::renderer::camera::Camera;
 use crate::renderer::wgpu_context::WgpuContext;
-use crate::utils::bind_resources::BindResources;
 use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::profiler::GpuProfiler;
+
+/// Word offset `PushConstants::sprite_frame_offset` has when there is no sprite frame channel.
+const NO_CHANNEL: u32 = u32::MAX;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstants {
+    use_particle_colors: u32,
+    shape: u32,
+    extras_stride: u32,
+    sprite_frame_offset: u32,
+    atlas_columns: u32,
+    atlas_rows: u32,
+}
+
+/// Texture of the sprite frames, a white pixel until `set_sprite_atlas`.
+struct AtlasTexture {
+    view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    columns: u32,
+    rows: u32,
+}
 
 pub struct ParticleDrawer{
     render_pipeline: Option<wgpu::RenderPipeline>,
     vertices: GpuBuffer<Vec2>,
     indices: GpuBuffer<u32>,
-    bind_resources: BindResources,
+    bind_group_layout: BindGroupLayout,
+    bind_groups: Vec<BindGroup>, // One per buffer set it can draw, see `ParticleBufferSwapchain`
+    displayed_set: usize,
+    /// Draws the colors buffer instead of shading the particles by velocity, see `ParticleSystem::set_color_settings`
+    use_particle_colors: bool,
+    shape: ParticleShape,
+    atlas: AtlasTexture,
+    /// Visible particles and the indirect draw arguments, see `cull`
+    culling: ParticleCulling,
 }
 
 impl ParticleDrawer{
-    pub fn new(wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers, camera: &Camera ) -> Self {
+    /// `particle_buffer_sets` are the buffers it can draw, the first one until `show_set`.
+    pub fn new(wgpu_context: &WgpuContext, particle_buffer_sets: &[&ParticleBuffers], highlight_flags: &GpuBuffer<u32>, camera: &Camera ) -> Self {
         let shader = wgpu_context.get_device().create_shader_module(wgpu::include_wgsl!("particle_drawer.wgsl"));
-        let bind_resources = Self::create_binding_resources(wgpu_context, particle_buffers);
+        let atlas = Self::create_atlas_texture(wgpu_context, &SpriteAtlas::white());
+        let culling = ParticleCulling::new(wgpu_context);
+        let bind_group_layout = Self::create_binding_group_layout(wgpu_context);
+        let bind_groups = Self::create_bind_groups(wgpu_context, &bind_group_layout, particle_buffer_sets, highlight_flags, &atlas, culling.visible_ids());
         let render_pipeline_layout = wgpu_context.get_device().create_pipeline_layout(&wgpu::PipelineLayoutDescriptor{
             label: Some("Render Pipeline Layout"),
-            bind_group_layouts: &[&bind_resources.bind_group_layout, &camera.camera_bind_group_layout()],
-            push_constant_ranges: &[],
+            bind_group_layouts: &[&bind_group_layout, camera.camera_bind_group_layout()],
+            push_constant_ranges: &[
+                wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    range: 0..size_of::<PushConstants>() as u32,
+                }
+            ],
         });
 
         let render_pipeline = wgpu_context.get_device().create_render_pipeline(&wgpu::RenderPipelineDescriptor{
@@ -42,7 +83,7 @@ impl ParticleDrawer{
                 module: &shader,
                 entry_point: Some("fs_main"),
                 targets: &[Some(wgpu::ColorTargetState{
-                    format: wgpu_context.get_surface_config().format,
+                    format: wgpu_context.surface_format(),
                     blend: Some(wgpu::BlendState::ALPHA_BLENDING),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
@@ -66,7 +107,7 @@ impl ParticleDrawer{
                 alpha_to_coverage_enabled: false,
             },
             multiview: None,
-            cache: None,
+            cache: wgpu_context.pipeline_cache(),
         });
         
         
@@ -77,11 +118,66 @@ impl ParticleDrawer{
             render_pipeline: Some(render_pipeline),
             vertices,
             indices,
-            bind_resources,
+            bind_group_layout,
+            bind_groups,
+            displayed_set: 0,
+            use_particle_colors: false,
+            shape: ParticleShape::default(),
+            atlas,
+            culling,
         }
         
     }
 
+    fn create_atlas_texture(wgpu_context: &WgpuContext, sprite_atlas: &SpriteAtlas) -> AtlasTexture {
+        let size = wgpu::Extent3d {
+            width: sprite_atlas.size().x,
+            height: sprite_atlas.size().y,
+            depth_or_array_layers: 1,
+        };
+        let texture = wgpu_context.get_device().create_texture(&wgpu::TextureDescriptor {
+            label: Some("Sprite atlas"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        wgpu_context.get_queue().write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            sprite_atlas.pixels(),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * size.width),
+                rows_per_image: Some(size.height),
+            },
+            size,
+        );
+        wgpu_context.transfers().record_upload(sprite_atlas.pixels().len() as u64);
+
+        let sampler = wgpu_context.get_device().create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Sprite atlas sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        AtlasTexture {
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            sampler,
+            columns: sprite_atlas.columns(),
+            rows: sprite_atlas.rows(),
+        }
+    }
+
     fn create_model_vertices(wgpu_context: &WgpuContext) -> GpuBuffer<Vec2>{
         GpuBuffer::new(
             wgpu_context,
@@ -102,31 +198,61 @@ impl ParticleDrawer{
         ], wgpu::BufferUsages::INDEX)
     }
 
-    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, camera: &Camera, num_particles: u32){
+    /// Finds the first `num_particles` particles of `particle_buffers` seen through `view_proj`, the ones the next
+    /// `draw` draws. Must be submitted before the render pass, once per frame. Returns true if the bind groups
+    /// must be recreated with `refresh`.
+    pub fn cull(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, particle_buffers: &ParticleBuffers, num_particles: usize, view_proj: Mat4) -> bool {
+        self.culling.cull(wgpu_context, gpu_profiler, &particle_buffers.current_positions, &particle_buffers.radii, num_particles, view_proj)
+    }
+
+    /// Draws the particles found by the last `cull`, one instance each, with the instance count written by the GPU.
+    /// `sprite_frame_offset` is the word of the `SPRITE_FRAME_CHANNEL` inside the `extras_stride` words of a particle, if registered.
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, camera: &Camera, extras_stride: u32, sprite_frame_offset: Option<u32>){
         render_pass.set_pipeline(self.render_pipeline.as_ref().expect("Render pipeline not set"));
         render_pass.set_vertex_buffer(0, self.vertices.buffer().slice(..));
         render_pass.set_index_buffer(self.indices.buffer().slice(..), wgpu::IndexFormat::Uint32);
 
-        render_pass.set_bind_group(0, &self.bind_resources.bind_group, &[]);
+        render_pass.set_bind_group(0, &self.bind_groups[self.displayed_set], &[]);
         render_pass.set_bind_group(1, camera.binding_group(), &[]);
-        render_pass.draw_indexed(0..self.get_indices().len() as u32, 0, 0..num_particles);
+        let push_constants = PushConstants {
+            use_particle_colors: self.use_particle_colors as u32,
+            shape: self.shape.id(),
+            extras_stride,
+            sprite_frame_offset: sprite_frame_offset.unwrap_or(NO_CHANNEL),
+            atlas_columns: self.atlas.columns,
+            atlas_rows: self.atlas.rows,
+        };
+        render_pass.set_push_constants(wgpu::ShaderStages::VERTEX_FRAGMENT, 0, bytemuck::bytes_of(&push_constants));
+        render_pass.draw_indexed_indirect(self.culling.draw_args().buffer(), 0);
     }
     
-    fn get_indices(&self) -> &Vec<u32>{
-        self.indices.data()
+    pub fn set_use_particle_colors(&mut self, use_particle_colors: bool) {
+        self.use_particle_colors = use_particle_colors;
     }
 
-    fn create_binding_resources(wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers) -> BindResources {
-        let bind_group_layout = Self::create_binding_group_layout(wgpu_context);
-        let bind_group = Self::create_bind_group(wgpu_context, &bind_group_layout, particle_buffers);
+    pub fn set_shape(&mut self, shape: ParticleShape) {
+        self.shape = shape;
+    }
 
-        BindResources{
-            bind_group_layout,
-            bind_group,
-        }
+    /// Draws the set at `index` of the sets given to `new` or `refresh`.
+    pub fn show_set(&mut self, index: usize) {
+        assert!(index < self.bind_groups.len(), "No particle buffer set {}", index);
+        self.displayed_set = index;
     }
 
-    fn create_bind_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, particle_buffers: &ParticleBuffers) -> BindGroup {
+    /// Uploads the frames of the sprite shape. The bind groups are recreated with the new texture.
+    pub fn set_sprite_atlas(&mut self, wgpu_context: &WgpuContext, sprite_atlas: &SpriteAtlas, particle_buffer_sets: &[&ParticleBuffers], highlight_flags: &GpuBuffer<u32>) {
+        self.atlas = Self::create_atlas_texture(wgpu_context, sprite_atlas);
+        self.refresh(wgpu_context, particle_buffer_sets, highlight_flags);
+    }
+
+    fn create_bind_groups(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, particle_buffer_sets: &[&ParticleBuffers], highlight_flags: &GpuBuffer<u32>, atlas: &AtlasTexture, visible_ids: &GpuBuffer<u32>) -> Vec<BindGroup> {
+        particle_buffer_sets.iter()
+            .map(|particle_buffers| Self::create_bind_group(wgpu_context, bind_group_layout, particle_buffers, highlight_flags, atlas, visible_ids))
+            .collect()
+    }
+
+    fn create_bind_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, particle_buffers: &ParticleBuffers, highlight_flags: &GpuBuffer<u32>, atlas: &AtlasTexture, visible_ids: &GpuBuffer<u32>) -> BindGroup {
         wgpu_context.get_device().create_bind_group(
             &wgpu::BindGroupDescriptor {
                 label: None,
@@ -144,6 +270,30 @@ impl ParticleDrawer{
                         binding: 2,
                         resource: particle_buffers.radii.buffer().as_entire_binding(),
                     },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: highlight_flags.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: particle_buffers.colors.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: particle_buffers.extras.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 6,
+                        resource: wgpu::BindingResource::TextureView(&atlas.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 7,
+                        resource: wgpu::BindingResource::Sampler(&atlas.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 8,
+                        resource: visible_ids.buffer().as_entire_binding(),
+                    },
                 ],
             }
         )
@@ -186,14 +336,78 @@ impl ParticleDrawer{
                     },
                     count: None,
                 },
+                // Binding 3: The particles' highlight flags
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Binding 4: The particles' colors
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Binding 5: The particles' channels, for the sprite frames
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Binding 6: The sprite atlas
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Binding 7: The sprite atlas sampler
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                // Binding 8: The particle of every instance, see `cull`
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         };
 
         wgpu_context.get_device().create_bind_group_layout(&bind_group_layout_descriptor)
     }
 
-    pub fn refresh(&mut self, wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers) {
-        self.bind_resources.bind_group = Self::create_bind_group(wgpu_context, &self.bind_resources.bind_group_layout, particle_buffers);
+    /// Binds `particle_buffer_sets`, keeping the index of the shown set.
+    pub fn refresh(&mut self, wgpu_context: &WgpuContext, particle_buffer_sets: &[&ParticleBuffers], highlight_flags: &GpuBuffer<u32>) {
+        self.bind_groups = Self::create_bind_groups(wgpu_context, &self.bind_group_layout, particle_buffer_sets, highlight_flags, &self.atlas, self.culling.visible_ids());
+        self.displayed_set = self.displayed_set.min(self.bind_groups.len() - 1);
     }
 
 
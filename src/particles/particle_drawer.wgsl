@@ -11,25 +11,76 @@ struct Camera {
 @group(0) @binding(0) var<storage, read> positions: array<vec2<f32>>;
 @group(0) @binding(1) var<storage, read> previous_positions: array<vec2<f32>>;
 @group(0) @binding(2) var<storage, read> radius: array<f32>;
+@group(0) @binding(3) var<storage, read> highlight_flags: array<u32>;
+// Written by particle_color_kernel.wgsl
+@group(0) @binding(4) var<storage, read> colors: array<vec4<f32>>;
+// Interleaved particle channels, see ParticleChannels
+@group(0) @binding(5) var<storage, read> extras: array<u32>;
+@group(0) @binding(6) var atlas: texture_2d<f32>;
+@group(0) @binding(7) var atlas_sampler: sampler;
+// Particle of every instance, written by particle_culling.wgsl
+@group(0) @binding(8) var<storage, read> visible_ids: array<u32>;
+
+// Must match ParticleShape::id
+const SHAPE_CIRCLE = 0u;
+const SHAPE_SQUARE = 1u;
+const SHAPE_HEXAGON = 2u;
+const SHAPE_SPRITE = 3u;
+const NO_CHANNEL = 0xffffffffu;
+// Inradius of a hexagon of circumradius 1
+const HEXAGON_INRADIUS = 0.8660254;
+
+struct PushConstantsData {
+    // Non-zero to draw the colors buffer instead of the velocity shading
+    use_particle_colors: u32,
+    shape: u32,
+    extras_stride: u32,
+    // Word of the sprite frame inside a particle's extras, NO_CHANNEL if there is none
+    sprite_frame_offset: u32,
+    atlas_columns: u32,
+    atlas_rows: u32,
+}
+
+var<push_constant> push_constants: PushConstantsData;
+
+// Highlighted particles are drawn this much larger, the extra space holds the outline. Must match CULLING_RADIUS_SCALE
+const HIGHLIGHT_SCALE = 1.3;
+const HIGHLIGHT_COLOR = vec3<f32>(1.0, 1.0, 1.0);
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) color: vec3<f32>,
     @location(1) local_pos: vec2<f32>,
+    // Quad scale, 1.0 unless the particle is highlighted
+    @location(2) @interpolate(flat) scale: f32,
+    // Atlas frame of the sprite shape
+    @location(3) @interpolate(flat) frame: u32,
 };
 
 @vertex
 fn vs_main(@builtin(instance_index) instance_id : u32,
 model: VertexInput) -> VertexOutput {
     var out: VertexOutput;
-    let particle_pos = positions[instance_id];
-    let radius = radius[instance_id];
-    let vel = particle_pos - previous_positions[instance_id];
-
-    out.color = get_particle_color(vel);
+    let index = visible_ids[instance_id];
+    let particle_pos = positions[index];
+    let radius = radius[index];
+    let vel = particle_pos - previous_positions[index];
+
+    if push_constants.use_particle_colors != 0u {
+        out.color = colors[min(index, arrayLength(&colors) - 1u)].rgb;
+    }
+    else {
+        out.color = get_particle_color(vel);
+    }
     out.local_pos = model.position;
-
-    let scaled_position = model.position * radius * 2.0;
+    out.scale = select(1.0, HIGHLIGHT_SCALE, highlight_flags[index] != 0u);
+    out.frame = 0u;
+    if push_constants.sprite_frame_offset != NO_CHANNEL {
+        let word = index * push_constants.extras_stride + push_constants.sprite_frame_offset;
+        out.frame = extras[min(word, arrayLength(&extras) - 1u)];
+    }
+
+    let scaled_position = model.position * radius * 2.0 * out.scale;
     let world_position = scaled_position + particle_pos;
 
     out.clip_position = u_camera.view_proj * vec4<f32>(world_position, 0.0, 1.0);
@@ -66,16 +117,55 @@ fn get_particle_color(particle_velocity: vec2<f32>) -> vec3<f32>{
     return color; // pass the velocity to the fragment shader
 }
 
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32>{
-    // Distance of the current pixel from the center (0.0, 0.0)
-    let dist_sq = dot(in.local_pos, in.local_pos);
+// Alpha of the shape at `pos`, relative to a quad from -0.5 to 0.5, with a smooth edge
+fn shape_coverage(pos: vec2<f32>) -> f32 {
+    switch push_constants.shape {
+        case SHAPE_SQUARE, SHAPE_SPRITE: {
+            return 1.0 - smoothstep(0.48, 0.5, max(abs(pos.x), abs(pos.y)));
+        }
+        case SHAPE_HEXAGON: {
+            // Pointy top: the inradius is along x
+            let q = abs(pos);
+            let dist = max(q.x, dot(q, vec2<f32>(0.5, HEXAGON_INRADIUS))) / HEXAGON_INRADIUS;
+            return 1.0 - smoothstep(0.48, 0.5, dist);
+        }
+        default: {
+            // When dist_sq is <= 0.2304 smoothstep is 0. Thus, alpha = 1 and the pixel is close to the center.
+            // When dist_sq is >= 0.25 smoothstep is 1. Thus, alpha = 0 and the pixel is far from the center.
+            // When dist_sq is in between 0.2304 0.25, smoothstep is in between 0 and 1. Creates a smooth fading effect
+            return 1.0 - smoothstep(0.2304, 0.25, dot(pos, pos));
+        }
+    }
+}
 
-    // Compute alpha value for this pixel
-    // When dist_sq is <= 0.2304 smoothstep is 0. Thus, alpha = 1 and the pixel is close to the center.
-    // When dist_sq is >= 0.25 smoothstep is 1. Thus, alpha = 0 and the pixel is far from the center.
-    // When dist_sq is in between 0.2304 0.25, smoothstep is in between 0 and 1. Creates a smooth fading effect
-    let alpha = 1.0 - smoothstep(0.2304, 0.25, dist_sq);
+// Texture coordinates of `pos` inside `frame`. Must match SpriteAtlas::frame_uv
+fn frame_uv(frame: u32, pos: vec2<f32>) -> vec2<f32> {
+    let grid = max(vec2<u32>(push_constants.atlas_columns, push_constants.atlas_rows), vec2<u32>(1u));
+    let wrapped = frame % (grid.x * grid.y);
+    let cell = vec2<f32>(vec2<u32>(wrapped % grid.x, wrapped / grid.x));
+    // The quad y goes up, the texture v goes down
+    let in_frame = vec2<f32>(pos.x + 0.5, 0.5 - pos.y);
+    return (cell + in_frame) / vec2<f32>(grid);
+}
 
-    return vec4<f32>(in.color, alpha);
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32>{
+    // Position of the current pixel relative to the particle and not to the quad
+    let body_pos = in.local_pos * in.scale;
+    // Sampled in uniform control flow, only used by the sprites
+    let texel = textureSample(atlas, atlas_sampler, frame_uv(in.frame, clamp(body_pos, vec2<f32>(-0.5), vec2<f32>(0.5))));
+
+    var color = in.color;
+    var alpha = shape_coverage(body_pos);
+    if push_constants.shape == SHAPE_SPRITE {
+        color = texel.rgb * select(vec3<f32>(1.0), in.color, push_constants.use_particle_colors != 0u);
+        alpha *= texel.a;
+    }
+    if in.scale == 1.0 {
+        return vec4<f32>(color, alpha);
+    }
+
+    // Soft outline filling the rest of the enlarged quad
+    let outline_alpha = 0.8 * shape_coverage(in.local_pos);
+    return vec4<f32>(mix(HIGHLIGHT_COLOR, color, alpha), max(alpha, outline_alpha));
 }
@@ -0,0 +1,109 @@
+use glam::Vec2;
+use crate::particles::particle_system::{SpawnMass, SpawnReport};
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::simulation::Simulation;
+use crate::utils::telemetry::RefreshTiming;
+
+/// Particles an emitter waits for before spawning, so the buffers are not refreshed every frame.
+pub const DEFAULT_MIN_BATCH: usize = 64;
+
+/// Spawns particles at a steady rate in rows along a horizontal line, e.g. sand poured into a funnel.
+/// The rows start at `position`, centered on it and `width` wide, and stack upwards.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParticleEmitter {
+    position: Vec2,
+    width: f32,
+    /// Particles per second
+    rate: f32,
+    min_batch: usize,
+    /// Mass of the emitted particles, `None` leaves the spawn mass of the simulation alone
+    mass: Option<SpawnMass>,
+    /// Particles owed but not spawned yet
+    pending: f32,
+    emitted: usize,
+}
+
+impl ParticleEmitter {
+    pub fn new(position: Vec2, width: f32, rate: f32) -> Self {
+        Self {
+            position,
+            width,
+            rate,
+            min_batch: DEFAULT_MIN_BATCH,
+            mass: None,
+            pending: 0.0,
+            emitted: 0,
+        }
+    }
+
+    /// Waits for `min_batch` particles before spawning, at least one.
+    pub fn with_min_batch(mut self, min_batch: usize) -> Self {
+        self.min_batch = min_batch.max(1);
+        self
+    }
+
+    /// Gives the emitted particles `mass`: before every batch the emitter sets it as the spawn mass of the
+    /// simulation, enabling the masses, see `Simulation::set_spawn_mass`.
+    pub fn with_mass(mut self, mass: SpawnMass) -> Self {
+        self.mass = Some(mass);
+        self
+    }
+
+    pub fn mass(&self) -> Option<SpawnMass> {
+        self.mass
+    }
+
+    pub fn position(&self) -> Vec2 {
+        self.position
+    }
+
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    pub fn rate(&self) -> f32 {
+        self.rate
+    }
+
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate.max(0.0);
+    }
+
+    /// Particles spawned so far, not counting the refused ones.
+    pub fn emitted(&self) -> usize {
+        self.emitted
+    }
+
+    /// Advances the emitter by `delta_time` and returns the positions to spawn, `spacing` apart.
+    /// Empty until `min_batch` particles are owed.
+    pub fn take_positions(&mut self, delta_time: f32, spacing: f32) -> Vec<Vec2> {
+        self.pending += self.rate * delta_time.max(0.0);
+        if self.pending < self.min_batch as f32 {
+            return Vec::new();
+        }
+        let count = self.pending as usize;
+        self.pending -= count as f32;
+
+        let columns = (self.width / spacing) as usize + 1;
+        let row_width = (columns - 1) as f32 * spacing;
+        (0..count).map(|i| {
+            let (row, column) = (i / columns, i % columns);
+            self.position + Vec2::new(column as f32 * spacing - row_width / 2.0, row as f32 * spacing)
+        }).collect()
+    }
+
+    /// Spawns the particles owed after `delta_time` into `simulation`, spaced by the largest spawn radius.
+    pub fn update(&mut self, wgpu_context: &WgpuContext, simulation: &mut Simulation, delta_time: f32) -> (SpawnReport, Vec<(&'static str, RefreshTiming)>) {
+        let (_, max_radius) = simulation.particles().spawn_radius_range();
+        let positions = self.take_positions(delta_time, 2.0 * max_radius);
+        if positions.is_empty() {
+            return (SpawnReport::default(), Vec::new());
+        }
+        if let Some(mass) = self.mass {
+            simulation.set_spawn_mass(wgpu_context, mass);
+        }
+        let (report, refreshes) = simulation.add_particles_at(wgpu_context, &positions);
+        self.emitted += report.spawned + report.recycled;
+        (report, refreshes)
+    }
+}
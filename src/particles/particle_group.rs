@@ -0,0 +1,157 @@
+use glam::Vec4;
+use wgpu::{BindGroupLayout, PushConstantRange};
+use crate::particles::particle_buffers::ParticleBuffers;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+
+pub const WORKGROUP_SIZE: u32 = 64;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstants {
+    color: Vec4,
+    num_ids: u32,
+    _padding: [u32; 3],
+}
+
+/// Something done to a group of particles, e.g. the ones of a `ParticleSelectionQuery`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GroupOperation {
+    /// Paints the particles with an RGBA color.
+    Recolor(Vec4),
+    /// Zeroes the velocity of the particles. Collisions and the mouse can set them moving again.
+    Freeze,
+    /// Deletes the particles, see `Simulation::apply_group_operation`.
+    Remove,
+}
+
+/// Runs a `GroupOperation` over a list of particle indices on the GPU.
+pub struct ParticleGroupOperations {
+    recolor_shader: ComputeShader,
+    freeze_shader: ComputeShader,
+    mark_removed_shader: ComputeShader,
+    bind_group_layout: BindGroupLayout,
+    ids: GpuBuffer<u32>,
+}
+
+impl ParticleGroupOperations {
+    pub fn new(wgpu_context: &WgpuContext) -> Self {
+        let ids = GpuBuffer::new(wgpu_context, vec![0u32], wgpu::BufferUsages::STORAGE);
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let create_shader = |entry_point: &str| ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("particle_group.wgsl"),
+            entry_point,
+            &bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange{
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstants>() as u32,
+                }
+            ]
+        );
+        let recolor_shader = create_shader("recolor");
+        let freeze_shader = create_shader("freeze");
+        let mark_removed_shader = create_shader("mark_removed");
+
+        Self {
+            recolor_shader,
+            freeze_shader,
+            mark_removed_shader,
+            bind_group_layout,
+            ids,
+        }
+    }
+
+    /// Submits `operation` over the particles at `ids`. `Remove` only moves the particles far outside
+    /// the world, the next compaction deletes them.
+    pub fn apply(&mut self, wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers, ids: &[u32], operation: GroupOperation) {
+        if ids.is_empty() {
+            return;
+        }
+        if self.ids.len() < ids.len() {
+            self.ids.push_all(&vec![0u32; ids.len() - self.ids.len()], wgpu_context);
+        }
+        wgpu_context.get_queue().write_buffer(self.ids.buffer(), 0, bytemuck::cast_slice(ids));
+        wgpu_context.transfers().record_upload(size_of_val(ids) as u64);
+        let bind_group = self.create_bind_group(wgpu_context, particle_buffers);
+
+        let (shader, color) = match operation {
+            GroupOperation::Recolor(color) => (&self.recolor_shader, color),
+            GroupOperation::Freeze => (&self.freeze_shader, Vec4::ZERO),
+            GroupOperation::Remove => (&self.mark_removed_shader, Vec4::ZERO),
+        };
+        let push_constants = PushConstants {
+            color,
+            num_ids: ids.len() as u32,
+            _padding: [0; 3],
+        };
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Particle group operation encoder") }
+        );
+        shader.dispatch_by_items(
+            &mut encoder,
+            (ids.len() as u32, 1, 1),
+            Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+            &bind_group
+        );
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+    }
+
+    fn create_bind_group(&self, wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                label: Some("Particle group operation bind group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: particle_buffers.current_positions.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: particle_buffers.previous_positions.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: particle_buffers.colors.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: self.ids.buffer().as_entire_binding(),
+                    },
+                ],
+            }
+        )
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Particle group operation bind group layout"),
+            entries: &[
+                // Positions, previous positions and colors
+                storage_entry(0, false),
+                storage_entry(1, false),
+                storage_entry(2, false),
+                // Indices of the group
+                storage_entry(3, true),
+            ],
+        })
+    }
+}
@@ -0,0 +1,45 @@
+override WORKGROUP_SIZE = 64u;
+
+// Far outside any world: the compaction removes the particles moved there
+const REMOVED_POSITION = vec2<f32>(-3.40282347e38);
+
+struct PushConstants {
+    color: vec4<f32>,
+    num_ids: u32,
+}
+
+@group(0) @binding(0) var<storage, read_write> positions: array<vec2<f32>>;
+@group(0) @binding(1) var<storage, read_write> previous_positions: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read_write> colors: array<vec4<f32>>;
+// Indices of the particles of the group
+@group(0) @binding(3) var<storage, read> ids: array<u32>;
+
+var<push_constant> push_constants: PushConstants;
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn recolor(@builtin(global_invocation_id) global_id: vec3<u32>){
+    if global_id.x >= push_constants.num_ids {
+        return;
+    }
+    colors[ids[global_id.x]] = push_constants.color;
+}
+
+// Stops the particles: the previous position is the velocity of the Verlet integration
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn freeze(@builtin(global_invocation_id) global_id: vec3<u32>){
+    if global_id.x >= push_constants.num_ids {
+        return;
+    }
+    let idx = ids[global_id.x];
+    previous_positions[idx] = positions[idx];
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn mark_removed(@builtin(global_invocation_id) global_id: vec3<u32>){
+    if global_id.x >= push_constants.num_ids {
+        return;
+    }
+    let idx = ids[global_id.x];
+    positions[idx] = REMOVED_POSITION;
+    previous_positions[idx] = REMOVED_POSITION;
+}
@@ -1,5 +1,6 @@
+use glam::Vec2;
 use wgpu::{BindGroup, BindGroupLayout, CommandEncoder, PushConstantRange};
-use wgpu_profiler::GpuProfiler;
+use crate::utils::profiler::GpuProfiler;
 use crate::particles::particle_buffers::ParticleBuffers;
 use crate::particles::particle_sort::WORKGROUP_SIZE;
 use crate::renderer::wgpu_context::WgpuContext;
@@ -17,6 +18,7 @@ pub struct ParticleHomeCellIdsKernel {
 struct PushConstantData{
     num_particles: u32,
     cell_size: f32,
+    origin: Vec2,
 }
 
 impl ParticleHomeCellIdsKernel {
@@ -129,7 +131,7 @@ impl ParticleHomeCellIdsKernel {
         self.bind_resources.bind_group = Self::create_bind_group(wgpu_context, &self.bind_resources.bind_group_layout, particle_buffers, particle_ids);
     }
     
-    pub fn create_home_cell_ids(&self, encoder: &mut CommandEncoder, gpu_profiler: &mut GpuProfiler, num_particles: u32, cell_size: f32) {
+    pub fn create_home_cell_ids(&self, encoder: &mut CommandEncoder, gpu_profiler: &GpuProfiler, num_particles: u32, cell_size: f32, origin: Vec2) {
         {
             let mut scope = gpu_profiler.scope("Particle home cells", encoder);
             self.home_cell_ids_pass.dispatch_by_items(
@@ -137,7 +139,8 @@ impl ParticleHomeCellIdsKernel {
                 (num_particles, 1, 1),
                 Some(vec![(0u32, bytemuck::bytes_of(&PushConstantData {
                     num_particles,
-                    cell_size
+                    cell_size,
+                    origin,
                 }))]),
                 &self.bind_resources.bind_group
             );
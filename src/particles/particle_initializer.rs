@@ -0,0 +1,78 @@
+use glam::Vec2;
+use rand::Rng;
+use crate::utils::rng;
+
+/// How the initial particles are placed in the world.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InitialLayout {
+    /// Uniformly random positions. Particles start overlapped and push each other apart in the first frames.
+    Random,
+    /// Hexagonal lattice spread over the whole region, without overlaps.
+    HexPacking,
+}
+
+impl InitialLayout {
+    /// Generates `count` positions for particles of `radius` inside `[region_min, region_max]`.
+    pub fn generate(&self, region_min: Vec2, region_max: Vec2, radius: f32, count: usize) -> Vec<Vec2> {
+        match self {
+            InitialLayout::Random => random_positions(region_min, region_max, count),
+            InitialLayout::HexPacking => hex_packing(region_min, region_max, radius, count),
+        }
+    }
+}
+
+fn random_positions(region_min: Vec2, region_max: Vec2, count: usize) -> Vec<Vec2> {
+    let mut rng = rng::rng();
+    (0..count)
+        .map(|_| Vec2::new(rng.random_range(region_min.x..region_max.x), rng.random_range(region_min.y..region_max.y)))
+        .collect()
+}
+
+/// Places up to `count` circles of `radius` on a hexagonal lattice inside `[region_min, region_max]`.
+/// The lattice spacing is the largest one that still fits `count` circles, so the particles are spread
+/// over the whole region, but never smaller than the diameter, so no circles overlap.
+/// Returns fewer positions if the region cannot hold `count` circles.
+pub fn hex_packing(region_min: Vec2, region_max: Vec2, radius: f32, count: usize) -> Vec<Vec2> {
+    let size = region_max - region_min;
+    let diameter = 2.0 * radius;
+    if count == 0 || size.x < diameter || size.y < diameter {
+        return Vec::new();
+    }
+
+    let row_height_factor = 3.0f32.sqrt() / 2.0;
+    let lattice_size = |spacing: f32| -> (usize, usize) {
+        // Odd rows are shifted by half a spacing, keep room for the shift
+        let cols = ((size.x - diameter - spacing / 2.0) / spacing).floor().max(0.0) as usize + 1;
+        let rows = ((size.y - diameter) / (spacing * row_height_factor)).floor().max(0.0) as usize + 1;
+        (cols, rows)
+    };
+
+    // Start from the spacing that would cover the area exactly and shrink until the lattice fits
+    let mut spacing = (size.x * size.y / (count as f32 * row_height_factor)).sqrt().max(diameter);
+    while spacing > diameter {
+        let (cols, rows) = lattice_size(spacing);
+        if cols * rows >= count {
+            break;
+        }
+        spacing = (spacing * 0.99).max(diameter);
+    }
+
+    let (cols, rows) = lattice_size(spacing);
+    if cols * rows < count {
+        log::warn!("Hex packing: the region only fits {} of {} particles", cols * rows, count);
+    }
+
+    let mut positions = Vec::with_capacity(count.min(cols * rows));
+    'rows: for row in 0..rows {
+        let row_offset = if row % 2 == 1 { spacing / 2.0 } else { 0.0 };
+        let y = region_min.y + radius + row as f32 * spacing * row_height_factor;
+        for col in 0..cols {
+            if positions.len() == count {
+                break 'rows;
+            }
+            let x = region_min.x + radius + row_offset + col as f32 * spacing;
+            positions.push(Vec2::new(x, y));
+        }
+    }
+    positions
+}
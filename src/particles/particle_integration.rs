@@ -1,19 +1,32 @@
 use glam::Vec2;
-use wgpu::{BindGroup, BindGroupLayout, PushConstantRange};
-use wgpu_profiler::GpuProfiler;
-use winit::event::ElementState;
+use wgpu::{CommandEncoder, PushConstantRange};
+use crate::utils::profiler::GpuProfiler;
 use crate::particles::particle_buffers::ParticleBuffers;
+use crate::particles::particle_channels::ParticleChannels;
+use crate::particles::particle_system::{BOUNDARY_MATERIAL_CHANNEL, PARTICLE_FLAGS_CHANNEL};
+use crate::physics::forces::{Forces, GlobalForces};
+use crate::particles::particle_interaction::{InteractionTool, InteractionUniform};
 use crate::renderer::wgpu_context::WgpuContext;
-use crate::utils::bind_resources::BindResources;
+use crate::simulation_config::{BoundaryMaterial, BoundaryMode, GravityMode, RadialFalloff, WorldBoundary};
+use crate::utils::bind_resources::{Bindings, BindingsBuilder};
 use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
 
 
 const WORKGROUP_SIZE: (u32, u32, u32) = (64, 1, 1);
+const NO_CHANNEL: u32 = u32::MAX;
 
 pub struct ParticleIntegration {
     integration_pass: ComputeShader,
-    bind_resources: BindResources,
+    bindings: Bindings,
     sim_params: SimParams,
+    interaction: InteractionTool,
+    /// Cursor position of the previous step
+    previous_interaction_position: Vec2,
+    interaction_buffer: GpuBuffer<InteractionUniform>,
+    /// Never empty, so it can be bound. `SimParams::num_materials` tells how many entries are real.
+    materials: GpuBuffer<BoundaryMaterial>,
+    forces: Forces,
 }
 
 #[repr(C)]
@@ -22,42 +35,93 @@ struct SimParams {
     pub delta_time: f32,
     pub world_width: f32,
     pub world_height: f32,
-    pub is_mouse_pressed: u32,
-    pub mouse_pos: Vec2,
     pub num_particles: u32,
+    /// Minimum corner of the world
+    pub world_origin: Vec2,
+    /// Center of the radial gravity
+    pub radial_center: Vec2,
+    pub radial_strength: f32,
+    /// 0 uniform, 1 radial constant, 2 radial inverse square. Must match particle_integration.wgsl
+    pub gravity_mode: u32,
+    pub boundary_center: Vec2,
+    pub boundary_radius: f32,
+    /// 0 rectangle, 1 circle
+    pub boundary_shape: u32,
+    pub boundary_restitution: f32,
+    pub boundary_friction: f32,
+    pub extras_stride: u32,
+    pub material_offset: u32, // NO_CHANNEL if the particles have no material channel
+    pub num_materials: u32,
+    pub flags_offset: u32, // NO_CHANNEL if the particles have no flags channel
+    /// 0 closed, 1 wrap, 2 open. Must match particle_integration.wgsl
+    pub boundary_mode: u32,
+    pub _padding: u32,
 }
 
 
 
 
 impl ParticleIntegration {
-    pub fn new(wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers, world_size: &Vec2) -> Self {
-        let bind_resources = Self::create_binding_resources(&wgpu_context, &particle_buffers);
-        let integration_pass = Self::create_integration_pass(wgpu_context, &bind_resources);
+    pub fn new(wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers, channels: &ParticleChannels, world_size: &Vec2) -> Self {
+        let interaction = InteractionTool::new();
+        let interaction_buffer = GpuBuffer::new(wgpu_context, vec![interaction.uniform(interaction.position())], wgpu::BufferUsages::UNIFORM);
+        let materials = GpuBuffer::new(wgpu_context, vec![BoundaryMaterial::default()], wgpu::BufferUsages::STORAGE);
+        let forces = Forces::new(wgpu_context);
+        let bindings = Self::bindings_builder(particle_buffers, &interaction_buffer, &materials, &forces).build(wgpu_context);
+        let integration_pass = Self::create_integration_pass(wgpu_context, &bindings);
 
         let sim_params = SimParams { 
             delta_time: 0.0, 
             world_width: world_size.x, 
             world_height: world_size.y, 
-            is_mouse_pressed: 0, 
-            mouse_pos: Vec2::new(0.0, 0.0), 
-            num_particles: particle_buffers.current_positions.len() as u32 };
+            num_particles: particle_buffers.current_positions.len() as u32,
+            world_origin: Vec2::ZERO,
+            radial_center: Vec2::ZERO,
+            radial_strength: 0.0,
+            gravity_mode: 0,
+            boundary_center: Vec2::ZERO,
+            boundary_radius: 0.0,
+            boundary_shape: 0,
+            boundary_restitution: 0.0,
+            boundary_friction: 0.0,
+            extras_stride: channels.stride(),
+            material_offset: Self::material_offset(channels),
+            num_materials: 0,
+            flags_offset: Self::flags_offset(channels),
+            boundary_mode: 0,
+            _padding: 0,
+        };
 
 
         Self {
             integration_pass,
-            bind_resources,
+            bindings,
             sim_params,
+            interaction,
+            previous_interaction_position: Vec2::ZERO,
+            interaction_buffer,
+            materials,
+            forces,
         }
     }
 
+    /// Offset of the `BOUNDARY_MATERIAL_CHANNEL` channel in the extras of a particle.
+    fn material_offset(channels: &ParticleChannels) -> u32 {
+        channels.find(BOUNDARY_MATERIAL_CHANNEL).map_or(NO_CHANNEL, |id| channels.offset(id))
+    }
+
+    /// Offset of the `PARTICLE_FLAGS_CHANNEL` channel in the extras of a particle.
+    fn flags_offset(channels: &ParticleChannels) -> u32 {
+        channels.find(PARTICLE_FLAGS_CHANNEL).map_or(NO_CHANNEL, |id| channels.offset(id))
+    }
+
     /// Creates the integration kernel
-    fn create_integration_pass(wgpu_context: &WgpuContext, particle_binding_group: &BindResources) -> ComputeShader {
+    fn create_integration_pass(wgpu_context: &WgpuContext, bindings: &Bindings) -> ComputeShader {
         ComputeShader::new(
             wgpu_context,
             wgpu::include_wgsl!("particle_integration.wgsl"),
             "verlet_integration",
-            &particle_binding_group.bind_group_layout,
+            bindings.layout(),
             WORKGROUP_SIZE,
             &vec![],
             &vec![
@@ -70,118 +134,171 @@ impl ParticleIntegration {
     }
     
     pub fn update_positions(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, delta_time: f32){
-        self.sim_params.delta_time = delta_time;
-        
         // Create a command encoder to build the command buffer
         let mut encoder = wgpu_context.get_device().create_command_encoder(
             &wgpu::CommandEncoderDescriptor { label: Some("Compute Encoder") }
         );
-
-        {
-            let mut scope = gpu_profiler.scope("Particle integration pass", &mut encoder);
-            self.integration_pass.dispatch_by_items(
-                &mut scope,
-                (self.sim_params.num_particles, 1, 1),
-                Some(vec![(0, bytemuck::bytes_of(&self.sim_params))]),
-                &self.bind_resources.bind_group,
-            );
-        }
+        self.record_update_positions(wgpu_context, &mut encoder, gpu_profiler, delta_time);
         gpu_profiler.resolve_queries(&mut encoder);
 
         // Submit the commands to the GPU
         wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
     }
 
-    fn create_binding_resources(wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers) -> BindResources {
-        let bind_group_layout = Self::create_binding_group_layout(wgpu_context);
-        let bind_group = Self::create_bind_group(wgpu_context, &bind_group_layout, particle_buffers);
+    /// Records the integration pass into `encoder`. The interaction uniform is written by `encoder` too, so the
+    /// steps of a frame recorded into one encoder each move the held particles by their own cursor motion.
+    pub fn record_update_positions(&mut self, wgpu_context: &WgpuContext, encoder: &mut CommandEncoder, gpu_profiler: &GpuProfiler, delta_time: f32){
+        self.sim_params.delta_time = delta_time;
+        let interaction_uniform = self.interaction.uniform(self.previous_interaction_position);
+        self.interaction_buffer.record_replace_elem(encoder, interaction_uniform, 0, wgpu_context);
+        self.previous_interaction_position = self.interaction.position();
+        self.forces.upload(wgpu_context);
 
-        BindResources{
-            bind_group_layout,
-            bind_group,
-        }
+        let mut scope = gpu_profiler.scope("Particle integration pass", encoder);
+        self.integration_pass.dispatch_by_items(
+            &mut scope,
+            (self.sim_params.num_particles, 1, 1),
+            Some(vec![(0, bytemuck::bytes_of(&self.sim_params))]),
+            &self.bindings.bind_group(),
+        );
     }
-    
-    fn create_bind_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, particle_buffers: &ParticleBuffers) -> BindGroup {
-        wgpu_context.get_device().create_bind_group(
-            &wgpu::BindGroupDescriptor {
-                label: None,
-                layout: &bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: particle_buffers.current_positions.buffer().as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: particle_buffers.previous_positions.buffer().as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 2,
-                        resource: particle_buffers.radii.buffer().as_entire_binding(),
-                    },
-                ],
-            }
-        )
+
+    fn bindings_builder(particle_buffers: &ParticleBuffers, interaction_buffer: &GpuBuffer<InteractionUniform>, materials: &GpuBuffer<BoundaryMaterial>, forces: &Forces) -> BindingsBuilder {
+        BindingsBuilder::new("Particle integration")
+            .storage_rw(&particle_buffers.current_positions)
+            .storage_rw(&particle_buffers.previous_positions)
+            .storage_ro(&particle_buffers.radii)
+            // The mouse interaction
+            .uniform(interaction_buffer)
+            // The extras, for the material and flags channels. The held flag is cleared on release
+            .storage_rw(&particle_buffers.extras)
+            // The boundary material table
+            .storage_ro(materials)
+            // The global forces
+            .uniform(forces.buffer())
     }
     
-    fn create_binding_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout{
-        let bind_group_layout_descriptor = wgpu::BindGroupLayoutDescriptor {
-            label: Some("Bind Group Layout Descriptor"),
-            entries: &[
-                // Binding 0: The particles' current positions
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false }, 
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // Binding 1: The particles' previous positions
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false }, // false means read-write
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // Binding 2: The particles' radius
-                wgpu::BindGroupLayoutEntry {
-                    binding: 2,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
+    /// Rebinds the kernel to the current buffers and follows the channel layout, after the buffers or the channels
+    /// were replaced. Buffers that only grew are followed by the bindings.
+    pub fn refresh(&mut self, particle_buffers: &ParticleBuffers, channels: &ParticleChannels) {
+        self.sim_params.num_particles = particle_buffers.current_positions.len() as u32;
+        self.sim_params.extras_stride = channels.stride();
+        self.sim_params.material_offset = Self::material_offset(channels);
+        self.sim_params.flags_offset = Self::flags_offset(channels);
+        self.bindings.rebind(Self::bindings_builder(particle_buffers, &self.interaction_buffer, &self.materials, &self.forces));
+    }
+
+    /// Updates the number of particles integrated, when the buffers grew in place and the bind group is still valid.
+    pub fn set_num_particles(&mut self, num_particles: u32) {
+        self.sim_params.num_particles = num_particles;
+    }
+
+    pub fn set_world_size(&mut self, world_size: Vec2) {
+        self.sim_params.world_width = world_size.x;
+        self.sim_params.world_height = world_size.y;
+    }
+
+    pub fn set_world_origin(&mut self, world_origin: Vec2) {
+        self.sim_params.world_origin = world_origin;
+    }
+
+    /// In world units, uploaded by the next integration.
+    pub fn set_forces(&mut self, forces: GlobalForces) {
+        self.forces.set(forces);
+    }
+
+    pub fn forces(&self) -> GlobalForces {
+        self.forces.get()
+    }
+
+    /// In world units, see `SimulationConfig::world_gravity_mode`.
+    pub fn set_gravity_mode(&mut self, gravity_mode: GravityMode) {
+        (self.sim_params.gravity_mode, self.sim_params.radial_center, self.sim_params.radial_strength) = match gravity_mode {
+            GravityMode::Uniform => (0, Vec2::ZERO, 0.0),
+            GravityMode::Radial { center, strength, falloff: RadialFalloff::Constant } => (1, center, strength),
+            GravityMode::Radial { center, strength, falloff: RadialFalloff::InverseSquare } => (2, center, strength),
         };
+    }
 
-        wgpu_context.get_device().create_bind_group_layout(&bind_group_layout_descriptor)
+    pub fn gravity_mode(&self) -> GravityMode {
+        let (center, strength) = (self.sim_params.radial_center, self.sim_params.radial_strength);
+        match self.sim_params.gravity_mode {
+            1 => GravityMode::Radial { center, strength, falloff: RadialFalloff::Constant },
+            2 => GravityMode::Radial { center, strength, falloff: RadialFalloff::InverseSquare },
+            _ => GravityMode::Uniform,
+        }
     }
-    
-    pub fn refresh(&mut self, wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers) {
-        self.sim_params.num_particles = particle_buffers.current_positions.len() as u32;
-        self.bind_resources.bind_group = Self::create_bind_group(wgpu_context, &self.bind_resources.bind_group_layout, particle_buffers);
+
+    /// In world units, see `SimulationConfig::world_boundary`.
+    pub fn set_boundary(&mut self, boundary: WorldBoundary) {
+        (self.sim_params.boundary_shape, self.sim_params.boundary_center, self.sim_params.boundary_radius) = match boundary {
+            WorldBoundary::Rectangle => (0, Vec2::ZERO, 0.0),
+            WorldBoundary::Circle { center, radius } => (1, center, radius),
+        };
     }
 
-    pub fn mouse_click_callback(&mut self, mouse_state: &ElementState, position: Vec2) {
-        self.sim_params.is_mouse_pressed = mouse_state.is_pressed() as u32;
-        self.sim_params.mouse_pos = position;
+    pub fn boundary(&self) -> WorldBoundary {
+        match self.sim_params.boundary_shape {
+            1 => WorldBoundary::Circle { center: self.sim_params.boundary_center, radius: self.sim_params.boundary_radius },
+            _ => WorldBoundary::Rectangle,
+        }
     }
 
-    pub fn mouse_move_callback(&mut self, position: Vec2) {
-        if self.sim_params.is_mouse_pressed == 1 {
-            self.sim_params.mouse_pos = position;
+    /// What the edges of the world rectangle do, see `BoundaryMode`.
+    pub fn set_boundary_mode(&mut self, boundary_mode: BoundaryMode) {
+        self.sim_params.boundary_mode = match boundary_mode {
+            BoundaryMode::Closed => 0,
+            BoundaryMode::Wrap => 1,
+            BoundaryMode::Open => 2,
+        };
+    }
+
+    pub fn boundary_mode(&self) -> BoundaryMode {
+        match self.sim_params.boundary_mode {
+            1 => BoundaryMode::Wrap,
+            2 => BoundaryMode::Open,
+            _ => BoundaryMode::Closed,
         }
     }
+
+    /// Response of the particles without a material of their own.
+    pub fn set_boundary_material(&mut self, material: BoundaryMaterial) {
+        self.sim_params.boundary_restitution = material.restitution;
+        self.sim_params.boundary_friction = material.friction;
+    }
+
+    pub fn boundary_material(&self) -> BoundaryMaterial {
+        BoundaryMaterial::new(self.sim_params.boundary_restitution, self.sim_params.boundary_friction)
+    }
+
+    /// Replaces the table the `BOUNDARY_MATERIAL_CHANNEL` channel indexes.
+    pub fn set_materials(&mut self, wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers, materials: &[BoundaryMaterial]) {
+        self.sim_params.num_materials = materials.len() as u32;
+        let data = if materials.is_empty() { vec![BoundaryMaterial::default()] } else { materials.to_vec() };
+        self.materials = GpuBuffer::new(wgpu_context, data, wgpu::BufferUsages::STORAGE);
+        self.bindings.rebind(Self::bindings_builder(particle_buffers, &self.interaction_buffer, &self.materials, &self.forces));
+    }
+
+    pub fn materials(&self) -> &[BoundaryMaterial] {
+        &self.materials.data()[..self.sim_params.num_materials as usize]
+    }
+
+    pub fn mouse_click_callback(&mut self, is_pressed: bool, position: Vec2) {
+        self.interaction.press(is_pressed, position);
+        // A new drag starts without cursor velocity
+        self.previous_interaction_position = position;
+    }
+
+    pub fn mouse_move_callback(&mut self, position: Vec2) {
+        self.interaction.move_to(position);
+    }
+
+    pub fn interaction(&self) -> &InteractionTool {
+        &self.interaction
+    }
+
+    pub fn interaction_mut(&mut self) -> &mut InteractionTool {
+        &mut self.interaction
+    }
     
 }
\ No newline at end of file
@@ -3,23 +3,101 @@ struct SimParams {
     delta_time: f32,
     world_width: f32,
     world_height: f32,
-    is_mouse_pressed: u32,
-    mouse_pos: vec2<f32>,
     num_particles: u32,
+    // Minimum corner of the world, the world spans [world_origin, world_origin + (world_width, world_height)]
+    world_origin: vec2<f32>,
+    // Center of the radial gravity modes
+    radial_center: vec2<f32>,
+    // Acceleration of GRAVITY_RADIAL_CONSTANT, gravitational parameter of GRAVITY_RADIAL_INVERSE_SQUARE
+    radial_strength: f32,
+    gravity_mode: u32,
+    // Circle of BOUNDARY_CIRCLE
+    boundary_center: vec2<f32>,
+    boundary_radius: f32,
+    boundary_shape: u32,
+    // Boundary response of the particles without a material
+    boundary_restitution: f32,
+    boundary_friction: f32,
+    extras_stride: u32,
+    // Word of the material index in the extras, NO_CHANNEL without the material channel
+    material_offset: u32,
+    num_materials: u32,
+    // Word of the particle flags in the extras, NO_CHANNEL without the flags channel
+    flags_offset: u32,
+    // What the world rectangle does to the particles leaving it, BOUNDARY_CLOSED, BOUNDARY_WRAP or BOUNDARY_OPEN
+    boundary_mode: u32,
 };
 
+// Restitution and friction against the world boundary, see BoundaryMaterial
+struct BoundaryMaterial {
+    restitution: f32,
+    friction: f32,
+};
+
+const NO_CHANNEL: u32 = 0xffffffffu;
+// Must match the flags of particle_system.rs
+const PINNED_FLAG: u32 = 1u;
+const HELD_FLAG: u32 = 2u;
+const SLEEPING_FLAG: u32 = 4u;
+
+// Forces on every particle, see GlobalForces
+struct GlobalForces {
+    // In world units per second squared, for GRAVITY_UNIFORM
+    gravity: vec2<f32>,
+    // In world units per second squared
+    wind: vec2<f32>,
+    // Part of the velocity lost per second
+    linear_drag: f32,
+};
+
+// Must match ParticleIntegration::set_gravity_mode
+const GRAVITY_UNIFORM: u32 = 0u;
+const GRAVITY_RADIAL_CONSTANT: u32 = 1u;
+const GRAVITY_RADIAL_INVERSE_SQUARE: u32 = 2u;
+// Must match ParticleIntegration::set_boundary
+const BOUNDARY_CIRCLE: u32 = 1u;
+// Must match ParticleIntegration::set_boundary_mode
+const BOUNDARY_CLOSED: u32 = 0u;
+const BOUNDARY_WRAP: u32 = 1u;
+const BOUNDARY_OPEN: u32 = 2u;
+
+// Same as the removed particles of particle_group.wgsl, the compaction deletes particles outside the world
+const REMOVED_POSITION = vec2<f32>(-3.40282347e38);
+
+// Mouse tool, see InteractionTool
+struct Interaction {
+    position: vec2<f32>,
+    // Cursor position of the previous step
+    previous_position: vec2<f32>,
+    radius: f32,
+    strength: f32,
+    mode: u32,
+    is_active: u32,
+    // From the cursor to the particle held by MODE_PICK
+    grab_offset: vec2<f32>,
+};
+
+const MODE_ATTRACT: u32 = 0u;
+const MODE_REPEL: u32 = 1u;
+const MODE_VORTEX: u32 = 2u;
+const MODE_DRAG: u32 = 3u;
+const MODE_PICK: u32 = 4u;
+// MODE_HEAT (5) moves nothing, heat_diffusion.wgsl heats the particles under the cursor
+
 // Bindings for the Compute Shader
 @group(0) @binding(0) var<storage, read_write> positions: array<vec2<f32>>;
 @group(0) @binding(1) var<storage, read_write> previous_positions: array<vec2<f32>>;
 @group(0) @binding(2) var<storage, read> radius: array<f32>;
+@group(0) @binding(3) var<uniform> interaction: Interaction;
+@group(0) @binding(4) var<storage, read_write> extras: array<u32>;
+@group(0) @binding(5) var<storage, read> materials: array<BoundaryMaterial>;
+@group(0) @binding(6) var<uniform> forces: GlobalForces;
 
 
 var<push_constant> push_constants: SimParams;
 
 const WORKGROUP_SIZE: u32 = 64u;
 
-const FORCE_OF_GRAVITY: vec2<f32> = vec2<f32>(0.0, 0.0);
-const MOUSE_ATTRACTION_STRENGTH: f32 = 150.0;
 
 @compute @workgroup_size(WORKGROUP_SIZE)
 fn verlet_integration(@builtin(global_invocation_id) global_id: vec3<u32>) {
@@ -34,24 +112,61 @@ fn verlet_integration(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let current_position = positions[index];
     let previous_position = previous_positions[index];
 
-
+    // Kinematic particles skip the forces and the boundary
+    let flags = particle_flags(index);
+    if ((flags & HELD_FLAG) != 0u) {
+        if (interaction.is_active == 1u && interaction.mode == MODE_PICK) {
+            // On the cursor, with the velocity of the cursor
+            let held_position = interaction.position + interaction.grab_offset;
+            positions[index] = held_position;
+            previous_positions[index] = held_position - (interaction.position - interaction.previous_position);
+            return;
+        }
+        // Released, the particle is thrown with the velocity of its last step
+        extras[index * push_constants.extras_stride + push_constants.flags_offset] = flags & ~HELD_FLAG;
+    }
+    if ((flags & PINNED_FLAG) != 0u) {
+        previous_positions[index] = current_position;
+        return;
+    }
+    // Asleep, left in place until particle_sleep.wgsl wakes it. The mouse interaction moves it, which wakes it
+    if ((flags & SLEEPING_FLAG) != 0u && !is_under_interaction(current_position)) {
+        return;
+    }
 
     // Verlet integration
-    let velocity: vec2<f32> = (current_position - previous_position);
-
-    var total_acceleration = FORCE_OF_GRAVITY;
-
-    if (push_constants.is_mouse_pressed == 1u) {
-        // Calculate a vector pointing from the particle to the mouse
-        let direction_to_mouse = push_constants.mouse_pos - current_position;
+    var velocity: vec2<f32> = (current_position - previous_position);
+    velocity *= max(1.0 - forces.linear_drag * push_constants.delta_time, 0.0);
 
-        // Normalize the direction to get a unit vector, then scale by our strength constant.
-        // This creates the mouse attraction acceleration.
-        let mouse_attraction_accel = normalize(direction_to_mouse) * MOUSE_ATTRACTION_STRENGTH;
-
-        // Add the mouse attraction to the total acceleration
-        total_acceleration += mouse_attraction_accel;
+    var total_acceleration = forces.gravity;
+    if (push_constants.gravity_mode != GRAVITY_UNIFORM) {
+        total_acceleration = radial_gravity(current_position);
+    }
+    total_acceleration += forces.wind;
 
+    // Calculate a vector pointing from the particle to the mouse
+    let to_mouse = interaction.position - current_position;
+    let distance_to_mouse = length(to_mouse);
+    if (interaction.is_active == 1u && distance_to_mouse < interaction.radius) {
+        // Normalize the direction to get a unit vector, the particle under the cursor is left alone
+        let direction_to_mouse = select(vec2<f32>(0.0), to_mouse / distance_to_mouse, distance_to_mouse > 1e-6);
+        if (interaction.mode == MODE_ATTRACT) {
+            total_acceleration += direction_to_mouse * interaction.strength;
+        }
+        else if (interaction.mode == MODE_REPEL) {
+            total_acceleration -= direction_to_mouse * interaction.strength;
+        }
+        else if (interaction.mode == MODE_VORTEX) {
+            // Counterclockwise around the cursor, fading out at the edge of the area
+            let tangent = vec2<f32>(direction_to_mouse.y, -direction_to_mouse.x);
+            let falloff = 1.0 - distance_to_mouse / interaction.radius;
+            total_acceleration += tangent * interaction.strength * falloff;
+        }
+        else if (interaction.mode == MODE_DRAG) {
+            // Take the velocity of the cursor
+            let cursor_velocity = interaction.position - interaction.previous_position;
+            velocity = mix(velocity, cursor_velocity, clamp(interaction.strength * push_constants.delta_time, 0.0, 1.0));
+        }
     }
 
     // Predict the next position without applying constraints
@@ -59,21 +174,106 @@ fn verlet_integration(@builtin(global_invocation_id) global_id: vec3<u32>) {
     var predicted_position: vec2<f32> = current_position + velocity + total_acceleration * dt_squared;
 
 
-    // Update the previous position
-    // The current position becomes the old position
-    previous_positions[index] = current_position;
-
     let particle_radius = radius[index];
-    
-    // Apply boundary constraints
-    // Boundary check (bouncing)
-    predicted_position.x = clamp(predicted_position.x, particle_radius, push_constants.world_width - particle_radius);
-    predicted_position.y = clamp(predicted_position.y, particle_radius, push_constants.world_height - particle_radius);
+    let material = particle_material(index);
+    // Velocity of this step, before the boundary
+    let step_velocity = predicted_position - current_position;
+    let unconstrained_position = predicted_position;
 
+    // Apply boundary constraints, only the closed world has walls
+    let world_min = push_constants.world_origin;
+    let world_size = vec2<f32>(push_constants.world_width, push_constants.world_height);
+    let world_max = world_min + world_size;
+    if (push_constants.boundary_mode == BOUNDARY_CLOSED) {
+        predicted_position.x = clamp(predicted_position.x, world_min.x + particle_radius, world_max.x - particle_radius);
+        predicted_position.y = clamp(predicted_position.y, world_min.y + particle_radius, world_max.y - particle_radius);
+    }
 
+    // The velocity the next step sees, current position to constrained position unless a wall responds
+    var velocity_out = predicted_position - current_position;
+    if (predicted_position.x != unconstrained_position.x) {
+        velocity_out = boundary_response(velocity_out, step_velocity, vec2<f32>(1.0, 0.0), material);
+    }
+    if (predicted_position.y != unconstrained_position.y) {
+        velocity_out = boundary_response(velocity_out, step_velocity, vec2<f32>(0.0, 1.0), material);
+    }
+
+    if (push_constants.boundary_shape == BOUNDARY_CIRCLE) {
+        // Back to the nearest point inside the circle
+        let from_center = predicted_position - push_constants.boundary_center;
+        let max_distance = max(push_constants.boundary_radius - particle_radius, 0.0);
+        if (dot(from_center, from_center) > max_distance * max_distance) {
+            let normal = normalize(from_center);
+            predicted_position = push_constants.boundary_center + max_distance * normal;
+            velocity_out = boundary_response(predicted_position - current_position, step_velocity, normal, material);
+        }
+    }
 
-    // Write the updated data back to the buffer
+    if (push_constants.boundary_mode == BOUNDARY_WRAP) {
+        // Back in through the opposite edge, with the same velocity
+        let offset = predicted_position - world_min;
+        predicted_position = world_min + offset - world_size * floor(offset / world_size);
+    }
+    else if (push_constants.boundary_mode == BOUNDARY_OPEN && (any(predicted_position < world_min) || any(predicted_position > world_max))) {
+        // Gone through an edge, the next compaction deletes it
+        positions[index] = REMOVED_POSITION;
+        previous_positions[index] = REMOVED_POSITION;
+        return;
+    }
+
+    // Write the updated data back to the buffer. The previous position carries the velocity of the next step
     positions[index] = predicted_position;
+    previous_positions[index] = predicted_position - velocity_out;
+}
+
+// Flags of the particle, none without the flags channel
+fn particle_flags(index: u32) -> u32 {
+    if (push_constants.flags_offset == NO_CHANNEL) {
+        return 0u;
+    }
+    return extras[index * push_constants.extras_stride + push_constants.flags_offset];
+}
+
+// Inside the area of the active mouse interaction
+fn is_under_interaction(position: vec2<f32>) -> bool {
+    return interaction.is_active == 1u && distance(position, interaction.position) < interaction.radius;
+}
+
+// Material of the particle: the one of its material channel, or the simulation's
+fn particle_material(index: u32) -> BoundaryMaterial {
+    let default_material = BoundaryMaterial(push_constants.boundary_restitution, push_constants.boundary_friction);
+    if (push_constants.material_offset == NO_CHANNEL) {
+        return default_material;
+    }
+    let material_index = extras[index * push_constants.extras_stride + push_constants.material_offset];
+    if (material_index >= push_constants.num_materials) {
+        return default_material;
+    }
+    return materials[material_index];
+}
+
+// Velocity leaving a wall of the given normal. Without restitution nor friction it is `velocity_out`, the move
+// to the wall. Restitution blends its normal part towards the reflected `step_velocity`, friction slows its tangential part.
+fn boundary_response(velocity_out: vec2<f32>, step_velocity: vec2<f32>, normal: vec2<f32>, material: BoundaryMaterial) -> vec2<f32> {
+    let normal_part = dot(velocity_out, normal) * normal;
+    let tangential_part = velocity_out - normal_part;
+    let reflected = -dot(step_velocity, normal) * normal;
+    return mix(normal_part, reflected, material.restitution) + tangential_part * (1.0 - material.friction);
+}
+
+// Acceleration towards the radial center
+fn radial_gravity(position: vec2<f32>) -> vec2<f32> {
+    let to_center = push_constants.radial_center - position;
+    let distance_squared = dot(to_center, to_center);
+    if (distance_squared < 1e-12) {
+        return vec2<f32>(0.0);
+    }
+    let direction = to_center * inverseSqrt(distance_squared);
+    if (push_constants.gravity_mode == GRAVITY_RADIAL_INVERSE_SQUARE) {
+        // Closer than one world unit the acceleration stops growing
+        return direction * push_constants.radial_strength / max(distance_squared, 1.0);
+    }
+    return direction * push_constants.radial_strength;
 }
 
     /*
@@ -0,0 +1,164 @@
+use glam::Vec2;
+
+/// Radius of the area around the cursor the interaction acts on, in world units.
+pub const DEFAULT_INTERACTION_RADIUS: f32 = 1000.0;
+/// Acceleration of the attract, repel and vortex modes, in world units per second squared.
+pub const DEFAULT_INTERACTION_STRENGTH: f32 = 150.0;
+const MIN_INTERACTION_RADIUS: f32 = 5.0;
+const MAX_INTERACTION_RADIUS: f32 = 10_000.0;
+const MIN_INTERACTION_STRENGTH: f32 = 1.0;
+const MAX_INTERACTION_STRENGTH: f32 = 100_000.0;
+
+/// What holding the left mouse button does to the particles around the cursor.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum InteractionMode {
+    /// Pulls the particles towards the cursor.
+    #[default]
+    Attract,
+    /// Pushes the particles away from the cursor.
+    Repel,
+    /// Spins the particles counterclockwise around the cursor, faster close to it.
+    Vortex,
+    /// Grabs the particles, they follow the movement of the cursor.
+    Drag,
+    /// Picks the particle under the cursor, see `Simulation::pick_particle`. It follows the cursor as a
+    /// kinematic particle until the button is released, and keeps the velocity of the cursor.
+    Pick,
+    /// Heats the particles around the cursor by `strength` degrees per second, see `Simulation::enable_heat`.
+    /// Does nothing while the heat is disabled.
+    Heat,
+}
+
+impl InteractionMode {
+    pub const ALL: [InteractionMode; 6] = [InteractionMode::Attract, InteractionMode::Repel, InteractionMode::Vortex, InteractionMode::Drag, InteractionMode::Pick, InteractionMode::Heat];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            InteractionMode::Attract => "attract",
+            InteractionMode::Repel => "repel",
+            InteractionMode::Vortex => "vortex",
+            InteractionMode::Drag => "drag",
+            InteractionMode::Pick => "pick",
+            InteractionMode::Heat => "heat",
+        }
+    }
+
+    /// Id of the mode in particle_integration.wgsl
+    fn shader_id(self) -> u32 {
+        match self {
+            InteractionMode::Attract => 0,
+            InteractionMode::Repel => 1,
+            InteractionMode::Vortex => 2,
+            InteractionMode::Drag => 3,
+            InteractionMode::Pick => 4,
+            InteractionMode::Heat => 5,
+        }
+    }
+}
+
+/// The mouse tool of the integration pass: the selected mode, its area and strength, and where the cursor is.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct InteractionTool {
+    pub mode: InteractionMode,
+    radius: f32,
+    strength: f32,
+    active: bool,
+    position: Vec2,
+    /// From the cursor to the picked particle, kept while it is held
+    grab_offset: Vec2,
+}
+
+impl InteractionTool {
+    pub fn new() -> Self {
+        Self {
+            mode: InteractionMode::default(),
+            radius: DEFAULT_INTERACTION_RADIUS,
+            strength: DEFAULT_INTERACTION_STRENGTH,
+            active: false,
+            position: Vec2::ZERO,
+            grab_offset: Vec2::ZERO,
+        }
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    pub fn set_radius(&mut self, radius: f32) {
+        self.radius = radius.clamp(MIN_INTERACTION_RADIUS, MAX_INTERACTION_RADIUS);
+    }
+
+    /// Acceleration of the attract, repel and vortex modes. For the drag mode, how fast the particles
+    /// take the cursor velocity: they follow it exactly once `strength * delta_time` reaches 1. For the heat
+    /// mode, degrees per second.
+    pub fn strength(&self) -> f32 {
+        self.strength
+    }
+
+    pub fn set_strength(&mut self, strength: f32) {
+        self.strength = strength.clamp(MIN_INTERACTION_STRENGTH, MAX_INTERACTION_STRENGTH);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn position(&self) -> Vec2 {
+        self.position
+    }
+
+    /// Starts (`is_pressed`) or stops the interaction at `position`.
+    pub fn press(&mut self, is_pressed: bool, position: Vec2) {
+        self.active = is_pressed;
+        self.position = position;
+    }
+
+    /// Offset from the cursor the particle held by `InteractionMode::Pick` keeps, so it does not jump to the
+    /// cursor when it is picked off-center.
+    pub fn set_grab_offset(&mut self, grab_offset: Vec2) {
+        self.grab_offset = grab_offset;
+    }
+
+    pub fn grab_offset(&self) -> Vec2 {
+        self.grab_offset
+    }
+
+    /// Follows the cursor while the interaction is active.
+    pub fn move_to(&mut self, position: Vec2) {
+        if self.active {
+            self.position = position;
+        }
+    }
+
+    /// Uniform of the integration pass. `previous_position` is the cursor position of the previous step,
+    /// the drag mode moves the particles by the difference.
+    pub(crate) fn uniform(&self, previous_position: Vec2) -> InteractionUniform {
+        InteractionUniform {
+            position: self.position,
+            previous_position,
+            radius: self.radius,
+            strength: self.strength,
+            mode: self.mode.shader_id(),
+            is_active: self.active as u32,
+            grab_offset: self.grab_offset,
+        }
+    }
+}
+
+impl Default for InteractionTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct InteractionUniform {
+    position: Vec2,
+    previous_position: Vec2,
+    radius: f32,
+    strength: f32,
+    mode: u32,
+    is_active: u32,
+    grab_offset: Vec2,
+}
@@ -1,5 +1,5 @@
 use wgpu::{BindGroup, BindGroupLayout, CommandEncoder, PushConstantRange};
-use wgpu_profiler::GpuProfiler;
+use crate::utils::profiler::GpuProfiler;
 use crate::particles::particle_buffers::ParticleBuffers;
 use crate::particles::particle_sort::WORKGROUP_SIZE;
 use crate::renderer::wgpu_context::WgpuContext;
@@ -17,6 +17,7 @@ pub struct ParticleRearrangeKernel {
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct PushConstantData{
     num_particles: u32,
+    extras_stride: u32,
 }
 
 
@@ -121,6 +122,28 @@ impl ParticleRearrangeKernel {
                     },
                     count: None,
                 },
+                // Extras (particle channels) read
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Extras (particle channels) writing
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         };
 
@@ -161,6 +184,14 @@ impl ParticleRearrangeKernel {
                         binding: 6,
                         resource: particle_copy_buffers.previous_positions.buffer().as_entire_binding(),
                     },
+                    wgpu::BindGroupEntry {
+                        binding: 7,
+                        resource: particle_buffers.extras.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 8,
+                        resource: particle_copy_buffers.extras.buffer().as_entire_binding(),
+                    },
                 ],
             }
         )
@@ -187,7 +218,7 @@ impl ParticleRearrangeKernel {
         )
     }
     
-    pub fn rearrange(&self, encoder: &mut CommandEncoder, gpu_profiler: &mut GpuProfiler, particle_buffers: &ParticleBuffers, particle_copy_buffers: &ParticleBuffers){
+    pub fn rearrange(&self, encoder: &mut CommandEncoder, gpu_profiler: &GpuProfiler, particle_buffers: &ParticleBuffers, particle_copy_buffers: &ParticleBuffers, extras_stride: u32){
         let num_particles = particle_buffers.current_positions.len() as u32;
         
         {
@@ -197,6 +228,7 @@ impl ParticleRearrangeKernel {
                 (num_particles, 1, 1),
                 Some(vec![(0u32, bytemuck::bytes_of(&PushConstantData {
                     num_particles,
+                    extras_stride,
                 }))]),
                 &self.bind_resources.bind_group
             );
@@ -236,6 +268,17 @@ impl ParticleRearrangeKernel {
                 particle_copy_buffers.previous_positions.buffer().size(),
             );
         }
+
+        if extras_stride > 0 {
+            let mut scope = gpu_profiler.scope("Particle extras rearranging copy", encoder);
+            scope.copy_buffer_to_buffer(
+                particle_copy_buffers.extras.buffer(),
+                0,
+                particle_buffers.extras.buffer(),
+                0,
+                (num_particles * extras_stride) as u64 * size_of::<u32>() as u64,
+            );
+        }
         
     }
 }
\ No newline at end of file
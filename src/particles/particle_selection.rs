@@ -0,0 +1,176 @@
+use glam::{Mat4, Vec2};
+use wgpu::{BindGroupLayout, PushConstantRange};
+use crate::particles::particle_system::ParticleSystem;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+
+pub const WORKGROUP_SIZE: u32 = 64;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstants {
+    view_proj: [[f32; 4]; 4],
+    viewport: Vec2,
+    rect_min: Vec2,
+    rect_max: Vec2,
+    num_particles: u32,
+    _padding: u32,
+}
+
+/// A rectangle on the screen, e.g. dragged with the mouse, and the camera the particles are seen through.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SelectionRect {
+    /// View projection of the camera, see `Camera::view_projection`.
+    pub view_proj: Mat4,
+    /// Size of the window in pixels.
+    pub viewport: Vec2,
+    /// Corners of the rectangle in window pixels, (0, 0) being the top left corner.
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl SelectionRect {
+    /// Rectangle between two opposite corners, in any order.
+    pub fn from_corners(view_proj: Mat4, viewport: Vec2, corner: Vec2, opposite_corner: Vec2) -> Self {
+        Self {
+            view_proj,
+            viewport,
+            min: corner.min(opposite_corner),
+            max: corner.max(opposite_corner),
+        }
+    }
+}
+
+/// Finds the particles whose center is inside a screen rectangle: a GPU pass projects every position
+/// with the camera matrix and appends the matching ids to a compact list, which is read back.
+/// The ids feed the group operations of `ParticleSystem::apply_group_operation`.
+pub struct ParticleSelectionQuery {
+    select_shader: ComputeShader,
+    bind_group_layout: BindGroupLayout,
+    result: GpuBuffer<u32>,
+    selected_ids: GpuBuffer<u32>,
+}
+
+impl ParticleSelectionQuery {
+    pub fn new(wgpu_context: &WgpuContext) -> Self {
+        let result = GpuBuffer::new(wgpu_context, vec![0u32], wgpu::BufferUsages::STORAGE);
+        let selected_ids = GpuBuffer::new(wgpu_context, vec![0u32], wgpu::BufferUsages::STORAGE);
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let select_shader = ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("particle_selection.wgsl"),
+            "select_in_rect",
+            &bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange{
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstants>() as u32,
+                }
+            ]
+        );
+
+        Self {
+            select_shader,
+            bind_group_layout,
+            result,
+            selected_ids,
+        }
+    }
+
+    /// Indices of the particles inside `rect`, in increasing order. Stalls until the GPU is done.
+    /// Indices change when the particles are sorted or removed, use them right away.
+    pub fn select(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem, rect: &SelectionRect) -> Vec<u32> {
+        let num_particles = particle_system.len();
+        if num_particles == 0 {
+            return Vec::new();
+        }
+        if self.selected_ids.len() < num_particles {
+            self.selected_ids.push_all(&vec![0u32; num_particles - self.selected_ids.len()], wgpu_context);
+        }
+        // Bound on every query, the particle buffers may have been replaced since the last one
+        let bind_group = self.create_bind_group(wgpu_context, particle_system);
+
+        self.result.replace_elem(0, 0, wgpu_context);
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Particle selection encoder") }
+        );
+        let push_constants = PushConstants {
+            view_proj: rect.view_proj.to_cols_array_2d(),
+            viewport: rect.viewport,
+            rect_min: rect.min,
+            rect_max: rect.max,
+            num_particles: num_particles as u32,
+            _padding: 0,
+        };
+        self.select_shader.dispatch_by_items(
+            &mut encoder,
+            (num_particles as u32, 1, 1),
+            Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+            &bind_group
+        );
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+
+        let num_selected = self.result.read_back(wgpu_context).unwrap()[0] as usize;
+        if num_selected == 0 {
+            return Vec::new();
+        }
+        let mut selected = self.selected_ids.read_back(wgpu_context).unwrap();
+        selected.truncate(num_selected);
+        // Appended in no particular order
+        selected.sort_unstable();
+        selected
+    }
+
+    fn create_bind_group(&self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                label: Some("Particle selection bind group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: particle_system.buffers().current_positions.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: self.result.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: self.selected_ids.buffer().as_entire_binding(),
+                    },
+                ],
+            }
+        )
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Particle selection bind group layout"),
+            entries: &[
+                // Positions
+                storage_entry(0, true),
+                // Number of selected particles
+                storage_entry(1, false),
+                // Selected ids
+                storage_entry(2, false),
+            ],
+        })
+    }
+}
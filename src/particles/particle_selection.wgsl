@@ -0,0 +1,43 @@
+override WORKGROUP_SIZE = 64u;
+
+struct PushConstants {
+    // Camera view projection, world to clip space
+    view_proj: mat4x4<f32>,
+    // Size of the window in pixels
+    viewport: vec2<f32>,
+    // Selection rectangle in window pixels, y down
+    rect_min: vec2<f32>,
+    rect_max: vec2<f32>,
+    num_particles: u32,
+}
+
+struct SelectionResult {
+    num_selected: atomic<u32>,
+}
+
+@group(0) @binding(0) var<storage, read> positions: array<vec2<f32>>;
+@group(0) @binding(1) var<storage, read_write> result: SelectionResult;
+@group(0) @binding(2) var<storage, read_write> selected_ids: array<u32>;
+
+var<push_constant> push_constants: PushConstants;
+
+// Same convention as Camera::screen_to_world: (0, 0) is the top left corner of the window
+fn world_to_screen(position: vec2<f32>) -> vec2<f32> {
+    let clip = push_constants.view_proj * vec4<f32>(position, 0.0, 1.0);
+    let ndc = clip.xy / clip.w;
+    return vec2<f32>(ndc.x + 1.0, 1.0 - ndc.y) * 0.5 * push_constants.viewport;
+}
+
+// Appends the particles whose center is inside the rectangle to selected_ids, in no particular order
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn select_in_rect(@builtin(global_invocation_id) global_id: vec3<u32>){
+    let idx = global_id.x;
+    if idx >= push_constants.num_particles {
+        return;
+    }
+    let screen_position = world_to_screen(positions[idx]);
+    if all(screen_position >= push_constants.rect_min) && all(screen_position <= push_constants.rect_max) {
+        let slot = atomicAdd(&result.num_selected, 1u);
+        selected_ids[slot] = idx;
+    }
+}
@@ -0,0 +1,209 @@
+use glam::{UVec2, Vec2};
+
+/// Channel with the atlas frame each particle is drawn with, see `ParticleShape::Sprite`.
+pub const SPRITE_FRAME_CHANNEL: &str = "sprite_frame";
+
+/// How the drawer fills the quad of each particle. The collisions always use circles of the particle radius.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ParticleShape {
+    /// Antialiased disk, the default.
+    #[default]
+    Circle,
+    /// The whole quad.
+    Square,
+    /// Pointy-top hexagon inscribed in the quad.
+    Hexagon,
+    /// A frame of the `SpriteAtlas` of the particle system, picked by the `SPRITE_FRAME_CHANNEL` channel of
+    /// each particle. The texture is multiplied by the particle colors when they are enabled.
+    Sprite,
+}
+
+impl ParticleShape {
+    /// Shapes that need nothing else, in the order the shape toggle goes through.
+    pub const BUILT_IN: [ParticleShape; 3] = [ParticleShape::Circle, ParticleShape::Square, ParticleShape::Hexagon];
+
+    /// Must match the SHAPE_ constants of particle_drawer.wgsl
+    pub fn id(&self) -> u32 {
+        match self {
+            ParticleShape::Circle => 0,
+            ParticleShape::Square => 1,
+            ParticleShape::Hexagon => 2,
+            ParticleShape::Sprite => 3,
+        }
+    }
+
+    /// Next built-in shape of the toggle, the sprite goes back to the circle.
+    pub fn next_built_in(&self) -> ParticleShape {
+        match Self::BUILT_IN.iter().position(|shape| shape == self) {
+            Some(i) => Self::BUILT_IN[(i + 1) % Self::BUILT_IN.len()],
+            None => ParticleShape::Circle,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ParticleShape::Circle => "circle",
+            ParticleShape::Square => "square",
+            ParticleShape::Hexagon => "hexagon",
+            ParticleShape::Sprite => "sprite",
+        }
+    }
+}
+
+/// Why a `SpriteAtlas` can not be created.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpriteAtlasError {
+    /// The image or the frame grid is empty.
+    Empty,
+    /// The pixels are not `width * height` RGBA values.
+    WrongPixelCount { expected: usize, actual: usize },
+    /// The image does not split into `columns * rows` frames of whole pixels.
+    UnevenFrames { size: UVec2, columns: u32, rows: u32 },
+    /// The clip is empty or runs past the last frame of the atlas.
+    ClipOutOfRange { name: String, clip: SpriteClip, num_frames: u32 },
+    /// Two clips have the same name.
+    DuplicateClip(String),
+}
+
+impl std::fmt::Display for SpriteAtlasError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpriteAtlasError::Empty => write!(f, "empty sprite atlas"),
+            SpriteAtlasError::WrongPixelCount { expected, actual } =>
+                write!(f, "expected {} bytes of RGBA pixels, got {}", expected, actual),
+            SpriteAtlasError::UnevenFrames { size, columns, rows } =>
+                write!(f, "a {}x{} image can not be split into {}x{} frames", size.x, size.y, columns, rows),
+            SpriteAtlasError::ClipOutOfRange { name, clip, num_frames } =>
+                write!(f, "clip {} plays frames {}..{} of an atlas of {} frames", name, clip.first_frame, clip.first_frame as u64 + clip.num_frames as u64, num_frames),
+            SpriteAtlasError::DuplicateClip(name) => write!(f, "clip {} is defined twice", name),
+        }
+    }
+}
+
+/// Consecutive frames of a `SpriteAtlas` played as one animation, e.g. the frames of an explosion.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SpriteClip {
+    pub first_frame: u32,
+    pub num_frames: u32,
+    /// Starts over after the last frame, instead of holding it.
+    pub looping: bool,
+}
+
+impl SpriteClip {
+    pub fn new(first_frame: u32, num_frames: u32, looping: bool) -> Self {
+        Self { first_frame, num_frames, looping }
+    }
+}
+
+/// Layout of the image of a `SpriteAtlas`: its grid of frames and the named clips in it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpriteAtlasDescriptor {
+    pub columns: u32,
+    pub rows: u32,
+    pub clips: Vec<(String, SpriteClip)>,
+}
+
+impl SpriteAtlasDescriptor {
+    /// A grid of `columns` x `rows` frames without clips.
+    pub fn grid(columns: u32, rows: u32) -> Self {
+        Self { columns, rows, clips: Vec::new() }
+    }
+
+    pub fn with_clip(mut self, name: &str, clip: SpriteClip) -> Self {
+        self.clips.push((name.to_string(), clip));
+        self
+    }
+}
+
+/// Image holding the frames of the sprites, in a grid of `columns` x `rows` frames of the same size.
+/// Frame 0 is the top left one, then the frames go left to right and top to bottom.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpriteAtlas {
+    size: UVec2,
+    /// 8-bit RGBA, row-major from the top left
+    pixels: Vec<u8>,
+    columns: u32,
+    rows: u32,
+    clips: Vec<(String, SpriteClip)>,
+}
+
+impl SpriteAtlas {
+    /// An atlas of `columns` x `rows` frames without clips.
+    pub fn new(width: u32, height: u32, pixels: Vec<u8>, columns: u32, rows: u32) -> Result<Self, SpriteAtlasError> {
+        Self::from_descriptor(width, height, pixels, &SpriteAtlasDescriptor::grid(columns, rows))
+    }
+
+    /// An atlas laid out by `descriptor`. Every clip must hold at least one frame of the grid.
+    pub fn from_descriptor(width: u32, height: u32, pixels: Vec<u8>, descriptor: &SpriteAtlasDescriptor) -> Result<Self, SpriteAtlasError> {
+        let SpriteAtlasDescriptor { columns, rows, .. } = *descriptor;
+        if width == 0 || height == 0 || columns == 0 || rows == 0 {
+            return Err(SpriteAtlasError::Empty);
+        }
+        let expected = width as usize * height as usize * 4;
+        if pixels.len() != expected {
+            return Err(SpriteAtlasError::WrongPixelCount { expected, actual: pixels.len() });
+        }
+        let size = UVec2::new(width, height);
+        if !width.is_multiple_of(columns) || !height.is_multiple_of(rows) {
+            return Err(SpriteAtlasError::UnevenFrames { size, columns, rows });
+        }
+        let num_frames = columns * rows;
+        for (i, (name, clip)) in descriptor.clips.iter().enumerate() {
+            if clip.num_frames == 0 || clip.first_frame as u64 + clip.num_frames as u64 > num_frames as u64 {
+                return Err(SpriteAtlasError::ClipOutOfRange { name: name.clone(), clip: *clip, num_frames });
+            }
+            if descriptor.clips[..i].iter().any(|(other, _)| other == name) {
+                return Err(SpriteAtlasError::DuplicateClip(name.clone()));
+            }
+        }
+        Ok(Self { size, pixels, columns, rows, clips: descriptor.clips.clone() })
+    }
+
+    /// Single white pixel, the texture bound while there is no atlas.
+    pub fn white() -> Self {
+        Self { size: UVec2::ONE, pixels: vec![255; 4], columns: 1, rows: 1, clips: Vec::new() }
+    }
+
+    /// The grid and the clips of the atlas.
+    pub fn descriptor(&self) -> SpriteAtlasDescriptor {
+        SpriteAtlasDescriptor { columns: self.columns, rows: self.rows, clips: self.clips.clone() }
+    }
+
+    pub fn clip(&self, name: &str) -> Option<SpriteClip> {
+        self.clips.iter().find(|(clip_name, _)| clip_name == name).map(|(_, clip)| *clip)
+    }
+
+    /// Every frame of the atlas, in order.
+    pub fn all_frames(&self, looping: bool) -> SpriteClip {
+        SpriteClip::new(0, self.num_frames(), looping)
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.size
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn columns(&self) -> u32 {
+        self.columns
+    }
+
+    pub fn rows(&self) -> u32 {
+        self.rows
+    }
+
+    pub fn num_frames(&self) -> u32 {
+        self.columns * self.rows
+    }
+
+    /// Top left corner and size of `frame` in texture coordinates. Frames past the last one wrap around.
+    /// Must match `frame_uv` in particle_drawer.wgsl
+    pub fn frame_uv(&self, frame: u32) -> (Vec2, Vec2) {
+        let frame = frame % self.num_frames();
+        let cell = UVec2::new(frame % self.columns, frame / self.columns);
+        let frame_size = Vec2::ONE / UVec2::new(self.columns, self.rows).as_vec2();
+        (cell.as_vec2() * frame_size, frame_size)
+    }
+}
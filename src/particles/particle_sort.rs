@@ -1,12 +1,14 @@
 use std::num::NonZeroU32;
-use wgpu_profiler::GpuProfiler;
+use glam::Vec2;
+use crate::utils::profiler::GpuProfiler;
 use crate::particles::particle_buffers::ParticleBuffers;
 use crate::particles::particle_home_cell_ids_kernel::ParticleHomeCellIdsKernel;
 use crate::particles::particle_rearrange::ParticleRearrangeKernel;
 use crate::particles::particle_system::ParticleSystem;
+use crate::particles::sort_disorder::SortDisorderKernel;
 use crate::renderer::wgpu_context::WgpuContext;
 use crate::utils::gpu_buffer::GpuBuffer;
-use crate::utils::radix_sort::radix_sort::GPUSorter;
+use crate::utils::radix_sort::GPUSorter;
 
 
 
@@ -17,6 +19,7 @@ pub struct ParticleSort {
     rearrange_pass: ParticleRearrangeKernel,
     gpu_sorter: GPUSorter,
     particle_ids: GpuBuffer<u32>,
+    disorder_pass: SortDisorderKernel,
 }
 
 
@@ -31,23 +34,47 @@ impl ParticleSort{
         let home_cell_ids_pass = ParticleHomeCellIdsKernel::new(wgpu_context, particle_buffers, &particle_ids_buffer);
         let rearrange_pass = ParticleRearrangeKernel::new(wgpu_context, particle_buffers, &particle_ids_buffer, &particle_buffers_copy);
         let gpu_sorter = GPUSorter::new(wgpu_context, NonZeroU32::new(particle_buffers.home_cell_ids.len() as u32).unwrap(), &particle_buffers.home_cell_ids, &particle_ids_buffer);
-        Self{rearrange_pass, gpu_sorter, particle_ids: particle_ids_buffer, home_cell_ids_pass}
+        let disorder_pass = SortDisorderKernel::new(wgpu_context, particle_buffers);
+        Self{rearrange_pass, gpu_sorter, particle_ids: particle_ids_buffer, home_cell_ids_pass, disorder_pass}
     }
 
     
     
     
     pub fn refresh(&mut self, wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers, particle_buffers_copy: &ParticleBuffers) {
+        self.resize_sorter(wgpu_context, particle_buffers);
+        self.refresh_bindings(wgpu_context, particle_buffers, particle_buffers_copy);
+    }
+
+    /// Rebuilds the bind groups of the home cell id, disorder and rearrange kernels.
+    pub fn refresh_bindings(&mut self, wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers, particle_buffers_copy: &ParticleBuffers) {
         self.home_cell_ids_pass.refresh(wgpu_context, particle_buffers, &self.particle_ids);
+        self.disorder_pass.refresh(particle_buffers);
         self.rearrange_pass.refresh(wgpu_context, particle_buffers, &self.particle_ids, particle_buffers_copy);
+    }
+
+    /// Grows the particle ids so `capacity` particles fit. Returns true if they were recreated,
+    /// `refresh_bindings` must run then.
+    pub fn reserve(&mut self, wgpu_context: &WgpuContext, capacity: usize) -> bool {
+        self.particle_ids.reserve(wgpu_context, capacity)
+    }
+
+    /// Grows or shrinks the particle ids and the radix sorter buffers to the current number of particles.
+    /// Returns true if the particle ids were recreated, `refresh_bindings` must run then.
+    pub fn resize_sorter(&mut self, wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers) -> bool {
+        let prev_capacity = self.particle_ids.capacity();
         let prev_len = self.particle_ids.len() as u32;
         let curr_len = particle_buffers.home_cell_ids.len() as u32;
+        // Removed particles must not be sorted back among the live ones
+        self.particle_ids.truncate(curr_len as usize);
         let new_particle_ids: Vec<u32> = (prev_len..curr_len).collect();
         self.particle_ids.push_all(
             &new_particle_ids,
             wgpu_context
         );
-        self.gpu_sorter.update_sorting_buffers(wgpu_context, NonZeroU32::new(self.particle_ids.len() as u32).unwrap(), &particle_buffers.home_cell_ids, &self.particle_ids);
+        // The sorter needs at least one element, even once every particle was removed
+        self.gpu_sorter.update_sorting_buffers(wgpu_context, NonZeroU32::new(self.particle_ids.len().max(1) as u32).unwrap(), &particle_buffers.home_cell_ids, &self.particle_ids);
+        self.particle_ids.capacity() != prev_capacity
     }
     
     
@@ -55,9 +82,9 @@ impl ParticleSort{
 
    
     
-    pub fn sort(&self, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &mut GpuProfiler, particle_system: &ParticleSystem, cell_size: f32) {
+    pub fn sort(&self, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler, particle_system: &ParticleSystem, cell_size: f32) {
         // Compute the home cell ids using morton encoding
-        self.home_cell_ids_pass.create_home_cell_ids(encoder, gpu_profiler, particle_system.len() as u32, cell_size);
+        self.home_cell_ids_pass.create_home_cell_ids(encoder, gpu_profiler, particle_system.len() as u32, cell_size, particle_system.get_world_origin());
 
         {
             // Sort the particles by their home cell id
@@ -65,7 +92,32 @@ impl ParticleSort{
             self.gpu_sorter.sort(&mut scope, None);
         }
         // Rearrange the particles in the correct order
-        self.rearrange_pass.rearrange(encoder, gpu_profiler, particle_system.buffers(), particle_system.copy_buffers());
+        self.rearrange_pass.rearrange(encoder, gpu_profiler, particle_system.buffers(), particle_system.copy_buffers(), particle_system.channels().stride());
+    }
+
+    /// Records the home cell ids and the count of the particles out of their order, without sorting them, see
+    /// `SortDisorderKernel`. Ignored while the previous measure is in flight, `map_disorder` starts reading it back.
+    pub fn measure_disorder(&mut self, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler, num_particles: u32, cell_size: f32, origin: Vec2) {
+        if num_particles == 0 || self.disorder_pass.is_pending() {
+            return;
+        }
+        self.home_cell_ids_pass.create_home_cell_ids(encoder, gpu_profiler, num_particles, cell_size, origin);
+        self.disorder_pass.measure(encoder, gpu_profiler, num_particles);
+    }
+
+    /// Must be called after the encoder of `measure_disorder` was submitted.
+    pub fn map_disorder(&mut self, wgpu_context: &WgpuContext) {
+        self.disorder_pass.map(wgpu_context);
+    }
+
+    /// Fraction of the neighbouring particles out of order in the measure in flight, once it is done.
+    pub fn poll_disorder(&mut self, wgpu_context: &WgpuContext) -> Option<f32> {
+        self.disorder_pass.poll(wgpu_context)
+    }
+
+    /// Drops the measure in flight, it predates the last sort.
+    pub fn discard_disorder(&mut self) {
+        self.disorder_pass.discard_pending();
     }
 
     pub fn download_particle_ids(&mut self, wgpu_context: &WgpuContext) -> Vec<u32>{
@@ -1,59 +1,326 @@
+use std::ops::Range;
 use std::time::{Duration, Instant};
 use glam::{Vec2, Vec4};
-use rand::{random_range, Rng};
-use wgpu_profiler::GpuProfiler;
-use winit::event::{ElementState};
-use crate::{renderer::{camera::Camera, renderable::Renderable}, utils::gpu_buffer::GpuBuffer};
+use rand::Rng;
+use crate::utils::profiler::GpuProfiler;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::rng;
+#[cfg(feature = "windowing")]
+use crate::renderer::{camera::Camera, renderable::Renderable, renderer::Renderer};
 use crate::grid::grid::UNUSED_CELL_ID;
 use crate::particles::{particle_integration::ParticleIntegration, particle_buffers::ParticleBuffers};
+use crate::particles::particle_interaction::InteractionTool;
+#[cfg(feature = "windowing")]
 use crate::particles::particle_drawer::ParticleDrawer;
+#[cfg(feature = "windowing")]
+use crate::particles::trail_drawer::TrailDrawer;
 use crate::particles::particle_sort::ParticleSort;
+use crate::particles::particle_buffer_swapchain::ParticleBufferSwapchain;
+use crate::particles::particle_compaction::{CompactionParams, ParticleCompaction};
+use crate::particles::particle_group::{GroupOperation, ParticleGroupOperations};
+use crate::particles::particle_channels::{ChannelId, ParticleChannels};
+use crate::particles::particle_initializer::InitialLayout;
+use crate::particles::particle_color_kernel::{ColorParams, ParticleColorKernel, ParticleColorSettings};
+use crate::particles::color_palette::ColorPalette;
+use crate::particles::particle_shape::{ParticleShape, SpriteAtlas, SPRITE_FRAME_CHANNEL};
+use crate::particles::sprite_animation::{SpriteAnimation, SpriteAnimationDriver, SpriteAnimationKernel, SpriteChannelOffsets, SPRITE_AGE_CHANNEL};
+use crate::particles::particle_trails::{ParticleTrails, TrailChannelOffsets, NO_TRAIL, TRAIL_SLOT_CHANNEL};
+use crate::utils::telemetry::{RefreshTimer, RefreshTiming};
 use crate::renderer::wgpu_context::WgpuContext;
+use crate::physics::forces::GlobalForces;
+use crate::simulation_config::{BoundaryMaterial, BoundaryMode, GravityMode, SimulationConfig, WorldBoundary, DEFAULT_SORT_INTERVAL};
 
-const SORT_INTERVAL_SECONDS: u64 = 4;
-const SORT_INTERVAL: Duration = Duration::from_millis(SORT_INTERVAL_SECONDS * 1000); 
+const SPAWN_BATCH_SIZE: usize = 100;
+/// Smallest and largest radius of the spawned particles, in world units.
+const DEFAULT_SPAWN_RADIUS_RANGE: (f32, f32) = (1.0, 3.0);
+const SPAWN_ORDER_CHANNEL: &str = "spawn_order";
+/// Channel with the seconds a particle has left, see `ParticleSystem::enable_lifetime`.
+pub const LIFETIME_CHANNEL: &str = "lifetime";
+/// Channel with the index of a particle's entry in the boundary material table, see `enable_boundary_materials`.
+pub const BOUNDARY_MATERIAL_CHANNEL: &str = "boundary_material";
+/// Boundary material index of the particles that use the default `boundary_material`.
+pub const NO_MATERIAL: u32 = u32::MAX;
+/// Channel with the flags of a particle (`PINNED_FLAG`, `HELD_FLAG`, `SLEEPING_FLAG`), see `enable_particle_flags`.
+pub const PARTICLE_FLAGS_CHANNEL: &str = "flags";
+/// Kinematic particle left in place, without velocity, by the integration, the collision solvers and the springs.
+pub const PINNED_FLAG: u32 = 1;
+/// Kinematic particle held by `InteractionMode::Pick`, it follows the cursor. The integration clears the flag
+/// once the mouse button is released.
+pub const HELD_FLAG: u32 = 2;
+/// Settled particle skipped by the integration and kinematic for the collision solvers, set and cleared by
+/// `ParticleSleep`.
+pub const SLEEPING_FLAG: u32 = 4;
+/// Channel with the mass of a particle, read by the collision solvers and the springs, see `enable_mass`.
+pub const MASS_CHANNEL: &str = "mass";
+/// Enough for long interactive sessions without exhausting the memory of most GPUs.
+pub const DEFAULT_MAX_PARTICLES: usize = 2_000_000;
+
+/// What spawning does when a batch does not fit under the particle limit.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SpawnOverflow {
+    /// Spawns the particles that fit and drops the rest.
+    Refuse,
+    /// Respawns the oldest particles at the new positions, like a ring buffer.
+    RecycleOldest,
+}
+
+/// Mass of the spawned particles, once they have a `MASS_CHANNEL`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SpawnMass {
+    /// The same mass for every particle.
+    Fixed(f32),
+    /// The area of the particle times a material density, so larger particles are heavier.
+    Density(f32),
+}
+
+impl SpawnMass {
+    /// Mass of a particle of `radius`.
+    pub fn mass(self, radius: f32) -> f32 {
+        match self {
+            SpawnMass::Fixed(mass) => mass,
+            SpawnMass::Density(density) => density * std::f32::consts::PI * radius * radius,
+        }
+    }
+}
+
+impl Default for SpawnMass {
+    fn default() -> Self {
+        SpawnMass::Density(1.0)
+    }
+}
+
+/// Hard cap on the number of particles.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParticleLimit {
+    pub max_particles: usize,
+    pub overflow: SpawnOverflow,
+}
+
+impl Default for ParticleLimit {
+    fn default() -> Self {
+        Self {
+            max_particles: DEFAULT_MAX_PARTICLES,
+            overflow: SpawnOverflow::Refuse,
+        }
+    }
+}
+
+/// Outcome of one `add_particles` call.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SpawnReport {
+    /// New particles appended to the buffers.
+    pub spawned: usize,
+    /// Old particles respawned at the new positions.
+    pub recycled: usize,
+    /// Particles dropped because of the limit.
+    pub refused: usize,
+}
+
+struct SpawnedParticle {
+    position: Vec2,
+    radius: f32,
+    color: Vec4,
+}
 
 pub struct ParticleSystem {
     particle_buffers: ParticleBuffers,
     particle_buffers_copy: ParticleBuffers,
+    display_buffers: Option<ParticleBufferSwapchain>, // Set by set_double_buffered
+    #[cfg(feature = "windowing")]
     particle_drawer: Option<ParticleDrawer>, 
+    #[cfg(feature = "windowing")]
+    trail_drawer: Option<TrailDrawer>, // Created with the particle drawer
     max_radius: f32,
     particle_integration: ParticleIntegration,
     particle_sort: ParticleSort,
+    channels: ParticleChannels,
+    highlight_flags: GpuBuffer<u32>, // Non-zero for highlighted particles, read by the drawer. Not sorted
+    limit: ParticleLimit,
+    spawn_order: Option<ChannelId>, // Registered when the oldest particles are recycled
+    next_spawn_order: u32,
+    spawn_channel_values: Vec<(ChannelId, Vec<u32>)>, // Channel values of spawned particles, instead of the defaults
+    spawn_mass: SpawnMass, // Only used once the mass channel is registered
     last_sort_time: Instant,
+    sort_interval: Duration,
+    sort_disorder_threshold: Option<f32>, // Replaces the sort interval when set
+    sort_disorder: Option<f32>, // Last measured by measure_sort_disorder
+    last_refresh_timings: Vec<(&'static str, RefreshTiming)>,
+    world_size: Vec2,
+    world_origin: Vec2,
+    spawn_radius_range: (f32, f32),
+    compaction: Option<ParticleCompaction>, // Created by the first remove_dead_particles
+    group_operations: Option<ParticleGroupOperations>, // Created by the first apply_group_operation
+    color_kernel: Option<ParticleColorKernel>, // Set by set_color_settings
+    palette: Option<ColorPalette>, // Set by set_palette
+    spawn_batches: usize, // Picks the palette color of the next batch
+    shape: ParticleShape,
+    sprite_atlas: Option<SpriteAtlas>,
+    sprite_animation: Option<SpriteAnimationKernel>, // Set by set_sprite_animation
+    trails: Option<ParticleTrails>, // Set by set_trails
 }
 
 impl ParticleSystem {
-    pub fn new(wgpu_context: &WgpuContext, camera: &Camera, world_size: Vec2) -> Self {
-        const NUM_PARTICLES: usize = 1_000_000;
+    /// Generates the initial particles of `config`: its particle count, world size, initial radii and sort interval.
+    /// The other settings are applied by `Simulation::set_config`. They can be stepped right away; drawing them
+    /// needs `attach_drawer`.
+    pub fn new(wgpu_context: &WgpuContext, config: &SimulationConfig, layout: InitialLayout) -> Self {
+        let world_size = config.world_size_in_world_units();
+        let channels = ParticleChannels::new(wgpu_context, config.num_particles);
+        let palette = config.palette.map(ColorPalette::generate);
+        let ((buffers, buffers_copy), max_radius) = Self::generate_initial_particles(wgpu_context, &world_size, &channels, config.num_particles, config.world_initial_radius_range(), layout, palette.as_ref());
         
-        let ((buffers, buffers_copy), max_radius) = Self::generate_initial_particles(wgpu_context, &world_size, NUM_PARTICLES);
-        
-        let particle_integration = ParticleIntegration::new(wgpu_context, &buffers, &world_size);
+        let particle_integration = ParticleIntegration::new(wgpu_context, &buffers, &channels, &world_size);
+        let highlight_flags = Self::create_highlight_flags(wgpu_context, buffers.current_positions.len());
        
-        let particle_drawer = ParticleDrawer::new(wgpu_context, &buffers, &camera);
-        
         let particle_sort = ParticleSort::new(wgpu_context, &buffers, &buffers_copy);
 
         Self {
             particle_buffers: buffers,
             particle_buffers_copy: buffers_copy,
-            particle_drawer: Some(particle_drawer),
+            display_buffers: None,
+            #[cfg(feature = "windowing")]
+            particle_drawer: None,
+            #[cfg(feature = "windowing")]
+            trail_drawer: None,
             particle_sort,
+            channels,
+            highlight_flags,
+            limit: ParticleLimit::default(),
+            spawn_order: None,
+            next_spawn_order: 1,
+            spawn_channel_values: Vec::new(),
+            spawn_mass: SpawnMass::default(),
             max_radius,
             particle_integration,
-            last_sort_time: Instant::now() - SORT_INTERVAL,
+            last_sort_time: first_sort_time(config.sort_interval),
+            sort_interval: config.sort_interval,
+            sort_disorder_threshold: config.sort_disorder_threshold,
+            sort_disorder: None,
+            last_refresh_timings: Vec::new(),
+            world_size,
+            world_origin: Vec2::ZERO,
+            spawn_radius_range: DEFAULT_SPAWN_RADIUS_RANGE,
+            compaction: None,
+            group_operations: None,
+            color_kernel: None,
+            palette,
+            spawn_batches: 0,
+            shape: ParticleShape::default(),
+            sprite_atlas: None,
+            sprite_animation: None,
+            trails: None,
+        }
+    }
+
+    /// Creates the render pipeline of the particles, seen through `camera`.
+    #[cfg(feature = "windowing")]
+    pub fn attach_drawer(&mut self, wgpu_context: &WgpuContext, camera: &Camera) {
+        let mut particle_drawer = ParticleDrawer::new(wgpu_context, &Self::drawn_buffer_sets(&self.particle_buffers, self.display_buffers.as_ref()), &self.highlight_flags, camera);
+        particle_drawer.set_use_particle_colors(self.color_kernel.is_some() || self.palette.is_some());
+        particle_drawer.set_shape(self.shape);
+        if let Some(sprite_atlas) = &self.sprite_atlas {
+            particle_drawer.set_sprite_atlas(wgpu_context, sprite_atlas, &Self::drawn_buffer_sets(&self.particle_buffers, self.display_buffers.as_ref()), &self.highlight_flags);
+        }
+        if let Some(display_buffers) = &self.display_buffers {
+            particle_drawer.show_set(display_buffers.displayed_index());
+        }
+        self.particle_drawer = Some(particle_drawer);
+
+        let mut trail_drawer = TrailDrawer::new(wgpu_context, camera);
+        if let Some(trails) = &self.trails {
+            trail_drawer.bind_history(wgpu_context, trails.history());
+        }
+        self.trail_drawer = Some(trail_drawer);
+    }
+
+    /// Draws from a `ParticleBufferSwapchain`: the drawer reads copies of the particles made by
+    /// `record_publish_display`, so the next physics step does not wait for the draw of the current frame.
+    /// Costs two more copies of the drawn buffers. Nothing is drawn until the first publish.
+    pub fn set_double_buffered(&mut self, wgpu_context: &WgpuContext, double_buffered: bool) {
+        if double_buffered == self.display_buffers.is_some() {
+            return;
+        }
+        self.display_buffers = double_buffered.then(|| ParticleBufferSwapchain::new(wgpu_context, &self.particle_buffers));
+        #[cfg(feature = "windowing")]
+        self.refresh_drawer(wgpu_context);
+    }
+
+    pub fn is_double_buffered(&self) -> bool {
+        self.display_buffers.is_some()
+    }
+
+    pub fn display_buffers(&self) -> Option<&ParticleBufferSwapchain> {
+        self.display_buffers.as_ref()
+    }
+
+    /// Records the copy of the particles into the display set that is not drawn, and draws it from then on.
+    /// Once per frame, into the encoder of the physics, so the copy is submitted with the steps it follows.
+    /// Does nothing unless double buffered.
+    #[cfg_attr(not(feature = "windowing"), allow(unused_variables))]
+    pub fn record_publish_display(&mut self, wgpu_context: &WgpuContext, encoder: &mut wgpu::CommandEncoder) {
+        let num_particles = self.len();
+        let extras_stride = self.channels.stride();
+        let Some(display_buffers) = self.display_buffers.as_mut() else {
+            return;
+        };
+        let recreated = display_buffers.publish(wgpu_context, encoder, &self.particle_buffers, num_particles, extras_stride);
+        #[cfg(feature = "windowing")]
+        if recreated {
+            self.refresh_drawer(wgpu_context);
+        }
+        else if let Some(particle_drawer) = self.particle_drawer.as_mut() {
+            particle_drawer.show_set(display_buffers.displayed_index());
+        }
+    }
+
+    /// Rebinds the drawer to the live buffers, or to the display sets when double buffered.
+    #[cfg(feature = "windowing")]
+    fn refresh_drawer(&mut self, wgpu_context: &WgpuContext) {
+        if let Some(particle_drawer) = self.particle_drawer.as_mut() {
+            particle_drawer.refresh(wgpu_context, &Self::drawn_buffer_sets(&self.particle_buffers, self.display_buffers.as_ref()), &self.highlight_flags);
+            if let Some(display_buffers) = &self.display_buffers {
+                particle_drawer.show_set(display_buffers.displayed_index());
+            }
+        }
+    }
+
+    /// Finds the particles inside the view of `camera`, so the draw only shades those. Once per frame, after
+    /// the physics and before the render pass. Does nothing without a drawer. The live buffers are culled: the
+    /// displayed set is the copy of them published by the same frame, in the same order.
+    #[cfg(feature = "windowing")]
+    pub fn cull_particles(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, camera: &Camera) {
+        let num_particles = self.len();
+        let Some(particle_drawer) = self.particle_drawer.as_mut() else {
+            return;
+        };
+        if particle_drawer.cull(wgpu_context, gpu_profiler, &self.particle_buffers, num_particles, camera.view_projection()) {
+            self.refresh_drawer(wgpu_context);
+        }
+    }
+
+    /// Rebuilds the density heatmap of `renderer` from the particles. Once per frame, after the physics.
+    #[cfg(feature = "windowing")]
+    pub fn update_density_heatmap(&self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, renderer: &mut Renderer) {
+        renderer.update_density_heatmap(wgpu_context, gpu_profiler, &self.particle_buffers.current_positions, self.len());
+    }
+
+    #[cfg(feature = "windowing")]
+    fn drawn_buffer_sets<'a>(particle_buffers: &'a ParticleBuffers, display_buffers: Option<&'a ParticleBufferSwapchain>) -> Vec<&'a ParticleBuffers> {
+        match display_buffers {
+            Some(display_buffers) => display_buffers.sets().to_vec(),
+            None => vec![particle_buffers],
         }
     }
 
     pub fn new_from_buffers(wgpu_context: &WgpuContext, current_positions: GpuBuffer<Vec2>, radii: GpuBuffer<f32>) -> Self {
         let total_particles = current_positions.len();
         let max_radius: f32 = radii.data().iter().max_by(|x, y| x.abs().partial_cmp(&y.abs()).unwrap()).unwrap().clone();
+        let channels = ParticleChannels::new(wgpu_context, total_particles);
         
         let previous_positions_pong = GpuBuffer::new(wgpu_context, current_positions.data().clone(), wgpu::BufferUsages::STORAGE);
         let current_positions_pong = GpuBuffer::new(wgpu_context, current_positions.data().clone(), wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE);
         let radii_pong = GpuBuffer::new(wgpu_context, radii.data().clone(), wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE);
-        let colors_pong = GpuBuffer::new(wgpu_context, vec![glam::vec4(0.1, 0.4, 0.5, 1.0)], wgpu::BufferUsages::VERTEX);
+        let colors_pong = GpuBuffer::new(wgpu_context, vec![glam::vec4(0.1, 0.4, 0.5, 1.0)], wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE);
         let home_cell_ids_buffer = GpuBuffer::new(
             wgpu_context,
             vec![UNUSED_CELL_ID; total_particles],
@@ -66,10 +333,11 @@ impl ParticleSystem {
             current_positions: current_positions_pong,
             radii: radii_pong,
             colors: colors_pong,
+            extras: GpuBuffer::new(wgpu_context, channels.create_extras_data(total_particles), wgpu::BufferUsages::STORAGE),
         };
         
         let previous_positions = GpuBuffer::new(wgpu_context, current_positions.data().clone(), wgpu::BufferUsages::STORAGE);
-        let colors = GpuBuffer::new(wgpu_context, vec![glam::vec4(0.1, 0.4, 0.5, 1.0)], wgpu::BufferUsages::VERTEX);
+        let colors = GpuBuffer::new(wgpu_context, vec![glam::vec4(0.1, 0.4, 0.5, 1.0)], wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE);
         let home_cell_ids_copy = GpuBuffer::new(
             wgpu_context,
             vec![UNUSED_CELL_ID; total_particles],
@@ -81,41 +349,74 @@ impl ParticleSystem {
             previous_positions, 
             radii,
             colors,
+            extras: GpuBuffer::new(wgpu_context, channels.create_extras_data(total_particles), wgpu::BufferUsages::STORAGE),
         };
 
-        let particle_kernels = ParticleIntegration::new(wgpu_context, &buffers_ping, &Vec2::new(1920.0, 1080.0));
+        let world_size = Vec2::new(1920.0, 1080.0);
+        let particle_kernels = ParticleIntegration::new(wgpu_context, &buffers_ping, &channels, &world_size);
         
         let particle_sort = ParticleSort::new(wgpu_context, &buffers_ping, &buffers_pong);
         
         Self {
             particle_buffers: buffers_ping,
             particle_buffers_copy: buffers_pong,
+            display_buffers: None,
+            #[cfg(feature = "windowing")]
             particle_drawer: None,
+            #[cfg(feature = "windowing")]
+            trail_drawer: None,
             particle_sort,
+            channels,
+            highlight_flags: Self::create_highlight_flags(wgpu_context, total_particles),
+            limit: ParticleLimit::default(),
+            spawn_order: None,
+            next_spawn_order: 1,
+            spawn_channel_values: Vec::new(),
+            spawn_mass: SpawnMass::default(),
             max_radius,
             particle_integration: particle_kernels,
-            last_sort_time: Instant::now() - SORT_INTERVAL,
+            last_sort_time: first_sort_time(DEFAULT_SORT_INTERVAL),
+            sort_interval: DEFAULT_SORT_INTERVAL,
+            sort_disorder_threshold: None,
+            sort_disorder: None,
+            last_refresh_timings: Vec::new(),
+            world_size,
+            world_origin: Vec2::ZERO,
+            spawn_radius_range: DEFAULT_SPAWN_RADIUS_RANGE,
+            compaction: None,
+            group_operations: None,
+            color_kernel: None,
+            palette: None,
+            spawn_batches: 0,
+            shape: ParticleShape::default(),
+            sprite_atlas: None,
+            sprite_animation: None,
+            trails: None,
         }
     }
 
-    /// Generates the initial particle data and buffers.
-    fn generate_initial_particles(wgpu_context: &WgpuContext, world_size: &Vec2, num_particles: usize) -> ((ParticleBuffers, ParticleBuffers), f32){
-        let world_width: f32 = world_size.x;
-        let world_height: f32 = world_size.y;
+    fn create_highlight_flags(wgpu_context: &WgpuContext, num_particles: usize) -> GpuBuffer<u32> {
+        GpuBuffer::new(wgpu_context, vec![0u32; num_particles], wgpu::BufferUsages::STORAGE)
+    }
 
-        let mut rng = rand::rng();
+    /// Generates the initial particle data and buffers, with radii uniformly distributed in `radius_range`
+    /// and colors of `palette`, random if there is none.
+    fn generate_initial_particles(wgpu_context: &WgpuContext, world_size: &Vec2, channels: &ParticleChannels, num_particles: usize, radius_range: (f32, f32), layout: InitialLayout, palette: Option<&ColorPalette>) -> ((ParticleBuffers, ParticleBuffers), f32){
+        let mut rng = rng::rng();
 
-        let mut positions = Vec::with_capacity(num_particles);
+        // Spaced for the largest radius, so the lattice layouts do not overlap
+        let positions = layout.generate(Vec2::ZERO, *world_size, radius_range.1, num_particles);
+        let num_particles = positions.len();
         let mut radii = Vec::with_capacity(num_particles);
         let mut colors = Vec::with_capacity(num_particles);
         let mut max_radius = f32::MIN;
 
         for _ in 0..num_particles as u32 {
-            let x = rng.random_range(0.0..world_width);
-            let y = rng.random_range(0.0..world_height);
-            positions.push(Vec2::new(x, y));
-            let radius = rng.random_range(0.5..= 0.5) as f32;
-            colors.push(glam::vec4(rng.random_range(0.3..0.8), rng.random_range(0.3..0.8), rng.random_range(0.3..0.8), 1.0));
+            let radius = rng.random_range(radius_range.0..=radius_range.1);
+            colors.push(match palette {
+                Some(palette) => palette.random_color(&mut rng),
+                None => glam::vec4(rng.random_range(0.3..0.8), rng.random_range(0.3..0.8), rng.random_range(0.3..0.8), 1.0),
+            });
             if radius > max_radius {
                 max_radius = radius;
             }
@@ -137,7 +438,8 @@ impl ParticleSystem {
             current_positions,
             previous_positions,
             radii: radius,
-            colors: GpuBuffer::new(wgpu_context, colors.clone(), wgpu::BufferUsages::VERTEX),
+            colors: GpuBuffer::new(wgpu_context, colors.clone(), wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE),
+            extras: GpuBuffer::new(wgpu_context, channels.create_extras_data(num_particles), wgpu::BufferUsages::STORAGE),
         };
                 
         
@@ -155,85 +457,934 @@ impl ParticleSystem {
             previous_positions: previous_positions_copy,
             radii: radius_copy, 
             colors: colors_copy,
+            extras: GpuBuffer::new(wgpu_context, channels.create_extras_data(num_particles), wgpu::BufferUsages::STORAGE),
         };
         
         ((buffers, buffers_copy), max_radius)
     }
 
-    pub fn add_particles(&mut self, mouse_pos: &Vec2, wgpu_context: &WgpuContext){
-        
-        
-        for i in 0..100 {
+    /// Spawns a batch of particles around `mouse_pos`, as long as they fit under the particle limit.
+    /// The particles that do not fit are dropped or respawn the oldest ones, see `SpawnOverflow`.
+    pub fn add_particles(&mut self, mouse_pos: &Vec2, wgpu_context: &WgpuContext) -> SpawnReport {
+        let mut batch = Self::generate_spawn_batch(mouse_pos, self.spawn_radius_range);
+        self.paint_batch(&mut batch);
+        let report = self.spawn(wgpu_context, &batch);
+        println!("Total particles: {}", self.len());
+        report
+    }
+
+    /// Spawns a particle at each of `positions`, with a radius of the spawn radius range and a random color
+    /// (or the next color of the palette), under the particle limit like `add_particles`.
+    pub fn add_particles_at(&mut self, positions: &[Vec2], wgpu_context: &WgpuContext) -> SpawnReport {
+        let (min_radius, max_radius) = self.spawn_radius_range;
+        let mut batch: Vec<SpawnedParticle> = positions.iter().map(|&position| SpawnedParticle {
+            position,
+            radius: rng::random_range(min_radius..=max_radius),
+            color: glam::vec4(rng::random_range(0.3..1.0), rng::random_range(0.3..1.0), rng::random_range(0.3..1.0), 1.0),
+        }).collect();
+        self.paint_batch(&mut batch);
+        self.spawn(wgpu_context, &batch)
+    }
+
+    /// Paints the batch with the next color of the palette, if there is one.
+    fn paint_batch(&mut self, batch: &mut [SpawnedParticle]) {
+        let Some(palette) = &self.palette else {
+            return;
+        };
+        let color = palette.color(self.spawn_batches);
+        self.spawn_batches += 1;
+        let mut rng = rng::rng();
+        for particle in batch {
+            particle.color = ColorPalette::vary(color, &mut rng);
+        }
+    }
+
+    fn spawn(&mut self, wgpu_context: &WgpuContext, batch: &[SpawnedParticle]) -> SpawnReport {
+        let free_slots = self.limit.max_particles.saturating_sub(self.len());
+        let (spawned, overflow) = batch.split_at(batch.len().min(free_slots));
+
+        let recycled = match self.limit.overflow {
+            SpawnOverflow::Refuse => 0,
+            SpawnOverflow::RecycleOldest => self.recycle_oldest(wgpu_context, overflow),
+        };
+        if spawned.is_empty() {
+            self.last_refresh_timings.clear();
+        }
+        else {
+            self.push_particles(wgpu_context, spawned);
+        }
+
+        SpawnReport {
+            spawned: spawned.len(),
+            recycled,
+            refused: overflow.len() - recycled,
+        }
+    }
+
+    fn generate_spawn_batch(mouse_pos: &Vec2, (min_particle_radius, max_particle_radius): (f32, f32)) -> Vec<SpawnedParticle> {
+        (0..SPAWN_BATCH_SIZE).map(|i| {
             // Generate a random angle (0 to 2*PI radians)
-            let angle = random_range(0.0..std::f32::consts::TAU); // TAU is 2*PI
+            let angle = rng::random_range(0.0..std::f32::consts::TAU); // TAU is 2*PI
 
             // Generate a random radius (from mouse_pos)
             // Start the minimum radius higher to avoid center clumping
             // And potentially make the maximum radius larger or adjust its scaling
             let min_radius = 10.0 ; // Minimum distance from the center
             let max_radius = 50.0 + (i as f32 * 1.5); // Example: Gradually increase max radius
-            let radius = random_range(min_radius..=max_radius);
-
+            let radius = rng::random_range(min_radius..=max_radius);
 
             // Convert polar coordinates to Cartesian (x, y)
             let offset_x = radius * angle.cos();
             let offset_y = radius * angle.sin();
 
-            let pos: Vec2 = mouse_pos + Vec2::new(offset_x, offset_y);
-
-            self.particle_buffers.current_positions.push(pos.clone(), wgpu_context);
-            self.particle_buffers_copy.current_positions.push(pos.clone(), wgpu_context);
-            self.particle_buffers.previous_positions.push(pos, wgpu_context);
-            self.particle_buffers_copy.previous_positions.push(pos, wgpu_context);
-
-            let rng_radius_particle = random_range(1..=3) as f32; 
-            self.particle_buffers.radii.push(
-                rng_radius_particle,
-                wgpu_context
-            );
-            self.particle_buffers_copy.radii.push(
-                rng_radius_particle,
-                wgpu_context
-            );
-
-            self.max_radius = self.max_radius.max(rng_radius_particle);
-
-            self.particle_buffers.colors.push(
-                glam::vec4(random_range(0.3..1.0), random_range(0.3..1.0), random_range(0.3..1.0), 1.0),
-                wgpu_context
-            );
-            self.particle_buffers_copy.colors.push(
-                glam::vec4(random_range(0.3..1.0), random_range(0.3..1.0), random_range(0.3..1.0), 1.0),
-                wgpu_context
-            );
-            
-            self.particle_buffers.home_cell_ids.push(UNUSED_CELL_ID, wgpu_context);
-            self.particle_buffers_copy.home_cell_ids.push(UNUSED_CELL_ID, wgpu_context);
-            
+            SpawnedParticle {
+                position: mouse_pos + Vec2::new(offset_x, offset_y),
+                radius: rng::random_range(min_particle_radius..=max_particle_radius),
+                color: glam::vec4(rng::random_range(0.3..1.0), rng::random_range(0.3..1.0), rng::random_range(0.3..1.0), 1.0),
+            }
+        }).collect()
+    }
+
+    /// Appends the particles to the buffers and refreshes the kernels bound to them.
+    /// The kernels are only rebound if a buffer had to grow, see `reserve`.
+    fn push_particles(&mut self, wgpu_context: &WgpuContext, particles: &[SpawnedParticle]) {
+        let buffer_growth_timer = RefreshTimer::start(wgpu_context);
+        let num_particles = self.len() + particles.len();
+        // Grow every buffer at once, doubling like the pushes do, so the next batches write in place
+        let reallocated = num_particles > self.capacity() && self.reserve_buffers(wgpu_context, num_particles * 2);
+
+        let positions: Vec<Vec2> = particles.iter().map(|particle| particle.position).collect();
+        let radii: Vec<f32> = particles.iter().map(|particle| particle.radius).collect();
+        let colors: Vec<Vec4> = particles.iter().map(|particle| particle.color).collect();
+        let home_cell_ids = vec![UNUSED_CELL_ID; particles.len()];
+        for buffers in [&mut self.particle_buffers, &mut self.particle_buffers_copy] {
+            buffers.current_positions.push_all(&positions, wgpu_context);
+            buffers.previous_positions.push_all(&positions, wgpu_context);
+            buffers.radii.push_all(&radii, wgpu_context);
+            buffers.colors.push_all(&colors, wgpu_context);
+            buffers.home_cell_ids.push_all(&home_cell_ids, wgpu_context);
         }
-        
+        self.max_radius = radii.iter().fold(self.max_radius, |max_radius, &radius| max_radius.max(radius));
+
+        self.push_channel_defaults(wgpu_context, &radii);
+        self.highlight_flags.push_all(&vec![0u32; particles.len()], wgpu_context);
+        let buffer_growth = buffer_growth_timer.finish(wgpu_context);
+
+        let sorter_resize_timer = RefreshTimer::start(wgpu_context);
+        let particle_ids_reallocated = self.particle_sort.resize_sorter(wgpu_context, &self.particle_buffers);
+        let sorter_resize = sorter_resize_timer.finish(wgpu_context);
+
+        let rebinding_timer = RefreshTimer::start(wgpu_context);
+        if reallocated || particle_ids_reallocated {
+            self.particle_sort.refresh_bindings(wgpu_context, &self.particle_buffers, &self.particle_buffers_copy);
+        }
+        if reallocated {
+            self.rebind_kernels(wgpu_context);
+        }
+        else {
+            self.particle_integration.set_num_particles(self.len() as u32);
+            // Grows its own alive flags
+            if let Some(compaction) = self.compaction.as_mut() {
+                compaction.refresh(wgpu_context, &self.particle_buffers, &self.particle_buffers_copy);
+            }
+        }
+        let rebinding = rebinding_timer.finish(wgpu_context);
+
+        self.last_refresh_timings = vec![
+            ("Particle buffer growth", buffer_growth),
+            ("Particle sorter resize", sorter_resize),
+            ("Particle kernel re-binding", rebinding),
+        ];
+    }
+
+    /// Grows the particle buffers so `capacity` particles fit, e.g. before spawning large batches: the spawns up to
+    /// `capacity` then write into the existing buffers, without recreating them and rebinding every kernel.
+    /// Returns true if the buffers were recreated: the kernels of other systems bound to them must be rebound.
+    pub fn reserve(&mut self, wgpu_context: &WgpuContext, capacity: usize) -> bool {
+        let recreated = self.reserve_buffers(wgpu_context, capacity);
+        if recreated {
+            self.particle_sort.refresh_bindings(wgpu_context, &self.particle_buffers, &self.particle_buffers_copy);
+            self.rebind_kernels(wgpu_context);
+        }
+        recreated
+    }
+
+    /// Particles the buffers can hold without growing.
+    pub fn capacity(&self) -> usize {
+        let stride = self.channels.stride() as usize;
+        self.particle_buffers.capacity(stride)
+            .min(self.particle_buffers_copy.capacity(stride))
+            .min(self.highlight_flags.capacity())
+    }
+
+    /// Grows the buffers without rebinding the kernels. Returns true if any was recreated.
+    fn reserve_buffers(&mut self, wgpu_context: &WgpuContext, capacity: usize) -> bool {
+        let stride = self.channels.stride() as usize;
+        let mut recreated = self.particle_buffers.reserve(wgpu_context, capacity, stride);
+        recreated |= self.particle_buffers_copy.reserve(wgpu_context, capacity, stride);
+        recreated |= self.highlight_flags.reserve(wgpu_context, capacity);
+        recreated |= self.particle_sort.reserve(wgpu_context, capacity);
+        recreated
+    }
+
+    /// Rebinds the integration, the drawer and the optional kernels to the current buffers.
+    fn rebind_kernels(&mut self, wgpu_context: &WgpuContext) {
+        self.particle_integration.refresh(&self.particle_buffers, &self.channels);
+        #[cfg(feature = "windowing")]
+        self.refresh_drawer(wgpu_context);
+        if let Some(compaction) = self.compaction.as_mut() {
+            compaction.refresh(wgpu_context, &self.particle_buffers, &self.particle_buffers_copy);
+        }
+        if let Some(color_kernel) = self.color_kernel.as_mut() {
+            color_kernel.refresh(wgpu_context, &self.particle_buffers);
+        }
+        if let Some(sprite_animation) = self.sprite_animation.as_mut() {
+            sprite_animation.refresh(wgpu_context, &self.particle_buffers);
+        }
+        if let Some(trails) = self.trails.as_mut() {
+            trails.refresh(wgpu_context, &self.particle_buffers);
+        }
+    }
+
+    /// Respawns the oldest particles at the positions of `particles`. Returns how many were respawned.
+    /// Reads the spawn order channel back, so it stalls until the GPU is idle.
+    fn recycle_oldest(&mut self, wgpu_context: &WgpuContext, particles: &[SpawnedParticle]) -> usize {
+        let Some(spawn_order) = self.spawn_order else {
+            return 0;
+        };
+        let num_particles = self.len();
+        let count = particles.len().min(num_particles);
+        if count == 0 {
+            return 0;
+        }
+
+        let stride = self.channels.stride() as usize;
+        let offset = self.channels.offset(spawn_order) as usize;
+        let extras = self.particle_buffers.extras.download(wgpu_context).unwrap();
+        // (spawn order, particle index), the oldest first once partitioned
+        let mut ages: Vec<(u32, usize)> = (0..num_particles).map(|i| (extras[i * stride + offset], i)).collect();
+        ages.select_nth_unstable(count - 1);
+
+        for (&(_, index), particle) in ages[..count].iter().zip(particles) {
+            self.particle_buffers.current_positions.replace_elem(particle.position, index, wgpu_context);
+            self.particle_buffers.previous_positions.replace_elem(particle.position, index, wgpu_context);
+            self.particle_buffers.radii.replace_elem(particle.radius, index, wgpu_context);
+            self.max_radius = self.max_radius.max(particle.radius);
+            if index < self.particle_buffers.colors.len() {
+                self.particle_buffers.colors.replace_elem(particle.color, index, wgpu_context);
+            }
+            for (word, value) in self.new_particle_words(particle.radius).into_iter().enumerate() {
+                self.particle_buffers.extras.replace_elem(value, index * stride + word, wgpu_context);
+            }
+        }
+        count
+    }
+
+    /// Channel words of a spawned particle of `radius`: the spawn values, the next spawn order, the spawn mass and
+    /// the defaults.
+    fn new_particle_words(&mut self, radius: f32) -> Vec<u32> {
+        let spawn_order = self.spawn_order.map(|id| {
+            let order = self.next_spawn_order;
+            self.next_spawn_order += 1;
+            (id, [order])
+        });
+        let mut overrides: Vec<(ChannelId, &[u32])> = self.spawn_channel_values.iter()
+            .map(|(id, values)| (*id, values.as_slice()))
+            .collect();
+        if let Some((id, order)) = &spawn_order {
+            overrides.push((*id, order.as_slice()));
+        }
+        let mass = self.channels.find(MASS_CHANNEL)
+            .filter(|id| overrides.iter().all(|(other, _)| other != id))
+            .map(|id| (id, [self.spawn_mass.mass(radius).to_bits()]));
+        if let Some((id, mass)) = &mass {
+            overrides.push((*id, mass.as_slice()));
+        }
+        self.channels.particle_words(&overrides)
+    }
+
+    /// Appends the channel values of new particles of `radii` to the extras buffers.
+    fn push_channel_defaults(&mut self, wgpu_context: &WgpuContext, radii: &[f32]) {
+        if self.channels.stride() > 0 {
+            let words: Vec<u32> = radii.iter().flat_map(|&radius| self.new_particle_words(radius)).collect();
+            self.particle_buffers.extras.push_all(&words, wgpu_context);
+            self.particle_buffers_copy.extras.push_all(&words, wgpu_context);
+        }
+        self.channels.update_layout(wgpu_context, self.len());
+    }
+
+    /// Particles spawned from now on get `values` in channel `id` instead of the channel default.
+    pub fn set_spawn_channel_value(&mut self, id: ChannelId, values: &[u32]) {
+        self.spawn_channel_values.retain(|(other, _)| *other != id);
+        self.spawn_channel_values.push((id, values.to_vec()));
+    }
+
+    /// Sets the maximum number of particles and what spawning does once it is reached.
+    /// Recycling registers a spawn order channel, so the oldest particles can be found after sorting.
+    pub fn set_limit(&mut self, wgpu_context: &WgpuContext, limit: ParticleLimit) {
+        if limit.overflow == SpawnOverflow::RecycleOldest && self.spawn_order.is_none() {
+            // Existing particles get 0, they are all older than the next spawned ones
+            self.spawn_order = Some(self.register_channel(wgpu_context, SPAWN_ORDER_CHANNEL, &[0]));
+        }
+        self.limit = limit;
+    }
+
+    pub fn limit(&self) -> ParticleLimit {
+        self.limit
+    }
+
+    /// Registers a new per-particle channel, every existing particle gets `default`.
+    /// Kernels that bind the extras buffer see the new channel without changing their layouts.
+    pub fn register_channel(&mut self, wgpu_context: &WgpuContext, name: &str, default: &[u32]) -> ChannelId {
+        let num_particles = self.len();
+        let old_extras = self.particle_buffers.extras.download(wgpu_context).unwrap().clone();
+        let (id, new_extras) = self.channels.register(wgpu_context, name, default, &old_extras, num_particles);
+
+        self.particle_buffers.extras = GpuBuffer::new(wgpu_context, new_extras.clone(), wgpu::BufferUsages::STORAGE);
+        self.particle_buffers_copy.extras = GpuBuffer::new(wgpu_context, new_extras, wgpu::BufferUsages::STORAGE);
+
         self.particle_sort.refresh(wgpu_context, &self.particle_buffers, &self.particle_buffers_copy);
-        self.particle_integration.refresh(wgpu_context, &self.particle_buffers);
-        self.particle_drawer.as_mut().expect("Particle drawer null").refresh(wgpu_context, &self.particle_buffers);
-        
-        println!("Total particles: {}", self.len());
+        self.particle_integration.refresh(&self.particle_buffers, &self.channels);
+        if let Some(compaction) = self.compaction.as_mut() {
+            compaction.refresh(wgpu_context, &self.particle_buffers, &self.particle_buffers_copy);
+        }
+        #[cfg(feature = "windowing")]
+        self.refresh_drawer(wgpu_context);
+        if let Some(color_kernel) = self.color_kernel.as_mut() {
+            color_kernel.refresh(wgpu_context, &self.particle_buffers);
+        }
+        if let Some(sprite_animation) = self.sprite_animation.as_mut() {
+            sprite_animation.refresh(wgpu_context, &self.particle_buffers);
+        }
+        if let Some(trails) = self.trails.as_mut() {
+            trails.refresh(wgpu_context, &self.particle_buffers);
+        }
+        id
+    }
+
+    /// Overwrites a channel for the particles from `first_particle` on, e.g. the ones just spawned, without
+    /// replacing the extras buffer. `values` holds the channel components of each of them in order.
+    pub fn write_channel_from(&mut self, wgpu_context: &WgpuContext, id: ChannelId, first_particle: usize, values: &[u32]) {
+        let stride = self.channels.stride() as usize;
+        let offset = self.channels.offset(id) as usize;
+        let num_words = self.channels.num_components(id);
+        for (i, words) in values.chunks(num_words).enumerate() {
+            self.particle_buffers.extras.write_range((first_particle + i) * stride + offset, words, wgpu_context);
+        }
+    }
+
+    /// Overwrites a channel for every particle. `values` holds the channel components of particle 0, then particle 1...
+    pub fn write_channel(&mut self, wgpu_context: &WgpuContext, id: ChannelId, values: &[u32]) {
+        let stride = self.channels.stride() as usize;
+        let offset = self.channels.offset(id) as usize;
+        let num_words = values.len() / self.len();
+        let mut extras = self.particle_buffers.extras.download(wgpu_context).unwrap().clone();
+        for (particle, words) in values.chunks(num_words).enumerate() {
+            let start = particle * stride + offset;
+            extras[start..start + num_words].copy_from_slice(words);
+        }
+
+        self.particle_buffers.extras = GpuBuffer::new(wgpu_context, extras.clone(), wgpu::BufferUsages::STORAGE);
+        self.particle_buffers_copy.extras = GpuBuffer::new(wgpu_context, extras, wgpu::BufferUsages::STORAGE);
+        self.particle_sort.refresh(wgpu_context, &self.particle_buffers, &self.particle_buffers_copy);
+        self.particle_integration.refresh(&self.particle_buffers, &self.channels);
+        if let Some(compaction) = self.compaction.as_mut() {
+            compaction.refresh(wgpu_context, &self.particle_buffers, &self.particle_buffers_copy);
+        }
+        #[cfg(feature = "windowing")]
+        self.refresh_drawer(wgpu_context);
+        if let Some(color_kernel) = self.color_kernel.as_mut() {
+            color_kernel.refresh(wgpu_context, &self.particle_buffers);
+        }
+        if let Some(sprite_animation) = self.sprite_animation.as_mut() {
+            sprite_animation.refresh(wgpu_context, &self.particle_buffers);
+        }
+        if let Some(trails) = self.trails.as_mut() {
+            trails.refresh(wgpu_context, &self.particle_buffers);
+        }
     }
-    pub fn mouse_click_callback(&mut self, mouse_state: &ElementState, position: Vec2){
-        self.particle_integration.mouse_click_callback(mouse_state, position);
+
+    /// Gives every particle a lifetime in seconds, stored in the `LIFETIME_CHANNEL` channel: `remove_dead_particles`
+    /// removes the particles whose lifetime ran out. Existing particles never expire, spawned ones get
+    /// `set_spawn_lifetime`. Does nothing if lifetimes are already enabled.
+    pub fn enable_lifetime(&mut self, wgpu_context: &WgpuContext) -> ChannelId {
+        if let Some(id) = self.channels.find(LIFETIME_CHANNEL) {
+            return id;
+        }
+        self.register_channel(wgpu_context, LIFETIME_CHANNEL, &[f32::INFINITY.to_bits()])
+    }
+
+    /// Registers the `BOUNDARY_MATERIAL_CHANNEL` channel. Every existing particle gets `NO_MATERIAL` and keeps
+    /// the default `boundary_material`. Does nothing if it already exists.
+    pub fn enable_boundary_materials(&mut self, wgpu_context: &WgpuContext) -> ChannelId {
+        if let Some(id) = self.channels.find(BOUNDARY_MATERIAL_CHANNEL) {
+            return id;
+        }
+        self.register_channel(wgpu_context, BOUNDARY_MATERIAL_CHANNEL, &[NO_MATERIAL])
+    }
+
+    /// Registers the `MASS_CHANNEL` channel: the collision solvers and the springs then weigh their corrections
+    /// with the masses instead of the radii, see `SolverMethod::mass_exponent`. Every existing particle gets the mass
+    /// `spawn_mass` gives its radius, which reads the radii back. Does nothing if it already exists.
+    pub fn enable_mass(&mut self, wgpu_context: &WgpuContext) -> ChannelId {
+        if let Some(id) = self.channels.find(MASS_CHANNEL) {
+            return id;
+        }
+        let id = self.register_channel(wgpu_context, MASS_CHANNEL, &[self.spawn_mass.mass(1.0).to_bits()]);
+        if self.len() > 0 {
+            let radii = self.particle_buffers.radii.download(wgpu_context).unwrap().clone();
+            let masses: Vec<u32> = radii.iter().take(self.len()).map(|&radius| self.spawn_mass.mass(radius).to_bits()).collect();
+            self.write_channel_from(wgpu_context, id, 0, &masses);
+        }
+        id
+    }
+
+    /// Mass of the particles spawned from now on, once the mass channel is registered. Positive masses only.
+    pub fn set_spawn_mass(&mut self, spawn_mass: SpawnMass) {
+        self.spawn_mass = spawn_mass;
+    }
+
+    pub fn spawn_mass(&self) -> SpawnMass {
+        self.spawn_mass
+    }
+
+    /// Registers the `PARTICLE_FLAGS_CHANNEL` channel, no flag for every particle. Does nothing if it already exists.
+    pub fn enable_particle_flags(&mut self, wgpu_context: &WgpuContext) -> ChannelId {
+        if let Some(id) = self.channels.find(PARTICLE_FLAGS_CHANNEL) {
+            return id;
+        }
+        self.register_channel(wgpu_context, PARTICLE_FLAGS_CHANNEL, &[0])
+    }
+
+    /// Sets (`enabled`) or clears `flag` in the `PARTICLE_FLAGS_CHANNEL` of the particles in `range`, keeping
+    /// their other flags. Registers the channel if needed.
+    pub fn set_particle_flag(&mut self, wgpu_context: &WgpuContext, range: Range<usize>, flag: u32, enabled: bool) {
+        assert!(range.end <= self.len(), "Particles {:?} out of bounds of {} particles", range, self.len());
+        let id = self.enable_particle_flags(wgpu_context);
+        let stride = self.channels.stride() as usize;
+        let offset = self.channels.offset(id) as usize;
+        let mut words = self.particle_buffers.extras.download_range(wgpu_context, range.start * stride..range.end * stride).unwrap().to_vec();
+        for particle_words in words.chunks_mut(stride) {
+            particle_words[offset] = if enabled { particle_words[offset] | flag } else { particle_words[offset] & !flag };
+        }
+        self.particle_buffers.extras.write_range(range.start * stride, &words, wgpu_context);
+    }
+
+    /// Reads back the channel components of one particle right away.
+    pub fn read_channel_at(&mut self, wgpu_context: &WgpuContext, id: ChannelId, particle: usize) -> Vec<u32> {
+        let start = particle * self.channels.stride() as usize + self.channels.offset(id) as usize;
+        let range = start..start + self.channels.num_components(id);
+        self.particle_buffers.extras.download_range(wgpu_context, range).unwrap().to_vec()
+    }
+
+    /// Registers the `SPRITE_FRAME_CHANNEL` channel, frame 0 for every particle. Does nothing if it already exists.
+    pub fn enable_sprite_frames(&mut self, wgpu_context: &WgpuContext) -> ChannelId {
+        if let Some(id) = self.channels.find(SPRITE_FRAME_CHANNEL) {
+            return id;
+        }
+        self.register_channel(wgpu_context, SPRITE_FRAME_CHANNEL, &[0])
+    }
+
+    /// Shape the drawer gives the particles. `ParticleShape::Sprite` reads the frame of each particle from the
+    /// `SPRITE_FRAME_CHANNEL` channel, see `enable_sprite_frames`, and particles without it draw frame 0.
+    pub fn set_shape(&mut self, shape: ParticleShape) {
+        self.shape = shape;
+        #[cfg(feature = "windowing")]
+        if let Some(particle_drawer) = self.particle_drawer.as_mut() {
+            particle_drawer.set_shape(shape);
+        }
+    }
+
+    pub fn shape(&self) -> ParticleShape {
+        self.shape
+    }
+
+    /// Image with the frames of `ParticleShape::Sprite`. Kept for drawers attached later.
+    #[cfg_attr(not(feature = "windowing"), allow(unused_variables))]
+    pub fn set_sprite_atlas(&mut self, wgpu_context: &WgpuContext, sprite_atlas: SpriteAtlas) {
+        #[cfg(feature = "windowing")]
+        if let Some(particle_drawer) = self.particle_drawer.as_mut() {
+            particle_drawer.set_sprite_atlas(wgpu_context, &sprite_atlas, &Self::drawn_buffer_sets(&self.particle_buffers, self.display_buffers.as_ref()), &self.highlight_flags);
+        }
+        self.sprite_atlas = Some(sprite_atlas);
+    }
+
+    pub fn sprite_atlas(&self) -> Option<&SpriteAtlas> {
+        self.sprite_atlas.as_ref()
+    }
+
+    /// Writes the `SPRITE_FRAME_CHANNEL` of every particle from its age or speed with `update_sprite_animation`,
+    /// registering the sprite frame channel and, for `SpriteAnimationDriver::Age`, the `SPRITE_AGE_CHANNEL` one.
+    /// Spawned particles start at age 0. `None` stops the animation, the particles keep their last frame.
+    pub fn set_sprite_animation(&mut self, wgpu_context: &WgpuContext, animation: Option<SpriteAnimation>) {
+        let Some(animation) = animation else {
+            self.sprite_animation = None;
+            return;
+        };
+        assert!(animation.clip.num_frames > 0, "a sprite animation needs at least one frame");
+        match animation.driver {
+            SpriteAnimationDriver::Age { frames_per_second } => {
+                assert!(frames_per_second >= 0.0, "invalid frames per second {}", frames_per_second);
+                if self.channels.find(SPRITE_AGE_CHANNEL).is_none() {
+                    self.register_channel(wgpu_context, SPRITE_AGE_CHANNEL, &[0.0f32.to_bits()]);
+                }
+            }
+            SpriteAnimationDriver::Speed { max_speed } => assert!(max_speed > 0.0, "invalid max speed {}", max_speed),
+        }
+        self.enable_sprite_frames(wgpu_context);
+        match self.sprite_animation.as_mut() {
+            Some(sprite_animation) => sprite_animation.set_animation(animation),
+            None => self.sprite_animation = Some(SpriteAnimationKernel::new(wgpu_context, &self.particle_buffers, animation)),
+        }
+    }
+
+    pub fn sprite_animation(&self) -> Option<SpriteAnimation> {
+        self.sprite_animation.as_ref().map(SpriteAnimationKernel::animation)
+    }
+
+    /// Submits the sprite animation pass, if enabled by `set_sprite_animation`. `delta_time` is the simulated time
+    /// since the last update, the speed is measured over the last step of `step_delta_time`.
+    pub fn update_sprite_animation(&self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, delta_time: f32, step_delta_time: f32) {
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Sprite animation encoder") }
+        );
+        self.record_update_sprite_animation(&mut encoder, gpu_profiler, delta_time, step_delta_time);
+        gpu_profiler.resolve_queries(&mut encoder);
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+    }
+
+    /// Records `update_sprite_animation` into `encoder` instead of submitting it.
+    pub fn record_update_sprite_animation(&self, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler, delta_time: f32, step_delta_time: f32) {
+        let Some(sprite_animation) = self.sprite_animation.as_ref() else {
+            return;
+        };
+        let Some(frame_channel) = self.channels.find(SPRITE_FRAME_CHANNEL) else {
+            return;
+        };
+        let offsets = SpriteChannelOffsets {
+            extras_stride: self.channels.stride(),
+            frame_offset: self.channels.offset(frame_channel),
+            age_offset: self.channels.find(SPRITE_AGE_CHANNEL).map(|id| self.channels.offset(id)),
+        };
+        let delta_time = match sprite_animation.animation().driver {
+            SpriteAnimationDriver::Age { .. } => delta_time,
+            SpriteAnimationDriver::Speed { .. } => step_delta_time,
+        };
+        sprite_animation.record(encoder, gpu_profiler, self.len() as u32, offsets, delta_time);
+    }
+
+    /// Keeps the last `length` positions of every particle with `update_trails`, drawn as fading lines behind
+    /// the particles. Registers the `TRAIL_SLOT_CHANNEL` channel. Costs `length` positions of GPU memory per
+    /// particle. `None` removes the trails and frees their history.
+    pub fn set_trails(&mut self, wgpu_context: &WgpuContext, length: Option<u32>) {
+        let Some(length) = length else {
+            self.trails = None;
+            return;
+        };
+        if self.trails.as_ref().is_some_and(|trails| trails.length() == length) {
+            return;
+        }
+        if self.channels.find(TRAIL_SLOT_CHANNEL).is_none() {
+            self.register_channel(wgpu_context, TRAIL_SLOT_CHANNEL, &[NO_TRAIL]);
+        }
+        let trails = ParticleTrails::new(wgpu_context, &self.particle_buffers, length);
+        #[cfg(feature = "windowing")]
+        if let Some(trail_drawer) = self.trail_drawer.as_mut() {
+            trail_drawer.bind_history(wgpu_context, trails.history());
+        }
+        self.trails = Some(trails);
+    }
+
+    /// Positions kept per trail, `None` without trails.
+    pub fn trail_length(&self) -> Option<u32> {
+        self.trails.as_ref().map(ParticleTrails::length)
+    }
+
+    /// Submits the pass that appends the current positions to the trails, if enabled by `set_trails`.
+    /// Once per frame, after the steps. The trails restart when the number of particles changed.
+    pub fn update_trails(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler) {
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Particle trails encoder") }
+        );
+        self.record_update_trails(wgpu_context, &mut encoder, gpu_profiler);
+        gpu_profiler.resolve_queries(&mut encoder);
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+    }
+
+    /// Records `update_trails` into `encoder` instead of submitting it.
+    #[cfg_attr(not(feature = "windowing"), allow(unused_variables))]
+    pub fn record_update_trails(&mut self, wgpu_context: &WgpuContext, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler) {
+        let num_particles = self.len();
+        let Some(trails) = self.trails.as_mut() else {
+            return;
+        };
+        let Some(slot_channel) = self.channels.find(TRAIL_SLOT_CHANNEL) else {
+            return;
+        };
+        let offsets = TrailChannelOffsets {
+            extras_stride: self.channels.stride(),
+            slot_offset: self.channels.offset(slot_channel),
+        };
+        let recreated = trails.record(wgpu_context, encoder, gpu_profiler, &self.particle_buffers, num_particles, offsets);
+        #[cfg(feature = "windowing")]
+        if let (true, Some(trail_drawer)) = (recreated, self.trail_drawer.as_mut()) {
+            trail_drawer.bind_history(wgpu_context, trails.history());
+        }
+    }
+
+    /// Reads back the trail of one particle right away, the newest position first. `None` if the particle has
+    /// no trail, e.g. before the first `update_trails`.
+    pub fn download_trail(&mut self, wgpu_context: &WgpuContext, particle: usize) -> Option<Vec<Vec2>> {
+        let slot_channel = self.channels.find(TRAIL_SLOT_CHANNEL)?;
+        let slot = self.read_channel_at(wgpu_context, slot_channel, particle)[0];
+        let trails = self.trails.as_mut()?;
+        (slot < trails.num_slots()).then(|| trails.download_slot(wgpu_context, slot))
+    }
+
+    /// Lifetime of the particles spawned from now on, in seconds. Enables lifetimes if needed.
+    pub fn set_spawn_lifetime(&mut self, wgpu_context: &WgpuContext, lifetime: f32) {
+        let id = self.enable_lifetime(wgpu_context);
+        self.set_spawn_channel_value(id, &[lifetime.to_bits()]);
+    }
+
+    /// Removes the particles whose lifetime ran out during the last `elapsed_time` seconds, and the ones
+    /// outside the world (or at non-finite positions), on the GPU: the survivors are compacted to the front
+    /// of the buffers, keeping their order, and only their count is read back to shrink the buffers logically.
+    /// Stalls until the GPU is done. Returns the number of removed particles.
+    /// Kernels bound to the particle buffers stay valid, but whatever indexes particles (the grid) must be shrunk too.
+    pub fn remove_dead_particles(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, elapsed_time: f32) -> usize {
+        let num_particles = self.len();
+        if num_particles == 0 {
+            return 0;
+        }
+        let compaction = self.compaction.get_or_insert_with(|| ParticleCompaction::new(wgpu_context, &self.particle_buffers, &self.particle_buffers_copy));
+        let params = CompactionParams {
+            num_particles: num_particles as u32,
+            extras_stride: self.channels.stride(),
+            lifetime_offset: self.channels.find(LIFETIME_CHANNEL).map(|id| self.channels.offset(id)),
+            elapsed_time,
+            bounds_min: self.world_origin,
+            bounds_max: self.world_origin + self.world_size,
+        };
+
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Particle compaction encoder") }
+        );
+        compaction.compact(wgpu_context, &mut encoder, gpu_profiler, &self.particle_buffers, &self.particle_buffers_copy, &params);
+        gpu_profiler.resolve_queries(&mut encoder);
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+
+        let live_particles = compaction.read_live_particles(wgpu_context) as usize;
+        if live_particles < num_particles {
+            self.shrink_to(wgpu_context, live_particles);
+        }
+        num_particles - live_particles
+    }
+
+    /// Runs `operation` over the particles at `ids`, e.g. the result of a `ParticleSelectionQuery`.
+    /// `GroupOperation::Remove` only marks the particles, `remove_dead_particles` deletes them.
+    pub fn apply_group_operation(&mut self, wgpu_context: &WgpuContext, ids: &[u32], operation: GroupOperation) {
+        let group_operations = self.group_operations.get_or_insert_with(|| ParticleGroupOperations::new(wgpu_context));
+        group_operations.apply(wgpu_context, &self.particle_buffers, ids, operation);
+    }
+
+    /// Forgets the particles past `num_particles`. The GPU buffers keep their size, spawning reuses them.
+    fn shrink_to(&mut self, wgpu_context: &WgpuContext, num_particles: usize) {
+        let stride = self.channels.stride() as usize;
+        for buffers in [&mut self.particle_buffers, &mut self.particle_buffers_copy] {
+            buffers.current_positions.truncate(num_particles);
+            buffers.previous_positions.truncate(num_particles);
+            buffers.radii.truncate(num_particles);
+            buffers.colors.truncate(num_particles);
+            buffers.home_cell_ids.truncate(num_particles);
+            buffers.extras.truncate(num_particles * stride);
+        }
+        self.highlight_flags.truncate(num_particles);
+        self.channels.update_layout(wgpu_context, num_particles);
+        self.particle_sort.resize_sorter(wgpu_context, &self.particle_buffers);
+        self.particle_integration.refresh(&self.particle_buffers, &self.channels);
+    }
+
+    /// Recomputes the particle colors from their state with `update_colors`, instead of the velocity
+    /// shading of the drawer. `None` goes back to the drawer's shading.
+    pub fn set_color_settings(&mut self, wgpu_context: &WgpuContext, settings: Option<ParticleColorSettings>) {
+        match (settings, self.color_kernel.as_mut()) {
+            (Some(settings), Some(color_kernel)) => color_kernel.set_settings(settings),
+            (Some(settings), None) => self.color_kernel = Some(ParticleColorKernel::new(wgpu_context, &self.particle_buffers, settings)),
+            (None, _) => self.color_kernel = None,
+        }
+        self.refresh_drawer_colors();
+    }
+
+    /// Draws the colors buffer when the color kernel or a palette writes it, otherwise shades the particles by velocity.
+    fn refresh_drawer_colors(&mut self) {
+        #[cfg(feature = "windowing")]
+        if let Some(particle_drawer) = self.particle_drawer.as_mut() {
+            particle_drawer.set_use_particle_colors(self.color_kernel.is_some() || self.palette.is_some());
+        }
+    }
+
+    /// Paints the next spawn batches with the colors of `palette`, one color per batch, instead of random colors.
+    /// The particles already spawned keep their colors, see `recolor_with_palette`. `None` goes back to random colors.
+    pub fn set_palette(&mut self, palette: Option<ColorPalette>) {
+        self.palette = palette;
+        self.spawn_batches = 0;
+        self.refresh_drawer_colors();
+    }
+
+    pub fn palette(&self) -> Option<&ColorPalette> {
+        self.palette.as_ref()
+    }
+
+    /// Repaints every particle with a random color of the palette. Returns false, doing nothing, without a palette.
+    /// The color kernel, if enabled, overwrites the colors on its next update.
+    pub fn recolor_with_palette(&mut self, wgpu_context: &WgpuContext) -> bool {
+        let Some(palette) = &self.palette else {
+            return false;
+        };
+        let mut rng = rng::rng();
+        let colors: Vec<Vec4> = (0..self.particle_buffers.colors.len()).map(|_| palette.random_color(&mut rng)).collect();
+        self.particle_buffers.colors.overwrite(&colors, wgpu_context);
+        true
+    }
+
+    pub fn color_settings(&self) -> Option<ParticleColorSettings> {
+        self.color_kernel.as_ref().map(ParticleColorKernel::settings)
+    }
+
+    /// Submits the color passes, if enabled by `set_color_settings`. `delta_time` is the one of the last step,
+    /// the density is counted in cells of `cell_size`.
+    pub fn update_colors(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, delta_time: f32, cell_size: f32) {
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Particle color encoder") }
+        );
+        self.record_update_colors(wgpu_context, &mut encoder, gpu_profiler, delta_time, cell_size);
+        gpu_profiler.resolve_queries(&mut encoder);
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+    }
+
+    /// Records `update_colors` into `encoder` instead of submitting it.
+    pub fn record_update_colors(&mut self, wgpu_context: &WgpuContext, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler, delta_time: f32, cell_size: f32) {
+        let params = ColorParams {
+            num_particles: self.len() as u32,
+            delta_time,
+            origin: self.world_origin,
+            world_size: self.world_size,
+            cell_size,
+        };
+        if let Some(color_kernel) = self.color_kernel.as_mut() {
+            color_kernel.record(wgpu_context, encoder, gpu_profiler, &self.particle_buffers, &self.channels, params);
+        }
+    }
+
+    /// Timings of the refreshes done by the last `add_particles` call.
+    pub fn last_refresh_timings(&self) -> &[(&'static str, RefreshTiming)] {
+        &self.last_refresh_timings
+    }
+
+    pub fn channels(&self) -> &ParticleChannels {
+        &self.channels
+    }
+
+    /// One flag per particle, non-zero if the drawer should highlight it. Written by `NearestParticleQuery`.
+    pub fn highlight_flags(&self) -> &GpuBuffer<u32> {
+        &self.highlight_flags
+    }
+
+    pub fn download_extras(&mut self, wgpu_context: &WgpuContext) -> Vec<u32> {
+        self.particle_buffers.extras.download(wgpu_context).unwrap().clone()
+    }
+
+    /// Creates an independent particle system with a copy of the current GPU state.
+    /// Channels are not copied.
+    pub fn duplicate(&mut self, wgpu_context: &WgpuContext) -> ParticleSystem {
+        let buffers = self.download_particle_buffers(wgpu_context);
+        let positions = GpuBuffer::new(wgpu_context, buffers.current_positions.data().clone(), wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE);
+        let radii = GpuBuffer::new(wgpu_context, buffers.radii.data().clone(), wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE);
+        let previous_positions = buffers.previous_positions.data().clone();
+
+        let mut duplicate = ParticleSystem::new_from_buffers(wgpu_context, positions, radii);
+        duplicate.particle_buffers.previous_positions.overwrite(&previous_positions, wgpu_context);
+        duplicate.particle_buffers_copy.previous_positions.overwrite(&previous_positions, wgpu_context);
+        duplicate.set_world_size(self.world_size);
+        duplicate.set_world_origin(self.world_origin);
+        duplicate.set_forces(self.forces());
+        duplicate.set_gravity_mode(self.gravity_mode());
+        duplicate.set_boundary(self.boundary());
+        duplicate.set_boundary_material(self.boundary_material());
+        duplicate.spawn_radius_range = self.spawn_radius_range;
+        duplicate.sort_interval = self.sort_interval;
+        duplicate.sort_disorder_threshold = self.sort_disorder_threshold;
+        duplicate
+    }
+
+    pub fn world_size(&self) -> Vec2 {
+        self.world_size
+    }
+
+    pub fn set_world_size(&mut self, world_size: Vec2) {
+        self.world_size = world_size;
+        self.particle_integration.set_world_size(world_size);
+    }
+
+    /// Minimum corner of the world, (0, 0) by default. The integration keeps the particles inside
+    /// [world_origin, world_origin + world_size], e.g. `-world_size / 2` centers the world on (0, 0).
+    /// Particles outside the new bounds are pushed back in by the next step.
+    pub fn set_world_origin(&mut self, world_origin: Vec2) {
+        self.world_origin = world_origin;
+        self.particle_integration.set_world_origin(world_origin);
+    }
+
+    pub fn mouse_click_callback(&mut self, is_pressed: bool, position: Vec2){
+        self.particle_integration.mouse_click_callback(is_pressed, position);
 
     }
     pub fn mouse_move_callback(&mut self, position: Vec2){
         self.particle_integration.mouse_move_callback(position);
     }
+
+    /// Gravity, wind and drag applied to every particle by the integration, in world units.
+    pub fn set_forces(&mut self, forces: GlobalForces) {
+        self.particle_integration.set_forces(forces);
+    }
+
+    pub fn forces(&self) -> GlobalForces {
+        self.particle_integration.forces()
+    }
+
+    /// Acceleration applied to every particle by the integration, in world units per second squared.
+    /// Keeps the other `forces`.
+    pub fn set_gravity(&mut self, gravity: Vec2) {
+        self.set_forces(GlobalForces { gravity, ..self.forces() });
+    }
+
+    pub fn gravity(&self) -> Vec2 {
+        self.forces().gravity
+    }
+
+    /// Uniform (`set_gravity`) or radial gravity, in world units.
+    pub fn set_gravity_mode(&mut self, gravity_mode: GravityMode) {
+        self.particle_integration.set_gravity_mode(gravity_mode);
+    }
+
+    pub fn gravity_mode(&self) -> GravityMode {
+        self.particle_integration.gravity_mode()
+    }
+
+    /// Extra boundary inside the world rectangle, in world units.
+    pub fn set_boundary(&mut self, boundary: WorldBoundary) {
+        self.particle_integration.set_boundary(boundary);
+    }
+
+    pub fn boundary(&self) -> WorldBoundary {
+        self.particle_integration.boundary()
+    }
+
+    /// What the edges of the world rectangle do to the particles reaching them, see `BoundaryMode`.
+    pub fn set_boundary_mode(&mut self, boundary_mode: BoundaryMode) {
+        self.particle_integration.set_boundary_mode(boundary_mode);
+    }
+
+    pub fn boundary_mode(&self) -> BoundaryMode {
+        self.particle_integration.boundary_mode()
+    }
+
+    /// Response to the world boundary of the particles without a material of their own.
+    pub fn set_boundary_material(&mut self, material: BoundaryMaterial) {
+        self.particle_integration.set_boundary_material(material);
+    }
+
+    pub fn boundary_material(&self) -> BoundaryMaterial {
+        self.particle_integration.boundary_material()
+    }
+
+    /// Replaces the table of materials the `BOUNDARY_MATERIAL_CHANNEL` channel indexes, see `enable_boundary_materials`.
+    pub fn set_boundary_materials(&mut self, wgpu_context: &WgpuContext, materials: &[BoundaryMaterial]) {
+        self.particle_integration.set_materials(wgpu_context, &self.particle_buffers, materials);
+    }
+
+    pub fn boundary_materials(&self) -> &[BoundaryMaterial] {
+        self.particle_integration.materials()
+    }
+
+    /// Smallest and largest radius of the particles spawned by `add_particles`, in world units.
+    pub fn set_spawn_radius_range(&mut self, min_radius: f32, max_radius: f32) {
+        assert!(0.0 < min_radius && min_radius <= max_radius, "invalid spawn radius range {}..={}", min_radius, max_radius);
+        self.spawn_radius_range = (min_radius, max_radius);
+    }
+
+    pub fn spawn_radius_range(&self) -> (f32, f32) {
+        self.spawn_radius_range
+    }
+
+    /// The mouse tool, see `InteractionMode`.
+    pub fn interaction(&self) -> &InteractionTool {
+        self.particle_integration.interaction()
+    }
+
+    pub fn interaction_mut(&mut self) -> &mut InteractionTool {
+        self.particle_integration.interaction_mut()
+    }
     
+    /// With a sort disorder threshold, true once the last measured disorder exceeds it. Otherwise true every sort interval.
     pub fn is_it_time_to_sort(&self) -> bool {
-        self.last_sort_time.elapsed() >= SORT_INTERVAL
+        match self.sort_disorder_threshold {
+            Some(threshold) => self.sort_disorder.is_some_and(|disorder| disorder > threshold),
+            None => self.last_sort_time.elapsed() >= self.sort_interval,
+        }
     }
-    
+
+    /// Time between two sorts by cell id.
+    pub fn set_sort_interval(&mut self, sort_interval: Duration) {
+        self.sort_interval = sort_interval;
+    }
+
+    pub fn sort_interval(&self) -> Duration {
+        self.sort_interval
+    }
+
+    /// Sorts by cell id only once the fraction of neighbouring particles whose home cells are out of order
+    /// exceeds `threshold`, instead of every sort interval. None goes back to the sort interval.
+    pub fn set_sort_disorder_threshold(&mut self, threshold: Option<f32>) {
+        self.sort_disorder_threshold = threshold;
+    }
+
+    pub fn sort_disorder_threshold(&self) -> Option<f32> {
+        self.sort_disorder_threshold
+    }
+
+    /// Fraction of neighbouring particles whose home cells were out of order at the last measure, 0 after a sort.
+    /// None before the first measure.
+    pub fn sort_disorder(&self) -> Option<f32> {
+        self.sort_disorder
+    }
+
+    /// Records the measure of the sort disorder, read back by `poll_sort_disorder` a few steps later without
+    /// stalling. Ignored while the previous measure is in flight.
+    pub fn measure_sort_disorder(&mut self, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler, cell_size: f32) {
+        let (num_particles, origin) = (self.len() as u32, self.world_origin);
+        self.particle_sort.measure_disorder(encoder, gpu_profiler, num_particles, cell_size, origin);
+    }
+
+    /// Starts reading back the measure of `measure_sort_disorder`, must be called once its encoder was submitted.
+    pub fn map_sort_disorder(&mut self, wgpu_context: &WgpuContext) {
+        self.particle_sort.map_disorder(wgpu_context);
+    }
+
+    /// Updates `sort_disorder` if the measure in flight is done.
+    pub fn poll_sort_disorder(&mut self, wgpu_context: &WgpuContext) {
+        if let Some(disorder) = self.particle_sort.poll_disorder(wgpu_context) {
+            self.sort_disorder = Some(disorder);
+        }
+    }
+
+    /// Called after every sort by cell id: restarts the sort interval, and the particles are back in order.
     pub fn reset_last_sort_time(&mut self) {
         self.last_sort_time = Instant::now();
+        self.sort_disorder = Some(0.0);
+        self.particle_sort.discard_disorder();
     }
-    pub fn sort_by_cell_id(&self, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &mut GpuProfiler, cell_size: f32){
+    pub fn sort_by_cell_id(&self, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler, cell_size: f32){
         self.particle_sort.sort(
             encoder,
             gpu_profiler,
@@ -245,6 +1396,11 @@ impl ParticleSystem {
     pub fn update_positions(&mut self, delta_time:f32, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler) {
         self.particle_integration.update_positions(wgpu_context, gpu_profiler, delta_time);
     }
+
+    /// Records `update_positions` into `encoder` instead of submitting it.
+    pub fn record_update_positions(&mut self, delta_time:f32, wgpu_context: &WgpuContext, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler) {
+        self.particle_integration.record_update_positions(wgpu_context, encoder, gpu_profiler, delta_time);
+    }
     
     
     pub fn download_home_cell_ids(&mut self, wgpu_context: &WgpuContext) -> Vec<u32>{
@@ -261,6 +1417,7 @@ impl ParticleSystem {
         let _ = self.particle_buffers.previous_positions.download(wgpu_context);
         let _ = self.particle_buffers.colors.download(wgpu_context);
         let _ = self.particle_buffers.home_cell_ids.download(wgpu_context);
+        let _ = self.particle_buffers.extras.download(wgpu_context);
         &self.particle_buffers
     }
 
@@ -276,6 +1433,11 @@ impl ParticleSystem {
         self.buffers().current_positions.len()
     }
 
+    /// Reads back the positions of the particles in `range` right away.
+    pub fn download_positions(&mut self, wgpu_context: &WgpuContext, range: Range<usize>) -> &[Vec2] {
+        self.particle_buffers.current_positions.download_range(wgpu_context, range).unwrap()
+    }
+
     pub fn positions(&self) -> &GpuBuffer<Vec2>{
         &self.buffers().current_positions
     }
@@ -288,6 +1450,14 @@ impl ParticleSystem {
         self.buffers().colors.data()
     }
 
+    pub fn get_world_size(&self) -> Vec2 {
+        self.world_size
+    }
+
+    pub fn get_world_origin(&self) -> Vec2 {
+        self.world_origin
+    }
+
     pub fn get_max_radius(&self) -> f32 {
         self.max_radius
     }
@@ -295,10 +1465,20 @@ impl ParticleSystem {
 }
 
 
-
+#[cfg(feature = "windowing")]
 impl Renderable for ParticleSystem {
     fn draw(&self, render_pass: &mut wgpu::RenderPass, camera: &Camera){
-        self.particle_drawer.as_ref().expect("Particle drawer null").draw(render_pass, camera, self.len() as u32);
+        let sprite_frame_offset = self.channels.find(SPRITE_FRAME_CHANNEL).map(|id| self.channels.offset(id));
+        if let (Some(trails), Some(trail_drawer)) = (self.trails.as_ref(), self.trail_drawer.as_ref()) {
+            trail_drawer.draw(render_pass, camera, trails.length(), trails.head(), trails.num_slots());
+        }
+        self.particle_drawer.as_ref().expect("Particle drawer null").draw(render_pass, camera, self.channels.stride(), sprite_frame_offset);
     }
 
 }
+
+/// Last sort time that makes the first step sort.
+fn first_sort_time(sort_interval: Duration) -> Instant {
+    let now = Instant::now();
+    now.checked_sub(sort_interval).unwrap_or(now)
+}
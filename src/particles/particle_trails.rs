@@ -0,0 +1,206 @@
+use glam::Vec2;
+use wgpu::{BindGroupLayout, CommandEncoder, PushConstantRange};
+use crate::particles::particle_buffers::ParticleBuffers;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::bind_resources::BindResources;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::profiler::GpuProfiler;
+
+pub const WORKGROUP_SIZE: u32 = 64;
+
+/// Channel with the index of a particle's ring in the trail history, see `ParticleSystem::set_trails`.
+pub const TRAIL_SLOT_CHANNEL: &str = "trail_slot";
+/// Trail slot of the particles without a trail.
+pub const NO_TRAIL: u32 = u32::MAX;
+/// Positions kept per particle by the `M` key, about a quarter of a second at 60 frames per second.
+pub const DEFAULT_TRAIL_LENGTH: u32 = 16;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstants {
+    num_particles: u32,
+    extras_stride: u32,
+    slot_offset: u32,
+    trail_length: u32,
+    head: u32,
+    reset: u32,
+    num_slots: u32,
+}
+
+/// Where the trail slot is in the extras of a particle.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct TrailChannelOffsets {
+    pub extras_stride: u32,
+    pub slot_offset: u32,
+}
+
+/// Keeps the last `length` positions of every particle in a ring buffer per particle, the history, written
+/// once per frame by a compute pass. Each particle owns a slot of the history, stored in its `TRAIL_SLOT_CHANNEL`
+/// so it follows the particle through the sorts. The slots are handed out again, and the rings restarted,
+/// whenever the number of particles changes.
+pub(crate) struct ParticleTrails {
+    shader: ComputeShader,
+    bind_resources: BindResources,
+    history: GpuBuffer<Vec2>,
+    length: u32,
+    /// Rings in use, the particles past it have no trail
+    num_slots: u32,
+    /// Particles when the slots were handed out, `None` until the first update
+    tracked_particles: Option<usize>,
+    /// Entry of the rings written last
+    head: u32,
+}
+
+impl ParticleTrails {
+    pub fn new(wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers, length: u32) -> Self {
+        assert!(length >= 2, "a trail needs at least 2 positions, got {}", length);
+        let history = GpuBuffer::new(wgpu_context, vec![Vec2::ZERO; length as usize], wgpu::BufferUsages::STORAGE);
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let bind_group = Self::create_bind_group(wgpu_context, &bind_group_layout, particle_buffers, &history);
+        let bind_resources = BindResources::new(bind_group_layout, bind_group);
+
+        let shader = ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("particle_trails.wgsl"),
+            "record_trails",
+            &bind_resources.bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange {
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstants>() as u32,
+                }
+            ]
+        );
+
+        Self {
+            shader,
+            bind_resources,
+            history,
+            length,
+            num_slots: 0,
+            tracked_particles: None,
+            head: 0,
+        }
+    }
+
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    #[cfg(feature = "windowing")]
+    pub fn head(&self) -> u32 {
+        self.head
+    }
+
+    pub fn num_slots(&self) -> u32 {
+        self.num_slots
+    }
+
+    /// `length` positions per slot, read by the trail drawer.
+    #[cfg(feature = "windowing")]
+    pub fn history(&self) -> &GpuBuffer<Vec2> {
+        &self.history
+    }
+
+    /// Follows the particle buffers after they grew or the extras were replaced.
+    pub fn refresh(&mut self, wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers) {
+        self.bind_resources.bind_group = Self::create_bind_group(wgpu_context, &self.bind_resources.bind_group_layout, particle_buffers, &self.history);
+    }
+
+    /// Records the pass that writes the current positions into the history. Returns true if the history buffer
+    /// was recreated, the trail drawer must then be rebound.
+    pub fn record(&mut self, wgpu_context: &WgpuContext, encoder: &mut CommandEncoder, gpu_profiler: &GpuProfiler, particle_buffers: &ParticleBuffers, num_particles: usize, offsets: TrailChannelOffsets) -> bool {
+        let reset = self.tracked_particles != Some(num_particles);
+        let mut recreated = false;
+        if reset {
+            // One buffer of the device holds the whole history
+            let max_slots = wgpu_context.capabilities().max_buffer_size / (self.length as u64 * size_of::<Vec2>() as u64);
+            self.num_slots = (num_particles as u64).min(max_slots) as u32;
+            self.tracked_particles = Some(num_particles);
+            self.head = 0;
+            let history_len = self.num_slots as usize * self.length as usize;
+            if history_len > self.history.len() {
+                self.history = GpuBuffer::new(wgpu_context, vec![Vec2::ZERO; history_len], wgpu::BufferUsages::STORAGE);
+                self.refresh(wgpu_context, particle_buffers);
+                recreated = true;
+            }
+        }
+        else {
+            self.head = (self.head + 1) % self.length;
+        }
+        if num_particles == 0 {
+            return recreated;
+        }
+
+        let push_constants = PushConstants {
+            num_particles: num_particles as u32,
+            extras_stride: offsets.extras_stride,
+            slot_offset: offsets.slot_offset,
+            trail_length: self.length,
+            head: self.head,
+            reset: reset as u32,
+            num_slots: self.num_slots,
+        };
+        {
+            let mut scope = gpu_profiler.scope("Particle trails", encoder);
+            self.shader.dispatch_by_items(
+                &mut scope,
+                (num_particles as u32, 1, 1),
+                Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                &self.bind_resources.bind_group
+            );
+        }
+        recreated
+    }
+
+    /// Reads back the ring of `slot`, the newest position first.
+    pub fn download_slot(&mut self, wgpu_context: &WgpuContext, slot: u32) -> Vec<Vec2> {
+        assert!(slot < self.num_slots, "No trail slot {}", slot);
+        let length = self.length as usize;
+        let start = slot as usize * length;
+        let ring = self.history.download_range(wgpu_context, start..start + length).unwrap();
+        (0..length).map(|age| ring[(self.head as usize + length - age) % length]).collect()
+    }
+
+    fn create_bind_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, particle_buffers: &ParticleBuffers, history: &GpuBuffer<Vec2>) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Particle trails bind group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: particle_buffers.current_positions.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: particle_buffers.extras.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: history.buffer().as_entire_binding() },
+            ],
+        })
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Particle trails bind group layout"),
+            entries: &[
+                // Positions
+                storage_entry(0, true),
+                // Channels
+                storage_entry(1, false),
+                // History
+                storage_entry(2, false),
+            ],
+        })
+    }
+}
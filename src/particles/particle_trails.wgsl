@@ -0,0 +1,57 @@
+override WORKGROUP_SIZE = 64u;
+
+// Must match NO_TRAIL
+const NO_TRAIL = 0xffffffffu;
+
+struct PushConstantsData {
+    num_particles: u32,
+    extras_stride: u32,
+    // Offset of the trail slot channel in the extras of a particle
+    slot_offset: u32,
+    // Positions kept per trail
+    trail_length: u32,
+    // Entry of every ring written by this pass
+    head: u32,
+    // Non-zero to give every particle a new slot and fill its whole ring with its current position
+    reset: u32,
+    // Particles with an index past this one get no trail
+    num_slots: u32,
+}
+
+@group(0) @binding(0) var<storage, read> positions: array<vec2<f32>>;
+@group(0) @binding(1) var<storage, read_write> extras: array<u32>;
+// trail_length entries per slot, a ring indexed from head
+@group(0) @binding(2) var<storage, read_write> history: array<vec2<f32>>;
+
+var<push_constant> push_constants: PushConstantsData;
+
+// Writes the position of every particle into the ring of its slot. The slot is a channel, so it follows
+// the particle through the sorts.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn record_trails(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= push_constants.num_particles {
+        return;
+    }
+    let position = positions[index];
+    let slot_word = index * push_constants.extras_stride + push_constants.slot_offset;
+
+    if push_constants.reset != 0u {
+        if index >= push_constants.num_slots {
+            extras[slot_word] = NO_TRAIL;
+            return;
+        }
+        extras[slot_word] = index;
+        let start = index * push_constants.trail_length;
+        for (var i = 0u; i < push_constants.trail_length; i++) {
+            history[start + i] = position;
+        }
+        return;
+    }
+
+    let slot = extras[slot_word];
+    if slot >= push_constants.num_slots {
+        return;
+    }
+    history[slot * push_constants.trail_length + push_constants.head] = position;
+}
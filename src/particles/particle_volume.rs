@@ -0,0 +1,60 @@
+use glam::{Vec3, Vec4};
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::gpu_buffer::GpuBuffer;
+
+/// Particles of a volumetric (3D) system: positions and radii on the GPU, ready to be binned by `Grid::new_3d`.
+/// Positions are stored as `vec4<f32>` (w unused), since `vec3<f32>` arrays have a 16 byte stride in WGSL.
+/// Only the broad phase supports 3D so far; integration, the collision solver and the drawer work on `ParticleSystem`.
+pub struct ParticleVolume {
+    positions: GpuBuffer<Vec4>,
+    radii: GpuBuffer<f32>,
+    max_radius: f32,
+}
+
+impl ParticleVolume {
+    pub fn new(wgpu_context: &WgpuContext, positions: &[Vec3], radii: Vec<f32>) -> Self {
+        assert_eq!(positions.len(), radii.len(), "Every particle needs a radius");
+        let max_radius = radii.iter().copied().fold(0.0, f32::max);
+        Self {
+            positions: GpuBuffer::new(wgpu_context, Self::padded(positions), wgpu::BufferUsages::STORAGE),
+            radii: GpuBuffer::new(wgpu_context, radii, wgpu::BufferUsages::STORAGE),
+            max_radius,
+        }
+    }
+
+    fn padded(positions: &[Vec3]) -> Vec<Vec4> {
+        positions.iter().map(|position| position.extend(0.0)).collect()
+    }
+
+    /// Adds particles. The grid must be refreshed with `Grid::refresh_grid_3d` afterwards.
+    pub fn push_all(&mut self, wgpu_context: &WgpuContext, positions: &[Vec3], radii: &[f32]) {
+        assert_eq!(positions.len(), radii.len(), "Every particle needs a radius");
+        self.positions.push_all(&Self::padded(positions), wgpu_context);
+        self.radii.push_all(radii, wgpu_context);
+        self.max_radius = radii.iter().copied().fold(self.max_radius, f32::max);
+    }
+
+    pub fn len(&self) -> usize {
+        self.radii.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get_max_radius(&self) -> f32 {
+        self.max_radius
+    }
+
+    pub fn positions(&self) -> &GpuBuffer<Vec4> {
+        &self.positions
+    }
+
+    pub fn radii(&self) -> &GpuBuffer<f32> {
+        &self.radii
+    }
+
+    pub fn download_positions(&self, wgpu_context: &WgpuContext) -> Vec<Vec3> {
+        self.positions.read_back(wgpu_context).unwrap().iter().map(|position| position.truncate()).collect()
+    }
+}
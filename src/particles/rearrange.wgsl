@@ -3,6 +3,7 @@ override WORKGROUP_SIZE = 64u;
 
 struct PushConstantsData{
     num_particles: u32, 
+    extras_stride: u32,
 }
 
 @group(0) @binding(0) var<storage, read> positions_read: array<vec2<f32>>;
@@ -12,6 +13,8 @@ struct PushConstantsData{
 @group(0) @binding(4) var<storage, read_write> positions_write: array<vec2<f32>>;
 @group(0) @binding(5) var<storage, read_write> radius_write: array<f32>;
 @group(0) @binding(6) var<storage, read_write> previous_positions_write: array<vec2<f32>>;
+@group(0) @binding(7) var<storage, read> extras_read: array<u32>;
+@group(0) @binding(8) var<storage, read_write> extras_write: array<u32>;
 
 var<push_constant> push_constant_data: PushConstantsData;
 
@@ -32,4 +35,10 @@ fn rearrange(@builtin(global_invocation_id) global_id: vec3<u32>){
     positions_write[obj_id] = position; 
     radius_write[obj_id] = radius; 
     previous_positions_write[obj_id] = prev_position; 
+
+    // Move every channel word of the particle, whatever channels are registered
+    let stride = push_constant_data.extras_stride;
+    for (var i = 0u; i < stride; i++) {
+        extras_write[obj_id * stride + i] = extras_read[reading_idx * stride + i];
+    }
 }
@@ -0,0 +1,325 @@
+use glam::Vec2;
+use wgpu::{BindGroupLayout, PushConstantRange};
+use crate::particles::particle_system::ParticleSystem;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::profiler::GpuProfiler;
+use crate::utils::readback_queue::{ReadbackId, ReadbackQueue};
+
+pub const WORKGROUP_SIZE: u32 = 256;
+/// Most regions a layout can have, the sums of a workgroup live in workgroup memory.
+/// Must match MAX_REGIONS of region_energy.wgsl
+pub const MAX_REGIONS: usize = 1024;
+
+/// Coarse grid of regions over the world, independent of the collision grid. Region (column, row)
+/// covers `origin + [column, row] * region_size` to the next corner, rows go up.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RegionGridLayout {
+    pub origin: Vec2,
+    pub region_size: Vec2,
+    pub columns: u32,
+    pub rows: u32,
+}
+
+impl RegionGridLayout {
+    /// Splits the rectangle starting at `origin` into `columns` x `rows` regions.
+    pub fn covering(origin: Vec2, size: Vec2, columns: u32, rows: u32) -> Self {
+        let columns = columns.max(1);
+        let rows = rows.max(1);
+        Self {
+            origin,
+            region_size: size / Vec2::new(columns as f32, rows as f32),
+            columns,
+            rows,
+        }
+    }
+
+    pub fn num_regions(&self) -> usize {
+        self.columns as usize * self.rows as usize
+    }
+
+    /// Index of the region containing `position`, row major, `None` outside of the regions.
+    pub fn region_of(&self, position: Vec2) -> Option<usize> {
+        let cell = ((position - self.origin) / self.region_size).floor();
+        if cell.x < 0.0 || cell.y < 0.0 || cell.x >= self.columns as f32 || cell.y >= self.rows as f32 {
+            return None;
+        }
+        Some(cell.y as usize * self.columns as usize + cell.x as usize)
+    }
+
+    /// Center of the region at `index`.
+    pub fn region_center(&self, index: usize) -> Vec2 {
+        let column = (index % self.columns as usize) as f32;
+        let row = (index / self.columns as usize) as f32;
+        self.origin + (Vec2::new(column, row) + 0.5) * self.region_size
+    }
+}
+
+/// Averages of the particles of a region. The energy is per unit of mass, `0.5 * speed²`.
+/// Must match RegionEnergy of region_energy.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct RegionEnergy {
+    pub particles: u32,
+    pub mean_speed: f32,
+    pub mean_energy: f32,
+    _padding: u32,
+}
+
+/// Averages of every region of a layout, row major.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegionEnergyMap {
+    pub layout: RegionGridLayout,
+    pub regions: Vec<RegionEnergy>,
+}
+
+impl RegionEnergyMap {
+    pub fn region(&self, column: u32, row: u32) -> Option<&RegionEnergy> {
+        if column >= self.layout.columns || row >= self.layout.rows {
+            return None;
+        }
+        self.regions.get((row * self.layout.columns + column) as usize)
+    }
+
+    /// Particles inside the regions.
+    pub fn total_particles(&self) -> u32 {
+        self.regions.iter().map(|region| region.particles).sum()
+    }
+
+    /// Average energy of all the particles inside the regions.
+    pub fn mean_energy(&self) -> f32 {
+        let total_particles = self.total_particles();
+        if total_particles == 0 {
+            return 0.0;
+        }
+        let total_energy: f32 = self.regions.iter().map(|region| region.mean_energy * region.particles as f32).sum();
+        total_energy / total_particles as f32
+    }
+
+    /// Index and averages of the region with the highest mean energy, `None` if all are empty.
+    pub fn hottest_region(&self) -> Option<(usize, &RegionEnergy)> {
+        self.regions.iter().enumerate()
+            .filter(|(_, region)| region.particles > 0)
+            .max_by(|(_, a), (_, b)| a.mean_energy.total_cmp(&b.mean_energy))
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstants {
+    num_particles: u32,
+    columns: u32,
+    rows: u32,
+    inv_delta_time: f32,
+    origin: Vec2,
+    region_size: Vec2,
+}
+
+/// Average speed and kinetic energy of the particles in each region of a coarse grid, to follow how
+/// energy travels through granular media. The speeds come from the last step, like the speed colors.
+/// Each workgroup sums its particles in workgroup memory and adds one value per region to the global
+/// sums, a second pass turns them into the averages of `regions`, a small buffer the overlays can bind.
+/// Read back like `ReadbackQueue`, call `poll` once per frame.
+pub struct RegionEnergyQuery {
+    accumulate_shader: ComputeShader,
+    finalize_shader: ComputeShader,
+    bind_group_layout: BindGroupLayout,
+    layout: RegionGridLayout,
+    accumulators: GpuBuffer<u32>, // Count, speed sum and energy sum of every region
+    regions: GpuBuffer<RegionEnergy>,
+    readbacks: ReadbackQueue,
+    requested: Vec<(ReadbackId, RegionGridLayout)>,
+    last_map: Option<RegionEnergyMap>,
+}
+
+impl RegionEnergyQuery {
+    /// Panics if the layout has more than `MAX_REGIONS` regions.
+    pub fn new(wgpu_context: &WgpuContext, layout: RegionGridLayout) -> Self {
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let create_shader = |entry_point: &str| ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("region_energy.wgsl"),
+            entry_point,
+            &bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange {
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstants>() as u32,
+                }
+            ]
+        );
+        let accumulate_shader = create_shader("accumulate_region_energy");
+        let finalize_shader = create_shader("finalize_region_energy");
+        let (accumulators, regions) = Self::create_buffers(wgpu_context, &layout);
+
+        Self {
+            accumulate_shader,
+            finalize_shader,
+            bind_group_layout,
+            layout,
+            accumulators,
+            regions,
+            readbacks: ReadbackQueue::new(),
+            requested: Vec::new(),
+            last_map: None,
+        }
+    }
+
+    pub fn layout(&self) -> &RegionGridLayout {
+        &self.layout
+    }
+
+    /// Replaces the regions. Readbacks in flight keep the layout they were requested with.
+    pub fn set_layout(&mut self, wgpu_context: &WgpuContext, layout: RegionGridLayout) {
+        (self.accumulators, self.regions) = Self::create_buffers(wgpu_context, &layout);
+        self.layout = layout;
+    }
+
+    /// Averages of the last `measure`, one per region, row major. Kept on the GPU for the overlays.
+    pub fn regions(&self) -> &GpuBuffer<RegionEnergy> {
+        &self.regions
+    }
+
+    fn create_buffers(wgpu_context: &WgpuContext, layout: &RegionGridLayout) -> (GpuBuffer<u32>, GpuBuffer<RegionEnergy>) {
+        assert!(layout.num_regions() <= MAX_REGIONS, "{} regions, at most {} are supported", layout.num_regions(), MAX_REGIONS);
+        let accumulators = GpuBuffer::new(wgpu_context, vec![0u32; 3 * layout.num_regions()], wgpu::BufferUsages::STORAGE);
+        let regions = GpuBuffer::new(wgpu_context, vec![RegionEnergy::default(); layout.num_regions()], wgpu::BufferUsages::STORAGE);
+        (accumulators, regions)
+    }
+
+    /// Submits the passes computing the averages of every region. `delta_time` is the one of the last step.
+    /// Bound to the particle buffers on every call, so it can run at any time after a step.
+    pub fn measure(&self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, particle_system: &ParticleSystem, delta_time: f32) {
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Region energy encoder") }
+        );
+        self.record_measure(wgpu_context, &mut encoder, gpu_profiler, particle_system, delta_time);
+        gpu_profiler.resolve_queries(&mut encoder);
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+    }
+
+    /// `measure` recorded into `encoder`, after the steps. Leaves the profiler queries to the owner of the encoder.
+    pub fn record_measure(&self, wgpu_context: &WgpuContext, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler, particle_system: &ParticleSystem, delta_time: f32) {
+        let num_particles = particle_system.len() as u32;
+        let push_constants = PushConstants {
+            num_particles,
+            columns: self.layout.columns,
+            rows: self.layout.rows,
+            inv_delta_time: if delta_time > 0.0 { 1.0 / delta_time } else { 0.0 },
+            origin: self.layout.origin,
+            region_size: self.layout.region_size,
+        };
+        let bind_group = self.create_bind_group(wgpu_context, particle_system);
+
+        encoder.clear_buffer(self.accumulators.buffer(), 0, None);
+        {
+            let mut scope = gpu_profiler.scope("Region energy", encoder);
+            if num_particles > 0 {
+                self.accumulate_shader.dispatch_by_items(
+                    &mut scope,
+                    (num_particles, 1, 1),
+                    Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                    &bind_group
+                );
+            }
+            self.finalize_shader.dispatch_by_items(
+                &mut scope,
+                (self.layout.num_regions() as u32, 1, 1),
+                Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                &bind_group
+            );
+        }
+    }
+
+    /// Schedules the readback of the last `measure`, without blocking.
+    pub fn request(&mut self, wgpu_context: &WgpuContext) {
+        let id = self.readbacks.request(wgpu_context, "Region energy readback", &self.regions, 0..self.layout.num_regions());
+        self.requested.push((id, self.layout));
+    }
+
+    /// `request` recorded into `encoder`, after `record_measure`. `after_submit` must follow the submit of the encoder.
+    pub fn record_request(&mut self, wgpu_context: &WgpuContext, encoder: &mut wgpu::CommandEncoder) {
+        let id = self.readbacks.record_request(wgpu_context, encoder, "Region energy readback", &self.regions, 0..self.layout.num_regions());
+        self.requested.push((id, self.layout));
+    }
+
+    /// Starts the readbacks recorded by `record_request`.
+    pub fn after_submit(&mut self) {
+        self.readbacks.after_submit();
+    }
+
+    /// Checks the readbacks in flight, without blocking. Returns the newest map that arrived since the
+    /// last call, if any; `last_map` keeps it afterwards.
+    pub fn poll(&mut self, wgpu_context: &WgpuContext) -> Option<RegionEnergyMap> {
+        let mut arrived = None;
+        for readback in self.readbacks.poll(wgpu_context) {
+            let Some(position) = self.requested.iter().position(|(id, _)| *id == readback.id) else {
+                continue;
+            };
+            let (_, layout) = self.requested.remove(position);
+            arrived = Some(RegionEnergyMap { layout, regions: readback.to_vec() });
+        }
+        if arrived.is_some() {
+            self.last_map = arrived.clone();
+        }
+        arrived
+    }
+
+    /// Latest map returned by `poll`.
+    pub fn last_map(&self) -> Option<&RegionEnergyMap> {
+        self.last_map.as_ref()
+    }
+
+    /// Reads the averages of the last `measure`, blocking until the GPU is done. For tests and tools.
+    pub fn read(&self, wgpu_context: &WgpuContext) -> Result<RegionEnergyMap, wgpu::BufferAsyncError> {
+        Ok(RegionEnergyMap {
+            layout: self.layout,
+            regions: self.regions.read_back(wgpu_context)?,
+        })
+    }
+
+    fn create_bind_group(&self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Region energy bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: particle_system.positions().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: particle_system.buffers().previous_positions.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: self.accumulators.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 3, resource: self.regions.buffer().as_entire_binding() },
+            ],
+        })
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Region energy bind group layout"),
+            entries: &[
+                // Positions
+                storage_entry(0, true),
+                // Previous positions
+                storage_entry(1, true),
+                // Accumulators
+                storage_entry(2, false),
+                // Regions
+                storage_entry(3, false),
+            ],
+        })
+    }
+}
@@ -0,0 +1,113 @@
+override WORKGROUP_SIZE = 256u;
+
+// Must match MAX_REGIONS
+const MAX_REGIONS = 1024u;
+
+struct PushConstantsData {
+    num_particles: u32,
+    columns: u32,
+    rows: u32,
+    inv_delta_time: f32,
+    // World position of the corner of region (0, 0)
+    origin: vec2<f32>,
+    region_size: vec2<f32>,
+}
+
+// Must match RegionEnergy
+struct RegionEnergy {
+    particles: u32,
+    mean_speed: f32,
+    mean_energy: f32,
+    _padding: u32,
+}
+
+@group(0) @binding(0) var<storage, read> positions: array<vec2<f32>>;
+@group(0) @binding(1) var<storage, read> previous_positions: array<vec2<f32>>;
+// Per region: particle count, then the speed and energy sums as f32 bits. Cleared before every measure
+@group(0) @binding(2) var<storage, read_write> accumulators: array<atomic<u32>>;
+@group(0) @binding(3) var<storage, read_write> regions: array<RegionEnergy>;
+
+var<push_constant> push_constants: PushConstantsData;
+
+// Sums of the particles of the workgroup, merged into the accumulators once per region instead of once per particle
+var<workgroup> workgroup_counts: array<atomic<u32>, MAX_REGIONS>;
+// Speed and energy sums of region i at 2i and 2i + 1, as f32 bits
+var<workgroup> workgroup_sums: array<atomic<u32>, 2u * MAX_REGIONS>;
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn accumulate_region_energy(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    let num_regions = push_constants.columns * push_constants.rows;
+    for (var region = local_index; region < num_regions; region += WORKGROUP_SIZE) {
+        atomicStore(&workgroup_counts[region], 0u);
+        atomicStore(&workgroup_sums[2u * region], 0u);
+        atomicStore(&workgroup_sums[2u * region + 1u], 0u);
+    }
+    workgroupBarrier();
+
+    let index = global_id.x;
+    if index < push_constants.num_particles {
+        let position = positions[index];
+        let cell = floor((position - push_constants.origin) / push_constants.region_size);
+        // Particles outside of the regions, including the removed ones, are not counted
+        if all(cell >= vec2<f32>(0.0)) && cell.x < f32(push_constants.columns) && cell.y < f32(push_constants.rows) {
+            let region = u32(cell.y) * push_constants.columns + u32(cell.x);
+            let speed = length(position - previous_positions[index]) * push_constants.inv_delta_time;
+            atomicAdd(&workgroup_counts[region], 1u);
+            add_workgroup_sum(2u * region, speed);
+            add_workgroup_sum(2u * region + 1u, 0.5 * speed * speed);
+        }
+    }
+    workgroupBarrier();
+
+    for (var region = local_index; region < num_regions; region += WORKGROUP_SIZE) {
+        let count = atomicLoad(&workgroup_counts[region]);
+        if count == 0u {
+            continue;
+        }
+        atomicAdd(&accumulators[3u * region], count);
+        add_global_sum(3u * region + 1u, bitcast<f32>(atomicLoad(&workgroup_sums[2u * region])));
+        add_global_sum(3u * region + 2u, bitcast<f32>(atomicLoad(&workgroup_sums[2u * region + 1u])));
+    }
+}
+
+// Turns the sums of every region into averages
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn finalize_region_energy(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let region = global_id.x;
+    if region >= push_constants.columns * push_constants.rows {
+        return;
+    }
+    let count = atomicLoad(&accumulators[3u * region]);
+    var result = RegionEnergy(count, 0.0, 0.0, 0u);
+    if count > 0u {
+        result.mean_speed = bitcast<f32>(atomicLoad(&accumulators[3u * region + 1u])) / f32(count);
+        result.mean_energy = bitcast<f32>(atomicLoad(&accumulators[3u * region + 2u])) / f32(count);
+    }
+    regions[region] = result;
+}
+
+// Float atomic add, WGSL atomics only hold integers
+fn add_workgroup_sum(index: u32, value: f32) {
+    var old = atomicLoad(&workgroup_sums[index]);
+    loop {
+        let result = atomicCompareExchangeWeak(&workgroup_sums[index], old, bitcast<u32>(bitcast<f32>(old) + value));
+        if result.exchanged {
+            break;
+        }
+        old = result.old_value;
+    }
+}
+
+fn add_global_sum(index: u32, value: f32) {
+    var old = atomicLoad(&accumulators[index]);
+    loop {
+        let result = atomicCompareExchangeWeak(&accumulators[index], old, bitcast<u32>(bitcast<f32>(old) + value));
+        if result.exchanged {
+            break;
+        }
+        old = result.old_value;
+    }
+}
@@ -0,0 +1,235 @@
+use glam::{UVec2, Vec2};
+use wgpu::{BindGroupLayout, CommandEncoder, PushConstantRange};
+use crate::grid::grid::Grid;
+use crate::grid::morton;
+use crate::particles::particle_system::ParticleSystem;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+
+pub const WORKGROUP_SIZE: u32 = 64;
+/// Largest cell coordinate a 2D morton cell id holds.
+const MAX_CELL_COORD: u32 = 0xFFFF;
+
+/// Region of the world to find the particles in, in world coordinates. A particle is inside when its center is.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum QueryShape {
+    Circle { center: Vec2, radius: f32 },
+    /// Axis aligned rectangle, corners included.
+    Rect { min: Vec2, max: Vec2 },
+}
+
+impl QueryShape {
+    /// Rectangle between two opposite corners, in any order.
+    pub fn rect(corner: Vec2, opposite_corner: Vec2) -> Self {
+        QueryShape::Rect { min: corner.min(opposite_corner), max: corner.max(opposite_corner) }
+    }
+
+    /// Bounding box of the shape.
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        match *self {
+            QueryShape::Circle { center, radius } => (center - Vec2::splat(radius), center + Vec2::splat(radius)),
+            QueryShape::Rect { min, max } => (min, max),
+        }
+    }
+
+    /// Same test as region_query.wgsl.
+    pub fn contains(&self, position: Vec2) -> bool {
+        match *self {
+            QueryShape::Circle { center, radius } => position.distance_squared(center) <= radius * radius,
+            QueryShape::Rect { min, max } => position.cmpge(min).all() && position.cmple(max).all(),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstants {
+    center: Vec2,
+    radius: f32,
+    shape: u32,
+    rect_min: Vec2,
+    rect_max: Vec2,
+    origin: Vec2,
+    cell_size: f32,
+    total_cell_ids: u32,
+    first_cell: UVec2,
+    cell_counts: UVec2,
+    num_particles: u32,
+    _padding: u32,
+}
+
+/// Finds the particles inside a circle or a rectangle with the grid: one thread per cell around the shape
+/// binary searches its run in the sorted cell ids, tests the particles of the run and appends the ones inside
+/// to a compact list on the GPU, which `query` reads back.
+///
+/// The cells are the ones of the last grid update, i.e. the last step. The cells are widened by one around the
+/// shape, so particles that moved less than a cell since are still found; particles spawned since are not,
+/// nor is the query exact with `Grid::set_cell_compaction`, which leaves stale ids past the used ones.
+pub struct RegionQuery {
+    query_shader: ComputeShader,
+    bind_group_layout: BindGroupLayout,
+    visited: GpuBuffer<u32>,
+    result: GpuBuffer<u32>,
+    found_ids: GpuBuffer<u32>,
+}
+
+impl RegionQuery {
+    pub fn new(wgpu_context: &WgpuContext) -> Self {
+        let visited = GpuBuffer::new(wgpu_context, vec![0u32], wgpu::BufferUsages::STORAGE);
+        let result = GpuBuffer::new(wgpu_context, vec![0u32], wgpu::BufferUsages::STORAGE);
+        let found_ids = GpuBuffer::new(wgpu_context, vec![0u32], wgpu::BufferUsages::STORAGE);
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let query_shader = ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("region_query.wgsl"),
+            "query_region",
+            &bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange{
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstants>() as u32,
+                }
+            ]
+        );
+
+        Self {
+            query_shader,
+            bind_group_layout,
+            visited,
+            result,
+            found_ids,
+        }
+    }
+
+    /// Number of particles found by the last recorded query, followed by nothing else. Stays on the GPU.
+    pub fn result(&self) -> &GpuBuffer<u32> {
+        &self.result
+    }
+
+    /// Ids found by the last recorded query, the first `result()[0]` are valid, in no particular order.
+    pub fn found_ids(&self) -> &GpuBuffer<u32> {
+        &self.found_ids
+    }
+
+    /// Records the query of `shape` into `encoder`, leaving the ids on the GPU for other passes.
+    pub fn record(&mut self, wgpu_context: &WgpuContext, encoder: &mut CommandEncoder, particle_system: &ParticleSystem, grid: &Grid, shape: &QueryShape) {
+        let num_particles = particle_system.len();
+        if self.found_ids.len() < num_particles {
+            self.visited.push_all(&vec![0u32; num_particles - self.visited.len()], wgpu_context);
+            self.found_ids.push_all(&vec![0u32; num_particles - self.found_ids.len()], wgpu_context);
+        }
+        encoder.clear_buffer(self.visited.buffer(), 0, None);
+        encoder.clear_buffer(self.result.buffer(), 0, None);
+        if num_particles == 0 {
+            return;
+        }
+
+        // One more cell on every side, for the particles that left their cells since the grid update
+        let (min, max) = shape.bounds();
+        let first_cell = morton::cell_coord(min - grid.origin(), grid.cell_size()).saturating_sub(UVec2::ONE);
+        let last_cell = (morton::cell_coord(max - grid.origin(), grid.cell_size()) + UVec2::ONE).min(UVec2::splat(MAX_CELL_COORD));
+        let cell_counts = (last_cell + UVec2::ONE).saturating_sub(first_cell);
+        let (center, radius, rect_min, rect_max) = match *shape {
+            QueryShape::Circle { center, radius } => (center, radius, Vec2::ZERO, Vec2::ZERO),
+            QueryShape::Rect { min, max } => (Vec2::ZERO, 0.0, min, max),
+        };
+        let push_constants = PushConstants {
+            center,
+            radius,
+            shape: matches!(shape, QueryShape::Rect { .. }) as u32,
+            rect_min,
+            rect_max,
+            origin: grid.origin(),
+            cell_size: grid.cell_size(),
+            total_cell_ids: grid.cell_ids().len() as u32,
+            first_cell,
+            cell_counts,
+            num_particles: num_particles as u32,
+            _padding: 0,
+        };
+        // Bound on every query, the particle and grid buffers may have been replaced since the last one
+        let bind_group = self.create_bind_group(wgpu_context, particle_system, grid);
+        self.query_shader.dispatch_by_items(
+            encoder,
+            (cell_counts.x * cell_counts.y, 1, 1),
+            Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+            &bind_group
+        );
+    }
+
+    /// Indices of the particles inside `shape`, in increasing order. Stalls until the GPU is done.
+    /// Indices change when the particles are sorted or removed, use them right away.
+    pub fn query(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid, shape: &QueryShape) -> Vec<u32> {
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Region query encoder") }
+        );
+        self.record(wgpu_context, &mut encoder, particle_system, grid, shape);
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+        if particle_system.len() == 0 {
+            return Vec::new();
+        }
+
+        let num_found = self.result.read_back(wgpu_context).unwrap()[0] as usize;
+        if num_found == 0 {
+            return Vec::new();
+        }
+        let mut found = self.found_ids.read_back(wgpu_context).unwrap();
+        found.truncate(num_found);
+        // Appended in no particular order
+        found.sort_unstable();
+        found
+    }
+
+    fn create_bind_group(&self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                label: Some("Region query bind group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry { binding: 0, resource: grid.cell_ids().buffer().as_entire_binding() },
+                    wgpu::BindGroupEntry { binding: 1, resource: grid.object_ids().buffer().as_entire_binding() },
+                    wgpu::BindGroupEntry { binding: 2, resource: particle_system.buffers().current_positions.buffer().as_entire_binding() },
+                    wgpu::BindGroupEntry { binding: 3, resource: self.visited.buffer().as_entire_binding() },
+                    wgpu::BindGroupEntry { binding: 4, resource: self.result.buffer().as_entire_binding() },
+                    wgpu::BindGroupEntry { binding: 5, resource: self.found_ids.buffer().as_entire_binding() },
+                ],
+            }
+        )
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Region query bind group layout"),
+            entries: &[
+                // Cell ids
+                storage_entry(0, true),
+                // Object ids
+                storage_entry(1, true),
+                // Positions
+                storage_entry(2, true),
+                // Visited flags
+                storage_entry(3, false),
+                // Number of particles found
+                storage_entry(4, false),
+                // Found ids
+                storage_entry(5, false),
+            ],
+        })
+    }
+}
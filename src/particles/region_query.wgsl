@@ -0,0 +1,97 @@
+override WORKGROUP_SIZE = 64u;
+
+const UNUSED_CELL_ID = 0xffffffffu;
+// Must match QueryShape
+const SHAPE_CIRCLE = 0u;
+const SHAPE_RECT = 1u;
+
+struct PushConstants {
+    center: vec2<f32>,
+    radius: f32,
+    shape: u32,
+    rect_min: vec2<f32>,
+    rect_max: vec2<f32>,
+    // World position of the corner of cell (0, 0), see Grid::origin
+    origin: vec2<f32>,
+    cell_size: f32,
+    total_cell_ids: u32,
+    // Rectangle of cells visited, one thread per cell
+    first_cell: vec2<u32>,
+    cell_counts: vec2<u32>,
+    num_particles: u32,
+}
+
+struct QueryResult {
+    num_found: atomic<u32>,
+}
+
+// Sorted by the last grid update
+@group(0) @binding(0) var<storage, read> cell_ids: array<u32>;
+@group(0) @binding(1) var<storage, read> object_ids: array<u32>;
+@group(0) @binding(2) var<storage, read> positions: array<vec2<f32>>;
+// One flag per particle, cleared before every query: a particle is in up to 4 cells but reported once
+@group(0) @binding(3) var<storage, read_write> visited: array<atomic<u32>>;
+@group(0) @binding(4) var<storage, read_write> result: QueryResult;
+@group(0) @binding(5) var<storage, read_write> found_ids: array<u32>;
+
+var<push_constant> push_constants: PushConstants;
+
+// One thread per cell around the shape: finds the run of the cell in the sorted cell ids and appends the
+// particles of the run whose center is inside the shape to found_ids, in no particular order
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn query_region(@builtin(global_invocation_id) global_id: vec3<u32>){
+    let idx = global_id.x;
+    if idx >= push_constants.cell_counts.x * push_constants.cell_counts.y {
+        return;
+    }
+    let cell_coord = push_constants.first_cell + vec2<u32>(idx % push_constants.cell_counts.x, idx / push_constants.cell_counts.x);
+    let cell = morton_encode(cell_coord);
+
+    for (var i = lower_bound(cell); i < push_constants.total_cell_ids && cell_ids[i] == cell; i++) {
+        let object_id = object_ids[i];
+        if object_id >= push_constants.num_particles || !is_inside(positions[object_id]) {
+            continue;
+        }
+        if atomicExchange(&visited[object_id], 1u) == 0u {
+            let slot = atomicAdd(&result.num_found, 1u);
+            found_ids[slot] = object_id;
+        }
+    }
+}
+
+// First index of the sorted cell ids that is not below cell
+fn lower_bound(cell: u32) -> u32 {
+    var low = 0u;
+    var high = push_constants.total_cell_ids;
+    while low < high {
+        let middle = (low + high) / 2u;
+        if cell_ids[middle] < cell {
+            low = middle + 1u;
+        } else {
+            high = middle;
+        }
+    }
+    return low;
+}
+
+fn is_inside(position: vec2<f32>) -> bool {
+    if push_constants.shape == SHAPE_CIRCLE {
+        let offset = position - push_constants.center;
+        return dot(offset, offset) <= push_constants.radius * push_constants.radius;
+    }
+    return all(position >= push_constants.rect_min) && all(position <= push_constants.rect_max);
+}
+
+fn split_by_bits(n: u32) -> u32 {
+    var x = n & 0x0000FFFF;
+    x = (x | (x << 8)) & 0x00FF00FF;
+    x = (x | (x << 4)) & 0x0F0F0F0F;
+    x = (x | (x << 2)) & 0x33333333;
+    x = (x | (x << 1)) & 0x55555555;
+    return x;
+}
+
+// Must match morton::encode
+fn morton_encode(v: vec2<u32>) -> u32 {
+    return split_by_bits(v.x) | (split_by_bits(v.y) << 1);
+}
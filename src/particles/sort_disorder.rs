@@ -0,0 +1,161 @@
+use std::sync::mpsc::{Receiver, TryRecvError};
+use wgpu::{BufferAsyncError, CommandEncoder, PushConstantRange};
+use wgpu::wgt::PollType;
+use crate::utils::profiler::GpuProfiler;
+use crate::particles::particle_buffers::ParticleBuffers;
+use crate::particles::particle_sort::WORKGROUP_SIZE;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::bind_resources::{Bindings, BindingsBuilder};
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstantData {
+    num_particles: u32,
+}
+
+/// Measures how far the particles are from the order of their home cells: the fraction of neighbouring particles
+/// whose home cell ids are in descending order, 0 right after a sort. Like `ContactStatsReadback`, the count is
+/// read back asynchronously: `measure` records it, `map` starts the read once it is submitted and `poll` returns
+/// it without stalling.
+pub struct SortDisorderKernel {
+    bindings: Bindings,
+    count_pass: ComputeShader,
+    descending_pairs: GpuBuffer<u32>,
+    staging_buffer: wgpu::Buffer,
+    pending: Option<Receiver<Result<(), BufferAsyncError>>>,
+    /// Set by `measure` until `map`
+    recorded: bool,
+    /// Particles of the measure in flight
+    measured_particles: u32,
+    /// Set when the particles were sorted after the measure in flight, its result is dropped
+    stale: bool,
+}
+
+impl SortDisorderKernel {
+    pub fn new(wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers) -> Self {
+        let descending_pairs = GpuBuffer::new(wgpu_context, vec![0u32], wgpu::BufferUsages::STORAGE);
+        let staging_buffer = wgpu_context.get_device().create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sort disorder staging buffer"),
+            size: size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bindings = Self::bindings_builder(particle_buffers, &descending_pairs).build(wgpu_context);
+
+        let count_pass = ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("sort_disorder.wgsl"),
+            "count_descending_pairs",
+            bindings.layout(),
+            WORKGROUP_SIZE,
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE.0 as f64),
+            ],
+            &vec![
+                PushConstantRange{
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstantData>() as u32
+                }
+            ]
+        );
+
+        Self {
+            bindings,
+            count_pass,
+            descending_pairs,
+            staging_buffer,
+            pending: None,
+            recorded: false,
+            measured_particles: 0,
+            stale: false,
+        }
+    }
+
+    fn bindings_builder(particle_buffers: &ParticleBuffers, descending_pairs: &GpuBuffer<u32>) -> BindingsBuilder {
+        BindingsBuilder::new("Sort disorder")
+            .storage_ro(&particle_buffers.home_cell_ids)
+            .storage_rw(descending_pairs)
+    }
+
+    /// Binds other particle buffers. Grown ones are followed by the bindings.
+    pub fn refresh(&mut self, particle_buffers: &ParticleBuffers) {
+        self.bindings.rebind(Self::bindings_builder(particle_buffers, &self.descending_pairs));
+    }
+
+    /// True while a measure is recorded or in flight, the next one must wait for it.
+    pub fn is_pending(&self) -> bool {
+        self.recorded || self.pending.is_some()
+    }
+
+    /// Records the count of the descending pairs of home cell ids and its copy to the staging buffer. The home cell
+    /// ids must have been written. Ignored while a measure is in flight.
+    pub fn measure(&mut self, encoder: &mut CommandEncoder, gpu_profiler: &GpuProfiler, num_particles: u32) {
+        if self.is_pending() {
+            return;
+        }
+        encoder.clear_buffer(self.descending_pairs.buffer(), 0, None);
+        {
+            let mut scope = gpu_profiler.scope("Sort disorder", encoder);
+            self.count_pass.dispatch_by_items(
+                &mut scope,
+                (num_particles, 1, 1),
+                Some(vec![(0u32, bytemuck::bytes_of(&PushConstantData { num_particles }))]),
+                &self.bindings.bind_group()
+            );
+        }
+        encoder.copy_buffer_to_buffer(self.descending_pairs.buffer(), 0, &self.staging_buffer, 0, size_of::<u32>() as u64);
+        self.measured_particles = num_particles;
+        self.recorded = true;
+        self.stale = false;
+    }
+
+    /// Must be called after the encoder of `measure` was submitted. Does nothing if no measure was recorded.
+    pub fn map(&mut self, wgpu_context: &WgpuContext) {
+        if !self.recorded {
+            return;
+        }
+        self.recorded = false;
+        wgpu_context.transfers().record_readback(size_of::<u32>() as u64);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.staging_buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.pending = Some(receiver);
+    }
+
+    /// Drops the result of the measure recorded or in flight, e.g. after a sort.
+    pub fn discard_pending(&mut self) {
+        self.stale = true;
+    }
+
+    /// Returns the fraction of descending pairs of the measure in flight once it is done, without blocking.
+    pub fn poll(&mut self, wgpu_context: &WgpuContext) -> Option<f32> {
+        let receiver = self.pending.take()?;
+        let _ = wgpu_context.get_device().poll(PollType::Poll);
+        match receiver.try_recv() {
+            Ok(Ok(())) => {
+                let descending_pairs = {
+                    let mapped_range = self.staging_buffer.slice(..).get_mapped_range();
+                    bytemuck::pod_read_unaligned::<u32>(&mapped_range)
+                };
+                self.staging_buffer.unmap();
+                if self.stale {
+                    return None;
+                }
+                let pairs = self.measured_particles.saturating_sub(1).max(1);
+                Some(descending_pairs as f32 / pairs as f32)
+            }
+            Ok(Err(e)) => {
+                log::error!("Sort disorder readback failed: {:?}", e);
+                None
+            }
+            Err(TryRecvError::Empty) => {
+                self.pending = Some(receiver);
+                None
+            }
+            Err(TryRecvError::Disconnected) => None,
+        }
+    }
+}
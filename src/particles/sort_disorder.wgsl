@@ -0,0 +1,25 @@
+override WORKGROUP_SIZE = 64u;
+
+struct PushConstantsData {
+    num_particles: u32,
+}
+
+// Written by create_home_cell_ids, in the current order of the particles
+@group(0) @binding(0) var<storage, read> home_cell_ids: array<u32>;
+// Cleared before every measure
+@group(0) @binding(1) var<storage, read_write> descending_pairs: atomic<u32>;
+
+var<push_constant> push_constant_data: PushConstantsData;
+
+// One thread per pair of neighbouring particles: counts the pairs whose home cells are in descending order,
+// 0 right after a sort.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn count_descending_pairs(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let obj_id = global_id.x;
+    if obj_id + 1u >= push_constant_data.num_particles {
+        return;
+    }
+    if home_cell_ids[obj_id] > home_cell_ids[obj_id + 1u] {
+        atomicAdd(&descending_pairs, 1u);
+    }
+}
@@ -0,0 +1,204 @@
+use wgpu::{BindGroupLayout, CommandEncoder, PushConstantRange};
+use crate::particles::particle_buffers::ParticleBuffers;
+use crate::particles::particle_shape::SpriteClip;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::bind_resources::BindResources;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::profiler::GpuProfiler;
+
+pub const WORKGROUP_SIZE: u32 = 64;
+
+/// Channel with the seconds a particle has been animated for, see `SpriteAnimationDriver::Age`.
+pub const SPRITE_AGE_CHANNEL: &str = "sprite_age";
+
+/// What advances the frame of the clip.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SpriteAnimationDriver {
+    /// The time since the particle spawned, or since the animation started for the existing ones.
+    Age { frames_per_second: f32 },
+    /// |current - previous position| / delta time: at rest the first frame, at `max_speed` world units
+    /// per second and above the last one. Never loops.
+    Speed { max_speed: f32 },
+}
+
+/// Animates the sprites by writing the `SPRITE_FRAME_CHANNEL` of every particle on the GPU, once per frame.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SpriteAnimation {
+    pub clip: SpriteClip,
+    pub driver: SpriteAnimationDriver,
+}
+
+impl SpriteAnimation {
+    pub fn by_age(clip: SpriteClip, frames_per_second: f32) -> Self {
+        Self { clip, driver: SpriteAnimationDriver::Age { frames_per_second } }
+    }
+
+    pub fn by_speed(clip: SpriteClip, max_speed: f32) -> Self {
+        Self { clip, driver: SpriteAnimationDriver::Speed { max_speed } }
+    }
+
+    /// Frames per second of age, or per world unit per second of speed.
+    fn rate(&self) -> f32 {
+        match self.driver {
+            SpriteAnimationDriver::Age { frames_per_second } => frames_per_second,
+            SpriteAnimationDriver::Speed { max_speed } => self.clip.num_frames as f32 / max_speed,
+        }
+    }
+
+    fn looping(&self) -> bool {
+        self.clip.looping && matches!(self.driver, SpriteAnimationDriver::Age { .. })
+    }
+
+    /// Atlas frame of a particle of `value` seconds of age or world units per second of speed.
+    /// Must match `clip_frame` in sprite_animation.wgsl
+    pub fn frame(&self, value: f32) -> u32 {
+        let frame = (value * self.rate()).max(0.0) as u32;
+        let frame = if self.looping() { frame % self.clip.num_frames } else { frame.min(self.clip.num_frames - 1) };
+        self.clip.first_frame + frame
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstants {
+    num_particles: u32,
+    extras_stride: u32,
+    frame_offset: u32,
+    age_offset: u32,
+    driver: u32,
+    first_frame: u32,
+    num_frames: u32,
+    looping: u32,
+    rate: f32,
+    delta_time: f32,
+    inv_delta_time: f32,
+}
+
+/// Offsets of the channels the kernel writes, in the extras of a particle.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct SpriteChannelOffsets {
+    pub extras_stride: u32,
+    pub frame_offset: u32,
+    /// Only read by `SpriteAnimationDriver::Age`
+    pub age_offset: Option<u32>,
+}
+
+pub(crate) struct SpriteAnimationKernel {
+    shader: ComputeShader,
+    bind_resources: BindResources,
+    animation: SpriteAnimation,
+}
+
+impl SpriteAnimationKernel {
+    pub fn new(wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers, animation: SpriteAnimation) -> Self {
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let bind_group = Self::create_bind_group(wgpu_context, &bind_group_layout, particle_buffers);
+        let bind_resources = BindResources::new(bind_group_layout, bind_group);
+
+        let shader = ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("sprite_animation.wgsl"),
+            "animate_sprites",
+            &bind_resources.bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange {
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstants>() as u32,
+                }
+            ]
+        );
+
+        Self {
+            shader,
+            bind_resources,
+            animation,
+        }
+    }
+
+    pub fn animation(&self) -> SpriteAnimation {
+        self.animation
+    }
+
+    pub fn set_animation(&mut self, animation: SpriteAnimation) {
+        self.animation = animation;
+    }
+
+    /// Follows the particle buffers after they grew or the extras were replaced.
+    pub fn refresh(&mut self, wgpu_context: &WgpuContext, particle_buffers: &ParticleBuffers) {
+        self.bind_resources.bind_group = Self::create_bind_group(wgpu_context, &self.bind_resources.bind_group_layout, particle_buffers);
+    }
+
+    /// Records the animation pass. `delta_time` is the simulated time since the last update for the age,
+    /// and the one of the last step for the speed.
+    pub fn record(&self, encoder: &mut CommandEncoder, gpu_profiler: &GpuProfiler, num_particles: u32, offsets: SpriteChannelOffsets, delta_time: f32) {
+        if num_particles == 0 {
+            return;
+        }
+        let (driver, age_offset) = match self.animation.driver {
+            SpriteAnimationDriver::Age { .. } => (0, offsets.age_offset.expect("the age driver needs the sprite age channel")),
+            SpriteAnimationDriver::Speed { .. } => (1, 0),
+        };
+        let push_constants = PushConstants {
+            num_particles,
+            extras_stride: offsets.extras_stride,
+            frame_offset: offsets.frame_offset,
+            age_offset,
+            driver,
+            first_frame: self.animation.clip.first_frame,
+            num_frames: self.animation.clip.num_frames,
+            looping: self.animation.looping() as u32,
+            rate: self.animation.rate(),
+            delta_time,
+            inv_delta_time: if delta_time > 0.0 { 1.0 / delta_time } else { 0.0 },
+        };
+
+        let mut scope = gpu_profiler.scope("Sprite animation", encoder);
+        self.shader.dispatch_by_items(
+            &mut scope,
+            (num_particles, 1, 1),
+            Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+            &self.bind_resources.bind_group
+        );
+    }
+
+    fn create_bind_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, particle_buffers: &ParticleBuffers) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sprite animation bind group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: particle_buffers.current_positions.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: particle_buffers.previous_positions.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: particle_buffers.extras.buffer().as_entire_binding() },
+            ],
+        })
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sprite animation bind group layout"),
+            entries: &[
+                // Positions
+                storage_entry(0, true),
+                // Previous positions
+                storage_entry(1, true),
+                // Channels
+                storage_entry(2, false),
+            ],
+        })
+    }
+}
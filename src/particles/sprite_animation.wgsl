@@ -0,0 +1,56 @@
+override WORKGROUP_SIZE = 64u;
+
+// Must match SpriteAnimationDriver
+const DRIVER_AGE = 0u;
+const DRIVER_SPEED = 1u;
+
+struct PushConstantsData {
+    num_particles: u32,
+    extras_stride: u32,
+    // Offsets of the sprite frame and sprite age channels in the extras of a particle
+    frame_offset: u32,
+    age_offset: u32,
+    driver: u32,
+    first_frame: u32,
+    num_frames: u32,
+    looping: u32,
+    // Frames per second of age, or frames per world unit per second of speed
+    rate: f32,
+    delta_time: f32,
+    inv_delta_time: f32,
+}
+
+@group(0) @binding(0) var<storage, read> positions: array<vec2<f32>>;
+@group(0) @binding(1) var<storage, read> previous_positions: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read_write> extras: array<u32>;
+
+var<push_constant> push_constants: PushConstantsData;
+
+// Writes the atlas frame of every particle, from its age (advanced here) or its speed
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn animate_sprites(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= push_constants.num_particles {
+        return;
+    }
+    let base = index * push_constants.extras_stride;
+    var value: f32;
+    if push_constants.driver == DRIVER_AGE {
+        let age = bitcast<f32>(extras[base + push_constants.age_offset]) + push_constants.delta_time;
+        extras[base + push_constants.age_offset] = bitcast<u32>(age);
+        value = age;
+    }
+    else {
+        value = length(positions[index] - previous_positions[index]) * push_constants.inv_delta_time;
+    }
+    extras[base + push_constants.frame_offset] = push_constants.first_frame + clip_frame(value);
+}
+
+// Frame of the clip at `value`, must match SpriteAnimation::frame
+fn clip_frame(value: f32) -> u32 {
+    let frame = u32(max(value * push_constants.rate, 0.0));
+    if push_constants.looping != 0u {
+        return frame % push_constants.num_frames;
+    }
+    return min(frame, push_constants.num_frames - 1u);
+}
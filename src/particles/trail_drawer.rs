@@ -0,0 +1,133 @@
+use glam::{Vec2, Vec4};
+use wgpu::{BindGroup, BindGroupLayout};
+use crate::renderer::camera::Camera;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::gpu_buffer::GpuBuffer;
+
+/// Color of the newest segment of a trail, the older ones fade to transparent.
+const TRAIL_COLOR: Vec4 = Vec4::new(0.75, 0.9, 1.0, 0.6);
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstants {
+    color: Vec4,
+    trail_length: u32,
+    head: u32,
+    _padding: [u32; 2],
+}
+
+/// Draws the history of `ParticleTrails` as fading lines, one instance per trail slot.
+pub struct TrailDrawer {
+    render_pipeline: wgpu::RenderPipeline,
+    bind_group_layout: BindGroupLayout,
+    bind_group: Option<BindGroup>, // Set by bind_history
+}
+
+impl TrailDrawer {
+    pub fn new(wgpu_context: &WgpuContext, camera: &Camera) -> Self {
+        let shader = wgpu_context.get_device().create_shader_module(wgpu::include_wgsl!("trail_drawer.wgsl"));
+        let bind_group_layout = wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Trail drawer bind group layout"),
+            entries: &[
+                // History
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let render_pipeline_layout = wgpu_context.get_device().create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Trail render pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout, camera.camera_bind_group_layout()],
+            push_constant_ranges: &[
+                wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStages::VERTEX,
+                    range: 0..size_of::<PushConstants>() as u32,
+                }
+            ],
+        });
+
+        let render_pipeline = wgpu_context.get_device().create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Trail render pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                // The positions come from the history
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: wgpu_context.surface_format(),
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: wgpu_context.pipeline_cache(),
+        });
+
+        Self {
+            render_pipeline,
+            bind_group_layout,
+            bind_group: None,
+        }
+    }
+
+    /// Draws from `history` from now on. Must follow every recreation of the history buffer.
+    pub fn bind_history(&mut self, wgpu_context: &WgpuContext, history: &GpuBuffer<Vec2>) {
+        self.bind_group = Some(wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Trail drawer bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: history.buffer().as_entire_binding() },
+            ],
+        }));
+    }
+
+    /// Draws the `trail_length - 1` segments of the first `num_slots` rings, whose newest entry is `head`.
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, camera: &Camera, trail_length: u32, head: u32, num_slots: u32) {
+        let Some(bind_group) = &self.bind_group else {
+            return;
+        };
+        if num_slots == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.set_bind_group(1, camera.binding_group(), &[]);
+        let push_constants = PushConstants {
+            color: TRAIL_COLOR,
+            trail_length,
+            head,
+            _padding: [0; 2],
+        };
+        render_pass.set_push_constants(wgpu::ShaderStages::VERTEX, 0, bytemuck::bytes_of(&push_constants));
+        render_pass.draw(0..2 * (trail_length - 1), 0..num_slots);
+    }
+}
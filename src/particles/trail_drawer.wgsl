@@ -0,0 +1,42 @@
+struct Camera {
+    view_proj: mat4x4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> u_camera: Camera;
+// trail_length entries per slot, written by particle_trails.wgsl
+@group(0) @binding(0) var<storage, read> history: array<vec2<f32>>;
+
+struct PushConstantsData {
+    color: vec4<f32>,
+    trail_length: u32,
+    // Entry of the rings written last
+    head: u32,
+}
+
+var<push_constant> push_constants: PushConstantsData;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+// One instance per slot, two vertices per segment: segment s joins the positions of age s and s + 1
+@vertex
+fn vs_main(@builtin(instance_index) slot: u32, @builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let trail_length = push_constants.trail_length;
+    let age = vertex_index / 2u + (vertex_index & 1u);
+    let entry = (push_constants.head + trail_length - age) % trail_length;
+    let position = history[slot * trail_length + entry];
+
+    var out: VertexOutput;
+    out.clip_position = u_camera.view_proj * vec4<f32>(position, 0.0, 1.0);
+    // Fades out towards the oldest position
+    let fade = 1.0 - f32(age) / f32(trail_length - 1u);
+    out.color = vec4<f32>(push_constants.color.rgb, push_constants.color.a * fade);
+    return out;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    return input.color;
+}
@@ -0,0 +1,292 @@
+use glam::{UVec2, Vec2};
+use wgpu::{BindGroupLayout, CommandEncoder, PushConstantRange};
+use crate::grid::grid::Grid;
+use crate::grid::morton;
+use crate::particles::particle_system::{ParticleSystem, MASS_CHANNEL, PARTICLE_FLAGS_CHANNEL};
+use crate::physics::collision_system::COLLISION_FILTER_CHANNEL;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::bind_resources::BindResources;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::profiler::GpuProfiler;
+
+pub const WORKGROUP_SIZE: u32 = 64;
+const NO_CHANNEL: u32 = u32::MAX;
+
+/// Broadphase and solver of `BroadphaseMode::CellRanges`. After the grid sorted the cell ids, a pass writes
+/// where every cell starts and ends in them, into tables indexed by morton cell id. Each particle then visits
+/// the particles of the cells it touches, without collision cells nor color passes. The tables cover the
+/// world of the particle system, particles outside of it do not collide.
+pub(crate) struct CellRangeSolver {
+    find_cell_boundaries_shader: ComputeShader,
+    solve_shader: ComputeShader,
+    apply_shader: ComputeShader,
+    bind_resources: BindResources,
+    tables: CellTables,
+    total_cell_ids: u32,
+    origin: Vec2,
+    extras_stride: u32,
+    flags_offset: u32, // NO_CHANNEL if the particles have no flags channel
+    filter_offset: u32, // NO_CHANNEL if the particles have no collision filter channel
+    mass_offset: u32, // NO_CHANNEL if the particles have no mass channel
+}
+
+/// Buffers owned by the solver, bound next to the grid and the particles.
+struct CellTables {
+    uniform_data: GpuBuffer<UniformData>,
+    cell_start: GpuBuffer<u32>,
+    cell_end: GpuBuffer<u32>,
+    corrections: GpuBuffer<Vec2>,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct UniformData {
+    total_cell_ids: u32,
+    num_particles: u32,
+    table_len: u32,
+    cell_size: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstantsData {
+    stiffness: f32,
+    slop: f32,
+    origin: Vec2,
+    mass_exponent: f32,
+    extras_stride: u32,
+    flags_offset: u32,
+    filter_offset: u32,
+    mass_offset: u32,
+    _padding: u32,
+}
+
+impl CellRangeSolver {
+    pub fn new(wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid) -> Self {
+        let uniform = Self::uniform(particle_system, grid);
+        let cell_start = GpuBuffer::new(wgpu_context, vec![0; uniform.table_len as usize], wgpu::BufferUsages::STORAGE);
+        let cell_end = GpuBuffer::new(wgpu_context, vec![0; uniform.table_len as usize], wgpu::BufferUsages::STORAGE);
+        let corrections = GpuBuffer::new(wgpu_context, vec![Vec2::ZERO; particle_system.len().max(1)], wgpu::BufferUsages::STORAGE);
+        let uniform_data = GpuBuffer::new(wgpu_context, vec![uniform], wgpu::BufferUsages::UNIFORM);
+        let tables = CellTables { uniform_data, cell_start, cell_end, corrections };
+
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let bind_group = Self::create_bind_group(wgpu_context, &bind_group_layout, particle_system, grid, &tables);
+        let bind_resources = BindResources::new(bind_group_layout, bind_group);
+
+        let create_shader = |entry_point: &str| ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("cell_range_solver.wgsl"),
+            entry_point,
+            &bind_resources.bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange {
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstantsData>() as u32,
+                }
+            ]
+        );
+        let find_cell_boundaries_shader = create_shader("find_cell_boundaries");
+        let solve_shader = create_shader("solve_cell_ranges");
+        let apply_shader = create_shader("apply_corrections");
+
+        Self {
+            find_cell_boundaries_shader,
+            solve_shader,
+            apply_shader,
+            bind_resources,
+            tables,
+            total_cell_ids: uniform.total_cell_ids,
+            origin: grid.origin(),
+            extras_stride: particle_system.channels().stride(),
+            flags_offset: Self::channel_offset(particle_system, PARTICLE_FLAGS_CHANNEL),
+            filter_offset: Self::channel_offset(particle_system, COLLISION_FILTER_CHANNEL),
+            mass_offset: Self::channel_offset(particle_system, MASS_CHANNEL),
+        }
+    }
+
+    /// Offset of the channel `name` in the extras of a particle, e.g. `PARTICLE_FLAGS_CHANNEL`.
+    fn channel_offset(particle_system: &ParticleSystem, name: &str) -> u32 {
+        let channels = particle_system.channels();
+        channels.find(name).map_or(NO_CHANNEL, |id| channels.offset(id))
+    }
+
+    /// Number of entries of the cell tables: every morton id of the cells of the world, plus a border cell.
+    pub fn table_len(world_size: Vec2, cell_size: f32) -> u32 {
+        let max_cell = (world_size / cell_size).ceil().as_uvec2() + UVec2::ONE;
+        morton::encode(max_cell) + 1
+    }
+
+    fn uniform(particle_system: &ParticleSystem, grid: &Grid) -> UniformData {
+        UniformData {
+            total_cell_ids: grid.cell_ids().len() as u32,
+            num_particles: particle_system.len() as u32,
+            table_len: Self::table_len(particle_system.get_world_size(), grid.cell_size()),
+            cell_size: grid.cell_size(),
+        }
+    }
+
+    /// Follows `Grid::set_origin`, the cells of the particles are relative to it.
+    pub fn set_origin(&mut self, origin: Vec2) {
+        self.origin = origin;
+    }
+
+    pub fn origin(&self) -> Vec2 {
+        self.origin
+    }
+
+    /// Where every cell starts in the sorted object ids, indexed by morton cell id.
+    pub fn cell_start(&self) -> &GpuBuffer<u32> {
+        &self.tables.cell_start
+    }
+
+    /// Where every cell ends in the sorted object ids, indexed by morton cell id.
+    pub fn cell_end(&self) -> &GpuBuffer<u32> {
+        &self.tables.cell_end
+    }
+
+    /// Number of particles and cell ids, length of the cell tables and cell size, in the `UniformData` layout.
+    pub fn uniform_buffer(&self) -> &wgpu::Buffer {
+        self.tables.uniform_data.buffer()
+    }
+
+    fn push_constants(&self, stiffness: f32, slop: f32, mass_exponent: f32) -> PushConstantsData {
+        PushConstantsData {
+            stiffness,
+            slop,
+            origin: self.origin,
+            mass_exponent,
+            extras_stride: self.extras_stride,
+            flags_offset: self.flags_offset,
+            filter_offset: self.filter_offset,
+            mass_offset: self.mass_offset,
+            _padding: 0,
+        }
+    }
+
+    /// Follows new particles, a new cell size or new channels.
+    pub fn refresh_buffers(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid) {
+        let uniform = Self::uniform(particle_system, grid);
+        if uniform.table_len as usize != self.tables.cell_start.len() {
+            self.tables.cell_start = GpuBuffer::new(wgpu_context, vec![0; uniform.table_len as usize], wgpu::BufferUsages::STORAGE);
+            self.tables.cell_end = GpuBuffer::new(wgpu_context, vec![0; uniform.table_len as usize], wgpu::BufferUsages::STORAGE);
+        }
+        if particle_system.len() > self.tables.corrections.len() {
+            self.tables.corrections.push_all(&vec![Vec2::ZERO; particle_system.len() - self.tables.corrections.len()], wgpu_context);
+        }
+        self.total_cell_ids = uniform.total_cell_ids;
+        self.extras_stride = particle_system.channels().stride();
+        self.flags_offset = Self::channel_offset(particle_system, PARTICLE_FLAGS_CHANNEL);
+        self.filter_offset = Self::channel_offset(particle_system, COLLISION_FILTER_CHANNEL);
+        self.mass_offset = Self::channel_offset(particle_system, MASS_CHANNEL);
+        self.tables.uniform_data.replace_elem(uniform, 0, wgpu_context);
+        self.bind_resources.bind_group = Self::create_bind_group(wgpu_context, &self.bind_resources.bind_group_layout, particle_system, grid, &self.tables);
+    }
+
+    fn create_bind_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, particle_system: &ParticleSystem, grid: &Grid, tables: &CellTables) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Cell range solver bind group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: grid.cell_ids().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: grid.object_ids().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: tables.cell_start.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 3, resource: tables.cell_end.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 4, resource: particle_system.positions().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 5, resource: particle_system.radius().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 6, resource: tables.corrections.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 7, resource: tables.uniform_data.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 8, resource: particle_system.buffers().extras.buffer().as_entire_binding() },
+            ],
+        })
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Cell range solver bind group layout"),
+            entries: &[
+                // Cell ids
+                storage_entry(0, true),
+                // Object ids
+                storage_entry(1, true),
+                // Cell start
+                storage_entry(2, false),
+                // Cell end
+                storage_entry(3, false),
+                // Positions
+                storage_entry(4, false),
+                // Radius
+                storage_entry(5, true),
+                // Corrections
+                storage_entry(6, false),
+                // Uniform data
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Extras, for the flags channel
+                storage_entry(8, true),
+            ],
+        })
+    }
+
+    /// Records the cell boundary pass. Must follow the sort of the grid.
+    pub fn find_cell_boundaries(&self, encoder: &mut CommandEncoder, gpu_profiler: &GpuProfiler) {
+        encoder.clear_buffer(self.tables.cell_start.buffer(), 0, None);
+        encoder.clear_buffer(self.tables.cell_end.buffer(), 0, None);
+        {
+            let mut scope = gpu_profiler.scope("Find Cell Boundaries", encoder);
+            self.find_cell_boundaries_shader.dispatch_by_items(
+                &mut scope,
+                (self.total_cell_ids, 1, 1),
+                Some(vec![(0u32, bytemuck::bytes_of(&self.push_constants(0.0, 0.0, 1.0)))]),
+                &self.bind_resources.bind_group
+            );
+        }
+    }
+
+    /// Records one solver iteration: every particle computes its correction, then all of them are applied.
+    pub fn solve_iteration(&self, encoder: &mut CommandEncoder, gpu_profiler: &GpuProfiler, num_particles: u32, stiffness: f32, slop: f32, mass_exponent: f32) {
+        if num_particles == 0 {
+            return;
+        }
+        let push_constants = self.push_constants(stiffness, slop, mass_exponent);
+        {
+            let mut scope = gpu_profiler.scope("Solve Cell Ranges", encoder);
+            self.solve_shader.dispatch_by_items(
+                &mut scope,
+                (num_particles, 1, 1),
+                Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                &self.bind_resources.bind_group
+            );
+            self.apply_shader.dispatch_by_items(
+                &mut scope,
+                (num_particles, 1, 1),
+                Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                &self.bind_resources.bind_group
+            );
+        }
+    }
+}
@@ -0,0 +1,211 @@
+override WORKGROUP_SIZE = 64u;
+
+const UNUSED_CELL_ID = 0xffffffffu;
+// Cells a particle can touch, see grid.wgsl
+const MAX_CELLS_PER_OBJECT = 4u;
+const NO_CHANNEL = 0xffffffffu;
+// Must match the flags of particle_system.rs, all make a particle kinematic
+const PINNED_FLAG = 1u;
+const HELD_FLAG = 2u;
+const SLEEPING_FLAG = 4u;
+
+struct UniformData {
+    total_cell_ids: u32,
+    num_particles: u32,
+    // Length of cell_start and cell_end, cells with a larger morton id are not solved
+    table_len: u32,
+    cell_size: f32,
+}
+
+struct PushConstantsData {
+    stiffness: f32,
+    // Penetration left uncorrected, relative to the sum of the radii
+    slop: f32,
+    // World position of the corner of cell (0, 0), see Grid::origin
+    origin: vec2<f32>,
+    // The mass of a particle is its radius to this power, see SolverMethod::mass_exponent
+    mass_exponent: f32,
+    extras_stride: u32,
+    // Word offset of the particle flags channel, NO_CHANNEL if the particles have none
+    flags_offset: u32,
+    // Word offset of the collision filter channel, NO_CHANNEL if the particles have none
+    filter_offset: u32,
+    // Word offset of the mass channel, NO_CHANNEL to weigh the particles by their radii
+    mass_offset: u32,
+    _padding: u32,
+}
+
+// Sorted by the grid
+@group(0) @binding(0) var<storage, read> cell_ids: array<u32>;
+@group(0) @binding(1) var<storage, read> object_ids: array<u32>;
+// Indexed by morton cell id: the cell holds object_ids[cell_start..cell_end]. Cleared to 0 every step.
+@group(0) @binding(2) var<storage, read_write> cell_start: array<u32>;
+@group(0) @binding(3) var<storage, read_write> cell_end: array<u32>;
+@group(0) @binding(4) var<storage, read_write> positions: array<vec2<f32>>;
+@group(0) @binding(5) var<storage, read> radius: array<f32>;
+@group(0) @binding(6) var<storage, read_write> corrections: array<vec2<f32>>;
+@group(0) @binding(7) var<uniform> uniform_data: UniformData;
+@group(0) @binding(8) var<storage, read> extras: array<u32>;
+
+var<push_constant> push_constants: PushConstantsData;
+
+// One thread per sorted cell id: the first and last ids of every run write where the cell starts and ends
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn find_cell_boundaries(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= uniform_data.total_cell_ids {
+        return;
+    }
+    let cell = cell_ids[index];
+    if cell == UNUSED_CELL_ID || cell >= uniform_data.table_len {
+        return;
+    }
+    if index == 0u || cell_ids[index - 1u] != cell {
+        cell_start[cell] = index;
+    }
+    if index + 1u == uniform_data.total_cell_ids || cell_ids[index + 1u] != cell {
+        cell_end[cell] = index + 1u;
+    }
+}
+
+// One thread per particle: visits the particles of its cells and stores the correction of its own position.
+// Positions are only read, so the result does not depend on the scheduling (Jacobi iteration).
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn solve_cell_ranges(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let object_id = global_id.x;
+    if object_id >= uniform_data.num_particles {
+        return;
+    }
+    // Kinematic particles are not pushed
+    if is_kinematic(object_id) {
+        corrections[object_id] = vec2<f32>(0.0);
+        return;
+    }
+
+    let position = positions[object_id];
+    let object_radius = radius[object_id];
+    var cells: array<u32, MAX_CELLS_PER_OBJECT>;
+    let num_cells = particle_cells(position, object_radius, &cells);
+
+    var correction = vec2<f32>(0.0);
+    for (var k = 0u; k < num_cells; k++) {
+        let cell = cells[k];
+        if cell >= uniform_data.table_len {
+            continue;
+        }
+        for (var j = cell_start[cell]; j < cell_end[cell]; j++) {
+            let other_object_id = object_ids[j];
+            // Particles sharing several cells are solved once
+            if other_object_id == object_id || is_in_previous_cells(other_object_id, &cells, k) || !can_collide(object_id, other_object_id) {
+                continue;
+            }
+
+            let other_radius = radius[other_object_id];
+            let vec_i_j = position - positions[other_object_id];
+            let distance = length(vec_i_j);
+            let radius_sum = object_radius + other_radius;
+            if distance < radius_sum && distance > 0.0001 {
+                let penetration_depth = max(radius_sum - distance - push_constants.slop * radius_sum, 0.0);
+                // Same weights as collision_solver.wgsl, only this particle's share is applied
+                let inv_mass = inverse_mass(object_id, object_radius);
+                let other_inv_mass = select(inverse_mass(other_object_id, other_radius), 0.0, is_kinematic(other_object_id));
+                let weight = inv_mass / (inv_mass + other_inv_mass);
+                correction += vec_i_j / distance * penetration_depth * push_constants.stiffness * weight;
+            }
+        }
+    }
+    corrections[object_id] = correction;
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn apply_corrections(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let object_id = global_id.x;
+    if object_id >= uniform_data.num_particles {
+        return;
+    }
+    positions[object_id] += corrections[object_id];
+}
+
+// Same as inverse_mass in collision_solver.wgsl, without the kinematic particles
+fn inverse_mass(object_id: u32, particle_radius: f32) -> f32 {
+    if push_constants.mass_offset != NO_CHANNEL {
+        return 1.0 / bitcast<f32>(extras[object_id * push_constants.extras_stride + push_constants.mass_offset]);
+    }
+    return 1.0 / pow(particle_radius, push_constants.mass_exponent);
+}
+
+// Must match CollisionFilter::can_collide and can_collide in collision_solver.wgsl
+fn can_collide(object_id: u32, other_object_id: u32) -> bool {
+    if push_constants.filter_offset == NO_CHANNEL {
+        return true;
+    }
+    let start = object_id * push_constants.extras_stride + push_constants.filter_offset;
+    let other_start = other_object_id * push_constants.extras_stride + push_constants.filter_offset;
+    return (extras[start] & extras[other_start + 1u]) != 0u && (extras[other_start] & extras[start + 1u]) != 0u;
+}
+
+// Pinned, held or asleep, see PINNED_FLAG, HELD_FLAG and SLEEPING_FLAG. Sleeping particles skip their neighbours
+fn is_kinematic(object_id: u32) -> bool {
+    if push_constants.flags_offset == NO_CHANNEL {
+        return false;
+    }
+    let flags = extras[object_id * push_constants.extras_stride + push_constants.flags_offset];
+    return (flags & (PINNED_FLAG | HELD_FLAG | SLEEPING_FLAG)) != 0u;
+}
+
+// The home cell and the phantom cells of a particle, like build_cell_ids_array in grid.wgsl
+fn particle_cells(world_position: vec2<f32>, object_radius: f32, cells: ptr<function, array<u32, MAX_CELLS_PER_OBJECT>>) -> u32 {
+    let position = world_position - push_constants.origin;
+    let home_cell_coord = max(vec2<i32>(floor(position / uniform_data.cell_size)), vec2<i32>(0));
+    (*cells)[0] = morton_encode(home_cell_coord);
+    var num_cells = 1u;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let neighbour_coord = home_cell_coord + vec2<i32>(x, y);
+            if (x == 0 && y == 0) || any(neighbour_coord < vec2<i32>(0)) || num_cells == MAX_CELLS_PER_OBJECT {
+                continue;
+            }
+            if is_obj_in_cell(position, object_radius * object_radius, neighbour_coord) {
+                (*cells)[num_cells] = morton_encode(neighbour_coord);
+                num_cells++;
+            }
+        }
+    }
+    return num_cells;
+}
+
+fn is_in_previous_cells(object_id: u32, cells: ptr<function, array<u32, MAX_CELLS_PER_OBJECT>>, num_previous: u32) -> bool {
+    for (var k = 0u; k < num_previous; k++) {
+        let cell = (*cells)[k];
+        if cell >= uniform_data.table_len {
+            continue;
+        }
+        for (var j = cell_start[cell]; j < cell_end[cell]; j++) {
+            if object_ids[j] == object_id {
+                return true;
+            }
+        }
+    }
+    return false;
+}
+
+fn is_obj_in_cell(particle_pos: vec2<f32>, particle_sq_radius: f32, cell_coord: vec2<i32>) -> bool {
+    let cell_bottom_left_corner = vec2<f32>(cell_coord) * uniform_data.cell_size;
+    let cell_top_right_corner = cell_bottom_left_corner + vec2<f32>(uniform_data.cell_size);
+    let distance_vec = particle_pos - clamp(particle_pos, cell_bottom_left_corner, cell_top_right_corner);
+    return dot(distance_vec, distance_vec) < particle_sq_radius;
+}
+
+fn split_by_bits(n: u32) -> u32 {
+    var x = n & 0x0000FFFF;
+    x = (x | (x << 8)) & 0x00FF00FF;
+    x = (x | (x << 4)) & 0x0F0F0F0F;
+    x = (x | (x << 2)) & 0x33333333;
+    x = (x | (x << 1)) & 0x55555555;
+    return x;
+}
+
+// Must match morton::encode
+fn morton_encode(v: vec2<i32>) -> u32 {
+    return split_by_bits(u32(v.x)) | (split_by_bits(u32(v.y)) << 1);
+}
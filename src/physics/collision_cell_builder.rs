@@ -1,14 +1,16 @@
 use wgpu::{BindGroupLayout, CommandEncoder};
-use wgpu_profiler::GpuProfiler;
-use crate::grid::grid::{Grid, MAX_CELLS_PER_OBJECT, UNUSED_CELL_ID};
+use crate::utils::profiler::GpuProfiler;
+use crate::grid::grid::{Grid, UNUSED_CELL_ID};
+use crate::grid::live_count::COLLISION_CHUNKS_ARGS_OFFSET;
 use crate::physics::collision_cell_buffers::CollisionCellBuffers;
+use crate::physics::collision_solver::CollisionSolver;
 use crate::renderer::wgpu_context::WgpuContext;
 use crate::utils::bind_resources::BindResources;
 use crate::utils::compute_shader::ComputeShader;
 use crate::utils::gpu_buffer::GpuBuffer;
 use crate::utils::prefix_sum::prefix_sum::PrefixSum;
 
-const WORKGROUP_SIZE: (u32, u32, u32) = (64u32, 1u32, 1u32);
+pub const WORKGROUP_SIZE: (u32, u32, u32) = (64u32, 1u32, 1u32);
 /// The value must match in the compute shader.
 pub const COUNTING_CHUNK_SIZE: u32 = 4;
 
@@ -19,6 +21,8 @@ pub struct CollisionCellBuilder{
     build_collision_cells_shader: ComputeShader,
     collision_cell_buffers: CollisionCellBuffers,
     uniform_data: GpuBuffer<UniformData>,
+    // Live count buffer of the grid, when the chunk kernels are dispatched from it
+    indirect_dispatch: Option<wgpu::Buffer>,
 }
 
 #[repr(C)]
@@ -52,7 +56,7 @@ impl CollisionCellBuilder{
             WORKGROUP_SIZE,
             &vec![
                 ("WORKGROUP_SIZE", WORKGROUP_SIZE.0 as f64),
-                ("MAX_CELLS_PER_OBJECT", MAX_CELLS_PER_OBJECT as f64),
+                ("MAX_CELLS_PER_OBJECT", grid.max_cells_per_object() as f64),
                 ("CHUNK_SIZE", COUNTING_CHUNK_SIZE as f64)
             ],
             &vec![]
@@ -66,8 +70,9 @@ impl CollisionCellBuilder{
             WORKGROUP_SIZE,
             &vec![
                 ("WORKGROUP_SIZE", WORKGROUP_SIZE.0 as f64),
-                ("MAX_CELLS_PER_OBJECT", MAX_CELLS_PER_OBJECT as f64),
-                ("CHUNK_SIZE", COUNTING_CHUNK_SIZE as f64)
+                ("MAX_CELLS_PER_OBJECT", grid.max_cells_per_object() as f64),
+                ("CHUNK_SIZE", COUNTING_CHUNK_SIZE as f64),
+                ("SOLVER_WORKGROUP_SIZE", CollisionSolver::workgroup_size(wgpu_context) as f64),
             ],
             &vec![]
         );
@@ -81,6 +86,7 @@ impl CollisionCellBuilder{
             collision_cell_buffers,
             bind_resources,
             uniform_data,
+            indirect_dispatch: None,
         }
     }
     
@@ -205,21 +211,31 @@ impl CollisionCellBuilder{
         self.bind_resources.bind_group = Self::create_bind_group(wgpu_context, &self.bind_resources.bind_group_layout, &self.collision_cell_buffers, &self.uniform_data, grid);
     }
 
+    /// Dispatches the chunk kernels from the live particle count of the grid if its indirect dispatch is enabled,
+    /// or from its used cell count if its cell compaction is.
+    /// The chunks past the covered particles are skipped: they hold no cell ids and were zeroed by an earlier step.
+    pub fn set_indirect_dispatch(&mut self, grid: &Grid) {
+        let indirect = grid.is_indirect_dispatch() || grid.is_cell_compaction();
+        self.indirect_dispatch = indirect.then(|| grid.live_count().args().buffer().clone());
+    }
+
+    fn dispatch_chunks(&self, shader: &ComputeShader, encoder: &mut CommandEncoder, num_chunks: u32) {
+        match &self.indirect_dispatch {
+            Some(indirect_buffer) => shader.indirect_dispatch(encoder, indirect_buffer, COLLISION_CHUNKS_ARGS_OFFSET, None, &self.bind_resources.bind_group),
+            None => shader.dispatch_by_items(encoder, (num_chunks, 1, 1), None, &self.bind_resources.bind_group),
+        }
+    }
+
     /// Step 3: Builds the collision cell list.
     /// Key: cell id; Value: Object id
     /// Collision cells are cells that contain more than one object, and therefore they need to be checked for potential collisions 
-    pub fn build_collision_cells(&self, wgpu_context: &WgpuContext,  encoder: &mut CommandEncoder, gpu_profiler: &mut GpuProfiler){
+    pub fn build_collision_cells(&self, wgpu_context: &WgpuContext,  encoder: &mut CommandEncoder, gpu_profiler: &GpuProfiler){
         let num_chunks = self.get_num_counting_chunks();
 
         // Step 3.1 Count the number of objects in each chunk that share the same cell id
         {
             let mut scope = gpu_profiler.scope("Collision cell count objects per chunk", encoder);
-            self.count_objects_per_chunk_shader.dispatch_by_items(
-                &mut scope,
-                (num_chunks, 1, 1),
-                None,
-                &self.bind_resources.bind_group
-            );
+            self.dispatch_chunks(&self.count_objects_per_chunk_shader, &mut scope, num_chunks);
         }
         
         // Step 3.2 Prefix sums the number of objects in each chunk
@@ -231,7 +247,7 @@ impl CollisionCellBuilder{
         // Step 3.3 Build the collision cell list
         {
             let mut scope = gpu_profiler.scope("Build collision cells", encoder);
-            self.build_collision_cells_shader.dispatch_by_items(&mut scope, (num_chunks, 1, 1), None, &self.bind_resources.bind_group);
+            self.dispatch_chunks(&self.build_collision_cells_shader, &mut scope, num_chunks);
         }
     }
 
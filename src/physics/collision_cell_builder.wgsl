@@ -1,5 +1,7 @@
 override CHUNK_SIZE: u32 = 4;
 override WORKGROUP_SIZE = 64u;
+// Workgroup size of the collision solver, which the indirect args are written for
+override SOLVER_WORKGROUP_SIZE = 64u;
 // For 2D, an object can touch at most 2^2 = 4 cells.
 override MAX_CELLS_PER_OBJECT = 4u;
 const UNUSED_CELL_ID = 0xffffffffu;
@@ -100,7 +102,7 @@ fn prepare_dispatch_buffer(){
     let total_items = chunk_obj_count[uniform_data.num_counting_chunks - 1];
 
     // Calculate workgroups needed
-    let workgroups_x = (total_items + WORKGROUP_SIZE - 1u) / WORKGROUP_SIZE;
+    let workgroups_x = (total_items + SOLVER_WORKGROUP_SIZE - 1u) / SOLVER_WORKGROUP_SIZE;
 
     // Write to the indirect buffer
     indirect_args.x = workgroups_x;
@@ -0,0 +1,282 @@
+use wgpu::{BindGroupLayout, CommandEncoder, PushConstantRange};
+use crate::utils::profiler::GpuProfiler;
+use crate::grid::grid::Grid;
+use crate::grid::morton::NUM_CELL_COLORS;
+use crate::particles::particle_system::ParticleSystem;
+use crate::physics::collision_cell_builder::CollisionCellBuilder;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::bind_resources::BindResources;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::readback_queue::ReadbackQueue;
+
+pub const WORKGROUP_SIZE: u32 = 64;
+const NUM_COLORS: usize = NUM_CELL_COLORS as usize;
+const SHARED_PARTICLES_LABEL: &str = "Shared particle color violations";
+const NEIGHBORING_CELLS_LABEL: &str = "Neighboring cell color violations";
+
+/// Violations found by `CollisionColorValidator`, per color pass.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ColorViolations {
+    /// Particles found in more than one collision cell of the color.
+    pub shared_particles: [u32; NUM_COLORS],
+    /// Collision cells with a neighbouring collision cell of the same color, counted from both cells.
+    pub neighboring_cells: [u32; NUM_COLORS],
+}
+
+impl ColorViolations {
+    pub fn is_empty(&self) -> bool {
+        self.shared_particles.iter().chain(&self.neighboring_cells).all(|&violations| violations == 0)
+    }
+}
+
+/// Debug passes that check the color exclusivity the collision solver relies on:
+/// during one color pass, every particle must belong to at most one collision cell,
+/// and no two neighbouring collision cells may share a color.
+/// Otherwise two threads would move the same particle concurrently and the result
+/// would depend on scheduling.
+pub struct CollisionColorValidator {
+    validation_shader: ComputeShader,
+    neighbor_validation_shader: ComputeShader,
+    bind_resources: BindResources,
+    counters: ValidationCounters,
+    readbacks: ReadbackQueue,
+    // Counters of the validation in flight that were already read back
+    shared_particles: Option<[u32; NUM_COLORS]>,
+    neighboring_cells: Option<[u32; NUM_COLORS]>,
+}
+
+/// Buffers owned by the validator, bound next to the grid and the collision cells.
+struct ValidationCounters {
+    uniform_data: GpuBuffer<UniformData>,
+    touch_counts: GpuBuffer<u32>,
+    violations: GpuBuffer<u32>,
+    neighbor_violations: GpuBuffer<u32>,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct CellColor{
+    color: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct UniformData {
+    num_counting_chunks: u32,
+    total_cell_ids: u32,
+}
+
+impl CollisionColorValidator {
+    pub fn new(wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid, collision_cell_builder: &CollisionCellBuilder) -> Self {
+        let uniform_data = GpuBuffer::new(
+            wgpu_context,
+            vec![UniformData{
+                num_counting_chunks: collision_cell_builder.get_num_counting_chunks(),
+                total_cell_ids: grid.cell_ids().len() as u32,
+            }],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+        let touch_counts = GpuBuffer::new(wgpu_context, vec![0; particle_system.len()], wgpu::BufferUsages::STORAGE);
+        let violations = GpuBuffer::new(wgpu_context, vec![0; NUM_COLORS], wgpu::BufferUsages::STORAGE);
+        let neighbor_violations = GpuBuffer::new(wgpu_context, vec![0; NUM_COLORS], wgpu::BufferUsages::STORAGE);
+        let counters = ValidationCounters { uniform_data, touch_counts, violations, neighbor_violations };
+
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let bind_group = Self::create_bind_group(wgpu_context, &bind_group_layout, grid, collision_cell_builder, &counters);
+        let bind_resources = BindResources::new(bind_group_layout, bind_group);
+
+        let create_shader = |entry_point: &str| ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("collision_color_validator.wgsl"),
+            entry_point,
+            &bind_resources.bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange{
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<CellColor>() as u32,
+                }
+            ]
+        );
+        let validation_shader = create_shader("validate_cell_colors");
+        let neighbor_validation_shader = create_shader("validate_neighbor_colors");
+
+        Self {
+            validation_shader,
+            neighbor_validation_shader,
+            bind_resources,
+            counters,
+            readbacks: ReadbackQueue::new(),
+            shared_particles: None,
+            neighboring_cells: None,
+        }
+    }
+
+    pub fn refresh_buffers(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid, collision_cell_builder: &CollisionCellBuilder) {
+        let new_uniform = UniformData {
+            num_counting_chunks: collision_cell_builder.get_num_counting_chunks(),
+            total_cell_ids: grid.cell_ids().len() as u32,
+        };
+        self.counters.uniform_data.replace_elem(new_uniform, 0, wgpu_context);
+
+        let particles_added = particle_system.len() - self.counters.touch_counts.len();
+        self.counters.touch_counts.push_all(&vec![0; particles_added], wgpu_context);
+
+        self.bind_resources.bind_group = Self::create_bind_group(wgpu_context, &self.bind_resources.bind_group_layout, grid, collision_cell_builder, &self.counters);
+    }
+
+    fn create_bind_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, grid: &Grid, collision_cell_builder: &CollisionCellBuilder, counters: &ValidationCounters) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                label: Some("Collision color validator bind group"),
+                layout: bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: collision_cell_builder.chunk_obj_count().buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: collision_cell_builder.collision_cells().buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: grid.cell_ids().buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: grid.object_ids().buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: counters.touch_counts.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: counters.violations.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 6,
+                        resource: counters.uniform_data.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 7,
+                        resource: counters.neighbor_violations.buffer().as_entire_binding(),
+                    },
+                ],
+            }
+        )
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Collision color validator bind group layout"),
+            entries: &[
+                // Chunk obj count
+                storage_entry(0, true),
+                // Collision cells
+                storage_entry(1, true),
+                // Cell IDs
+                storage_entry(2, true),
+                // Object IDs
+                storage_entry(3, true),
+                // Touch counts, one per particle
+                storage_entry(4, false),
+                // Violations, one per color
+                storage_entry(5, false),
+                // Uniform data
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Neighbor violations, one per color
+                storage_entry(7, false),
+            ],
+        })
+    }
+
+    /// Records the checks of the collision cells recorded before in `encoder` and the readback of their counters,
+    /// unless the counters of an earlier validation are still in flight. `after_submit` must follow the submit
+    /// of the encoder, `poll` returns the violations once the GPU is done.
+    pub fn record_validation(&mut self, wgpu_context: &WgpuContext, encoder: &mut CommandEncoder, gpu_profiler: &GpuProfiler, indirect_dispatch_buffer: &GpuBuffer<u32>) {
+        if !self.readbacks.is_empty() {
+            return;
+        }
+        encoder.clear_buffer(self.counters.violations.buffer(), 0, None);
+        encoder.clear_buffer(self.counters.neighbor_violations.buffer(), 0, None);
+
+        {
+            let mut scope = gpu_profiler.scope("Validate neighbor collision colors", encoder);
+            self.neighbor_validation_shader.indirect_dispatch(
+                &mut scope,
+                indirect_dispatch_buffer.buffer(),
+                0,
+                Some(vec![(0u32, bytemuck::bytes_of(&CellColor {
+                    color: 0
+                }))]),
+                &self.bind_resources.bind_group
+            );
+        }
+
+        for color in 1u32..=NUM_COLORS as u32 {
+            encoder.clear_buffer(self.counters.touch_counts.buffer(), 0, None);
+            let mut scope = gpu_profiler.scope(format!("Validate collision colors - Color {}", color), encoder);
+            self.validation_shader.indirect_dispatch(
+                &mut scope,
+                indirect_dispatch_buffer.buffer(),
+                0,
+                Some(vec![(0u32, bytemuck::bytes_of(&CellColor {
+                    color
+                }))]),
+                &self.bind_resources.bind_group
+            );
+        }
+
+        self.readbacks.record_request(wgpu_context, encoder, SHARED_PARTICLES_LABEL, &self.counters.violations, 0..NUM_COLORS);
+        self.readbacks.record_request(wgpu_context, encoder, NEIGHBORING_CELLS_LABEL, &self.counters.neighbor_violations, 0..NUM_COLORS);
+    }
+
+    /// Starts the readback of the counters recorded by `record_validation`, once its encoder is submitted.
+    pub fn after_submit(&mut self) {
+        self.readbacks.after_submit();
+    }
+
+    /// Returns the violations found by the last validation once both of its counters are read back, without blocking.
+    pub fn poll(&mut self, wgpu_context: &WgpuContext) -> Option<ColorViolations> {
+        for readback in self.readbacks.poll(wgpu_context) {
+            let counts: [u32; NUM_COLORS] = readback.to_vec().try_into().unwrap();
+            match readback.label {
+                SHARED_PARTICLES_LABEL => self.shared_particles = Some(counts),
+                _ => self.neighboring_cells = Some(counts),
+            }
+        }
+        if !self.readbacks.is_empty() {
+            return None;
+        }
+        Some(ColorViolations {
+            shared_particles: self.shared_particles.take()?,
+            neighboring_cells: self.neighboring_cells.take()?,
+        })
+    }
+}
@@ -0,0 +1,144 @@
+override WORKGROUP_SIZE = 64u;
+
+struct UniformData {
+    num_counting_chunks: u32,
+    total_cell_ids: u32,
+};
+
+@group(0) @binding(0) var<storage, read> chunk_obj_count: array<u32>;
+@group(0) @binding(1) var<storage, read> collision_cells: array<u32>;
+@group(0) @binding(2) var<storage, read> cell_ids: array<u32>;
+@group(0) @binding(3) var<storage, read> object_ids: array<u32>;
+@group(0) @binding(4) var<storage, read_write> touch_counts: array<atomic<u32>>;
+@group(0) @binding(5) var<storage, read_write> violations: array<atomic<u32>>;
+@group(0) @binding(6) var<uniform> uniform_data: UniformData;
+@group(0) @binding(7) var<storage, read_write> neighbor_violations: array<atomic<u32>>;
+
+var<workgroup> num_collision_cells: u32;
+
+var<push_constant> current_cell_color: u32;
+
+// Mirrors solve_collisions, but instead of moving particles it counts how many collision cells
+// of the current color touch each particle. Any particle touched twice would be written by two
+// threads at the same time in the solver, which breaks determinism.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn validate_cell_colors(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(local_invocation_id) local_id: vec3<u32>){
+
+    let tid: u32 = global_id.x;
+
+    load_number_of_collision_cells(local_id.x);
+
+    if tid >= num_collision_cells {
+        return;
+    }
+
+    let start = collision_cells[tid];
+    let cell_hash: u32 = cell_ids[start];
+
+    if get_cell_color(cell_hash) != current_cell_color {
+        return;
+    }
+
+    for(var i: u32 = start; i < uniform_data.total_cell_ids; i++){
+        if cell_ids[i] != cell_hash {
+            break;
+        }
+        let previous_touches = atomicAdd(&touch_counts[object_ids[i]], 1u);
+        if previous_touches > 0u {
+            atomicAdd(&violations[current_cell_color - 1u], 1u);
+        }
+    }
+}
+
+// Checks that no collision cell has a neighbouring collision cell of the same color (8-neighbourhood).
+// Objects overlap up to 4 cells, so neighbouring cells share objects and must never be solved in the
+// same pass, whatever the coloring scheme is. Each offending pair is counted from both sides.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn validate_neighbor_colors(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(local_invocation_id) local_id: vec3<u32>){
+
+    let tid: u32 = global_id.x;
+
+    load_number_of_collision_cells(local_id.x);
+
+    if tid >= num_collision_cells {
+        return;
+    }
+
+    let cell_hash: u32 = cell_ids[collision_cells[tid]];
+    let cell_color = get_cell_color(cell_hash);
+    let cell = vec2<i32>(morton_decode(cell_hash));
+
+    for(var dy: i32 = -1; dy <= 1; dy++){
+        for(var dx: i32 = -1; dx <= 1; dx++){
+            let neighbor = cell + vec2<i32>(dx, dy);
+            if (dx == 0 && dy == 0) || neighbor.x < 0 || neighbor.y < 0 {
+                continue;
+            }
+            let neighbor_hash = morton_encode(vec2<u32>(neighbor));
+            if get_cell_color(neighbor_hash) == cell_color && is_collision_cell(neighbor_hash) {
+                atomicAdd(&neighbor_violations[cell_color - 1u], 1u);
+            }
+        }
+    }
+}
+
+// True if the sorted cell ids hold at least two objects in the cell
+fn is_collision_cell(cell_hash: u32) -> bool {
+    var low: u32 = 0u;
+    var high: u32 = uniform_data.total_cell_ids;
+    while low < high {
+        let mid = (low + high) / 2u;
+        if cell_ids[mid] < cell_hash {
+            low = mid + 1u;
+        }
+        else {
+            high = mid;
+        }
+    }
+    return low + 1u < uniform_data.total_cell_ids && cell_ids[low] == cell_hash && cell_ids[low + 1u] == cell_hash;
+}
+
+fn load_number_of_collision_cells(local_id: u32) {
+    if local_id == 0 {
+        num_collision_cells = chunk_obj_count[uniform_data.num_counting_chunks - 1u];
+    }
+    workgroupBarrier();
+}
+
+// Must match get_cell_color in collision_solver.wgsl and morton::cell_color
+fn get_cell_color(cell_hash: u32) -> u32 {
+    let cell_grid_coords: vec2<u32> = morton_decode(cell_hash);
+    return 1u + (cell_grid_coords.x % 2u) + (cell_grid_coords.y % 2u) * 2u;
+}
+
+/// Spreads the lower 16 bits of `n` to the even bit positions.
+fn split_by_bits(n: u32) -> u32 {
+    var x = n & 0x0000FFFF;
+    x = (x | (x << 8)) & 0x00FF00FF;
+    x = (x | (x << 4)) & 0x0F0F0F0F;
+    x = (x | (x << 2)) & 0x33333333;
+    x = (x | (x << 1)) & 0x55555555;
+    return x;
+}
+
+fn morton_encode(cell: vec2<u32>) -> u32 {
+    return split_by_bits(cell.x) | (split_by_bits(cell.y) << 1);
+}
+
+/// Compacts bits from every other position to the lower 16 bits.
+/// This is the inverse of `split_by_bits`.
+/// Example (2-bit): n = 5 (binary 0101) becomes 3 (binary 11).
+fn unsplit_by_bits(n: u32) -> u32 {
+    var x = n & 0x55555555;
+    x = (x | (x >> 1)) & 0x33333333;
+    x = (x | (x >> 2)) & 0x0F0F0F0F;
+    x = (x | (x >> 4)) & 0x00FF00FF;
+    x = (x | (x >> 8)) & 0x0000FFFF;
+    return x;
+}
+
+/// Decodes a 1D Morton index back into 2D coordinates.
+/// Example: 15 (binary 1111) -> (x=3, y=3).
+fn morton_decode(morton_code: u32) -> vec2<u32> {
+    return vec2<u32>(unsplit_by_bits(morton_code), unsplit_by_bits(morton_code >> 1));
+}
@@ -1,25 +1,77 @@
-use wgpu::{BindGroupLayout, PushConstantRange};
-use wgpu_profiler::GpuProfiler;
+use glam::Vec2;
+use wgpu::PushConstantRange;
+use crate::utils::profiler::GpuProfiler;
 use crate::grid::grid::{Grid, MAX_CELLS_PER_OBJECT};
-use crate::particles::particle_system::ParticleSystem;
+use crate::particles::particle_system::{ParticleSystem, MASS_CHANNEL, PARTICLE_FLAGS_CHANNEL};
 use crate::physics::collision_cell_builder::{CollisionCellBuilder};
 use crate::renderer::wgpu_context::WgpuContext;
-use crate::utils::bind_resources::BindResources;
+use crate::utils::bind_resources::{Bindings, BindingsBuilder};
 use crate::utils::compute_shader::ComputeShader;
 use crate::utils::gpu_buffer::GpuBuffer;
+use crate::physics::collision_system::{SolverBatching, SolverConfig, StaticSegment, COLLISION_FILTER_CHANNEL, RESTITUTION_CHANNEL};
+use crate::physics::static_colliders::StaticColliders;
+use crate::physics::contact_stats::{CollisionStats, ContactStats, ContactStatsData, ContactStatsReadback};
 
-const WORKGROUP_SIZE: u32 = 64;
+/// Default workgroup size, see `CollisionSolver::workgroup_size` and `KernelTuning`.
+pub(crate) const WORKGROUP_SIZE: u32 = 64;
+const NO_CHANNEL: u32 = u32::MAX;
 
 pub struct CollisionSolver {
     collision_solver_shader: ComputeShader,
-    bind_resources: BindResources,
-    uniform_data: GpuBuffer<UniformData>
+    segment_collision_shader: ComputeShader,
+    accumulate_shader: ComputeShader,
+    apply_deltas_shader: ComputeShader,
+    cell_stats_shader: ComputeShader,
+    bindings: Bindings,
+    uniform_data: GpuBuffer<UniformData>,
+    static_segments: Vec<StaticSegment>,
+    segments: GpuBuffer<StaticSegment>, // Holds a single unused segment when there are none
+    num_particles: u32,
+    config: SolverConfig,
+    extras_stride: u32,
+    restitution_offset: u32, // NO_CHANNEL if the particles have no restitution channel
+    flags_offset: u32, // NO_CHANNEL if the particles have no flags channel
+    filter_offset: u32, // NO_CHANNEL if the particles have no collision filter channel
+    mass_offset: u32, // NO_CHANNEL if the particles have no mass channel
+    contact_stats: GpuBuffer<ContactStatsData>,
+    contact_stats_readback: ContactStatsReadback,
+    stats_copy_recorded: bool, // The copy of the contact stats waits for the submit to be mapped
+    last_stats: Option<CollisionStats>,
+    deltas: GpuBuffer<i32>, // Fixed-point x and y correction of every particle, see SolverBatching::Atomics
+    grid_origin: Vec2,
+    cell_size: f32,
+    table_bits: u32, // 0 with morton cell ids, see Grid::set_spatial_hash
+    period: Vec2, // Zero if the world does not wrap, see Grid::set_period
 }
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct CellColor{
+struct PushConstantsData{
     color: u32,
+    stiffness: f32,
+    extras_stride: u32,
+    restitution_offset: u32,
+    restitution_combine: u32,
+    record_stats: u32,
+    slop: f32,
+    mass_exponent: f32,
+    grid_origin: Vec2,
+    cell_size: f32,
+    max_particles_per_cell: u32,
+    flags_offset: u32,
+    table_bits: u32,
+    filter_offset: u32,
+    mass_offset: u32,
+    period: Vec2,
+}
+
+/// Where the channels read by the solver are in the extras of a particle, `NO_CHANNEL` for the missing ones.
+struct ExtrasLayout {
+    extras_stride: u32,
+    restitution_offset: u32,
+    flags_offset: u32,
+    filter_offset: u32,
+    mass_offset: u32,
 }
 
 #[repr(C)]
@@ -27,219 +79,334 @@ struct CellColor{
 struct UniformData {
     num_counting_chunks: u32,
     total_cell_ids: u32, 
+    num_segments: u32,
+    num_particles: u32,
 }
 
 impl CollisionSolver {
-    pub fn new(wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid, collision_cell_builder: &CollisionCellBuilder) -> Self {
+    pub fn new(wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid, collision_cell_builder: &CollisionCellBuilder, config: SolverConfig) -> Self {
         let uniform_data = GpuBuffer::new(
             wgpu_context,
             vec![UniformData{
                 num_counting_chunks: collision_cell_builder.get_num_counting_chunks(),
                 total_cell_ids: particle_system.len() as u32 * MAX_CELLS_PER_OBJECT,
+                num_segments: 0,
+                num_particles: particle_system.len() as u32,
             }],
             wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         );
+        let segments = Self::create_segments_buffer(wgpu_context, &[]);
+        let contact_stats = GpuBuffer::new(wgpu_context, vec![ContactStatsData::default()], wgpu::BufferUsages::STORAGE);
+        let deltas = GpuBuffer::new(wgpu_context, vec![0; particle_system.len().max(1) * 2], wgpu::BufferUsages::STORAGE);
         
-        let bind_resources = Self::create_bind_resources(wgpu_context, particle_system, grid, collision_cell_builder, &uniform_data);
+        let bindings = Self::bindings_builder(particle_system, grid, collision_cell_builder, &uniform_data, &segments, &contact_stats, &deltas).build(wgpu_context);
         
-        let collision_solver_shader = ComputeShader::new(
+        let workgroup_size = Self::workgroup_size(wgpu_context);
+        let create_shader = |entry_point: &str| ComputeShader::new(
             wgpu_context,
             wgpu::include_wgsl!("collision_solver.wgsl"),
-            "solve_collisions",
-            &bind_resources.bind_group_layout,
-            (WORKGROUP_SIZE, 1, 1),
+            entry_point,
+            bindings.layout(),
+            (workgroup_size, 1, 1),
             &vec![
-                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+                ("WORKGROUP_SIZE", workgroup_size as f64),
             ],
             &vec![
                 PushConstantRange{
                     stages: wgpu::ShaderStages::COMPUTE,
-                    range: 0..size_of::<CellColor>() as u32,
+                    range: 0..size_of::<PushConstantsData>() as u32,
                 }
             ]
         );
+        let collision_solver_shader = create_shader("solve_collisions");
+        let segment_collision_shader = create_shader("solve_segment_collisions");
+        let accumulate_shader = create_shader("accumulate_collisions");
+        let apply_deltas_shader = create_shader("apply_deltas");
+        let cell_stats_shader = create_shader("record_cell_stats");
         
+        let ExtrasLayout { extras_stride, restitution_offset, flags_offset, filter_offset, mass_offset } = Self::extras_layout(particle_system);
         Self {
             collision_solver_shader,
-            bind_resources,
+            segment_collision_shader,
+            accumulate_shader,
+            apply_deltas_shader,
+            cell_stats_shader,
+            bindings,
             uniform_data,
+            static_segments: Vec::new(),
+            segments,
+            num_particles: particle_system.len() as u32,
+            config,
+            extras_stride,
+            restitution_offset,
+            flags_offset,
+            filter_offset,
+            mass_offset,
+            contact_stats,
+            contact_stats_readback: ContactStatsReadback::new(wgpu_context),
+            stats_copy_recorded: false,
+            last_stats: None,
+            deltas,
+            grid_origin: grid.origin(),
+            cell_size: grid.cell_size(),
+            table_bits: grid.spatial_hash_bits().unwrap_or(0),
+            period: grid.period().unwrap_or(Vec2::ZERO),
         }
     }
 
+    /// Workgroup size of the solver kernels on this device, the one of `KernelTuning` if the device allows it.
+    /// The collision cell builder writes the indirect args of the color passes for it.
+    pub fn workgroup_size(wgpu_context: &WgpuContext) -> u32 {
+        wgpu_context.capabilities().workgroup_size(wgpu_context.kernel_tuning().collision_workgroup_size)
+    }
+
+    /// Stride of the extras buffer and offsets of the channels the solver reads in it.
+    fn extras_layout(particle_system: &ParticleSystem) -> ExtrasLayout {
+        let channels = particle_system.channels();
+        let offset = |name: &str| channels.find(name).map_or(NO_CHANNEL, |id| channels.offset(id));
+        ExtrasLayout {
+            extras_stride: channels.stride(),
+            restitution_offset: offset(RESTITUTION_CHANNEL),
+            flags_offset: offset(PARTICLE_FLAGS_CHANNEL),
+            filter_offset: offset(COLLISION_FILTER_CHANNEL),
+            mass_offset: offset(MASS_CHANNEL),
+        }
+    }
+
+    pub fn set_config(&mut self, config: SolverConfig) {
+        self.config = config;
+    }
+
+    pub fn config(&self) -> SolverConfig {
+        self.config
+    }
+
+    /// Contact statistics of a recent step, None until the first readback completed.
+    pub fn last_contact_stats(&self) -> Option<ContactStats> {
+        self.last_stats.map(|stats| stats.contacts)
+    }
+
+    /// Contact and cell statistics of a recent step, None until the first readback completed.
+    pub fn last_stats(&self) -> Option<CollisionStats> {
+        self.last_stats
+    }
+
+    /// Follows `Grid::set_origin`, the atomic batching finds the cell of every contact point.
+    pub fn set_grid_origin(&mut self, origin: Vec2) {
+        self.grid_origin = origin;
+    }
+
+    /// Follows `Grid::set_spatial_hash`: the colors and the contact cells come from the hashed cell ids.
+    pub fn set_spatial_hash(&mut self, table_bits: Option<u32>) {
+        self.table_bits = table_bits.unwrap_or(0);
+    }
+
+    /// Follows `Grid::set_period`: the particles on both sides of the seam are pushed apart across it.
+    pub fn set_period(&mut self, period: Option<Vec2>) {
+        self.period = period.unwrap_or(Vec2::ZERO);
+    }
+
+    pub fn num_particles(&self) -> u32 {
+        self.num_particles
+    }
+
+    pub fn static_segments(&self) -> &[StaticSegment] {
+        &self.static_segments
+    }
+
+    /// Replaces the static segments the particles collide with.
+    pub fn set_static_segments(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid, collision_cell_builder: &CollisionCellBuilder, static_segments: Vec<StaticSegment>) {
+        self.segments = Self::create_segments_buffer(wgpu_context, &static_segments);
+        self.static_segments = static_segments;
+        self.refresh_buffers(wgpu_context, particle_system, grid, collision_cell_builder);
+    }
+
+    fn create_segments_buffer(wgpu_context: &WgpuContext, static_segments: &[StaticSegment]) -> GpuBuffer<StaticSegment> {
+        // Bindings can not be empty
+        let segments = if static_segments.is_empty() { vec![StaticSegment::default()] } else { static_segments.to_vec() };
+        GpuBuffer::new(wgpu_context, segments, wgpu::BufferUsages::STORAGE)
+    }
+
     pub fn refresh_buffers(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid, collision_cell_builder: &CollisionCellBuilder) {
+        self.num_particles = particle_system.len() as u32;
+        self.cell_size = grid.cell_size();
+        self.table_bits = grid.spatial_hash_bits().unwrap_or(0);
+        self.period = grid.period().unwrap_or(Vec2::ZERO);
+        let num_deltas = self.num_particles as usize * 2;
+        if num_deltas > self.deltas.len() {
+            self.deltas.push_all(&vec![0; num_deltas - self.deltas.len()], wgpu_context);
+        }
         let new_uniform = UniformData {
             num_counting_chunks: collision_cell_builder.get_num_counting_chunks(),
             total_cell_ids: grid.cell_ids().len() as u32,
+            num_segments: self.static_segments.len() as u32,
+            num_particles: self.num_particles,
         };
         
         self.uniform_data.replace_elem(new_uniform, 0, wgpu_context);
         
-        let bind_group = Self::create_bind_group(wgpu_context, &self.bind_resources.bind_group_layout, particle_system, grid, collision_cell_builder, &self.uniform_data);
-        self.bind_resources.bind_group = bind_group;
+        self.bindings.rebind(Self::bindings_builder(particle_system, grid, collision_cell_builder, &self.uniform_data, &self.segments, &self.contact_stats, &self.deltas));
+        ExtrasLayout {
+            extras_stride: self.extras_stride,
+            restitution_offset: self.restitution_offset,
+            flags_offset: self.flags_offset,
+            filter_offset: self.filter_offset,
+            mass_offset: self.mass_offset,
+        } = Self::extras_layout(particle_system);
     }
     
-    fn create_bind_resources(wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid, collision_cell_builder: &CollisionCellBuilder, uniform_data: &GpuBuffer<UniformData>) -> BindResources {
-        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
-        let bind_group = Self::create_bind_group(wgpu_context, &bind_group_layout, particle_system, grid, collision_cell_builder, uniform_data);
-        BindResources {
-            bind_group,
-            bind_group_layout,
-        }
+    fn bindings_builder(particle_system: &ParticleSystem, grid: &Grid, collision_cell_builder: &CollisionCellBuilder, uniform_data: &GpuBuffer<UniformData>, segments: &GpuBuffer<StaticSegment>, contact_stats: &GpuBuffer<ContactStatsData>, deltas: &GpuBuffer<i32>) -> BindingsBuilder {
+        BindingsBuilder::new("Collision solver")
+            .storage_ro(collision_cell_builder.chunk_obj_count())
+            .storage_ro(collision_cell_builder.collision_cells())
+            .storage_ro(grid.cell_ids())
+            .storage_ro(grid.object_ids())
+            .storage_rw(particle_system.positions())
+            .storage_ro(particle_system.radius())
+            .uniform(uniform_data)
+            // Previous positions, only written when there is a restitution channel
+            .storage_rw(&particle_system.buffers().previous_positions)
+            // Extras (per-particle channels)
+            .storage_ro(&particle_system.buffers().extras)
+            .storage_ro(segments)
+            .storage_rw(contact_stats)
+            // Fixed-point deltas of the atomic batching
+            .storage_rw(deltas)
     }
-    
-    fn create_bind_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, particle_system: &ParticleSystem, grid: &Grid, collision_cell_builder: &CollisionCellBuilder, uniform_data: &GpuBuffer<UniformData>) -> wgpu::BindGroup {
-        wgpu_context.get_device().create_bind_group(
-            &wgpu::BindGroupDescriptor {
-                label: None,
-                layout: bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: collision_cell_builder.chunk_obj_count().buffer().as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: collision_cell_builder.collision_cells().buffer().as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 2,
-                        resource: grid.cell_ids().buffer().as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 3,
-                        resource: grid.object_ids().buffer().as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 4,
-                        resource: particle_system.positions().buffer().as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 5,
-                        resource: particle_system.radius().buffer().as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 6,
-                        resource: uniform_data.buffer().as_entire_binding(),
-                    },
-                ],
+
+    /// Step 4: Records the solve of the collisions between objects in the same cell, then against the static segments
+    /// and circles. The first iteration also records the contact statistics, read back without stalling: the
+    /// adaptive iterations follow the contacts of a step a few frames old. `after_submit` must follow the submit of the encoder.
+    pub fn record_solve(&mut self, wgpu_context: &WgpuContext, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler, indirect_dispatch_buffer: &GpuBuffer<u32>, static_colliders: &StaticColliders){
+        if let Some(stats) = self.contact_stats_readback.poll(wgpu_context) {
+            if let Some(adaptive_iterations) = self.config.adaptive_iterations {
+                self.config.iterations = adaptive_iterations.next_iterations(self.config.iterations, &stats.contacts);
             }
-        )
-    }
-    
-    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout{
-        let compute_bind_group_layout = wgpu::BindGroupLayoutDescriptor {
-            label: Some("Collision solver bind group layout"),
-            entries: &[
-                // Chunk obj count
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // Collision cells
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true},
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // Cell IDs
-                wgpu::BindGroupLayoutEntry {
-                    binding: 2,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true }, 
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // Object IDs
-                wgpu::BindGroupLayoutEntry {
-                    binding: 3,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // Positions
-                wgpu::BindGroupLayoutEntry {
-                    binding: 4,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // Radius
-                wgpu::BindGroupLayoutEntry {
-                    binding: 5,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // Uniform data
-                wgpu::BindGroupLayoutEntry {
-                    binding: 6,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
-        };
+            let was_overflowing = self.last_stats.is_some_and(|last_stats| last_stats.cells.overflow_cells > 0);
+            if stats.cells.overflow_cells > 0 && !was_overflowing {
+                log::warn!(
+                    "{} collision cells hold more than {} particles (up to {}), {} particles were not solved in them",
+                    stats.cells.overflow_cells, self.config.max_particles_per_cell, stats.cells.max_particles_per_cell, stats.cells.overflow_particles
+                );
+            }
+            self.last_stats = Some(stats);
+        }
+        // Only one read in flight, the steps in between are not measured
+        let record_stats = !self.contact_stats_readback.is_pending() && !self.stats_copy_recorded;
 
-        wgpu_context.get_device().create_bind_group_layout(&compute_bind_group_layout)
-    }
-    
+        if record_stats {
+            encoder.clear_buffer(self.contact_stats.buffer(), 0, None);
+            let mut scope = gpu_profiler.scope("Collision Cell Stats", encoder);
+            self.cell_stats_shader.indirect_dispatch(
+                &mut scope,
+                indirect_dispatch_buffer.buffer(),
+                0,
+                Some(vec![(0u32, bytemuck::bytes_of(&self.push_constants(0, true)))]),
+                &self.bindings.bind_group()
+            );
+        }
+        
+        for iteration in 0..self.config.iterations {
+            let record_iteration = record_stats && iteration == 0;
+            if self.config.batching == SolverBatching::Atomics {
+                self.record_atomic_iteration(encoder, gpu_profiler, indirect_dispatch_buffer, record_iteration);
+                self.solve_segment_collisions(encoder, gpu_profiler);
+                static_colliders.solve_collisions(encoder, gpu_profiler);
+                continue;
+            }
+            for color in 1u32..=4u32 {
 
+                let scope_label = format!("Solve Collisions - Color {}", color);
 
-    /// Step 4: Solves collisions between objects in the same cell.
-    pub fn solve_collisions(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, indirect_dispatch_buffer: &GpuBuffer<u32>){
-        let mut encoder = wgpu_context.get_device().create_command_encoder(
-            &wgpu::CommandEncoderDescriptor { label: Some("Collision Encoder Color") }
-        );
-        
-        for color in 1u32..=4u32 {
-            
-            let scope_label = format!("Solve Collisions - Color {}", color);
-            
-            {
-                let mut scope = gpu_profiler.scope(scope_label, &mut encoder);
+                let mut scope = gpu_profiler.scope(scope_label, encoder);
 
                 self.collision_solver_shader.indirect_dispatch(
                     &mut scope,
                     indirect_dispatch_buffer.buffer(),
                     0,
-                    Some(vec![(0u32, bytemuck::bytes_of(&CellColor {
-                        color
-                    }))]),
-                    &self.bind_resources.bind_group
+                    Some(vec![(0u32, bytemuck::bytes_of(&self.push_constants(color, record_iteration)))]),
+                    &self.bindings.bind_group()
                 );
             }
-            gpu_profiler.resolve_queries(&mut encoder);
+
+            self.solve_segment_collisions(encoder, gpu_profiler);
+            static_colliders.solve_collisions(encoder, gpu_profiler);
+        }
+        if record_stats {
+            self.contact_stats_readback.copy(encoder, &self.contact_stats);
+            self.stats_copy_recorded = true;
         }
-        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
     }
-}
\ No newline at end of file
+
+    /// Records one iteration of `SolverBatching::Atomics`: every collision cell adds the corrections of its contacts
+    /// to the deltas, then every particle applies and clears its own.
+    fn record_atomic_iteration(&self, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler, indirect_dispatch_buffer: &GpuBuffer<u32>, record_stats: bool) {
+        let push_constants = self.push_constants(0, record_stats);
+        {
+            let mut scope = gpu_profiler.scope("Solve Collisions - Atomics", encoder);
+            self.accumulate_shader.indirect_dispatch(
+                &mut scope,
+                indirect_dispatch_buffer.buffer(),
+                0,
+                Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                &self.bindings.bind_group()
+            );
+        }
+        if self.num_particles == 0 {
+            return;
+        }
+        let mut scope = gpu_profiler.scope("Apply Collision Deltas", encoder);
+        self.apply_deltas_shader.dispatch_by_items(
+            &mut scope,
+            (self.num_particles, 1, 1),
+            Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+            &self.bindings.bind_group()
+        );
+    }
+
+    /// Starts the readback of the contact stats copied by the last `record_solve`, once its encoder is submitted.
+    pub fn after_submit(&mut self) {
+        if self.stats_copy_recorded {
+            self.contact_stats_readback.map();
+            self.stats_copy_recorded = false;
+        }
+    }
+
+    /// Records the pass pushing the particles out of the static segments, if there are any.
+    pub fn solve_segment_collisions(&self, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler) {
+        if self.static_segments.is_empty() || self.num_particles == 0 {
+            return;
+        }
+        {
+            let mut scope = gpu_profiler.scope("Solve Segment Collisions", encoder);
+            self.segment_collision_shader.dispatch_by_items(
+                &mut scope,
+                (self.num_particles, 1, 1),
+                // The color is not used by the segment pass
+                Some(vec![(0u32, bytemuck::bytes_of(&self.push_constants(0, false)))]),
+                &self.bindings.bind_group()
+            );
+        }
+    }
+
+    fn push_constants(&self, color: u32, record_stats: bool) -> PushConstantsData {
+        PushConstantsData {
+            color,
+            stiffness: self.config.iteration_stiffness(),
+            extras_stride: self.extras_stride,
+            restitution_offset: self.restitution_offset,
+            restitution_combine: self.config.restitution_combine as u32,
+            record_stats: record_stats as u32,
+            slop: self.config.slop,
+            mass_exponent: self.config.method.mass_exponent(),
+            grid_origin: self.grid_origin,
+            cell_size: self.cell_size,
+            max_particles_per_cell: self.config.max_particles_per_cell.max(2),
+            flags_offset: self.flags_offset,
+            table_bits: self.table_bits,
+            filter_offset: self.filter_offset,
+            mass_offset: self.mass_offset,
+            period: self.period,
+        }
+    }
+}
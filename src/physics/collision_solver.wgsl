@@ -1,12 +1,47 @@
 override WORKGROUP_SIZE = 64u;
-const STIFFNESS: f32 = 0.6;
 
+const NO_CHANNEL = 0xffffffffu;
+// Must match the flags of particle_system.rs, all make a particle kinematic
+const PINNED_FLAG = 1u;
+const HELD_FLAG = 2u;
+const SLEEPING_FLAG = 4u;
+// Must match RestitutionCombine
+const COMBINE_AVERAGE = 0u;
+const COMBINE_MIN = 1u;
+const COMBINE_MAX = 2u;
+const COMBINE_MULTIPLY = 3u;
+// Must match contact_stats::OVERLAP_SCALE
+const OVERLAP_SCALE = 1024.0;
+// Fixed-point scale of the deltas of the atomic batching
+const DELTA_SCALE = 65536.0;
+
+
+// Must match ContactStatsData
+struct ContactStats {
+    contacts: atomic<u32>,
+    overlap_sum: atomic<u32>,
+    max_overlap: atomic<u32>,
+    // Filled by record_cell_stats
+    max_particles_per_cell: atomic<u32>,
+    collision_cells: atomic<u32>,
+    overflow_cells: atomic<u32>,
+    overflow_particles: atomic<u32>,
+    _padding: u32,
+}
 
 struct UniformData {
     num_counting_chunks: u32,
     total_cell_ids: u32,
+    num_segments: u32,
+    num_particles: u32,
 };
 
+// Static collider, see CollisionSystem::add_static_segment
+struct Segment {
+    start: vec2<f32>,
+    end: vec2<f32>,
+}
+
 @group(0) @binding(0) var<storage, read> chunk_obj_count: array<u32>;
 @group(0) @binding(1) var<storage, read> collision_cells: array<u32>;
 @group(0) @binding(2) var<storage, read> cell_ids: array<u32>;
@@ -14,12 +49,50 @@ struct UniformData {
 @group(0) @binding(4) var<storage, read_write> positions: array<vec2<f32>>;
 @group(0) @binding(5) var<storage, read> radius: array<f32>;
 @group(0) @binding(6) var<uniform> uniform_data: UniformData;
+@group(0) @binding(7) var<storage, read_write> previous_positions: array<vec2<f32>>;
+@group(0) @binding(8) var<storage, read> extras: array<u32>;
+// Holds a single unused segment when there are none
+@group(0) @binding(9) var<storage, read> segments: array<Segment>;
+// Cleared every step, filled by the first iteration
+@group(0) @binding(10) var<storage, read_write> contact_stats: ContactStats;
+// x and y corrections of every particle in fixed point, only used by accumulate_collisions and apply_deltas
+@group(0) @binding(11) var<storage, read_write> deltas: array<atomic<i32>>;
 
 
 
 var<workgroup> num_collision_cells: u32;
 
-var<push_constant> current_cell_color: u32;
+struct PushConstantsData {
+    cell_color: u32,
+    stiffness: f32,
+    extras_stride: u32,
+    // Word offset of the restitution channel, NO_CHANNEL if the particles have none
+    restitution_offset: u32,
+    restitution_combine: u32,
+    // 1 during the first iteration of a step, the contacts are recorded into contact_stats
+    record_stats: u32,
+    // Penetration left uncorrected, relative to the sum of the radii
+    slop: f32,
+    // The mass of a particle is its radius to this power, see SolverMethod::mass_exponent
+    mass_exponent: f32,
+    // World position of the corner of cell (0, 0) and cell size, see Grid::origin
+    grid_origin: vec2<f32>,
+    cell_size: f32,
+    // Particles of a collision cell solved against each other, see SolverConfig::max_particles_per_cell
+    max_particles_per_cell: u32,
+    // Word offset of the particle flags channel, NO_CHANNEL if the particles have none
+    flags_offset: u32,
+    // Bits of the spatial hash table of the grid, 0 for morton cell ids, see spatial_hash.rs
+    table_bits: u32,
+    // Word offset of the collision filter channel, NO_CHANNEL if the particles have none
+    filter_offset: u32,
+    // Word offset of the mass channel, NO_CHANNEL to weigh the particles by their radii
+    mass_offset: u32,
+    // Size of the periodic world, zero if it does not wrap, see Grid::set_period
+    period: vec2<f32>,
+}
+
+var<push_constant> push_constants: PushConstantsData;
 
 // Use the collision cells to solve the collisions between objects.
 @compute @workgroup_size(WORKGROUP_SIZE)
@@ -39,7 +112,7 @@ fn solve_collisions(@builtin(global_invocation_id) global_id: vec3<u32>, @builti
     let cell_color: u32 = get_cell_color(cell_hash);
 
     // Only resolve collisions if the cell color matches the current one
-    if cell_color == current_cell_color {
+    if cell_color == push_constants.cell_color {
         resolve_cell_collisons(cell_hash, start);
     }
 
@@ -52,7 +125,11 @@ fn load_number_of_collision_cells(local_id: u32) {
     workgroupBarrier();
 }
 
+// Must match morton::cell_color, which documents the coloring, and spatial_hash::cell_id_color
 fn get_cell_color(cell_hash: u32) -> u32 {
+    if push_constants.table_bits != 0u {
+        return 1u + (cell_hash & 3u);
+    }
     let cell_grid_coords: vec2<u32> = morton_decode(cell_hash);
     return 1u + (cell_grid_coords.x % 2u) + (cell_grid_coords.y % 2u) * 2u;
 }
@@ -63,9 +140,15 @@ fn are_colliding(sq_distance: f32, rad_1: f32, rad_2: f32) -> bool {
     return sq_radius_sum > sq_distance;
 }
 
+// The particles of the cell starting at start that are solved, past the cap are left for the next steps
+fn solved_cell_end(start: u32) -> u32 {
+    return min(uniform_data.total_cell_ids, start + push_constants.max_particles_per_cell);
+}
+
 fn resolve_cell_collisons(cell_hash: u32, start: u32) {
+    let end = solved_cell_end(start);
 
-    for(var i: u32 = start; i < uniform_data.total_cell_ids; i++){
+    for(var i: u32 = start; i < end; i++){
         if cell_ids[i] != cell_hash {
             break;
         }
@@ -74,13 +157,16 @@ fn resolve_cell_collisons(cell_hash: u32, start: u32) {
 
 
         // Check collisions with the current object and the rest of the objects in the cell
-        for(var j: u32 = i + 1; j < uniform_data.total_cell_ids; j++){
+        for(var j: u32 = i + 1; j < end; j++){
             let other_cell_hash = cell_ids[j];
 
             // Check if the other object is inside the same cell
             if other_cell_hash != cell_hash {break;}
 
             let other_object_id = object_ids[j];
+            if !can_collide(object_id, other_object_id) {
+                continue;
+            }
 
             let obj_1_pos = positions[object_id];
             let obj_2_pos = positions[other_object_id];
@@ -88,27 +174,41 @@ fn resolve_cell_collisons(cell_hash: u32, start: u32) {
             let obj_2_radius = radius[other_object_id];
 
 
-            let vec_i_j = obj_1_pos - obj_2_pos;
+            let vec_i_j = separation(obj_1_pos, obj_2_pos);
 
             let distance = length(vec_i_j);
 
             if are_colliding(distance * distance, obj_1_radius, obj_2_radius) && distance > 0.0001{
                 // Solve collision
                 let penetration_depth = (obj_1_radius + obj_2_radius) - distance;
+                if push_constants.record_stats != 0u {
+                    record_contact(penetration_depth / (obj_1_radius + obj_2_radius));
+                }
                 let collision_direction_vector = vec_i_j / distance;
 
 
-                let correction_vector: vec2<f32> = collision_direction_vector * penetration_depth * STIFFNESS;
+                let corrected_depth = max(penetration_depth - push_constants.slop * (obj_1_radius + obj_2_radius), 0.0);
+                let correction_vector: vec2<f32> = collision_direction_vector * corrected_depth * push_constants.stiffness;
 
-                let inv_mass_1 = 1/obj_1_radius;
-                let inv_mass_2 = 1/obj_2_radius;
+                let inv_mass_1 = inverse_mass(object_id, obj_1_radius);
+                let inv_mass_2 = inverse_mass(other_object_id, obj_2_radius);
+                // Two kinematic particles do not push each other
+                if inv_mass_1 + inv_mass_2 == 0.0 {
+                    continue;
+                }
+                let weight_1 = inv_mass_1 / (inv_mass_1+inv_mass_2);
+                let weight_2 = inv_mass_2 / (inv_mass_1+inv_mass_2);
 
                 // Displace each particles by half of the penetration depth along the collision normal.
-                let displacement = correction_vector * (inv_mass_1 / (inv_mass_1+inv_mass_2));
-                let displacement_2 = correction_vector * (inv_mass_2 / (inv_mass_1+inv_mass_2));
+                let displacement = correction_vector * weight_1;
+                let displacement_2 = correction_vector * weight_2;
 
                 positions[object_id] += displacement;
                 positions[other_object_id] -= displacement_2;
+
+                if push_constants.restitution_offset != NO_CHANNEL {
+                    apply_restitution(object_id, other_object_id, collision_direction_vector, displacement, displacement_2, weight_1, weight_2);
+                }
             }
 
         }
@@ -117,6 +217,312 @@ fn resolve_cell_collisons(cell_hash: u32, start: u32) {
 
 }
 
+// SolverBatching::Atomics: one thread per collision cell, whatever its color. The corrections are added to the
+// deltas instead of the positions, so the contacts of neighbouring cells can be solved at the same time.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn accumulate_collisions(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(local_invocation_id) local_id: vec3<u32>){
+    let tid: u32 = global_id.x;
+
+    load_number_of_collision_cells(local_id.x);
+
+    if tid >= num_collision_cells {
+        return;
+    }
+
+    let start = collision_cells[tid];
+    let cell_hash: u32 = cell_ids[start];
+    let end = solved_cell_end(start);
+
+    for(var i: u32 = start; i < end; i++){
+        if cell_ids[i] != cell_hash {
+            break;
+        }
+        let object_id = object_ids[i];
+
+        for(var j: u32 = i + 1; j < end; j++){
+            if cell_ids[j] != cell_hash {break;}
+            let other_object_id = object_ids[j];
+            if !can_collide(object_id, other_object_id) {
+                continue;
+            }
+
+            let obj_1_pos = positions[object_id];
+            let obj_2_pos = positions[other_object_id];
+            let obj_1_radius = radius[object_id];
+            let obj_2_radius = radius[other_object_id];
+            let vec_i_j = separation(obj_1_pos, obj_2_pos);
+            let distance = length(vec_i_j);
+
+            if !are_colliding(distance * distance, obj_1_radius, obj_2_radius) || distance <= 0.0001 {
+                continue;
+            }
+            // Two particles can share up to 4 cells, only the cell of a point inside both solves their contact
+            let contact_point = obj_1_pos - vec_i_j * (obj_1_radius / (obj_1_radius + obj_2_radius));
+            if contact_cell(contact_point) != cell_hash {
+                continue;
+            }
+
+            let penetration_depth = (obj_1_radius + obj_2_radius) - distance;
+            if push_constants.record_stats != 0u {
+                record_contact(penetration_depth / (obj_1_radius + obj_2_radius));
+            }
+            let corrected_depth = max(penetration_depth - push_constants.slop * (obj_1_radius + obj_2_radius), 0.0);
+            let correction_vector = vec_i_j / distance * corrected_depth * push_constants.stiffness;
+
+            let inv_mass_1 = inverse_mass(object_id, obj_1_radius);
+            let inv_mass_2 = inverse_mass(other_object_id, obj_2_radius);
+            if inv_mass_1 + inv_mass_2 == 0.0 {
+                continue;
+            }
+            add_delta(object_id, correction_vector * inv_mass_1 / (inv_mass_1 + inv_mass_2));
+            add_delta(other_object_id, -correction_vector * inv_mass_2 / (inv_mass_1 + inv_mass_2));
+        }
+    }
+}
+
+// One thread per particle: moves it by its accumulated correction and clears it for the next iteration
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn apply_deltas(@builtin(global_invocation_id) global_id: vec3<u32>){
+    let object_id = global_id.x;
+    if object_id >= uniform_data.num_particles {
+        return;
+    }
+    let delta = vec2<f32>(
+        f32(atomicExchange(&deltas[object_id * 2u], 0)),
+        f32(atomicExchange(&deltas[object_id * 2u + 1u], 0)),
+    );
+    positions[object_id] += delta / DELTA_SCALE;
+}
+
+fn add_delta(object_id: u32, delta: vec2<f32>) {
+    atomicAdd(&deltas[object_id * 2u], i32(round(delta.x * DELTA_SCALE)));
+    atomicAdd(&deltas[object_id * 2u + 1u], i32(round(delta.y * DELTA_SCALE)));
+}
+
+// From the second particle to the first. In a periodic world, to the nearest copy of the first one across the seams
+fn separation(obj_1_pos: vec2<f32>, obj_2_pos: vec2<f32>) -> vec2<f32> {
+    let vec_i_j = obj_1_pos - obj_2_pos;
+    let period = push_constants.period;
+    if period.x <= 0.0 {
+        return vec_i_j;
+    }
+    return vec_i_j - period * round(vec_i_j / period);
+}
+
+// Must match Grid::periodic_cells and periodic_cells in grid.wgsl
+fn periodic_cells(period: vec2<f32>) -> vec2<i32> {
+    return vec2<i32>(max(floor(period / (2.0 * push_constants.cell_size)) * 2.0, vec2<f32>(2.0)));
+}
+
+// Morton or hashed id of the cell holding world_position, like the cell ids of the grid
+fn contact_cell(world_position: vec2<f32>) -> u32 {
+    var cell_coord = vec2<i32>(floor((world_position - push_constants.grid_origin) / push_constants.cell_size));
+    let period = push_constants.period;
+    if period.x > 0.0 {
+        // The contact point can be past the seam, the cells of the grid are wrapped
+        var position = world_position - push_constants.grid_origin;
+        position -= period * floor(position / period);
+        let num_cells = periodic_cells(period);
+        cell_coord = vec2<i32>(floor(position / (period / vec2<f32>(num_cells))));
+        cell_coord = ((cell_coord % num_cells) + num_cells) % num_cells;
+    }
+    if push_constants.table_bits != 0u {
+        return spatial_hash_encode(cell_coord, push_constants.table_bits);
+    }
+    let cell = max(cell_coord, vec2<i32>(0));
+    return split_by_bits(u32(cell.x)) | (split_by_bits(u32(cell.y)) << 1);
+}
+
+// Must match spatial_hash::encode and spatial_hash_encode in grid.wgsl
+fn spatial_hash_encode(cell_coord: vec2<i32>, table_bits: u32) -> u32 {
+    let block = vec2<u32>(cell_coord >> vec2<u32>(1u));
+    let hash = (block.x * 73856093u) ^ (block.y * 19349663u);
+    let slot = hash & ((1u << table_bits) - 1u);
+    let parity = vec2<u32>(cell_coord & vec2<i32>(1));
+    return slot * 4u + parity.x + parity.y * 2u;
+}
+
+fn split_by_bits(n: u32) -> u32 {
+    var x = n & 0x0000FFFF;
+    x = (x | (x << 8)) & 0x00FF00FF;
+    x = (x | (x << 4)) & 0x0F0F0F0F;
+    x = (x | (x << 2)) & 0x33333333;
+    x = (x | (x << 1)) & 0x55555555;
+    return x;
+}
+
+// Kinematic particles have an infinite mass, the other particle takes the whole correction.
+// Without a mass channel, the mass is the radius to the power of mass_exponent
+fn inverse_mass(object_id: u32, particle_radius: f32) -> f32 {
+    if is_kinematic(object_id) {
+        return 0.0;
+    }
+    if push_constants.mass_offset != NO_CHANNEL {
+        return 1.0 / bitcast<f32>(extras[object_id * push_constants.extras_stride + push_constants.mass_offset]);
+    }
+    return 1.0 / pow(particle_radius, push_constants.mass_exponent);
+}
+
+// Pinned, held or asleep, see PINNED_FLAG, HELD_FLAG and SLEEPING_FLAG. Two sleeping particles skip their contact
+fn is_kinematic(object_id: u32) -> bool {
+    if push_constants.flags_offset == NO_CHANNEL {
+        return false;
+    }
+    let flags = extras[object_id * push_constants.extras_stride + push_constants.flags_offset];
+    return (flags & (PINNED_FLAG | HELD_FLAG | SLEEPING_FLAG)) != 0u;
+}
+
+// Must match CollisionFilter::can_collide: the group of each particle has a bit in the mask of the other
+fn can_collide(object_id: u32, other_object_id: u32) -> bool {
+    if push_constants.filter_offset == NO_CHANNEL {
+        return true;
+    }
+    let object_filter = collision_filter(object_id);
+    let other_filter = collision_filter(other_object_id);
+    return (object_filter.x & other_filter.y) != 0u && (other_filter.x & object_filter.y) != 0u;
+}
+
+// Group and mask of the particle
+fn collision_filter(object_id: u32) -> vec2<u32> {
+    let start = object_id * push_constants.extras_stride + push_constants.filter_offset;
+    return vec2<u32>(extras[start], extras[start + 1u]);
+}
+
+// One thread per collision cell, whatever its color: its number of particles, against the cap of the solver
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn record_cell_stats(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(local_invocation_id) local_id: vec3<u32>){
+    let tid: u32 = global_id.x;
+
+    load_number_of_collision_cells(local_id.x);
+
+    if tid >= num_collision_cells {
+        return;
+    }
+
+    let start = collision_cells[tid];
+    let cell_hash: u32 = cell_ids[start];
+    var end = start + 1u;
+    while end < uniform_data.total_cell_ids && cell_ids[end] == cell_hash {
+        end++;
+    }
+    let num_particles = end - start;
+
+    atomicAdd(&contact_stats.collision_cells, 1u);
+    atomicMax(&contact_stats.max_particles_per_cell, num_particles);
+    if num_particles > push_constants.max_particles_per_cell {
+        atomicAdd(&contact_stats.overflow_cells, 1u);
+        atomicAdd(&contact_stats.overflow_particles, num_particles - push_constants.max_particles_per_cell);
+    }
+}
+
+// Overlap is the penetration relative to the sum of the radii, in [0, 1]
+fn record_contact(overlap: f32) {
+    atomicAdd(&contact_stats.contacts, 1u);
+    atomicAdd(&contact_stats.overlap_sum, u32(overlap * OVERLAP_SCALE));
+    // Positive floats are ordered like their bits
+    atomicMax(&contact_stats.max_overlap, bitcast<u32>(overlap));
+}
+
+// Pushes the particles out of the static segments. One thread per particle, after the color passes of an iteration
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn solve_segment_collisions(@builtin(global_invocation_id) global_id: vec3<u32>){
+    let object_id = global_id.x;
+    if object_id >= uniform_data.num_particles || is_kinematic(object_id) {
+        return;
+    }
+
+    let object_radius = radius[object_id];
+    for (var i = 0u; i < uniform_data.num_segments; i++) {
+        let segment = segments[i];
+        let position = positions[object_id];
+        let vec_segment_obj = position - closest_point_on_segment(position, segment);
+        let distance = length(vec_segment_obj);
+        if distance >= object_radius {
+            continue;
+        }
+
+        // A particle centered on the segment is pushed to the left side of it
+        var normal = segment_left_normal(segment);
+        if distance > 0.0001 {
+            normal = vec_segment_obj / distance;
+        }
+        // Segments do not move: the whole penetration is corrected on the particle
+        let displacement = normal * (object_radius - distance);
+        positions[object_id] = position + displacement;
+
+        if push_constants.restitution_offset != NO_CHANNEL {
+            bounce_off_segment(object_id, normal, displacement);
+        }
+    }
+}
+
+fn closest_point_on_segment(position: vec2<f32>, segment: Segment) -> vec2<f32> {
+    let direction = segment.end - segment.start;
+    let length_sq = dot(direction, direction);
+    if length_sq == 0.0 {
+        return segment.start;
+    }
+    let t = clamp(dot(position - segment.start, direction) / length_sq, 0.0, 1.0);
+    return segment.start + direction * t;
+}
+
+fn segment_left_normal(segment: Segment) -> vec2<f32> {
+    let direction = segment.end - segment.start;
+    if dot(direction, direction) == 0.0 {
+        return vec2<f32>(0.0, 1.0);
+    }
+    return normalize(vec2<f32>(-direction.y, direction.x));
+}
+
+// Same as apply_restitution, against an immovable collider with the restitution of the particle
+fn bounce_off_segment(object_id: u32, normal: vec2<f32>, displacement: vec2<f32>) {
+    var previous = previous_positions[object_id] + displacement;
+    let normal_velocity = dot(positions[object_id] - previous, normal);
+    if normal_velocity < 0.0 {
+        previous += normal * (1.0 + get_restitution(object_id)) * normal_velocity;
+    }
+    previous_positions[object_id] = previous;
+}
+
+fn get_restitution(object_id: u32) -> f32 {
+    return bitcast<f32>(extras[object_id * push_constants.extras_stride + push_constants.restitution_offset]);
+}
+
+fn combine_restitution(restitution_1: f32, restitution_2: f32) -> f32 {
+    let combine = push_constants.restitution_combine;
+    if combine == COMBINE_MIN {
+        return min(restitution_1, restitution_2);
+    }
+    if combine == COMBINE_MAX {
+        return max(restitution_1, restitution_2);
+    }
+    if combine == COMBINE_MULTIPLY {
+        return restitution_1 * restitution_2;
+    }
+    // COMBINE_AVERAGE
+    return (restitution_1 + restitution_2) * 0.5;
+}
+
+// Velocities are implicit in verlet integration (position - previous position).
+// The previous positions are moved with the correction, so the correction adds no velocity,
+// then the approaching normal velocity is reflected and scaled by the contact restitution.
+fn apply_restitution(object_id: u32, other_object_id: u32, normal: vec2<f32>, displacement: vec2<f32>, displacement_2: vec2<f32>, weight_1: f32, weight_2: f32) {
+    var previous_1 = previous_positions[object_id] + displacement;
+    var previous_2 = previous_positions[other_object_id] - displacement_2;
+
+    let relative_velocity = (positions[object_id] - previous_1) - (positions[other_object_id] - previous_2);
+    let normal_velocity = dot(relative_velocity, normal);
+    if normal_velocity < 0.0 {
+        let restitution = combine_restitution(get_restitution(object_id), get_restitution(other_object_id));
+        let velocity_change = normal * (1.0 + restitution) * normal_velocity;
+        previous_1 += velocity_change * weight_1;
+        previous_2 -= velocity_change * weight_2;
+    }
+
+    previous_positions[object_id] = previous_1;
+    previous_positions[other_object_id] = previous_2;
+}
+
 /// Compacts bits from every other position to the lower 16 bits.
 /// This is the inverse of `split_by_bits`.
 /// Example (2-bit): n = 5 (binary 0101) becomes 3 (binary 11).
@@ -1,45 +1,461 @@
+use glam::Vec2;
 use wgpu::CommandEncoder;
-use wgpu_profiler::GpuProfiler;
+use crate::utils::profiler::GpuProfiler;
 use crate::grid::grid::Grid;
+use crate::grid::spatial_hash;
 use crate::particles::particle_system::ParticleSystem;
+use crate::physics::cell_range_solver::CellRangeSolver;
 use crate::physics::collision_cell_builder::CollisionCellBuilder;
 use crate::physics::collision_solver::CollisionSolver;
+use crate::physics::collision_color_validator::{CollisionColorValidator, ColorViolations};
+use crate::physics::contact_stats::{AdaptiveIterations, CollisionStats, ContactStats};
+use crate::physics::frame_graph::FrameGraph;
+use crate::physics::pass_validation::PhysicsPass;
+use crate::physics::static_colliders::{StaticCircle, StaticColliders};
 use crate::renderer::wgpu_context::WgpuContext;
 
+/// Name of the per-particle `f32` channel read by the solver as restitution, see `Simulation::enable_restitution`.
+/// Without it the penetration correction alone decides how particles bounce.
+pub const RESTITUTION_CHANNEL: &str = "restitution";
+/// Name of the per-particle `[group, mask]` channel of the `CollisionFilter`s, see `Simulation::enable_collision_filters`.
+pub const COLLISION_FILTER_CHANNEL: &str = "collision_filter";
+
+/// Which particles a particle collides with, like the collision filters of rigid body engines: two particles
+/// collide when the group of each one has a bit in the mask of the other. The filters only apply between
+/// particles; the world boundary and the static colliders stop every particle.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CollisionFilter {
+    /// Bits of the sets the particle belongs to.
+    pub group: u32,
+    /// Bits of the sets the particle collides with.
+    pub mask: u32,
+}
+
+impl CollisionFilter {
+    /// In the first set, colliding with every set. The filter of the particles that were not given one.
+    pub const DEFAULT: Self = Self { group: 1, mask: u32::MAX };
+
+    pub fn new(group: u32, mask: u32) -> Self {
+        Self { group, mask }
+    }
+
+    /// Must match `can_collide` in the solver shaders.
+    pub fn can_collide(&self, other: &CollisionFilter) -> bool {
+        (self.group & other.mask) != 0 && (other.group & self.mask) != 0
+    }
+
+    /// Components of the filter in the collision filter channel.
+    pub fn to_words(self) -> [u32; 2] {
+        [self.group, self.mask]
+    }
+}
+
+impl Default for CollisionFilter {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// How the restitutions of two colliding particles are blended into the restitution of the contact.
+/// The values must match the COMBINE_* constants of the solver shader.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RestitutionCombine {
+    Average = 0,
+    Min = 1,
+    Max = 2,
+    Multiply = 3,
+}
+
+/// How the collision solver corrects the contacts, see `SolverConfig::method`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SolverMethod {
+    /// Every contact is relaxed by `stiffness` in every iteration, weighted by the inverse radius of the particles.
+    /// More iterations separate the particles further within a step.
+    #[default]
+    Relaxation,
+    /// Position based dynamics: every contact is a constraint projected with the inverse masses of the particles
+    /// (their areas), with the stiffness spread over the iterations so a step corrects `stiffness` of the penetration
+    /// whatever their number. More iterations only make the solve converge, trading performance for stable stacks.
+    PositionBased,
+}
+
+impl SolverMethod {
+    /// Power of the radius that makes the mass of a particle: the length for relaxation, the area for projection.
+    pub fn mass_exponent(self) -> f32 {
+        match self {
+            SolverMethod::Relaxation => 1.0,
+            SolverMethod::PositionBased => 2.0,
+        }
+    }
+}
+
+/// How the collision solver runs the collision cells in parallel, see `SolverConfig::batching`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SolverBatching {
+    /// Four passes per iteration, one per cell color, so that no particle is moved by two threads at once.
+    #[default]
+    Colors,
+    /// One pass over every collision cell that adds the corrections of the contacts into a fixed-point atomic buffer,
+    /// then a pass applying them to the particles. Every contact is solved once, in the cell of its contact point,
+    /// but from the positions before the iteration (Jacobi), and without restitution.
+    Atomics,
+}
+
+/// Settings of the collision solver.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SolverConfig {
+    /// Fraction of the penetration corrected by each contact resolution (the positional correction percentage).
+    /// Lower values jitter less in piles but leave more overlap after an iteration.
+    pub stiffness: f32,
+    /// Penetration left uncorrected by every contact between two particles, as a fraction of the sum of their
+    /// radii. A small slop (e.g. 0.01) stops resting contacts from being pushed apart and back every step,
+    /// which removes the jitter of dense piles at the cost of visibly overlapping particles. Walls ignore it.
+    pub slop: f32,
+    /// Number of times the four color passes run per step (per substep with a fixed timestep).
+    pub iterations: u32,
+    /// Relaxation (the default) or constraint projection.
+    pub method: SolverMethod,
+    /// Color passes (the default) or atomic corrections. Only used by `BroadphaseMode::CollisionCells`.
+    pub batching: SolverBatching,
+    /// Particles of a collision cell solved against each other. A cell holding more, e.g. small particles piled into
+    /// the cell of a large radius, only solves its first ones in that step and is counted in `CellStats`.
+    pub max_particles_per_cell: u32,
+    /// Only used when the particles have a restitution channel.
+    pub restitution_combine: RestitutionCombine,
+    /// When set, `iterations` follows the contact overlap measured by the solver, within the given bounds.
+    pub adaptive_iterations: Option<AdaptiveIterations>,
+}
+
+impl SolverConfig {
+    /// Stiffness applied by each iteration. With `SolverMethod::PositionBased`, `1 - (1 - stiffness)^(1 / iterations)`,
+    /// so that all the iterations of a step together correct `stiffness` of an isolated contact.
+    pub fn iteration_stiffness(&self) -> f32 {
+        match self.method {
+            SolverMethod::Relaxation => self.stiffness,
+            SolverMethod::PositionBased => 1.0 - (1.0 - self.stiffness.clamp(0.0, 1.0)).powf(1.0 / self.iterations.max(1) as f32),
+        }
+    }
+}
+
+impl Default for SolverConfig {
+    fn default() -> Self {
+        Self {
+            stiffness: 0.6,
+            slop: 0.0,
+            iterations: 1,
+            method: SolverMethod::Relaxation,
+            batching: SolverBatching::Colors,
+            max_particles_per_cell: 64,
+            restitution_combine: RestitutionCombine::Average,
+            adaptive_iterations: None,
+        }
+    }
+}
+
+/// How the collision system finds the particles close to each other, see `CollisionSystem::set_broadphase_mode`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum BroadphaseMode {
+    /// Lists the cells holding more than one particle (collision cells) and solves them in four color passes,
+    /// moving both particles of every contact.
+    #[default]
+    CollisionCells,
+    /// Writes where each cell starts and ends in the sorted cell ids, then every particle visits the particles
+    /// of the cells it touches and moves only itself. No color passes, but no restitution nor contact statistics.
+    CellRanges,
+    /// Collision cells like `CollisionCells`, but the grid hashes the cell coordinates into a table of
+    /// `spatial_hash::DEFAULT_TABLE_BITS` bits instead of morton encoding them (see `Grid::set_spatial_hash`),
+    /// for very large or sparse worlds where the morton ids waste key space. Cells sharing a table slot are solved
+    /// as one collision cell. The color validation is skipped, it decodes morton ids, and the fluid mode needs them.
+    SpatialHash,
+}
+
+/// Immovable line segment the particles collide with, e.g. a ramp or the wall of a funnel.
+/// Particles are pushed out of it by their radius, on whichever side they are.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct StaticSegment {
+    pub start: Vec2,
+    pub end: Vec2,
+}
+
 pub struct CollisionSystem {
     collision_cell_builder: CollisionCellBuilder,
     collision_solver: CollisionSolver,
+    color_validator: Option<CollisionColorValidator>,
+    color_violations: ColorViolations,
+    broadphase_mode: BroadphaseMode,
+    // Only created in BroadphaseMode::CellRanges
+    cell_range_solver: Option<CellRangeSolver>,
+    static_colliders: StaticColliders,
 }
 impl CollisionSystem {
     pub fn new(wgpu_context: &WgpuContext, dim: u32, particle_system: &ParticleSystem, grid: &Grid) -> Self {
+        Self::new_with_config(wgpu_context, dim, particle_system, grid, SolverConfig::default())
+    }
+
+    pub fn new_with_config(wgpu_context: &WgpuContext, dim: u32, particle_system: &ParticleSystem, grid: &Grid, solver_config: SolverConfig) -> Self {
         let collision_cell_builder = CollisionCellBuilder::new(wgpu_context, particle_system.len(), dim, grid);
-        let collision_solver = CollisionSolver::new(wgpu_context, particle_system, grid, &collision_cell_builder);
+        let collision_solver = CollisionSolver::new(wgpu_context, particle_system, grid, &collision_cell_builder, solver_config);
+        let static_colliders = StaticColliders::new(wgpu_context, particle_system);
         
         Self {
             collision_solver,
             collision_cell_builder,
+            color_validator: None,
+            color_violations: ColorViolations::default(),
+            broadphase_mode: BroadphaseMode::default(),
+            cell_range_solver: None,
+            static_colliders,
         }
     }
+
+    pub fn set_solver_config(&mut self, solver_config: SolverConfig) {
+        self.collision_solver.set_config(solver_config);
+    }
+
+    /// With adaptive iterations, `iterations` is the count used by the next step.
+    pub fn solver_config(&self) -> SolverConfig {
+        self.collision_solver.config()
+    }
+
+    /// Number of contacts and their overlap before the correction, in a recent step.
+    pub fn last_contact_stats(&self) -> Option<ContactStats> {
+        self.collision_solver.last_contact_stats()
+    }
+
+    /// Contacts and collision cell occupancy of a recent step, None until the first readback completed
+    /// and in `BroadphaseMode::CellRanges`.
+    pub fn stats(&self) -> Option<CollisionStats> {
+        self.collision_solver.last_stats()
+    }
+
+    /// Switches between the collision cell, the cell range and the spatial hash broadphases, e.g. to compare their
+    /// performance. Switches the cell ids of `grid` between morton and hashed ids too.
+    pub fn set_broadphase_mode(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &mut Grid, broadphase_mode: BroadphaseMode) {
+        self.broadphase_mode = broadphase_mode;
+        grid.set_spatial_hash((broadphase_mode == BroadphaseMode::SpatialHash).then_some(spatial_hash::DEFAULT_TABLE_BITS));
+        self.collision_solver.set_spatial_hash(grid.spatial_hash_bits());
+        self.cell_range_solver = (broadphase_mode == BroadphaseMode::CellRanges).then(|| CellRangeSolver::new(wgpu_context, particle_system, grid));
+    }
+
+    pub fn broadphase_mode(&self) -> BroadphaseMode {
+        self.broadphase_mode
+    }
+
+    /// Follows `Grid::set_origin`.
+    pub fn set_grid_origin(&mut self, origin: Vec2) {
+        self.collision_solver.set_grid_origin(origin);
+        if let Some(cell_range_solver) = self.cell_range_solver.as_mut() {
+            cell_range_solver.set_origin(origin);
+        }
+    }
+
+    /// Follows `Grid::set_period`. Only the collision cells are solved across the seam, `BroadphaseMode::CellRanges`
+    /// does not look past the edges of the world.
+    pub fn set_period(&mut self, period: Option<Vec2>) {
+        self.collision_solver.set_period(period);
+    }
+
+    /// Enables the checks that no particle is in two collision cells of the same color
+    /// and that neighbouring collision cells never share a color (see `morton::cell_color`).
+    /// The counters are read back without stalling the frame, a validation runs whenever the last one was read.
+    pub fn set_color_validation(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid, enabled: bool) {
+        self.color_validator = enabled.then(|| CollisionColorValidator::new(wgpu_context, particle_system, grid, &self.collision_cell_builder));
+        self.color_violations = ColorViolations::default();
+    }
+
+    /// Number of particles found in more than one collision cell during each color pass of the last validation
+    /// read back, usually a frame or two old. Always zero when the validation is disabled.
+    pub fn color_violations(&self) -> [u32; 4] {
+        self.color_violations.shared_particles
+    }
+
+    /// Number of collision cells with a neighbouring collision cell of the same color, per color,
+    /// in the last validation read back. Always zero when the validation is disabled.
+    pub fn neighbor_color_violations(&self) -> [u32; 4] {
+        self.color_violations.neighboring_cells
+    }
     
     pub fn refresh(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid, particles_added: usize){
-        let new_buffer_size = particles_added * 4; 
+        let new_buffer_size = particles_added * grid.max_cells_per_object() as usize;
         self.collision_cell_builder.refresh_buffers(wgpu_context, new_buffer_size, grid);
         self.collision_solver.refresh_buffers(wgpu_context, particle_system, grid, &self.collision_cell_builder);
+        if let Some(color_validator) = self.color_validator.as_mut() {
+            color_validator.refresh_buffers(wgpu_context, particle_system, grid, &self.collision_cell_builder);
+        }
+        if let Some(cell_range_solver) = self.cell_range_solver.as_mut() {
+            cell_range_solver.refresh_buffers(wgpu_context, particle_system, grid);
+        }
+        self.static_colliders.refresh(wgpu_context, particle_system);
+    }
+    
+    /// Follows the dispatch mode of the grid, see `Grid::set_indirect_dispatch` and `Grid::set_cell_compaction`.
+    pub fn set_indirect_dispatch(&mut self, grid: &Grid){
+        self.collision_cell_builder.set_indirect_dispatch(grid);
+    }
+
+    /// Adds a static segment collider from `start` to `end`, in world coordinates, and returns its index.
+    /// Particles are tested against every segment after each solver iteration.
+    pub fn add_static_segment(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid, start: Vec2, end: Vec2) -> usize {
+        let mut static_segments = self.collision_solver.static_segments().to_vec();
+        static_segments.push(StaticSegment { start, end });
+        self.collision_solver.set_static_segments(wgpu_context, particle_system, grid, &self.collision_cell_builder, static_segments);
+        self.collision_solver.static_segments().len() - 1
+    }
+
+    /// Removes every static segment.
+    pub fn clear_static_segments(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid) {
+        self.collision_solver.set_static_segments(wgpu_context, particle_system, grid, &self.collision_cell_builder, Vec::new());
+    }
+
+    pub fn static_segments(&self) -> &[StaticSegment] {
+        self.collision_solver.static_segments()
+    }
+
+    /// Adds a static circle collider, in world coordinates, and returns its index.
+    /// Particles are pushed out of every circle after each solver iteration.
+    pub fn add_static_circle(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem, center: Vec2, radius: f32) -> usize {
+        let mut static_circles = self.static_colliders.circles().to_vec();
+        static_circles.push(StaticCircle::new(center, radius));
+        self.static_colliders.set_circles(wgpu_context, particle_system, static_circles);
+        self.static_colliders.circles().len() - 1
+    }
+
+    /// Removes the last added circle containing `position`, if any, and returns it.
+    pub fn remove_static_circle_at(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem, position: Vec2) -> Option<StaticCircle> {
+        let index = self.static_colliders.circle_at(position)?;
+        let mut static_circles = self.static_colliders.circles().to_vec();
+        let removed = static_circles.remove(index);
+        self.static_colliders.set_circles(wgpu_context, particle_system, static_circles);
+        Some(removed)
+    }
+
+    /// Removes every static circle.
+    pub fn clear_static_circles(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem) {
+        self.static_colliders.set_circles(wgpu_context, particle_system, Vec::new());
+    }
+
+    pub fn static_circles(&self) -> &[StaticCircle] {
+        self.static_colliders.circles()
+    }
+
+    /// Records one pass of the static segments and circles alone, without the particle collisions.
+    pub fn record_static_collisions(&self, encoder: &mut CommandEncoder, gpu_profiler: &GpuProfiler) {
+        self.collision_solver.solve_segment_collisions(encoder, gpu_profiler);
+        self.static_colliders.solve_collisions(encoder, gpu_profiler);
+    }
+
+    /// Rebinds the particle buffers after a channel was registered, which replaces the extras buffer.
+    pub fn refresh_particle_bindings(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid){
+        self.collision_solver.refresh_buffers(wgpu_context, particle_system, grid, &self.collision_cell_builder);
+        if let Some(cell_range_solver) = self.cell_range_solver.as_mut() {
+            cell_range_solver.refresh_buffers(wgpu_context, particle_system, grid);
+        }
+        self.static_colliders.refresh(wgpu_context, particle_system);
     }
     
-    pub fn solve_collisions(&mut self, wgpu_context: &WgpuContext, mut encoder: CommandEncoder, gpu_profiler: &mut GpuProfiler){
-        self.collision_cell_builder.build_collision_cells(wgpu_context, &mut encoder, gpu_profiler);
+    pub fn solve_collisions(&mut self, wgpu_context: &WgpuContext, encoder: CommandEncoder, gpu_profiler: &mut GpuProfiler){
+        self.build_collision_cells(wgpu_context, encoder, gpu_profiler);
+        self.solve_built_collision_cells(wgpu_context, gpu_profiler);
+    }
+
+    /// Adds the collision cell construction, its color validation if enabled, and the solve to `frame_graph`.
+    /// `after_submit` must follow the submit of the frame.
+    pub fn register_passes(&mut self, wgpu_context: &WgpuContext, frame_graph: &mut FrameGraph, gpu_profiler: &mut GpuProfiler){
+        frame_graph.add_pass(PhysicsPass::BuildCollisionCells, gpu_profiler, |encoder, gpu_profiler| {
+            self.record_collision_cells(wgpu_context, encoder, gpu_profiler);
+            self.record_color_validation(wgpu_context, encoder, gpu_profiler);
+        });
+        frame_graph.add_pass(PhysicsPass::SolveCollisions, gpu_profiler, |encoder, gpu_profiler| self.record_solve(wgpu_context, encoder, gpu_profiler));
+    }
+
+    /// Starts the readbacks recorded by the last solve, once it is submitted.
+    pub fn after_submit(&mut self){
+        self.collision_solver.after_submit();
+        if let Some(color_validator) = self.color_validator.as_mut() {
+            color_validator.after_submit();
+        }
+    }
+
+    /// Records the collision cell construction in `encoder` and submits it.
+    /// With `BroadphaseMode::CellRanges`, records the cell boundaries instead.
+    pub fn build_collision_cells(&mut self, wgpu_context: &WgpuContext, mut encoder: CommandEncoder, gpu_profiler: &mut GpuProfiler){
+        self.record_collision_cells(wgpu_context, &mut encoder, gpu_profiler);
         gpu_profiler.resolve_queries(&mut encoder);
 
         // Submit the commands to the GPU
         wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
-        
-        let indirect_dispatch_buffer = self.collision_cell_builder.indirect_dispatch_buffer(); 
-        self.collision_solver.solve_collisions(wgpu_context, gpu_profiler, indirect_dispatch_buffer);
+    }
+
+    fn record_collision_cells(&self, wgpu_context: &WgpuContext, encoder: &mut CommandEncoder, gpu_profiler: &GpuProfiler){
+        match &self.cell_range_solver {
+            Some(cell_range_solver) => cell_range_solver.find_cell_boundaries(encoder, gpu_profiler),
+            None => self.collision_cell_builder.build_collision_cells(wgpu_context, encoder, gpu_profiler),
+        }
+    }
+
+    /// Solves the collisions of the cells built by `build_collision_cells`.
+    pub fn solve_built_collision_cells(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler){
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Collision solver encoder") }
+        );
+        self.record_color_validation(wgpu_context, &mut encoder, gpu_profiler);
+        self.record_solve(wgpu_context, &mut encoder, gpu_profiler);
+        gpu_profiler.resolve_queries(&mut encoder);
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+        self.after_submit();
+    }
+
+    /// Records the color validator, if enabled, over the collision cells recorded before in `encoder`.
+    fn record_color_validation(&mut self, wgpu_context: &WgpuContext, encoder: &mut CommandEncoder, gpu_profiler: &GpuProfiler){
+        if self.broadphase_mode != BroadphaseMode::CollisionCells {
+            return;
+        }
+        self.poll_color_validation(wgpu_context);
+        if let Some(color_validator) = self.color_validator.as_mut() {
+            color_validator.record_validation(wgpu_context, encoder, gpu_profiler, self.collision_cell_builder.indirect_dispatch_buffer());
+        }
+    }
+
+    /// Updates `color_violations` with the last validation read back, without blocking, and returns whether one
+    /// finished. Every solve calls it.
+    pub fn poll_color_validation(&mut self, wgpu_context: &WgpuContext) -> bool {
+        let Some(color_violations) = self.color_validator.as_mut().and_then(|color_validator| color_validator.poll(wgpu_context)) else {
+            return false;
+        };
+        self.color_violations = color_violations;
+        if !self.color_violations.is_empty() {
+            log::error!("Collision cell colors overlap, violations per color: {:?}", self.color_violations);
+        }
+        true
+    }
+
+    fn record_solve(&mut self, wgpu_context: &WgpuContext, encoder: &mut CommandEncoder, gpu_profiler: &GpuProfiler){
+        if let Some(cell_range_solver) = &self.cell_range_solver {
+            let solver_config = self.collision_solver.config();
+            for _ in 0..solver_config.iterations {
+                cell_range_solver.solve_iteration(encoder, gpu_profiler, self.collision_solver.num_particles(), solver_config.iteration_stiffness(), solver_config.slop, solver_config.method.mass_exponent());
+                self.collision_solver.solve_segment_collisions(encoder, gpu_profiler);
+                self.static_colliders.solve_collisions(encoder, gpu_profiler);
+            }
+            return;
+        }
+        let indirect_dispatch_buffer = self.collision_cell_builder.indirect_dispatch_buffer();
+        self.collision_solver.record_solve(wgpu_context, encoder, gpu_profiler, indirect_dispatch_buffer, &self.static_colliders);
     }
     
     pub fn download_collision_cells(&mut self, wgpu_context: &WgpuContext) -> Vec<u32>{
         self.collision_cell_builder.download_collision_cells(wgpu_context)
     }
+
+    /// Prefix summed number of collision cells found in each counting chunk.
+    pub fn download_chunk_counts(&self, wgpu_context: &WgpuContext) -> Vec<u32>{
+        self.collision_cell_builder.chunk_obj_count().read_back(wgpu_context).unwrap()
+    }
+
+    /// Number of collision cells found by the last `build_collision_cells`: the last prefix summed chunk count.
+    pub fn download_num_collision_cells(&self, wgpu_context: &WgpuContext) -> u32{
+        self.collision_cell_builder.chunk_obj_count().download_last(wgpu_context).unwrap().unwrap_or(0)
+    }
     
 }
@@ -0,0 +1,169 @@
+use std::sync::mpsc::{Receiver, TryRecvError};
+use wgpu::BufferAsyncError;
+use wgpu::wgt::PollType;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::gpu_buffer::GpuBuffer;
+
+/// Fixed point scale of the overlap sum, atomics only add integers. Must match collision_solver.wgsl
+pub(crate) const OVERLAP_SCALE: f32 = 1024.0;
+
+/// Contacts found by the first solver iteration of a step, before any correction.
+/// Overlaps are penetration depths relative to the sum of the two radii: 0 is touching, 1 is concentric.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ContactStats {
+    pub contacts: u32,
+    pub mean_overlap: f32,
+    pub max_overlap: f32,
+}
+
+/// Occupancy of the collision cells in the same step as the `ContactStats`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CellStats {
+    /// Largest number of particles found in one collision cell.
+    pub max_particles_per_cell: u32,
+    /// Cells holding more than one particle.
+    pub collision_cells: u32,
+    /// Collision cells holding more than `SolverConfig::max_particles_per_cell` particles.
+    pub overflow_cells: u32,
+    /// Particles of those cells past the cap, which the cell did not solve.
+    pub overflow_particles: u32,
+}
+
+/// Statistics recorded by the solver in one step, see `CollisionSystem::stats`.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct CollisionStats {
+    pub contacts: ContactStats,
+    pub cells: CellStats,
+}
+
+/// Adapts the solver iterations to the contacts of the previous steps, see `SolverConfig::adaptive_iterations`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AdaptiveIterations {
+    pub min_iterations: u32,
+    pub max_iterations: u32,
+    /// Below this mean overlap the particles are resting, one iteration less is enough.
+    pub low_overlap: f32,
+    /// Above this max overlap, e.g. after a spawn burst, the iterations double.
+    pub high_overlap: f32,
+}
+
+impl Default for AdaptiveIterations {
+    fn default() -> Self {
+        Self {
+            min_iterations: 1,
+            max_iterations: 8,
+            low_overlap: 0.01,
+            high_overlap: 0.1,
+        }
+    }
+}
+
+impl AdaptiveIterations {
+    /// Iterations of the next steps, given the current ones and the last contact statistics.
+    pub fn next_iterations(&self, iterations: u32, stats: &ContactStats) -> u32 {
+        let min_iterations = self.min_iterations.max(1);
+        let max_iterations = self.max_iterations.max(min_iterations);
+        let next = if stats.contacts > 0 && stats.max_overlap > self.high_overlap {
+            iterations.saturating_mul(2)
+        }
+        else if stats.contacts == 0 || stats.mean_overlap < self.low_overlap {
+            iterations.saturating_sub(1)
+        }
+        else {
+            iterations
+        };
+        next.clamp(min_iterations, max_iterations)
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct ContactStatsData {
+    contacts: u32,
+    /// Sum of the overlaps, times `OVERLAP_SCALE`
+    overlap_sum: u32,
+    /// Bits of the largest overlap, positive floats compare like their bits
+    max_overlap: u32,
+    max_particles_per_cell: u32,
+    collision_cells: u32,
+    overflow_cells: u32,
+    overflow_particles: u32,
+    _padding: u32,
+}
+
+/// Reads the contact statistics back asynchronously, like `StabilityWatchdog`: `copy` records the copy
+/// to a staging buffer, `map` starts the read once the copy is submitted and `poll` returns it without stalling.
+pub(crate) struct ContactStatsReadback {
+    staging_buffer: wgpu::Buffer,
+    pending: Option<Receiver<Result<(), BufferAsyncError>>>,
+}
+
+impl ContactStatsReadback {
+    pub fn new(wgpu_context: &WgpuContext) -> Self {
+        let staging_buffer = wgpu_context.get_device().create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Contact stats staging buffer"),
+            size: size_of::<ContactStatsData>() as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            staging_buffer,
+            pending: None,
+        }
+    }
+
+    /// True while a read is in flight, the staging buffer can not be written.
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    pub fn copy(&self, encoder: &mut wgpu::CommandEncoder, stats: &GpuBuffer<ContactStatsData>) {
+        encoder.copy_buffer_to_buffer(stats.buffer(), 0, &self.staging_buffer, 0, size_of::<ContactStatsData>() as u64);
+    }
+
+    /// Must be called after the encoder of `copy` was submitted.
+    pub fn map(&mut self) {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.staging_buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.pending = Some(receiver);
+    }
+
+    /// Returns the statistics of the read in flight once it is done, without blocking.
+    pub fn poll(&mut self, wgpu_context: &WgpuContext) -> Option<CollisionStats> {
+        let receiver = self.pending.take()?;
+        let _ = wgpu_context.get_device().poll(PollType::Poll);
+        match receiver.try_recv() {
+            Ok(Ok(())) => {
+                let data = {
+                    let mapped_range = self.staging_buffer.slice(..).get_mapped_range();
+                    bytemuck::pod_read_unaligned::<ContactStatsData>(&mapped_range)
+                };
+                self.staging_buffer.unmap();
+                Some(CollisionStats {
+                    contacts: ContactStats {
+                        contacts: data.contacts,
+                        mean_overlap: if data.contacts == 0 { 0.0 } else { data.overlap_sum as f32 / OVERLAP_SCALE / data.contacts as f32 },
+                        max_overlap: f32::from_bits(data.max_overlap),
+                    },
+                    cells: CellStats {
+                        max_particles_per_cell: data.max_particles_per_cell,
+                        collision_cells: data.collision_cells,
+                        overflow_cells: data.overflow_cells,
+                        overflow_particles: data.overflow_particles,
+                    },
+                })
+            }
+            Ok(Err(e)) => {
+                log::error!("Contact stats readback failed: {:?}", e);
+                None
+            }
+            Err(TryRecvError::Empty) => {
+                self.pending = Some(receiver);
+                None
+            }
+            Err(TryRecvError::Disconnected) => None,
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use glam::Vec2;
+use wgpu::{BindGroupLayout, CommandEncoder, PushConstantRange};
+use crate::grid::grid::Grid;
+use crate::particles::particle_system::ParticleSystem;
+use crate::physics::cell_range_solver::CellRangeSolver;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::bind_resources::BindResources;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::profiler::GpuProfiler;
+
+pub const WORKGROUP_SIZE: u32 = 64;
+
+/// How the particles interact with each other, see `Simulation::set_mode`.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum SimulationMode {
+    /// Rigid particles pushed apart by the collision solver.
+    #[default]
+    Rigid,
+    /// A fluid: pressure and viscosity forces between the particles (SPH), instead of the collision solver.
+    Fluid(FluidConfig),
+}
+
+/// Settings of `SimulationMode::Fluid`, in world units. The mass of a particle is its area and the smoothing
+/// radius of the kernels is the grid cell size, so the density of a packed fluid is close to the fraction
+/// of the area the particles cover.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FluidConfig {
+    /// Density the pressure pushes the fluid back to, in mass per squared world unit. Lower values spread the fluid.
+    pub rest_density: f32,
+    /// Pressure per unit of density above `rest_density`. Stiffer fluids compress less but need smaller steps.
+    pub pressure_stiffness: f32,
+    /// How much the velocities of neighbouring particles are averaged, 0 for an inviscid fluid.
+    pub viscosity: f32,
+}
+
+impl Default for FluidConfig {
+    fn default() -> Self {
+        Self {
+            rest_density: 0.5,
+            pressure_stiffness: 2000.0,
+            viscosity: 0.5,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstantsData {
+    delta_time: f32,
+    rest_density: f32,
+    pressure_stiffness: f32,
+    viscosity: f32,
+    origin: Vec2,
+}
+
+/// Smoothed particle hydrodynamics of `SimulationMode::Fluid`. Finds the neighbours in the cell tables of a
+/// `CellRangeSolver`, then estimates the density and the pressure of every particle and turns the pressure and
+/// viscosity forces into accelerations, integrated by the next integration.
+pub(crate) struct FluidSolver {
+    cell_ranges: CellRangeSolver,
+    density_shader: ComputeShader,
+    acceleration_shader: ComputeShader,
+    apply_shader: ComputeShader,
+    bind_resources: BindResources,
+    densities: GpuBuffer<Vec2>,
+    accelerations: GpuBuffer<Vec2>,
+    config: FluidConfig,
+}
+
+impl FluidSolver {
+    pub fn new(wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid, config: FluidConfig) -> Self {
+        let cell_ranges = CellRangeSolver::new(wgpu_context, particle_system, grid);
+        let densities = GpuBuffer::new(wgpu_context, vec![Vec2::ZERO; particle_system.len().max(1)], wgpu::BufferUsages::STORAGE);
+        let accelerations = GpuBuffer::new(wgpu_context, vec![Vec2::ZERO; particle_system.len().max(1)], wgpu::BufferUsages::STORAGE);
+
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let bind_group = Self::create_bind_group(wgpu_context, &bind_group_layout, particle_system, grid, &cell_ranges, &densities, &accelerations);
+        let bind_resources = BindResources::new(bind_group_layout, bind_group);
+
+        let create_shader = |entry_point: &str| ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("fluid_solver.wgsl"),
+            entry_point,
+            &bind_resources.bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange {
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstantsData>() as u32,
+                }
+            ]
+        );
+        let density_shader = create_shader("compute_densities");
+        let acceleration_shader = create_shader("compute_accelerations");
+        let apply_shader = create_shader("apply_accelerations");
+
+        Self {
+            cell_ranges,
+            density_shader,
+            acceleration_shader,
+            apply_shader,
+            bind_resources,
+            densities,
+            accelerations,
+            config,
+        }
+    }
+
+    pub fn set_config(&mut self, config: FluidConfig) {
+        self.config = config;
+    }
+
+    pub fn config(&self) -> FluidConfig {
+        self.config
+    }
+
+    /// Follows `Grid::set_origin`.
+    pub fn set_origin(&mut self, origin: Vec2) {
+        self.cell_ranges.set_origin(origin);
+    }
+
+    /// Follows new or removed particles, a new cell size or new particle buffers.
+    pub fn refresh_buffers(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid) {
+        self.cell_ranges.refresh_buffers(wgpu_context, particle_system, grid);
+        if particle_system.len() > self.densities.len() {
+            self.densities.push_all(&vec![Vec2::ZERO; particle_system.len() - self.densities.len()], wgpu_context);
+            self.accelerations.push_all(&vec![Vec2::ZERO; particle_system.len() - self.accelerations.len()], wgpu_context);
+        }
+        self.bind_resources.bind_group = Self::create_bind_group(wgpu_context, &self.bind_resources.bind_group_layout, particle_system, grid, &self.cell_ranges, &self.densities, &self.accelerations);
+    }
+
+    /// Density and pressure of every particle in the last step, for debugging.
+    pub fn download_densities(&self, wgpu_context: &WgpuContext, num_particles: usize) -> Vec<Vec2> {
+        let mut densities = self.densities.read_back(wgpu_context).unwrap();
+        densities.truncate(num_particles);
+        densities
+    }
+
+    fn create_bind_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, particle_system: &ParticleSystem, grid: &Grid, cell_ranges: &CellRangeSolver, densities: &GpuBuffer<Vec2>, accelerations: &GpuBuffer<Vec2>) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Fluid solver bind group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: grid.object_ids().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: cell_ranges.cell_start().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: cell_ranges.cell_end().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 3, resource: particle_system.positions().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 4, resource: particle_system.buffers().previous_positions.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 5, resource: particle_system.radius().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 6, resource: densities.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 7, resource: accelerations.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 8, resource: cell_ranges.uniform_buffer().as_entire_binding() },
+            ],
+        })
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Fluid solver bind group layout"),
+            entries: &[
+                // Object ids
+                storage_entry(0, true),
+                // Cell start
+                storage_entry(1, true),
+                // Cell end
+                storage_entry(2, true),
+                // Positions
+                storage_entry(3, true),
+                // Previous positions
+                storage_entry(4, false),
+                // Radius
+                storage_entry(5, true),
+                // Densities
+                storage_entry(6, false),
+                // Accelerations
+                storage_entry(7, false),
+                // Uniform data of the cell ranges
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    /// Records the cell boundary pass. Must follow the sort of the grid.
+    pub fn find_cell_boundaries(&self, encoder: &mut CommandEncoder, gpu_profiler: &GpuProfiler) {
+        self.cell_ranges.find_cell_boundaries(encoder, gpu_profiler);
+    }
+
+    /// Records the density, acceleration and apply passes of a step of `delta_time`.
+    pub fn record_forces(&self, encoder: &mut CommandEncoder, gpu_profiler: &GpuProfiler, num_particles: u32, delta_time: f32) {
+        if num_particles == 0 || delta_time <= 0.0 {
+            return;
+        }
+        let push_constants = PushConstantsData {
+            delta_time,
+            rest_density: self.config.rest_density,
+            pressure_stiffness: self.config.pressure_stiffness,
+            viscosity: self.config.viscosity,
+            origin: self.cell_ranges.origin(),
+        };
+        let mut scope = gpu_profiler.scope("Fluid forces", encoder);
+        for shader in [&self.density_shader, &self.acceleration_shader, &self.apply_shader] {
+            shader.dispatch_by_items(
+                &mut scope,
+                (num_particles, 1, 1),
+                Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                &self.bind_resources.bind_group
+            );
+        }
+    }
+}
@@ -0,0 +1,177 @@
+override WORKGROUP_SIZE = 64u;
+
+const PI = 3.14159265;
+
+// Same layout as the UniformData of cell_range_solver.wgsl
+struct UniformData {
+    total_cell_ids: u32,
+    num_particles: u32,
+    // Length of cell_start and cell_end, cells with a larger morton id have no neighbours
+    table_len: u32,
+    // Also the smoothing radius of the kernels
+    cell_size: f32,
+}
+
+struct PushConstantsData {
+    delta_time: f32,
+    // Mass per squared world unit the pressure pushes the fluid back to
+    rest_density: f32,
+    pressure_stiffness: f32,
+    viscosity: f32,
+    // World position of the corner of cell (0, 0), see Grid::origin
+    origin: vec2<f32>,
+}
+
+// Sorted by the grid
+@group(0) @binding(0) var<storage, read> object_ids: array<u32>;
+// Indexed by morton cell id: the cell holds object_ids[cell_start..cell_end], see find_cell_boundaries
+@group(0) @binding(1) var<storage, read> cell_start: array<u32>;
+@group(0) @binding(2) var<storage, read> cell_end: array<u32>;
+@group(0) @binding(3) var<storage, read> positions: array<vec2<f32>>;
+@group(0) @binding(4) var<storage, read_write> previous_positions: array<vec2<f32>>;
+@group(0) @binding(5) var<storage, read> radius: array<f32>;
+// Density and pressure of every particle
+@group(0) @binding(6) var<storage, read_write> densities: array<vec2<f32>>;
+@group(0) @binding(7) var<storage, read_write> accelerations: array<vec2<f32>>;
+@group(0) @binding(8) var<uniform> uniform_data: UniformData;
+
+var<push_constant> push_constants: PushConstantsData;
+
+// One thread per particle: sums the poly6 kernel over the neighbours, itself included
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn compute_densities(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let object_id = global_id.x;
+    if object_id >= uniform_data.num_particles {
+        return;
+    }
+
+    let h = uniform_data.cell_size;
+    let h_squared = h * h;
+    let poly6 = 4.0 / (PI * pow(h, 8.0));
+    let position = positions[object_id];
+    let home_cell = home_cell_coord(position);
+
+    var density = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let cell_coord = home_cell + vec2<i32>(x, y);
+            if any(cell_coord < vec2<i32>(0)) {
+                continue;
+            }
+            let cell = morton_encode(cell_coord);
+            if cell >= uniform_data.table_len {
+                continue;
+            }
+            for (var j = cell_start[cell]; j < cell_end[cell]; j++) {
+                let other_object_id = object_ids[j];
+                let other_position = positions[other_object_id];
+                // The phantom cells of a particle hold it too, only its home cell counts
+                if any(home_cell_coord(other_position) != cell_coord) {
+                    continue;
+                }
+                let offset = position - other_position;
+                let distance_squared = dot(offset, offset);
+                if distance_squared < h_squared {
+                    let falloff = h_squared - distance_squared;
+                    density += particle_mass(other_object_id) * poly6 * falloff * falloff * falloff;
+                }
+            }
+        }
+    }
+    // Only compression pushes, a negative pressure would pull the particles into clumps
+    let pressure = push_constants.pressure_stiffness * max(density - push_constants.rest_density, 0.0);
+    densities[object_id] = vec2<f32>(density, pressure);
+}
+
+// One thread per particle: pressure (spiky kernel) and viscosity accelerations from the neighbours.
+// Velocities are only read, so the result does not depend on the scheduling.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn compute_accelerations(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let object_id = global_id.x;
+    if object_id >= uniform_data.num_particles {
+        return;
+    }
+
+    let h = uniform_data.cell_size;
+    let spiky_gradient = 30.0 / (PI * pow(h, 5.0));
+    let viscosity_laplacian = 40.0 / (PI * pow(h, 5.0));
+    let position = positions[object_id];
+    let velocity = particle_velocity(object_id);
+    let density_pressure = densities[object_id];
+    let home_cell = home_cell_coord(position);
+
+    var pressure_acceleration = vec2<f32>(0.0);
+    var viscosity_acceleration = vec2<f32>(0.0);
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let cell_coord = home_cell + vec2<i32>(x, y);
+            if any(cell_coord < vec2<i32>(0)) {
+                continue;
+            }
+            let cell = morton_encode(cell_coord);
+            if cell >= uniform_data.table_len {
+                continue;
+            }
+            for (var j = cell_start[cell]; j < cell_end[cell]; j++) {
+                let other_object_id = object_ids[j];
+                let other_position = positions[other_object_id];
+                if other_object_id == object_id || any(home_cell_coord(other_position) != cell_coord) {
+                    continue;
+                }
+                let offset = position - other_position;
+                let distance = length(offset);
+                if distance >= h || distance < 0.0001 {
+                    continue;
+                }
+                let other_density_pressure = densities[other_object_id];
+                let mass_over_density = particle_mass(other_object_id) / other_density_pressure.x;
+                let falloff = h - distance;
+                // Symmetric pressure, pushing the two particles apart
+                let shared_pressure = (density_pressure.y + other_density_pressure.y) * 0.5;
+                pressure_acceleration += offset / distance * mass_over_density * shared_pressure * spiky_gradient * falloff * falloff;
+                viscosity_acceleration += (particle_velocity(other_object_id) - velocity) * mass_over_density * viscosity_laplacian * falloff;
+            }
+        }
+    }
+    accelerations[object_id] = (pressure_acceleration + push_constants.viscosity * viscosity_acceleration) / density_pressure.x;
+}
+
+// Integrated by the next integration, like ForceKernel accelerations
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn apply_accelerations(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let object_id = global_id.x;
+    if object_id >= uniform_data.num_particles {
+        return;
+    }
+    previous_positions[object_id] -= accelerations[object_id] * push_constants.delta_time * push_constants.delta_time;
+}
+
+// The area of the particle, so a packed fluid has a density close to the fraction of the area it covers
+fn particle_mass(object_id: u32) -> f32 {
+    let particle_radius = radius[object_id];
+    return PI * particle_radius * particle_radius;
+}
+
+// In world units per second
+fn particle_velocity(object_id: u32) -> vec2<f32> {
+    return (positions[object_id] - previous_positions[object_id]) / push_constants.delta_time;
+}
+
+// Like particle_cells in cell_range_solver.wgsl
+fn home_cell_coord(world_position: vec2<f32>) -> vec2<i32> {
+    return max(vec2<i32>(floor((world_position - push_constants.origin) / uniform_data.cell_size)), vec2<i32>(0));
+}
+
+fn split_by_bits(n: u32) -> u32 {
+    var x = n & 0x0000FFFF;
+    x = (x | (x << 8)) & 0x00FF00FF;
+    x = (x | (x << 4)) & 0x0F0F0F0F;
+    x = (x | (x << 2)) & 0x33333333;
+    x = (x | (x << 1)) & 0x55555555;
+    return x;
+}
+
+// Must match morton::encode
+fn morton_encode(v: vec2<i32>) -> u32 {
+    return split_by_bits(u32(v.x)) | (split_by_bits(u32(v.y)) << 1);
+}
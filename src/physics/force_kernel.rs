@@ -0,0 +1,239 @@
+use std::borrow::Cow;
+use glam::{Vec2, Vec4};
+use wgpu::{BindGroupLayout, PushConstantRange};
+use crate::particles::particle_system::ParticleSystem;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::bind_resources::BindResources;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::profiler::GpuProfiler;
+
+const WORKGROUP_SIZE: u32 = 64;
+
+/// WGSL prepended to every user kernel: bindings, push constants and helper functions.
+pub const FORCE_KERNEL_PRELUDE: &str = include_str!("force_kernel_prelude.wgsl");
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ForceParams {
+    delta_time: f32,
+    num_particles: u32,
+    world_size: Vec2,
+    params: Vec4,
+}
+
+/// Source of a user force kernel.
+/// `source` only declares the entry point (and its own helpers): the bindings come from
+/// `FORCE_KERNEL_PRELUDE`, which documents them. Channels are read by index, pass the
+/// `ChannelId`s the kernel needs as override `constants`.
+#[derive(Clone, Debug)]
+pub struct ForceKernelDescriptor {
+    pub label: String,
+    pub source: String,
+    pub entry_point: String,
+    pub constants: Vec<(String, f64)>,
+}
+
+impl ForceKernelDescriptor {
+    pub fn new(label: &str, source: &str, entry_point: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            source: source.to_string(),
+            entry_point: entry_point.to_string(),
+            constants: Vec::new(),
+        }
+    }
+
+    pub fn with_constant(mut self, name: &str, value: f64) -> Self {
+        self.constants.push((name.to_string(), value));
+        self
+    }
+}
+
+/// Handle of a force kernel added to a `Simulation`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ForceKernelId(pub u32);
+
+/// A user supplied WGSL kernel run over every particle once per step, after the collisions and
+/// before the integration, e.g. for custom force fields without forking the integration shader.
+/// It gets read-write access to the positions, previous positions and channels, see `FORCE_KERNEL_PRELUDE`.
+pub struct ForceKernel {
+    label: String,
+    shader: ComputeShader,
+    bind_resources: BindResources,
+    params: Vec4,
+    enabled: bool,
+}
+
+impl ForceKernel {
+    /// Compiles the kernel. Invalid WGSL is reported by wgpu like for the built-in shaders.
+    pub fn new(wgpu_context: &WgpuContext, descriptor: &ForceKernelDescriptor, particle_system: &ParticleSystem) -> Self {
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let bind_group = Self::create_bind_group(wgpu_context, &bind_group_layout, particle_system);
+        let bind_resources = BindResources::new(bind_group_layout, bind_group);
+
+        let source = format!("{}\n{}", FORCE_KERNEL_PRELUDE, descriptor.source);
+        let mut constants = vec![("WORKGROUP_SIZE", WORKGROUP_SIZE as f64)];
+        constants.extend(descriptor.constants.iter().map(|(name, value)| (name.as_str(), *value)));
+
+        let shader = ComputeShader::new(
+            wgpu_context,
+            wgpu::ShaderModuleDescriptor {
+                label: Some(&descriptor.label),
+                source: wgpu::ShaderSource::Wgsl(Cow::Owned(source)),
+            },
+            &descriptor.entry_point,
+            &bind_resources.bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &constants,
+            &vec![
+                PushConstantRange{
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<ForceParams>() as u32,
+                }
+            ]
+        );
+
+        Self {
+            label: descriptor.label.clone(),
+            shader,
+            bind_resources,
+            params: Vec4::ZERO,
+            enabled: true,
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Values the kernel reads as `force_params.params`.
+    pub fn set_params(&mut self, params: Vec4) {
+        self.params = params;
+    }
+
+    pub fn params(&self) -> Vec4 {
+        self.params
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Must be called after the particle buffers are refreshed or a channel is registered.
+    pub fn refresh(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem) {
+        self.bind_resources.bind_group = Self::create_bind_group(wgpu_context, &self.bind_resources.bind_group_layout, particle_system);
+    }
+
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler, particle_system: &ParticleSystem, delta_time: f32) {
+        if !self.enabled || particle_system.len() == 0 {
+            return;
+        }
+        let force_params = ForceParams {
+            delta_time,
+            num_particles: particle_system.len() as u32,
+            world_size: particle_system.get_world_size(),
+            params: self.params,
+        };
+        let mut scope = gpu_profiler.scope(&self.label, encoder);
+        self.shader.dispatch_by_items(
+            &mut scope,
+            (force_params.num_particles, 1, 1),
+            Some(vec![(0, bytemuck::bytes_of(&force_params))]),
+            &self.bind_resources.bind_group,
+        );
+    }
+
+    fn create_bind_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, particle_system: &ParticleSystem) -> wgpu::BindGroup {
+        let buffers = particle_system.buffers();
+        wgpu_context.get_device().create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                label: Some("Force kernel bind group"),
+                layout: bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffers.current_positions.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: buffers.previous_positions.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: buffers.radii.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: buffers.extras.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: particle_system.channels().layout_buffer().buffer().as_entire_binding(),
+                    },
+                ],
+            }
+        )
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Force kernel bind group layout"),
+            entries: &[
+                // Positions
+                storage_entry(0, false),
+                // Previous positions
+                storage_entry(1, false),
+                // Radii
+                storage_entry(2, true),
+                // Extras (per-particle channels)
+                storage_entry(3, false),
+                // Channel layout
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+}
+
+/// Runs the enabled kernels in order and submits them. Does nothing without kernels.
+pub fn apply_force_kernels(wgpu_context: &WgpuContext, force_kernels: &[ForceKernel], particle_system: &ParticleSystem, gpu_profiler: &mut GpuProfiler, delta_time: f32) {
+    if !force_kernels.iter().any(ForceKernel::is_enabled) {
+        return;
+    }
+    let mut encoder = wgpu_context.get_device().create_command_encoder(
+        &wgpu::CommandEncoderDescriptor { label: Some("Force kernels encoder") }
+    );
+    record_force_kernels(&mut encoder, force_kernels, particle_system, gpu_profiler, delta_time);
+    gpu_profiler.resolve_queries(&mut encoder);
+    wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+}
+
+/// Records the enabled kernels in order into `encoder`, see `FrameGraph`.
+pub fn record_force_kernels(encoder: &mut wgpu::CommandEncoder, force_kernels: &[ForceKernel], particle_system: &ParticleSystem, gpu_profiler: &GpuProfiler, delta_time: f32) {
+    for force_kernel in force_kernels {
+        force_kernel.dispatch(encoder, gpu_profiler, particle_system, delta_time);
+    }
+}
@@ -0,0 +1,87 @@
+// Interface of the user force kernels, see ForceKernel.
+// This file is prepended to the user source, which only has to declare its entry point:
+//
+//     @compute @workgroup_size(WORKGROUP_SIZE)
+//     fn my_force(@builtin(global_invocation_id) global_id: vec3<u32>) {
+//         let index = global_id.x;
+//         if index >= force_params.num_particles {
+//             return;
+//         }
+//         apply_acceleration(index, vec2<f32>(0.0, -98.0));
+//     }
+//
+// The bindings, the push constants and the helper functions below are stable.
+
+override WORKGROUP_SIZE: u32 = 64u;
+
+// Must match MAX_PARTICLE_CHANNELS of particle_channels.rs
+const MAX_PARTICLE_CHANNELS: u32 = 16u;
+
+struct ForceParams {
+    // Seconds simulated by this step
+    delta_time: f32,
+    num_particles: u32,
+    world_size: vec2<f32>,
+    // Free for the kernel, set with ForceKernel::set_params
+    params: vec4<f32>,
+};
+
+// Same layout as ChannelLayoutUniform
+struct ChannelLayout {
+    stride: u32,
+    num_channels: u32,
+    num_particles: u32,
+    _padding: u32,
+    offsets: array<vec4<u32>, 4>,
+};
+
+@group(0) @binding(0) var<storage, read_write> positions: array<vec2<f32>>;
+@group(0) @binding(1) var<storage, read_write> previous_positions: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read> radius: array<f32>;
+// Interleaved per-particle channels, read them with the channel_* functions
+@group(0) @binding(3) var<storage, read_write> extras: array<u32>;
+@group(0) @binding(4) var<uniform> channel_layout: ChannelLayout;
+
+var<push_constant> force_params: ForceParams;
+
+
+/// Velocity of a particle in world units per second.
+fn particle_velocity(index: u32) -> vec2<f32> {
+    return (positions[index] - previous_positions[index]) / force_params.delta_time;
+}
+
+/// Replaces the velocity of a particle, in world units per second.
+fn set_particle_velocity(index: u32, velocity: vec2<f32>) {
+    previous_positions[index] = positions[index] - velocity * force_params.delta_time;
+}
+
+/// Accelerates a particle during this step, in world units per second squared.
+/// Integrated exactly like the built-in forces.
+fn apply_acceleration(index: u32, acceleration: vec2<f32>) {
+    previous_positions[index] -= acceleration * force_params.delta_time * force_params.delta_time;
+}
+
+/// True if the particles have the channel, pass the ChannelId as an override constant.
+fn has_channel(channel: u32) -> bool {
+    return channel < channel_layout.num_channels;
+}
+
+fn channel_word_index(index: u32, channel: u32, component: u32) -> u32 {
+    return index * channel_layout.stride + channel_layout.offsets[channel / 4u][channel % 4u] + component;
+}
+
+fn channel_u32(index: u32, channel: u32, component: u32) -> u32 {
+    return extras[channel_word_index(index, channel, component)];
+}
+
+fn channel_f32(index: u32, channel: u32, component: u32) -> f32 {
+    return bitcast<f32>(channel_u32(index, channel, component));
+}
+
+fn set_channel_u32(index: u32, channel: u32, component: u32, value: u32) {
+    extras[channel_word_index(index, channel, component)] = value;
+}
+
+fn set_channel_f32(index: u32, channel: u32, component: u32, value: f32) {
+    set_channel_u32(index, channel, component, bitcast<u32>(value));
+}
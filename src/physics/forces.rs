@@ -0,0 +1,70 @@
+use glam::Vec2;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::gpu_buffer::GpuBuffer;
+
+/// Forces applied to every particle by the integration, in world units. `SimulationConfig::world_forces`
+/// converts the SI values of a config.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct GlobalForces {
+    /// Acceleration of `GravityMode::Uniform`, in world units per second squared. The radial modes ignore it.
+    pub gravity: Vec2,
+    /// Acceleration added in every gravity mode, in world units per second squared.
+    pub wind: Vec2,
+    /// Part of the velocity lost per second, e.g. 0.5 halves it in about a second.
+    pub linear_drag: f32,
+}
+
+/// Layout of `GlobalForces` in particle_integration.wgsl.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ForcesUniform {
+    gravity: Vec2,
+    wind: Vec2,
+    linear_drag: f32,
+    _padding: u32,
+}
+
+/// The global forces and their uniform buffer. Changes are uploaded by `upload`, before the integration runs.
+pub struct Forces {
+    forces: GlobalForces,
+    buffer: GpuBuffer<ForcesUniform>,
+    dirty: bool,
+}
+
+impl Forces {
+    pub fn new(wgpu_context: &WgpuContext) -> Self {
+        let forces = GlobalForces::default();
+        let buffer = GpuBuffer::new(wgpu_context, vec![Self::uniform(&forces)], wgpu::BufferUsages::UNIFORM);
+        Self { forces, buffer, dirty: false }
+    }
+
+    fn uniform(forces: &GlobalForces) -> ForcesUniform {
+        ForcesUniform {
+            gravity: forces.gravity,
+            wind: forces.wind,
+            linear_drag: forces.linear_drag,
+            _padding: 0,
+        }
+    }
+
+    pub fn set(&mut self, forces: GlobalForces) {
+        self.dirty |= forces != self.forces;
+        self.forces = forces;
+    }
+
+    pub fn get(&self) -> GlobalForces {
+        self.forces
+    }
+
+    /// Writes the forces changed since the last upload to the uniform buffer.
+    pub fn upload(&mut self, wgpu_context: &WgpuContext) {
+        if self.dirty {
+            self.buffer.replace_elem(Self::uniform(&self.forces), 0, wgpu_context);
+            self.dirty = false;
+        }
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        self.buffer.buffer()
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::grid::grid::Grid;
+use crate::particles::particle_system::ParticleSystem;
+use crate::physics::collision_system::CollisionSystem;
+use crate::physics::force_kernel::{apply_force_kernels, ForceKernel};
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::profiler::GpuProfiler;
+
+const MANIFEST_FILE: &str = "manifest.txt";
+
+/// Every intermediate buffer of one physics step, stored as raw bytes.
+/// Captures are saved as one `<name>.bin` file per buffer plus a `manifest.txt`
+/// with the buffer names and the step metadata (cell size, delta time...),
+/// so single kernels can be replayed from them in tests.
+#[derive(Default)]
+pub struct FrameCapture {
+    buffers: BTreeMap<String, Vec<u8>>,
+    metadata: BTreeMap<String, String>,
+}
+
+impl FrameCapture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert<T: bytemuck::Pod>(&mut self, name: &str, data: &[T]) {
+        self.buffers.insert(name.to_string(), bytemuck::cast_slice(data).to_vec());
+    }
+
+    pub fn get<T: bytemuck::Pod>(&self, name: &str) -> Option<Vec<T>> {
+        let bytes = self.buffers.get(name)?;
+        Some(bytes.chunks_exact(size_of::<T>()).map(bytemuck::pod_read_unaligned).collect())
+    }
+
+    pub fn set_metadata(&mut self, key: &str, value: impl ToString) {
+        self.metadata.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn metadata<T: std::str::FromStr>(&self, key: &str) -> Option<T> {
+        self.metadata.get(key)?.parse().ok()
+    }
+
+    pub fn buffer_names(&self) -> impl Iterator<Item = &str> {
+        self.buffers.keys().map(String::as_str)
+    }
+
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+        let mut manifest = String::new();
+        for (name, bytes) in &self.buffers {
+            fs::write(dir.join(format!("{}.bin", name)), bytes)?;
+            manifest.push_str(&format!("buffer {} {}\n", name, bytes.len()));
+        }
+        for (key, value) in &self.metadata {
+            manifest.push_str(&format!("meta {} {}\n", key, value));
+        }
+        fs::write(dir.join(MANIFEST_FILE), manifest)
+    }
+
+    pub fn load(dir: &Path) -> io::Result<Self> {
+        let mut capture = Self::new();
+        let manifest = fs::read_to_string(dir.join(MANIFEST_FILE))?;
+        for line in manifest.lines() {
+            let mut fields = line.splitn(3, ' ');
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some("buffer"), Some(name), Some(_)) => {
+                    let bytes = fs::read(dir.join(format!("{}.bin", name)))?;
+                    capture.buffers.insert(name.to_string(), bytes);
+                }
+                (Some("meta"), Some(key), Some(value)) => {
+                    capture.metadata.insert(key.to_string(), value.to_string());
+                }
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid manifest line: {}", line))),
+            }
+        }
+        Ok(capture)
+    }
+}
+
+/// Snapshot of the particle state only: positions, previous positions, radii and channels.
+/// Much cheaper than `capture_physics_step`, it does not run any kernel.
+pub fn capture_particle_state(wgpu_context: &WgpuContext, particles: &mut ParticleSystem) -> FrameCapture {
+    let mut capture = FrameCapture::new();
+    capture.set_metadata("num_particles", particles.len());
+    capture.set_metadata("max_radius", particles.get_max_radius());
+    capture.set_metadata("channel_stride", particles.channels().stride());
+
+    let buffers = particles.download_particle_buffers(wgpu_context);
+    capture.insert("positions", buffers.current_positions.data());
+    capture.insert("previous_positions", buffers.previous_positions.data());
+    capture.insert("radii", buffers.radii.data());
+    capture.insert("extras", buffers.extras.data());
+    capture
+}
+
+/// Runs one physics step (grid, collision cells, solver, user forces and integration), submitting every stage
+/// separately and reading back its outputs. Very slow, meant for debugging a single frame.
+pub fn capture_physics_step(wgpu_context: &WgpuContext, particles: &mut ParticleSystem, grid: &mut Grid, collision_system: &mut CollisionSystem, force_kernels: &[ForceKernel], gpu_profiler: &mut GpuProfiler, delta_time: f32) -> FrameCapture {
+    let mut capture = FrameCapture::new();
+    capture.set_metadata("num_particles", particles.len());
+    capture.set_metadata("cell_size", grid.cell_size());
+    capture.set_metadata("max_radius", particles.get_max_radius());
+    capture.set_metadata("delta_time", delta_time);
+
+    let buffers = particles.download_particle_buffers(wgpu_context);
+    capture.insert("positions_before_solve", buffers.current_positions.data());
+    capture.insert("previous_positions", buffers.previous_positions.data());
+    capture.insert("radii", buffers.radii.data());
+
+    // Step 1: cell ids
+    let mut encoder = wgpu_context.get_device().create_command_encoder(
+        &wgpu::CommandEncoderDescriptor { label: Some("Capture build cell ids") }
+    );
+    grid.build_cell_ids(&mut encoder);
+    wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+    capture.insert("cell_ids_unsorted", &grid.download_cell_ids(wgpu_context).unwrap());
+    capture.insert("object_ids_unsorted", &grid.download_object_ids(wgpu_context).unwrap());
+
+    // Step 2: sort
+    let mut encoder = wgpu_context.get_device().create_command_encoder(
+        &wgpu::CommandEncoderDescriptor { label: Some("Capture sort map") }
+    );
+    grid.sort_map(&mut encoder);
+    wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+    capture.insert("cell_ids_sorted", &grid.download_cell_ids(wgpu_context).unwrap());
+    capture.insert("object_ids_sorted", &grid.download_object_ids(wgpu_context).unwrap());
+
+    // Step 3: collision cells
+    let encoder = wgpu_context.get_device().create_command_encoder(
+        &wgpu::CommandEncoderDescriptor { label: Some("Capture collision cells") }
+    );
+    collision_system.build_collision_cells(wgpu_context, encoder, gpu_profiler);
+    capture.insert("chunk_counts", &collision_system.download_chunk_counts(wgpu_context));
+    capture.insert("collision_cells", &collision_system.download_collision_cells(wgpu_context));
+
+    // Step 4: solve
+    collision_system.solve_built_collision_cells(wgpu_context, gpu_profiler);
+    let buffers = particles.download_particle_buffers(wgpu_context);
+    capture.insert("positions_after_solve", buffers.current_positions.data());
+
+    // Step 5: user forces
+    if !force_kernels.is_empty() {
+        apply_force_kernels(wgpu_context, force_kernels, particles, gpu_profiler, delta_time);
+        let buffers = particles.download_particle_buffers(wgpu_context);
+        capture.insert("previous_positions_after_forces", buffers.previous_positions.data());
+    }
+
+    // Step 6: integration
+    particles.update_positions(delta_time, wgpu_context, gpu_profiler);
+    let buffers = particles.download_particle_buffers(wgpu_context);
+    capture.insert("positions_after_integration", buffers.current_positions.data());
+
+    capture
+}
@@ -0,0 +1,77 @@
+use wgpu::CommandEncoder;
+use crate::physics::pass_validation::PhysicsPass;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::profiler::GpuProfiler;
+
+/// Schedules the compute work of a frame: the subsystems register their passes with `add_pass`, which
+/// records them into one shared encoder inside a profiler scope named after the pass, and `submit` hands
+/// the whole frame to the queue at once, instead of one small command buffer per subsystem.
+///
+/// Work that reads results back on the CPU in the middle of a frame, like the compaction, calls `flush`
+/// first, so the commands recorded until then run before the read.
+pub struct FrameGraph {
+    label: &'static str,
+    encoder: CommandEncoder,
+    passes: Vec<PhysicsPass>,
+    submissions: u32,
+}
+
+impl FrameGraph {
+    pub fn new(wgpu_context: &WgpuContext, label: &'static str) -> Self {
+        Self {
+            label,
+            encoder: Self::create_encoder(wgpu_context, label),
+            passes: Vec::new(),
+            submissions: 0,
+        }
+    }
+
+    /// Records a pass: `record` encodes its commands, wrapped in a profiler scope named after `pass`, and can
+    /// open nested scopes with the profiler it is given. Kernels recording into the encoder must not resolve
+    /// the profiler queries, `flush` and `submit` do it.
+    pub fn add_pass(&mut self, pass: PhysicsPass, gpu_profiler: &GpuProfiler, record: impl FnOnce(&mut CommandEncoder, &GpuProfiler)) {
+        {
+            let mut scope = gpu_profiler.scope(pass.label(), &mut self.encoder);
+            record(&mut scope, gpu_profiler);
+        }
+        self.passes.push(pass);
+    }
+
+    /// The shared encoder, for the commands outside of a pass: debug groups, timestamps.
+    pub fn encoder(&mut self) -> &mut CommandEncoder {
+        &mut self.encoder
+    }
+
+    /// Passes added so far, in recording order.
+    pub fn passes(&self) -> &[PhysicsPass] {
+        &self.passes
+    }
+
+    /// Command buffers submitted so far by `flush`.
+    pub fn submissions(&self) -> u32 {
+        self.submissions
+    }
+
+    /// Submits the commands recorded so far and keeps recording into a new encoder.
+    pub fn flush(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler) -> wgpu::SubmissionIndex {
+        let encoder = std::mem::replace(&mut self.encoder, Self::create_encoder(wgpu_context, self.label));
+        self.submissions += 1;
+        Self::submit_encoder(wgpu_context, gpu_profiler, encoder)
+    }
+
+    /// Resolves the profiler queries of the frame and submits everything that was not flushed yet.
+    pub fn submit(self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler) -> wgpu::SubmissionIndex {
+        Self::submit_encoder(wgpu_context, gpu_profiler, self.encoder)
+    }
+
+    fn submit_encoder(wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, mut encoder: CommandEncoder) -> wgpu::SubmissionIndex {
+        gpu_profiler.resolve_queries(&mut encoder);
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()))
+    }
+
+    fn create_encoder(wgpu_context: &WgpuContext, label: &'static str) -> CommandEncoder {
+        wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some(label) }
+        )
+    }
+}
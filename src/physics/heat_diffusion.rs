@@ -0,0 +1,262 @@
+use glam::Vec2;
+use wgpu::{BindGroupLayout, CommandEncoder, PushConstantRange};
+use crate::grid::grid::Grid;
+use crate::particles::particle_system::ParticleSystem;
+use crate::physics::cell_range_solver::CellRangeSolver;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::bind_resources::BindResources;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::profiler::GpuProfiler;
+
+pub const WORKGROUP_SIZE: u32 = 64;
+
+/// Name of the per-particle temperature channel, an `f32` in degrees.
+pub const TEMPERATURE_CHANNEL: &str = "temperature";
+
+/// Settings of the heat of `Simulation::enable_heat`, in world units. Temperatures have no unit, only their
+/// differences to `ambient_temperature` matter.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HeatConfig {
+    /// Fraction of their temperature difference two touching particles exchange per second. Capped at an
+    /// eighth per step, so a packed pile does not overshoot.
+    pub conductivity: f32,
+    /// Fraction of the difference to `ambient_temperature` every particle loses per second, 0 keeps the heat.
+    pub cooling: f32,
+    /// Temperature of the existing particles, and the one the particles cool down to.
+    pub ambient_temperature: f32,
+    /// Acceleration per degree above `ambient_temperature`, in world units per second squared, against the
+    /// uniform gravity (upwards without gravity). Colder particles sink.
+    pub buoyancy: f32,
+}
+
+impl Default for HeatConfig {
+    fn default() -> Self {
+        Self {
+            conductivity: 2.0,
+            cooling: 0.05,
+            ambient_temperature: 0.0,
+            buoyancy: 2.0,
+        }
+    }
+}
+
+/// A circle of the world heating the particles inside it, e.g. under the cursor with `InteractionMode::Heat`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HeatBrush {
+    pub position: Vec2,
+    pub radius: f32,
+    /// Degrees per second added to the particles inside, negative to cool them.
+    pub rate: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstantsData {
+    delta_time: f32,
+    conductivity: f32,
+    cooling: f32,
+    ambient_temperature: f32,
+    origin: Vec2,
+    buoyancy: Vec2,
+    brush_position: Vec2,
+    brush_radius: f32,
+    brush_rate: f32,
+    extras_stride: u32,
+    temperature_offset: u32,
+}
+
+/// Heat of `Simulation::enable_heat`. The temperature of every particle lives in the `TEMPERATURE_CHANNEL`
+/// channel, so it follows the particles through the sort and the compaction. Every step, each particle finds the
+/// particles it touches in the cell tables of a `CellRangeSolver` and exchanges heat with them, cools down towards
+/// the ambient temperature and takes the heat of the brush; the new temperatures then push the warm particles up.
+pub(crate) struct HeatDiffusion {
+    cell_ranges: CellRangeSolver,
+    diffuse_shader: ComputeShader,
+    apply_shader: ComputeShader,
+    bind_resources: BindResources,
+    next_temperatures: GpuBuffer<f32>,
+    config: HeatConfig,
+    brush: Option<HeatBrush>,
+    extras_stride: u32,
+    temperature_offset: u32,
+}
+
+impl HeatDiffusion {
+    /// The particles must have the temperature channel.
+    pub fn new(wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid, config: HeatConfig) -> Self {
+        let cell_ranges = CellRangeSolver::new(wgpu_context, particle_system, grid);
+        let next_temperatures = GpuBuffer::new(wgpu_context, vec![0.0; particle_system.len().max(1)], wgpu::BufferUsages::STORAGE);
+
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let bind_group = Self::create_bind_group(wgpu_context, &bind_group_layout, particle_system, grid, &cell_ranges, &next_temperatures);
+        let bind_resources = BindResources::new(bind_group_layout, bind_group);
+
+        let create_shader = |entry_point: &str| ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("heat_diffusion.wgsl"),
+            entry_point,
+            &bind_resources.bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange {
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstantsData>() as u32,
+                }
+            ]
+        );
+        let diffuse_shader = create_shader("diffuse_heat");
+        let apply_shader = create_shader("apply_heat");
+
+        Self {
+            cell_ranges,
+            diffuse_shader,
+            apply_shader,
+            bind_resources,
+            next_temperatures,
+            config,
+            brush: None,
+            extras_stride: particle_system.channels().stride(),
+            temperature_offset: Self::temperature_offset(particle_system),
+        }
+    }
+
+    fn temperature_offset(particle_system: &ParticleSystem) -> u32 {
+        let channels = particle_system.channels();
+        let id = channels.find(TEMPERATURE_CHANNEL).expect("The heat needs the temperature channel");
+        channels.offset(id)
+    }
+
+    pub fn set_config(&mut self, config: HeatConfig) {
+        self.config = config;
+    }
+
+    pub fn config(&self) -> HeatConfig {
+        self.config
+    }
+
+    /// Heats the particles under `brush` every step, `None` stops.
+    pub fn set_brush(&mut self, brush: Option<HeatBrush>) {
+        self.brush = brush;
+    }
+
+    pub fn brush(&self) -> Option<HeatBrush> {
+        self.brush
+    }
+
+    /// Follows `Grid::set_origin`.
+    pub fn set_origin(&mut self, origin: Vec2) {
+        self.cell_ranges.set_origin(origin);
+    }
+
+    /// Follows new or removed particles, a new cell size, new particle buffers or new channels.
+    pub fn refresh_buffers(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid) {
+        self.cell_ranges.refresh_buffers(wgpu_context, particle_system, grid);
+        if particle_system.len() > self.next_temperatures.len() {
+            self.next_temperatures.push_all(&vec![0.0; particle_system.len() - self.next_temperatures.len()], wgpu_context);
+        }
+        self.extras_stride = particle_system.channels().stride();
+        self.temperature_offset = Self::temperature_offset(particle_system);
+        self.bind_resources.bind_group = Self::create_bind_group(wgpu_context, &self.bind_resources.bind_group_layout, particle_system, grid, &self.cell_ranges, &self.next_temperatures);
+    }
+
+    fn create_bind_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, particle_system: &ParticleSystem, grid: &Grid, cell_ranges: &CellRangeSolver, next_temperatures: &GpuBuffer<f32>) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Heat diffusion bind group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: grid.object_ids().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: cell_ranges.cell_start().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: cell_ranges.cell_end().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 3, resource: particle_system.positions().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 4, resource: particle_system.buffers().previous_positions.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 5, resource: particle_system.radius().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 6, resource: particle_system.buffers().extras.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 7, resource: next_temperatures.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 8, resource: cell_ranges.uniform_buffer().as_entire_binding() },
+            ],
+        })
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Heat diffusion bind group layout"),
+            entries: &[
+                // Object ids
+                storage_entry(0, true),
+                // Cell start
+                storage_entry(1, true),
+                // Cell end
+                storage_entry(2, true),
+                // Positions
+                storage_entry(3, true),
+                // Previous positions
+                storage_entry(4, false),
+                // Radius
+                storage_entry(5, true),
+                // Extras, for the temperature channel
+                storage_entry(6, false),
+                // Next temperatures
+                storage_entry(7, false),
+                // Uniform data of the cell ranges
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    /// Records the cell boundaries, the diffusion and the apply passes of a step of `delta_time`. Must follow the
+    /// sort of the grid. `gravity` gives the direction of the buoyancy, `brush` replaces the one of `set_brush`.
+    pub fn record(&self, encoder: &mut CommandEncoder, gpu_profiler: &GpuProfiler, num_particles: u32, delta_time: f32, gravity: Vec2, brush: Option<HeatBrush>) {
+        if num_particles == 0 || delta_time <= 0.0 {
+            return;
+        }
+        self.cell_ranges.find_cell_boundaries(encoder, gpu_profiler);
+        let up = -gravity.normalize_or(Vec2::NEG_Y);
+        let brush = brush.unwrap_or(HeatBrush { position: Vec2::ZERO, radius: 0.0, rate: 0.0 });
+        let push_constants = PushConstantsData {
+            delta_time,
+            conductivity: self.config.conductivity,
+            cooling: self.config.cooling,
+            ambient_temperature: self.config.ambient_temperature,
+            origin: self.cell_ranges.origin(),
+            buoyancy: up * self.config.buoyancy,
+            brush_position: brush.position,
+            brush_radius: brush.radius,
+            brush_rate: brush.rate,
+            extras_stride: self.extras_stride,
+            temperature_offset: self.temperature_offset,
+        };
+        let mut scope = gpu_profiler.scope("Heat diffusion", encoder);
+        for shader in [&self.diffuse_shader, &self.apply_shader] {
+            shader.dispatch_by_items(
+                &mut scope,
+                (num_particles, 1, 1),
+                Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                &self.bind_resources.bind_group
+            );
+        }
+    }
+}
@@ -0,0 +1,132 @@
+override WORKGROUP_SIZE = 64u;
+
+// Temperature taken from every touching neighbour per step, at most, so the explicit diffusion stays stable
+const MAX_EXCHANGE = 0.125;
+
+// Same layout as the UniformData of cell_range_solver.wgsl
+struct UniformData {
+    total_cell_ids: u32,
+    num_particles: u32,
+    // Length of cell_start and cell_end, cells with a larger morton id have no neighbours
+    table_len: u32,
+    cell_size: f32,
+}
+
+struct PushConstantsData {
+    delta_time: f32,
+    // Fraction of the temperature difference exchanged per second by two touching particles
+    conductivity: f32,
+    // Fraction of the difference to the ambient temperature lost per second
+    cooling: f32,
+    ambient_temperature: f32,
+    // World position of the corner of cell (0, 0), see Grid::origin
+    origin: vec2<f32>,
+    // Acceleration per degree above the ambient temperature, against the gravity
+    buoyancy: vec2<f32>,
+    brush_position: vec2<f32>,
+    // 0 when the brush is off
+    brush_radius: f32,
+    // Degrees per second added to the particles under the brush
+    brush_rate: f32,
+    extras_stride: u32,
+    // Word offset of the temperature channel
+    temperature_offset: u32,
+}
+
+// Sorted by the grid
+@group(0) @binding(0) var<storage, read> object_ids: array<u32>;
+// Indexed by morton cell id: the cell holds object_ids[cell_start..cell_end], see find_cell_boundaries
+@group(0) @binding(1) var<storage, read> cell_start: array<u32>;
+@group(0) @binding(2) var<storage, read> cell_end: array<u32>;
+@group(0) @binding(3) var<storage, read> positions: array<vec2<f32>>;
+@group(0) @binding(4) var<storage, read_write> previous_positions: array<vec2<f32>>;
+@group(0) @binding(5) var<storage, read> radius: array<f32>;
+@group(0) @binding(6) var<storage, read_write> extras: array<u32>;
+// Temperatures after the step, copied into the channel by apply_heat
+@group(0) @binding(7) var<storage, read_write> next_temperatures: array<f32>;
+@group(0) @binding(8) var<uniform> uniform_data: UniformData;
+
+var<push_constant> push_constants: PushConstantsData;
+
+// One thread per particle: exchanges heat with the touching particles, cools down and takes the heat of the brush.
+// Temperatures are only read, so the result does not depend on the scheduling.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn diffuse_heat(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let object_id = global_id.x;
+    if object_id >= uniform_data.num_particles {
+        return;
+    }
+
+    let position = positions[object_id];
+    let object_radius = radius[object_id];
+    let temperature = read_temperature(object_id);
+    let exchange = min(push_constants.conductivity * push_constants.delta_time, MAX_EXCHANGE);
+    let home_cell = home_cell_coord(position);
+
+    var heat = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let cell_coord = home_cell + vec2<i32>(x, y);
+            if any(cell_coord < vec2<i32>(0)) {
+                continue;
+            }
+            let cell = morton_encode(cell_coord);
+            if cell >= uniform_data.table_len {
+                continue;
+            }
+            for (var j = cell_start[cell]; j < cell_end[cell]; j++) {
+                let other_object_id = object_ids[j];
+                let other_position = positions[other_object_id];
+                // The phantom cells of a particle hold it too, only its home cell counts
+                if other_object_id == object_id || any(home_cell_coord(other_position) != cell_coord) {
+                    continue;
+                }
+                if distance(position, other_position) < object_radius + radius[other_object_id] {
+                    heat += (read_temperature(other_object_id) - temperature) * exchange;
+                }
+            }
+        }
+    }
+    heat -= (temperature - push_constants.ambient_temperature) * min(push_constants.cooling * push_constants.delta_time, 1.0);
+    if distance(position, push_constants.brush_position) < push_constants.brush_radius {
+        heat += push_constants.brush_rate * push_constants.delta_time;
+    }
+    next_temperatures[object_id] = temperature + heat;
+}
+
+// Stores the new temperatures and lifts the particles warmer than the ambient temperature.
+// The buoyancy is integrated by the next integration, like ForceKernel accelerations.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn apply_heat(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let object_id = global_id.x;
+    if object_id >= uniform_data.num_particles {
+        return;
+    }
+    let temperature = next_temperatures[object_id];
+    extras[object_id * push_constants.extras_stride + push_constants.temperature_offset] = bitcast<u32>(temperature);
+    let acceleration = push_constants.buoyancy * (temperature - push_constants.ambient_temperature);
+    previous_positions[object_id] -= acceleration * push_constants.delta_time * push_constants.delta_time;
+}
+
+fn read_temperature(object_id: u32) -> f32 {
+    return bitcast<f32>(extras[object_id * push_constants.extras_stride + push_constants.temperature_offset]);
+}
+
+// Like particle_cells in cell_range_solver.wgsl
+fn home_cell_coord(world_position: vec2<f32>) -> vec2<i32> {
+    return max(vec2<i32>(floor((world_position - push_constants.origin) / uniform_data.cell_size)), vec2<i32>(0));
+}
+
+fn split_by_bits(n: u32) -> u32 {
+    var x = n & 0x0000FFFF;
+    x = (x | (x << 8)) & 0x00FF00FF;
+    x = (x | (x << 4)) & 0x0F0F0F0F;
+    x = (x | (x << 2)) & 0x33333333;
+    x = (x | (x << 1)) & 0x55555555;
+    return x;
+}
+
+// Must match morton::encode
+fn morton_encode(v: vec2<i32>) -> u32 {
+    return split_by_bits(u32(v.x)) | (split_by_bits(u32(v.y)) << 1);
+}
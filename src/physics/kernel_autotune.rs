@@ -0,0 +1,176 @@
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use glam::Vec2;
+use wgpu::wgt::PollType::WaitForSubmissionIndex;
+use crate::grid::grid::Grid;
+use crate::particles::particle_system::ParticleSystem;
+use crate::physics::collision_system::CollisionSystem;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::simulation::DIMENSION;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::kernel_tuning::KernelTuning;
+use crate::utils::prefix_sum::prefix_sum::PrefixSum;
+use crate::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+use crate::utils::radix_sort::GPUSorter;
+
+const SORT_WORKGROUP_SIZES: [u32; 3] = [64, 128, 256];
+const SORT_BLOCKS_PER_WORKGROUP: [u32; 4] = [15, 30, 45, 60];
+const PREFIX_SUM_WORKGROUP_SIZES: [u32; 3] = [64, 128, 256];
+const COLLISION_WORKGROUP_SIZES: [u32; 4] = [32, 64, 128, 256];
+
+/// Keys of the sort measures and items of the prefix sum measures.
+const MEASURED_KEYS: u32 = 1 << 18;
+/// Particles along each side of the square of the collision measures.
+const MEASURED_PARTICLES_SIDE: u32 = 128;
+/// Runs before the measured ones, which compile the pipelines and warm the clocks up.
+const WARMUP_RUNS: u32 = 2;
+/// The fastest of these runs is the time of a candidate.
+const MEASURED_RUNS: u32 = 5;
+
+/// Measures the candidate workgroup sizes of `KernelTuning` on the adapter of `wgpu_context` and returns the
+/// fastest. One kernel after the other, each with the best sizes found for the previous ones. Workgroup sizes
+/// are capped by `DeviceCapabilities::workgroup_size` first, so sizes the device caps to the same one are
+/// measured once. Leaves the returned tuning set on the context.
+pub fn autotune(wgpu_context: &mut WgpuContext) -> KernelTuning {
+    let capabilities = wgpu_context.capabilities();
+    let capped = |sizes: &[u32]| -> Vec<u32> { sizes.iter().map(|&size| capabilities.workgroup_size(size)).collect() };
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings {
+        enable_timer_queries: false,
+        enable_debug_groups: false,
+        max_num_pending_frames: 1,
+    }).unwrap();
+    let mut tuning = KernelTuning::default();
+
+    tuning.sort_workgroup_size = fastest(wgpu_context, tuning, &capped(&SORT_WORKGROUP_SIZES),
+        |tuning, size| tuning.sort_workgroup_size = size, measure_sort);
+    tuning.sort_blocks_per_workgroup = fastest(wgpu_context, tuning, &SORT_BLOCKS_PER_WORKGROUP,
+        |tuning, blocks| tuning.sort_blocks_per_workgroup = blocks, measure_sort);
+    tuning.prefix_sum_workgroup_size = fastest(wgpu_context, tuning, &capped(&PREFIX_SUM_WORKGROUP_SIZES),
+        |tuning, size| tuning.prefix_sum_workgroup_size = size, measure_prefix_sum);
+    tuning.collision_workgroup_size = fastest(wgpu_context, tuning, &capped(&COLLISION_WORKGROUP_SIZES),
+        |tuning, size| tuning.collision_workgroup_size = size, |wgpu_context| measure_collisions(wgpu_context, &mut gpu_profiler));
+
+    wgpu_context.set_kernel_tuning(tuning);
+    tuning
+}
+
+/// The tuning cached at `path` for the adapter of `wgpu_context`, or a new one from `autotune`, which is then
+/// cached. Either way it is set on the context.
+pub fn load_or_autotune(wgpu_context: &mut WgpuContext, path: impl AsRef<Path>) -> KernelTuning {
+    let path = path.as_ref();
+    let adapter = KernelTuning::adapter_key(wgpu_context);
+    match KernelTuning::load(path, &adapter) {
+        Ok(Some(tuning)) => {
+            log::info!("Kernel tuning for {} loaded from {}: {:?}", adapter, path.display(), tuning);
+            wgpu_context.set_kernel_tuning(tuning);
+            return tuning;
+        }
+        Ok(None) => {}
+        Err(e) => log::warn!("Ignoring the kernel tuning cache: {}", e),
+    }
+
+    let start = Instant::now();
+    let tuning = autotune(wgpu_context);
+    log::info!("Kernel tuning for {} measured in {:.1} s: {:?}", adapter, start.elapsed().as_secs_f64(), tuning);
+    if let Err(e) = tuning.save(path, &adapter) {
+        log::warn!("Could not cache the kernel tuning at {}: {}", path.display(), e);
+    }
+    tuning
+}
+
+/// The candidate of `candidates` whose `measure` is the fastest, the other sizes being the ones of `tuning`.
+fn fastest(
+    wgpu_context: &mut WgpuContext,
+    tuning: KernelTuning,
+    candidates: &[u32],
+    apply: impl Fn(&mut KernelTuning, u32),
+    mut measure: impl FnMut(&WgpuContext) -> Duration,
+) -> u32 {
+    let mut best: Option<(u32, Duration)> = None;
+    let mut measured = Vec::new();
+    for &candidate in candidates {
+        let mut candidate_tuning = tuning;
+        apply(&mut candidate_tuning, candidate);
+        if measured.contains(&candidate_tuning) {
+            continue;
+        }
+        measured.push(candidate_tuning);
+
+        wgpu_context.set_kernel_tuning(candidate_tuning);
+        let time = measure(wgpu_context);
+        log::debug!("Kernel tuning {:?}: {:.3} ms", candidate_tuning, time.as_secs_f64() * 1000.0);
+        if best.is_none_or(|(_, best_time)| time < best_time) {
+            best = Some((candidate, time));
+        }
+    }
+    best.map_or(candidates[0], |(candidate, _)| candidate)
+}
+
+/// The fastest of `MEASURED_RUNS` runs of `record`, each submitted alone and waited for.
+fn time_runs(wgpu_context: &WgpuContext, mut record: impl FnMut(&mut wgpu::CommandEncoder)) -> Duration {
+    let mut best = Duration::MAX;
+    for run in 0..WARMUP_RUNS + MEASURED_RUNS {
+        let start = Instant::now();
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Kernel autotune encoder") }
+        );
+        record(&mut encoder);
+        let idx = wgpu_context.get_queue().submit(Some(encoder.finish()));
+        wgpu_context.get_device().poll(WaitForSubmissionIndex(idx)).unwrap();
+        if run >= WARMUP_RUNS {
+            best = best.min(start.elapsed());
+        }
+    }
+    best
+}
+
+/// Keys in a fixed pseudo random order, so every candidate sorts the same ones.
+fn scrambled_keys(len: u32) -> Vec<u32> {
+    (0..len).map(|i| i.wrapping_mul(2654435761) ^ (i >> 7)).collect()
+}
+
+fn measure_sort(wgpu_context: &WgpuContext) -> Duration {
+    let keys = GpuBuffer::new(wgpu_context, scrambled_keys(MEASURED_KEYS), wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST);
+    let payload = GpuBuffer::new(wgpu_context, (0..MEASURED_KEYS).collect(), wgpu::BufferUsages::STORAGE);
+    let sorter = GPUSorter::new(wgpu_context, NonZeroU32::new(MEASURED_KEYS).unwrap(), &keys, &payload);
+    time_runs(wgpu_context, |encoder| sorter.sort(encoder, None))
+}
+
+fn measure_prefix_sum(wgpu_context: &WgpuContext) -> Duration {
+    let items = GpuBuffer::new(wgpu_context, vec![1u32; MEASURED_KEYS as usize], wgpu::BufferUsages::STORAGE);
+    let prefix_sum = PrefixSum::new(wgpu_context, &items);
+    time_runs(wgpu_context, |encoder| prefix_sum.record(encoder, MEASURED_KEYS))
+}
+
+/// A square of touching particles, every one with a contact on each side.
+fn measure_collisions(wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler) -> Duration {
+    let positions: Vec<Vec2> = (0..MEASURED_PARTICLES_SIDE * MEASURED_PARTICLES_SIDE)
+        .map(|i| Vec2::new((i % MEASURED_PARTICLES_SIDE) as f32, (i / MEASURED_PARTICLES_SIDE) as f32) * 1.9 + Vec2::splat(10.0))
+        .collect();
+    let radii = vec![1.0; positions.len()];
+    let particles = ParticleSystem::new_from_buffers(
+        wgpu_context,
+        GpuBuffer::new(wgpu_context, positions, wgpu::BufferUsages::STORAGE),
+        GpuBuffer::new(wgpu_context, radii, wgpu::BufferUsages::STORAGE),
+    );
+    let mut grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let mut collision_system = CollisionSystem::new(wgpu_context, DIMENSION, &particles, &grid);
+
+    let mut best = Duration::MAX;
+    for run in 0..WARMUP_RUNS + MEASURED_RUNS {
+        let start = Instant::now();
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Kernel autotune encoder") }
+        );
+        grid.update(&mut encoder, gpu_profiler);
+        collision_system.solve_collisions(wgpu_context, encoder, gpu_profiler);
+        let idx = wgpu_context.get_queue().submit(std::iter::empty());
+        wgpu_context.get_device().poll(WaitForSubmissionIndex(idx)).unwrap();
+        gpu_profiler.end_frame().unwrap();
+        if run >= WARMUP_RUNS {
+            best = best.min(start.elapsed());
+        }
+    }
+    best
+}
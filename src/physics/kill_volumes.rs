@@ -0,0 +1,168 @@
+use glam::Vec2;
+use wgpu::{BindGroupLayout, PushConstantRange};
+use crate::particles::particle_system::ParticleSystem;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::profiler::GpuProfiler;
+
+pub const WORKGROUP_SIZE: u32 = 64;
+
+/// Region of the world that deletes the particles whose center enters it, e.g. the bottom of a funnel.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum KillVolume {
+    /// Everything below the horizontal line at `y`.
+    BelowLine { y: f32 },
+    /// Everything outside the circle.
+    OutsideCircle { center: Vec2, radius: f32 },
+}
+
+impl KillVolume {
+    pub fn contains(&self, position: Vec2) -> bool {
+        match *self {
+            KillVolume::BelowLine { y } => position.y < y,
+            KillVolume::OutsideCircle { center, radius } => position.distance_squared(center) > radius * radius,
+        }
+    }
+
+    /// Must match the KILL_ constants of kill_volumes.wgsl
+    fn to_gpu(self) -> KillVolumeData {
+        match self {
+            KillVolume::BelowLine { y } => KillVolumeData { center: Vec2::new(0.0, y), radius: 0.0, kind: 0 },
+            KillVolume::OutsideCircle { center, radius } => KillVolumeData { center, radius, kind: 1 },
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct KillVolumeData {
+    center: Vec2,
+    radius: f32,
+    kind: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstantsData {
+    num_particles: u32,
+    num_volumes: u32,
+}
+
+/// Kill volumes of a simulation, see `Simulation::add_kill_volume`. `mark_killed` moves the particles inside
+/// them far outside the world, like `GroupOperation::Remove`, so the compaction that follows deletes them.
+pub struct KillVolumes {
+    shader: ComputeShader,
+    bind_group_layout: BindGroupLayout,
+    kill_volumes: Vec<KillVolume>,
+    volumes: GpuBuffer<KillVolumeData>, // Holds a single unused volume when there are none
+}
+
+impl KillVolumes {
+    pub fn new(wgpu_context: &WgpuContext) -> Self {
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let shader = ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("kill_volumes.wgsl"),
+            "mark_killed",
+            &bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange {
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstantsData>() as u32,
+                }
+            ]
+        );
+
+        Self {
+            shader,
+            bind_group_layout,
+            kill_volumes: Vec::new(),
+            volumes: Self::create_volumes_buffer(wgpu_context, &[]),
+        }
+    }
+
+    pub fn volumes(&self) -> &[KillVolume] {
+        &self.kill_volumes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kill_volumes.is_empty()
+    }
+
+    /// Replaces the kill volumes.
+    pub fn set_volumes(&mut self, wgpu_context: &WgpuContext, kill_volumes: Vec<KillVolume>) {
+        self.volumes = Self::create_volumes_buffer(wgpu_context, &kill_volumes);
+        self.kill_volumes = kill_volumes;
+    }
+
+    fn create_volumes_buffer(wgpu_context: &WgpuContext, kill_volumes: &[KillVolume]) -> GpuBuffer<KillVolumeData> {
+        // Bindings can not be empty
+        let volumes = if kill_volumes.is_empty() { vec![KillVolumeData::default()] } else { kill_volumes.iter().map(|volume| volume.to_gpu()).collect() };
+        GpuBuffer::new(wgpu_context, volumes, wgpu::BufferUsages::STORAGE)
+    }
+
+    /// Records the pass marking the particles inside the volumes, if there are any. Bound to the particle
+    /// buffers on every call, it only runs before a compaction.
+    pub fn mark_killed(&self, wgpu_context: &WgpuContext, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler, particle_system: &ParticleSystem) {
+        let num_particles = particle_system.len() as u32;
+        if self.kill_volumes.is_empty() || num_particles == 0 {
+            return;
+        }
+        let bind_group = self.create_bind_group(wgpu_context, particle_system);
+        let push_constants = PushConstantsData {
+            num_particles,
+            num_volumes: self.kill_volumes.len() as u32,
+        };
+        {
+            let mut scope = gpu_profiler.scope("Kill volumes", encoder);
+            self.shader.dispatch_by_items(
+                &mut scope,
+                (num_particles, 1, 1),
+                Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                &bind_group
+            );
+        }
+    }
+
+    fn create_bind_group(&self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Kill volumes bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: particle_system.positions().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: particle_system.buffers().previous_positions.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: self.volumes.buffer().as_entire_binding() },
+            ],
+        })
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Kill volumes bind group layout"),
+            entries: &[
+                // Positions
+                storage_entry(0, false),
+                // Previous positions
+                storage_entry(1, false),
+                // Volumes
+                storage_entry(2, true),
+            ],
+        })
+    }
+}
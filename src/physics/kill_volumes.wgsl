@@ -0,0 +1,52 @@
+override WORKGROUP_SIZE = 64u;
+
+// Must match KillVolume::kind
+const KILL_BELOW_LINE = 0u;
+const KILL_OUTSIDE_CIRCLE = 1u;
+
+// Same as the removed particles of particle_group.wgsl, the compaction deletes particles outside the world
+const REMOVED_POSITION = vec2<f32>(-3.40282347e38);
+
+struct Volume {
+    // The line is at center.y
+    center: vec2<f32>,
+    radius: f32,
+    kind: u32,
+}
+
+struct PushConstantsData {
+    num_particles: u32,
+    num_volumes: u32,
+}
+
+@group(0) @binding(0) var<storage, read_write> positions: array<vec2<f32>>;
+@group(0) @binding(1) var<storage, read_write> previous_positions: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read> volumes: array<Volume>;
+
+var<push_constant> push_constants: PushConstantsData;
+
+// One thread per particle: marks it dead if its center is inside any kill volume
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn mark_killed(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let object_id = global_id.x;
+    if object_id >= push_constants.num_particles {
+        return;
+    }
+
+    let position = positions[object_id];
+    var killed = false;
+    for (var i = 0u; i < push_constants.num_volumes; i++) {
+        let volume = volumes[i];
+        if volume.kind == KILL_BELOW_LINE {
+            killed = killed || position.y < volume.center.y;
+        }
+        else {
+            let offset = position - volume.center;
+            killed = killed || dot(offset, offset) > volume.radius * volume.radius;
+        }
+    }
+    if killed {
+        positions[object_id] = REMOVED_POSITION;
+        previous_positions[object_id] = REMOVED_POSITION;
+    }
+}
@@ -1,4 +1,24 @@
-mod collision_solver;
-mod collision_cell_builder;
+pub mod cell_range_solver;
+pub(crate) mod collision_solver;
+pub mod collision_cell_builder;
 mod collision_cell_buffers;
+pub mod collision_color_validator;
 pub mod collision_system;
+pub mod contact_stats;
+pub mod fluid_solver;
+pub mod force_kernel;
+pub mod forces;
+pub mod frame_capture;
+pub mod frame_graph;
+pub mod heat_diffusion;
+pub mod kernel_autotune;
+pub mod kill_volumes;
+pub mod pass_validation;
+pub mod particle_sleep;
+pub mod solver_comparison;
+pub mod spring_constraints;
+pub mod stability_watchdog;
+#[cfg(feature = "windowing")]
+pub mod static_collider_drawer;
+pub mod static_colliders;
+pub mod trajectory;
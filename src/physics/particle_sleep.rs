@@ -0,0 +1,229 @@
+use glam::Vec2;
+use wgpu::{BindGroupLayout, CommandEncoder, PushConstantRange};
+use crate::grid::grid::Grid;
+use crate::particles::particle_system::{ParticleSystem, PARTICLE_FLAGS_CHANNEL};
+use crate::physics::cell_range_solver::CellRangeSolver;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::bind_resources::BindResources;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::profiler::GpuProfiler;
+
+pub const WORKGROUP_SIZE: u32 = 64;
+
+/// Name of the per-particle sleep channel, a `u32` counting the steps the particle has been still, up to
+/// `SleepConfig::sleep_steps`.
+pub const SLEEP_CHANNEL: &str = "sleep";
+
+/// Settings of the sleep of `Simulation::enable_sleep`, in world units.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SleepConfig {
+    /// Below this speed, in world units per second, a particle is still.
+    pub sleep_speed: f32,
+    /// Steps a particle must stay still before it falls asleep, at least 1.
+    pub sleep_steps: u32,
+}
+
+impl Default for SleepConfig {
+    fn default() -> Self {
+        Self {
+            sleep_speed: 5.0,
+            sleep_steps: 30,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstantsData {
+    delta_time: f32,
+    sleep_speed: f32,
+    sleep_steps: u32,
+    extras_stride: u32,
+    origin: Vec2,
+    flags_offset: u32,
+    sleep_offset: u32,
+}
+
+/// Sleep of `Simulation::enable_sleep`. Every step, before the integration, each particle counts the steps it has
+/// been slower than the sleep speed in the `SLEEP_CHANNEL` channel, and falls asleep once it has been still for
+/// `SleepConfig::sleep_steps` steps: it gets the `SLEEPING_FLAG`, so the integration leaves it in place and the
+/// collision solvers treat it as kinematic. A sleeping particle wakes up when it is moved, e.g. by a force kernel,
+/// a spring or the mouse, or when an awake particle moving faster than the sleep speed gets close to it, found in
+/// the cell tables of a `CellRangeSolver`.
+pub(crate) struct ParticleSleep {
+    cell_ranges: CellRangeSolver,
+    update_shader: ComputeShader,
+    apply_shader: ComputeShader,
+    bind_resources: BindResources,
+    next_still_steps: GpuBuffer<u32>,
+    config: SleepConfig,
+    extras_stride: u32,
+    flags_offset: u32,
+    sleep_offset: u32,
+}
+
+impl ParticleSleep {
+    /// The particles must have the flags and the sleep channels.
+    pub fn new(wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid, config: SleepConfig) -> Self {
+        let cell_ranges = CellRangeSolver::new(wgpu_context, particle_system, grid);
+        let next_still_steps = GpuBuffer::new(wgpu_context, vec![0; particle_system.len().max(1)], wgpu::BufferUsages::STORAGE);
+
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let bind_group = Self::create_bind_group(wgpu_context, &bind_group_layout, particle_system, grid, &cell_ranges, &next_still_steps);
+        let bind_resources = BindResources::new(bind_group_layout, bind_group);
+
+        let create_shader = |entry_point: &str| ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("particle_sleep.wgsl"),
+            entry_point,
+            &bind_resources.bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange {
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstantsData>() as u32,
+                }
+            ]
+        );
+        let update_shader = create_shader("update_sleep");
+        let apply_shader = create_shader("apply_sleep");
+
+        Self {
+            cell_ranges,
+            update_shader,
+            apply_shader,
+            bind_resources,
+            next_still_steps,
+            config,
+            extras_stride: particle_system.channels().stride(),
+            flags_offset: Self::channel_offset(particle_system, PARTICLE_FLAGS_CHANNEL),
+            sleep_offset: Self::channel_offset(particle_system, SLEEP_CHANNEL),
+        }
+    }
+
+    fn channel_offset(particle_system: &ParticleSystem, name: &str) -> u32 {
+        let channels = particle_system.channels();
+        let id = channels.find(name).unwrap_or_else(|| panic!("The sleep needs the {} channel", name));
+        channels.offset(id)
+    }
+
+    pub fn set_config(&mut self, config: SleepConfig) {
+        self.config = config;
+    }
+
+    pub fn config(&self) -> SleepConfig {
+        self.config
+    }
+
+    /// Follows `Grid::set_origin`.
+    pub fn set_origin(&mut self, origin: Vec2) {
+        self.cell_ranges.set_origin(origin);
+    }
+
+    /// Follows new or removed particles, a new cell size, new particle buffers or new channels.
+    pub fn refresh_buffers(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem, grid: &Grid) {
+        self.cell_ranges.refresh_buffers(wgpu_context, particle_system, grid);
+        if particle_system.len() > self.next_still_steps.len() {
+            self.next_still_steps.push_all(&vec![0; particle_system.len() - self.next_still_steps.len()], wgpu_context);
+        }
+        self.extras_stride = particle_system.channels().stride();
+        self.flags_offset = Self::channel_offset(particle_system, PARTICLE_FLAGS_CHANNEL);
+        self.sleep_offset = Self::channel_offset(particle_system, SLEEP_CHANNEL);
+        self.bind_resources.bind_group = Self::create_bind_group(wgpu_context, &self.bind_resources.bind_group_layout, particle_system, grid, &self.cell_ranges, &self.next_still_steps);
+    }
+
+    fn create_bind_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, particle_system: &ParticleSystem, grid: &Grid, cell_ranges: &CellRangeSolver, next_still_steps: &GpuBuffer<u32>) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Particle sleep bind group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: grid.object_ids().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: cell_ranges.cell_start().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: cell_ranges.cell_end().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 3, resource: particle_system.positions().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 4, resource: particle_system.buffers().previous_positions.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 5, resource: particle_system.radius().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 6, resource: particle_system.buffers().extras.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 7, resource: next_still_steps.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 8, resource: cell_ranges.uniform_buffer().as_entire_binding() },
+            ],
+        })
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Particle sleep bind group layout"),
+            entries: &[
+                // Object ids
+                storage_entry(0, true),
+                // Cell start
+                storage_entry(1, true),
+                // Cell end
+                storage_entry(2, true),
+                // Positions
+                storage_entry(3, true),
+                // Previous positions, the sleeping particles lose their velocity
+                storage_entry(4, false),
+                // Radius
+                storage_entry(5, true),
+                // Extras, for the flags and the sleep channels
+                storage_entry(6, false),
+                // Next still steps
+                storage_entry(7, false),
+                // Uniform data of the cell ranges
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    /// Records the cell boundaries, the update and the apply passes of a step of `delta_time`. Must follow the
+    /// sort of the grid, and come before the integration.
+    pub fn record(&self, encoder: &mut CommandEncoder, gpu_profiler: &GpuProfiler, num_particles: u32, delta_time: f32) {
+        if num_particles == 0 || delta_time <= 0.0 {
+            return;
+        }
+        self.cell_ranges.find_cell_boundaries(encoder, gpu_profiler);
+        let push_constants = PushConstantsData {
+            delta_time,
+            sleep_speed: self.config.sleep_speed,
+            sleep_steps: self.config.sleep_steps.max(1),
+            extras_stride: self.extras_stride,
+            origin: self.cell_ranges.origin(),
+            flags_offset: self.flags_offset,
+            sleep_offset: self.sleep_offset,
+        };
+        let mut scope = gpu_profiler.scope("Particle sleep", encoder);
+        for shader in [&self.update_shader, &self.apply_shader] {
+            shader.dispatch_by_items(
+                &mut scope,
+                (num_particles, 1, 1),
+                Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                &self.bind_resources.bind_group
+            );
+        }
+    }
+}
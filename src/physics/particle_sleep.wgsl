@@ -0,0 +1,158 @@
+override WORKGROUP_SIZE = 64u;
+
+// Must match the flags of particle_system.rs
+const PINNED_FLAG = 1u;
+const HELD_FLAG = 2u;
+const SLEEPING_FLAG = 4u;
+
+// Same layout as the UniformData of cell_range_solver.wgsl
+struct UniformData {
+    total_cell_ids: u32,
+    num_particles: u32,
+    // Length of cell_start and cell_end, cells with a larger morton id have no neighbours
+    table_len: u32,
+    cell_size: f32,
+}
+
+struct PushConstantsData {
+    delta_time: f32,
+    // Below this speed, in world units per second, a particle is still
+    sleep_speed: f32,
+    // Still steps after which a particle falls asleep, at least 1
+    sleep_steps: u32,
+    extras_stride: u32,
+    // World position of the corner of cell (0, 0), see Grid::origin
+    origin: vec2<f32>,
+    // Word offset of the particle flags channel
+    flags_offset: u32,
+    // Word offset of the sleep channel, the steps the particle has been still
+    sleep_offset: u32,
+}
+
+// Sorted by the grid
+@group(0) @binding(0) var<storage, read> object_ids: array<u32>;
+// Indexed by morton cell id: the cell holds object_ids[cell_start..cell_end], see find_cell_boundaries
+@group(0) @binding(1) var<storage, read> cell_start: array<u32>;
+@group(0) @binding(2) var<storage, read> cell_end: array<u32>;
+@group(0) @binding(3) var<storage, read> positions: array<vec2<f32>>;
+@group(0) @binding(4) var<storage, read_write> previous_positions: array<vec2<f32>>;
+@group(0) @binding(5) var<storage, read> radius: array<f32>;
+@group(0) @binding(6) var<storage, read_write> extras: array<u32>;
+// Still steps after the step, copied into the channel by apply_sleep
+@group(0) @binding(7) var<storage, read_write> next_still_steps: array<u32>;
+@group(0) @binding(8) var<uniform> uniform_data: UniformData;
+
+var<push_constant> push_constants: PushConstantsData;
+
+// One thread per particle: counts the steps it has been still, and wakes the sleeping particles that moved or that a
+// moving neighbour is about to reach. The flags and the velocities are only read, so the result does not depend on
+// the scheduling.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn update_sleep(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let object_id = global_id.x;
+    if object_id >= uniform_data.num_particles {
+        return;
+    }
+    let flags = read_flags(object_id);
+    // Kinematic particles never sleep
+    if (flags & (PINNED_FLAG | HELD_FLAG)) != 0u {
+        next_still_steps[object_id] = 0u;
+        return;
+    }
+
+    var still_steps = extras[object_id * push_constants.extras_stride + push_constants.sleep_offset];
+    if displacement(object_id) >= push_constants.sleep_speed * push_constants.delta_time {
+        still_steps = 0u;
+    }
+    else if (flags & SLEEPING_FLAG) != 0u && is_near_moving_particle(object_id) {
+        still_steps = 0u;
+    }
+    else {
+        still_steps = min(still_steps + 1u, push_constants.sleep_steps);
+    }
+    next_still_steps[object_id] = still_steps;
+}
+
+// Stores the still steps and the sleeping flag. A sleeping particle loses its velocity, the integration skips it.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn apply_sleep(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let object_id = global_id.x;
+    if object_id >= uniform_data.num_particles {
+        return;
+    }
+    let still_steps = next_still_steps[object_id];
+    let extras_start = object_id * push_constants.extras_stride;
+    extras[extras_start + push_constants.sleep_offset] = still_steps;
+    let flags = extras[extras_start + push_constants.flags_offset];
+    if still_steps >= push_constants.sleep_steps {
+        extras[extras_start + push_constants.flags_offset] = flags | SLEEPING_FLAG;
+        previous_positions[object_id] = positions[object_id];
+    }
+    else {
+        extras[extras_start + push_constants.flags_offset] = flags & ~SLEEPING_FLAG;
+    }
+}
+
+// An awake neighbour faster than the sleep speed touches the particle, or will after moving by its velocity
+fn is_near_moving_particle(object_id: u32) -> bool {
+    let position = positions[object_id];
+    let object_radius = radius[object_id];
+    let home_cell = home_cell_coord(position);
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let cell_coord = home_cell + vec2<i32>(x, y);
+            if any(cell_coord < vec2<i32>(0)) {
+                continue;
+            }
+            let cell = morton_encode(cell_coord);
+            if cell >= uniform_data.table_len {
+                continue;
+            }
+            for (var j = cell_start[cell]; j < cell_end[cell]; j++) {
+                let other_object_id = object_ids[j];
+                let other_position = positions[other_object_id];
+                // The phantom cells of a particle hold it too, only its home cell counts
+                if other_object_id == object_id || any(home_cell_coord(other_position) != cell_coord) {
+                    continue;
+                }
+                if (read_flags(other_object_id) & SLEEPING_FLAG) != 0u {
+                    continue;
+                }
+                let other_displacement = displacement(other_object_id);
+                if other_displacement >= push_constants.sleep_speed * push_constants.delta_time
+                    && distance(position, other_position) < object_radius + radius[other_object_id] + other_displacement {
+                    return true;
+                }
+            }
+        }
+    }
+    return false;
+}
+
+// Distance the particle moves in the next integration, without the forces
+fn displacement(object_id: u32) -> f32 {
+    return distance(positions[object_id], previous_positions[object_id]);
+}
+
+fn read_flags(object_id: u32) -> u32 {
+    return extras[object_id * push_constants.extras_stride + push_constants.flags_offset];
+}
+
+// Like particle_cells in cell_range_solver.wgsl
+fn home_cell_coord(world_position: vec2<f32>) -> vec2<i32> {
+    return max(vec2<i32>(floor((world_position - push_constants.origin) / uniform_data.cell_size)), vec2<i32>(0));
+}
+
+fn split_by_bits(n: u32) -> u32 {
+    var x = n & 0x0000FFFF;
+    x = (x | (x << 8)) & 0x00FF00FF;
+    x = (x | (x << 4)) & 0x0F0F0F0F;
+    x = (x | (x << 2)) & 0x33333333;
+    x = (x | (x << 1)) & 0x55555555;
+    return x;
+}
+
+// Must match morton::encode
+fn morton_encode(v: vec2<i32>) -> u32 {
+    return split_by_bits(u32(v.x)) | (split_by_bits(u32(v.y)) << 1);
+}
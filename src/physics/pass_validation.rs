@@ -0,0 +1,142 @@
+use std::fmt;
+
+/// A GPU pass of a simulation step, in the order `Simulation::step` must encode them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PhysicsPass {
+    /// Periodic reordering of the particles by home cell, or the measure of their disorder that triggers it
+    ParticleSort,
+    /// Indirect dispatch sizes of the grid kernels, see `LiveParticleCount`
+    PrepareGridDispatch,
+    BuildCellIds,
+    SortMap,
+    BuildCollisionCells,
+    SolveCollisions,
+    /// Exchange of heat between touching particles, see `HeatDiffusion`
+    HeatDiffusion,
+    /// Sleeping and waking of the settled particles, see `ParticleSleep`
+    Sleep,
+    ForceKernels,
+    Integration,
+    /// Distance constraints between particles, see `SpringConstraints`
+    SpringConstraints,
+    /// Periodic removal of the dead particles, see `ParticleCompaction`
+    Compaction,
+}
+
+impl PhysicsPass {
+    /// Name of the profiler scope of the pass, see `FrameGraph`.
+    pub fn label(self) -> &'static str {
+        match self {
+            PhysicsPass::ParticleSort => "Particle sort pass",
+            PhysicsPass::PrepareGridDispatch => "Prepare grid dispatch pass",
+            PhysicsPass::BuildCellIds => "Build cell ids pass",
+            PhysicsPass::SortMap => "Sort map pass",
+            PhysicsPass::BuildCollisionCells => "Build collision cells pass",
+            PhysicsPass::SolveCollisions => "Solve collisions pass",
+            PhysicsPass::HeatDiffusion => "Heat diffusion pass",
+            PhysicsPass::Sleep => "Sleep pass",
+            PhysicsPass::ForceKernels => "Force kernels pass",
+            PhysicsPass::Integration => "Integration pass",
+            PhysicsPass::SpringConstraints => "Spring constraints pass",
+            PhysicsPass::Compaction => "Compaction pass",
+        }
+    }
+
+    /// Position of the pass in a step. Passes can be skipped, but never encoded before a pass of a lower rank.
+    fn rank(self) -> u32 {
+        match self {
+            PhysicsPass::ParticleSort => 0,
+            PhysicsPass::PrepareGridDispatch => 1,
+            PhysicsPass::BuildCellIds => 2,
+            PhysicsPass::SortMap => 3,
+            PhysicsPass::BuildCollisionCells => 4,
+            PhysicsPass::SolveCollisions => 5,
+            PhysicsPass::HeatDiffusion => 6,
+            PhysicsPass::Sleep => 7,
+            PhysicsPass::ForceKernels => 8,
+            PhysicsPass::Integration => 9,
+            PhysicsPass::SpringConstraints => 10,
+            PhysicsPass::Compaction => 11,
+        }
+    }
+
+    /// Passes that write the buffers this pass reads, and must run before it in the same step.
+    fn dependencies(self) -> &'static [PhysicsPass] {
+        match self {
+            // Cell ids -> sorted map -> collision cells -> solved positions
+            PhysicsPass::SortMap => &[PhysicsPass::BuildCellIds],
+            PhysicsPass::BuildCollisionCells => &[PhysicsPass::SortMap],
+            PhysicsPass::SolveCollisions => &[PhysicsPass::BuildCollisionCells],
+            // The neighbours are found in the sorted cell ids
+            PhysicsPass::HeatDiffusion | PhysicsPass::Sleep => &[PhysicsPass::SortMap],
+            // The constraints correct the integrated positions
+            PhysicsPass::SpringConstraints => &[PhysicsPass::Integration],
+            _ => &[],
+        }
+    }
+}
+
+/// A step that did not encode its passes as expected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PassOrderError {
+    /// `pass` was encoded after `after`, which must come later in the step.
+    OutOfOrder { pass: PhysicsPass, after: PhysicsPass, trace: Vec<PhysicsPass> },
+    /// `pass` was encoded before the pass writing its input.
+    MissingDependency { pass: PhysicsPass, dependency: PhysicsPass, trace: Vec<PhysicsPass> },
+    /// The step ended without encoding `pass`.
+    MissingPass { pass: PhysicsPass, trace: Vec<PhysicsPass> },
+}
+
+impl fmt::Display for PassOrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PassOrderError::OutOfOrder { pass, after, trace } =>
+                write!(f, "{:?} encoded after {:?}, passes of the step: {:?}", pass, after, trace),
+            PassOrderError::MissingDependency { pass, dependency, trace } =>
+                write!(f, "{:?} encoded before {:?}, which writes its input, passes of the step: {:?}", pass, dependency, trace),
+            PassOrderError::MissingPass { pass, trace } =>
+                write!(f, "step ended without {:?}, passes of the step: {:?}", pass, trace),
+        }
+    }
+}
+
+/// Records the passes encoded by a step and checks them against the expected ordering
+/// (build -> sort -> collision cells -> solve -> forces -> integration).
+/// `Simulation` enables it in debug builds, so a refactor of the step scheduling that reorders
+/// or drops a pass fails on the first step instead of silently reading stale buffers.
+#[derive(Clone, Debug, Default)]
+pub struct PassValidator {
+    trace: Vec<PhysicsPass>,
+}
+
+impl PassValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that `pass` was encoded.
+    pub fn record(&mut self, pass: PhysicsPass) -> Result<(), PassOrderError> {
+        self.trace.push(pass);
+        if let Some(&after) = self.trace.iter().find(|recorded| recorded.rank() > pass.rank()) {
+            return Err(PassOrderError::OutOfOrder { pass, after, trace: self.trace.clone() });
+        }
+        if let Some(&dependency) = pass.dependencies().iter().find(|dependency| !self.trace.contains(dependency)) {
+            return Err(PassOrderError::MissingDependency { pass, dependency, trace: self.trace.clone() });
+        }
+        Ok(())
+    }
+
+    /// Ends the step: every pass of `required` must have been recorded. Clears the trace.
+    pub fn finish_step(&mut self, required: &[PhysicsPass]) -> Result<(), PassOrderError> {
+        let trace = std::mem::take(&mut self.trace);
+        match required.iter().find(|pass| !trace.contains(pass)) {
+            Some(&pass) => Err(PassOrderError::MissingPass { pass, trace }),
+            None => Ok(()),
+        }
+    }
+
+    /// Passes recorded since the last `finish_step`.
+    pub fn trace(&self) -> &[PhysicsPass] {
+        &self.trace
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+use glam::Vec2;
+use wgpu::wgt::PollType::WaitForSubmissionIndex;
+use crate::grid::grid::Grid;
+use crate::particles::particle_system::ParticleSystem;
+use crate::physics::collision_system::{CollisionSystem, SolverConfig};
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+/// Positions of both runs are compared every this many frames.
+const DIVERGENCE_SAMPLE_INTERVAL: u32 = 10;
+
+/// Distance between the particles of run A and run B at one frame.
+#[derive(Copy, Clone, Debug)]
+pub struct DivergenceSample {
+    pub frame: u32,
+    pub mean_distance: f32,
+    pub max_distance: f32,
+}
+
+/// Timing of one side of the comparison.
+#[derive(Copy, Clone, Debug)]
+pub struct SolverRunStats {
+    pub config: SolverConfig,
+    /// Mean wall time of a physics step, waiting for the GPU to finish it.
+    pub mean_step_time: Duration,
+    pub max_step_time: Duration,
+}
+
+/// Result of running two solver configurations on the same starting state.
+#[derive(Clone, Debug)]
+pub struct SolverComparison {
+    pub frames: u32,
+    pub num_particles: usize,
+    pub a: SolverRunStats,
+    pub b: SolverRunStats,
+    pub divergence: Vec<DivergenceSample>,
+}
+
+impl SolverComparison {
+    pub fn final_divergence(&self) -> Option<DivergenceSample> {
+        self.divergence.last().copied()
+    }
+}
+
+impl fmt::Display for SolverComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Solver comparison: {} frames, {} particles", self.frames, self.num_particles)?;
+        for (name, run) in [("A", &self.a), ("B", &self.b)] {
+            writeln!(
+                f,
+                "  {}: {:?} -> mean step {:.3} ms, max step {:.3} ms",
+                name,
+                run.config,
+                run.mean_step_time.as_secs_f64() * 1000.0,
+                run.max_step_time.as_secs_f64() * 1000.0,
+            )?;
+        }
+        if let Some(divergence) = self.final_divergence() {
+            write!(f, "  Divergence at frame {}: mean {:.4}, max {:.4}", divergence.frame, divergence.mean_distance, divergence.max_distance)?;
+        }
+        Ok(())
+    }
+}
+
+/// One side of the comparison, with its own copy of every buffer.
+struct SolverRun {
+    particles: ParticleSystem,
+    grid: Grid,
+    collision_system: CollisionSystem,
+    step_times: Vec<Duration>,
+}
+
+impl SolverRun {
+    fn new(wgpu_context: &WgpuContext, source: &mut ParticleSystem, dim: u32, config: SolverConfig) -> Self {
+        let particles = source.duplicate(wgpu_context);
+        let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+        let collision_system = CollisionSystem::new_with_config(wgpu_context, dim, &particles, &grid, config);
+        Self {
+            particles,
+            grid,
+            collision_system,
+            step_times: Vec::new(),
+        }
+    }
+
+    fn step(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, delta_time: f32) {
+        let start = Instant::now();
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Solver comparison encoder") }
+        );
+        self.grid.update(&mut encoder, gpu_profiler);
+        self.collision_system.solve_collisions(wgpu_context, encoder, gpu_profiler);
+        self.particles.update_positions(delta_time, wgpu_context, gpu_profiler);
+
+        let idx = wgpu_context.get_queue().submit(std::iter::empty());
+        wgpu_context.get_device().poll(WaitForSubmissionIndex(idx)).unwrap();
+        self.step_times.push(start.elapsed());
+    }
+
+    fn positions(&self, wgpu_context: &WgpuContext) -> Vec<Vec2> {
+        self.particles.positions().read_back(wgpu_context).unwrap()
+    }
+
+    fn stats(&self) -> SolverRunStats {
+        let total: Duration = self.step_times.iter().sum();
+        SolverRunStats {
+            config: self.collision_system.solver_config(),
+            mean_step_time: total / self.step_times.len().max(1) as u32,
+            max_step_time: self.step_times.iter().max().copied().unwrap_or_default(),
+        }
+    }
+}
+
+pub(crate) fn measure_divergence(frame: u32, positions_a: &[Vec2], positions_b: &[Vec2]) -> DivergenceSample {
+    let mut total_distance = 0.0f64;
+    let mut max_distance = 0.0f32;
+    for (a, b) in positions_a.iter().zip(positions_b) {
+        let distance = a.distance(*b);
+        total_distance += distance as f64;
+        max_distance = max_distance.max(distance);
+    }
+    DivergenceSample {
+        frame,
+        mean_distance: (total_distance / positions_a.len().max(1) as f64) as f32,
+        max_distance,
+    }
+}
+
+/// Runs `config_a` and `config_b` side by side on clones of `particles` for `frames` steps.
+/// The source particle system is only read. Both runs use a fixed `delta_time` and skip the
+/// periodic particle sort, so particle indices stay comparable between runs.
+pub fn compare_solvers(wgpu_context: &WgpuContext, particles: &mut ParticleSystem, dim: u32, config_a: SolverConfig, config_b: SolverConfig, frames: u32, delta_time: f32) -> SolverComparison {
+    let mut run_a = SolverRun::new(wgpu_context, particles, dim, config_a);
+    let mut run_b = SolverRun::new(wgpu_context, particles, dim, config_b);
+
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings {
+        enable_timer_queries: false,
+        enable_debug_groups: false,
+        max_num_pending_frames: 1,
+    }).unwrap();
+
+    let mut divergence = Vec::new();
+    for frame in 1..=frames {
+        // Interleave the runs so both see the same GPU clocks
+        run_a.step(wgpu_context, &mut gpu_profiler, delta_time);
+        run_b.step(wgpu_context, &mut gpu_profiler, delta_time);
+        gpu_profiler.end_frame().unwrap();
+
+        if frame % DIVERGENCE_SAMPLE_INTERVAL == 0 || frame == frames {
+            divergence.push(measure_divergence(frame, &run_a.positions(wgpu_context), &run_b.positions(wgpu_context)));
+        }
+    }
+
+    SolverComparison {
+        frames,
+        num_particles: particles.len(),
+        a: run_a.stats(),
+        b: run_b.stats(),
+        divergence,
+    }
+}
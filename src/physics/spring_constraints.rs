@@ -0,0 +1,348 @@
+use std::ops::Range;
+use glam::Vec2;
+use wgpu::{BindGroupLayout, PushConstantRange};
+use crate::particles::particle_channels::ChannelId;
+use crate::particles::particle_system::{ParticleSystem, MASS_CHANNEL, PARTICLE_FLAGS_CHANNEL};
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::profiler::GpuProfiler;
+
+pub const WORKGROUP_SIZE: u32 = 64;
+const NO_CHANNEL: u32 = u32::MAX;
+/// Channel with the handle of every particle that belongs to a spring.
+pub const SPRING_HANDLE_CHANNEL: &str = "spring_handle";
+/// Handle of the particles without springs. Must match NO_HANDLE of spring_constraints.wgsl
+pub const NO_HANDLE: u32 = u32::MAX;
+/// Times the springs are enforced per step by default.
+pub const DEFAULT_SPRING_ITERATIONS: u32 = 4;
+
+/// Stable name of a particle in the springs. Particle indices change when the particles are sorted or
+/// compacted, the handle lives in the `SPRING_HANDLE_CHANNEL` and moves with the particle.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ParticleHandle(pub u32);
+
+/// Distance constraint between two particles, solved position based: every iteration moves both ends
+/// towards `rest_length` by `stiffness` (0 to 1) of the error.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Spring {
+    pub a: ParticleHandle,
+    pub b: ParticleHandle,
+    pub rest_length: f32,
+    pub stiffness: f32,
+}
+
+/// Particles and springs created by `Simulation::add_chain` or `Simulation::add_cloth`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpringBody {
+    /// Handles of the particles, in the order of the positions they were spawned at.
+    pub handles: Range<u32>,
+    /// Springs added between its particles.
+    pub num_springs: usize,
+}
+
+impl SpringBody {
+    pub fn handle(&self, index: usize) -> ParticleHandle {
+        ParticleHandle(self.handles.start + index as u32)
+    }
+
+    pub fn num_particles(&self) -> usize {
+        self.handles.len()
+    }
+}
+
+/// Positions of a chain from `start` towards `end`, `spacing` apart, and the springs between neighbours as
+/// (first, second) indices into the positions.
+pub fn chain_layout(start: Vec2, end: Vec2, spacing: f32) -> (Vec<Vec2>, Vec<(usize, usize)>) {
+    let length = start.distance(end);
+    let count = (length / spacing) as usize + 1;
+    let direction = (end - start).normalize_or(Vec2::X);
+    let positions = (0..count).map(|i| start + direction * (i as f32 * spacing)).collect();
+    let springs = (1..count).map(|i| (i - 1, i)).collect();
+    (positions, springs)
+}
+
+/// Positions of a `columns` x `rows` cloth from `bottom_left`, `spacing` apart, row by row, and its springs:
+/// the horizontal and vertical neighbours, plus both diagonals so it resists shearing.
+pub fn cloth_layout(bottom_left: Vec2, columns: usize, rows: usize, spacing: f32) -> (Vec<Vec2>, Vec<(usize, usize)>) {
+    let index = |column: usize, row: usize| row * columns + column;
+    let mut positions = Vec::with_capacity(columns * rows);
+    let mut springs = Vec::new();
+    for row in 0..rows {
+        for column in 0..columns {
+            positions.push(bottom_left + Vec2::new(column as f32, row as f32) * spacing);
+            if column + 1 < columns {
+                springs.push((index(column, row), index(column + 1, row)));
+            }
+            if row + 1 < rows {
+                springs.push((index(column, row), index(column, row + 1)));
+            }
+            if column + 1 < columns && row + 1 < rows {
+                springs.push((index(column, row), index(column + 1, row + 1)));
+                springs.push((index(column + 1, row), index(column, row + 1)));
+            }
+        }
+    }
+    (positions, springs)
+}
+
+/// Groups the springs so no two springs of a group share a particle, greedily. Returns the springs
+/// ordered by group and the range of every group; the groups are solved one after the other.
+pub fn color_springs(springs: &[Spring]) -> (Vec<Spring>, Vec<Range<usize>>) {
+    // Groups already touching each handle
+    let mut used: std::collections::HashMap<u32, Vec<usize>> = std::collections::HashMap::new();
+    let mut groups: Vec<Vec<Spring>> = Vec::new();
+    for spring in springs {
+        let used_a = used.get(&spring.a.0).map(Vec::as_slice).unwrap_or(&[]);
+        let used_b = used.get(&spring.b.0).map(Vec::as_slice).unwrap_or(&[]);
+        let group = (0..).find(|group| !used_a.contains(group) && !used_b.contains(group)).unwrap();
+        if group == groups.len() {
+            groups.push(Vec::new());
+        }
+        groups[group].push(*spring);
+        used.entry(spring.a.0).or_default().push(group);
+        used.entry(spring.b.0).or_default().push(group);
+    }
+
+    let mut ranges = Vec::with_capacity(groups.len());
+    let mut ordered = Vec::with_capacity(springs.len());
+    for group in groups {
+        ranges.push(ordered.len()..ordered.len() + group.len());
+        ordered.extend(group);
+    }
+    (ordered, ranges)
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct SpringData {
+    a: u32,
+    b: u32,
+    rest_length: f32,
+    stiffness: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstantsData {
+    num_particles: u32,
+    extras_stride: u32,
+    handle_offset: u32,
+    num_handles: u32,
+    first_spring: u32,
+    num_springs: u32,
+    flags_offset: u32,
+    mass_offset: u32,
+}
+
+/// Springs of a simulation, see `Simulation::add_springs`. Enforced after the integration of every step,
+/// position based: moving the current positions also changes the Verlet velocities. A first pass finds the
+/// particle of every handle, then the springs are solved group by group so no two threads move the same particle.
+pub struct SpringConstraints {
+    map_shader: ComputeShader,
+    solve_shader: ComputeShader,
+    bind_group_layout: BindGroupLayout,
+    handle_channel: ChannelId,
+    springs: Vec<Spring>,
+    groups: Vec<Range<usize>>,
+    spring_buffer: GpuBuffer<SpringData>, // Holds a single unused spring when there are none
+    handle_slots: GpuBuffer<u32>,
+    next_handle: u32,
+    iterations: u32,
+}
+
+impl SpringConstraints {
+    /// `handle_channel` is the `SPRING_HANDLE_CHANNEL` of the particles, with `NO_HANDLE` as default.
+    pub fn new(wgpu_context: &WgpuContext, handle_channel: ChannelId) -> Self {
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let create_shader = |entry_point: &str| ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("spring_constraints.wgsl"),
+            entry_point,
+            &bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange {
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstantsData>() as u32,
+                }
+            ]
+        );
+        let map_shader = create_shader("map_spring_handles");
+        let solve_shader = create_shader("solve_springs");
+
+        Self {
+            map_shader,
+            solve_shader,
+            bind_group_layout,
+            handle_channel,
+            springs: Vec::new(),
+            groups: Vec::new(),
+            spring_buffer: GpuBuffer::new(wgpu_context, vec![SpringData::default()], wgpu::BufferUsages::STORAGE),
+            handle_slots: GpuBuffer::new(wgpu_context, vec![0], wgpu::BufferUsages::STORAGE),
+            next_handle: 0,
+            iterations: DEFAULT_SPRING_ITERATIONS,
+        }
+    }
+
+    pub fn handle_channel(&self) -> ChannelId {
+        self.handle_channel
+    }
+
+    /// Reserves `count` new handles, to be written in the handle channel of the particles they name.
+    pub fn allocate_handles(&mut self, wgpu_context: &WgpuContext, count: usize) -> Range<u32> {
+        let handles = self.next_handle..self.next_handle + count as u32;
+        self.next_handle = handles.end;
+        self.handle_slots = GpuBuffer::new(wgpu_context, vec![0; (self.next_handle as usize).max(1)], wgpu::BufferUsages::STORAGE);
+        handles
+    }
+
+    /// Handles reserved so far, every handle is below it.
+    pub fn num_handles(&self) -> u32 {
+        self.next_handle
+    }
+
+    /// Springs, grouped so no two springs of a group share a particle.
+    pub fn springs(&self) -> &[Spring] {
+        &self.springs
+    }
+
+    pub fn num_groups(&self) -> usize {
+        self.groups.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.springs.is_empty()
+    }
+
+    /// Adds springs between allocated handles. Panics on a handle that was not allocated.
+    pub fn add_springs(&mut self, wgpu_context: &WgpuContext, springs: &[Spring]) {
+        for spring in springs {
+            assert!(spring.a.0 < self.next_handle && spring.b.0 < self.next_handle, "Spring between unallocated handles {:?}", spring);
+        }
+        let mut all_springs = self.springs.clone();
+        all_springs.extend_from_slice(springs);
+        self.set_springs(wgpu_context, all_springs);
+    }
+
+    /// Removes every spring. The particles keep their handles.
+    pub fn clear(&mut self, wgpu_context: &WgpuContext) {
+        self.set_springs(wgpu_context, Vec::new());
+    }
+
+    fn set_springs(&mut self, wgpu_context: &WgpuContext, springs: Vec<Spring>) {
+        let (springs, groups) = color_springs(&springs);
+        // Bindings can not be empty
+        let data = if springs.is_empty() {
+            vec![SpringData::default()]
+        }
+        else {
+            springs.iter().map(|spring| SpringData {
+                a: spring.a.0,
+                b: spring.b.0,
+                rest_length: spring.rest_length,
+                stiffness: spring.stiffness.clamp(0.0, 1.0),
+            }).collect()
+        };
+        self.spring_buffer = GpuBuffer::new(wgpu_context, data, wgpu::BufferUsages::STORAGE);
+        self.springs = springs;
+        self.groups = groups;
+    }
+
+    pub fn iterations(&self) -> u32 {
+        self.iterations
+    }
+
+    /// Times every spring is enforced per step. More iterations make long chains and cloths stiffer.
+    pub fn set_iterations(&mut self, iterations: u32) {
+        self.iterations = iterations.max(1);
+    }
+
+    /// Records the passes enforcing the springs, if there are any. Bound to the particle buffers on every
+    /// call, it runs after the integration of the step.
+    pub fn solve(&self, wgpu_context: &WgpuContext, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler, particle_system: &ParticleSystem) {
+        let num_particles = particle_system.len() as u32;
+        if self.springs.is_empty() || num_particles == 0 {
+            return;
+        }
+        let bind_group = self.create_bind_group(wgpu_context, particle_system);
+        let push_constants = PushConstantsData {
+            num_particles,
+            extras_stride: particle_system.channels().stride(),
+            handle_offset: particle_system.channels().offset(self.handle_channel),
+            num_handles: self.next_handle,
+            first_spring: 0,
+            num_springs: 0,
+            flags_offset: particle_system.channels().find(PARTICLE_FLAGS_CHANNEL).map_or(NO_CHANNEL, |id| particle_system.channels().offset(id)),
+            mass_offset: particle_system.channels().find(MASS_CHANNEL).map_or(NO_CHANNEL, |id| particle_system.channels().offset(id)),
+        };
+        encoder.clear_buffer(self.handle_slots.buffer(), 0, None);
+        {
+            let mut scope = gpu_profiler.scope("Spring constraints", encoder);
+            self.map_shader.dispatch_by_items(
+                &mut scope,
+                (num_particles, 1, 1),
+                Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                &bind_group
+            );
+            for _ in 0..self.iterations {
+                for group in &self.groups {
+                    let push_constants = PushConstantsData {
+                        first_spring: group.start as u32,
+                        num_springs: group.len() as u32,
+                        ..push_constants
+                    };
+                    self.solve_shader.dispatch_by_items(
+                        &mut scope,
+                        (group.len() as u32, 1, 1),
+                        Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                        &bind_group
+                    );
+                }
+            }
+        }
+    }
+
+    fn create_bind_group(&self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Spring constraints bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: particle_system.positions().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: particle_system.buffers().extras.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: self.spring_buffer.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 3, resource: self.handle_slots.buffer().as_entire_binding() },
+            ],
+        })
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Spring constraints bind group layout"),
+            entries: &[
+                // Positions
+                storage_entry(0, false),
+                // Extras
+                storage_entry(1, true),
+                // Springs
+                storage_entry(2, true),
+                // Handle slots
+                storage_entry(3, false),
+            ],
+        })
+    }
+}
@@ -0,0 +1,103 @@
+override WORKGROUP_SIZE = 64u;
+
+// Must match NO_HANDLE
+const NO_HANDLE = 0xffffffffu;
+const NO_CHANNEL = 0xffffffffu;
+// Must match the flags of particle_system.rs, both make a particle kinematic
+const PINNED_FLAG = 1u;
+const HELD_FLAG = 2u;
+
+struct PushConstantsData {
+    num_particles: u32,
+    extras_stride: u32,
+    handle_offset: u32,
+    num_handles: u32,
+    // Springs of one color, no two of them share a particle
+    first_spring: u32,
+    num_springs: u32,
+    // Word offset of the particle flags channel, NO_CHANNEL if the particles have none
+    flags_offset: u32,
+    // Word offset of the mass channel, NO_CHANNEL if the particles have none: both ends move the same
+    mass_offset: u32,
+}
+
+// Must match SpringData
+struct Spring {
+    a: u32,
+    b: u32,
+    rest_length: f32,
+    stiffness: f32,
+}
+
+@group(0) @binding(0) var<storage, read_write> positions: array<vec2<f32>>;
+@group(0) @binding(1) var<storage, read> extras: array<u32>;
+@group(0) @binding(2) var<storage, read> springs: array<Spring>;
+// Index + 1 of the particle holding each handle, 0 if it is gone. Cleared before every solve
+@group(0) @binding(3) var<storage, read_write> handle_slots: array<u32>;
+
+var<push_constant> push_constants: PushConstantsData;
+
+// The sort and the compaction move the particles, the handles move with them
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn map_spring_handles(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= push_constants.num_particles {
+        return;
+    }
+    let spring_handle = extras[index * push_constants.extras_stride + push_constants.handle_offset];
+    if spring_handle != NO_HANDLE && spring_handle < push_constants.num_handles {
+        handle_slots[spring_handle] = index + 1u;
+    }
+}
+
+// Moves both ends of every spring towards the rest length, scaled by the stiffness and split by the inverse masses.
+// A kinematic end stays in place and the other one moves the whole way, so pinned particles anchor the springs
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn solve_springs(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if global_id.x >= push_constants.num_springs {
+        return;
+    }
+    let spring = springs[push_constants.first_spring + global_id.x];
+    let slot_a = handle_slots[spring.a];
+    let slot_b = handle_slots[spring.b];
+    // One of the ends was removed or recycled
+    if slot_a == 0u || slot_b == 0u {
+        return;
+    }
+    let a = slot_a - 1u;
+    let b = slot_b - 1u;
+
+    let delta = positions[b] - positions[a];
+    let distance = length(delta);
+    if distance < 1e-6 {
+        return;
+    }
+    let weight_a = inverse_mass(a);
+    let weight_b = inverse_mass(b);
+    if weight_a + weight_b == 0.0 {
+        return;
+    }
+    let correction = delta * (spring.stiffness * (distance - spring.rest_length) / distance / (weight_a + weight_b));
+    positions[a] += correction * weight_a;
+    positions[b] -= correction * weight_b;
+}
+
+// Zero for kinematic particles, one without a mass channel
+fn inverse_mass(index: u32) -> f32 {
+    if is_kinematic(index) {
+        return 0.0;
+    }
+    if push_constants.mass_offset == NO_CHANNEL {
+        return 1.0;
+    }
+    return 1.0 / bitcast<f32>(extras[index * push_constants.extras_stride + push_constants.mass_offset]);
+}
+
+// Pinned or held, see PINNED_FLAG and HELD_FLAG
+fn is_kinematic(index: u32) -> bool {
+    if push_constants.flags_offset == NO_CHANNEL {
+        return false;
+    }
+    let flags = extras[index * push_constants.extras_stride + push_constants.flags_offset];
+    return (flags & (PINNED_FLAG | HELD_FLAG)) != 0u;
+}
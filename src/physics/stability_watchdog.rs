@@ -0,0 +1,243 @@
+use std::sync::mpsc::{Receiver, TryRecvError};
+use wgpu::{BindGroupLayout, BufferAsyncError, CommandEncoder, PushConstantRange};
+use wgpu::wgt::PollType;
+use crate::particles::particle_system::ParticleSystem;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::bind_resources::BindResources;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+
+pub const WORKGROUP_SIZE: u32 = 64;
+const NO_PARTICLE: u32 = u32::MAX;
+
+/// Fraction of the largest world dimension a particle may move in one step before it counts as an explosion.
+const MAX_STEP_DISPLACEMENT_FRACTION: f32 = 0.1;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstants {
+    num_particles: u32,
+    max_step_displacement: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct WatchdogResult {
+    invalid_particles: u32,
+    first_invalid_particle: u32,
+}
+
+/// Result of one check.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StabilityReport {
+    /// Particles with a NaN/infinite position or that moved more than the allowed distance in one step.
+    pub invalid_particles: u32,
+    /// Lowest index of an invalid particle, if any.
+    pub first_invalid_particle: Option<u32>,
+}
+
+impl StabilityReport {
+    pub fn is_stable(&self) -> bool {
+        self.invalid_particles == 0
+    }
+}
+
+/// Looks for NaN positions and exploding particles on the GPU.
+/// Like `CellOccupancyQuery`, the result is read back asynchronously: `request` starts a check
+/// and `poll` returns it once the GPU is done, without stalling the frame. `record` and `after_submit`
+/// do the same with the check recorded into the encoder of the frame.
+pub struct StabilityWatchdog {
+    check_shader: ComputeShader,
+    bind_resources: BindResources,
+    result: GpuBuffer<WatchdogResult>,
+    staging_buffer: wgpu::Buffer,
+    pending: Option<Receiver<Result<(), BufferAsyncError>>>,
+    /// Set by `record` until `after_submit`
+    copy_recorded: bool,
+    max_step_displacement: f32,
+}
+
+impl StabilityWatchdog {
+    pub fn new(wgpu_context: &WgpuContext, particle_system: &ParticleSystem) -> Self {
+        let result = GpuBuffer::new(wgpu_context, vec![Self::cleared_result()], wgpu::BufferUsages::STORAGE);
+        let staging_buffer = wgpu_context.get_device().create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Stability watchdog staging buffer"),
+            size: size_of::<WatchdogResult>() as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let bind_group = Self::create_bind_group(wgpu_context, &bind_group_layout, particle_system, &result);
+        let bind_resources = BindResources::new(bind_group_layout, bind_group);
+
+        let check_shader = ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("stability_watchdog.wgsl"),
+            "check_particles",
+            &bind_resources.bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange{
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstants>() as u32,
+                }
+            ]
+        );
+
+        let world_size = particle_system.get_world_size();
+        Self {
+            check_shader,
+            bind_resources,
+            result,
+            staging_buffer,
+            pending: None,
+            copy_recorded: false,
+            max_step_displacement: world_size.x.max(world_size.y) * MAX_STEP_DISPLACEMENT_FRACTION,
+        }
+    }
+
+    fn cleared_result() -> WatchdogResult {
+        WatchdogResult {
+            invalid_particles: 0,
+            first_invalid_particle: NO_PARTICLE,
+        }
+    }
+
+    /// Must be called after the particle buffers are refreshed.
+    pub fn refresh(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem) {
+        self.bind_resources.bind_group = Self::create_bind_group(wgpu_context, &self.bind_resources.bind_group_layout, particle_system, &self.result);
+    }
+
+    /// Max distance a particle may travel in one step before it is reported.
+    pub fn set_max_step_displacement(&mut self, max_step_displacement: f32) {
+        self.max_step_displacement = max_step_displacement;
+    }
+
+    fn create_bind_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, particle_system: &ParticleSystem, result: &GpuBuffer<WatchdogResult>) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                label: Some("Stability watchdog bind group"),
+                layout: bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: particle_system.buffers().current_positions.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: particle_system.buffers().previous_positions.buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: result.buffer().as_entire_binding(),
+                    },
+                ],
+            }
+        )
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Stability watchdog bind group layout"),
+            entries: &[
+                // Positions
+                storage_entry(0, true),
+                // Previous positions
+                storage_entry(1, true),
+                // Result
+                storage_entry(2, false),
+            ],
+        })
+    }
+
+    /// Starts checking the current particle state. Ignored while the previous check is still in flight.
+    pub fn request(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem) {
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Stability watchdog encoder") }
+        );
+        self.record(wgpu_context, &mut encoder, particle_system);
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+        self.after_submit();
+    }
+
+    /// Records a check into `encoder`, `after_submit` must follow its submit. Ignored while the previous
+    /// check is still in flight.
+    pub fn record(&mut self, wgpu_context: &WgpuContext, encoder: &mut CommandEncoder, particle_system: &ParticleSystem) {
+        if self.pending.is_some() || self.copy_recorded {
+            return;
+        }
+
+        self.result.replace_elem(Self::cleared_result(), 0, wgpu_context);
+        let num_particles = particle_system.len() as u32;
+        self.check_shader.dispatch_by_items(
+            encoder,
+            (num_particles, 1, 1),
+            Some(vec![(0u32, bytemuck::bytes_of(&PushConstants {
+                num_particles,
+                max_step_displacement: self.max_step_displacement,
+            }))]),
+            &self.bind_resources.bind_group
+        );
+        encoder.copy_buffer_to_buffer(self.result.buffer(), 0, &self.staging_buffer, 0, size_of::<WatchdogResult>() as u64);
+        wgpu_context.transfers().record_readback(size_of::<WatchdogResult>() as u64);
+        self.copy_recorded = true;
+    }
+
+    /// Starts the readback of the check recorded by `record`, once its encoder is submitted.
+    pub fn after_submit(&mut self) {
+        if !self.copy_recorded {
+            return;
+        }
+        self.copy_recorded = false;
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.staging_buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.pending = Some(receiver);
+    }
+
+    /// Returns the report of the check in flight once it is done, without blocking.
+    /// Every report is returned only once.
+    pub fn poll(&mut self, wgpu_context: &WgpuContext) -> Option<StabilityReport> {
+        let receiver = self.pending.take()?;
+        let _ = wgpu_context.get_device().poll(PollType::Poll);
+        match receiver.try_recv() {
+            Ok(Ok(())) => {
+                let result = {
+                    let mapped_range = self.staging_buffer.slice(..).get_mapped_range();
+                    bytemuck::pod_read_unaligned::<WatchdogResult>(&mapped_range)
+                };
+                self.staging_buffer.unmap();
+                Some(StabilityReport {
+                    invalid_particles: result.invalid_particles,
+                    first_invalid_particle: (result.first_invalid_particle != NO_PARTICLE).then_some(result.first_invalid_particle),
+                })
+            }
+            Ok(Err(e)) => {
+                log::error!("Stability watchdog readback failed: {:?}", e);
+                None
+            }
+            Err(TryRecvError::Empty) => {
+                self.pending = Some(receiver);
+                None
+            }
+            Err(TryRecvError::Disconnected) => None,
+        }
+    }
+}
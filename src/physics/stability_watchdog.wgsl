@@ -0,0 +1,49 @@
+override WORKGROUP_SIZE = 64u;
+
+const NO_PARTICLE = 0xffffffffu;
+
+struct PushConstants {
+    num_particles: u32,
+    max_step_displacement: f32,
+}
+
+struct WatchdogResult {
+    invalid_particles: atomic<u32>,
+    first_invalid_particle: atomic<u32>,
+}
+
+@group(0) @binding(0) var<storage, read> positions: array<vec2<f32>>;
+@group(0) @binding(1) var<storage, read> previous_positions: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read_write> result: WatchdogResult;
+
+var<push_constant> push_constants: PushConstants;
+
+// NaN and infinity both have every exponent bit set.
+// Comparing a value with itself is not reliable, the compiler may assume floats are never NaN.
+fn is_finite(v: vec2<f32>) -> bool {
+    let exponents = bitcast<vec2<u32>>(v) & vec2<u32>(0x7f800000u);
+    return all(exponents != vec2<u32>(0x7f800000u));
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn check_particles(@builtin(global_invocation_id) global_id: vec3<u32>){
+    let idx = global_id.x;
+    if idx >= push_constants.num_particles {
+        return;
+    }
+
+    let position = positions[idx];
+    let previous_position = previous_positions[idx];
+
+    var invalid = !is_finite(position) || !is_finite(previous_position);
+    if !invalid {
+        // A particle moving this far in one step means the solver blew up
+        let step = position - previous_position;
+        invalid = dot(step, step) > push_constants.max_step_displacement * push_constants.max_step_displacement;
+    }
+
+    if invalid {
+        atomicAdd(&result.invalid_particles, 1u);
+        atomicMin(&result.first_invalid_particle, idx);
+    }
+}
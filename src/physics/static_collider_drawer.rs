@@ -0,0 +1,51 @@
+use glam::{Vec2, Vec4};
+use crate::lines::lines::Lines;
+use crate::physics::collision_system::StaticSegment;
+use crate::physics::static_colliders::StaticCircle;
+use crate::renderer::camera::Camera;
+use crate::renderer::renderable::Renderable;
+use crate::renderer::wgpu_context::WgpuContext;
+
+const OUTLINE_COLOR: Vec4 = Vec4::new(0.9, 0.9, 0.9, 1.0);
+/// Segments of the outline of a circle.
+const OUTLINE_SEGMENTS: u32 = 32;
+
+/// Outlines of the static circle colliders and the static segments.
+pub struct StaticColliderDrawer {
+    outlines: Lines,
+}
+
+impl StaticColliderDrawer {
+    pub fn new(wgpu_context: &WgpuContext, camera: &Camera) -> Self {
+        Self {
+            outlines: Lines::new(wgpu_context, camera),
+        }
+    }
+
+    /// Rebuilds the outlines, call it after the circles or the segments changed.
+    pub fn update(&mut self, wgpu_context: &WgpuContext, static_circles: &[StaticCircle], static_segments: &[StaticSegment]) {
+        self.outlines.clear();
+        if static_circles.is_empty() && static_segments.is_empty() {
+            return;
+        }
+        let mut positions: Vec<Vec2> = static_circles.iter().flat_map(|circle| {
+            (0..OUTLINE_SEGMENTS).flat_map(move |i| {
+                let point = |i: u32| {
+                    let angle = i as f32 / OUTLINE_SEGMENTS as f32 * std::f32::consts::TAU;
+                    circle.center + Vec2::from_angle(angle) * circle.radius
+                };
+                [point(i), point(i + 1)]
+            })
+        }).collect();
+        positions.extend(static_segments.iter().flat_map(|segment| [segment.start, segment.end]));
+        let colors = vec![OUTLINE_COLOR; positions.len()];
+        let thicknesses = vec![2.0; positions.len()];
+        self.outlines.push_all(wgpu_context, &positions, &colors, &thicknesses);
+    }
+}
+
+impl Renderable for StaticColliderDrawer {
+    fn draw(&self, render_pass: &mut wgpu::RenderPass, camera: &Camera) {
+        self.outlines.draw(render_pass, camera);
+    }
+}
@@ -0,0 +1,165 @@
+use glam::Vec2;
+use wgpu::{BindGroupLayout, PushConstantRange};
+use crate::particles::particle_system::ParticleSystem;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::bind_resources::BindResources;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::profiler::GpuProfiler;
+
+pub const WORKGROUP_SIZE: u32 = 64;
+
+/// Immovable circle the particles collide with, e.g. an obstacle or a peg of a Galton board.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct StaticCircle {
+    pub center: Vec2,
+    pub radius: f32,
+    _padding: f32,
+}
+
+impl StaticCircle {
+    pub fn new(center: Vec2, radius: f32) -> Self {
+        Self { center, radius, _padding: 0.0 }
+    }
+
+    pub fn contains(&self, position: Vec2) -> bool {
+        position.distance_squared(self.center) <= self.radius * self.radius
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PushConstantsData {
+    num_particles: u32,
+    num_circles: u32,
+}
+
+/// Static circle colliders, see `CollisionSystem::add_static_circle`. The particles are pushed out of
+/// the circles after each solver iteration, like the static segments.
+pub struct StaticColliders {
+    circle_collision_shader: ComputeShader,
+    bind_resources: BindResources,
+    static_circles: Vec<StaticCircle>,
+    circles: GpuBuffer<StaticCircle>, // Holds a single unused circle when there are none
+    num_particles: u32,
+}
+
+impl StaticColliders {
+    pub fn new(wgpu_context: &WgpuContext, particle_system: &ParticleSystem) -> Self {
+        let circles = Self::create_circles_buffer(wgpu_context, &[]);
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context);
+        let bind_group = Self::create_bind_group(wgpu_context, &bind_group_layout, particle_system, &circles);
+        let bind_resources = BindResources::new(bind_group_layout, bind_group);
+
+        let circle_collision_shader = ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("static_colliders.wgsl"),
+            "solve_circle_collisions",
+            &bind_resources.bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange {
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstantsData>() as u32,
+                }
+            ]
+        );
+
+        Self {
+            circle_collision_shader,
+            bind_resources,
+            static_circles: Vec::new(),
+            circles,
+            num_particles: particle_system.len() as u32,
+        }
+    }
+
+    pub fn circles(&self) -> &[StaticCircle] {
+        &self.static_circles
+    }
+
+    /// Index of the last added circle containing `position`.
+    pub fn circle_at(&self, position: Vec2) -> Option<usize> {
+        self.static_circles.iter().rposition(|circle| circle.contains(position))
+    }
+
+    /// Replaces the circles the particles collide with.
+    pub fn set_circles(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem, static_circles: Vec<StaticCircle>) {
+        self.circles = Self::create_circles_buffer(wgpu_context, &static_circles);
+        self.static_circles = static_circles;
+        self.refresh(wgpu_context, particle_system);
+    }
+
+    /// Follows the particle buffers after they grew.
+    pub fn refresh(&mut self, wgpu_context: &WgpuContext, particle_system: &ParticleSystem) {
+        self.num_particles = particle_system.len() as u32;
+        self.bind_resources.bind_group = Self::create_bind_group(wgpu_context, &self.bind_resources.bind_group_layout, particle_system, &self.circles);
+    }
+
+    fn create_circles_buffer(wgpu_context: &WgpuContext, static_circles: &[StaticCircle]) -> GpuBuffer<StaticCircle> {
+        // Bindings can not be empty
+        let circles = if static_circles.is_empty() { vec![StaticCircle::default()] } else { static_circles.to_vec() };
+        GpuBuffer::new(wgpu_context, circles, wgpu::BufferUsages::STORAGE)
+    }
+
+    /// Records the pass pushing the particles out of the circles, if there are any.
+    pub fn solve_collisions(&self, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler) {
+        if self.static_circles.is_empty() || self.num_particles == 0 {
+            return;
+        }
+        let push_constants = PushConstantsData {
+            num_particles: self.num_particles,
+            num_circles: self.static_circles.len() as u32,
+        };
+        {
+            let mut scope = gpu_profiler.scope("Solve Circle Collisions", encoder);
+            self.circle_collision_shader.dispatch_by_items(
+                &mut scope,
+                (self.num_particles, 1, 1),
+                Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                &self.bind_resources.bind_group
+            );
+        }
+    }
+
+    fn create_bind_group(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, particle_system: &ParticleSystem, circles: &GpuBuffer<StaticCircle>) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Static colliders bind group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: particle_system.positions().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: particle_system.radius().buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: circles.buffer().as_entire_binding() },
+            ],
+        })
+    }
+
+    fn create_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Static colliders bind group layout"),
+            entries: &[
+                // Positions
+                storage_entry(0, false),
+                // Radius
+                storage_entry(1, true),
+                // Circles
+                storage_entry(2, true),
+            ],
+        })
+    }
+}
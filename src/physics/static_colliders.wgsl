@@ -0,0 +1,46 @@
+override WORKGROUP_SIZE = 64u;
+
+struct Circle {
+    center: vec2<f32>,
+    radius: f32,
+}
+
+struct PushConstantsData {
+    num_particles: u32,
+    num_circles: u32,
+}
+
+@group(0) @binding(0) var<storage, read_write> positions: array<vec2<f32>>;
+@group(0) @binding(1) var<storage, read> radius: array<f32>;
+@group(0) @binding(2) var<storage, read> circles: array<Circle>;
+
+var<push_constant> push_constants: PushConstantsData;
+
+// One thread per particle: pushes it out of every circle it overlaps
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn solve_circle_collisions(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let object_id = global_id.x;
+    if object_id >= push_constants.num_particles {
+        return;
+    }
+
+    let object_radius = radius[object_id];
+    var position = positions[object_id];
+    for (var i = 0u; i < push_constants.num_circles; i++) {
+        let circle = circles[i];
+        let vec_circle_obj = position - circle.center;
+        let distance = length(vec_circle_obj);
+        let min_distance = circle.radius + object_radius;
+        if distance >= min_distance {
+            continue;
+        }
+        // A particle at the center is pushed up
+        var normal = vec2<f32>(0.0, 1.0);
+        if distance > 0.0001 {
+            normal = vec_circle_obj / distance;
+        }
+        // Circles do not move: the whole penetration is corrected on the particle
+        position = circle.center + normal * min_distance;
+    }
+    positions[object_id] = position;
+}
@@ -0,0 +1,224 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use glam::Vec2;
+use crate::particles::particle_channels::ChannelId;
+use crate::particles::particle_system::ParticleSystem;
+use crate::physics::solver_comparison::{measure_divergence, DivergenceSample};
+use crate::renderer::wgpu_context::WgpuContext;
+
+/// Channel holding the index each particle had when the recording started.
+pub const TRACKING_ID_CHANNEL: &str = "tracking_id";
+/// Particles spawned (or recycled) after the recording started have this tracking id and are not recorded.
+const UNTRACKED: u32 = u32::MAX;
+const MAGIC: &[u8; 8] = b"GPETRAJ1";
+
+/// Positions of the tracked particles at one recorded frame, in tracking id order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrajectoryFrame {
+    pub frame: u32,
+    pub positions: Vec<Vec2>,
+}
+
+/// Positions of the same particles over a run, to check if two runs of a scene are identical.
+/// Saved as a small binary file: magic, particle count, frame count, then every frame number followed by its positions.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trajectory {
+    num_particles: u32,
+    frames: Vec<TrajectoryFrame>,
+}
+
+impl Trajectory {
+    pub fn new(num_particles: u32) -> Self {
+        Self {
+            num_particles,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, frame: u32, positions: Vec<Vec2>) {
+        assert_eq!(positions.len(), self.num_particles as usize, "Every frame of a trajectory must hold the same particles");
+        self.frames.push(TrajectoryFrame { frame, positions });
+    }
+
+    pub fn num_particles(&self) -> u32 {
+        self.num_particles
+    }
+
+    pub fn frames(&self) -> &[TrajectoryFrame] {
+        &self.frames
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&self.num_particles.to_le_bytes());
+        bytes.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for frame in &self.frames {
+            bytes.extend_from_slice(&frame.frame.to_le_bytes());
+            for position in &frame.positions {
+                bytes.extend_from_slice(&position.x.to_le_bytes());
+                bytes.extend_from_slice(&position.y.to_le_bytes());
+            }
+        }
+        fs::write(path, bytes)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), message));
+        if bytes.len() < MAGIC.len() + 8 || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(invalid("not a trajectory recording"));
+        }
+        let mut words = bytes[MAGIC.len()..].chunks_exact(4).map(|word| u32::from_le_bytes(word.try_into().unwrap()));
+        let mut next = || words.next().ok_or_else(|| invalid("truncated recording"));
+
+        let mut trajectory = Self::new(next()?);
+        let num_frames = next()?;
+        for _ in 0..num_frames {
+            let frame = next()?;
+            let positions = (0..trajectory.num_particles)
+                .map(|_| Ok(Vec2::new(f32::from_bits(next()?), f32::from_bits(next()?))))
+                .collect::<io::Result<Vec<Vec2>>>()?;
+            trajectory.push(frame, positions);
+        }
+        Ok(trajectory)
+    }
+}
+
+/// Records the positions of the particles of a `ParticleSystem`, following them through the
+/// periodic sorts with a tracking id channel. Only the particles alive when the recorder is created are tracked.
+/// Every `record` downloads the particle buffers and stalls, so record every few frames on big scenes.
+pub struct TrajectoryRecorder {
+    tracking_id: ChannelId,
+    trajectory: Trajectory,
+}
+
+impl TrajectoryRecorder {
+    pub fn new(wgpu_context: &WgpuContext, particle_system: &mut ParticleSystem) -> Self {
+        let tracking_id = match particle_system.channels().find(TRACKING_ID_CHANNEL) {
+            Some(id) => id,
+            None => particle_system.register_channel(wgpu_context, TRACKING_ID_CHANNEL, &[UNTRACKED]),
+        };
+        let num_particles = particle_system.len() as u32;
+        if num_particles > 0 {
+            particle_system.write_channel(wgpu_context, tracking_id, &(0..num_particles).collect::<Vec<u32>>());
+        }
+        particle_system.set_spawn_channel_value(tracking_id, &[UNTRACKED]);
+
+        Self {
+            tracking_id,
+            trajectory: Trajectory::new(num_particles),
+        }
+    }
+
+    /// Must be called after the particle buffers are refreshed, e.g. after spawning, like the kernels that bind the extras.
+    pub fn record(&mut self, wgpu_context: &WgpuContext, particle_system: &mut ParticleSystem, frame: u32) {
+        let stride = particle_system.channels().stride() as usize;
+        let offset = particle_system.channels().offset(self.tracking_id) as usize;
+        let buffers = particle_system.download_particle_buffers(wgpu_context);
+
+        let mut positions = vec![Vec2::NAN; self.trajectory.num_particles as usize];
+        for (particle, position) in buffers.current_positions.data().iter().enumerate() {
+            let tracking_id = buffers.extras.data()[particle * stride + offset];
+            if let Some(tracked) = positions.get_mut(tracking_id as usize) {
+                *tracked = *position;
+            }
+        }
+        self.trajectory.push(frame, positions);
+    }
+
+    pub fn trajectory(&self) -> &Trajectory {
+        &self.trajectory
+    }
+
+    pub fn finish(self) -> Trajectory {
+        self.trajectory
+    }
+}
+
+/// Where and how much two recordings of the same scene differ.
+#[derive(Clone, Debug)]
+pub struct TrajectoryDivergence {
+    /// Distance under which two positions count as equal.
+    pub tolerance: f32,
+    /// First frame, present in both recordings, where a particle moved further than `tolerance` from its counterpart.
+    pub first_divergent_frame: Option<u32>,
+    /// Divergence of every frame present in both recordings.
+    pub samples: Vec<DivergenceSample>,
+    /// Set if the recordings track a different number of particles; only the common ones are compared.
+    pub particle_count_mismatch: Option<(u32, u32)>,
+    /// Frames recorded in only one of the recordings.
+    pub unmatched_frames: usize,
+}
+
+impl TrajectoryDivergence {
+    pub fn max_divergence(&self) -> f32 {
+        self.samples.iter().map(|sample| sample.max_distance).fold(0.0, f32::max)
+    }
+
+    pub fn is_identical(&self) -> bool {
+        self.first_divergent_frame.is_none() && self.particle_count_mismatch.is_none() && self.unmatched_frames == 0
+    }
+}
+
+impl fmt::Display for TrajectoryDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((a, b)) = self.particle_count_mismatch {
+            writeln!(f, "Particle count differs: {} vs {}, comparing the first {}", a, b, a.min(b))?;
+        }
+        if self.unmatched_frames > 0 {
+            writeln!(f, "{} frames are only in one of the recordings", self.unmatched_frames)?;
+        }
+        writeln!(f, "{} frames compared, max divergence {:.6}", self.samples.len(), self.max_divergence())?;
+        for sample in &self.samples {
+            writeln!(f, "  frame {}: mean {:.6}, max {:.6}", sample.frame, sample.mean_distance, sample.max_distance)?;
+        }
+        match self.first_divergent_frame {
+            Some(frame) => write!(f, "First divergence (> {}) at frame {}", self.tolerance, frame),
+            None => write!(f, "No divergence above {}", self.tolerance),
+        }
+    }
+}
+
+/// Compares the frames with the same number in both recordings.
+/// Particles that stopped being tracked, because they were recycled, have NaN positions; they count as
+/// divergent unless they are missing in both.
+pub fn compare_trajectories(a: &Trajectory, b: &Trajectory, tolerance: f32) -> TrajectoryDivergence {
+    let num_particles = a.num_particles.min(b.num_particles) as usize;
+    let mut samples = Vec::new();
+    let mut first_divergent_frame = None;
+    let mut matched_frames = 0;
+
+    for frame_a in &a.frames {
+        let Some(frame_b) = b.frames.iter().find(|frame_b| frame_b.frame == frame_a.frame) else {
+            continue;
+        };
+        matched_frames += 1;
+        // Particles recycled in both runs are skipped, recycled in only one of them diverge
+        let (positions_a, positions_b): (Vec<Vec2>, Vec<Vec2>) = frame_a.positions[..num_particles].iter()
+            .zip(&frame_b.positions[..num_particles])
+            .filter(|(a, b)| !(a.is_nan() && b.is_nan()))
+            .map(|(a, b)| (*a, *b))
+            .unzip();
+        let mut sample = measure_divergence(frame_a.frame, &positions_a, &positions_b);
+        if positions_a.iter().zip(&positions_b).any(|(a, b)| a.is_nan() != b.is_nan()) {
+            sample.max_distance = f32::INFINITY;
+        }
+        if first_divergent_frame.is_none() && sample.max_distance > tolerance {
+            first_divergent_frame = Some(frame_a.frame);
+        }
+        samples.push(sample);
+    }
+
+    TrajectoryDivergence {
+        tolerance,
+        first_divergent_frame,
+        samples,
+        particle_count_mismatch: (a.num_particles != b.num_particles).then_some((a.num_particles, b.num_particles)),
+        unmatched_frames: a.frames.len() + b.frames.len() - 2 * matched_frames,
+    }
+}
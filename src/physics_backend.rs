@@ -0,0 +1,77 @@
+use glam::Vec2;
+use crate::particles::particle_system::SpawnReport;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::simulation::{Simulation, SimulationStats};
+use crate::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+/// What a downstream application or a test needs from a physics implementation. The GPU pipeline
+/// implements it through `GpuBackend`; a CPU fallback can implement it too, so the same scenarios run
+/// against both and applications can switch between them at runtime with a `Box<dyn PhysicsBackend>`.
+pub trait PhysicsBackend {
+    /// Short name, for logs and test messages.
+    fn name(&self) -> &'static str;
+
+    /// Advances the physics by `delta_time` seconds.
+    fn step(&mut self, delta_time: f32);
+
+    /// Spawns a batch of particles around `position`, in world coordinates.
+    fn spawn(&mut self, position: Vec2) -> SpawnReport;
+
+    fn stats(&mut self) -> SimulationStats;
+
+    /// Positions of every particle, in the order of the backend.
+    fn positions(&mut self) -> Vec<Vec2>;
+}
+
+/// The GPU pipeline as a `PhysicsBackend`: a `Simulation` with the context and profiler it is stepped with.
+pub struct GpuBackend<'a> {
+    wgpu_context: &'a WgpuContext,
+    simulation: Simulation,
+    gpu_profiler: GpuProfiler,
+}
+
+impl<'a> GpuBackend<'a> {
+    pub fn new(wgpu_context: &'a WgpuContext, simulation: Simulation) -> Self {
+        let gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+        Self {
+            wgpu_context,
+            simulation,
+            gpu_profiler,
+        }
+    }
+
+    pub fn simulation(&self) -> &Simulation {
+        &self.simulation
+    }
+
+    pub fn simulation_mut(&mut self) -> &mut Simulation {
+        &mut self.simulation
+    }
+
+    pub fn into_simulation(self) -> Simulation {
+        self.simulation
+    }
+}
+
+impl PhysicsBackend for GpuBackend<'_> {
+    fn name(&self) -> &'static str {
+        "gpu"
+    }
+
+    fn step(&mut self, delta_time: f32) {
+        self.simulation.step(self.wgpu_context, &mut self.gpu_profiler, delta_time, None);
+        self.gpu_profiler.end_frame().unwrap();
+    }
+
+    fn spawn(&mut self, position: Vec2) -> SpawnReport {
+        self.simulation.add_particles(self.wgpu_context, position).0
+    }
+
+    fn stats(&mut self) -> SimulationStats {
+        self.simulation.stats(self.wgpu_context)
+    }
+
+    fn positions(&mut self) -> Vec<Vec2> {
+        self.simulation.particles_mut().download_particle_buffers(self.wgpu_context).current_positions.data().to_vec()
+    }
+}
@@ -189,6 +189,11 @@
             &self.camera_uniform
         }
 
+        /// View projection of the last frame, see `build_view_projection_matrix`.
+        pub fn view_projection(&self) -> Mat4 {
+            Mat4::from_cols_array_2d(&self.camera_uniform.view_proj)
+        }
+
         pub fn camera_buffer(&self) -> &wgpu::Buffer {
             &self.camera_buffer
         }
@@ -0,0 +1,350 @@
+use glam::{Mat4, UVec2, Vec2};
+use wgpu::wgt::PollType::Wait;
+use wgpu::{BindGroup, BindGroupLayout, PushConstantRange};
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::profiler::GpuProfiler;
+
+pub const WORKGROUP_SIZE: u32 = 64;
+/// Window pixels per texel of the density texture along each axis.
+pub const HEATMAP_DOWNSAMPLE: u32 = 2;
+const DENSITY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ComputePushConstants {
+    view_proj: [[f32; 4]; 4],
+    size: UVec2,
+    num_particles: u32,
+    _padding: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DrawPushConstants {
+    downsample: u32,
+}
+
+/// Offscreen target of the heatmap and what reads or writes it, recreated when the window is resized.
+struct DensityTarget {
+    size: UVec2,
+    texture: wgpu::Texture,
+    counts: GpuBuffer<u32>,
+    draw_bind_group: BindGroup,
+    /// The compute bind group and the positions it binds, created again when they are reallocated
+    compute_bind_group: Option<(wgpu::Buffer, BindGroup)>,
+}
+
+/// Draws the particle density instead of the particles: a compute pass counts the particles under every texel
+/// of an offscreen R32Float texture, a quarter of the window, and resolves the counts into a density from 0 to
+/// 1, relative to the densest texel on a log scale. A second render pipeline then covers the window with the
+/// texture through the viridis color map. Empty texels leave the background.
+pub struct DensityHeatmap {
+    scatter_shader: ComputeShader,
+    resolve_shader: ComputeShader,
+    compute_bind_group_layout: BindGroupLayout,
+    draw_bind_group_layout: BindGroupLayout,
+    render_pipeline: wgpu::RenderPipeline,
+    max_count: GpuBuffer<u32>,
+    target: Option<DensityTarget>, // Created by the first update
+}
+
+impl DensityHeatmap {
+    /// `target_format` is the format of the render pass the heatmap is drawn in, the surface format in the window.
+    pub fn new(wgpu_context: &WgpuContext, target_format: wgpu::TextureFormat) -> Self {
+        let compute_bind_group_layout = Self::create_compute_bind_group_layout(wgpu_context);
+        let create_shader = |entry_point: &str| ComputeShader::new(
+            wgpu_context,
+            wgpu::include_wgsl!("density_heatmap.wgsl"),
+            entry_point,
+            &compute_bind_group_layout,
+            (WORKGROUP_SIZE, 1, 1),
+            &vec![
+                ("WORKGROUP_SIZE", WORKGROUP_SIZE as f64),
+            ],
+            &vec![
+                PushConstantRange {
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..size_of::<ComputePushConstants>() as u32,
+                }
+            ]
+        );
+        let scatter_shader = create_shader("scatter_density");
+        let resolve_shader = create_shader("resolve_density");
+
+        let draw_bind_group_layout = wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Density heatmap draw bind group layout"),
+            entries: &[
+                // Density texture
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let render_pipeline = Self::create_render_pipeline(wgpu_context, &draw_bind_group_layout, target_format);
+
+        Self {
+            scatter_shader,
+            resolve_shader,
+            compute_bind_group_layout,
+            draw_bind_group_layout,
+            render_pipeline,
+            max_count: GpuBuffer::new(wgpu_context, vec![0u32], wgpu::BufferUsages::STORAGE),
+            target: None,
+        }
+    }
+
+    /// Texels of the density texture, `None` before the first `update`.
+    pub fn size(&self) -> Option<UVec2> {
+        self.target.as_ref().map(|target| target.size)
+    }
+
+    /// Submits the passes that rebuild the density texture from the first `num_particles` positions, seen
+    /// through `view_proj` in a window of `screen_size` pixels. Once per frame, before the render pass.
+    pub fn update(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, positions: &GpuBuffer<Vec2>, num_particles: usize, view_proj: Mat4, screen_size: Vec2) {
+        let size = (screen_size.as_uvec2() + HEATMAP_DOWNSAMPLE - 1) / HEATMAP_DOWNSAMPLE;
+        let size = size.max(UVec2::ONE);
+        if self.target.as_ref().is_none_or(|target| target.size != size) {
+            self.target = Some(self.create_target(wgpu_context, size));
+        }
+        let target = self.target.as_ref().unwrap();
+        if target.compute_bind_group.as_ref().is_none_or(|(bound_positions, _)| bound_positions != positions.buffer()) {
+            let bind_group = self.create_compute_bind_group(wgpu_context, positions, target);
+            self.target.as_mut().unwrap().compute_bind_group = Some((positions.buffer().clone(), bind_group));
+        }
+        let (_, bind_group) = self.target.as_ref().unwrap().compute_bind_group.as_ref().unwrap();
+        self.max_count.replace_elem(0, 0, wgpu_context);
+
+        let push_constants = ComputePushConstants {
+            view_proj: view_proj.to_cols_array_2d(),
+            size,
+            num_particles: num_particles as u32,
+            _padding: 0,
+        };
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Density heatmap encoder") }
+        );
+        {
+            let mut scope = gpu_profiler.scope("Density heatmap", &mut encoder);
+            if num_particles > 0 {
+                self.scatter_shader.dispatch_by_items(
+                    &mut scope,
+                    (num_particles as u32, 1, 1),
+                    Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                    bind_group
+                );
+            }
+            self.resolve_shader.dispatch_by_items(
+                &mut scope,
+                (size.x * size.y, 1, 1),
+                Some(vec![(0u32, bytemuck::bytes_of(&push_constants))]),
+                bind_group
+            );
+        }
+        gpu_profiler.resolve_queries(&mut encoder);
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+    }
+
+    /// Covers the render target with the density of the last `update`.
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        let Some(target) = &self.target else {
+            return;
+        };
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &target.draw_bind_group, &[]);
+        let push_constants = DrawPushConstants { downsample: HEATMAP_DOWNSAMPLE };
+        render_pass.set_push_constants(wgpu::ShaderStages::FRAGMENT, 0, bytemuck::bytes_of(&push_constants));
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Reads the density texture back, row by row from the top left texel. Stalls until the GPU is done.
+    pub fn download_density(&self, wgpu_context: &WgpuContext) -> Vec<f32> {
+        let Some(target) = &self.target else {
+            return Vec::new();
+        };
+        let device = wgpu_context.get_device();
+        let row_bytes = target.size.x * size_of::<f32>() as u32;
+        // Rows of a texture copy are aligned to 256 bytes
+        let padded_row_bytes = row_bytes.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let staging_size = (padded_row_bytes * target.size.y) as u64;
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Density heatmap staging buffer"),
+            size: staging_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Density heatmap download encoder") });
+        encoder.copy_texture_to_buffer(
+            target.texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &staging_buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_bytes),
+                    rows_per_image: Some(target.size.y),
+                },
+            },
+            wgpu::Extent3d { width: target.size.x, height: target.size.y, depth_or_array_layers: 1 },
+        );
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+        wgpu_context.transfers().record_readback(staging_size);
+
+        let buffer_slice = staging_buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |result| sender.send(result).unwrap());
+        device.poll(Wait).unwrap();
+        receiver.recv().unwrap().expect("Unable to map the density heatmap staging buffer");
+
+        let mapped_range = buffer_slice.get_mapped_range();
+        let density = mapped_range.chunks(padded_row_bytes as usize)
+            .flat_map(|row| bytemuck::cast_slice::<u8, f32>(&row[..row_bytes as usize]).to_vec())
+            .collect();
+        drop(mapped_range);
+        staging_buffer.unmap();
+        density
+    }
+
+    fn create_target(&self, wgpu_context: &WgpuContext, size: UVec2) -> DensityTarget {
+        let texture = wgpu_context.get_device().create_texture(&wgpu::TextureDescriptor {
+            label: Some("Density heatmap texture"),
+            size: wgpu::Extent3d { width: size.x, height: size.y, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DENSITY_FORMAT,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let counts = GpuBuffer::new(wgpu_context, vec![0u32; (size.x * size.y) as usize], wgpu::BufferUsages::STORAGE);
+        let draw_bind_group = wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Density heatmap draw bind group"),
+            layout: &self.draw_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.create_view(&wgpu::TextureViewDescriptor::default())),
+                },
+            ],
+        });
+        DensityTarget {
+            size,
+            texture,
+            counts,
+            draw_bind_group,
+            compute_bind_group: None,
+        }
+    }
+
+    fn create_compute_bind_group(&self, wgpu_context: &WgpuContext, positions: &GpuBuffer<Vec2>, target: &DensityTarget) -> BindGroup {
+        wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Density heatmap compute bind group"),
+            layout: &self.compute_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: positions.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: target.counts.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: self.max_count.buffer().as_entire_binding() },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&target.texture.create_view(&wgpu::TextureViewDescriptor::default())),
+                },
+            ],
+        })
+    }
+
+    fn create_compute_bind_group_layout(wgpu_context: &WgpuContext) -> BindGroupLayout {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Density heatmap compute bind group layout"),
+            entries: &[
+                // Positions
+                storage_entry(0, true),
+                // Counts
+                storage_entry(1, false),
+                // Largest count
+                storage_entry(2, false),
+                // Density texture
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: DENSITY_FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    fn create_render_pipeline(wgpu_context: &WgpuContext, bind_group_layout: &BindGroupLayout, target_format: wgpu::TextureFormat) -> wgpu::RenderPipeline {
+        let shader = wgpu_context.get_device().create_shader_module(wgpu::include_wgsl!("density_heatmap_draw.wgsl"));
+        let render_pipeline_layout = wgpu_context.get_device().create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Density heatmap render pipeline layout"),
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[
+                wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStages::FRAGMENT,
+                    range: 0..size_of::<DrawPushConstants>() as u32,
+                }
+            ],
+        });
+
+        wgpu_context.get_device().create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Density heatmap render pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                // A full screen triangle, from the vertex index
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: target_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: wgpu_context.pipeline_cache(),
+        })
+    }
+}
@@ -0,0 +1,55 @@
+override WORKGROUP_SIZE = 64u;
+
+struct PushConstants {
+    // Camera view projection, world to clip space
+    view_proj: mat4x4<f32>,
+    // Texels of the density texture
+    size: vec2<u32>,
+    num_particles: u32,
+}
+
+@group(0) @binding(0) var<storage, read> positions: array<vec2<f32>>;
+// Particles per texel, row by row from the top left corner. Cleared by resolve_density
+@group(0) @binding(1) var<storage, read_write> counts: array<atomic<u32>>;
+// Largest count of the frame
+@group(0) @binding(2) var<storage, read_write> max_count: atomic<u32>;
+@group(0) @binding(3) var density: texture_storage_2d<r32float, write>;
+
+var<push_constant> push_constants: PushConstants;
+
+// Counts every particle in the texel under its center, the particles outside the view are skipped
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn scatter_density(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let idx = global_id.x;
+    if idx >= push_constants.num_particles {
+        return;
+    }
+    let clip = push_constants.view_proj * vec4<f32>(positions[idx], 0.0, 1.0);
+    let ndc = clip.xy / clip.w;
+    if any(abs(ndc) > vec2<f32>(1.0)) {
+        return;
+    }
+    // y down, like the window
+    let uv = vec2<f32>(ndc.x + 1.0, 1.0 - ndc.y) * 0.5;
+    let texel = min(vec2<u32>(uv * vec2<f32>(push_constants.size)), push_constants.size - 1u);
+    let count = atomicAdd(&counts[texel.y * push_constants.size.x + texel.x], 1u) + 1u;
+    atomicMax(&max_count, count);
+}
+
+// One thread per texel: writes the count relative to the largest one, on a log scale so sparse regions
+// stay visible next to packed ones, and clears it for the next frame
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn resolve_density(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= push_constants.size.x * push_constants.size.y {
+        return;
+    }
+    let count = atomicExchange(&counts[index], 0u);
+    let largest = atomicLoad(&max_count);
+    var value = 0.0;
+    if largest > 0u {
+        value = log(1.0 + f32(count)) / log(1.0 + f32(largest));
+    }
+    let texel = vec2<u32>(index % push_constants.size.x, index / push_constants.size.x);
+    textureStore(density, texel, vec4<f32>(value, 0.0, 0.0, 1.0));
+}
@@ -0,0 +1,43 @@
+// Written by density_heatmap.wgsl, 0 for an empty texel and 1 for the densest one
+@group(0) @binding(0) var density: texture_2d<f32>;
+
+struct PushConstants {
+    // Window pixels per texel along each axis
+    downsample: u32,
+}
+
+var<push_constant> push_constants: PushConstants;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+};
+
+// A triangle covering the whole window
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = min(vec2<u32>(input.clip_position.xy) / push_constants.downsample, textureDimensions(density) - 1u);
+    let value = textureLoad(density, texel, 0).r;
+    // Empty texels show the background
+    return vec4<f32>(viridis(value), select(0.0, 1.0, value > 0.0));
+}
+
+// Polynomial fit of viridis, like sample_color_map in particle_color_kernel.wgsl
+fn viridis(t: f32) -> vec3<f32> {
+    let c0 = vec3<f32>(0.2777273272234177, 0.005407344544966578, 0.3340998053353061);
+    let c1 = vec3<f32>(0.1050930431085774, 1.404613529898575, 1.384590162594685);
+    let c2 = vec3<f32>(-0.3308618287255563, 0.214847559468213, 0.09509516302823659);
+    let c3 = vec3<f32>(-4.634230498983486, -5.799100973351585, -19.33244095627987);
+    let c4 = vec3<f32>(6.228269936347081, 14.17993336680509, 56.69055260068105);
+    let c5 = vec3<f32>(4.776384997670288, -13.74514537774601, -65.35303263337234);
+    let c6 = vec3<f32>(-5.435455855934631, 4.645852612178535, 26.3124352495832);
+    let color = c0 + t * (c1 + t * (c2 + t * (c3 + t * (c4 + t * (c5 + t * c6)))));
+    return clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+}
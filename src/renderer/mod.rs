@@ -1,5 +1,16 @@
+#[cfg(feature = "windowing")]
 pub mod renderable;
+#[cfg(feature = "windowing")]
 pub mod renderer;
+#[cfg(feature = "windowing")]
 pub mod camera;
+#[cfg(feature = "windowing")]
 pub mod surface_manager;
 pub mod wgpu_context;
+#[cfg(feature = "windowing")]
+pub mod profiler_overlay;
+#[cfg(feature = "windowing")]
+pub mod density_heatmap;
+pub mod offscreen_target;
+pub mod recorder;
+pub mod stroke_font;
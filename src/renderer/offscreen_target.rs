@@ -0,0 +1,112 @@
+use glam::UVec2;
+use wgpu::wgt::PollType::Wait;
+use crate::renderer::wgpu_context::WgpuContext;
+
+/// Color texture the frame can be rendered into instead of the surface, and copied back from.
+/// It has the format of the surface so that the same pipelines draw into it.
+pub struct OffscreenTarget {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    size: UVec2,
+}
+
+impl OffscreenTarget {
+    pub fn new(wgpu_context: &WgpuContext, size: UVec2, format: wgpu::TextureFormat) -> Self {
+        let size = size.max(UVec2::ONE);
+        let texture = wgpu_context.get_device().create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen target texture"),
+            size: wgpu::Extent3d { width: size.x, height: size.y, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self { texture, view, size }
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.size
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.texture.format()
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// Bytes of a row in the staging buffer: rows of a texture copy are aligned to 256 bytes.
+    pub fn padded_row_bytes(&self) -> u32 {
+        let row_bytes = self.size.x * 4;
+        row_bytes.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT
+    }
+
+    /// Size of a staging buffer that `copy_to_buffer` can copy the whole texture into.
+    pub fn staging_size(&self) -> u64 {
+        (self.padded_row_bytes() * self.size.y) as u64
+    }
+
+    /// Records the copy of the texture into `staging_buffer`, with rows of `padded_row_bytes`.
+    pub fn copy_to_buffer(&self, encoder: &mut wgpu::CommandEncoder, staging_buffer: &wgpu::Buffer) {
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: staging_buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_row_bytes()),
+                    rows_per_image: Some(self.size.y),
+                },
+            },
+            wgpu::Extent3d { width: self.size.x, height: self.size.y, depth_or_array_layers: 1 },
+        );
+    }
+
+    /// Converts the mapped staging data of `copy_to_buffer` into tightly packed RGBA pixels, row-major from the
+    /// top left, as `png::encode_rgba8` expects. BGRA surfaces are swizzled.
+    pub fn to_rgba8(&self, padded: &[u8]) -> Vec<u8> {
+        let row_bytes = (self.size.x * 4) as usize;
+        let bgra = matches!(self.format(), wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb);
+        let mut pixels = Vec::with_capacity(row_bytes * self.size.y as usize);
+        for row in padded.chunks(self.padded_row_bytes() as usize).take(self.size.y as usize) {
+            pixels.extend_from_slice(&row[..row_bytes]);
+        }
+        if bgra {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        pixels
+    }
+
+    /// Copies the texture back as RGBA pixels, see `to_rgba8`. Stalls until the GPU is done.
+    pub fn read_rgba8(&self, wgpu_context: &WgpuContext) -> Vec<u8> {
+        let device = wgpu_context.get_device();
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Offscreen target staging buffer"),
+            size: self.staging_size(),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Offscreen target download encoder") });
+        self.copy_to_buffer(&mut encoder, &staging_buffer);
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+        wgpu_context.transfers().record_readback(self.staging_size());
+
+        let buffer_slice = staging_buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |result| sender.send(result).unwrap());
+        device.poll(Wait).unwrap();
+        receiver.recv().unwrap().expect("Unable to map the offscreen target staging buffer");
+
+        let mapped_range = buffer_slice.get_mapped_range();
+        let pixels = self.to_rgba8(&mapped_range);
+        drop(mapped_range);
+        staging_buffer.unmap();
+        pixels
+    }
+}
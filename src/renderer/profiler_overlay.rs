@@ -0,0 +1,148 @@
+use std::time::Duration;
+use glam::{Vec2, Vec4};
+use crate::lines::lines::Lines;
+use crate::renderer::camera::Camera;
+use crate::renderer::renderable::Renderable;
+use crate::renderer::stroke_font;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::gpu_timings::{self, GpuTimings, DEFAULT_WINDOW_FRAMES};
+use crate::utils::profiler::GpuTimerQueryResult;
+
+const TEXT_COLOR: Vec4 = Vec4::new(0.9, 0.9, 0.9, 1.0);
+const BAR_COLOR: Vec4 = Vec4::new(0.3, 0.8, 0.4, 1.0);
+const BACKGROUND_COLOR: Vec4 = Vec4::new(0.0, 0.0, 0.0, 0.7);
+/// Sizes in pixels: the text height, the row height, the margin around the panel and the longest bar.
+const TEXT_HEIGHT: f32 = 10.0;
+const ROW_HEIGHT: f32 = 16.0;
+const MARGIN: f32 = 10.0;
+const MAX_BAR_LENGTH: f32 = 160.0;
+/// Longer labels are cut, the column of the bars starts after this many characters.
+const MAX_LABEL_CHARS: usize = 40;
+/// Indentation of the nested scopes, in characters.
+const INDENT_CHARS: usize = 2;
+/// Rows past this are not drawn.
+const MAX_ROWS: usize = 40;
+
+/// Panel in the top left corner of the window with the average GPU time of every profiler scope over
+/// the last frames, as a bar and in milliseconds. Fed with the finished profiler frames, which need the
+/// timer queries of the profiler enabled.
+pub struct ProfilerOverlay {
+    lines: Lines,
+    timings: GpuTimings,
+    visible: bool,
+}
+
+impl ProfilerOverlay {
+    pub fn new(wgpu_context: &WgpuContext, camera: &Camera) -> Self {
+        Self {
+            lines: Lines::new(wgpu_context, camera),
+            timings: GpuTimings::new(DEFAULT_WINDOW_FRAMES),
+            visible: false,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Showing the overlay starts the averages over.
+    pub fn set_visible(&mut self, visible: bool) {
+        if visible && !self.visible {
+            self.timings.clear();
+        }
+        if !visible {
+            self.lines.clear();
+        }
+        self.visible = visible;
+    }
+
+    pub fn timings(&self) -> &GpuTimings {
+        &self.timings
+    }
+
+    /// Adds a frame returned by `GpuProfiler::process_finished_frame`. Ignored while hidden.
+    pub fn record_frame(&mut self, results: &[GpuTimerQueryResult]) {
+        if self.visible {
+            self.timings.record_frame(gpu_timings::scope_times(results));
+        }
+    }
+
+    /// Rebuilds the panel for the current view of the camera, once per frame after the camera moved.
+    pub fn update(&mut self, wgpu_context: &WgpuContext, camera: &Camera, screen_size: &Vec2) {
+        if !self.visible {
+            return;
+        }
+        self.lines.clear();
+        // Screen pixels to world: the panel is laid out from the top left corner, with y up
+        let top_left = camera.screen_to_world(screen_size, &Vec2::ZERO);
+        let (positions, colors) = panel_lines(&self.timings, top_left, 1.0 / camera.zoom);
+        let thicknesses = vec![1.0; positions.len()];
+        self.lines.push_all(wgpu_context, &positions, &colors, &thicknesses);
+    }
+}
+
+/// Lines of the panel for `timings`, as the two ends of every line and their colors. `top_left` is the
+/// world position of the top left corner of the window and `pixel` the world size of a screen pixel.
+pub fn panel_lines(timings: &GpuTimings, top_left: Vec2, pixel: f32) -> (Vec<Vec2>, Vec<Vec4>) {
+    let to_world = |point: Vec2| top_left + point * pixel;
+
+    let averages = timings.averages();
+    let longest = averages.iter().map(|average| average.average).max().unwrap_or_default().as_secs_f32();
+    let label_width = stroke_font::text_width(&"M".repeat(MAX_LABEL_CHARS), TEXT_HEIGHT);
+    let panel_width = label_width + MAX_BAR_LENGTH + 10.0 * TEXT_HEIGHT;
+
+    // Label, and the bar length as a fraction of the longest one with the average time
+    let mut rows: Vec<(String, Option<(f32, Duration)>)> = Vec::new();
+    rows.push((format!("GPU {:.3} MS PER FRAME ({} FRAMES)", timings.frame_average().as_secs_f64() * 1000.0, timings.num_frames()), None));
+    if averages.is_empty() {
+        rows.push(("WAITING FOR TIMER QUERIES".to_string(), None));
+    }
+    for average in averages.iter().take(MAX_ROWS) {
+        let indent = " ".repeat(average.depth as usize * INDENT_CHARS);
+        let label: String = format!("{}{}", indent, average.label).chars().take(MAX_LABEL_CHARS).collect();
+        let fraction = if longest > 0.0 { average.average.as_secs_f32() / longest } else { 0.0 };
+        rows.push((label, Some((fraction, average.average))));
+    }
+
+    let panel_height = rows.len() as f32 * ROW_HEIGHT + MARGIN;
+    let mut positions = Vec::new();
+    let mut colors = Vec::new();
+    // Filled with one line per pixel row, `Lines` has no quads
+    for y in 0..(panel_height + MARGIN) as u32 {
+        let y = -(y as f32);
+        positions.extend([to_world(Vec2::new(0.0, y)), to_world(Vec2::new(panel_width + 2.0 * MARGIN, y))]);
+        colors.extend([BACKGROUND_COLOR; 2]);
+    }
+
+    for (row, (label, bar)) in rows.iter().enumerate() {
+        let baseline = -(MARGIN + (row + 1) as f32 * ROW_HEIGHT) + (ROW_HEIGHT - TEXT_HEIGHT) / 2.0;
+        for (start, end) in stroke_font::text_strokes(label, Vec2::new(MARGIN, baseline), TEXT_HEIGHT) {
+            positions.extend([to_world(start), to_world(end)]);
+            colors.extend([TEXT_COLOR; 2]);
+        }
+        let Some((fraction, average)) = bar else {
+            continue;
+        };
+        let bar_start = MARGIN + label_width + TEXT_HEIGHT;
+        let bar_length = (fraction * MAX_BAR_LENGTH).max(1.0);
+        for y in 0..TEXT_HEIGHT as u32 {
+            let y = baseline + y as f32;
+            positions.extend([to_world(Vec2::new(bar_start, y)), to_world(Vec2::new(bar_start + bar_length, y))]);
+            colors.extend([BAR_COLOR; 2]);
+        }
+        let time = format!("{:.3}", average.as_secs_f64() * 1000.0);
+        for (start, end) in stroke_font::text_strokes(&time, Vec2::new(bar_start + MAX_BAR_LENGTH + TEXT_HEIGHT, baseline), TEXT_HEIGHT) {
+            positions.extend([to_world(start), to_world(end)]);
+            colors.extend([TEXT_COLOR; 2]);
+        }
+    }
+    (positions, colors)
+}
+
+impl Renderable for ProfilerOverlay {
+    fn draw(&self, render_pass: &mut wgpu::RenderPass, camera: &Camera) {
+        if self.visible {
+            self.lines.draw(render_pass, camera);
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::JoinHandle;
+use glam::UVec2;
+use wgpu::BufferAsyncError;
+use wgpu::wgt::PollType;
+use crate::renderer::offscreen_target::OffscreenTarget;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::png;
+
+/// Staging buffers in flight at most. A frame is read back two frames after it was drawn, the recorder only
+/// waits for the GPU when all of them are still being copied into.
+pub const STAGING_BUFFERS: usize = 3;
+
+/// Where the recorded frames go.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// Numbered PNG files in a directory, `frame_00000.png` and on.
+    PngSequence,
+    /// Raw RGBA frames piped to the standard input of `ffmpeg`, which must be on the PATH, encoded as H.264.
+    Ffmpeg,
+}
+
+impl RecordingFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "png" => Some(Self::PngSequence),
+            "ffmpeg" => Some(Self::Ffmpeg),
+            _ => None,
+        }
+    }
+}
+
+/// What `Recorder` records, part of the `[recording]` table of the config file.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RecordingSettings {
+    /// Frames between two recorded frames, 1 records every frame.
+    pub frame_interval: u32,
+    pub format: RecordingFormat,
+    /// Frame rate of the video made by `ffmpeg`.
+    pub frame_rate: u32,
+    /// Starts recording with the first frame instead of waiting for the key.
+    pub record_on_start: bool,
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        Self {
+            frame_interval: 1,
+            format: RecordingFormat::PngSequence,
+            frame_rate: 60,
+            record_on_start: false,
+        }
+    }
+}
+
+struct StagingSlot {
+    staging_buffer: wgpu::Buffer,
+    /// Set while the buffer is being copied into and mapped
+    receiver: Option<Receiver<Result<(), BufferAsyncError>>>,
+}
+
+/// Pixels of a recorded frame, see `OffscreenTarget::to_rgba8`.
+struct RecordedFrame {
+    pixels: Vec<u8>,
+    size: UVec2,
+}
+
+/// Encodes the frames on its own thread, so the file writes and the pipe do not hold the frame.
+struct FrameWriter {
+    sender: Sender<RecordedFrame>,
+    thread: JoinHandle<()>,
+}
+
+/// Records the rendered frames as a PNG sequence or a video. Every `frame_interval`th frame is rendered into an
+/// offscreen target and copied into one of a ring of `STAGING_BUFFERS` staging buffers, which `poll` hands to
+/// a writer thread once they are mapped. The frames keep the window size of the first recorded frame.
+///
+/// ```ignore
+/// if recorder.advance_frame() {
+///     let target = recorder.target(wgpu_context, window_size, surface_format);
+///     renderer.render_offscreen(wgpu_context, &renderables, gpu_profiler, target);
+///     recorder.capture(wgpu_context);
+/// }
+/// recorder.poll(wgpu_context);
+/// ```
+pub struct Recorder {
+    settings: RecordingSettings,
+    target: Option<OffscreenTarget>,
+    slots: Vec<StagingSlot>,
+    /// Slots being copied into, oldest first: the frames are written in order
+    in_flight: VecDeque<usize>,
+    writer: Option<FrameWriter>,
+    frames_seen: u64,
+    frames_recorded: u64,
+}
+
+impl Recorder {
+    pub fn new(settings: RecordingSettings) -> Self {
+        Self {
+            settings,
+            target: None,
+            slots: Vec::new(),
+            in_flight: VecDeque::new(),
+            writer: None,
+            frames_seen: 0,
+            frames_recorded: 0,
+        }
+    }
+
+    pub fn settings(&self) -> RecordingSettings {
+        self.settings
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    /// Frames captured since the recording started.
+    pub fn frames_recorded(&self) -> u64 {
+        self.frames_recorded
+    }
+
+    /// Starts a recording at `path`: the directory of the PNG sequence, or the video file for `ffmpeg` (with
+    /// the `.mp4` extension). The video is encoded once the first frame tells its size.
+    pub fn start(&mut self, path: &Path) -> io::Result<()> {
+        if self.is_recording() {
+            return Err(io::Error::other("already recording"));
+        }
+        if cfg!(target_arch = "wasm32") {
+            return Err(io::Error::other("the web build has no files nor threads to record with"));
+        }
+        let (sender, receiver) = mpsc::channel::<RecordedFrame>();
+        let thread = match self.settings.format {
+            RecordingFormat::PngSequence => {
+                std::fs::create_dir_all(path)?;
+                let dir = path.to_path_buf();
+                std::thread::spawn(move || write_png_sequence(&dir, receiver))
+            }
+            RecordingFormat::Ffmpeg => {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                let path = path.with_extension("mp4");
+                let frame_rate = self.settings.frame_rate.max(1);
+                std::thread::spawn(move || {
+                    if let Err(e) = pipe_to_ffmpeg(&path, frame_rate, receiver) {
+                        log::error!("Unable to record {}: {:?}", path.display(), e);
+                    }
+                })
+            }
+        };
+        self.writer = Some(FrameWriter { sender, thread });
+        self.target = None;
+        self.frames_seen = 0;
+        self.frames_recorded = 0;
+        Ok(())
+    }
+
+    /// Writes the frames still in flight and finishes the recording. Stalls until the GPU and the writer are done.
+    pub fn stop(&mut self, wgpu_context: &WgpuContext) {
+        if !self.is_recording() {
+            return;
+        }
+        while !self.in_flight.is_empty() {
+            self.finish_oldest(wgpu_context);
+        }
+        if let Some(writer) = self.writer.take() {
+            drop(writer.sender);
+            if writer.thread.join().is_err() {
+                log::error!("The recording writer panicked");
+            }
+        }
+        self.target = None;
+        self.slots.clear();
+    }
+
+    /// Counts a rendered frame, returns true if it is recorded: render it into `target` and `capture` it.
+    pub fn advance_frame(&mut self) -> bool {
+        if !self.is_recording() {
+            return false;
+        }
+        let due = self.frames_seen.is_multiple_of(self.settings.frame_interval.max(1) as u64);
+        self.frames_seen += 1;
+        due
+    }
+
+    /// The target to render the recorded frame into. Created with the first frame, with `size` and `format`.
+    pub fn target(&mut self, wgpu_context: &WgpuContext, size: UVec2, format: wgpu::TextureFormat) -> &OffscreenTarget {
+        self.target.get_or_insert_with(|| OffscreenTarget::new(wgpu_context, size, format))
+    }
+
+    /// Submits the copy of `target` into a free staging buffer. Waits for the oldest frame if none is free.
+    pub fn capture(&mut self, wgpu_context: &WgpuContext) {
+        let Some(staging_size) = self.target.as_ref().map(OffscreenTarget::staging_size) else {
+            return;
+        };
+        let slot_index = match (0..self.slots.len()).find(|index| !self.in_flight.contains(index)) {
+            Some(index) => index,
+            None if self.slots.len() < STAGING_BUFFERS => {
+                self.slots.push(StagingSlot {
+                    staging_buffer: wgpu_context.get_device().create_buffer(&wgpu::BufferDescriptor {
+                        label: Some("Recorder staging buffer"),
+                        size: staging_size,
+                        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                        mapped_at_creation: false,
+                    }),
+                    receiver: None,
+                });
+                self.slots.len() - 1
+            }
+            None => self.finish_oldest(wgpu_context),
+        };
+        let Some(target) = &self.target else {
+            return;
+        };
+
+        let slot = &mut self.slots[slot_index];
+        let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Recorder encoder") });
+        target.copy_to_buffer(&mut encoder, &slot.staging_buffer);
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+        wgpu_context.transfers().record_readback(staging_size);
+
+        let (sender, receiver) = mpsc::channel();
+        slot.staging_buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        slot.receiver = Some(receiver);
+        self.in_flight.push_back(slot_index);
+        self.frames_recorded += 1;
+    }
+
+    /// Hands the frames whose copy finished to the writer, in order, without blocking. Call it once per frame.
+    pub fn poll(&mut self, wgpu_context: &WgpuContext) {
+        if self.in_flight.is_empty() {
+            return;
+        }
+        let _ = wgpu_context.get_device().poll(PollType::Poll);
+        while let Some(&slot_index) = self.in_flight.front() {
+            let Some(receiver) = &self.slots[slot_index].receiver else {
+                break;
+            };
+            match receiver.try_recv() {
+                Ok(result) => self.write_slot(slot_index, result),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => self.write_slot(slot_index, Err(BufferAsyncError)),
+            }
+        }
+    }
+
+    /// Waits for the oldest frame in flight and writes it, returns its now free slot.
+    fn finish_oldest(&mut self, wgpu_context: &WgpuContext) -> usize {
+        let slot_index = self.in_flight[0];
+        if let Err(e) = wgpu_context.get_device().poll(PollType::Wait) {
+            log::error!("Unable to wait for the recorded frame: {:?}", e);
+        }
+        let result = self.slots[slot_index].receiver.as_ref()
+            .map_or(Err(BufferAsyncError), |receiver| receiver.recv().unwrap_or(Err(BufferAsyncError)));
+        self.write_slot(slot_index, result);
+        slot_index
+    }
+
+    fn write_slot(&mut self, slot_index: usize, result: Result<(), BufferAsyncError>) {
+        self.in_flight.retain(|&index| index != slot_index);
+        let slot = &mut self.slots[slot_index];
+        slot.receiver = None;
+        if let Err(e) = result {
+            log::error!("Unable to read a recorded frame back: {:?}", e);
+            return;
+        }
+        let Some(target) = &self.target else {
+            slot.staging_buffer.unmap();
+            return;
+        };
+        let pixels = target.to_rgba8(&slot.staging_buffer.slice(..).get_mapped_range());
+        slot.staging_buffer.unmap();
+        if let Some(writer) = &self.writer {
+            // The writer only hangs up after a failure it already logged
+            let _ = writer.sender.send(RecordedFrame { pixels, size: target.size() });
+        }
+    }
+}
+
+fn write_png_sequence(dir: &Path, frames: Receiver<RecordedFrame>) {
+    for (index, frame) in frames.into_iter().enumerate() {
+        let path = dir.join(format!("frame_{:05}.png", index));
+        if let Err(e) = png::save_rgba8(&path, frame.size.x, frame.size.y, &frame.pixels) {
+            log::error!("Unable to save the recorded frame {}: {:?}", path.display(), e);
+            return;
+        }
+    }
+    log::info!("Recording saved to {}", dir.display());
+}
+
+fn pipe_to_ffmpeg(path: &Path, frame_rate: u32, frames: Receiver<RecordedFrame>) -> io::Result<()> {
+    let mut ffmpeg: Option<Child> = None;
+    for frame in frames {
+        if ffmpeg.is_none() {
+            ffmpeg = Some(Command::new("ffmpeg")
+                .args(["-y", "-loglevel", "error", "-f", "rawvideo", "-pixel_format", "rgba"])
+                .args(["-video_size", &format!("{}x{}", frame.size.x, frame.size.y), "-framerate", &frame_rate.to_string()])
+                .args(["-i", "-", "-c:v", "libx264", "-pix_fmt", "yuv420p"])
+                .arg(path)
+                .stdin(Stdio::piped())
+                .spawn()?);
+        }
+        let stdin = ffmpeg.as_mut().and_then(|child| child.stdin.as_mut()).ok_or_else(|| io::Error::other("ffmpeg has no standard input"))?;
+        stdin.write_all(&frame.pixels)?;
+    }
+    if let Some(mut child) = ffmpeg {
+        // Closing the standard input ends the video
+        drop(child.stdin.take());
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("ffmpeg exited with {}", status)));
+        }
+        log::info!("Recording saved to {}", path.display());
+    }
+    Ok(())
+}
@@ -1,15 +1,23 @@
-use wgpu_profiler::GpuProfiler;
+use crate::utils::profiler::GpuProfiler;
 use winit::dpi::PhysicalPosition;
 use winit::event::MouseScrollDelta;
 use winit::keyboard::{KeyCode};
+use glam::Vec2;
 use crate::renderer::camera::{Camera};
+use crate::renderer::density_heatmap::DensityHeatmap;
+use crate::renderer::offscreen_target::OffscreenTarget;
 use crate::renderer::renderable::Renderable;
 use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::gpu_buffer::GpuBuffer;
+use crate::utils::png;
+use std::io;
+use std::path::Path;
 
 // Manages multiple render pipelines
 pub struct Renderer {
     background_color: wgpu::Color,
     camera: Camera,
+    density_heatmap: Option<DensityHeatmap>, // Drawn instead of the particles while enabled
 }
 
 
@@ -22,11 +30,10 @@ impl Renderer {
         Some(Self {
             background_color: wgpu::Color::BLACK,
             camera,
+            density_heatmap: None,
         })
     }
     pub fn render(&self, wgpu_context: &WgpuContext, renderables: &[&dyn Renderable], gpu_profiler: &mut GpuProfiler) -> Result<(), wgpu::SurfaceError>{
-        wgpu_context.get_window().request_redraw();
-
         // We can't render unless the window is configured
         if !wgpu_context.is_surface_configured() {
             return Ok(());
@@ -41,39 +48,67 @@ impl Renderer {
         let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor{
             label: Some("Render Encoder"),
         });
+        self.record_render_pass(&mut encoder, &view, renderables, gpu_profiler);
 
-        // Use encoder to create a RenderPass
-        {
-            let mut scope_encoder = gpu_profiler.scope("Render pass", &mut encoder);
-            let mut render_pass = scope_encoder.begin_render_pass(&wgpu::RenderPassDescriptor{
-                label: Some("Render Pass"),
-                color_attachments: &[
-                    Some(wgpu::RenderPassColorAttachment{
-                        view: &view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(self.background_color),
-                            store: wgpu::StoreOp::Store,
-                        },
-                        depth_slice: None,
-                    })],
-                depth_stencil_attachment: None,
-                occlusion_query_set: None,
-                timestamp_writes: None,
-            });
-
-            // Draw all renderables
-            for renderable in renderables {
-                renderable.draw(&mut render_pass, &self.camera);
-            }
-        }
-        
         gpu_profiler.resolve_queries(&mut encoder);
         wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
         output.present();
         Ok(())
     }
 
+    /// Renders the renderables like `render` does, into an offscreen texture of the window size instead of the
+    /// surface, and saves it as a PNG at `path`. Stalls until the GPU is done.
+    pub fn capture_frame(&self, wgpu_context: &WgpuContext, renderables: &[&dyn Renderable], gpu_profiler: &mut GpuProfiler, path: &Path) -> io::Result<()> {
+        let size = wgpu_context.window_size().as_uvec2();
+        let target = OffscreenTarget::new(wgpu_context, size, wgpu_context.surface_format());
+        self.render_offscreen(wgpu_context, renderables, gpu_profiler, &target);
+
+        let pixels = target.read_rgba8(wgpu_context);
+        png::save_rgba8(path, target.size().x, target.size().y, &pixels)
+    }
+
+    /// Submits the frame `render` draws, drawn into `target` instead of the surface. The target must have the
+    /// surface format.
+    pub fn render_offscreen(&self, wgpu_context: &WgpuContext, renderables: &[&dyn Renderable], gpu_profiler: &mut GpuProfiler, target: &OffscreenTarget) {
+        let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor{
+            label: Some("Offscreen Render Encoder"),
+        });
+        self.record_render_pass(&mut encoder, target.view(), renderables, gpu_profiler);
+        gpu_profiler.resolve_queries(&mut encoder);
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+    }
+
+    /// Records the render pass that clears `view` and draws the renderables into it.
+    fn record_render_pass(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, renderables: &[&dyn Renderable], gpu_profiler: &mut GpuProfiler) {
+        let mut scope_encoder = gpu_profiler.scope("Render pass", encoder);
+        let mut render_pass = scope_encoder.begin_render_pass(&wgpu::RenderPassDescriptor{
+            label: Some("Render Pass"),
+            color_attachments: &[
+                Some(wgpu::RenderPassColorAttachment{
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.background_color),
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        // The heatmap goes under everything else, it replaces the background
+        if let Some(density_heatmap) = &self.density_heatmap {
+            density_heatmap.draw(&mut render_pass);
+        }
+
+        // Draw all renderables
+        for renderable in renderables {
+            renderable.draw(&mut render_pass, &self.camera);
+        }
+    }
+
     pub fn camera(&self) -> &Camera {
         &self.camera
     }
@@ -108,12 +143,29 @@ impl Renderer {
             0, // offset
             bytemuck::cast_slice(&[*self.camera.get_uniform()])
         );
+        wgpu_context.transfers().record_upload(size_of_val(self.camera.get_uniform()) as u64);
     }
 
     pub fn update_camera(&mut self, wgpu_context: &WgpuContext){
         self.update_camera_matrices(wgpu_context);
     }
 
+    /// Enables or disables the density heatmap render mode, see `DensityHeatmap`.
+    pub fn set_density_heatmap(&mut self, wgpu_context: &WgpuContext, enabled: bool) {
+        self.density_heatmap = enabled.then(|| DensityHeatmap::new(wgpu_context, wgpu_context.surface_format()));
+    }
+
+    pub fn is_density_heatmap_enabled(&self) -> bool {
+        self.density_heatmap.is_some()
+    }
+
+    /// Rebuilds the density heatmap from the particle positions as seen by the camera, if it is enabled.
+    pub fn update_density_heatmap(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, positions: &GpuBuffer<Vec2>, num_particles: usize) {
+        if let Some(density_heatmap) = &mut self.density_heatmap {
+            density_heatmap.update(wgpu_context, gpu_profiler, positions, num_particles, self.camera.view_projection(), wgpu_context.window_size());
+        }
+    }
+
     pub fn background_color(&mut self) -> &mut wgpu::Color {
         &mut self.background_color
     }
@@ -0,0 +1,116 @@
+//! Line strokes of text, for the overlays drawn with `Lines`. Letters and digits are fourteen segment
+//! glyphs, lowercase is drawn as uppercase; characters without a glyph are left blank.
+use glam::Vec2;
+
+/// Width of a glyph relative to its height.
+pub const GLYPH_WIDTH: f32 = 0.6;
+/// Space between two glyphs relative to the height.
+pub const GLYPH_SPACING: f32 = 0.3;
+
+// Segments of the unit glyph box, y up: top, top right, bottom right, bottom, bottom left, top left,
+// middle left, middle right, top left diagonal, top center, top right diagonal, bottom left diagonal,
+// bottom center, bottom right diagonal
+const SEGMENTS: [(Vec2, Vec2); 14] = [
+    (Vec2::new(0.0, 1.0), Vec2::new(1.0, 1.0)),
+    (Vec2::new(1.0, 1.0), Vec2::new(1.0, 0.5)),
+    (Vec2::new(1.0, 0.5), Vec2::new(1.0, 0.0)),
+    (Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0)),
+    (Vec2::new(0.0, 0.5), Vec2::new(0.0, 0.0)),
+    (Vec2::new(0.0, 1.0), Vec2::new(0.0, 0.5)),
+    (Vec2::new(0.0, 0.5), Vec2::new(0.5, 0.5)),
+    (Vec2::new(0.5, 0.5), Vec2::new(1.0, 0.5)),
+    (Vec2::new(0.0, 1.0), Vec2::new(0.5, 0.5)),
+    (Vec2::new(0.5, 1.0), Vec2::new(0.5, 0.5)),
+    (Vec2::new(1.0, 1.0), Vec2::new(0.5, 0.5)),
+    (Vec2::new(0.0, 0.0), Vec2::new(0.5, 0.5)),
+    (Vec2::new(0.5, 0.5), Vec2::new(0.5, 0.0)),
+    (Vec2::new(0.5, 0.5), Vec2::new(1.0, 0.0)),
+];
+
+/// Lit segments of A to Z, bit i is `SEGMENTS[i]`.
+const LETTER_SEGMENTS: [u16; 26] = [
+    0b00000011110111, 0b01001010001111, 0b00000000111001, 0b01001000001111, 0b00000001111001,
+    0b00000001110001, 0b00000010111101, 0b00000011110110, 0b01001000001001, 0b00000000011110,
+    0b10010001110000, 0b00000000111000, 0b00010100110110, 0b10000100110110, 0b00000000111111,
+    0b00000011110011, 0b10000000111111, 0b10000011110011, 0b00000011101101, 0b01001000000001,
+    0b00000000111110, 0b00110000110000, 0b10100000110110, 0b10110100000000, 0b01010100000000,
+    0b00110000001001,
+];
+
+/// Lit segments of 0 to 9.
+const DIGIT_SEGMENTS: [u16; 10] = [
+    0b00000000111111, 0b00000000000110, 0b00000011011011, 0b00000011001111, 0b00000011100110,
+    0b00000011101101, 0b00000011111101, 0b00000000000111, 0b00000011111111, 0b00000011101111,
+];
+
+/// Lit segments of `c`, `None` for the characters that are not a letter or a digit.
+pub fn glyph_segments(c: char) -> Option<u16> {
+    let c = c.to_ascii_uppercase();
+    match c {
+        'A'..='Z' => Some(LETTER_SEGMENTS[(c as u8 - b'A') as usize]),
+        '0'..='9' => Some(DIGIT_SEGMENTS[(c as u8 - b'0') as usize]),
+        _ => None,
+    }
+}
+
+// Strokes of the punctuation, in the unit glyph box. `Vec2::new` is not promoted to a `'static` value, so
+// `punctuation_strokes` returns these items.
+const PERIOD: [(Vec2, Vec2); 1] = [(Vec2::new(0.5, 0.0), Vec2::new(0.5, 0.1))];
+const COMMA: [(Vec2, Vec2); 1] = [(Vec2::new(0.5, 0.1), Vec2::new(0.3, -0.15))];
+const COLON: [(Vec2, Vec2); 2] = [(Vec2::new(0.5, 0.15), Vec2::new(0.5, 0.25)), (Vec2::new(0.5, 0.75), Vec2::new(0.5, 0.85))];
+const HYPHEN: [(Vec2, Vec2); 1] = [(Vec2::new(0.1, 0.5), Vec2::new(0.9, 0.5))];
+const UNDERSCORE: [(Vec2, Vec2); 1] = [(Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0))];
+const SLASH: [(Vec2, Vec2); 1] = [(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0))];
+const PERCENT: [(Vec2, Vec2); 3] = [(Vec2::new(0.0, 0.0), Vec2::new(1.0, 1.0)), (Vec2::new(0.1, 0.9), Vec2::new(0.2, 0.8)), (Vec2::new(0.8, 0.2), Vec2::new(0.9, 0.1))];
+const OPEN_PARENTHESIS: [(Vec2, Vec2); 2] = [(Vec2::new(0.7, 1.0), Vec2::new(0.4, 0.5)), (Vec2::new(0.4, 0.5), Vec2::new(0.7, 0.0))];
+const CLOSE_PARENTHESIS: [(Vec2, Vec2); 2] = [(Vec2::new(0.3, 1.0), Vec2::new(0.6, 0.5)), (Vec2::new(0.6, 0.5), Vec2::new(0.3, 0.0))];
+const PLUS: [(Vec2, Vec2); 2] = [(Vec2::new(0.1, 0.5), Vec2::new(0.9, 0.5)), (Vec2::new(0.5, 0.2), Vec2::new(0.5, 0.8))];
+
+/// Strokes of `c`, empty for the characters without a glyph.
+fn punctuation_strokes(c: char) -> &'static [(Vec2, Vec2)] {
+    match c {
+        '.' => &PERIOD,
+        ',' => &COMMA,
+        ':' => &COLON,
+        '-' => &HYPHEN,
+        '_' => &UNDERSCORE,
+        '/' => &SLASH,
+        '%' => &PERCENT,
+        '(' => &OPEN_PARENTHESIS,
+        ')' => &CLOSE_PARENTHESIS,
+        '+' => &PLUS,
+        _ => &[],
+    }
+}
+
+/// Width of `text` drawn `height` tall.
+pub fn text_width(text: &str, height: f32) -> f32 {
+    let num_glyphs = text.chars().count() as f32;
+    if num_glyphs == 0.0 {
+        return 0.0;
+    }
+    (num_glyphs * (GLYPH_WIDTH + GLYPH_SPACING) - GLYPH_SPACING) * height
+}
+
+/// Strokes of `text` on a single line, with the bottom left corner of the first glyph at `bottom_left`.
+/// Each stroke is a (start, end) pair.
+pub fn text_strokes(text: &str, bottom_left: Vec2, height: f32) -> Vec<(Vec2, Vec2)> {
+    let glyph_size = Vec2::new(GLYPH_WIDTH, 1.0) * height;
+    let mut strokes = Vec::new();
+    for (i, c) in text.chars().enumerate() {
+        let corner = bottom_left + Vec2::new(i as f32 * (GLYPH_WIDTH + GLYPH_SPACING) * height, 0.0);
+        match glyph_segments(c) {
+            Some(lit) => {
+                for (segment, (start, end)) in SEGMENTS.iter().enumerate() {
+                    if lit & (1 << segment) != 0 {
+                        strokes.push((corner + *start * glyph_size, corner + *end * glyph_size));
+                    }
+                }
+            }
+            None => {
+                strokes.extend(punctuation_strokes(c).iter().map(|(start, end)| (corner + *start * glyph_size, corner + *end * glyph_size)));
+            }
+        }
+    }
+    strokes
+}
@@ -1,18 +1,97 @@
+#[cfg(feature = "windowing")]
 use std::sync::Arc;
+#[cfg(feature = "windowing")]
 use glam::Vec2;
 use wgpu::Adapter;
+use crate::utils::buffer_registry::BufferRegistry;
+use crate::utils::kernel_tuning::KernelTuning;
+use crate::utils::pipeline_cache::PipelineCache;
+#[cfg(debug_assertions)]
+use crate::utils::shader_watcher::ShaderRegistry;
+use crate::utils::telemetry::TransferCounter;
+#[cfg(feature = "windowing")]
 use winit::window::Window;
 
+#[cfg(feature = "windowing")]
 use crate::renderer::surface_manager::SurfaceManager;
 
+/// Features the windowed app requests, when the adapter supports them.
+#[cfg(feature = "windowing")]
+const APP_FEATURES: wgpu::Features = wgpu::Features::PUSH_CONSTANTS
+    .union(wgpu::Features::TIMESTAMP_QUERY)
+    .union(wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS)
+    .union(wgpu::Features::SUBGROUP)
+    .union(wgpu::Features::SUBGROUP_BARRIER)
+    .union(wgpu::Features::VERTEX_WRITABLE_STORAGE)
+    .union(wgpu::Features::PIPELINE_CACHE);
+
+/// Features the test contexts request, when the adapter supports them.
+pub const TEST_FEATURES: wgpu::Features = wgpu::Features::PUSH_CONSTANTS
+    .union(wgpu::Features::SUBGROUP)
+    .union(wgpu::Features::SUBGROUP_BARRIER)
+    .union(wgpu::Features::VERTEX_WRITABLE_STORAGE);
+
+/// What the device can do, read once at creation. The kernels pick their workgroup sizes and variants from it
+/// instead of assuming a desktop GPU, e.g. the WebGPU baseline has neither subgroups nor push constants.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DeviceCapabilities {
+    /// Subgroup operations for the prefix sum. Without them it scans in workgroup memory.
+    pub subgroups: bool,
+    pub max_subgroup_size: u32,
+    /// Bytes of push constants, 0 without them. `ComputeShader` passes constants that do not fit in a uniform buffer.
+    pub max_push_constant_size: u32,
+    pub max_workgroup_size_x: u32,
+    pub max_invocations_per_workgroup: u32,
+    pub max_workgroup_storage_size: u32,
+    pub max_buffer_size: u64,
+}
+
+impl DeviceCapabilities {
+    /// `backend` is the one of the adapter. GLES advertises push constants but rejects the struct blocks the
+    /// kernels declare, so on it every kernel takes the uniform buffer instead.
+    pub fn new(features: wgpu::Features, limits: &wgpu::Limits, backend: wgpu::Backend) -> Self {
+        let push_constants = features.contains(wgpu::Features::PUSH_CONSTANTS) && backend != wgpu::Backend::Gl;
+        Self {
+            subgroups: features.contains(wgpu::Features::SUBGROUP),
+            max_subgroup_size: limits.max_subgroup_size,
+            max_push_constant_size: if push_constants { limits.max_push_constant_size } else { 0 },
+            max_workgroup_size_x: limits.max_compute_workgroup_size_x,
+            max_invocations_per_workgroup: limits.max_compute_invocations_per_workgroup,
+            max_workgroup_storage_size: limits.max_compute_workgroup_storage_size,
+            max_buffer_size: limits.max_buffer_size,
+        }
+    }
+
+    /// Whether `size` bytes of push constants fit, see `ComputeShader`.
+    pub fn fits_push_constants(&self, size: u32) -> bool {
+        size <= self.max_push_constant_size
+    }
+
+    /// The largest power of two up to `preferred` that a one-dimensional workgroup may have on this device.
+    pub fn workgroup_size(&self, preferred: u32) -> u32 {
+        let max = preferred.min(self.max_workgroup_size_x).min(self.max_invocations_per_workgroup).max(1);
+        1 << max.ilog2()
+    }
+}
+
 pub struct WgpuContext {
     device: wgpu::Device,
     queue: wgpu::Queue,
+    #[cfg(feature = "windowing")]
     surface_manager: Option<SurfaceManager>,
     adapter: Adapter,
+    transfers: TransferCounter,
+    buffers: BufferRegistry,
+    capabilities: DeviceCapabilities,
+    kernel_tuning: KernelTuning,
+    pipeline_cache: Option<PipelineCache>,
+    measure_queue_drain: bool,
+    #[cfg(debug_assertions)]
+    shaders: ShaderRegistry,
 }
 
 impl WgpuContext {
+    #[cfg(feature = "windowing")]
     pub async fn new(window: Arc<Window>) -> anyhow::Result<Self> {
 
 
@@ -20,8 +99,9 @@ impl WgpuContext {
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
             #[cfg(not(target_arch = "wasm32"))]
             backends: wgpu::Backends::PRIMARY,
+            // WebGL2 has no compute shaders
             #[cfg(target_arch = "wasm32")]
-            backends: wgpu::Backends::GL,
+            backends: wgpu::Backends::BROWSER_WEBGPU,
             ..Default::default()
         });
 
@@ -39,38 +119,49 @@ impl WgpuContext {
 
       
 
+        // The kernels pick their variants from the features the adapter has, see `DeviceCapabilities`
+        let required_features = APP_FEATURES & adapter.features();
+        let required_limits = WgpuContext::get_limits(&adapter);
         let (device, queue) = adapter
             .request_device(&wgpu::DeviceDescriptor{
                 label: None,
-                required_features: wgpu::Features::PUSH_CONSTANTS | wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS | wgpu::Features::SUBGROUP | wgpu::Features::SUBGROUP_BARRIER | wgpu::Features::VERTEX_WRITABLE_STORAGE,
-                required_limits: WgpuContext::get_limits(&adapter),
+                required_features,
+                required_limits: required_limits.clone(),
                 memory_hints: Default::default(),
                 trace: wgpu::Trace::Off,
             }).await?;
-
-
+        let capabilities = DeviceCapabilities::new(required_features, &required_limits, adapter.get_info().backend);
 
         Ok(Self {
             device,
             queue,
             surface_manager,
             adapter,
+            transfers: TransferCounter::default(),
+            buffers: BufferRegistry::new(&required_limits),
+            capabilities,
+            kernel_tuning: KernelTuning::default(),
+            pipeline_cache: None,
+            measure_queue_drain: false,
+            #[cfg(debug_assertions)]
+            shaders: ShaderRegistry::default(),
         })
     }
     
+    /// The limits the adapter reports. On the web these are the ones of the browser's WebGPU adapter,
+    /// the WebGL2 defaults have no compute shaders.
+    #[cfg(feature = "windowing")]
     fn get_limits(adapter: &Adapter) -> wgpu::Limits {
-        let limits;
-        if cfg!(target_arch = "wasm32") {
-            // When on web, request the browser's supported limits
-            limits = wgpu::Limits::downlevel_webgl2_defaults().using_resolution(adapter.limits());
-        } else {
-            // For native, use the adapter's reported limits
-            limits = adapter.limits();
-        }
-        
-        limits
+        adapter.limits()
     }
+
     pub async fn new_for_test() -> anyhow::Result<Self> {
+        Self::new_for_test_with_features(TEST_FEATURES).await
+    }
+
+    /// Test context that only requests the `features` the adapter supports, e.g. without push constants
+    /// and subgroups to run the kernels the way the WebGPU baseline does.
+    pub async fn new_for_test_with_features(features: wgpu::Features) -> anyhow::Result<Self> {
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
@@ -80,28 +171,111 @@ impl WgpuContext {
             })
             .await?;
             
-
+        let required_features = features & adapter.features();
+        let required_limits = adapter.limits();
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: Some("Test Device"),
-                    required_features: wgpu::Features::PUSH_CONSTANTS | wgpu::Features::SUBGROUP | wgpu::Features::SUBGROUP_BARRIER | wgpu::Features::VERTEX_WRITABLE_STORAGE,
-                    required_limits: WgpuContext::get_limits(&adapter),
+                    required_features,
+                    // Tests only run compute kernels, on the web that means WebGPU, not the WebGL2 limits
+                    required_limits: required_limits.clone(),
                     ..Default::default()
                 },
             )
             .await?;
+        let capabilities = DeviceCapabilities::new(required_features, &required_limits, adapter.get_info().backend);
 
         Ok(Self {
             device,
             queue,
+            #[cfg(feature = "windowing")]
             surface_manager: None,
+            transfers: TransferCounter::default(),
+            buffers: BufferRegistry::new(&required_limits),
+            capabilities,
+            kernel_tuning: KernelTuning::default(),
+            pipeline_cache: None,
+            measure_queue_drain: false,
+            #[cfg(debug_assertions)]
+            shaders: ShaderRegistry::default(),
             adapter,
         })
     }
 
+    pub fn get_device(&self) -> &wgpu::Device {
+        &self.device
+    }
+    
+    pub fn get_queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
 
+    pub fn get_adapter(&self) -> &Adapter {
+        &self.adapter
+    }
+
+    /// Features and limits of the device the kernels adapt to, see `DeviceCapabilities`.
+    pub fn capabilities(&self) -> DeviceCapabilities {
+        self.capabilities
+    }
+
+    /// Workgroup sizes the sorter, the prefix sum and the collision solver are created with, see `KernelTuning`.
+    pub fn kernel_tuning(&self) -> KernelTuning {
+        self.kernel_tuning
+    }
 
+    /// Only the kernels created afterwards use the new tuning, set it before creating the simulation.
+    pub fn set_kernel_tuning(&mut self, kernel_tuning: KernelTuning) {
+        self.kernel_tuning = kernel_tuning;
+    }
+
+    /// Whether `RefreshTimer::finish` waits for the queue to drain, see `RefreshTiming::queue_drain`.
+    pub fn measures_queue_drain(&self) -> bool {
+        self.measure_queue_drain
+    }
+
+    /// Off by default: the wait stalls the CPU after every refresh of a spawn.
+    pub fn set_measure_queue_drain(&mut self, measure_queue_drain: bool) {
+        self.measure_queue_drain = measure_queue_drain;
+    }
+
+    /// Loads the pipeline cache of the adapter from `dir`, see `PipelineCache`. Only the pipelines created afterwards
+    /// use it, load it before creating the simulation. Returns false on devices without `Features::PIPELINE_CACHE`.
+    pub fn load_pipeline_cache(&mut self, dir: impl AsRef<std::path::Path>) -> bool {
+        self.pipeline_cache = PipelineCache::load(&self.device, &self.adapter.get_info(), dir);
+        self.pipeline_cache.is_some()
+    }
+
+    /// The cache every compute and render pipeline is created with, `None` until `load_pipeline_cache`.
+    pub fn pipeline_cache(&self) -> Option<&wgpu::PipelineCache> {
+        self.pipeline_cache.as_ref().map(PipelineCache::cache)
+    }
+
+    /// Writes the pipelines compiled so far to the cache directory. Does nothing without a pipeline cache.
+    pub fn save_pipeline_cache(&self) -> std::io::Result<()> {
+        self.pipeline_cache.as_ref().map_or(Ok(()), PipelineCache::save)
+    }
+
+    /// Bytes uploaded, copied and read back through this context, see `Telemetry::record_frame_transfers`.
+    pub fn transfers(&self) -> &TransferCounter {
+        &self.transfers
+    }
+
+    /// Memory held by the `GpuBuffer`s of this context, per subsystem, see `BufferRegistry::report`.
+    pub fn buffers(&self) -> &BufferRegistry {
+        &self.buffers
+    }
+
+    /// The live compute kernels of this context, which `ShaderWatcher` reloads. Debug builds only.
+    #[cfg(debug_assertions)]
+    pub fn shaders(&self) -> &ShaderRegistry {
+        &self.shaders
+    }
+}
+
+#[cfg(feature = "windowing")]
+impl WgpuContext {
     pub fn window_size(&self) -> Vec2 {
         if self.surface_manager.is_none() {
             return Vec2::ZERO;
@@ -125,19 +299,13 @@ impl WgpuContext {
         self.surface_manager.as_ref().expect("No surface in this context").is_surface_configured()
     }
     
-    pub fn get_device(&self) -> &wgpu::Device {
-        &self.device
-    }
-    
-    pub fn get_queue(&self) -> &wgpu::Queue {
-        &self.queue
-    }
-
-    pub fn get_adapter(&self) -> &Adapter {
-        &self.adapter
-    }
-    
     pub fn get_surface_config(&self) -> &wgpu::SurfaceConfiguration{
         &self.surface_manager.as_ref().expect("No surface in this context").get_config()
     }
+
+    /// Format the render pipelines draw in: the one of the surface, `Rgba8Unorm` in contexts without one,
+    /// e.g. the tests.
+    pub fn surface_format(&self) -> wgpu::TextureFormat {
+        self.surface_manager.as_ref().map_or(wgpu::TextureFormat::Rgba8Unorm, |surface_manager| surface_manager.get_config().format)
+    }
 }
\ No newline at end of file
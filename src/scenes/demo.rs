@@ -0,0 +1,36 @@
+//! Demo mode, started with `--demo`: a scene embedded into the binary, so it runs without any file, with a
+//! particle count that fits the adapter. The profiler overlay is shown and the controls are listed in the
+//! window title for the first seconds.
+use std::time::Duration;
+use crate::grid::grid::MAX_CELLS_PER_OBJECT;
+use crate::simulation_config::SimulationConfig;
+use crate::utils::config_file::ConfigError;
+
+/// The embedded scene, see `SimulationConfig::from_toml_str`.
+pub const DEMO_SCENE: &str = include_str!("demo.toml");
+/// How long the control hints stay in the window title.
+pub const HINTS_DURATION: Duration = Duration::from_secs(12);
+pub const CONTROL_HINTS: &str = "WASD move | Wheel zoom | Left click attract, 1-5 mode | Right click wall | P spawn | O hourglass | J/L rotate gravity | V colors | Space pause | F3 profiler | Esc quit";
+/// Bytes of the largest per particle buffer: the cell ids of the grid map.
+const GRID_BYTES_PER_PARTICLE: u64 = MAX_CELLS_PER_OBJECT as u64 * size_of::<u32>() as u64;
+
+/// Particles the demo starts with on an adapter of `device_type`, limited so the grid map fits in a storage
+/// buffer binding of `max_storage_buffer_binding_size` bytes.
+pub fn particle_budget(device_type: wgpu::DeviceType, max_storage_buffer_binding_size: u32) -> usize {
+    let budget = match device_type {
+        wgpu::DeviceType::DiscreteGpu => 500_000,
+        wgpu::DeviceType::IntegratedGpu => 150_000,
+        wgpu::DeviceType::VirtualGpu | wgpu::DeviceType::Other => 50_000,
+        wgpu::DeviceType::Cpu => 10_000,
+    };
+    let binding_limit = max_storage_buffer_binding_size as u64 / GRID_BYTES_PER_PARTICLE;
+    budget.min(binding_limit as usize).max(1)
+}
+
+/// The embedded scene with the particle budget of the adapter.
+pub fn demo_config(adapter_info: &wgpu::AdapterInfo, limits: &wgpu::Limits) -> Result<SimulationConfig, ConfigError> {
+    let config = SimulationConfig::from_toml_str(DEMO_SCENE)?;
+    let num_particles = particle_budget(adapter_info.device_type, limits.max_storage_buffer_binding_size);
+    log::info!("Demo on {} ({:?}): {} particles", adapter_info.name, adapter_info.device_type, num_particles);
+    Ok(SimulationConfig { num_particles, ..config })
+}
@@ -0,0 +1,140 @@
+//! Hourglass demo: an emitter pours particles into a funnel of static segments, they flow through the neck,
+//! spread along the lower bulb and are deleted by a kill line at the bottom. Exercises the spawning, the
+//! static colliders, the solver and the compaction together, and runs forever at a steady particle count.
+use std::fmt;
+use glam::Vec2;
+use crate::particles::particle_emitter::ParticleEmitter;
+use crate::particles::particle_group::GroupOperation;
+use crate::particles::particle_system::SpawnReport;
+use crate::physics::kill_volumes::KillVolume;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::simulation::Simulation;
+use crate::simulation_config::{BoundaryMode, FixedTimestep, GravityMode, SimulationConfig, WorldBoundary, EARTH_GRAVITY};
+use crate::utils::profiler::GpuProfiler;
+use crate::utils::telemetry::RefreshTiming;
+
+/// Heights, as fractions of the world height: the top of the funnel, the neck, the bottom of the lower bulb and the kill line.
+const FUNNEL_TOP: f32 = 0.85;
+const NECK: f32 = 0.5;
+const BULB_BOTTOM: f32 = 0.2;
+const KILL_LINE: f32 = 0.1;
+/// Half width of the funnel mouth, as a fraction of the smallest world side.
+const MOUTH_HALF_WIDTH: f32 = 0.35;
+/// Width of the neck in largest spawn radii, wide enough for the particles not to jam.
+const NECK_WIDTH_IN_RADII: f32 = 12.0;
+/// Smallest and largest radius of the poured particles, in meters.
+const SPAWN_RADIUS_RANGE: (f32, f32) = (0.02, 0.03);
+/// Passes per step. The particles reach several meters per second at the neck, with longer substeps
+/// they would cross the walls between two passes.
+const SUBSTEPS: u32 = 8;
+/// Particles per second poured by the emitter.
+pub const EMITTER_RATE: f32 = 3000.0;
+
+/// Counters shown by the HUD.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct HourglassStats {
+    pub emitted: usize,
+    pub alive: usize,
+    /// Deleted by the kill line, or by leaving the world.
+    pub removed: usize,
+}
+
+impl fmt::Display for HourglassStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Hourglass: {} emitted, {} alive, {} removed", self.emitted, self.alive, self.removed)
+    }
+}
+
+pub struct HourglassScene {
+    emitter: ParticleEmitter,
+    kill_line: f32,
+    neck: Vec2,
+    neck_half_width: f32,
+}
+
+impl HourglassScene {
+    /// Replaces what `simulation` holds with the scene: removes every particle, static collider and kill volume,
+    /// turns on the earth gravity with `SUBSTEPS` substeps and builds the funnel, the kill line and the emitter for the world size.
+    pub fn load(wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, simulation: &mut Simulation) -> Self {
+        let all_particles: Vec<u32> = (0..simulation.particles().len() as u32).collect();
+        simulation.apply_group_operation(wgpu_context, gpu_profiler, &all_particles, GroupOperation::Remove);
+        simulation.clear_static_circles(wgpu_context);
+        simulation.clear_static_segments(wgpu_context);
+        simulation.clear_kill_volumes(wgpu_context);
+        simulation.set_config(SimulationConfig {
+            gravity: EARTH_GRAVITY,
+            gravity_mode: GravityMode::Uniform,
+            boundary: WorldBoundary::Rectangle,
+            boundary_mode: BoundaryMode::Closed,
+            spawn_radius_range: SPAWN_RADIUS_RANGE,
+            timestep: Some(FixedTimestep { substeps: SUBSTEPS, ..FixedTimestep::default() }),
+            ..*simulation.config()
+        });
+
+        let origin = simulation.world_origin();
+        let size = simulation.particles().get_world_size();
+        let height = |fraction: f32| origin.y + size.y * fraction;
+        let center_x = origin.x + size.x / 2.0;
+        let mouth_half_width = MOUTH_HALF_WIDTH * size.x.min(size.y);
+        let (_, max_radius) = simulation.particles().spawn_radius_range();
+        let neck_half_width = NECK_WIDTH_IN_RADII * max_radius / 2.0;
+        let neck = Vec2::new(center_x, height(NECK));
+
+        for side in [-1.0, 1.0] {
+            let mouth = Vec2::new(center_x + side * mouth_half_width, height(FUNNEL_TOP));
+            let neck_edge = Vec2::new(center_x + side * neck_half_width, neck.y);
+            let bulb = Vec2::new(center_x + side * mouth_half_width, height(BULB_BOTTOM));
+            simulation.add_static_segment(wgpu_context, mouth, neck_edge);
+            simulation.add_static_segment(wgpu_context, neck_edge, bulb);
+        }
+        let kill_line = height(KILL_LINE);
+        simulation.add_kill_volume(wgpu_context, KillVolume::BelowLine { y: kill_line });
+
+        // Poured just below the mouth, over its middle half
+        let emitter = ParticleEmitter::new(
+            Vec2::new(center_x, height(FUNNEL_TOP) - 4.0 * max_radius),
+            mouth_half_width,
+            EMITTER_RATE,
+        );
+        Self {
+            emitter,
+            kill_line,
+            neck,
+            neck_half_width,
+        }
+    }
+
+    /// Pours the particles owed after `delta_time`, call it before stepping. The spawned particles need the
+    /// same refreshes as `Simulation::add_particles`.
+    pub fn update(&mut self, wgpu_context: &WgpuContext, simulation: &mut Simulation, delta_time: f32) -> (SpawnReport, Vec<(&'static str, RefreshTiming)>) {
+        self.emitter.update(wgpu_context, simulation, delta_time)
+    }
+
+    pub fn stats(&self, simulation: &Simulation) -> HourglassStats {
+        let emitted = self.emitter.emitted();
+        let alive = simulation.particles().len();
+        HourglassStats {
+            emitted,
+            alive,
+            removed: emitted.saturating_sub(alive),
+        }
+    }
+
+    pub fn emitter(&self) -> &ParticleEmitter {
+        &self.emitter
+    }
+
+    pub fn emitter_mut(&mut self) -> &mut ParticleEmitter {
+        &mut self.emitter
+    }
+
+    /// Height of the kill line, in world units.
+    pub fn kill_line(&self) -> f32 {
+        self.kill_line
+    }
+
+    /// Center of the neck and its half width, in world units.
+    pub fn neck(&self) -> (Vec2, f32) {
+        (self.neck, self.neck_half_width)
+    }
+}
@@ -0,0 +1,1311 @@
+use std::fmt;
+use std::ops::Range;
+use std::time::Duration;
+use glam::Vec2;
+use crate::grid::grid::Grid;
+use crate::particles::particle_initializer::InitialLayout;
+use crate::particles::particle_system::{ParticleLimit, ParticleSystem, SpawnMass, SpawnReport, BOUNDARY_MATERIAL_CHANNEL, HELD_FLAG, LIFETIME_CHANNEL, MASS_CHANNEL, PARTICLE_FLAGS_CHANNEL, PINNED_FLAG, SLEEPING_FLAG};
+use crate::particles::particle_channels::ChannelId;
+use crate::particles::particle_group::GroupOperation;
+use crate::particles::particle_interaction::InteractionMode;
+use crate::particles::particle_color_kernel::ParticleColorSettings;
+use crate::particles::region_query::{QueryShape, RegionQuery};
+use crate::particles::color_palette::ColorPalette;
+use crate::particles::particle_shape::{ParticleShape, SPRITE_FRAME_CHANNEL};
+use crate::particles::sprite_animation::SpriteAnimation;
+use crate::physics::collision_system::{BroadphaseMode, CollisionFilter, CollisionSystem, StaticSegment, COLLISION_FILTER_CHANNEL, RESTITUTION_CHANNEL};
+use crate::physics::fluid_solver::{FluidSolver, SimulationMode};
+use crate::physics::force_kernel::{record_force_kernels, ForceKernel, ForceKernelDescriptor, ForceKernelId};
+use crate::physics::frame_capture::{self, FrameCapture};
+use crate::physics::frame_graph::FrameGraph;
+use crate::physics::heat_diffusion::{HeatBrush, HeatConfig, HeatDiffusion, TEMPERATURE_CHANNEL};
+use crate::physics::kill_volumes::{KillVolume, KillVolumes};
+use crate::physics::pass_validation::{PassValidator, PhysicsPass};
+use crate::physics::particle_sleep::{ParticleSleep, SleepConfig, SLEEP_CHANNEL};
+use crate::physics::spring_constraints::{self, Spring, SpringBody, SpringConstraints, NO_HANDLE, SPRING_HANDLE_CHANNEL};
+use crate::physics::static_colliders::StaticCircle;
+use crate::physics::trajectory::TrajectoryRecorder;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::simulation_config::{BoundaryMaterial, BoundaryMode, SimulationConfig};
+use crate::utils::profiler::GpuProfiler;
+use crate::utils::step_accumulator::StepAccumulator;
+use crate::utils::telemetry::{GpuStepTimer, RefreshTimer, RefreshTiming};
+
+pub const DIMENSION: u32 = 2;
+/// Steps between two removals of the dead particles, when lifetimes are enabled. Each one reads a count back.
+pub const COMPACTION_INTERVAL_STEPS: u64 = 30;
+
+/// Health of the simulation, for host applications to display or log.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SimulationStats {
+    /// Sum of the delta times of every step, in seconds.
+    pub simulated_time: f64,
+    pub step_count: u64,
+    pub particle_count: usize,
+    /// Collision cells (cells with more than one object) found by the last step.
+    pub active_collision_cells: u32,
+    /// GPU time of the last measured step. `None` if the device has no timestamp queries
+    /// or no step was measured yet; it lags a frame or two behind.
+    pub last_step_gpu_time: Option<Duration>,
+}
+
+impl fmt::Display for SimulationStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} steps, {:.2} s simulated, {} particles, {} collision cells",
+            self.step_count, self.simulated_time, self.particle_count, self.active_collision_cells
+        )?;
+        if let Some(gpu_time) = self.last_step_gpu_time {
+            write!(f, ", last step {:.3} ms GPU", gpu_time.as_secs_f64() * 1000.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// The physics pipeline: particles, the grid and the collision system, stepped together.
+pub struct Simulation {
+    particles: ParticleSystem,
+    grid: Grid,
+    collision_system: CollisionSystem,
+    force_kernels: Vec<ForceKernel>,
+    simulated_time: f64,
+    step_count: u64,
+    step_timer: Option<GpuStepTimer>,
+    last_step_gpu_time: Option<Duration>,
+    pass_validator: Option<PassValidator>,
+    /// `simulated_time` of the last removal of the dead particles
+    last_compaction_time: f64,
+    kill_volumes: Option<KillVolumes>, // Created by the first add_kill_volume
+    springs: Option<SpringConstraints>, // Created by the first spring body
+    fluid_solver: Option<FluidSolver>, // Only in SimulationMode::Fluid
+    heat: Option<HeatDiffusion>, // Set by enable_heat
+    sleep: Option<ParticleSleep>, // Set by enable_sleep
+    region_query: Option<RegionQuery>, // Created by the first query
+    config: SimulationConfig,
+    step_accumulator: StepAccumulator,
+}
+
+impl Simulation {
+    /// Creates the particles, the grid and the collision system. Needs no window nor camera, so it also runs
+    /// headless, e.g. with `WgpuContext::new_for_test`; the drawers are attached by the windowed `State`.
+    /// The world is `world_size` world units wide, the rest of the config is the default one.
+    pub fn new(wgpu_context: &WgpuContext, world_size: Vec2, layout: InitialLayout) -> Self {
+        let config = SimulationConfig::default();
+        let config = SimulationConfig { world_size: config.units.size_to_meters(world_size), ..config };
+        Self::with_config(wgpu_context, config, layout)
+    }
+
+    /// Like `new`, with the initial particles, the world size and the physical parameters of `config`.
+    /// Panics if `config` does not pass `SimulationConfig::validate`.
+    pub fn with_config(wgpu_context: &WgpuContext, config: SimulationConfig, layout: InitialLayout) -> Self {
+        if let Err(error) = config.validate() {
+            panic!("invalid simulation config: {}", error);
+        }
+        let particles = ParticleSystem::new(wgpu_context, &config, layout);
+        let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+
+        #[allow(unused_mut)]
+        let mut collision_system = CollisionSystem::new(wgpu_context, DIMENSION, &particles, &grid);
+        #[cfg(debug_assertions)]
+        collision_system.set_color_validation(wgpu_context, &particles, &grid, true);
+
+        let mut simulation = Self::from_parts(wgpu_context, particles, grid, collision_system);
+        simulation.set_config(config);
+        #[cfg(debug_assertions)]
+        simulation.set_pass_validation(true);
+        simulation
+    }
+
+    /// Builds a simulation from already created parts, e.g. particles from test buffers.
+    pub fn from_parts(wgpu_context: &WgpuContext, particles: ParticleSystem, mut grid: Grid, collision_system: CollisionSystem) -> Self {
+        grid.set_origin(particles.get_world_origin());
+        Self {
+            particles,
+            grid,
+            collision_system,
+            force_kernels: Vec::new(),
+            simulated_time: 0.0,
+            step_count: 0,
+            step_timer: GpuStepTimer::new(wgpu_context),
+            last_step_gpu_time: None,
+            pass_validator: None,
+            last_compaction_time: 0.0,
+            kill_volumes: None,
+            springs: None,
+            fluid_solver: None,
+            heat: None,
+            sleep: None,
+            region_query: None,
+            config: SimulationConfig::default(),
+            step_accumulator: StepAccumulator::new(),
+        }
+    }
+
+    /// Sets the physical parameters, converted to world units: the gravity of the integration, the
+    /// radii of the spawned particles, the sort interval and the sort disorder threshold. The default config has no
+    /// gravity and spawns 1 to 3 units wide particles. The initial particles and the world size of `config` are ignored, see `with_config`.
+    pub fn set_config(&mut self, config: SimulationConfig) {
+        self.particles.set_forces(config.world_forces());
+        self.particles.set_gravity_mode(config.world_gravity_mode());
+        self.particles.set_boundary(config.world_boundary());
+        self.particles.set_boundary_mode(config.boundary_mode);
+        self.particles.set_boundary_material(config.boundary_material);
+        let (min_radius, max_radius) = config.world_spawn_radius_range();
+        self.particles.set_spawn_radius_range(min_radius, max_radius);
+        self.particles.set_sort_interval(config.sort_interval);
+        self.particles.set_sort_disorder_threshold(config.sort_disorder_threshold);
+        if config.palette != self.particles.palette().map(ColorPalette::theme) {
+            self.particles.set_palette(config.palette.map(ColorPalette::generate));
+        }
+        if config.timestep != self.config.timestep {
+            self.step_accumulator.reset();
+        }
+        self.config = config;
+        self.refresh_period();
+    }
+
+    /// Wraps the grid and the collision solver around the world when it wraps, see `Grid::set_period`. The cell
+    /// ranges, the fluid solver, the heat and the sleep find the cells of a particle from the cell size, so their grid
+    /// does not wrap and the particles do not meet across the seam.
+    fn refresh_period(&mut self) {
+        let joined = self.config.boundary_mode == BoundaryMode::Wrap
+            && self.fluid_solver.is_none()
+            && self.heat.is_none()
+            && self.sleep.is_none()
+            && self.collision_system.broadphase_mode() != BroadphaseMode::CellRanges;
+        let period = joined.then(|| self.particles.get_world_size());
+        self.grid.set_period(period);
+        self.collision_system.set_period(period);
+    }
+
+    pub fn config(&self) -> &SimulationConfig {
+        &self.config
+    }
+
+    /// Uniform gravity in m/s², see `SimulationConfig::gravity`. Keeps the rest of the config.
+    pub fn set_gravity(&mut self, gravity: Vec2) {
+        self.set_config(SimulationConfig { gravity, ..self.config });
+    }
+
+    pub fn gravity(&self) -> Vec2 {
+        self.config.gravity
+    }
+
+    /// In m/s², see `SimulationConfig::wind`.
+    pub fn set_wind(&mut self, wind: Vec2) {
+        self.set_config(SimulationConfig { wind, ..self.config });
+    }
+
+    /// Closes, wraps or opens the edges of the world, see `SimulationConfig::boundary_mode`. A periodic world
+    /// is only joined across the seam by the collision cells of `BroadphaseMode::CollisionCells` and
+    /// `BroadphaseMode::SpatialHash`: the cell ranges, the fluid mode and the springs do not look past the edges.
+    pub fn set_boundary_mode(&mut self, boundary_mode: BoundaryMode) {
+        self.set_config(SimulationConfig { boundary_mode, ..self.config });
+    }
+
+    pub fn boundary_mode(&self) -> BoundaryMode {
+        self.config.boundary_mode
+    }
+
+    /// In 1/s, see `SimulationConfig::linear_drag`.
+    pub fn set_linear_drag(&mut self, linear_drag: f32) {
+        self.set_config(SimulationConfig { linear_drag, ..self.config });
+    }
+
+    /// Advances the physics by `delta_time`: periodic sort, grid, collisions, heat, sleep, user forces and integration.
+    /// Every pass is recorded into one `FrameGraph` and submitted once, the compaction flushes it before reading back.
+    /// `label` wraps the grid work in a debug group, so the step can be found in GPU captures.
+    pub fn step(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, delta_time: f32, label: Option<&str>) {
+        let mut frame_graph = FrameGraph::new(wgpu_context, "Physics step encoder");
+        self.record_step(wgpu_context, &mut frame_graph, gpu_profiler, delta_time, label);
+        frame_graph.submit(wgpu_context, gpu_profiler);
+        self.after_submit(wgpu_context);
+    }
+
+    /// Records a `step` into `frame_graph` without submitting it, so the steps and the per-frame work of a whole
+    /// frame share one encoder and one submission. `after_submit` must follow the submit of `frame_graph`.
+    pub fn record_step(&mut self, wgpu_context: &WgpuContext, frame_graph: &mut FrameGraph, gpu_profiler: &mut GpuProfiler, delta_time: f32, label: Option<&str>) {
+        let first_pass = frame_graph.passes().len();
+        self.begin_step(wgpu_context, frame_graph, gpu_profiler, label);
+        self.grid.register_passes(frame_graph, gpu_profiler);
+        if label.is_some() {
+            frame_graph.encoder().pop_debug_group();
+        }
+        match &self.fluid_solver {
+            None => self.collision_system.register_passes(wgpu_context, frame_graph, gpu_profiler),
+            Some(fluid_solver) => {
+                // The fluid forces take the place of the collision cells and their solve
+                frame_graph.add_pass(PhysicsPass::BuildCollisionCells, gpu_profiler, |encoder, gpu_profiler| {
+                    fluid_solver.find_cell_boundaries(encoder, gpu_profiler);
+                });
+                frame_graph.add_pass(PhysicsPass::SolveCollisions, gpu_profiler, |encoder, gpu_profiler| {
+                    fluid_solver.record_forces(encoder, gpu_profiler, self.particles.len() as u32, delta_time);
+                    self.collision_system.record_static_collisions(encoder, gpu_profiler);
+                });
+            }
+        }
+        if let Some(heat) = &self.heat {
+            let brush = self.interaction_heat_brush().or(heat.brush());
+            frame_graph.add_pass(PhysicsPass::HeatDiffusion, gpu_profiler, |encoder, gpu_profiler| {
+                heat.record(encoder, gpu_profiler, self.particles.len() as u32, delta_time, self.particles.gravity(), brush);
+            });
+        }
+        if let Some(sleep) = &self.sleep {
+            frame_graph.add_pass(PhysicsPass::Sleep, gpu_profiler, |encoder, gpu_profiler| {
+                sleep.record(encoder, gpu_profiler, self.particles.len() as u32, delta_time);
+            });
+        }
+        if self.force_kernels.iter().any(ForceKernel::is_enabled) {
+            frame_graph.add_pass(PhysicsPass::ForceKernels, gpu_profiler, |encoder, gpu_profiler| {
+                record_force_kernels(encoder, &self.force_kernels, &self.particles, gpu_profiler, delta_time);
+            });
+        }
+        frame_graph.add_pass(PhysicsPass::Integration, gpu_profiler, |encoder, gpu_profiler| {
+            self.particles.record_update_positions(delta_time, wgpu_context, encoder, gpu_profiler);
+        });
+        if let Some(springs) = self.springs.as_ref().filter(|springs| !springs.is_empty()) {
+            frame_graph.add_pass(PhysicsPass::SpringConstraints, gpu_profiler, |encoder, gpu_profiler| {
+                springs.solve(wgpu_context, encoder, gpu_profiler, &self.particles);
+            });
+        }
+        if self.removes_particles() && (self.step_count + 1).is_multiple_of(COMPACTION_INTERVAL_STEPS) {
+            self.compact_particles(wgpu_context, frame_graph, gpu_profiler, delta_time);
+        }
+        let required = self.required_passes();
+        for &pass in &frame_graph.passes()[first_pass..] {
+            self.record_pass(pass);
+        }
+        self.finish_pass_validation(&required);
+        if let Some(step_timer) = &mut self.step_timer {
+            step_timer.record_finish(frame_graph.encoder());
+        }
+        self.end_step(delta_time);
+    }
+
+    /// Starts the readbacks recorded by the steps of a submitted `FrameGraph`: contact statistics, GPU step time
+    /// and sort disorder, and polls the step time of an earlier frame.
+    pub fn after_submit(&mut self, wgpu_context: &WgpuContext) {
+        self.collision_system.after_submit();
+        self.particles.map_sort_disorder(wgpu_context);
+        if let Some(step_timer) = &mut self.step_timer {
+            step_timer.after_submit();
+            if let Some(gpu_time) = step_timer.poll(wgpu_context) {
+                self.last_step_gpu_time = Some(gpu_time);
+            }
+        }
+    }
+
+    /// Advances the physics by the time of a frame, following `SimulationConfig::timestep`: the frame time is
+    /// accumulated and every completed fixed step runs its substeps. Without a fixed timestep, steps once by
+    /// `frame_time`. Returns the number of `step`s run, zero when the frame was shorter than the remaining step time.
+    /// The steps are submitted together, see `record_advance`.
+    pub fn advance(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, frame_time: f32, label: Option<&str>) -> u32 {
+        let mut frame_graph = FrameGraph::new(wgpu_context, "Physics frame encoder");
+        let steps = self.record_advance(wgpu_context, &mut frame_graph, gpu_profiler, frame_time, label);
+        frame_graph.submit(wgpu_context, gpu_profiler);
+        self.after_submit(wgpu_context);
+        steps
+    }
+
+    /// Records the steps of `advance` into `frame_graph`, see `record_step`.
+    pub fn record_advance(&mut self, wgpu_context: &WgpuContext, frame_graph: &mut FrameGraph, gpu_profiler: &mut GpuProfiler, frame_time: f32, label: Option<&str>) -> u32 {
+        let Some(timestep) = self.config.timestep else {
+            self.record_step(wgpu_context, frame_graph, gpu_profiler, frame_time, label);
+            return 1;
+        };
+        let steps = self.step_accumulator.take_steps(frame_time, &timestep) * timestep.substeps.max(1);
+        for i in 0..steps {
+            self.record_step(wgpu_context, frame_graph, gpu_profiler, timestep.substep_time(), if i == 0 { label } else { None });
+        }
+        steps
+    }
+
+    /// Same as `step`, but runs every stage separately and reads back its buffers. Very slow.
+    /// The springs are not enforced.
+    pub fn capture_step(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, delta_time: f32, label: Option<&str>) -> FrameCapture {
+        let mut frame_graph = FrameGraph::new(wgpu_context, "Physics step encoder");
+        self.begin_step(wgpu_context, &mut frame_graph, gpu_profiler, label);
+        if label.is_some() {
+            frame_graph.encoder().pop_debug_group();
+        }
+        for &pass in frame_graph.passes() {
+            self.record_pass(pass);
+        }
+        frame_graph.submit(wgpu_context, gpu_profiler);
+        self.particles.map_sort_disorder(wgpu_context);
+        let capture = frame_capture::capture_physics_step(wgpu_context, &mut self.particles, &mut self.grid, &mut self.collision_system, &self.force_kernels, gpu_profiler, delta_time);
+        // The capture runs the stages on its own
+        self.finish_pass_validation(&[]);
+        self.end_step(delta_time);
+        if let Some(step_timer) = &mut self.step_timer {
+            step_timer.finish(wgpu_context);
+            if let Some(gpu_time) = step_timer.poll(wgpu_context) {
+                self.last_step_gpu_time = Some(gpu_time);
+            }
+        }
+        capture
+    }
+
+    fn begin_step(&mut self, wgpu_context: &WgpuContext, frame_graph: &mut FrameGraph, gpu_profiler: &GpuProfiler, label: Option<&str>) {
+        if let Some(step_timer) = &mut self.step_timer {
+            step_timer.start(frame_graph.encoder());
+        }
+        if let Some(label) = label {
+            frame_graph.encoder().push_debug_group(label);
+        }
+        if !self.grid.is_indirect_dispatch() {
+            self.particles.poll_sort_disorder(wgpu_context);
+            let cell_size = self.grid.cell_size();
+            if self.particles.is_it_time_to_sort() {
+                frame_graph.add_pass(PhysicsPass::ParticleSort, gpu_profiler, |encoder, gpu_profiler| {
+                    self.particles.sort_by_cell_id(encoder, gpu_profiler, cell_size);
+                });
+                self.particles.reset_last_sort_time();
+            }
+            else if self.particles.sort_disorder_threshold().is_some() {
+                // Read back by a later step, see poll_sort_disorder
+                frame_graph.add_pass(PhysicsPass::ParticleSort, gpu_profiler, |encoder, gpu_profiler| {
+                    self.particles.measure_sort_disorder(encoder, gpu_profiler, cell_size);
+                });
+            }
+        }
+    }
+
+    fn end_step(&mut self, delta_time: f32) {
+        self.simulated_time += delta_time as f64;
+        self.step_count += 1;
+    }
+
+    fn record_pass(&mut self, pass: PhysicsPass) {
+        if let Some(pass_validator) = &mut self.pass_validator
+            && let Err(error) = pass_validator.record(pass) {
+            panic!("Invalid simulation step: {}", error);
+        }
+    }
+
+    /// Ends the step of the pass validation: every pass of `required` must have been encoded.
+    fn finish_pass_validation(&mut self, required: &[PhysicsPass]) {
+        if let Some(pass_validator) = &mut self.pass_validator
+            && let Err(error) = pass_validator.finish_step(required) {
+            panic!("Invalid simulation step: {}", error);
+        }
+    }
+
+    /// Passes every `step` must encode.
+    fn required_passes(&self) -> Vec<PhysicsPass> {
+        let mut required = vec![PhysicsPass::BuildCellIds, PhysicsPass::SortMap, PhysicsPass::BuildCollisionCells, PhysicsPass::SolveCollisions, PhysicsPass::Integration];
+        if self.grid.is_indirect_dispatch() {
+            required.push(PhysicsPass::PrepareGridDispatch);
+        }
+        required
+    }
+
+    /// Spawns a batch of particles around `position` and refreshes everything bound to the particle buffers.
+    /// Returns what happened to the batch under the particle limit and the time spent in each refresh.
+    /// The cell size may change, a drawn grid needs `Grid::refresh_drawer`.
+    pub fn add_particles(&mut self, wgpu_context: &WgpuContext, position: Vec2) -> (SpawnReport, Vec<(&'static str, RefreshTiming)>) {
+        let prev_grid_capacity = self.grid.capacity();
+        let report = self.particles.add_particles(&position, wgpu_context);
+        self.finish_spawn(wgpu_context, report, prev_grid_capacity)
+    }
+
+    /// Spawns one particle at each of `positions`, see `ParticleSystem::add_particles_at`.
+    pub fn add_particles_at(&mut self, wgpu_context: &WgpuContext, positions: &[Vec2]) -> (SpawnReport, Vec<(&'static str, RefreshTiming)>) {
+        let prev_grid_capacity = self.grid.capacity();
+        let report = self.particles.add_particles_at(positions, wgpu_context);
+        self.finish_spawn(wgpu_context, report, prev_grid_capacity)
+    }
+
+    /// Spawns `count` particles spread over the whole world, e.g. to scale the scene up at runtime.
+    /// If the batch does not fit, the buffers are grown for one more batch first, so the next one writes in place.
+    pub fn add_particle_batch(&mut self, wgpu_context: &WgpuContext, count: usize) -> (SpawnReport, Vec<(&'static str, RefreshTiming)>) {
+        let num_particles = self.particles.len();
+        if num_particles + count > self.particles.capacity() {
+            self.reserve_particles(wgpu_context, (num_particles + 2 * count).min(self.particles.limit().max_particles));
+        }
+        let (_, max_radius) = self.particles.spawn_radius_range();
+        // Inside the walls
+        let region_min = self.particles.get_world_origin() + max_radius;
+        let region_max = self.particles.get_world_origin() + self.particles.get_world_size() - max_radius;
+        let positions = InitialLayout::Random.generate(region_min, region_max, max_radius, count);
+        self.add_particles_at(wgpu_context, &positions)
+    }
+
+    /// Removes the `count` last particles, or every particle if there are fewer. Returns how many were removed.
+    /// The buffers keep their size, spawning the particles again does not grow them.
+    pub fn remove_particle_batch(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, count: usize) -> usize {
+        let num_particles = self.particles.len() as u32;
+        let ids: Vec<u32> = (num_particles.saturating_sub(count as u32)..num_particles).collect();
+        if ids.is_empty() {
+            return 0;
+        }
+        self.apply_group_operation(wgpu_context, gpu_profiler, &ids, GroupOperation::Remove)
+    }
+
+    /// Grows the particle buffers so `capacity` particles fit, see `ParticleSystem::reserve`, and rebinds the
+    /// grid, the collision system and the force kernels if they were recreated.
+    pub fn reserve_particles(&mut self, wgpu_context: &WgpuContext, capacity: usize) {
+        if self.particles.reserve(wgpu_context, capacity) {
+            self.grid.refresh_grid(wgpu_context, &self.particles);
+            self.refresh_particle_bindings(wgpu_context);
+        }
+    }
+
+    /// Makes the grid, the collision system and the force kernels follow the spawned particles.
+    fn finish_spawn(&mut self, wgpu_context: &WgpuContext, report: SpawnReport, prev_grid_capacity: usize) -> (SpawnReport, Vec<(&'static str, RefreshTiming)>) {
+        if report.spawned == 0 {
+            // Recycled particles keep their slots, nothing to refresh
+            return (report, Vec::new());
+        }
+        let mut refreshes = self.particles.last_refresh_timings().to_vec();
+
+        let grid_timer = RefreshTimer::start(wgpu_context);
+        self.grid.refresh_grid(wgpu_context, &self.particles);
+        refreshes.push(("Grid refresh", grid_timer.finish(wgpu_context)));
+
+        let collision_timer = RefreshTimer::start(wgpu_context);
+        // Spawned particles first reuse the slots of the removed ones
+        self.collision_system.refresh(wgpu_context, &self.particles, &self.grid, self.grid.capacity() - prev_grid_capacity);
+        if let Some(fluid_solver) = self.fluid_solver.as_mut() {
+            fluid_solver.refresh_buffers(wgpu_context, &self.particles, &self.grid);
+        }
+        if let Some(heat) = self.heat.as_mut() {
+            heat.refresh_buffers(wgpu_context, &self.particles, &self.grid);
+        }
+        if let Some(sleep) = self.sleep.as_mut() {
+            sleep.refresh_buffers(wgpu_context, &self.particles, &self.grid);
+        }
+        refreshes.push(("Collision system refresh", collision_timer.finish(wgpu_context)));
+
+        if !self.force_kernels.is_empty() {
+            let force_timer = RefreshTimer::start(wgpu_context);
+            self.refresh_force_kernels(wgpu_context);
+            refreshes.push(("Force kernels refresh", force_timer.finish(wgpu_context)));
+        }
+
+        (report, refreshes)
+    }
+
+    /// Caps the number of particles, see `ParticleLimit`.
+    pub fn set_particle_limit(&mut self, wgpu_context: &WgpuContext, limit: ParticleLimit) {
+        self.particles.set_limit(wgpu_context, limit);
+        // Recycling may register a channel
+        self.refresh_particle_bindings(wgpu_context);
+    }
+
+    /// Gives every particle its own restitution, stored in the `RESTITUTION_CHANNEL` channel and
+    /// blended per contact with `SolverConfig::restitution_combine`. Existing particles get `default_restitution`.
+    /// Does nothing if restitution is already enabled.
+    pub fn enable_restitution(&mut self, wgpu_context: &WgpuContext, default_restitution: f32) -> ChannelId {
+        if let Some(id) = self.particles.channels().find(RESTITUTION_CHANNEL) {
+            return id;
+        }
+        let id = self.particles.register_channel(wgpu_context, RESTITUTION_CHANNEL, &[default_restitution.to_bits()]);
+        self.refresh_particle_bindings(wgpu_context);
+        id
+    }
+
+    /// Restitution of the particles spawned from now on. Enables restitution, with this value for
+    /// the existing particles, if it was not enabled yet.
+    pub fn set_spawn_restitution(&mut self, wgpu_context: &WgpuContext, restitution: f32) {
+        let id = self.enable_restitution(wgpu_context, restitution);
+        self.particles.set_spawn_channel_value(id, &[restitution.to_bits()]);
+    }
+
+    /// Gives every particle its own mass, see `ParticleSystem::enable_mass`: the contacts and the springs are then
+    /// corrected in inverse proportion to the masses. Does nothing if the masses are already enabled.
+    pub fn enable_mass(&mut self, wgpu_context: &WgpuContext) -> ChannelId {
+        if let Some(id) = self.particles.channels().find(MASS_CHANNEL) {
+            return id;
+        }
+        let id = self.particles.enable_mass(wgpu_context);
+        self.refresh_particle_bindings(wgpu_context);
+        id
+    }
+
+    /// Mass of the particles spawned from now on. Enables the masses if needed, the existing particles then get
+    /// the mass `spawn_mass` gives them. Panics if the mass or density is not positive.
+    pub fn set_spawn_mass(&mut self, wgpu_context: &WgpuContext, spawn_mass: SpawnMass) {
+        let (SpawnMass::Fixed(value) | SpawnMass::Density(value)) = spawn_mass;
+        assert!(value > 0.0 && value.is_finite(), "Invalid spawn mass {:?}", spawn_mass);
+        self.particles.set_spawn_mass(spawn_mass);
+        self.enable_mass(wgpu_context);
+    }
+
+    /// Gives the particles in `range` the mass, enabling the masses if needed. Indices are the ones of the current
+    /// order, the mass moves with the particles when they are sorted. Panics if the mass is not positive.
+    pub fn set_particle_mass(&mut self, wgpu_context: &WgpuContext, range: Range<usize>, mass: f32) {
+        assert!(mass > 0.0 && mass.is_finite(), "Invalid particle mass {}", mass);
+        let id = self.enable_mass(wgpu_context);
+        let range = range.start.min(self.particles.len())..range.end.min(self.particles.len());
+        let words = vec![mass.to_bits(); range.len()];
+        self.particles.write_channel_from(wgpu_context, id, range.start, &words);
+    }
+
+    /// Gives every particle a `CollisionFilter`, stored in the `COLLISION_FILTER_CHANNEL` channel and checked by the
+    /// solver before it resolves a pair. Existing particles get `CollisionFilter::DEFAULT`, so they keep colliding with
+    /// each other. Does nothing if the filters are already enabled.
+    pub fn enable_collision_filters(&mut self, wgpu_context: &WgpuContext) -> ChannelId {
+        if let Some(id) = self.particles.channels().find(COLLISION_FILTER_CHANNEL) {
+            return id;
+        }
+        let id = self.particles.register_channel(wgpu_context, COLLISION_FILTER_CHANNEL, &CollisionFilter::DEFAULT.to_words());
+        self.refresh_particle_bindings(wgpu_context);
+        id
+    }
+
+    /// Collision filter of the particles spawned from now on, enabling the filters if needed.
+    pub fn set_spawn_collision_filter(&mut self, wgpu_context: &WgpuContext, filter: CollisionFilter) {
+        let id = self.enable_collision_filters(wgpu_context);
+        self.particles.set_spawn_channel_value(id, &filter.to_words());
+    }
+
+    /// Gives the particles in `range` the collision filter, enabling the filters if needed. Indices are the ones
+    /// of the current order, the filter moves with the particles when they are sorted.
+    pub fn set_collision_filter(&mut self, wgpu_context: &WgpuContext, range: Range<usize>, filter: CollisionFilter) {
+        let id = self.enable_collision_filters(wgpu_context);
+        let range = range.start.min(self.particles.len())..range.end.min(self.particles.len());
+        let words: Vec<u32> = range.clone().flat_map(|_| filter.to_words()).collect();
+        self.particles.write_channel_from(wgpu_context, id, range.start, &words);
+    }
+
+    /// Gives every particle an index into the boundary material table, see `ParticleSystem::enable_boundary_materials`.
+    /// Does nothing if the materials are already enabled.
+    pub fn enable_boundary_materials(&mut self, wgpu_context: &WgpuContext) -> ChannelId {
+        if let Some(id) = self.particles.channels().find(BOUNDARY_MATERIAL_CHANNEL) {
+            return id;
+        }
+        let id = self.particles.enable_boundary_materials(wgpu_context);
+        self.refresh_particle_bindings(wgpu_context);
+        id
+    }
+
+    /// Table of the materials the particles bounce off the world boundary with, enabling the material channel
+    /// if needed. A particle with an index past the table uses `SimulationConfig::boundary_material`.
+    pub fn set_boundary_materials(&mut self, wgpu_context: &WgpuContext, materials: &[BoundaryMaterial]) {
+        self.enable_boundary_materials(wgpu_context);
+        self.particles.set_boundary_materials(wgpu_context, materials);
+    }
+
+    /// Index in the boundary material table of the particles spawned from now on, `NO_MATERIAL` for the default one.
+    pub fn set_spawn_boundary_material(&mut self, wgpu_context: &WgpuContext, material_index: u32) {
+        let id = self.enable_boundary_materials(wgpu_context);
+        self.particles.set_spawn_channel_value(id, &[material_index]);
+    }
+
+    /// Gives every particle a lifetime in seconds, see `ParticleSystem::enable_lifetime`. `step` then removes
+    /// the dead particles every `COMPACTION_INTERVAL_STEPS` steps.
+    pub fn enable_lifetime(&mut self, wgpu_context: &WgpuContext) -> ChannelId {
+        if let Some(id) = self.particles.channels().find(LIFETIME_CHANNEL) {
+            return id;
+        }
+        let id = self.particles.enable_lifetime(wgpu_context);
+        self.last_compaction_time = self.simulated_time;
+        self.refresh_particle_bindings(wgpu_context);
+        id
+    }
+
+    /// Lifetime of the particles spawned from now on, in seconds. Enables lifetimes if needed.
+    pub fn set_spawn_lifetime(&mut self, wgpu_context: &WgpuContext, lifetime: f32) {
+        let id = self.enable_lifetime(wgpu_context);
+        self.particles.set_spawn_channel_value(id, &[lifetime.to_bits()]);
+    }
+
+    /// Gives every particle an atlas frame, see `ParticleSystem::enable_sprite_frames`.
+    pub fn enable_sprite_frames(&mut self, wgpu_context: &WgpuContext) -> ChannelId {
+        if let Some(id) = self.particles.channels().find(SPRITE_FRAME_CHANNEL) {
+            return id;
+        }
+        let id = self.particles.enable_sprite_frames(wgpu_context);
+        self.refresh_particle_bindings(wgpu_context);
+        id
+    }
+
+    /// Shape the particles are drawn with. `ParticleShape::Sprite` enables the sprite frames if needed,
+    /// the atlas is set with `ParticleSystem::set_sprite_atlas`.
+    pub fn set_particle_shape(&mut self, wgpu_context: &WgpuContext, shape: ParticleShape) {
+        if shape == ParticleShape::Sprite {
+            self.enable_sprite_frames(wgpu_context);
+        }
+        self.particles.set_shape(shape);
+    }
+
+    /// Animates the sprite frames, see `ParticleSystem::set_sprite_animation`. Registering the channels keeps
+    /// the collision system and the force kernels bound.
+    pub fn set_sprite_animation(&mut self, wgpu_context: &WgpuContext, animation: Option<SpriteAnimation>) {
+        let num_channels = self.particles.channels().len();
+        self.particles.set_sprite_animation(wgpu_context, animation);
+        if self.particles.channels().len() != num_channels {
+            self.refresh_particle_bindings(wgpu_context);
+        }
+    }
+
+    /// Advances the sprite animation, once per frame after the steps. `delta_time` is the simulated time of the
+    /// frame, `step_delta_time` the one of the last step.
+    pub fn update_sprite_animation(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, delta_time: f32, step_delta_time: f32) {
+        self.particles.update_sprite_animation(wgpu_context, gpu_profiler, delta_time, step_delta_time);
+    }
+
+    /// Records `update_sprite_animation` into `encoder`, e.g. the one of the frame after `record_advance`.
+    pub fn record_sprite_animation(&mut self, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler, delta_time: f32, step_delta_time: f32) {
+        self.particles.record_update_sprite_animation(encoder, gpu_profiler, delta_time, step_delta_time);
+    }
+
+    /// Keeps the last `length` positions of every particle, see `ParticleSystem::set_trails`. Registering the
+    /// slot channel keeps the collision system and the force kernels bound.
+    pub fn set_trails(&mut self, wgpu_context: &WgpuContext, length: Option<u32>) {
+        let num_channels = self.particles.channels().len();
+        self.particles.set_trails(wgpu_context, length);
+        if self.particles.channels().len() != num_channels {
+            self.refresh_particle_bindings(wgpu_context);
+        }
+    }
+
+    pub fn trail_length(&self) -> Option<u32> {
+        self.particles.trail_length()
+    }
+
+    /// Appends the current positions to the trails, once per frame after the steps.
+    pub fn update_trails(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler) {
+        self.particles.update_trails(wgpu_context, gpu_profiler);
+    }
+
+    /// Records `update_trails` into `encoder`.
+    pub fn record_trails(&mut self, wgpu_context: &WgpuContext, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler) {
+        self.particles.record_update_trails(wgpu_context, encoder, gpu_profiler);
+    }
+
+    /// Removes the expired, out-of-world and killed particles now, see `ParticleSystem::remove_dead_particles` and `add_kill_volume`,
+    /// and shrinks the grid to the survivors. Returns the number of removed particles.
+    pub fn remove_dead_particles(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler) -> usize {
+        self.compact_now(wgpu_context, gpu_profiler)
+    }
+
+    /// Indices of the particles whose center is within `radius` of `center`, in increasing order.
+    /// Found with the grid of the last step, see `RegionQuery`. Waits for the GPU.
+    pub fn query_circle(&mut self, wgpu_context: &WgpuContext, center: Vec2, radius: f32) -> Vec<u32> {
+        self.query(wgpu_context, &QueryShape::Circle { center, radius })
+    }
+
+    /// Indices of the particles whose center is in the rectangle between two opposite corners, like `query_circle`.
+    pub fn query_rect(&mut self, wgpu_context: &WgpuContext, corner: Vec2, opposite_corner: Vec2) -> Vec<u32> {
+        self.query(wgpu_context, &QueryShape::rect(corner, opposite_corner))
+    }
+
+    pub fn query(&mut self, wgpu_context: &WgpuContext, shape: &QueryShape) -> Vec<u32> {
+        let region_query = self.region_query.get_or_insert_with(|| RegionQuery::new(wgpu_context));
+        region_query.query(wgpu_context, &self.particles, &self.grid, shape)
+    }
+
+    /// Gives every particle the flags of `ParticleSystem::enable_particle_flags`. Does nothing if they are already enabled.
+    pub fn enable_particle_flags(&mut self, wgpu_context: &WgpuContext) -> ChannelId {
+        if let Some(id) = self.particles.channels().find(PARTICLE_FLAGS_CHANNEL) {
+            return id;
+        }
+        let id = self.particles.enable_particle_flags(wgpu_context);
+        self.refresh_particle_bindings(wgpu_context);
+        id
+    }
+
+    /// Pins the particles in `range`: the integration leaves them in place, the collisions and the springs do not
+    /// move them, and the particles they touch take the whole correction. For anchors and static obstacles made of
+    /// particles. Indices are the ones of the current order, the flag moves with the particles when they are sorted.
+    pub fn pin_particles(&mut self, wgpu_context: &WgpuContext, range: Range<usize>) {
+        self.enable_particle_flags(wgpu_context);
+        self.particles.set_particle_flag(wgpu_context, range, PINNED_FLAG, true);
+    }
+
+    /// Lets the particles in `range` move again, from rest.
+    pub fn unpin_particles(&mut self, wgpu_context: &WgpuContext, range: Range<usize>) {
+        self.enable_particle_flags(wgpu_context);
+        self.particles.set_particle_flag(wgpu_context, range, PINNED_FLAG, false);
+    }
+
+    /// Picks the particle whose center is nearest to `position`, within the largest particle radius, for
+    /// `InteractionMode::Pick`: it gets the `HELD_FLAG` and follows the cursor, at its current offset from
+    /// `position`, while the interaction is active. Returns its index, `None` if no particle was close enough.
+    /// Found with `query_circle`, so it waits for the GPU.
+    pub fn pick_particle(&mut self, wgpu_context: &WgpuContext, position: Vec2) -> Option<u32> {
+        let candidates = self.query_circle(wgpu_context, position, self.particles.get_max_radius());
+        let (&first, &last) = (candidates.first()?, candidates.last()?);
+        // The candidates are sorted, so their positions are one range
+        let positions = self.particles.download_positions(wgpu_context, first as usize..last as usize + 1);
+        let (particle, particle_position) = candidates.iter()
+            .map(|&particle| (particle, positions[(particle - first) as usize]))
+            .min_by(|a, b| a.1.distance_squared(position).total_cmp(&b.1.distance_squared(position)))?;
+
+        self.enable_particle_flags(wgpu_context);
+        self.particles.set_particle_flag(wgpu_context, particle as usize..particle as usize + 1, HELD_FLAG, true);
+        self.particles.interaction_mut().set_grab_offset(particle_position - position);
+        Some(particle)
+    }
+
+    /// Runs `operation` over the particles at `ids`, e.g. the result of a `ParticleSelectionQuery`.
+    /// Removed particles are deleted right away, along with the dead ones; returns the number of deleted particles.
+    pub fn apply_group_operation(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, ids: &[u32], operation: GroupOperation) -> usize {
+        self.particles.apply_group_operation(wgpu_context, ids, operation);
+        if operation != GroupOperation::Remove {
+            return 0;
+        }
+        self.compact_now(wgpu_context, gpu_profiler)
+    }
+
+    /// Compaction outside of a step.
+    fn compact_now(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler) -> usize {
+        let mut frame_graph = FrameGraph::new(wgpu_context, "Compaction encoder");
+        let removed = self.compact_particles(wgpu_context, &mut frame_graph, gpu_profiler, 0.0);
+        frame_graph.submit(wgpu_context, gpu_profiler);
+        removed
+    }
+
+    /// Adds the compaction to `frame_graph` and flushes it, the removal reads the number of survivors back.
+    /// `pending_time` was simulated by the current step, which has not ended yet.
+    fn compact_particles(&mut self, wgpu_context: &WgpuContext, frame_graph: &mut FrameGraph, gpu_profiler: &mut GpuProfiler, pending_time: f32) -> usize {
+        let elapsed_time = (self.simulated_time - self.last_compaction_time) as f32 + pending_time;
+        self.last_compaction_time = self.simulated_time + pending_time as f64;
+        frame_graph.add_pass(PhysicsPass::Compaction, gpu_profiler, |encoder, gpu_profiler| {
+            if let Some(kill_volumes) = self.kill_volumes.as_ref().filter(|kill_volumes| !kill_volumes.is_empty()) {
+                kill_volumes.mark_killed(wgpu_context, encoder, gpu_profiler, &self.particles);
+            }
+        });
+        frame_graph.flush(wgpu_context, gpu_profiler);
+        let removed = self.particles.remove_dead_particles(wgpu_context, gpu_profiler, elapsed_time);
+        if removed > 0 {
+            self.grid.remove_particles(wgpu_context, self.particles.len());
+            // The segment pass runs over every particle
+            self.collision_system.refresh_particle_bindings(wgpu_context, &self.particles, &self.grid);
+            if let Some(fluid_solver) = self.fluid_solver.as_mut() {
+                fluid_solver.refresh_buffers(wgpu_context, &self.particles, &self.grid);
+            }
+            if let Some(heat) = self.heat.as_mut() {
+                heat.refresh_buffers(wgpu_context, &self.particles, &self.grid);
+            }
+            if let Some(sleep) = self.sleep.as_mut() {
+                sleep.refresh_buffers(wgpu_context, &self.particles, &self.grid);
+            }
+        }
+        removed
+    }
+
+    /// Whether `step` runs the periodic compaction: lifetimes, kill volumes or an open world can remove particles.
+    fn removes_particles(&self) -> bool {
+        self.particles.channels().find(LIFETIME_CHANNEL).is_some()
+            || self.kill_volumes.as_ref().is_some_and(|kill_volumes| !kill_volumes.is_empty())
+            || self.config.boundary_mode == BoundaryMode::Open
+    }
+
+    /// Adds a region that deletes the particles entering it, e.g. below the outlet of a funnel. The volumes are
+    /// checked by the compaction `step` runs every `COMPACTION_INTERVAL_STEPS` steps, and by `remove_dead_particles`.
+    /// Returns its index.
+    pub fn add_kill_volume(&mut self, wgpu_context: &WgpuContext, kill_volume: KillVolume) -> usize {
+        let kill_volumes = self.kill_volumes.get_or_insert_with(|| KillVolumes::new(wgpu_context));
+        let mut volumes = kill_volumes.volumes().to_vec();
+        volumes.push(kill_volume);
+        kill_volumes.set_volumes(wgpu_context, volumes);
+        if self.particles.channels().find(LIFETIME_CHANNEL).is_none() && kill_volumes.volumes().len() == 1 {
+            // The interval starts now, like for the lifetimes
+            self.last_compaction_time = self.simulated_time;
+        }
+        kill_volumes.volumes().len() - 1
+    }
+
+    /// Removes the kill volume at `index`, returning it.
+    pub fn remove_kill_volume(&mut self, wgpu_context: &WgpuContext, index: usize) -> Option<KillVolume> {
+        let kill_volumes = self.kill_volumes.as_mut()?;
+        let mut volumes = kill_volumes.volumes().to_vec();
+        if index >= volumes.len() {
+            return None;
+        }
+        let removed = volumes.remove(index);
+        kill_volumes.set_volumes(wgpu_context, volumes);
+        Some(removed)
+    }
+
+    pub fn clear_kill_volumes(&mut self, wgpu_context: &WgpuContext) {
+        if let Some(kill_volumes) = self.kill_volumes.as_mut() {
+            kill_volumes.set_volumes(wgpu_context, Vec::new());
+        }
+    }
+
+    pub fn kill_volumes(&self) -> &[KillVolume] {
+        self.kill_volumes.as_ref().map(KillVolumes::volumes).unwrap_or(&[])
+    }
+
+    /// Spawns a chain of particles from `start` towards `end`, linked by springs of `stiffness` (0 to 1).
+    /// The particles are as far apart as the largest spawn radius allows without overlapping.
+    /// Returns `None`, spawning nothing, when the particle limit can not hold the whole chain.
+    pub fn add_chain(&mut self, wgpu_context: &WgpuContext, start: Vec2, end: Vec2, stiffness: f32) -> Option<SpringBody> {
+        let spacing = 2.0 * self.particles.spawn_radius_range().1;
+        let (positions, links) = spring_constraints::chain_layout(start, end, spacing);
+        self.add_spring_body(wgpu_context, &positions, &links, stiffness)
+    }
+
+    /// Spawns a `columns` x `rows` cloth of particles from `bottom_left`, linked to their neighbours and
+    /// diagonals by springs of `stiffness` (0 to 1), spaced like `add_chain`. `None` when it does not fit
+    /// under the particle limit.
+    pub fn add_cloth(&mut self, wgpu_context: &WgpuContext, bottom_left: Vec2, columns: usize, rows: usize, stiffness: f32) -> Option<SpringBody> {
+        let spacing = 2.0 * self.particles.spawn_radius_range().1;
+        let (positions, links) = spring_constraints::cloth_layout(bottom_left, columns, rows, spacing);
+        self.add_spring_body(wgpu_context, &positions, &links, stiffness)
+    }
+
+    /// Spawns a particle at each of `positions` and a spring for each (first, second) pair of indices into
+    /// them, at the rest length of their initial distance.
+    pub fn add_spring_body(&mut self, wgpu_context: &WgpuContext, positions: &[Vec2], links: &[(usize, usize)], stiffness: f32) -> Option<SpringBody> {
+        let free_slots = self.particles.limit().max_particles.saturating_sub(self.particles.len());
+        if positions.is_empty() || positions.len() > free_slots {
+            return None;
+        }
+        let handle_channel = match self.particles.channels().find(SPRING_HANDLE_CHANNEL) {
+            Some(id) => id,
+            None => self.register_channel(wgpu_context, SPRING_HANDLE_CHANNEL, &[NO_HANDLE]),
+        };
+        let springs = self.springs.get_or_insert_with(|| SpringConstraints::new(wgpu_context, handle_channel));
+        let handles = springs.allocate_handles(wgpu_context, positions.len());
+
+        // Everything fits, so the particles are appended after the existing ones
+        let first_particle = self.particles.len();
+        self.add_particles_at(wgpu_context, positions);
+        self.particles.write_channel_from(wgpu_context, handle_channel, first_particle, &handles.clone().collect::<Vec<u32>>());
+
+        let body = SpringBody { handles, num_springs: links.len() };
+        let springs: Vec<Spring> = links.iter().map(|&(a, b)| Spring {
+            a: body.handle(a),
+            b: body.handle(b),
+            rest_length: positions[a].distance(positions[b]),
+            stiffness,
+        }).collect();
+        self.add_springs(wgpu_context, &springs);
+        Some(body)
+    }
+
+    /// Adds springs between the particles of spring bodies, e.g. to tie a chain to a cloth.
+    /// Panics if there are no spring bodies yet.
+    pub fn add_springs(&mut self, wgpu_context: &WgpuContext, springs: &[Spring]) {
+        self.springs.as_mut().expect("Springs need the handles of a spring body").add_springs(wgpu_context, springs);
+    }
+
+    /// Removes every spring, the particles stay.
+    pub fn clear_springs(&mut self, wgpu_context: &WgpuContext) {
+        if let Some(springs) = self.springs.as_mut() {
+            springs.clear(wgpu_context);
+        }
+    }
+
+    pub fn springs(&self) -> &[Spring] {
+        self.springs.as_ref().map(SpringConstraints::springs).unwrap_or(&[])
+    }
+
+    /// Times every spring is enforced per step, see `SpringConstraints::set_iterations`.
+    pub fn set_spring_iterations(&mut self, iterations: u32) {
+        if let Some(springs) = self.springs.as_mut() {
+            springs.set_iterations(iterations);
+        }
+    }
+
+    /// Position of the particle of every spring handle, `None` for the removed ones. Waits for the GPU.
+    pub fn spring_handle_positions(&mut self, wgpu_context: &WgpuContext) -> Vec<Option<Vec2>> {
+        let (Some(springs), Some(handle_channel)) = (self.springs.as_ref(), self.particles.channels().find(SPRING_HANDLE_CHANNEL)) else {
+            return Vec::new();
+        };
+        let mut positions = vec![None; springs.num_handles() as usize];
+        let stride = self.particles.channels().stride() as usize;
+        let offset = self.particles.channels().offset(handle_channel) as usize;
+        let buffers = self.particles.download_particle_buffers(wgpu_context);
+        for (particle, position) in buffers.current_positions.data().iter().enumerate() {
+            let handle = buffers.extras.data()[particle * stride + offset];
+            if let Some(slot) = positions.get_mut(handle as usize) {
+                *slot = Some(*position);
+            }
+        }
+        positions
+    }
+
+    /// Adds an immovable segment collider, see `CollisionSystem::add_static_segment`. Returns its index.
+    pub fn add_static_segment(&mut self, wgpu_context: &WgpuContext, start: Vec2, end: Vec2) -> usize {
+        self.collision_system.add_static_segment(wgpu_context, &self.particles, &self.grid, start, end)
+    }
+
+    pub fn clear_static_segments(&mut self, wgpu_context: &WgpuContext) {
+        self.collision_system.clear_static_segments(wgpu_context, &self.particles, &self.grid);
+    }
+
+    /// Switches between rigid particles and a fluid, see `SimulationMode`. The fluid replaces the collision
+    /// solver, whatever the broadphase mode; the static colliders and the springs still apply.
+    pub fn set_mode(&mut self, wgpu_context: &WgpuContext, mode: SimulationMode) {
+        self.fluid_solver = match (mode, self.fluid_solver.take()) {
+            (SimulationMode::Rigid, _) => None,
+            (SimulationMode::Fluid(config), Some(mut fluid_solver)) => {
+                fluid_solver.set_config(config);
+                Some(fluid_solver)
+            }
+            (SimulationMode::Fluid(config), None) => {
+                let mut fluid_solver = FluidSolver::new(wgpu_context, &self.particles, &self.grid, config);
+                fluid_solver.set_origin(self.grid.origin());
+                Some(fluid_solver)
+            }
+        };
+        self.refresh_period();
+    }
+
+    pub fn mode(&self) -> SimulationMode {
+        self.fluid_solver.as_ref().map_or(SimulationMode::Rigid, |fluid_solver| SimulationMode::Fluid(fluid_solver.config()))
+    }
+
+    /// Density and pressure of every particle in the last fluid step, empty in `SimulationMode::Rigid`.
+    /// Waits for the GPU.
+    pub fn download_fluid_densities(&self, wgpu_context: &WgpuContext) -> Vec<Vec2> {
+        self.fluid_solver.as_ref().map_or(Vec::new(), |fluid_solver| fluid_solver.download_densities(wgpu_context, self.particles.len()))
+    }
+
+    /// Gives every particle a temperature, stored in the `TEMPERATURE_CHANNEL` channel, and adds the heat pass to
+    /// `step`: touching particles exchange heat, every particle cools down towards the ambient temperature and the
+    /// warm particles rise, see `HeatConfig`. Existing particles start at the ambient temperature. If the heat is
+    /// already enabled, only its config changes.
+    pub fn enable_heat(&mut self, wgpu_context: &WgpuContext, config: HeatConfig) -> ChannelId {
+        if let Some(heat) = self.heat.as_mut() {
+            heat.set_config(config);
+            return self.particles.channels().find(TEMPERATURE_CHANNEL).unwrap();
+        }
+        let id = match self.particles.channels().find(TEMPERATURE_CHANNEL) {
+            Some(id) => id,
+            None => self.particles.register_channel(wgpu_context, TEMPERATURE_CHANNEL, &[config.ambient_temperature.to_bits()]),
+        };
+        let mut heat = HeatDiffusion::new(wgpu_context, &self.particles, &self.grid, config);
+        heat.set_origin(self.grid.origin());
+        self.heat = Some(heat);
+        self.refresh_particle_bindings(wgpu_context);
+        self.refresh_period();
+        id
+    }
+
+    /// Stops the heat pass. The particles keep their temperatures, `enable_heat` continues from them.
+    pub fn disable_heat(&mut self) {
+        self.heat = None;
+        self.refresh_period();
+    }
+
+    /// `None` while the heat is disabled.
+    pub fn heat_config(&self) -> Option<HeatConfig> {
+        self.heat.as_ref().map(HeatDiffusion::config)
+    }
+
+    /// Temperature of the particles spawned from now on. Enables the heat with the default config if needed.
+    pub fn set_spawn_temperature(&mut self, wgpu_context: &WgpuContext, temperature: f32) {
+        let id = self.enable_heat_if_disabled(wgpu_context);
+        self.particles.set_spawn_channel_value(id, &[temperature.to_bits()]);
+    }
+
+    /// Gives the particles in `range` the temperature, enabling the heat with the default config if needed.
+    /// Indices are the ones of the current order, the temperature moves with the particles when they are sorted.
+    pub fn set_temperature(&mut self, wgpu_context: &WgpuContext, range: Range<usize>, temperature: f32) {
+        let id = self.enable_heat_if_disabled(wgpu_context);
+        let range = range.start.min(self.particles.len())..range.end.min(self.particles.len());
+        let words = vec![temperature.to_bits(); range.len()];
+        self.particles.write_channel_from(wgpu_context, id, range.start, &words);
+    }
+
+    fn enable_heat_if_disabled(&mut self, wgpu_context: &WgpuContext) -> ChannelId {
+        let config = self.heat_config().unwrap_or_default();
+        self.enable_heat(wgpu_context, config)
+    }
+
+    /// Heats the particles inside `brush` every step, `None` stops. Ignored while the heat is disabled, and while
+    /// the mouse interaction heats the particles under the cursor (`InteractionMode::Heat`).
+    pub fn set_heat_brush(&mut self, brush: Option<HeatBrush>) {
+        if let Some(heat) = self.heat.as_mut() {
+            heat.set_brush(brush);
+        }
+    }
+
+    /// The brush of the mouse interaction, while it is active in `InteractionMode::Heat`.
+    fn interaction_heat_brush(&self) -> Option<HeatBrush> {
+        let interaction = self.particles.interaction();
+        (interaction.is_active() && interaction.mode == InteractionMode::Heat).then(|| HeatBrush {
+            position: interaction.position(),
+            radius: interaction.radius(),
+            rate: interaction.strength(),
+        })
+    }
+
+    /// Temperature of every particle, empty while the particles have no temperature. Waits for the GPU.
+    pub fn download_temperatures(&mut self, wgpu_context: &WgpuContext) -> Vec<f32> {
+        let Some(id) = self.particles.channels().find(TEMPERATURE_CHANNEL) else {
+            return Vec::new();
+        };
+        let stride = self.particles.channels().stride() as usize;
+        let offset = self.particles.channels().offset(id) as usize;
+        let extras = self.particles.download_extras(wgpu_context);
+        (0..self.particles.len()).map(|particle| f32::from_bits(extras[particle * stride + offset])).collect()
+    }
+
+    /// Lets the settled particles fall asleep, see `ParticleSleep`: a particle slower than `SleepConfig::sleep_speed`
+    /// for `sleep_steps` steps is left out of the integration and is kinematic for the collision solvers, until it is
+    /// moved or a moving particle gets close to it. Registers the flags and the `SLEEP_CHANNEL` channels. If the sleep
+    /// is already enabled, only its config changes.
+    pub fn enable_sleep(&mut self, wgpu_context: &WgpuContext, config: SleepConfig) -> ChannelId {
+        if let Some(sleep) = self.sleep.as_mut() {
+            sleep.set_config(config);
+            return self.particles.channels().find(SLEEP_CHANNEL).unwrap();
+        }
+        self.particles.enable_particle_flags(wgpu_context);
+        let id = match self.particles.channels().find(SLEEP_CHANNEL) {
+            Some(id) => id,
+            None => self.particles.register_channel(wgpu_context, SLEEP_CHANNEL, &[0]),
+        };
+        let mut sleep = ParticleSleep::new(wgpu_context, &self.particles, &self.grid, config);
+        sleep.set_origin(self.grid.origin());
+        self.sleep = Some(sleep);
+        self.refresh_particle_bindings(wgpu_context);
+        self.refresh_period();
+        id
+    }
+
+    /// Stops the sleep pass and wakes every particle.
+    pub fn disable_sleep(&mut self, wgpu_context: &WgpuContext) {
+        self.wake_particles(wgpu_context);
+        self.sleep = None;
+        self.refresh_period();
+    }
+
+    /// `None` while the sleep is disabled.
+    pub fn sleep_config(&self) -> Option<SleepConfig> {
+        self.sleep.as_ref().map(ParticleSleep::config)
+    }
+
+    /// Wakes every particle, e.g. after the gravity changed or a support was removed: the sleeping particles do not
+    /// notice either. They fall asleep again once they have been still for `SleepConfig::sleep_steps` steps.
+    pub fn wake_particles(&mut self, wgpu_context: &WgpuContext) {
+        let Some(id) = self.particles.channels().find(SLEEP_CHANNEL) else {
+            return;
+        };
+        let len = self.particles.len();
+        if len == 0 {
+            return;
+        }
+        self.particles.set_particle_flag(wgpu_context, 0..len, SLEEPING_FLAG, false);
+        self.particles.write_channel_from(wgpu_context, id, 0, &vec![0; len]);
+    }
+
+    /// Number of sleeping particles. Waits for the GPU.
+    pub fn count_sleeping_particles(&mut self, wgpu_context: &WgpuContext) -> usize {
+        let Some(id) = self.particles.channels().find(PARTICLE_FLAGS_CHANNEL) else {
+            return 0;
+        };
+        let stride = self.particles.channels().stride() as usize;
+        let offset = self.particles.channels().offset(id) as usize;
+        let extras = self.particles.download_extras(wgpu_context);
+        (0..self.particles.len()).filter(|particle| extras[particle * stride + offset] & SLEEPING_FLAG != 0).count()
+    }
+
+    /// Selects how the collisions are found and solved, see `BroadphaseMode`.
+    pub fn set_broadphase_mode(&mut self, wgpu_context: &WgpuContext, broadphase_mode: BroadphaseMode) {
+        if broadphase_mode == BroadphaseMode::SpatialHash && self.fluid_solver.is_some() {
+            log::warn!("The fluid solver indexes its cell ranges by morton id, it misses neighbours with hashed cell ids");
+        }
+        if broadphase_mode == BroadphaseMode::SpatialHash && self.heat.is_some() {
+            log::warn!("The heat indexes its cell ranges by morton id, it misses neighbours with hashed cell ids");
+        }
+        if broadphase_mode == BroadphaseMode::SpatialHash && self.sleep.is_some() {
+            log::warn!("The sleep indexes its cell ranges by morton id, it misses neighbours with hashed cell ids");
+        }
+        self.collision_system.set_broadphase_mode(wgpu_context, &self.particles, &mut self.grid, broadphase_mode);
+        self.refresh_period();
+    }
+
+    /// Colors the particles by speed, density or temperature, see `ParticleSystem::set_color_settings`. `None` restores
+    /// the velocity shading of the drawer.
+    pub fn set_particle_colors(&mut self, wgpu_context: &WgpuContext, settings: Option<ParticleColorSettings>) {
+        self.particles.set_color_settings(wgpu_context, settings);
+    }
+
+    /// Recomputes the particle colors, once per frame after the steps. `delta_time` is the one of the last step.
+    pub fn update_particle_colors(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, delta_time: f32) {
+        self.particles.update_colors(wgpu_context, gpu_profiler, delta_time, self.grid.cell_size());
+    }
+
+    /// Records `update_particle_colors` into `encoder`.
+    pub fn record_particle_colors(&mut self, wgpu_context: &WgpuContext, encoder: &mut wgpu::CommandEncoder, gpu_profiler: &GpuProfiler, delta_time: f32) {
+        self.particles.record_update_colors(wgpu_context, encoder, gpu_profiler, delta_time, self.grid.cell_size());
+    }
+
+    pub fn static_segments(&self) -> &[StaticSegment] {
+        self.collision_system.static_segments()
+    }
+
+    /// Adds an immovable circle collider, see `CollisionSystem::add_static_circle`. Returns its index.
+    pub fn add_static_circle(&mut self, wgpu_context: &WgpuContext, center: Vec2, radius: f32) -> usize {
+        self.collision_system.add_static_circle(wgpu_context, &self.particles, center, radius)
+    }
+
+    /// Removes the last added circle containing `position`, if any.
+    pub fn remove_static_circle_at(&mut self, wgpu_context: &WgpuContext, position: Vec2) -> Option<StaticCircle> {
+        self.collision_system.remove_static_circle_at(wgpu_context, &self.particles, position)
+    }
+
+    pub fn clear_static_circles(&mut self, wgpu_context: &WgpuContext) {
+        self.collision_system.clear_static_circles(wgpu_context, &self.particles);
+    }
+
+    pub fn static_circles(&self) -> &[StaticCircle] {
+        self.collision_system.static_circles()
+    }
+
+    /// Starts recording the positions of the current particles, see `TrajectoryRecorder`.
+    /// Record the frames with `record_trajectory`; two recordings of the same scene can be
+    /// compared with `trajectory::compare_trajectories` or the `compare-trajectories` binary.
+    pub fn start_trajectory_recording(&mut self, wgpu_context: &WgpuContext) -> TrajectoryRecorder {
+        let recorder = TrajectoryRecorder::new(wgpu_context, &mut self.particles);
+        // The tracking ids live in a channel
+        self.refresh_particle_bindings(wgpu_context);
+        recorder
+    }
+
+    /// Records the particle positions as the frame of the current step count. Waits for the GPU.
+    pub fn record_trajectory(&mut self, wgpu_context: &WgpuContext, recorder: &mut TrajectoryRecorder) {
+        recorder.record(wgpu_context, &mut self.particles, self.step_count as u32);
+    }
+
+    /// Registers a per-particle channel, e.g. for a force kernel, and rebinds every kernel reading channels.
+    pub fn register_channel(&mut self, wgpu_context: &WgpuContext, name: &str, default: &[u32]) -> ChannelId {
+        let id = self.particles.register_channel(wgpu_context, name, default);
+        self.refresh_particle_bindings(wgpu_context);
+        id
+    }
+
+    /// Writes a channel of every particle, see `ParticleSystem::write_channel`.
+    pub fn write_channel(&mut self, wgpu_context: &WgpuContext, id: ChannelId, values: &[u32]) {
+        self.particles.write_channel(wgpu_context, id, values);
+        self.refresh_particle_bindings(wgpu_context);
+    }
+
+    /// Moves the world so it starts at `world_origin`: the integration bounds and the grid cells follow it,
+    /// so particles at negative coordinates get correct cell ids. `-world_size / 2` centers the world on (0, 0).
+    /// A drawn grid needs `Grid::refresh_drawer`.
+    pub fn set_world_origin(&mut self, world_origin: Vec2) {
+        self.particles.set_world_origin(world_origin);
+        self.grid.set_origin(world_origin);
+        self.collision_system.set_grid_origin(world_origin);
+        if let Some(fluid_solver) = self.fluid_solver.as_mut() {
+            fluid_solver.set_origin(world_origin);
+        }
+        if let Some(heat) = self.heat.as_mut() {
+            heat.set_origin(world_origin);
+        }
+        if let Some(sleep) = self.sleep.as_mut() {
+            sleep.set_origin(world_origin);
+        }
+    }
+
+    pub fn world_origin(&self) -> Vec2 {
+        self.particles.get_world_origin()
+    }
+
+    /// Checks the order of the passes encoded by every step (build -> sort -> collision cells -> solve ->
+    /// forces -> integration) and panics on the first mistake, see `PassValidator`. On by default in debug builds.
+    pub fn set_pass_validation(&mut self, enabled: bool) {
+        self.pass_validator = enabled.then(PassValidator::new);
+    }
+
+    pub fn is_pass_validation_enabled(&self) -> bool {
+        self.pass_validator.is_some()
+    }
+
+    /// Dispatches the broad phase (cell ids, sort and collision cells) from the live particle count on the GPU,
+    /// see `LiveParticleCount`. The integration still covers every particle, and the periodic particle sort
+    /// is skipped since it would mix the particles past the live count with the live ones.
+    pub fn set_indirect_dispatch(&mut self, enabled: bool) {
+        self.grid.set_indirect_dispatch(enabled);
+        self.collision_system.set_indirect_dispatch(&self.grid);
+    }
+
+    /// Sorts only the used cell ids and builds the collision cells from them, see `Grid::set_cell_compaction`.
+    /// The count never leaves the GPU, so it combines with `set_indirect_dispatch`.
+    pub fn set_cell_compaction(&mut self, wgpu_context: &WgpuContext, enabled: bool) {
+        self.grid.set_cell_compaction(wgpu_context, enabled);
+        self.collision_system.set_indirect_dispatch(&self.grid);
+    }
+
+    /// Sorts the cell ids on the bits the cells of the world need, with fewer radix passes in small worlds,
+    /// see `Grid::set_key_compression`.
+    pub fn set_key_compression(&mut self, enabled: bool) {
+        self.grid.set_key_compression(enabled.then(|| self.particles.get_world_size()));
+    }
+
+    /// Compiles a user force kernel, see `ForceKernel`. Kernels run in the order they were added.
+    pub fn add_force_kernel(&mut self, wgpu_context: &WgpuContext, descriptor: &ForceKernelDescriptor) -> ForceKernelId {
+        self.force_kernels.push(ForceKernel::new(wgpu_context, descriptor, &self.particles));
+        ForceKernelId(self.force_kernels.len() as u32 - 1)
+    }
+
+    pub fn force_kernel(&self, id: ForceKernelId) -> &ForceKernel {
+        &self.force_kernels[id.0 as usize]
+    }
+
+    pub fn force_kernel_mut(&mut self, id: ForceKernelId) -> &mut ForceKernel {
+        &mut self.force_kernels[id.0 as usize]
+    }
+
+    fn refresh_force_kernels(&mut self, wgpu_context: &WgpuContext) {
+        for force_kernel in &mut self.force_kernels {
+            force_kernel.refresh(wgpu_context, &self.particles);
+        }
+    }
+
+    /// Rebinds the kernels that read the extras buffer, after a channel was registered or written.
+    fn refresh_particle_bindings(&mut self, wgpu_context: &WgpuContext) {
+        self.collision_system.refresh_particle_bindings(wgpu_context, &self.particles, &self.grid);
+        if let Some(fluid_solver) = self.fluid_solver.as_mut() {
+            fluid_solver.refresh_buffers(wgpu_context, &self.particles, &self.grid);
+        }
+        if let Some(heat) = self.heat.as_mut() {
+            heat.refresh_buffers(wgpu_context, &self.particles, &self.grid);
+        }
+        if let Some(sleep) = self.sleep.as_mut() {
+            sleep.refresh_buffers(wgpu_context, &self.particles, &self.grid);
+        }
+        self.refresh_force_kernels(wgpu_context);
+    }
+
+    /// Current statistics. Reads the number of collision cells back from the GPU, which waits
+    /// for the last step to finish; do not call it in the middle of a frame.
+    pub fn stats(&self, wgpu_context: &WgpuContext) -> SimulationStats {
+        SimulationStats {
+            simulated_time: self.simulated_time,
+            step_count: self.step_count,
+            particle_count: self.particles.len(),
+            active_collision_cells: self.collision_system.download_num_collision_cells(wgpu_context),
+            last_step_gpu_time: self.last_step_gpu_time,
+        }
+    }
+
+    /// GPU time of the last measured step, see `SimulationStats::last_step_gpu_time`. Does not wait for the GPU.
+    pub fn last_step_gpu_time(&self) -> Option<Duration> {
+        self.last_step_gpu_time
+    }
+
+    pub fn particles(&self) -> &ParticleSystem {
+        &self.particles
+    }
+
+    pub fn particles_mut(&mut self) -> &mut ParticleSystem {
+        &mut self.particles
+    }
+
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    pub fn grid_mut(&mut self) -> &mut Grid {
+        &mut self.grid
+    }
+
+    pub fn collision_system(&self) -> &CollisionSystem {
+        &self.collision_system
+    }
+
+    pub fn collision_system_mut(&mut self) -> &mut CollisionSystem {
+        &mut self.collision_system
+    }
+}
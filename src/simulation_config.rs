@@ -0,0 +1,618 @@
+use std::path::Path;
+use std::time::Duration;
+use glam::Vec2;
+use crate::particles::color_palette::{ColorHarmony, ColorTheme};
+use crate::physics::forces::GlobalForces;
+use crate::renderer::recorder::{RecordingFormat, RecordingSettings};
+use crate::utils::config_file::{self, ConfigEntry, ConfigError};
+
+/// Time between two sorts of the particles by grid cell.
+pub const DEFAULT_SORT_INTERVAL: Duration = Duration::from_secs(4);
+
+/// Standard gravity of the earth, in m/s².
+pub const STANDARD_GRAVITY: f32 = 9.81;
+/// Earth gravity pointing down the y axis, in m/s².
+pub const EARTH_GRAVITY: Vec2 = Vec2::new(0.0, -STANDARD_GRAVITY);
+
+/// Scale between the physical units of the user-facing parameters (meters, seconds) and the world units
+/// of the simulation (the coordinates of the particles, pixels at zoom 1). Time is in seconds on both sides.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PhysicalUnits {
+    /// Length of one world unit, in meters.
+    pub meters_per_world_unit: f32,
+}
+
+impl PhysicalUnits {
+    pub fn new(meters_per_world_unit: f32) -> Self {
+        assert!(meters_per_world_unit > 0.0, "meters_per_world_unit must be positive, got {}", meters_per_world_unit);
+        Self { meters_per_world_unit }
+    }
+
+    /// Meters to world units.
+    pub fn length_to_world(&self, meters: f32) -> f32 {
+        meters / self.meters_per_world_unit
+    }
+
+    /// World units to meters.
+    pub fn length_to_meters(&self, world_length: f32) -> f32 {
+        world_length * self.meters_per_world_unit
+    }
+
+    /// Point in meters to world coordinates.
+    pub fn position_to_world(&self, meters: Vec2) -> Vec2 {
+        meters / self.meters_per_world_unit
+    }
+
+    /// m/s to world units per second.
+    pub fn velocity_to_world(&self, meters_per_second: Vec2) -> Vec2 {
+        meters_per_second / self.meters_per_world_unit
+    }
+
+    /// m/s² to world units per second squared.
+    pub fn acceleration_to_world(&self, meters_per_second_squared: Vec2) -> Vec2 {
+        meters_per_second_squared / self.meters_per_world_unit
+    }
+
+    /// Size in meters to world units.
+    pub fn size_to_world(&self, meters: Vec2) -> Vec2 {
+        meters / self.meters_per_world_unit
+    }
+
+    /// Size in world units to meters.
+    pub fn size_to_meters(&self, world_size: Vec2) -> Vec2 {
+        world_size * self.meters_per_world_unit
+    }
+
+    /// World units per second squared to m/s².
+    pub fn acceleration_to_meters(&self, world_acceleration: Vec2) -> Vec2 {
+        world_acceleration * self.meters_per_world_unit
+    }
+}
+
+impl Default for PhysicalUnits {
+    /// One world unit is one centimeter: the spawned particles are 1 to 3 cm wide.
+    fn default() -> Self {
+        Self::new(0.01)
+    }
+}
+
+/// Fixed timestep of `Simulation::advance`, in seconds. The frame times are accumulated and consumed in
+/// steps of `step`, so the simulation does the same work for the same simulated time at any frame rate.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FixedTimestep {
+    /// Simulated time of one step.
+    pub step: f32,
+    /// Collision and integration passes per step, each one advancing `step / substeps`.
+    pub substeps: u32,
+    /// Steps a frame may run to catch up. The time of the steps over it is dropped, so a slow frame
+    /// slows the simulation down instead of making the next frames even slower.
+    pub max_steps_per_frame: u32,
+}
+
+impl FixedTimestep {
+    /// Delta time of each collision and integration pass.
+    pub fn substep_time(&self) -> f32 {
+        self.step / self.substeps.max(1) as f32
+    }
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self {
+            step: 1.0 / 60.0,
+            substeps: 1,
+            max_steps_per_frame: 4,
+        }
+    }
+}
+
+/// How the particles are pulled by gravity. In a `SimulationConfig` the positions and strengths are in SI units,
+/// `SimulationConfig::world_gravity_mode` converts them to world units for the integration.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GravityMode {
+    /// `SimulationConfig::gravity` everywhere.
+    Uniform,
+    /// Towards `center`, e.g. a planet for accretion demos. `SimulationConfig::gravity` is ignored.
+    Radial {
+        center: Vec2,
+        /// Acceleration (m/s²) for `RadialFalloff::Constant`, gravitational parameter GM (m³/s²) for `InverseSquare`.
+        strength: f32,
+        falloff: RadialFalloff,
+    },
+}
+
+/// Magnitude of `GravityMode::Radial` over the distance to the center.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RadialFalloff {
+    Constant,
+    /// strength / distance². Closer than one world unit, the acceleration stops growing.
+    InverseSquare,
+}
+
+/// What keeps the particles in the world. The world rectangle always bounds them, see `Simulation::set_world_origin`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WorldBoundary {
+    Rectangle,
+    /// Particles are also kept inside this circle, in SI units in a `SimulationConfig`.
+    Circle { center: Vec2, radius: f32 },
+}
+
+/// What the edges of the world rectangle do to the particles reaching them.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum BoundaryMode {
+    /// Walls, the particles bounce off them with their `BoundaryMaterial`.
+    #[default]
+    Closed,
+    /// Periodic world: a particle leaving through an edge comes back through the opposite one, and collides
+    /// with the particles across the seam.
+    Wrap,
+    /// A particle whose center leaves the world is deleted by the next compaction.
+    Open,
+}
+
+impl BoundaryMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "closed" => Some(Self::Closed),
+            "wrap" => Some(Self::Wrap),
+            "open" => Some(Self::Open),
+            _ => None,
+        }
+    }
+}
+
+/// How a particle leaves the world boundary it hits. Dimensionless, both in [0, 1]. The default one, without
+/// restitution nor friction, stops the particle at the wall and lets it slide along it.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct BoundaryMaterial {
+    /// Part of the speed towards the wall kept after bouncing: 0 stops, 1 bounces back at full speed.
+    pub restitution: f32,
+    /// Part of the speed along the wall lost on contact: 0 slides freely, 1 sticks.
+    pub friction: f32,
+}
+
+impl BoundaryMaterial {
+    pub fn new(restitution: f32, friction: f32) -> Self {
+        Self { restitution, friction }
+    }
+}
+
+/// Physical parameters of a `Simulation`, in SI units. `Simulation::set_config` converts them with `units`
+/// into the world units the integrator and the emitters work in. The collision solver parameters
+/// (`SolverConfig`) are dimensionless and need no conversion.
+///
+/// `num_particles`, `world_size` and `initial_radius_range` describe the initial particles, they are only read
+/// by `Simulation::with_config`. Build one with `SimulationConfig::builder` or load it with `SimulationConfig::load`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SimulationConfig {
+    pub units: PhysicalUnits,
+    /// Particles generated at the start. The layout may fit fewer in a small world.
+    pub num_particles: usize,
+    /// Size of the world rectangle, in meters.
+    pub world_size: Vec2,
+    /// Smallest and largest radius of the initial particles, in meters, uniformly distributed.
+    pub initial_radius_range: (f32, f32),
+    /// Acceleration applied to every particle, in m/s². None by default, `EARTH_GRAVITY` for a falling pile.
+    pub gravity: Vec2,
+    /// Uniform by default, following `gravity`.
+    pub gravity_mode: GravityMode,
+    /// Acceleration added to the gravity in every gravity mode, in m/s². None by default.
+    pub wind: Vec2,
+    /// Part of the velocity every particle loses per second, in 1/s. None by default.
+    pub linear_drag: f32,
+    pub boundary: WorldBoundary,
+    /// Closed by default. Only the closed world keeps the particles inside the rectangle, the circle of
+    /// `boundary` bounds them in every mode.
+    pub boundary_mode: BoundaryMode,
+    /// Response of the particles without a material of their own, see `Simulation::set_boundary_materials`.
+    pub boundary_material: BoundaryMaterial,
+    /// Smallest and largest radius of the spawned particles, in meters.
+    pub spawn_radius_range: (f32, f32),
+    /// Timestep of `Simulation::advance`. None steps once per frame with the frame time, which makes
+    /// the simulation depend on the frame rate and unstable when it drops.
+    pub timestep: Option<FixedTimestep>,
+    /// Time between two sorts of the particles by grid cell. Sorting keeps the particles of a cell close
+    /// in memory, a longer interval sorts less often but the collisions get slower as the particles mix.
+    pub sort_interval: Duration,
+    /// Replaces `sort_interval` when set: the disorder of the particles is measured every step on the GPU, as the
+    /// fraction of neighbouring particles whose grid cells are out of order, and they are sorted once it exceeds
+    /// this threshold. None by default.
+    pub sort_disorder_threshold: Option<f32>,
+    /// Theme of the palette the initial particles and every spawn batch are painted with, see `ParticleSystem::set_palette`.
+    /// None paints each particle with a random color.
+    pub palette: Option<ColorTheme>,
+    /// Frames the window records, see `Recorder`. Not read by the simulation.
+    pub recording: RecordingSettings,
+}
+
+impl SimulationConfig {
+    pub fn builder() -> SimulationConfigBuilder {
+        SimulationConfigBuilder::default()
+    }
+
+    /// Reads a config file, see `from_toml_str`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(|error| ConfigError::Io {
+            path: path.display().to_string(),
+            message: error.to_string(),
+        })?;
+        Self::from_toml_str(&source)
+    }
+
+    /// Parses a config in a subset of TOML. Every setting is optional and defaults to `SimulationConfig::default`,
+    /// lengths are in meters and times in seconds:
+    ///
+    /// ```toml
+    /// num_particles = 200_000
+    /// world_size = [30.48, 10.48]
+    /// meters_per_world_unit = 0.01
+    /// gravity = [0.0, -9.81]
+    /// wind = [1.5, 0.0]
+    /// linear_drag = 0.1
+    /// initial_radius_range = [0.004, 0.006]
+    /// spawn_radius_range = [0.01, 0.03]
+    /// sort_interval = 4.0
+    /// sort_disorder_threshold = 0.1   # replaces sort_interval
+    /// boundary_mode = "wrap"   # closed, wrap or open
+    ///
+    /// [timestep]          # enabled = false steps once per frame
+    /// step = 0.016666
+    /// substeps = 2
+    /// max_steps_per_frame = 4
+    ///
+    /// [radial_gravity]    # replaces the uniform gravity
+    /// center = [15.0, 5.0]
+    /// strength = 50.0
+    /// falloff = "inverse_square"
+    ///
+    /// [circle_boundary]
+    /// center = [15.0, 5.0]
+    /// radius = 5.0
+    ///
+    /// [boundary_material]
+    /// restitution = 0.5
+    /// friction = 0.1
+    ///
+    /// [palette]           # analogous, complementary, split_complementary, triadic or monochromatic
+    /// seed = 42
+    /// harmony = "triadic"
+    ///
+    /// [recording]         # format = "png" or "ffmpeg"
+    /// format = "ffmpeg"
+    /// frame_interval = 2
+    /// frame_rate = 30
+    /// start = true
+    /// ```
+    pub fn from_toml_str(source: &str) -> Result<Self, ConfigError> {
+        let entries = config_file::parse(source)?;
+        let mut builder = Self::builder();
+        let mut timestep = FixedTimestep::default();
+        let mut timestep_enabled = true;
+        let (mut radial_center, mut radial_strength, mut radial_falloff) = (None, None, RadialFalloff::Constant);
+        let (mut circle_center, mut circle_radius) = (None, None);
+        let mut boundary_material = BoundaryMaterial::default();
+        let (mut palette_seed, mut palette_harmony) = (None, ColorHarmony::default());
+        let mut recording = RecordingSettings::default();
+        let vec2 = |entry: &ConfigEntry| entry.pair().map(|(x, y)| Vec2::new(x as f32, y as f32));
+
+        for entry in &entries {
+            builder = match entry.key.as_str() {
+                "num_particles" => builder.num_particles(entry.count()? as usize),
+                "world_size" => builder.world_size(vec2(entry)?),
+                "meters_per_world_unit" => {
+                    let meters = entry.number()? as f32;
+                    if meters.is_nan() || meters <= 0.0 {
+                        return Err(entry.invalid("must be positive"));
+                    }
+                    builder.units(PhysicalUnits::new(meters))
+                }
+                "gravity" => builder.gravity(vec2(entry)?),
+                "wind" => builder.wind(vec2(entry)?),
+                "linear_drag" => builder.linear_drag(entry.number()? as f32),
+                "initial_radius_range" => {
+                    let (min_radius, max_radius) = entry.pair()?;
+                    builder.initial_radius_range(min_radius as f32, max_radius as f32)
+                }
+                "spawn_radius_range" => {
+                    let (min_radius, max_radius) = entry.pair()?;
+                    builder.spawn_radius_range(min_radius as f32, max_radius as f32)
+                }
+                "sort_interval" => {
+                    let seconds = entry.number()?;
+                    Duration::try_from_secs_f64(seconds).map(|interval| builder.sort_interval(interval))
+                        .map_err(|_| entry.invalid("expected seconds that are not negative"))?
+                }
+                "sort_disorder_threshold" => builder.sort_disorder_threshold(Some(entry.number()? as f32)),
+                "boundary_mode" => {
+                    let boundary_mode = BoundaryMode::from_name(entry.string()?)
+                        .ok_or_else(|| entry.invalid("expected \"closed\", \"wrap\" or \"open\""))?;
+                    builder.boundary_mode(boundary_mode)
+                }
+                "timestep.enabled" => { timestep_enabled = entry.bool()?; builder }
+                "timestep.step" => { timestep.step = entry.number()? as f32; builder }
+                "timestep.substeps" => { timestep.substeps = entry.count()?.try_into().map_err(|_| entry.invalid("too many substeps"))?; builder }
+                "timestep.max_steps_per_frame" => { timestep.max_steps_per_frame = entry.count()?.try_into().map_err(|_| entry.invalid("too many steps"))?; builder }
+                "radial_gravity.center" => { radial_center = Some(vec2(entry)?); builder }
+                "radial_gravity.strength" => { radial_strength = Some(entry.number()? as f32); builder }
+                "radial_gravity.falloff" => {
+                    radial_falloff = match entry.string()? {
+                        "constant" => RadialFalloff::Constant,
+                        "inverse_square" => RadialFalloff::InverseSquare,
+                        _ => return Err(entry.invalid("expected \"constant\" or \"inverse_square\"")),
+                    };
+                    builder
+                }
+                "circle_boundary.center" => { circle_center = Some(vec2(entry)?); builder }
+                "circle_boundary.radius" => { circle_radius = Some(entry.number()? as f32); builder }
+                "boundary_material.restitution" => { boundary_material.restitution = entry.number()? as f32; builder }
+                "boundary_material.friction" => { boundary_material.friction = entry.number()? as f32; builder }
+                "palette.seed" => { palette_seed = Some(entry.count()?); builder }
+                "palette.harmony" => {
+                    palette_harmony = ColorHarmony::from_name(entry.string()?)
+                        .ok_or_else(|| entry.invalid("expected \"analogous\", \"complementary\", \"split_complementary\", \"triadic\" or \"monochromatic\""))?;
+                    builder
+                }
+                "recording.format" => {
+                    recording.format = RecordingFormat::from_name(entry.string()?)
+                        .ok_or_else(|| entry.invalid("expected \"png\" or \"ffmpeg\""))?;
+                    builder
+                }
+                "recording.frame_interval" => { recording.frame_interval = entry.count()?.try_into().map_err(|_| entry.invalid("too many frames"))?; builder }
+                "recording.frame_rate" => { recording.frame_rate = entry.count()?.try_into().map_err(|_| entry.invalid("too many frames per second"))?; builder }
+                "recording.start" => { recording.record_on_start = entry.bool()?; builder }
+                _ => return Err(entry.unknown()),
+            };
+        }
+
+        builder = builder.timestep(timestep_enabled.then_some(timestep)).boundary_material(boundary_material).recording(recording);
+        match (radial_center, radial_strength) {
+            (Some(center), Some(strength)) => builder = builder.gravity_mode(GravityMode::Radial { center, strength, falloff: radial_falloff }),
+            (None, None) => {}
+            _ => return Err(ConfigError::InvalidValue { key: "radial_gravity".to_string(), message: "needs a center and a strength".to_string() }),
+        }
+        match (circle_center, circle_radius) {
+            (Some(center), Some(radius)) => builder = builder.boundary(WorldBoundary::Circle { center, radius }),
+            (None, None) => {}
+            _ => return Err(ConfigError::InvalidValue { key: "circle_boundary".to_string(), message: "needs a center and a radius".to_string() }),
+        }
+        if let Some(seed) = palette_seed {
+            builder = builder.palette(Some(ColorTheme { seed, harmony: palette_harmony }));
+        }
+        builder.build()
+    }
+
+    /// Checks the values a simulation can not run with.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |key: &str, message: String| Err(ConfigError::InvalidValue { key: key.to_string(), message });
+        let valid_range = |(min, max): (f32, f32)| 0.0 < min && min <= max && max.is_finite();
+        if self.num_particles == 0 {
+            return invalid("num_particles", "at least one particle is needed".to_string());
+        }
+        if !self.world_size.is_finite() || self.world_size.min_element() <= 0.0 {
+            return invalid("world_size", format!("{} is not a positive size", self.world_size));
+        }
+        if !valid_range(self.initial_radius_range) {
+            return invalid("initial_radius_range", format!("{:?} is not a positive range", self.initial_radius_range));
+        }
+        if !valid_range(self.spawn_radius_range) {
+            return invalid("spawn_radius_range", format!("{:?} is not a positive range", self.spawn_radius_range));
+        }
+        if !self.gravity.is_finite() {
+            return invalid("gravity", format!("{} is not finite", self.gravity));
+        }
+        if !self.wind.is_finite() {
+            return invalid("wind", format!("{} is not finite", self.wind));
+        }
+        if !self.linear_drag.is_finite() || self.linear_drag < 0.0 {
+            return invalid("linear_drag", format!("{} is not a finite drag that is not negative", self.linear_drag));
+        }
+        if let Some(timestep) = self.timestep
+            && (timestep.step.is_nan() || timestep.step <= 0.0 || timestep.substeps == 0 || timestep.max_steps_per_frame == 0) {
+            return invalid("timestep", format!("{:?} needs a positive step, substeps and steps per frame", timestep));
+        }
+        if let WorldBoundary::Circle { radius, .. } = self.boundary
+            && (radius.is_nan() || radius <= 0.0) {
+            return invalid("circle_boundary", format!("radius {} is not positive", radius));
+        }
+        if let Some(threshold) = self.sort_disorder_threshold
+            && !(0.0..=1.0).contains(&threshold) {
+            return invalid("sort_disorder_threshold", format!("{} is not a fraction between 0 and 1", threshold));
+        }
+        if self.recording.frame_interval == 0 || self.recording.frame_rate == 0 {
+            return invalid("recording", format!("{:?} needs a positive frame interval and frame rate", self.recording));
+        }
+        let BoundaryMaterial { restitution, friction } = self.boundary_material;
+        if !(0.0..=1.0).contains(&restitution) || !(0.0..=1.0).contains(&friction) {
+            return invalid("boundary_material", format!("restitution {} and friction {} must be in [0, 1]", restitution, friction));
+        }
+        Ok(())
+    }
+
+    /// `world_size` in world units.
+    pub fn world_size_in_world_units(&self) -> Vec2 {
+        self.units.size_to_world(self.world_size)
+    }
+
+    /// `initial_radius_range` in world units.
+    pub fn world_initial_radius_range(&self) -> (f32, f32) {
+        (self.units.length_to_world(self.initial_radius_range.0), self.units.length_to_world(self.initial_radius_range.1))
+    }
+
+    /// Gravity in world units per second squared.
+    pub fn world_gravity(&self) -> Vec2 {
+        self.units.acceleration_to_world(self.gravity)
+    }
+
+    /// `gravity`, `wind` and `linear_drag` in world units, for the integration.
+    pub fn world_forces(&self) -> GlobalForces {
+        GlobalForces {
+            gravity: self.world_gravity(),
+            wind: self.units.acceleration_to_world(self.wind),
+            linear_drag: self.linear_drag,
+        }
+    }
+
+    /// `gravity_mode` in world units: positions in world coordinates, accelerations in world units per second squared.
+    pub fn world_gravity_mode(&self) -> GravityMode {
+        match self.gravity_mode {
+            GravityMode::Uniform => GravityMode::Uniform,
+            GravityMode::Radial { center, strength, falloff } => {
+                let meters_per_world_unit = self.units.meters_per_world_unit;
+                let strength = match falloff {
+                    RadialFalloff::Constant => strength / meters_per_world_unit,
+                    // GM / r² with r in meters: the length cubed
+                    RadialFalloff::InverseSquare => strength / meters_per_world_unit.powi(3),
+                };
+                GravityMode::Radial { center: self.units.position_to_world(center), strength, falloff }
+            }
+        }
+    }
+
+    /// `boundary` in world units.
+    pub fn world_boundary(&self) -> WorldBoundary {
+        match self.boundary {
+            WorldBoundary::Rectangle => WorldBoundary::Rectangle,
+            WorldBoundary::Circle { center, radius } => WorldBoundary::Circle {
+                center: self.units.position_to_world(center),
+                radius: self.units.length_to_world(radius),
+            },
+        }
+    }
+
+    /// `spawn_radius_range` in world units.
+    pub fn world_spawn_radius_range(&self) -> (f32, f32) {
+        (self.units.length_to_world(self.spawn_radius_range.0), self.units.length_to_world(self.spawn_radius_range.1))
+    }
+}
+
+impl Default for SimulationConfig {
+    /// A million particles of 1 cm in a 30.48 x 10.48 m world, without gravity.
+    fn default() -> Self {
+        Self {
+            units: PhysicalUnits::default(),
+            num_particles: 1_000_000,
+            world_size: Vec2::new(30.48, 10.48),
+            initial_radius_range: (0.005, 0.005),
+            gravity: Vec2::ZERO,
+            gravity_mode: GravityMode::Uniform,
+            wind: Vec2::ZERO,
+            linear_drag: 0.0,
+            boundary: WorldBoundary::Rectangle,
+            boundary_mode: BoundaryMode::Closed,
+            boundary_material: BoundaryMaterial::default(),
+            spawn_radius_range: (0.01, 0.03),
+            timestep: Some(FixedTimestep::default()),
+            sort_interval: DEFAULT_SORT_INTERVAL,
+            sort_disorder_threshold: None,
+            palette: None,
+            recording: RecordingSettings::default(),
+        }
+    }
+}
+
+/// Builds a `SimulationConfig`, starting from the default one. `build` checks the values.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SimulationConfigBuilder {
+    config: SimulationConfig,
+}
+
+impl SimulationConfigBuilder {
+    pub fn units(mut self, units: PhysicalUnits) -> Self {
+        self.config.units = units;
+        self
+    }
+
+    pub fn num_particles(mut self, num_particles: usize) -> Self {
+        self.config.num_particles = num_particles;
+        self
+    }
+
+    /// In meters.
+    pub fn world_size(mut self, world_size: Vec2) -> Self {
+        self.config.world_size = world_size;
+        self
+    }
+
+    /// In m/s², for the uniform gravity.
+    pub fn gravity(mut self, gravity: Vec2) -> Self {
+        self.config.gravity = gravity;
+        self
+    }
+
+    /// In m/s².
+    pub fn wind(mut self, wind: Vec2) -> Self {
+        self.config.wind = wind;
+        self
+    }
+
+    /// In 1/s.
+    pub fn linear_drag(mut self, linear_drag: f32) -> Self {
+        self.config.linear_drag = linear_drag;
+        self
+    }
+
+    pub fn gravity_mode(mut self, gravity_mode: GravityMode) -> Self {
+        self.config.gravity_mode = gravity_mode;
+        self
+    }
+
+    pub fn boundary(mut self, boundary: WorldBoundary) -> Self {
+        self.config.boundary = boundary;
+        self
+    }
+
+    pub fn boundary_mode(mut self, boundary_mode: BoundaryMode) -> Self {
+        self.config.boundary_mode = boundary_mode;
+        self
+    }
+
+    pub fn boundary_material(mut self, boundary_material: BoundaryMaterial) -> Self {
+        self.config.boundary_material = boundary_material;
+        self
+    }
+
+    /// In meters. Equal radii give particles of one size.
+    pub fn initial_radius_range(mut self, min_radius: f32, max_radius: f32) -> Self {
+        self.config.initial_radius_range = (min_radius, max_radius);
+        self
+    }
+
+    /// In meters.
+    pub fn spawn_radius_range(mut self, min_radius: f32, max_radius: f32) -> Self {
+        self.config.spawn_radius_range = (min_radius, max_radius);
+        self
+    }
+
+    pub fn timestep(mut self, timestep: Option<FixedTimestep>) -> Self {
+        self.config.timestep = timestep;
+        self
+    }
+
+    pub fn sort_interval(mut self, sort_interval: Duration) -> Self {
+        self.config.sort_interval = sort_interval;
+        self
+    }
+
+    /// Fraction of neighbouring particles out of order, between 0 and 1.
+    pub fn sort_disorder_threshold(mut self, threshold: Option<f32>) -> Self {
+        self.config.sort_disorder_threshold = threshold;
+        self
+    }
+
+    pub fn palette(mut self, palette: Option<ColorTheme>) -> Self {
+        self.config.palette = palette;
+        self
+    }
+
+    pub fn recording(mut self, recording: RecordingSettings) -> Self {
+        self.config.recording = recording;
+        self
+    }
+
+    pub fn build(self) -> Result<SimulationConfig, ConfigError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
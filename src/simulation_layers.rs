@@ -0,0 +1,174 @@
+//! Several independent simulations in one scene, e.g. two fluids that must not mix.
+use crate::physics::frame_graph::FrameGraph;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::simulation::Simulation;
+use crate::utils::profiler::GpuProfiler;
+
+/// A named simulation of `SimulationLayers`, with its own particles, grid, collision system and config.
+pub struct SimulationLayer {
+    name: String,
+    simulation: Simulation,
+    /// Disabled layers are neither stepped nor drawn
+    enabled: bool,
+}
+
+impl SimulationLayer {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn simulation(&self) -> &Simulation {
+        &self.simulation
+    }
+
+    pub fn simulation_mut(&mut self) -> &mut Simulation {
+        &mut self.simulation
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+/// The layers of a scene, stepped one after the other with the same frame time and drawn in order, the
+/// first one at the bottom. Each layer only collides with itself: particles of different layers pass through
+/// each other, so particles that must collide belong in the same layer.
+/// There is always at least one layer, and one of them is active: it receives the input of the window.
+pub struct SimulationLayers {
+    layers: Vec<SimulationLayer>,
+    active: usize,
+}
+
+impl SimulationLayers {
+    /// A single active layer.
+    pub fn new(name: &str, simulation: Simulation) -> Self {
+        Self {
+            layers: vec![SimulationLayer { name: name.to_string(), simulation, enabled: true }],
+            active: 0,
+        }
+    }
+
+    /// Adds a layer on top of the others and returns its index.
+    pub fn add(&mut self, name: &str, simulation: Simulation) -> usize {
+        self.layers.push(SimulationLayer { name: name.to_string(), simulation, enabled: true });
+        self.layers.len() - 1
+    }
+
+    /// Removes the layer at `index`, the indices of the layers above it go down by one. The last layer
+    /// is never removed. If the active layer is removed, the one below it becomes active.
+    pub fn remove(&mut self, index: usize) -> Option<SimulationLayer> {
+        if index >= self.layers.len() || self.layers.len() == 1 {
+            return None;
+        }
+        let layer = self.layers.remove(index);
+        if self.active > index || self.active == self.layers.len() {
+            self.active -= 1;
+        }
+        Some(layer)
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Always false, there is at least one layer.
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&SimulationLayer> {
+        self.layers.get(index)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut SimulationLayer> {
+        self.layers.get_mut(index)
+    }
+
+    /// Index of the first layer called `name`.
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SimulationLayer> {
+        self.layers.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut SimulationLayer> {
+        self.layers.iter_mut()
+    }
+
+    /// The enabled layers, bottom first.
+    pub fn enabled(&self) -> impl Iterator<Item = &SimulationLayer> {
+        self.layers.iter().filter(|layer| layer.enabled)
+    }
+
+    /// Enables or disables the layer at `index`. The active layer is still active while disabled.
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(layer) = self.layers.get_mut(index) {
+            layer.enabled = enabled;
+        }
+    }
+
+    /// Makes the layer at `index` the active one. Out of range indices are ignored.
+    pub fn set_active(&mut self, index: usize) {
+        if index < self.layers.len() {
+            self.active = index;
+        }
+    }
+
+    /// Makes the next layer active, back to the first one after the last.
+    pub fn cycle_active(&mut self) -> usize {
+        self.active = (self.active + 1) % self.layers.len();
+        self.active
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.active
+    }
+
+    pub fn active_layer(&self) -> &SimulationLayer {
+        &self.layers[self.active]
+    }
+
+    pub fn active(&self) -> &Simulation {
+        &self.layers[self.active].simulation
+    }
+
+    pub fn active_mut(&mut self) -> &mut Simulation {
+        &mut self.layers[self.active].simulation
+    }
+
+    /// Particles of every layer, disabled ones included.
+    pub fn particle_count(&self) -> usize {
+        self.layers.iter().map(|layer| layer.simulation.particles().len()).sum()
+    }
+
+    /// Advances every enabled layer by `frame_time`, see `Simulation::advance`. Each layer follows the timestep of
+    /// its own config. Returns the number of steps run by each layer, zero for the disabled ones.
+    /// The layers are submitted together, see `record_advance`.
+    pub fn advance(&mut self, wgpu_context: &WgpuContext, gpu_profiler: &mut GpuProfiler, frame_time: f32, label: Option<&str>) -> Vec<u32> {
+        let mut frame_graph = FrameGraph::new(wgpu_context, "Physics frame encoder");
+        let steps = self.record_advance(wgpu_context, &mut frame_graph, gpu_profiler, frame_time, label);
+        frame_graph.submit(wgpu_context, gpu_profiler);
+        self.after_submit(wgpu_context);
+        steps
+    }
+
+    /// Records the steps of `advance` into `frame_graph`, see `Simulation::record_advance`. `after_submit` must
+    /// follow the submit of `frame_graph`.
+    pub fn record_advance(&mut self, wgpu_context: &WgpuContext, frame_graph: &mut FrameGraph, gpu_profiler: &mut GpuProfiler, frame_time: f32, label: Option<&str>) -> Vec<u32> {
+        self.layers.iter_mut()
+            .map(|layer| match layer.enabled {
+                true => layer.simulation.record_advance(wgpu_context, frame_graph, gpu_profiler, frame_time, label),
+                false => 0,
+            })
+            .collect()
+    }
+
+    /// See `Simulation::after_submit`.
+    pub fn after_submit(&mut self, wgpu_context: &WgpuContext) {
+        for layer in self.layers.iter_mut().filter(|layer| layer.enabled) {
+            layer.simulation.after_submit(wgpu_context);
+        }
+    }
+}
@@ -1,141 +1,921 @@
 use std::sync::{Arc};
-use glam::Vec2;
-use wgpu_profiler::{GpuProfiler, GpuProfilerSettings};
+use glam::{UVec2, Vec2};
+use crate::utils::profiler::{GpuProfiler, GpuProfilerSettings};
 use winit::dpi;
-use winit::event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent};
-use winit::event_loop::ActiveEventLoop;
-use winit::keyboard::{KeyCode, PhysicalKey};
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::event_loop::{ActiveEventLoop, ControlFlow};
+use winit::keyboard::KeyCode;
 use winit::window::Window;
-use crate::particles::particle_system::ParticleSystem;
+use crate::particles::particle_initializer::InitialLayout;
 use crate::utils::input_manager::InputManager;
+use crate::utils::input_log::{InputEvent, InputSession};
 use crate::utils::render_timer::RenderTimer;
 use crate::renderer::renderer::Renderer;
+use crate::renderer::profiler_overlay::ProfilerOverlay;
+use crate::renderer::recorder::Recorder;
 use crate::renderer::wgpu_context::WgpuContext;
-use crate::grid::grid::Grid;
-use crate::physics::collision_system::CollisionSystem;
+use crate::grid::morton;
+use crate::grid::cell_occupancy_query::CellOccupancyQuery;
+use crate::grid::velocity_field_drawer::VelocityFieldDrawer;
+use crate::particles::nearest_particle_query::NearestParticleQuery;
+use crate::particles::particle_group::GroupOperation;
+use crate::particles::particle_color_kernel::{ColorMap, ParticleColorSettings};
+use crate::particles::particle_shape::ParticleShape;
+use crate::particles::particle_interaction::InteractionMode;
+use crate::particles::particle_selection::{ParticleSelectionQuery, SelectionRect};
+use crate::particles::heightmap::{Heightmap, HeightmapSettings};
+use crate::particles::region_energy::{RegionEnergyQuery, RegionGridLayout};
 use crate::renderer::renderable::Renderable;
+use crate::utils::telemetry::{EnergySample, SpawnBatchStats, Telemetry};
+use crate::physics::frame_capture;
+use crate::physics::frame_graph::FrameGraph;
+use crate::physics::heat_diffusion::HeatConfig;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::physics::kernel_autotune;
+use crate::physics::particle_sleep::SleepConfig;
+use crate::physics::collision_system::SolverConfig;
+use crate::physics::solver_comparison;
+use crate::physics::stability_watchdog::{StabilityReport, StabilityWatchdog};
+use crate::physics::static_collider_drawer::StaticColliderDrawer;
+use crate::utils::command_queue::{CommandQueue, SimulationCommand};
+use crate::utils::idle_throttle::{IdleThrottle, PowerSavingConfig};
+use crate::utils::present_schedule::{PresentSchedule, PresentSkipConfig};
+use crate::simulation::{Simulation, SimulationStats, DIMENSION};
+use crate::simulation_config::SimulationConfig;
+use crate::simulation_layers::SimulationLayers;
+use crate::particles::color_palette::ColorTheme;
+use crate::scenes::demo;
+use crate::scenes::hourglass::HourglassScene;
+use crate::particles::particle_system::SpawnReport;
+use crate::particles::particle_trails::DEFAULT_TRAIL_LENGTH;
+use crate::utils::telemetry::{RefreshTimer, RefreshTiming};
+#[cfg(feature = "benchmark")]
+use crate::utils::benchmark::BenchmarkHarness;
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+use crate::utils::shader_watcher::ShaderWatcher;
 
-const DIMENSION: u32 = 2; 
+const SOLVER_COMPARISON_FRAMES: u32 = 300;
+const SOLVER_COMPARISON_DELTA_TIME: f32 = 1.0 / 60.0;
+const SNAPSHOT_DIR: &str = "snapshots";
+/// Cache of the kernel tuning of `--autotune`, see `KernelTuning::load`.
+#[cfg(not(target_arch = "wasm32"))]
+const KERNEL_TUNING_PATH: &str = "kernel_tuning.toml";
+/// Directory of the pipeline cache, see `PipelineCache`.
+#[cfg(not(target_arch = "wasm32"))]
+const PIPELINE_CACHE_DIR: &str = "pipeline_cache";
+/// How often debug builds check the WGSL files for changes, see `ShaderWatcher`.
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+const SHADER_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+/// How long a notice, like a refused spawn, stays in the window title.
+const NOTICE_DURATION: std::time::Duration = std::time::Duration::from_secs(3);
+/// Radius of the static circles placed with the right mouse button.
+const STATIC_CIRCLE_RADIUS: f32 = 40.0;
+/// Frames between two kinetic energy samples of the telemetry, and the regions they are split in.
+const REGION_ENERGY_INTERVAL_FRAMES: u64 = 30;
+const REGION_ENERGY_COLUMNS: u32 = 16;
+const REGION_ENERGY_ROWS: u32 = 9;
 
 // This will store the state of the program
 pub struct State {
     world_size: Vec2,
-    wgpu_context: WgpuContext,
     render_timer: RenderTimer,
     renderer: Renderer,
-    particles: ParticleSystem,
-    grid: Grid,
-    collision_system: CollisionSystem,
+    /// Every layer is stepped and drawn, the input goes to the active one
+    layers: SimulationLayers,
+    static_collider_drawer: StaticColliderDrawer,
+    profiler_overlay: ProfilerOverlay,
+    velocity_field_drawer: VelocityFieldDrawer,
     mouse_position: Option<dpi::PhysicalPosition<f64>>,
     gpu_profiler: GpuProfiler,
+    telemetry: Telemetry,
+    pending_capture: Option<std::path::PathBuf>,
+    /// Where the next rendered frame is saved as a PNG, see `Renderer::capture_frame`
+    pending_screenshot: Option<std::path::PathBuf>,
+    recorder: Recorder,
+    /// Logs the input for a replay, or replays a log, see `InputLog`
+    input_session: InputSession,
+    commands: CommandQueue,
+    cell_occupancy_query: CellOccupancyQuery,
+    nearest_particle_query: NearestParticleQuery,
+    particle_selection_query: ParticleSelectionQuery,
+    /// Screen rectangle of the selected particles. The particles inside it are selected again before
+    /// every group operation, since the sort and the removals change the particle indices.
+    selection: Option<SelectionRect>,
+    /// Screen position where the shift-drag selection started
+    selection_drag_start: Option<Vec2>,
+    shift_pressed: bool,
+    window_title: String,
+    stability_watchdog: StabilityWatchdog,
+    region_energy_query: RegionEnergyQuery,
+    paused: bool,
+    status_message: Option<String>,
+    notice: Option<(String, std::time::Instant)>,
+    /// Collision cells over the cap in the last statistics, a notice is shown when they appear
+    overflow_cells: u32,
+    /// Until when the control hints of the demo mode are shown in the window title
+    hints_until: Option<std::time::Instant>,
+    time_scale: f32,
+    frame_index: u64,
+    idle_throttle: IdleThrottle,
+    present_schedule: PresentSchedule,
+    /// Loaded with the O key, its emitter is updated before every physics update
+    hourglass: Option<HourglassScene>,
+    #[cfg(feature = "benchmark")]
+    benchmark: BenchmarkHarness,
+    /// Reloads the edited compute shaders between frames
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    shader_watcher: ShaderWatcher,
+    /// Set once `shutdown` ran
+    shut_down: bool,
+    // Declared last so it is dropped last: every GPU resource above was created from its device
+    wgpu_context: WgpuContext,
 }
 
 impl State {
-    pub async fn new(window: Arc<Window>) -> anyhow::Result<Self> {
-        let world_size = Vec2::new(3048.0, 1048.0);
-        let wgpu_context = WgpuContext::new(window).await?;
+    /// Opens the simulation of `config` in `window`, or the demo scene of `scenes::demo` sized for the adapter if `demo` is set.
+    /// The input comes from the window or from the log of `input_session`. With `autotune`, the kernels are created
+    /// with the workgroup sizes measured on the adapter, before the first frame. The web build has no cache to keep
+    /// them in and ignores it. `measure_queue_drain` times the refreshes of the spawns with a stall, see
+    /// `WgpuContext::set_measure_queue_drain`.
+    pub async fn new(window: Arc<Window>, config: SimulationConfig, demo: bool, autotune: bool, measure_queue_drain: bool, mut input_session: InputSession) -> anyhow::Result<Self> {
+        let mut wgpu_context = WgpuContext::new(window).await?;
+        wgpu_context.set_measure_queue_drain(measure_queue_drain);
+        // Before the first pipeline is created, the autotune ones included
+        #[cfg(not(target_arch = "wasm32"))]
+        wgpu_context.load_pipeline_cache(PIPELINE_CACHE_DIR);
+        #[cfg(not(target_arch = "wasm32"))]
+        if autotune {
+            kernel_autotune::load_or_autotune(&mut wgpu_context, KERNEL_TUNING_PATH);
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = autotune;
+        // Seeded before anything random is drawn, the initial particles included
+        input_session.begin(wgpu_context.window_size().as_uvec2());
+        let config = if demo {
+            demo::demo_config(&wgpu_context.get_adapter().get_info(), &wgpu_context.get_device().limits())?
+        } else {
+            config
+        };
+        let world_size = config.world_size_in_world_units();
         let renderer = Renderer::new(&wgpu_context, &world_size).unwrap();
 
-        let particles = ParticleSystem::new(&wgpu_context, renderer.camera(), world_size);
-        let grid =  Grid::new(&wgpu_context, renderer.camera(), world_size, &particles);
+        // Every run gets its own theme, unless the config picks one
+        let palette = config.palette.unwrap_or_else(ColorTheme::random);
+        log::info!("Color theme: seed {}, {}", palette.seed, palette.harmony.name());
+        let config = SimulationConfig { palette: Some(palette), ..config };
+        let mut simulation = Simulation::with_config(&wgpu_context, config, InitialLayout::HexPacking);
+        Self::attach_layer_drawers(&wgpu_context, &renderer, &mut simulation);
 
+        let cell_occupancy_query = CellOccupancyQuery::new(&wgpu_context, simulation.grid());
+        let nearest_particle_query = NearestParticleQuery::new(&wgpu_context, simulation.particles());
+        let particle_selection_query = ParticleSelectionQuery::new(&wgpu_context);
+        let stability_watchdog = StabilityWatchdog::new(&wgpu_context, simulation.particles());
+        let region_energy_query = RegionEnergyQuery::new(&wgpu_context, RegionGridLayout::covering(
+            simulation.particles().get_world_origin(),
+            simulation.particles().get_world_size(),
+            REGION_ENERGY_COLUMNS,
+            REGION_ENERGY_ROWS,
+        ));
+        let static_collider_drawer = StaticColliderDrawer::new(&wgpu_context, renderer.camera());
+        let profiler_overlay = ProfilerOverlay::new(&wgpu_context, renderer.camera());
+        let velocity_field_drawer = VelocityFieldDrawer::new(&wgpu_context, renderer.camera());
         let render_timer = RenderTimer::new();
 
         let mouse_position = None;
         
         
-        #[cfg(feature = "benchmark")]
-        let gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default())?;
-        #[cfg(not(feature = "benchmark"))]
-        let gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings{
-            enable_timer_queries: false,
-            enable_debug_groups: false,
-            max_num_pending_frames: 1
-        })?;
-        
-        let collision_system = CollisionSystem::new(&wgpu_context, DIMENSION, &particles, &grid);
+        let gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), Self::profiler_settings(false))?;
         
-        Ok(Self {
+        // Throttling skips physics steps, which a replay would not repeat
+        let power_saving = !cfg!(feature = "benchmark") && input_session.is_live();
+        let mut state = Self {
             world_size,
             wgpu_context,
             render_timer,
-            particles,
+            layers: SimulationLayers::new("main", simulation),
+            static_collider_drawer,
+            profiler_overlay,
+            velocity_field_drawer,
             renderer,
             mouse_position,
-            grid,
             gpu_profiler,
-            collision_system
-        })
+            telemetry: Telemetry::new(),
+            pending_capture: None,
+            pending_screenshot: None,
+            recorder: Recorder::new(config.recording),
+            input_session,
+            commands: CommandQueue::new(),
+            cell_occupancy_query,
+            nearest_particle_query,
+            particle_selection_query,
+            selection: None,
+            selection_drag_start: None,
+            shift_pressed: false,
+            window_title: String::new(),
+            stability_watchdog,
+            region_energy_query,
+            paused: false,
+            status_message: None,
+            notice: None,
+            overflow_cells: 0,
+            hints_until: None,
+            time_scale: 1.0,
+            frame_index: 0,
+            // Benchmarks and logged runs must not slow down when the window loses the focus
+            idle_throttle: IdleThrottle::new(PowerSavingConfig {
+                enabled: power_saving,
+                ..PowerSavingConfig::default()
+            }),
+            present_schedule: PresentSchedule::new(PresentSkipConfig::default()),
+            hourglass: None,
+            #[cfg(feature = "benchmark")]
+            benchmark: BenchmarkHarness::new(),
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            shader_watcher: ShaderWatcher::new(SHADER_POLL_INTERVAL),
+            shut_down: false,
+        };
+        if config.recording.record_on_start {
+            state.toggle_recording();
+        }
+        if demo {
+            state.toggle_profiler_overlay();
+            state.hints_until = Some(std::time::Instant::now() + demo::HINTS_DURATION);
+        }
+        // Every startup pipeline exists by now, the next run starts from them even if this one crashes
+        state.save_pipeline_cache();
+        Ok(state)
+    }
 
+    /// Attaches the particle drawer of the layer, and builds the drawer of its grid.
+    fn attach_layer_drawers(wgpu_context: &WgpuContext, renderer: &Renderer, simulation: &mut Simulation) {
+        simulation.particles_mut().attach_drawer(wgpu_context, renderer.camera());
+        // The next frame's physics is submitted while this one is drawn
+        simulation.particles_mut().set_double_buffered(wgpu_context, true);
+        let world_size = simulation.particles().get_world_size();
+        simulation.grid_mut().refresh_drawer(wgpu_context, renderer.camera(), world_size);
+    }
+
+    /// Adds a layer with the particles of `config` on top of the others and returns its index, see `SimulationLayers`.
+    /// Its particles do not collide with the ones of the other layers. The palette of the active layer is used if
+    /// `config` has none.
+    pub fn add_layer(&mut self, name: &str, config: SimulationConfig, layout: InitialLayout) -> usize {
+        let palette = config.palette.or(self.layers.active().config().palette);
+        let config = SimulationConfig { palette, ..config };
+        let mut simulation = Simulation::with_config(&self.wgpu_context, config, layout);
+        Self::attach_layer_drawers(&self.wgpu_context, &self.renderer, &mut simulation);
+        self.layers.add(name, simulation)
+    }
+
+    pub fn layers(&self) -> &SimulationLayers {
+        &self.layers
+    }
+
+    /// Sends the input to the layer at `index`, the queries and the collider drawer follow it.
+    pub fn set_active_layer(&mut self, index: usize) {
+        if index == self.layers.active_index() || index >= self.layers.len() {
+            return;
+        }
+        self.layers.set_active(index);
+        self.on_active_layer_changed();
+    }
+
+    fn cycle_active_layer(&mut self) {
+        if self.layers.len() > 1 {
+            self.layers.cycle_active();
+            self.on_active_layer_changed();
+        }
+    }
+
+    fn on_active_layer_changed(&mut self) {
+        self.selection = None;
+        self.static_collider_drawer.update(&self.wgpu_context, self.layers.active().static_circles(), self.layers.active().static_segments());
+        self.refresh_after_particle_change();
+        let message = format!("Layer {}/{}: {}", self.layers.active_index() + 1, self.layers.len(), self.layers.active_layer().name());
+        self.show_notice(message);
     }
 
-    
     /// This function is called every frame
     pub fn render_loop(&mut self, event: &WindowEvent, event_loop: &ActiveEventLoop){
         match event {
             WindowEvent::CloseRequested => event_loop.exit(),
             WindowEvent::Resized(size ) => self.wgpu_context.resize(size.width, size.height),
-            WindowEvent::RedrawRequested => self.update_and_redraw(),
-            WindowEvent::KeyboardInput {
-                event:
-                    KeyEvent {
-                        physical_key: PhysicalKey::Code(code),
-                        state: key_state,
-                        ..
-                    },
-                ..
-            } => InputManager::process_keyboard_input(self, event_loop, code, key_state),
-            WindowEvent::CursorMoved { position, .. } => InputManager::process_cursor_moved(self, position),
-            WindowEvent::MouseInput {state: mouse_state, button: mouse_button, ..} => InputManager::process_mouse_input(self, mouse_state, mouse_button),
-            WindowEvent::MouseWheel { delta, .. } => InputManager::process_mouse_wheel(self, *delta), 
-            _ => {}
+            WindowEvent::RedrawRequested => self.update_and_redraw(event_loop),
+            WindowEvent::Focused(focused) => self.set_focused(*focused),
+            _ => {
+                if let Some(input) = InputEvent::from_window_event(event) {
+                    self.process_window_input(event_loop, input);
+                }
+            }
+        }
+        // Input is answered right away, even while throttled
+        if matches!(event, WindowEvent::KeyboardInput { .. } | WindowEvent::MouseInput { .. } | WindowEvent::MouseWheel { .. } | WindowEvent::CursorMoved { .. })
+            && self.idle_throttle.is_throttled(self.paused) {
+            self.wgpu_context.get_window().request_redraw();
         }
     }
-    
-    fn update_and_redraw(&mut self) {
-        self.update();
-        match self.render() {
-            Ok(_) => {}
-            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
-                let size = self.wgpu_context.window_size();
-                self.wgpu_context.resize(size.x as u32, size.y as u32);
+
+    /// Handles the input of the window, logging it while recording. While replaying, only Escape is taken
+    /// from the window, the rest of the input comes from the log.
+    fn process_window_input(&mut self, event_loop: &ActiveEventLoop, input: InputEvent) {
+        if self.input_session.is_replaying() {
+            if matches!(input, InputEvent::Key { code: KeyCode::Escape, pressed: true }) {
+                event_loop.exit();
+            }
+            return;
+        }
+        self.input_session.record_event(input);
+        InputManager::process_input_event(self, event_loop, &input);
+    }
+
+    /// Called when the event loop runs out of events. Draws the next throttled frame once it is due.
+    pub fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if self.idle_throttle.is_throttled(self.paused) {
+            if self.idle_throttle.is_frame_due(std::time::Instant::now()) {
+                self.wgpu_context.get_window().request_redraw();
             }
-            Err(e) => {
-                log::error!("Unable to render: {:?}", e);
+            else {
+                event_loop.set_control_flow(ControlFlow::WaitUntil(self.idle_throttle.next_frame_time()));
+            }
+        }
+    }
+
+    fn set_focused(&mut self, focused: bool) {
+        self.idle_throttle.set_focused(focused);
+        if focused {
+            // The time spent unfocused must not end up in the next physics step
+            self.render_timer.restart_delta();
+            self.wgpu_context.get_window().request_redraw();
+        }
+        log::info!("Window {}", if focused { "focused" } else { "unfocused, throttling" });
+    }
+
+    /// Low power mode options, see `PowerSavingConfig`.
+    pub fn set_power_saving(&mut self, config: PowerSavingConfig) {
+        self.idle_throttle.set_config(config);
+        self.wgpu_context.get_window().request_redraw();
+    }
+
+    pub fn get_power_saving(&self) -> PowerSavingConfig {
+        self.idle_throttle.config()
+    }
+
+    /// Present skip options, see `PresentSkipConfig`.
+    pub fn set_present_skip(&mut self, config: PresentSkipConfig) {
+        self.present_schedule.set_config(config);
+    }
+
+    pub fn get_present_skip(&self) -> PresentSkipConfig {
+        self.present_schedule.config()
+    }
+
+    fn update_and_redraw(&mut self, event_loop: &ActiveEventLoop) {
+        for input in self.input_session.replay_events() {
+            InputManager::process_input_event(self, event_loop, &input);
+        }
+        let present = self.update();
+        if present {
+            match self.render() {
+                Ok(_) => {}
+                Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                    let size = self.wgpu_context.window_size();
+                    self.wgpu_context.resize(size.x as u32, size.y as u32);
+                }
+                Err(e) => {
+                    log::error!("Unable to render: {:?}", e);
+                }
             }
         }
 
         self.gpu_profiler.end_frame().unwrap();
-        #[cfg(feature = "benchmark")]
-        if let Some(profiling_data) = self.gpu_profiler.process_finished_frame(self.wgpu_context.get_queue().get_timestamp_period()) {
-            wgpu_profiler::chrometrace::write_chrometrace(std::path::Path::new("benchmark.json"), &profiling_data).unwrap();
+        self.schedule_next_frame(event_loop);
+        self.process_finished_profiler_frames();
+    }
+
+    /// Settings of the profiler: the timer queries only run while the overlay shows them, or always in benchmark builds.
+    fn profiler_settings(timer_queries: bool) -> GpuProfilerSettings {
+        if cfg!(feature = "benchmark") {
+            return GpuProfilerSettings::default();
+        }
+        GpuProfilerSettings {
+            enable_timer_queries: timer_queries,
+            enable_debug_groups: false,
+            // The queries are read back a frame or two later
+            max_num_pending_frames: if timer_queries { 3 } else { 1 },
         }
     }
-    
-    fn update(&mut self){
-        let dt = self.render_timer.get_delta().as_secs_f32();
-        
+
+    /// Feeds the profiler frames whose queries were read back to the overlay, and writes them into `benchmark.json`.
+    fn process_finished_profiler_frames(&mut self) {
+        let timestamp_period = self.wgpu_context.get_queue().get_timestamp_period();
+        while let Some(profiling_data) = self.gpu_profiler.process_finished_frame(timestamp_period) {
+            self.profiler_overlay.record_frame(&profiling_data);
+            // Warm-up frames and frames before the steady state would skew the trace
+            #[cfg(feature = "benchmark")]
+            if self.benchmark.is_collecting() {
+                if let Err(e) = wgpu_profiler::chrometrace::write_chrometrace(std::path::Path::new("benchmark.json"), &profiling_data) {
+                    log::error!("Unable to write benchmark.json: {:?}", e);
+                }
+            }
+        }
+    }
+
+    /// Shows or hides the GPU profiler overlay, turning the timer queries on while it is shown.
+    fn toggle_profiler_overlay(&mut self) {
+        let visible = !self.profiler_overlay.is_visible();
+        self.profiler_overlay.set_visible(visible);
+        if let Err(e) = self.gpu_profiler.change_settings(Self::profiler_settings(visible)) {
+            log::error!("Unable to change the profiler settings: {:?}", e);
+        }
+    }
+
+    /// Waits for every submitted command buffer, so the pending readbacks and profiler frames complete,
+    /// then writes the benchmark trace and report and logs the telemetry summary. Called when the app
+    /// exits; also run on drop if it was not called. The GPU resources are released afterwards, by the
+    /// drop of the fields, with the context last.
+    pub fn shutdown(&mut self) {
+        if self.shut_down {
+            return;
+        }
+        self.shut_down = true;
+
+        if let Err(e) = self.wgpu_context.get_device().poll(wgpu::wgt::PollType::Wait) {
+            log::error!("Unable to wait for the GPU before exiting: {:?}", e);
+        }
+        // Writes the frames of an unfinished recording
+        self.recorder.stop(&self.wgpu_context);
+        if let Err(e) = self.input_session.finish() {
+            log::error!("Unable to save the input log: {:?}", e);
+        }
+        // Resolves the readbacks that were still in flight, their staging buffers get unmapped
+        self.stability_watchdog.poll(&self.wgpu_context);
+        self.cell_occupancy_query.poll(&self.wgpu_context);
+        self.nearest_particle_query.poll(&self.wgpu_context);
+        // With the pipelines of the layers and scenes created since the startup
+        self.save_pipeline_cache();
+
+        #[cfg(feature = "benchmark")]
         {
-            let mut encoder = self.wgpu_context.get_device().create_command_encoder(
-                &wgpu::CommandEncoderDescriptor { label: Some("Compute Encoder") }
-            );
-            if self.particles.is_it_time_to_sort(){
-                self.particles.sort_by_cell_id(&mut encoder, &mut self.gpu_profiler, self.grid.cell_size());
-                self.particles.reset_last_sort_time();                
+            self.process_finished_profiler_frames();
+            match self.benchmark.report() {
+                Some(report) => println!("{}", report),
+                None => println!("Benchmark: steady state was never reached ({:?}), no statistics collected", self.benchmark.phase()),
             }
-            self.grid.update(&mut encoder, &mut self.gpu_profiler);
-            self.collision_system.solve_collisions(&self.wgpu_context, encoder, &mut self.gpu_profiler);
         }
-        
-        self.particles.update_positions(dt, &self.wgpu_context, &mut self.gpu_profiler);
-        
-        // Update renderer with delta time (includes camera update)
+        log::info!("{}", self.telemetry.summary());
+        log::info!("{}", self.wgpu_context.buffers().report());
+    }
+    
+    /// Draws the next frame right away, or at the idle frame rate if throttled.
+    fn schedule_next_frame(&mut self, event_loop: &ActiveEventLoop) {
+        let now = std::time::Instant::now();
+        self.idle_throttle.frame_drawn(now);
+        if self.idle_throttle.is_throttled(self.paused) {
+            event_loop.set_control_flow(ControlFlow::WaitUntil(self.idle_throttle.next_frame_time()));
+        }
+        else {
+            event_loop.set_control_flow(ControlFlow::Wait);
+            self.wgpu_context.get_window().request_redraw();
+        }
+    }
+
+    /// Processes the commands, steps the physics and updates the camera. Returns false if the
+    /// frame should not be presented, see `PresentSchedule`.
+    fn update(&mut self) -> bool {
+        let frame_time = self.input_session.end_frame(self.render_timer.get_delta());
+        #[cfg(feature = "benchmark")]
+        self.benchmark.record_frame(frame_time);
+
+        // Every change requested since the last frame is applied here, before any GPU work is recorded
+        self.process_commands();
+        #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+        self.reload_shaders();
+        self.frame_index += 1;
+        let dt = frame_time.as_secs_f32();
+        let physics_dt = dt * self.time_scale;
+        let mut present = true;
+
+        // Update renderer with delta time (includes camera update), the hover queries of the frame follow it
         self.renderer.update(dt, &self.wgpu_context, &mut self.gpu_profiler);
+        // The steps of every layer and the passes that follow them share one encoder, submitted once
+        let mut frame_graph = FrameGraph::new(&self.wgpu_context, "Physics frame encoder");
+        let mut stepped = None;
+        if self.idle_throttle.should_step_physics(self.paused) {
+            self.update_hourglass(physics_dt);
+            let step_start = std::time::Instant::now();
+            // The first frame after a spawn batch carries its label, so it can be found in GPU captures
+            let frame_label = self.telemetry.take_frame_label();
+            let steps = if let Some(dir) = self.pending_capture.take() {
+                // A single pass of the active layer, with the delta time of a substep
+                let capture_dt = self.layers.active().config().timestep.map_or(physics_dt, |timestep| timestep.substep_time());
+                self.capture_physics_step(capture_dt, &dir, frame_label.as_deref());
+                let mut steps = vec![0; self.layers.len()];
+                steps[self.layers.active_index()] = 1;
+                steps
+            }
+            else {
+                self.layers.record_advance(&self.wgpu_context, &mut frame_graph, &mut self.gpu_profiler, physics_dt, frame_label.as_deref())
+            };
+            for (layer, &layer_steps) in self.layers.iter_mut().zip(&steps) {
+                if layer_steps > 0 {
+                    let simulation = layer.simulation_mut();
+                    let step_dt = simulation.config().timestep.map_or(physics_dt, |timestep| timestep.substep_time());
+                    simulation.record_particle_colors(&self.wgpu_context, frame_graph.encoder(), &self.gpu_profiler, step_dt);
+                    simulation.record_sprite_animation(frame_graph.encoder(), &self.gpu_profiler, layer_steps as f32 * step_dt, step_dt);
+                    simulation.record_trails(&self.wgpu_context, frame_graph.encoder(), &self.gpu_profiler);
+                }
+            }
+            // The tools that read the particles back follow the active layer
+            let steps = steps[self.layers.active_index()];
+            if steps > 0 {
+                let step_dt = self.layers.active().config().timestep.map_or(physics_dt, |timestep| timestep.substep_time());
+                let particles = self.layers.active().particles();
+                self.stability_watchdog.record(&self.wgpu_context, frame_graph.encoder(), particles);
+                self.velocity_field_drawer.record_update(&self.wgpu_context, frame_graph.encoder(), &self.gpu_profiler, particles, step_dt, self.layers.active().grid().cell_size());
+                if self.frame_index.is_multiple_of(REGION_ENERGY_INTERVAL_FRAMES) {
+                    self.region_energy_query.record_measure(&self.wgpu_context, frame_graph.encoder(), &self.gpu_profiler, particles, step_dt);
+                    self.region_energy_query.record_request(&self.wgpu_context, frame_graph.encoder());
+                }
+            }
+            stepped = Some((steps, step_start));
+        }
+        // The copies the window draws, submitted with the steps so the next frame's steps do not wait for the draw
+        for layer in self.layers.iter_mut().filter(|layer| layer.is_enabled()) {
+            layer.simulation_mut().particles_mut().record_publish_display(&self.wgpu_context, frame_graph.encoder());
+        }
+        self.record_hover_queries(frame_graph.encoder());
+        frame_graph.submit(&self.wgpu_context, &mut self.gpu_profiler);
+        if stepped.is_some() {
+            self.layers.after_submit(&self.wgpu_context);
+        }
+        self.stability_watchdog.after_submit();
+        self.region_energy_query.after_submit();
+        self.cell_occupancy_query.after_submit();
+        self.nearest_particle_query.after_submit();
+        if let Some((steps, step_start)) = stepped.filter(|(steps, _)| *steps > 0) {
+            // The CPU time only covers the recording, unless the GPU queue is full
+            let step_time = self.layers.active().last_step_gpu_time().map_or_else(|| step_start.elapsed(), |gpu_time| gpu_time * steps);
+            present = self.present_schedule.step_finished(step_time);
+        }
+
+        if let Some(report) = self.stability_watchdog.poll(&self.wgpu_context)
+            && !report.is_stable() && !self.paused {
+            self.handle_instability(report);
+        }
+        if let Some(map) = self.region_energy_query.poll(&self.wgpu_context) {
+            self.telemetry.record_energy_sample(EnergySample::from_map(&map));
+        }
+        self.update_overflow_notice();
+
+        for layer in self.layers.iter_mut().filter(|layer| layer.is_enabled()) {
+            layer.simulation_mut().grid_mut().update_cell_labels(&self.wgpu_context, self.renderer.camera());
+            layer.simulation_mut().grid_mut().update_debug_view(&self.wgpu_context, &mut self.gpu_profiler, self.renderer.camera());
+        }
+        self.profiler_overlay.update(&self.wgpu_context, self.renderer.camera(), &self.wgpu_context.window_size());
+        self.update_cell_readout();
+        // Everything uploaded, copied or read back since the last frame
+        self.telemetry.record_frame_transfers(self.wgpu_context.transfers().take());
+        present
+    }
+
+    /// Pauses the physics and dumps the particle state plus the last commands, so the
+    /// instability can be reproduced. The report arrives a frame or two after the step that caused it.
+    fn handle_instability(&mut self, report: StabilityReport) {
+        self.paused = true;
+        let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs();
+        let dir = std::path::Path::new(SNAPSHOT_DIR).join(format!("instability_{}", timestamp));
+
+        let mut snapshot = frame_capture::capture_particle_state(&self.wgpu_context, self.layers.active_mut().particles_mut());
+        snapshot.set_metadata("frame", self.frame_index);
+        snapshot.set_metadata("invalid_particles", report.invalid_particles);
+        if let Some(first_invalid_particle) = report.first_invalid_particle {
+            snapshot.set_metadata("first_invalid_particle", first_invalid_particle);
+        }
+        snapshot.set_metadata("time_scale", self.time_scale);
+        let solver_config = self.layers.active().collision_system().solver_config();
+        snapshot.set_metadata("solver_iterations", solver_config.iterations);
+        snapshot.set_metadata("solver_method", format!("{:?}", solver_config.method));
+        snapshot.set_metadata("solver_stiffness", solver_config.stiffness);
+        snapshot.set_metadata("solver_slop", solver_config.slop);
+
+        let commands: String = self.commands.history()
+            .map(|executed| format!("{} {:?}\n", executed.frame, executed.command))
+            .collect();
+        let saved = snapshot.save(&dir).and_then(|_| std::fs::write(dir.join("commands.txt"), commands));
+
+        let message = match saved {
+            Ok(_) => format!("PAUSED: {} unstable particles, state saved to {} (Space to resume)", report.invalid_particles, dir.display()),
+            Err(e) => format!("PAUSED: {} unstable particles, unable to save the state: {:?} (Space to resume)", report.invalid_particles, e),
+        };
+        log::error!("{}", message);
+        self.status_message = Some(message);
+    }
+
+    fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        if !self.paused {
+            self.status_message = None;
+        }
+        log::info!("Physics {}", if self.paused { "paused" } else { "resumed" });
+    }
+
+    /// Shows the cell under the cursor in the window title: world position, cell coordinates,
+    /// morton cell id, solver color group, how many objects touch it and the hovered particle, which is also highlighted.
+    /// The occupancy and the particle index arrive a few frames late.
+    /// Status messages, like an automatic pause, recent notices, the control hints of the demo mode and the statistics
+    /// of the hourglass demo are shown before it.
+    fn update_cell_readout(&mut self) {
+        if self.notice.as_ref().is_some_and(|(_, shown_at)| shown_at.elapsed() >= NOTICE_DURATION) {
+            self.notice = None;
+        }
+        if self.hints_until.is_some_and(|until| std::time::Instant::now() >= until) {
+            self.hints_until = None;
+        }
+        let mouse_position = self.mouse_position;
+        let readout = mouse_position.map(|_| {
+            let (world_position, cell) = self.hovered_cell();
+            let cell_id = morton::encode(cell);
+            let occupancy = match self.cell_occupancy_query.poll(&self.wgpu_context) {
+                Some(result) if result.cell_id == cell_id => result.objects.to_string(),
+                _ => "...".to_string(),
+            };
+
+            let hovered = match self.nearest_particle_query.poll(&self.wgpu_context).and_then(|result| result.nearest) {
+                Some(nearest) => nearest.particle.to_string(),
+                None => "-".to_string(),
+            };
+            format!(
+                "World ({:.1}, {:.1}) | Cell ({}, {}) | Morton id {} | Color {} | Objects {} | Particle {}",
+                world_position.x, world_position.y, cell.x, cell.y, cell_id, morton::cell_color(cell), occupancy, hovered
+            )
+        });
+
+        let notice = self.notice.as_ref().map(|(message, _)| message.clone());
+        let hourglass = self.hourglass.as_ref().map(|scene| scene.stats(self.layers.active()).to_string());
+        let hints = self.hints_until.map(|_| demo::CONTROL_HINTS.to_string());
+        let title = [self.status_message.clone(), notice, hints, hourglass, readout].into_iter().flatten().collect::<Vec<_>>().join(" | ");
+        if title != self.window_title {
+            self.wgpu_context.get_window().set_title(&title);
+            self.window_title = title;
+        }
     }
     
+    /// World position of the cursor and the cell of the active layer's grid under it. The cursor must be in the window.
+    fn hovered_cell(&self) -> (Vec2, UVec2) {
+        let world_position = self.get_mouse_world_position();
+        let grid = self.layers.active().grid();
+        (world_position, morton::cell_coord(world_position - grid.origin(), grid.cell_size()))
+    }
+
+    /// Records the occupancy count of the cell and the pick of the particle under the cursor, after the steps
+    /// of the frame. Their results are shown by `update_cell_readout` once they arrive.
+    fn record_hover_queries(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if self.mouse_position.is_none() {
+            return;
+        }
+        let (world_position, cell) = self.hovered_cell();
+        self.cell_occupancy_query.record_request(&self.wgpu_context, encoder, self.layers.active().grid(), morton::encode(cell));
+        self.nearest_particle_query.record_request(&self.wgpu_context, encoder, self.layers.active().particles(), world_position);
+    }
+
+    fn process_commands(&mut self) {
+        for command in self.commands.drain(self.frame_index) {
+            self.execute_command(command);
+        }
+    }
+
+    fn execute_command(&mut self, command: SimulationCommand) {
+        match command {
+            SimulationCommand::SpawnParticles { position } => {
+                let label = self.telemetry.next_spawn_label();
+                self.add_particles_labeled(position, label);
+            }
+            SimulationCommand::AddParticleBatch(count) => self.add_particle_batch(count),
+            SimulationCommand::RemoveParticleBatch(count) => self.remove_particle_batch(count),
+            SimulationCommand::ToggleGrid => self.layers.active_mut().grid_mut().toggle_grid_drawing(),
+            SimulationCommand::CycleGridDebugView => self.layers.active_mut().grid_mut().cycle_debug_view(),
+            SimulationCommand::ToggleTrails => {
+                let length = match self.layers.active().trail_length() {
+                    Some(_) => None,
+                    None => Some(DEFAULT_TRAIL_LENGTH),
+                };
+                self.layers.active_mut().set_trails(&self.wgpu_context, length);
+            }
+            SimulationCommand::ToggleVelocityField => {
+                let visible = !self.velocity_field_drawer.is_visible();
+                self.velocity_field_drawer.set_visible(visible);
+            }
+            SimulationCommand::ToggleDensityHeatmap => {
+                let enabled = !self.renderer.is_density_heatmap_enabled();
+                self.renderer.set_density_heatmap(&self.wgpu_context, enabled);
+            }
+            SimulationCommand::TogglePause => self.toggle_pause(),
+            SimulationCommand::ApplyImpulse { position, active } => {
+                if active && self.layers.active().particles().interaction().mode == InteractionMode::Pick {
+                    self.layers.active_mut().pick_particle(&self.wgpu_context, position);
+                }
+                self.layers.active_mut().particles_mut().mouse_click_callback(active, position);
+            }
+            SimulationCommand::MoveImpulse { position } => self.layers.active_mut().particles_mut().mouse_move_callback(position),
+            SimulationCommand::SetInteractionMode(mode) => {
+                if mode == InteractionMode::Heat {
+                    self.enable_heat();
+                }
+                self.layers.active_mut().particles_mut().interaction_mut().mode = mode;
+                self.show_interaction_notice();
+            }
+            SimulationCommand::ScaleInteractionRadius(factor) => {
+                let interaction = self.layers.active_mut().particles_mut().interaction_mut();
+                interaction.set_radius(interaction.radius() * factor);
+                self.show_interaction_notice();
+            }
+            SimulationCommand::ScaleInteractionStrength(factor) => {
+                let interaction = self.layers.active_mut().particles_mut().interaction_mut();
+                interaction.set_strength(interaction.strength() * factor);
+                self.show_interaction_notice();
+            }
+            SimulationCommand::SetTimeScale(time_scale) => {
+                self.time_scale = time_scale.max(0.0);
+                log::info!("Time scale: {}", self.time_scale);
+            }
+            SimulationCommand::SetGravity(gravity) => {
+                self.layers.active_mut().set_gravity(gravity);
+                // The sleeping particles would not notice
+                self.layers.active_mut().wake_particles(&self.wgpu_context);
+                self.show_notice(format!("Gravity: ({:.2}, {:.2}) m/s²", gravity.x, gravity.y));
+            }
+            SimulationCommand::ChangeSolverIterations(delta) => self.change_solver_iterations(delta),
+            SimulationCommand::CompareSolvers => self.compare_solver_variant(),
+            SimulationCommand::CaptureFrame(dir) => self.pending_capture = Some(dir),
+            SimulationCommand::ExportHeightmap(path) => self.export_heightmap(&path),
+            SimulationCommand::CaptureScreenshot(path) => self.pending_screenshot = Some(path),
+            SimulationCommand::ToggleRecording => self.toggle_recording(),
+            SimulationCommand::SelectParticles(rect) => self.select_particles(rect),
+            SimulationCommand::ApplyToSelection(operation) => self.apply_to_selection(operation),
+            SimulationCommand::SetParticleColors(settings) => self.layers.active_mut().set_particle_colors(&self.wgpu_context, settings),
+            SimulationCommand::SetColorTheme(theme) => self.set_color_theme(theme),
+            SimulationCommand::SetParticleShape(shape) => {
+                self.layers.active_mut().set_particle_shape(&self.wgpu_context, shape);
+                self.show_notice(format!("Particle shape: {}", shape.name()));
+            }
+            SimulationCommand::AddStaticCircle { center, radius } => {
+                self.layers.active_mut().add_static_circle(&self.wgpu_context, center, radius);
+                self.static_collider_drawer.update(&self.wgpu_context, self.layers.active().static_circles(), self.layers.active().static_segments());
+            }
+            SimulationCommand::RemoveStaticCircleAt { position } => {
+                if self.layers.active_mut().remove_static_circle_at(&self.wgpu_context, position).is_some() {
+                    self.static_collider_drawer.update(&self.wgpu_context, self.layers.active().static_circles(), self.layers.active().static_segments());
+                }
+            }
+            SimulationCommand::LoadHourglassDemo => self.load_hourglass(),
+            SimulationCommand::ToggleProfilerOverlay => self.toggle_profiler_overlay(),
+            SimulationCommand::CycleActiveLayer => self.cycle_active_layer(),
+            SimulationCommand::ToggleSleep => self.toggle_sleep(),
+        }
+    }
+
+    /// Paints the particles and the next spawn batches with the palette of `theme`.
+    fn set_color_theme(&mut self, theme: ColorTheme) {
+        let config = SimulationConfig { palette: Some(theme), ..*self.layers.active().config() };
+        self.layers.active_mut().set_config(config);
+        self.layers.active_mut().particles_mut().recolor_with_palette(&self.wgpu_context);
+        self.show_notice(format!("Color theme: seed {}, {}", theme.seed, theme.harmony.name()));
+    }
+
+    /// Replaces the particles and the colliders with the hourglass demo, see `HourglassScene`.
+    fn load_hourglass(&mut self) {
+        let scene = HourglassScene::load(&self.wgpu_context, &mut self.gpu_profiler, self.layers.active_mut());
+        self.static_collider_drawer.update(&self.wgpu_context, self.layers.active().static_circles(), self.layers.active().static_segments());
+        self.selection = None;
+        self.refresh_after_particle_change();
+        self.hourglass = Some(scene);
+        self.show_notice("Hourglass demo loaded".to_string());
+    }
+
+    /// Pours the particles of the hourglass demo, if loaded.
+    fn update_hourglass(&mut self, delta_time: f32) {
+        let Some(scene) = self.hourglass.as_mut() else {
+            return;
+        };
+        let (report, refreshes) = scene.update(&self.wgpu_context, self.layers.active_mut(), delta_time);
+        if report.spawned + report.recycled + report.refused > 0 {
+            let label = self.telemetry.next_spawn_label();
+            self.finish_spawn(report, refreshes, label);
+        }
+    }
+
+    /// Enables the heat of the active layer, for the heat mouse interaction, and shows the temperatures.
+    fn enable_heat(&mut self) {
+        let simulation = self.layers.active_mut();
+        if simulation.heat_config().is_some() {
+            return;
+        }
+        simulation.enable_heat(&self.wgpu_context, HeatConfig::default());
+        simulation.set_particle_colors(&self.wgpu_context, Some(ParticleColorSettings::temperature(ColorMap::Heat)));
+    }
+
+    /// Lets the settled particles of the active layer fall asleep, or wakes them all and stops.
+    fn toggle_sleep(&mut self) {
+        let simulation = self.layers.active_mut();
+        if simulation.sleep_config().is_some() {
+            simulation.disable_sleep(&self.wgpu_context);
+            self.show_notice("Sleep: off".to_string());
+            return;
+        }
+        simulation.enable_sleep(&self.wgpu_context, SleepConfig::default());
+        self.show_notice("Sleep: on".to_string());
+    }
+
+    fn show_interaction_notice(&mut self) {
+        let interaction = self.layers.active().particles().interaction();
+        self.show_notice(format!("Mouse: {} (radius {:.0}, strength {:.0})", interaction.mode.name(), interaction.radius(), interaction.strength()));
+    }
+
+    fn select_particles(&mut self, rect: SelectionRect) {
+        let selected = self.particle_selection_query.select(&self.wgpu_context, self.layers.active().particles(), &rect);
+        self.selection = (!selected.is_empty()).then_some(rect);
+        self.show_notice(format!("{} particles selected (C recolor, F freeze, Delete remove)", selected.len()));
+    }
+
+    fn apply_to_selection(&mut self, operation: GroupOperation) {
+        let Some(rect) = self.selection else {
+            return;
+        };
+        let selected = self.particle_selection_query.select(&self.wgpu_context, self.layers.active().particles(), &rect);
+        let removed = self.layers.active_mut().apply_group_operation(&self.wgpu_context, &mut self.gpu_profiler, &selected, operation);
+        if operation == GroupOperation::Remove {
+            self.selection = None;
+            self.show_notice(format!("{} particles removed", removed));
+        }
+    }
+
+    fn export_heightmap(&mut self, path: &std::path::Path) {
+        let heightmap = Heightmap::from_particle_system(&self.wgpu_context, self.layers.active_mut().particles_mut(), HeightmapSettings::default());
+        match heightmap.save_png(path) {
+            Ok(_) => log::info!("Heightmap saved to {}", path.display()),
+            Err(e) => log::error!("Unable to save the heightmap: {:?}", e),
+        }
+    }
+
+    /// Starts a recording into `recordings/`, or finishes the current one.
+    fn toggle_recording(&mut self) {
+        if self.recorder.is_recording() {
+            self.recorder.stop(&self.wgpu_context);
+            self.show_notice(format!("Recording stopped: {} frames", self.recorder.frames_recorded()));
+            return;
+        }
+        let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs();
+        let path = std::path::PathBuf::from(format!("recordings/recording_{}", timestamp));
+        match self.recorder.start(&path) {
+            Ok(_) => self.show_notice(format!("Recording into {}", path.display())),
+            Err(e) => log::error!("Unable to start the recording: {:?}", e),
+        }
+    }
+
+    /// Runs the physics step stage by stage and dumps every intermediate buffer into `dir`.
+    fn capture_physics_step(&mut self, dt: f32, dir: &std::path::Path, label: Option<&str>) {
+        let capture = self.layers.active_mut().capture_step(&self.wgpu_context, &mut self.gpu_profiler, dt, label);
+        match capture.save(dir) {
+            Ok(_) => log::info!("Frame captured into {}", dir.display()),
+            Err(e) => log::error!("Unable to save the frame capture: {:?}", e),
+        }
+    }
+
     fn render(&mut self)  -> anyhow::Result<(), wgpu::SurfaceError>{
-        let renderables: Vec<&dyn Renderable> = vec![&self.particles, &self.grid,];
+        let heatmap = self.renderer.is_density_heatmap_enabled();
+        let active = self.layers.active_index();
+        for (index, layer) in self.layers.iter_mut().enumerate().filter(|(_, layer)| layer.is_enabled()) {
+            let particles = layer.simulation_mut().particles_mut();
+            if heatmap {
+                // The heatmap of the active layer stands in for the particles
+                if index == active {
+                    particles.update_density_heatmap(&self.wgpu_context, &mut self.gpu_profiler, &mut self.renderer);
+                }
+            } else {
+                particles.cull_particles(&self.wgpu_context, &mut self.gpu_profiler, self.renderer.camera());
+            }
+        }
+        // Bottom layer first, each layer's particles under its grid
+        let mut renderables: Vec<&dyn Renderable> = Vec::new();
+        for layer in self.layers.enabled() {
+            if !heatmap {
+                renderables.push(layer.simulation().particles());
+            }
+            renderables.push(layer.simulation().grid());
+        }
+        renderables.extend([&self.velocity_field_drawer as &dyn Renderable, &self.static_collider_drawer, &self.profiler_overlay]);
+        if let Some(path) = self.pending_screenshot.take() {
+            match self.renderer.capture_frame(&self.wgpu_context, &renderables, &mut self.gpu_profiler, &path) {
+                Ok(_) => log::info!("Screenshot saved to {}", path.display()),
+                Err(e) => log::error!("Unable to save the screenshot: {:?}", e),
+            }
+        }
         self.renderer.render(&self.wgpu_context, &renderables, &mut self.gpu_profiler)?;
+        if self.recorder.advance_frame() {
+            let target = self.recorder.target(&self.wgpu_context, self.wgpu_context.window_size().as_uvec2(), self.wgpu_context.surface_format());
+            self.renderer.render_offscreen(&self.wgpu_context, &renderables, &mut self.gpu_profiler, target);
+            self.recorder.capture(&self.wgpu_context);
+        }
+        self.recorder.poll(&self.wgpu_context);
         Ok(())
     }
 }
@@ -144,6 +924,14 @@ impl State {
     pub fn get_mouse_position(&self) -> Option<dpi::PhysicalPosition<f64>> {
         self.mouse_position
     }
+
+    fn get_mouse_screen_position(&self) -> Option<Vec2> {
+        self.mouse_position.map(|position| Vec2::new(position.x as f32, position.y as f32))
+    }
+
+    pub fn set_shift_pressed(&mut self, shift_pressed: bool) {
+        self.shift_pressed = shift_pressed;
+    }
     pub fn get_wgpu_context(&self) -> &WgpuContext {
         &self.wgpu_context
     }
@@ -165,7 +953,13 @@ impl State {
         self.mouse_position = position;
         self.renderer.set_camera_zoom_position(position);
         let world_position = self.get_mouse_world_position();
-        self.particles.mouse_move_callback(world_position);
+        self.push_command(SimulationCommand::MoveImpulse { position: world_position });
+    }
+
+    /// Called when the cursor leaves the window, removes the hover highlight.
+    pub fn clear_mouse_position(&mut self) {
+        self.mouse_position = None;
+        self.nearest_particle_query.clear_highlight(&self.wgpu_context, self.layers.active().particles());
     }
 }
 
@@ -178,29 +972,206 @@ impl State {
     }
     
     pub fn mouse_click_callback(&mut self, mouse_state: &ElementState, button: &MouseButton){
-        if button == &MouseButton::Left {
+        if button == &MouseButton::Right {
+            // Right places a static circle, shift + right removes the one under the cursor
+            if mouse_state.is_pressed() && self.mouse_position.is_some() {
+                let position = self.get_mouse_world_position();
+                let command = if self.shift_pressed {
+                    SimulationCommand::RemoveStaticCircleAt { position }
+                } else {
+                    SimulationCommand::AddStaticCircle { center: position, radius: STATIC_CIRCLE_RADIUS }
+                };
+                self.push_command(command);
+            }
+            return;
+        }
+        if button != &MouseButton::Left {
+            return;
+        }
+        if let Some(start) = self.selection_drag_start.take_if(|_| !mouse_state.is_pressed()) {
+            if let Some(end) = self.get_mouse_screen_position() {
+                let rect = SelectionRect::from_corners(self.renderer.camera().view_projection(), self.wgpu_context.window_size(), start, end);
+                self.push_command(SimulationCommand::SelectParticles(rect));
+            }
+        }
+        else if self.shift_pressed && mouse_state.is_pressed() {
+            self.selection_drag_start = self.get_mouse_screen_position();
+        }
+        else {
             let position = self.get_mouse_world_position();
-            self.particles.mouse_click_callback(mouse_state, position);
+            self.push_command(SimulationCommand::ApplyImpulse { position, active: mouse_state.is_pressed() });
         }
     }
 
-    pub fn add_particles(&mut self){
-        let mouse_world_pos = self.get_mouse_world_position();
-        let prev_num_particles = self.particles.positions().len();
-        self.particles.add_particles(
-            &mouse_world_pos,
-            &self.wgpu_context
-        );
-        
-        let camera = self.renderer.camera();
-        let world_size = self.get_world_size();
-        self.grid.refresh_grid(&self.wgpu_context, camera, world_size, &self.particles, prev_num_particles);
-        let particles_added = self.particles.positions().len() - prev_num_particles;
-        self.collision_system.refresh(&self.wgpu_context, &self.particles, &self.grid, particles_added); 
+    /// Queues a command, it is applied at the start of the next update.
+    pub fn push_command(&mut self, command: SimulationCommand){
+        self.commands.push(command);
+    }
+
+    /// Last executed commands, oldest first.
+    pub fn get_command_history(&self) -> impl Iterator<Item = &crate::utils::command_queue::ExecutedCommand> {
+        self.commands.history()
+    }
+
+    pub fn get_time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// Uniform gravity of the simulation, in m/s².
+    pub fn gravity(&self) -> Vec2 {
+        self.layers.active().gravity()
+    }
+
+    /// Replaces the uniform gravity at the start of the next update, in m/s².
+    pub fn set_gravity(&mut self, gravity: Vec2) {
+        self.push_command(SimulationCommand::SetGravity(gravity));
+    }
+
+    pub fn particle_color_settings(&self) -> Option<ParticleColorSettings> {
+        self.layers.active().particles().color_settings()
+    }
+
+    pub fn particle_shape(&self) -> ParticleShape {
+        self.layers.active().particles().shape()
+    }
+
+    /// Spawns a batch of particles around `position`.
+    /// The refreshes it causes are reported in the telemetry under `label`.
+    pub fn add_particles_labeled(&mut self, position: Vec2, label: String){
+        let (report, refreshes) = self.layers.active_mut().add_particles(&self.wgpu_context, position);
+        self.finish_spawn(report, refreshes, label);
+    }
+
+    /// Spawns `count` particles over the whole world, see `Simulation::add_particle_batch`.
+    fn add_particle_batch(&mut self, count: usize) {
+        let label = self.telemetry.next_spawn_label();
+        let (report, refreshes) = self.layers.active_mut().add_particle_batch(&self.wgpu_context, count);
+        let at_limit = report.refused + report.recycled > 0;
+        self.finish_spawn(report, refreshes, label);
+        if !at_limit {
+            self.show_notice(format!("{} particles", self.layers.active().particles().len()));
+        }
+    }
+
+    /// Removes the `count` last particles, see `Simulation::remove_particle_batch`.
+    fn remove_particle_batch(&mut self, count: usize) {
+        let removed = self.layers.active_mut().remove_particle_batch(&self.wgpu_context, &mut self.gpu_profiler, count);
+        self.selection = None;
+        self.refresh_after_particle_change();
+        self.show_notice(format!("{} particles removed, {} left", removed, self.layers.active().particles().len()));
+    }
+
+    /// Shows the outcome of a spawn, refreshes what follows the particles and records the batch in the telemetry.
+    fn finish_spawn(&mut self, report: SpawnReport, mut refreshes: Vec<(&'static str, RefreshTiming)>, label: String) {
+        if report.spawned > 0 {
+            let world_size = self.layers.active().particles().get_world_size();
+            let grid_drawer_timer = RefreshTimer::start(&self.wgpu_context);
+            if self.layers.active_mut().grid_mut().refresh_drawer(&self.wgpu_context, self.renderer.camera(), world_size) {
+                refreshes.push(("Grid drawer rebuild", grid_drawer_timer.finish(&self.wgpu_context)));
+            }
+        }
+        let max_particles = self.layers.active().particles().limit().max_particles;
+        if report.refused > 0 {
+            self.show_notice(format!("Particle limit of {} reached, {} particles not spawned", max_particles, report.refused));
+        }
+        else if report.recycled > 0 {
+            self.show_notice(format!("Particle limit of {} reached, {} oldest particles recycled", max_particles, report.recycled));
+        }
+        self.refresh_after_particle_change();
+
+        self.telemetry.record_spawn_batch(SpawnBatchStats {
+            label,
+            particles_added: report.spawned,
+            total_particles: self.layers.active().particles().len(),
+            refreshes,
+        });
+    }
+
+    /// Rebinds the queries and the watchdog to the particle buffers, after particles were added or removed.
+    fn refresh_after_particle_change(&mut self) {
+        self.cell_occupancy_query.refresh(&self.wgpu_context, self.layers.active().grid());
+        self.nearest_particle_query.refresh(&self.wgpu_context, self.layers.active().particles());
+        self.stability_watchdog.refresh(&self.wgpu_context, self.layers.active().particles());
+    }
+
+    /// Shows a notice when collision cells start holding more particles than the solver cap. The solver logs it.
+    fn update_overflow_notice(&mut self) {
+        let Some(stats) = self.layers.active().collision_system().stats() else {
+            return;
+        };
+        if stats.cells.overflow_cells > 0 && self.overflow_cells == 0 {
+            let message = format!("{} cells over the solver cap (up to {} particles)", stats.cells.overflow_cells, stats.cells.max_particles_per_cell);
+            self.notice = Some((message, std::time::Instant::now()));
+        }
+        self.overflow_cells = stats.cells.overflow_cells;
+    }
+
+    /// Shows `message` in the window title for a few seconds.
+    /// Swaps in the pipelines of the edited WGSL files, see `ShaderWatcher`. The outcome is shown in the title.
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    fn reload_shaders(&mut self) {
+        for reload in self.shader_watcher.poll(&self.wgpu_context) {
+            let message = match reload.result {
+                Ok(()) => format!("Reloaded {}", reload.path.file_name().unwrap_or_default().to_string_lossy()),
+                Err(error) => error.to_string(),
+            };
+            self.notice = Some((message, std::time::Instant::now()));
+        }
+    }
+
+    fn save_pipeline_cache(&self) {
+        if let Err(e) = self.wgpu_context.save_pipeline_cache() {
+            log::warn!("Unable to save the pipeline cache: {:?}", e);
+        }
+    }
+
+    fn show_notice(&mut self, message: String) {
+        log::warn!("{}", message);
+        self.notice = Some((message, std::time::Instant::now()));
+    }
+
+    /// Statistics of the physics simulation. Waits for the GPU to finish the last step.
+    pub fn get_simulation_stats(&self) -> SimulationStats {
+        self.layers.active().stats(&self.wgpu_context)
+    }
+
+    pub fn get_telemetry(&self) -> &Telemetry {
+        &self.telemetry
     }
     
-    pub fn toggle_grid_drawing(&mut self){
-        self.grid.toggle_grid_drawing();
+    pub fn set_solver_config(&mut self, solver_config: SolverConfig){
+        log::info!("Solver config: {:?}", solver_config);
+        self.layers.active_mut().collision_system_mut().set_solver_config(solver_config);
+    }
+
+    /// Changes the number of solver iterations by `delta`, keeping at least one.
+    pub fn change_solver_iterations(&mut self, delta: i32){
+        let mut solver_config = self.layers.active().collision_system().solver_config();
+        solver_config.iterations = (solver_config.iterations as i32 + delta).max(1) as u32;
+        self.set_solver_config(solver_config);
+    }
+
+    /// Runs the current solver config against a variant with one more iteration on cloned particles
+    /// and logs the divergence and timings. Blocks until both runs are done.
+    pub fn compare_solver_variant(&mut self){
+        let current = self.layers.active().collision_system().solver_config();
+        let variant = SolverConfig { iterations: current.iterations + 1, ..current };
+        let comparison = solver_comparison::compare_solvers(
+            &self.wgpu_context,
+            self.layers.active_mut().particles_mut(),
+            DIMENSION,
+            current,
+            variant,
+            SOLVER_COMPARISON_FRAMES,
+            SOLVER_COMPARISON_DELTA_TIME,
+        );
+        log::info!("{}", comparison);
     }
+
 }
 
+impl Drop for State {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+/// Frames always discarded at the start (shader compilation, buffer uploads, first sorts...).
+pub const DEFAULT_WARM_UP_FRAMES: usize = 120;
+/// Number of frames used to decide if the frame time is stable.
+pub const DEFAULT_STEADY_STATE_WINDOW: usize = 60;
+/// Max coefficient of variation (std dev / mean) of the window to consider the frame time stable.
+pub const DEFAULT_STEADY_STATE_THRESHOLD: f64 = 0.05;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BenchmarkPhase {
+    WarmingUp,
+    WaitingForSteadyState,
+    Collecting,
+}
+
+/// Statistics of the collected frames, in milliseconds.
+#[derive(Copy, Clone, Debug)]
+pub struct BenchmarkReport {
+    pub discarded_frames: usize,
+    pub collected_frames: usize,
+    pub mean_ms: f64,
+    pub std_dev_ms: f64,
+    pub min_ms: f64,
+    pub median_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl fmt::Display for BenchmarkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Benchmark: {} frames collected ({} discarded)", self.collected_frames, self.discarded_frames)?;
+        writeln!(f, "  Mean: {:.3} ms (std dev {:.3} ms)", self.mean_ms, self.std_dev_ms)?;
+        writeln!(f, "  Min / median / max: {:.3} / {:.3} / {:.3} ms", self.min_ms, self.median_ms, self.max_ms)?;
+        write!(f, "  p95 / p99: {:.3} / {:.3} ms", self.p95_ms, self.p99_ms)
+    }
+}
+
+/// Discards the warm-up frames, waits until the frame time is stable and only then collects
+/// frame times, so the reported numbers do not include start-up spikes.
+pub struct BenchmarkHarness {
+    warm_up_frames: usize,
+    steady_state_window: usize,
+    steady_state_threshold: f64,
+    phase: BenchmarkPhase,
+    discarded_frames: usize,
+    window: VecDeque<f64>,
+    collected: Vec<f64>,
+}
+
+impl BenchmarkHarness {
+    pub fn new() -> Self {
+        Self::with_settings(DEFAULT_WARM_UP_FRAMES, DEFAULT_STEADY_STATE_WINDOW, DEFAULT_STEADY_STATE_THRESHOLD)
+    }
+
+    pub fn with_settings(warm_up_frames: usize, steady_state_window: usize, steady_state_threshold: f64) -> Self {
+        assert!(steady_state_window > 1, "The steady state window needs at least two frames");
+        Self {
+            warm_up_frames,
+            steady_state_window,
+            steady_state_threshold,
+            phase: if warm_up_frames > 0 { BenchmarkPhase::WarmingUp } else { BenchmarkPhase::WaitingForSteadyState },
+            discarded_frames: 0,
+            window: VecDeque::with_capacity(steady_state_window),
+            collected: Vec::new(),
+        }
+    }
+
+    /// Records the time of one frame. Returns true if the frame was collected.
+    pub fn record_frame(&mut self, frame_time: Duration) -> bool {
+        let frame_ms = frame_time.as_secs_f64() * 1000.0;
+        match self.phase {
+            BenchmarkPhase::WarmingUp => {
+                self.discarded_frames += 1;
+                if self.discarded_frames >= self.warm_up_frames {
+                    self.phase = BenchmarkPhase::WaitingForSteadyState;
+                }
+                false
+            }
+            BenchmarkPhase::WaitingForSteadyState => {
+                if self.window.len() == self.steady_state_window {
+                    self.window.pop_front();
+                }
+                self.window.push_back(frame_ms);
+                self.discarded_frames += 1;
+
+                if self.window.len() == self.steady_state_window && self.is_window_stable() {
+                    log::info!("Benchmark reached steady state after {} frames", self.discarded_frames);
+                    self.phase = BenchmarkPhase::Collecting;
+                }
+                false
+            }
+            BenchmarkPhase::Collecting => {
+                self.collected.push(frame_ms);
+                true
+            }
+        }
+    }
+
+    fn is_window_stable(&self) -> bool {
+        let (mean, std_dev) = mean_and_std_dev(self.window.iter().copied());
+        mean > 0.0 && std_dev / mean < self.steady_state_threshold
+    }
+
+    pub fn phase(&self) -> BenchmarkPhase {
+        self.phase
+    }
+
+    pub fn is_collecting(&self) -> bool {
+        self.phase == BenchmarkPhase::Collecting
+    }
+
+    /// Statistics of the collected frames, `None` until steady state was reached.
+    pub fn report(&self) -> Option<BenchmarkReport> {
+        if self.collected.is_empty() {
+            return None;
+        }
+        let mut sorted = self.collected.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let (mean, std_dev) = mean_and_std_dev(sorted.iter().copied());
+
+        Some(BenchmarkReport {
+            discarded_frames: self.discarded_frames,
+            collected_frames: sorted.len(),
+            mean_ms: mean,
+            std_dev_ms: std_dev,
+            min_ms: sorted[0],
+            median_ms: percentile(&sorted, 0.5),
+            p95_ms: percentile(&sorted, 0.95),
+            p99_ms: percentile(&sorted, 0.99),
+            max_ms: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+impl Default for BenchmarkHarness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Nearest-rank percentile of an already sorted slice.
+fn percentile(sorted: &[f64], fraction: f64) -> f64 {
+    let rank = (fraction * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn mean_and_std_dev(values: impl Iterator<Item = f64> + Clone) -> (f64, f64) {
+    let count = values.clone().count().max(1) as f64;
+    let mean = values.clone().sum::<f64>() / count;
+    let variance = values.map(|value| (value - mean) * (value - mean)).sum::<f64>() / count;
+    (mean, variance.sqrt())
+}
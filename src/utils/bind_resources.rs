@@ -1,4 +1,7 @@
-use wgpu::{BindGroup, BindGroupLayout};
+use std::sync::Mutex;
+use wgpu::{BindGroup, BindGroupLayout, Buffer};
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::gpu_buffer::{BufferHandle, GpuBuffer};
 
 pub struct BindResources {
     pub bind_group: BindGroup,
@@ -13,3 +16,160 @@ impl BindResources {
         }
     }
 }
+
+/// A buffer `BindingsBuilder` can bind.
+pub trait BindableBuffer {
+    fn binding_handle(&self) -> BufferHandle;
+}
+
+impl<T: bytemuck::Pod> BindableBuffer for GpuBuffer<T> {
+    fn binding_handle(&self) -> BufferHandle {
+        self.handle()
+    }
+}
+
+impl BindableBuffer for BufferHandle {
+    fn binding_handle(&self) -> BufferHandle {
+        self.clone()
+    }
+}
+
+/// A buffer that never grows, the bind group keeps it.
+impl BindableBuffer for Buffer {
+    fn binding_handle(&self) -> BufferHandle {
+        BufferHandle::new(self.clone())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BindingKind {
+    StorageReadOnly,
+    StorageReadWrite,
+    Uniform,
+}
+
+impl BindingKind {
+    fn buffer_binding_type(self) -> wgpu::BufferBindingType {
+        match self {
+            BindingKind::StorageReadOnly => wgpu::BufferBindingType::Storage { read_only: true },
+            BindingKind::StorageReadWrite => wgpu::BufferBindingType::Storage { read_only: false },
+            BindingKind::Uniform => wgpu::BufferBindingType::Uniform,
+        }
+    }
+}
+
+/// The buffers of a bind group, numbered in the order they are added:
+/// `BindingsBuilder::new("Grid").storage_rw(&positions).uniform(&uniform).build(wgpu_context)` binds the
+/// positions at 0 and the uniform at 1. Every binding is an entire buffer, visible to the compute stage by default.
+pub struct BindingsBuilder {
+    label: String,
+    visibility: wgpu::ShaderStages,
+    entries: Vec<(BindingKind, BufferHandle)>,
+}
+
+impl BindingsBuilder {
+    pub fn new(label: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            visibility: wgpu::ShaderStages::COMPUTE,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn visibility(mut self, visibility: wgpu::ShaderStages) -> Self {
+        self.visibility = visibility;
+        self
+    }
+
+    pub fn storage_ro(self, buffer: &impl BindableBuffer) -> Self {
+        self.binding(BindingKind::StorageReadOnly, buffer)
+    }
+
+    pub fn storage_rw(self, buffer: &impl BindableBuffer) -> Self {
+        self.binding(BindingKind::StorageReadWrite, buffer)
+    }
+
+    pub fn uniform(self, buffer: &impl BindableBuffer) -> Self {
+        self.binding(BindingKind::Uniform, buffer)
+    }
+
+    pub fn binding(mut self, kind: BindingKind, buffer: &impl BindableBuffer) -> Self {
+        self.entries.push((kind, buffer.binding_handle()));
+        self
+    }
+
+    /// Creates the layout. The bind group is created by the first `Bindings::bind_group`.
+    pub fn build(self, wgpu_context: &WgpuContext) -> Bindings {
+        let entries: Vec<wgpu::BindGroupLayoutEntry> = self.entries.iter().enumerate()
+            .map(|(binding, (kind, _))| wgpu::BindGroupLayoutEntry {
+                binding: binding as u32,
+                visibility: self.visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: kind.buffer_binding_type(),
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            })
+            .collect();
+        let layout = wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(&format!("{} bind group layout", self.label)),
+            entries: &entries,
+        });
+        Bindings {
+            device: wgpu_context.get_device().clone(),
+            layout,
+            builder: self,
+            bound: Mutex::new(None),
+        }
+    }
+}
+
+/// A bind group layout and the bind group of its buffers, recreated when one of them was replaced by a
+/// growing push or `GpuBuffer::reserve`. The kernels that own the bindings only call `rebind` when they bind
+/// other buffers, e.g. after the particle channels were recreated.
+pub struct Bindings {
+    device: wgpu::Device,
+    layout: BindGroupLayout,
+    builder: BindingsBuilder,
+    /// The bind group and the buffers it was created with, `None` until the first use after a `rebind`
+    bound: Mutex<Option<(BindGroup, Vec<Buffer>)>>,
+}
+
+impl Bindings {
+    pub fn layout(&self) -> &BindGroupLayout {
+        &self.layout
+    }
+
+    /// The bind group of the current buffers, recreated if any of them changed since the last call.
+    pub fn bind_group(&self) -> BindGroup {
+        let buffers: Vec<Buffer> = self.builder.entries.iter().map(|(_, handle)| handle.buffer()).collect();
+        let mut bound = self.bound.lock().unwrap();
+        if let Some((bind_group, _)) = bound.as_ref().filter(|(_, bound_buffers)| *bound_buffers == buffers) {
+            return bind_group.clone();
+        }
+        let entries: Vec<wgpu::BindGroupEntry> = buffers.iter().enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{} bind group", self.builder.label)),
+            layout: &self.layout,
+            entries: &entries,
+        });
+        *bound = Some((bind_group.clone(), buffers));
+        bind_group
+    }
+
+    /// Binds other buffers to the same layout, so the kernels created with it stay valid. `builder` must add
+    /// the same kinds of bindings in the same order.
+    pub fn rebind(&mut self, builder: BindingsBuilder) {
+        let kinds = |builder: &BindingsBuilder| builder.entries.iter().map(|(kind, _)| *kind).collect::<Vec<_>>();
+        assert_eq!(kinds(&builder), kinds(&self.builder), "{} must be rebound with the bindings of its layout", self.builder.label);
+        assert_eq!(builder.visibility, self.builder.visibility, "{} must be rebound with the visibility of its layout", self.builder.label);
+        self.builder = builder;
+        *self.bound.get_mut().unwrap() = None;
+    }
+}
@@ -0,0 +1,198 @@
+//! Bookkeeping of the GPU memory held by the `GpuBuffer`s of a `WgpuContext`, to see which subsystem grows when
+//! the particle count goes up and to warn before a buffer runs into the limits of the adapter.
+use std::collections::BTreeMap;
+use std::panic::Location;
+use std::path::Path;
+use std::sync::{Arc, Mutex, Weak};
+
+/// Fraction of a limit past which `BufferRegistry` warns.
+pub const LIMIT_WARNING_FRACTION: f64 = 0.8;
+
+/// A live buffer of the registry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BufferRecord {
+    /// Where the buffer was created, e.g. `particle_system.rs:120`.
+    pub label: String,
+    /// The module that created the buffer, e.g. `particle_system`.
+    pub subsystem: String,
+    pub size: u64,
+    pub usage: wgpu::BufferUsages,
+}
+
+/// Bytes held by the buffers of a subsystem.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubsystemUsage {
+    pub subsystem: String,
+    pub bytes: u64,
+    pub buffers: usize,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    next_id: u64,
+    records: BTreeMap<u64, BufferRecord>,
+    total_bytes: u64,
+    peak_bytes: u64,
+}
+
+/// Every `GpuBuffer` registers here with its label, size and usage while it is alive, see
+/// `WgpuContext::buffers`. The subsystem of a buffer is the source file that created it, which `GpuBuffer::new`
+/// reads from its caller, so no call site has to name itself.
+///
+/// A buffer past `LIMIT_WARNING_FRACTION` of the largest binding or buffer the device allows, or a total past
+/// the same fraction of the memory budget, is logged once when it crosses the line.
+#[derive(Debug)]
+pub struct BufferRegistry {
+    entries: Arc<Mutex<Entries>>,
+    max_buffer_size: u64,
+    max_storage_binding_size: u64,
+    /// Bytes the app expects to have, `None` skips the warning about the total.
+    memory_budget: Mutex<Option<u64>>,
+}
+
+/// Keeps a buffer in its registry until dropped.
+#[derive(Debug)]
+pub struct BufferRegistration {
+    entries: Weak<Mutex<Entries>>,
+    id: u64,
+}
+
+impl BufferRegistry {
+    pub fn new(limits: &wgpu::Limits) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(Entries::default())),
+            max_buffer_size: limits.max_buffer_size,
+            max_storage_binding_size: limits.max_storage_buffer_binding_size as u64,
+            memory_budget: Mutex::new(None),
+        }
+    }
+
+    /// Warns when the registered buffers hold more than `LIMIT_WARNING_FRACTION` of `bytes`. The adapters do
+    /// not report their memory, e.g. set it to the VRAM of the target GPU.
+    pub fn set_memory_budget(&self, bytes: Option<u64>) {
+        *self.memory_budget.lock().unwrap() = bytes;
+    }
+
+    /// Registers a buffer of `size` bytes created at `location`.
+    pub fn register(&self, location: &Location<'_>, size: u64, usage: wgpu::BufferUsages) -> BufferRegistration {
+        let path = Path::new(location.file());
+        let file_name = path.file_name().map_or_else(|| location.file().to_string(), |name| name.to_string_lossy().into_owned());
+        let subsystem = path.file_stem().map_or_else(|| file_name.clone(), |stem| stem.to_string_lossy().into_owned());
+        let record = BufferRecord {
+            label: format!("{}:{}", file_name, location.line()),
+            subsystem,
+            size: 0,
+            usage,
+        };
+        let id = {
+            let mut entries = self.entries.lock().unwrap();
+            let id = entries.next_id;
+            entries.next_id += 1;
+            entries.records.insert(id, record);
+            id
+        };
+        let registration = BufferRegistration { entries: Arc::downgrade(&self.entries), id };
+        self.resize(&registration, size);
+        registration
+    }
+
+    /// Updates the size of a buffer that was recreated with `size` bytes.
+    pub fn resize(&self, registration: &BufferRegistration, size: u64) {
+        let (record, old_total, new_total, old_size) = {
+            let mut entries = self.entries.lock().unwrap();
+            let old_total = entries.total_bytes;
+            let Some(record) = entries.records.get_mut(&registration.id) else {
+                return;
+            };
+            let old_size = std::mem::replace(&mut record.size, size);
+            let record = record.clone();
+            entries.total_bytes = old_total - old_size + size;
+            entries.peak_bytes = entries.peak_bytes.max(entries.total_bytes);
+            (record, old_total, entries.total_bytes, old_size)
+        };
+
+        let buffer_limit = if record.usage.contains(wgpu::BufferUsages::STORAGE) {
+            self.max_storage_binding_size.min(self.max_buffer_size)
+        } else {
+            self.max_buffer_size
+        };
+        if crosses_warning(old_size, size, buffer_limit) {
+            log::warn!(
+                "GPU buffer {} ({}) holds {:.2} MiB, close to the {:.2} MiB the device allows",
+                record.label, record.subsystem, mib(size), mib(buffer_limit),
+            );
+        }
+        if let Some(budget) = *self.memory_budget.lock().unwrap()
+            && crosses_warning(old_total, new_total, budget) {
+            log::warn!(
+                "GPU buffers hold {:.2} MiB, close to the budget of {:.2} MiB\n{}",
+                mib(new_total), mib(budget), self.report(),
+            );
+        }
+    }
+
+    /// Bytes held by the live buffers.
+    pub fn total_bytes(&self) -> u64 {
+        self.entries.lock().unwrap().total_bytes
+    }
+
+    /// Most bytes the buffers held at once.
+    pub fn peak_bytes(&self) -> u64 {
+        self.entries.lock().unwrap().peak_bytes
+    }
+
+    /// The live buffers, in the order they were created.
+    pub fn records(&self) -> Vec<BufferRecord> {
+        self.entries.lock().unwrap().records.values().cloned().collect()
+    }
+
+    /// Bytes per subsystem, largest first.
+    pub fn subsystems(&self) -> Vec<SubsystemUsage> {
+        let mut subsystems: Vec<SubsystemUsage> = Vec::new();
+        for record in self.entries.lock().unwrap().records.values() {
+            match subsystems.iter_mut().find(|usage| usage.subsystem == record.subsystem) {
+                Some(usage) => {
+                    usage.bytes += record.size;
+                    usage.buffers += 1;
+                }
+                None => subsystems.push(SubsystemUsage { subsystem: record.subsystem.clone(), bytes: record.size, buffers: 1 }),
+            }
+        }
+        subsystems.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.subsystem.cmp(&b.subsystem)));
+        subsystems
+    }
+
+    /// The total and one line per subsystem, logged when the app exits.
+    pub fn report(&self) -> String {
+        let mut report = format!(
+            "GPU buffers: {:.2} MiB in {} buffers (peak {:.2} MiB)",
+            mib(self.total_bytes()), self.records().len(), mib(self.peak_bytes()),
+        );
+        for usage in self.subsystems() {
+            report.push_str(&format!("\n  {:<28} {:>10.2} MiB in {} buffers", usage.subsystem, mib(usage.bytes), usage.buffers));
+        }
+        report
+    }
+}
+
+impl Drop for BufferRegistration {
+    fn drop(&mut self) {
+        let Some(entries) = self.entries.upgrade() else {
+            return;
+        };
+        let mut entries = entries.lock().unwrap();
+        if let Some(record) = entries.records.remove(&self.id) {
+            entries.total_bytes -= record.size;
+        }
+    }
+}
+
+/// Whether a size going from `old` to `new` bytes goes past the warning line of `limit`.
+fn crosses_warning(old: u64, new: u64, limit: u64) -> bool {
+    let line = limit as f64 * LIMIT_WARNING_FRACTION;
+    limit > 0 && old as f64 <= line && new as f64 > line
+}
+
+fn mib(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
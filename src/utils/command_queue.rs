@@ -0,0 +1,145 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use glam::Vec2;
+use crate::particles::particle_group::GroupOperation;
+use crate::particles::particle_color_kernel::ParticleColorSettings;
+use crate::particles::color_palette::ColorTheme;
+use crate::particles::particle_shape::ParticleShape;
+use crate::particles::particle_interaction::InteractionMode;
+use crate::particles::particle_selection::SelectionRect;
+
+/// Number of executed commands kept in the history.
+pub const COMMAND_HISTORY_SIZE: usize = 256;
+
+/// Something the user (or a script, or a remote controller) wants the simulation to do.
+/// Positions are in world coordinates.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SimulationCommand {
+    /// Stops or resumes the physics. Rendering and the camera keep working while paused.
+    TogglePause,
+    /// Spawns a batch of particles around `position`.
+    SpawnParticles { position: Vec2 },
+    ToggleGrid,
+    /// Colors the grid cells by their objects, then by their pair tests, then stops, see `GridDebugView`.
+    CycleGridDebugView,
+    /// Shows the recent path of every particle as a fading line, or hides it.
+    ToggleTrails,
+    /// Draws the particle density as a color mapped heatmap instead of the particles, or back.
+    ToggleDensityHeatmap,
+    /// Shows the average particle velocity over the world as arrows, or hides them.
+    ToggleVelocityField,
+    /// Spawns the number of particles spread over the whole world.
+    AddParticleBatch(usize),
+    /// Removes the number of particles, the last ones first.
+    RemoveParticleBatch(usize),
+    /// Starts (`active`) or stops the mouse interaction at `position`.
+    ApplyImpulse { position: Vec2, active: bool },
+    /// Moves the center of the mouse interaction, without changing if it is active.
+    MoveImpulse { position: Vec2 },
+    /// Selects what the mouse interaction does to the particles.
+    SetInteractionMode(InteractionMode),
+    /// Multiplies the radius of the mouse interaction.
+    ScaleInteractionRadius(f32),
+    /// Multiplies the strength of the mouse interaction.
+    ScaleInteractionStrength(f32),
+    /// Multiplies the delta time of every physics step.
+    SetTimeScale(f32),
+    /// Replaces the uniform gravity, in m/s².
+    SetGravity(Vec2),
+    /// Changes the number of solver iterations by the given amount, keeping at least one.
+    ChangeSolverIterations(i32),
+    /// Compares the current solver config against one more iteration.
+    CompareSolvers,
+    /// Captures every physics buffer of the next frame into the directory.
+    CaptureFrame(PathBuf),
+    /// Saves the particle distribution as a heightmap PNG at the path.
+    ExportHeightmap(PathBuf),
+    /// Saves the next rendered frame as a PNG at the path.
+    CaptureScreenshot(PathBuf),
+    /// Starts recording the frames into `recordings/`, or finishes the recording.
+    ToggleRecording,
+    /// Selects the particles inside the screen rectangle, replacing the previous selection.
+    SelectParticles(SelectionRect),
+    /// Applies the operation to the selected particles. Removing them also clears the selection.
+    ApplyToSelection(GroupOperation),
+    /// Colors the particles by speed or density, `None` for the velocity shading.
+    SetParticleColors(Option<ParticleColorSettings>),
+    /// Paints the particles and the next spawn batches with the palette of the theme.
+    SetColorTheme(ColorTheme),
+    /// Changes the shape the particles are drawn with.
+    SetParticleShape(ParticleShape),
+    /// Places a static circle collider.
+    AddStaticCircle { center: Vec2, radius: f32 },
+    /// Removes the last placed static circle containing `position`, if any.
+    RemoveStaticCircleAt { position: Vec2 },
+    /// Replaces the particles and the colliders with the hourglass demo scene.
+    LoadHourglassDemo,
+    /// Shows or hides the per-scope GPU times.
+    ToggleProfilerOverlay,
+    /// Sends the input to the next simulation layer, see `SimulationLayers`.
+    CycleActiveLayer,
+    /// Lets the settled particles of the active layer fall asleep, or wakes them and stops, see `Simulation::enable_sleep`.
+    ToggleSleep,
+}
+
+/// A command that was executed and the frame it was executed on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExecutedCommand {
+    pub frame: u64,
+    pub command: SimulationCommand,
+}
+
+/// Commands waiting to be applied to the simulation.
+/// Input handlers only push commands; they are applied all together at the start of the next update.
+pub struct CommandQueue {
+    pending: VecDeque<SimulationCommand>,
+    history: VecDeque<ExecutedCommand>,
+}
+
+impl CommandQueue {
+    pub fn new() -> Self {
+        Self {
+            pending: VecDeque::new(),
+            history: VecDeque::with_capacity(COMMAND_HISTORY_SIZE),
+        }
+    }
+
+    /// Queues a command. Consecutive `MoveImpulse` commands are merged, only the last position matters.
+    pub fn push(&mut self, command: SimulationCommand) {
+        if let (SimulationCommand::MoveImpulse { .. }, Some(SimulationCommand::MoveImpulse { .. })) = (&command, self.pending.back()) {
+            self.pending.pop_back();
+        }
+        self.pending.push_back(command);
+    }
+
+    /// Takes every pending command, in the order they were pushed, and records them in the history as executed on `frame`.
+    pub fn drain(&mut self, frame: u64) -> Vec<SimulationCommand> {
+        let commands: Vec<SimulationCommand> = self.pending.drain(..).collect();
+        for command in &commands {
+            if self.history.len() == COMMAND_HISTORY_SIZE {
+                self.history.pop_front();
+            }
+            self.history.push_back(ExecutedCommand { frame, command: command.clone() });
+        }
+        commands
+    }
+
+    /// Last executed commands, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &ExecutedCommand> {
+        self.history.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+impl Default for CommandQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
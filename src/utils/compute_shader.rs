@@ -1,14 +1,170 @@
 // in renderer/compute_shader.rs
 
+use std::borrow::Cow;
+#[cfg(debug_assertions)]
+use std::sync::Arc;
+use std::sync::Mutex;
 use wgpu::{BindGroup, CommandEncoder, PushConstantRange};
 use crate::renderer::wgpu_context::WgpuContext;
+#[cfg(debug_assertions)]
+use crate::utils::shader_watcher::{ReloadableKernel, ShaderReloadError};
 
+/// Bind group of the uniform buffer that replaces the push constants on devices without them.
+pub const UNIFORM_CONSTANTS_GROUP: u32 = 1;
+
+/// Rewrites the push constant declaration of a WGSL source into a uniform at binding 0 of `UNIFORM_CONSTANTS_GROUP`.
+/// The push constant structs only hold scalars, vectors and matrices, which have the same layout in a uniform.
+pub fn uniform_constants_source(source: &str) -> String {
+    source.replace("var<push_constant>", &format!("@group({}) @binding(0) var<uniform>", UNIFORM_CONSTANTS_GROUP))
+}
+
+/// Slots of the first ring of `UniformConstants`, doubled every time the dispatches fill it up to `MAX_UNIFORM_CONSTANT_SLOTS`.
+const INITIAL_UNIFORM_CONSTANT_SLOTS: u64 = 16;
+const MAX_UNIFORM_CONSTANT_SLOTS: u64 = 1024;
+
+/// Ring of uniform slots holding the push constants of a kernel, on devices without push constants. Every dispatch
+/// writes its constants into the next slot with a queue write and binds it with a dynamic offset, so the dispatches
+/// of one encoder each keep their own constants.
+struct UniformConstants {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Bytes of the constants and bytes between two slots, aligned for dynamic offsets
+    size: u64,
+    slot_stride: u64,
+    ring: Mutex<UniformConstantsRing>,
+}
+
+struct UniformConstantsRing {
+    buffer: wgpu::Buffer,
+    bind_group: BindGroup,
+    slots: u64,
+    next_slot: u64,
+}
+
+impl UniformConstants {
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue, push_constants: &[PushConstantRange]) -> Self {
+        let size = push_constants.iter().map(|range| range.range.end).max().unwrap_or(4).next_multiple_of(16) as u64;
+        let slot_stride = size.next_multiple_of(device.limits().min_uniform_buffer_offset_alignment as u64);
+        let bind_group_layout = Self::create_bind_group_layout(device, size);
+        let ring = Self::create_ring(device, &bind_group_layout, size, slot_stride, INITIAL_UNIFORM_CONSTANT_SLOTS);
+        Self {
+            device: device.clone(),
+            queue: queue.clone(),
+            bind_group_layout,
+            size,
+            slot_stride,
+            ring: Mutex::new(ring),
+        }
+    }
+
+    fn create_ring(device: &wgpu::Device, bind_group_layout: &wgpu::BindGroupLayout, size: u64, slot_stride: u64, slots: u64) -> UniformConstantsRing {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Uniform constants"),
+            size: slot_stride * slots,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Uniform constants bind group"),
+            layout: bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding { buffer: &buffer, offset: 0, size: wgpu::BufferSize::new(size) }),
+            }],
+        });
+        UniformConstantsRing { buffer, bind_group, slots, next_slot: 0 }
+    }
+
+    fn create_bind_group_layout(device: &wgpu::Device, size: u64) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Uniform constants bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(size),
+                },
+                count: None,
+            }],
+        })
+    }
+
+    /// Writes the constants into the next slot and returns the bind group and the dynamic offset of that slot.
+    /// A queue write lands before the whole submission, so a slot is never written twice: once every slot was
+    /// used, the ring is replaced by a new one, the dispatches recorded before keep the old one alive.
+    fn write(&self, constants: &[(u32, &[u8])]) -> (BindGroup, u32) {
+        let mut ring = self.ring.lock().unwrap();
+        if ring.next_slot == ring.slots {
+            let slots = (ring.slots * 2).min(MAX_UNIFORM_CONSTANT_SLOTS);
+            *ring = Self::create_ring(&self.device, &self.bind_group_layout, self.size, self.slot_stride, slots);
+        }
+        let offset = ring.next_slot * self.slot_stride;
+        ring.next_slot += 1;
+
+        let mut data = vec![0u8; self.size as usize];
+        for (constants_offset, bytes) in constants {
+            let start = *constants_offset as usize;
+            data[start..start + bytes.len()].copy_from_slice(bytes);
+        }
+        self.queue.write_buffer(&ring.buffer, offset, &data);
+        (ring.bind_group.clone(), offset as u32)
+    }
+}
+
+/// A compute pipeline and its dispatches. On devices without push constants, or with fewer bytes of them than the
+/// kernel needs (see `DeviceCapabilities`), the shader's push constants are turned into a uniform bound at
+/// `UNIFORM_CONSTANTS_GROUP`, which the dispatches fill instead.
+///
+/// In debug builds the kernel can be recompiled from its WGSL file while the app runs, see `ShaderWatcher`.
 pub struct ComputeShader {
     pipeline: wgpu::ComputePipeline,
     workgroup_size: (u32, u32, u32),
+    uniform_constants: Option<UniformConstants>,
+    #[cfg(debug_assertions)]
+    reloadable: Arc<ReloadableKernel>,
+}
+
+/// Compiles `shader_file` and creates the pipeline of `entry_point`, through the pipeline cache of the context if it
+/// has one. With `uniform_constants`, the push constants of the source are rewritten into the uniform of
+/// `UNIFORM_CONSTANTS_GROUP`.
+pub(crate) fn create_pipeline(
+    wgpu_context: &WgpuContext,
+    shader_file: wgpu::ShaderModuleDescriptor,
+    entry_point: &str,
+    pipeline_layout: &wgpu::PipelineLayout,
+    constants: &[(&str, f64)],
+    uniform_constants: bool,
+) -> wgpu::ComputePipeline {
+    let shader_file = match shader_file.source {
+        wgpu::ShaderSource::Wgsl(source) if uniform_constants => wgpu::ShaderModuleDescriptor {
+            label: shader_file.label,
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(uniform_constants_source(&source))),
+        },
+        source => wgpu::ShaderModuleDescriptor { label: shader_file.label, source },
+    };
+    let device = wgpu_context.get_device();
+    let compute_shader = device.create_shader_module(shader_file);
+
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(&format!("Compute Pipeline for {}", entry_point)),
+        layout: Some(pipeline_layout),
+        module: &compute_shader,
+        entry_point: Some(entry_point),
+        compilation_options: wgpu::PipelineCompilationOptions{
+            constants,
+            zero_initialize_workgroup_memory: true,
+        },
+        cache: wgpu_context.pipeline_cache(),
+    })
 }
 
 impl ComputeShader {
+   /// In debug builds, the WGSL file of the kernel is the label of `shader_file`, e.g. the one of `include_wgsl!`,
+   /// next to the source file of the caller.
+   #[track_caller]
    pub fn new(
         wgpu_context: &WgpuContext,
         shader_file: wgpu::ShaderModuleDescriptor,
@@ -19,33 +175,55 @@ impl ComputeShader {
         push_constants: &Vec<PushConstantRange>,
     ) -> Self {
         let device = wgpu_context.get_device();
-        let compute_shader = device.create_shader_module(shader_file);
+        // Constants larger than the push constants of the device go through the uniform buffer too
+        let capabilities = wgpu_context.capabilities();
+        let push_constants_size = push_constants.iter().map(|range| range.range.end).max().unwrap_or(0);
+        let has_push_constants = capabilities.max_push_constant_size > 0 && capabilities.fits_push_constants(push_constants_size);
+        #[cfg(debug_assertions)]
+        let label = shader_file.label.map(str::to_string);
 
+        let uniform_constants = (!has_push_constants && !push_constants.is_empty())
+            .then(|| UniformConstants::new(device, wgpu_context.get_queue(), push_constants));
+        let mut bind_group_layouts = vec![bind_group_layout];
+        bind_group_layouts.extend(uniform_constants.as_ref().map(|uniform_constants| &uniform_constants.bind_group_layout));
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some(&format!("Compute Pipeline Layout for {}", entry_point)),
-            bind_group_layouts: &[bind_group_layout],
-            push_constant_ranges: push_constants.as_slice(),
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: if has_push_constants { push_constants.as_slice() } else { &[] },
         });
 
-        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some(&format!("Compute Pipeline for {}", entry_point)),
-            layout: Some(&pipeline_layout),
-            module: &compute_shader,
-            entry_point: Some(entry_point),
-            compilation_options: wgpu::PipelineCompilationOptions{
-                constants: constants.as_slice(),
-                zero_initialize_workgroup_memory: true,
-            },
-            cache: None,
-        });
+        let pipeline = create_pipeline(wgpu_context, shader_file, entry_point, &pipeline_layout, constants, !has_push_constants);
+
+        #[cfg(debug_assertions)]
+        let reloadable = ReloadableKernel::new(
+            std::panic::Location::caller(),
+            label.as_deref(),
+            entry_point,
+            constants,
+            pipeline_layout,
+            !has_push_constants,
+        );
+        #[cfg(debug_assertions)]
+        wgpu_context.shaders().register(&reloadable);
        
         Self {
             pipeline,
             workgroup_size,
+            uniform_constants,
+            #[cfg(debug_assertions)]
+            reloadable,
         }
     }
 
+    /// Recompiles the kernel from `source`, with the same entry point, constants and layout. The passes recorded
+    /// afterwards use the new pipeline. On error the kernel keeps its pipeline. Debug builds only, see
+    /// `ShaderWatcher`, which reloads the kernels of the WGSL files that changed on disk.
+    #[cfg(debug_assertions)]
+    pub fn reload(&self, wgpu_context: &WgpuContext, source: &str) -> Result<(), ShaderReloadError> {
+        self.reloadable.reload(wgpu_context, source)
+    }
+
     /// Dispatches the compute shader.
     pub fn dispatch(
         &self,
@@ -55,24 +233,7 @@ impl ComputeShader {
         bind_group: &BindGroup,
     ) {
 
-        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: None,
-            timestamp_writes: None,
-        });
-
-
-        compute_pass.set_pipeline(&self.pipeline);
-
-        if let Some(constants) = push_constants_data {
-            for (offset, data) in constants {
-                compute_pass.set_push_constants(
-                    offset,
-                    data,
-                );
-            }
-        }
-
-        compute_pass.set_bind_group(0, bind_group, &[]);
+        let mut compute_pass = self.begin_pass(encoder, None, push_constants_data, bind_group);
         compute_pass.dispatch_workgroups(dispatch_size.0, dispatch_size.1, dispatch_size.2);
     }
 
@@ -106,22 +267,43 @@ impl ComputeShader {
         push_constants_data: Option<Vec<(u32, &[u8])>>,
         bind_group: &BindGroup,
     ) {
-        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("Solve Pass"), timestamp_writes: None });
+        let mut compute_pass = self.begin_pass(encoder, Some("Solve Pass"), push_constants_data, bind_group);
+        compute_pass.dispatch_workgroups_indirect(indirect_buffer, indirect_offset);
+    }
 
+    /// Begins a pass with the pipeline, the constants and the bind group set.
+    fn begin_pass<'a>(
+        &self,
+        encoder: &'a mut CommandEncoder,
+        label: Option<&str>,
+        push_constants_data: Option<Vec<(u32, &[u8])>>,
+        bind_group: &BindGroup,
+    ) -> wgpu::ComputePass<'a> {
+        let uniform_constants = self.uniform_constants.as_ref()
+            .map(|uniform_constants| uniform_constants.write(push_constants_data.as_deref().unwrap_or_default()));
 
-        compute_pass.set_pipeline(&self.pipeline);
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label,
+            timestamp_writes: None,
+        });
 
+        #[cfg(debug_assertions)]
+        let reloaded = self.reloadable.reloaded_pipeline();
+        #[cfg(debug_assertions)]
+        compute_pass.set_pipeline(reloaded.as_ref().unwrap_or(&self.pipeline));
+        #[cfg(not(debug_assertions))]
+        compute_pass.set_pipeline(&self.pipeline);
 
-        if let Some(constants) = push_constants_data {
-            for (offset, data) in constants {
-                compute_pass.set_push_constants(
-                    offset,
-                    data,
-                );
+        match &uniform_constants {
+            Some((bind_group, offset)) => compute_pass.set_bind_group(UNIFORM_CONSTANTS_GROUP, bind_group, &[*offset]),
+            None => {
+                for (offset, data) in push_constants_data.into_iter().flatten() {
+                    compute_pass.set_push_constants(offset, data);
+                }
             }
         }
-        
+
         compute_pass.set_bind_group(0, bind_group, &[]);
-        compute_pass.dispatch_workgroups_indirect(indirect_buffer, indirect_offset);
+        compute_pass
     }
 }
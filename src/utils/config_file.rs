@@ -0,0 +1,182 @@
+//! Reader for the TOML subset of the config files: `key = value` lines, `[table]` headers and `#` comments.
+//! Values are numbers, booleans, double quoted strings without escapes and flat arrays of numbers.
+use std::fmt;
+
+/// Why a config can not be loaded.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    /// The file could not be read.
+    Io { path: String, message: String },
+    /// The line is not valid in the subset, `line` starts at 1.
+    Syntax { line: usize, message: String },
+    /// The key is not a setting, usually a typo.
+    UnknownKey { line: usize, key: String },
+    /// The value has the wrong type or is out of range.
+    InvalidValue { key: String, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, message } => write!(f, "can not read {}: {}", path, message),
+            ConfigError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+            ConfigError::UnknownKey { line, key } => write!(f, "line {}: unknown setting {}", line, key),
+            ConfigError::InvalidValue { key, message } => write!(f, "invalid {}: {}", key, message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigValue {
+    Number(f64),
+    Bool(bool),
+    String(String),
+    Array(Vec<f64>),
+}
+
+/// One `key = value` line. Keys inside a table are prefixed with it, e.g. `timestep.substeps`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigEntry {
+    pub key: String,
+    pub value: ConfigValue,
+    pub line: usize,
+}
+
+impl ConfigEntry {
+    pub fn number(&self) -> Result<f64, ConfigError> {
+        match self.value {
+            ConfigValue::Number(number) => Ok(number),
+            _ => Err(self.invalid("expected a number")),
+        }
+    }
+
+    /// Whole number that is not negative.
+    pub fn count(&self) -> Result<u64, ConfigError> {
+        let number = self.number()?;
+        if number < 0.0 || number.fract() != 0.0 || number > u64::MAX as f64 {
+            return Err(self.invalid("expected a whole number that is not negative"));
+        }
+        Ok(number as u64)
+    }
+
+    pub fn bool(&self) -> Result<bool, ConfigError> {
+        match self.value {
+            ConfigValue::Bool(value) => Ok(value),
+            _ => Err(self.invalid("expected true or false")),
+        }
+    }
+
+    pub fn string(&self) -> Result<&str, ConfigError> {
+        match &self.value {
+            ConfigValue::String(value) => Ok(value),
+            _ => Err(self.invalid("expected a quoted string")),
+        }
+    }
+
+    /// Array of exactly two numbers.
+    pub fn pair(&self) -> Result<(f64, f64), ConfigError> {
+        match self.value.clone() {
+            ConfigValue::Array(values) if values.len() == 2 => Ok((values[0], values[1])),
+            _ => Err(self.invalid("expected an array of two numbers")),
+        }
+    }
+
+    pub fn invalid(&self, message: &str) -> ConfigError {
+        ConfigError::InvalidValue { key: self.key.clone(), message: message.to_string() }
+    }
+
+    pub fn unknown(&self) -> ConfigError {
+        ConfigError::UnknownKey { line: self.line, key: self.key.clone() }
+    }
+}
+
+/// Parses the entries of `source`, in file order. A key set twice is an error, like in TOML.
+pub fn parse(source: &str) -> Result<Vec<ConfigEntry>, ConfigError> {
+    let mut entries: Vec<ConfigEntry> = Vec::new();
+    let mut table = String::new();
+    for (index, raw_line) in source.lines().enumerate() {
+        let line = index + 1;
+        let syntax = |message: &str| ConfigError::Syntax { line, message: message.to_string() };
+        let text = strip_comment(raw_line).trim();
+        if text.is_empty() {
+            continue;
+        }
+        if let Some(header) = text.strip_prefix('[') {
+            let name = header.strip_suffix(']').ok_or_else(|| syntax("unclosed table header"))?.trim();
+            if !is_bare_key(name) {
+                return Err(syntax("invalid table name"));
+            }
+            table = name.to_string();
+            continue;
+        }
+
+        let (key, value) = text.split_once('=').ok_or_else(|| syntax("expected key = value"))?;
+        let key = key.trim();
+        if !is_bare_key(key) {
+            return Err(syntax("invalid key"));
+        }
+        let key = if table.is_empty() { key.to_string() } else { format!("{}.{}", table, key) };
+        if entries.iter().any(|entry| entry.key == key) {
+            return Err(syntax(&format!("{} is set twice", key)));
+        }
+        let value = parse_value(value.trim()).map_err(|message| syntax(&message))?;
+        entries.push(ConfigEntry { key, value, line });
+    }
+    Ok(entries)
+}
+
+/// Drops a `#` comment, unless the `#` is inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn parse_value(text: &str) -> Result<ConfigValue, String> {
+    match text {
+        "" => Err("missing value".to_string()),
+        "true" => Ok(ConfigValue::Bool(true)),
+        "false" => Ok(ConfigValue::Bool(false)),
+        _ if text.starts_with('"') => {
+            let inner = text[1..].strip_suffix('"').ok_or("unclosed string")?;
+            if inner.contains('"') || inner.contains('\\') {
+                return Err("escapes are not supported in strings".to_string());
+            }
+            Ok(ConfigValue::String(inner.to_string()))
+        }
+        _ if text.starts_with('[') => {
+            let inner = text[1..].strip_suffix(']').ok_or("unclosed array")?.trim();
+            // A trailing comma is allowed
+            let inner = inner.strip_suffix(',').unwrap_or(inner);
+            if inner.trim().is_empty() {
+                return Ok(ConfigValue::Array(Vec::new()));
+            }
+            inner.split(',').map(|item| parse_number(item.trim())).collect::<Result<Vec<_>, _>>().map(ConfigValue::Array)
+        }
+        _ => parse_number(text).map(ConfigValue::Number),
+    }
+}
+
+/// Integers and floats, with optional `_` separators between digits.
+fn parse_number(text: &str) -> Result<f64, String> {
+    let digits = text.replace('_', "");
+    if digits.is_empty() || text.starts_with('_') || text.ends_with('_') || text.contains("__") {
+        return Err(format!("invalid number {}", text));
+    }
+    match digits.parse::<f64>() {
+        Ok(number) if number.is_finite() => Ok(number),
+        _ => Err(format!("invalid number {}", text)),
+    }
+}
@@ -1,6 +1,14 @@
+use std::fmt;
+use std::future::Future;
 use std::mem;
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
 use crate::renderer::wgpu_context::{WgpuContext};
+use crate::utils::buffer_registry::BufferRegistration;
 use wgpu::{Buffer};
+use wgpu::util::DeviceExt;
 use wgpu::wgt::PollType::Wait;
 
 #[derive(Debug)]
@@ -8,24 +16,94 @@ pub struct GpuBuffer<T> {
     data: Vec<T>,
     buffer: Buffer,
     usage: wgpu::BufferUsages,
+    /// Required alignment of the binding offsets, in bytes, see `binding_range`
+    offset_alignment: u64,
+    /// Counts the buffer in `WgpuContext::buffers` until it is dropped
+    registration: BufferRegistration,
+    /// Follows `buffer` when it grows, for the `Bindings` that bind it
+    handle: BufferHandle,
+}
+
+/// The current GPU buffer of a `GpuBuffer`, shared with the `Bindings` that bind it. A push that grows the
+/// buffer replaces it here too, so the bind groups are recreated on their next use.
+#[derive(Clone, Debug)]
+pub struct BufferHandle {
+    buffer: Arc<Mutex<Buffer>>,
+}
+
+impl BufferHandle {
+    /// A handle that always refers to `buffer`, for buffers that are not owned by a `GpuBuffer`.
+    pub fn new(buffer: Buffer) -> Self {
+        Self { buffer: Arc::new(Mutex::new(buffer)) }
+    }
+
+    pub fn buffer(&self) -> Buffer {
+        self.buffer.lock().unwrap().clone()
+    }
+
+    fn replace(&self, buffer: Buffer) {
+        *self.buffer.lock().unwrap() = buffer;
+    }
+}
+
+/// Why `GpuBuffer::binding_range` can not bind a range.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BindingRangeError {
+    /// Bindings can not be empty.
+    Empty,
+    /// The range ends past the capacity of the buffer, in elements.
+    OutOfBounds { start: usize, len: usize, capacity: usize },
+    /// The byte offset is not a multiple of the offset alignment of the device.
+    MisalignedOffset { offset: u64, alignment: u64 },
+    /// The byte size is not a multiple of 4, which storage bindings require.
+    MisalignedSize { size: u64 },
+}
+
+impl fmt::Display for BindingRangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingRangeError::Empty => write!(f, "empty binding range"),
+            BindingRangeError::OutOfBounds { start, len, capacity } =>
+                write!(f, "range {}..{} out of a buffer of {} elements", start, start + len, capacity),
+            BindingRangeError::MisalignedOffset { offset, alignment } =>
+                write!(f, "offset of {} bytes is not a multiple of {}", offset, alignment),
+            BindingRangeError::MisalignedSize { size } =>
+                write!(f, "size of {} bytes is not a multiple of 4", size),
+        }
+    }
 }
 
 impl<T: bytemuck::Pod> GpuBuffer<T>{
+    /// Registers the buffer in `WgpuContext::buffers` under the file of the caller, its subsystem.
+    #[track_caller]
     pub fn new(wgpu_context: &WgpuContext, data: Vec<T>, usage: wgpu::BufferUsages) ->  Self {
         let usage = usage | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC;
+        let size = (data.capacity() * size_of::<T>().max(1)) as u64;
         let buffer = wgpu_context.get_device().create_buffer(&wgpu::BufferDescriptor  {
                     label: Some("GpuBuffer"),
-                    size: (data.capacity() * size_of::<T>().max(1)) as u64,
+                    size,
                     usage,
                     mapped_at_creation: false,
                 });
+        let registration = wgpu_context.buffers().register(std::panic::Location::caller(), size, usage);
         wgpu_context.get_queue().write_buffer(
             &buffer,
             0,
             bytemuck::cast_slice(&data)
         );
+        wgpu_context.transfers().record_upload((data.len() * size_of::<T>()) as u64);
+
+        let limits = wgpu_context.get_device().limits();
+        let mut offset_alignment = 4u64;
+        if usage.contains(wgpu::BufferUsages::STORAGE) {
+            offset_alignment = offset_alignment.max(limits.min_storage_buffer_offset_alignment as u64);
+        }
+        if usage.contains(wgpu::BufferUsages::UNIFORM) {
+            offset_alignment = offset_alignment.max(limits.min_uniform_buffer_offset_alignment as u64);
+        }
 
-        Self { data, buffer, usage}
+        let handle = BufferHandle::new(buffer.clone());
+        Self { data, buffer, usage, offset_alignment, registration, handle }
     }
     
     pub fn push(&mut self, value: T, wgpu_context: &WgpuContext) {
@@ -42,8 +120,23 @@ impl<T: bytemuck::Pod> GpuBuffer<T>{
         self.data.len()
     }
 
+    /// Shortens the buffer to `len` elements. The GPU buffer keeps its size and contents,
+    /// so the next pushes reuse the freed space.
+    pub fn truncate(&mut self, len: usize) {
+        self.data.truncate(len);
+    }
 
-
+    /// Grows the GPU buffer so it holds at least `capacity` elements, keeping its contents.
+    /// The pushes up to `capacity` then write in place, the bind groups of the buffer stay valid.
+    /// Returns true if the buffer was recreated: its bind groups must be recreated too, which `Bindings` does.
+    pub fn reserve(&mut self, wgpu_context: &WgpuContext, capacity: usize) -> bool {
+        if capacity <= self.capacity() {
+            return false;
+        }
+        self.data.reserve(capacity - self.data.len());
+        self.grow(wgpu_context, (capacity * size_of::<T>().max(1)) as u64, self.data.len());
+        true
+    }
 
     // Update the gpu buffer with the data in the vector
     fn upload(&mut self, wgpu_context: &WgpuContext, total_elems_added: usize) {
@@ -53,25 +146,7 @@ impl<T: bytemuck::Pod> GpuBuffer<T>{
 
         if needed_bytes > current_capacity {
             // need a bigger buffer: double the capacity
-            let new_capacity_bytes = needed_bytes.max(1) * 2;
-            let old_data_len_bytes = ((self.data.len()-total_elems_added) as u64) * elem_size;
-
-            let new_buffer = wgpu_context.get_device().create_buffer(&wgpu::BufferDescriptor {
-                label: Some("GpuBuffer (resized)"),
-                size: new_capacity_bytes,
-                usage: self.usage,
-                mapped_at_creation: false,
-            });
-
-            // Command a GPU-side copy from the old buffer to the new one.
-            let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("GpuBuffer Resize Copy"),
-            });
-            encoder.copy_buffer_to_buffer(&self.buffer, 0, &new_buffer, 0, old_data_len_bytes);
-            wgpu_context.get_queue().submit(Some(encoder.finish()));
-
-            // Replace the old buffer and update capacity.
-            self.buffer = new_buffer;
+            self.grow(wgpu_context, needed_bytes.max(1) * 2, self.data.len() - total_elems_added);
         }
 
         // small upload: write the new tail
@@ -83,7 +158,33 @@ impl<T: bytemuck::Pod> GpuBuffer<T>{
             byte_offset,
             bytemuck::cast_slice(slice),
         );
+        wgpu_context.transfers().record_upload(size_of_val(slice) as u64);
+
+    }
+
+    /// Replaces the GPU buffer by one of `new_capacity_bytes`, with a copy of the first `kept_len` elements.
+    fn grow(&mut self, wgpu_context: &WgpuContext, new_capacity_bytes: u64, kept_len: usize) {
+        let old_data_len_bytes = (kept_len * size_of::<T>().max(1)) as u64;
+
+        let new_buffer = wgpu_context.get_device().create_buffer(&wgpu::BufferDescriptor {
+            label: Some("GpuBuffer (resized)"),
+            size: new_capacity_bytes,
+            usage: self.usage,
+            mapped_at_creation: false,
+        });
 
+        // Command a GPU-side copy from the old buffer to the new one.
+        let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("GpuBuffer Resize Copy"),
+        });
+        encoder.copy_buffer_to_buffer(&self.buffer, 0, &new_buffer, 0, old_data_len_bytes);
+        wgpu_context.get_queue().submit(Some(encoder.finish()));
+        wgpu_context.transfers().record_copy(old_data_len_bytes);
+        wgpu_context.buffers().resize(&self.registration, new_capacity_bytes);
+
+        // Replace the old buffer and update capacity.
+        self.handle.replace(new_buffer.clone());
+        self.buffer = new_buffer;
     }
 
     /// Downloads data from the GPU buffer to the CPU-side `Vec`.
@@ -94,6 +195,13 @@ impl<T: bytemuck::Pod> GpuBuffer<T>{
     /// `Ok(&Vec<T>)` if the readback was successful.
     /// `Err(wgpu::BufferAsyncError)` if the buffer mapping fails.
     pub fn download(&mut self, wgpu_context: &WgpuContext) -> Result<&Vec<T>, wgpu::BufferAsyncError> {
+        self.data = self.read_back(wgpu_context)?;
+        Ok(&self.data)
+    }
+
+    /// Reads the GPU buffer back without touching the CPU-side `Vec`.
+    /// Reads as many elements as the CPU-side `Vec` tracks.
+    pub fn read_back(&self, wgpu_context: &WgpuContext) -> Result<Vec<T>, wgpu::BufferAsyncError> {
         let device = wgpu_context.get_device();
         let queue = wgpu_context.get_queue();
 
@@ -102,8 +210,7 @@ impl<T: bytemuck::Pod> GpuBuffer<T>{
         let size = (self.data.len() * mem::size_of::<T>()) as u64;
         if size == 0 {
             // Nothing to download.
-            self.data.clear();
-            return Ok(&self.data);
+            return Ok(Vec::new());
         }
 
         // 1. Create a "staging" buffer. This is a special buffer that the CPU can read.
@@ -132,6 +239,7 @@ impl<T: bytemuck::Pod> GpuBuffer<T>{
 
         // 4. Submit the command to the queue for the GPU to execute.
         queue.submit(Some(encoder.finish()));
+        wgpu_context.transfers().record_readback(size);
 
         // 5. Map the staging buffer to read its contents from the CPU.
         // `map_async` is an asynchronous operation. We use a channel to wait for it
@@ -157,15 +265,14 @@ impl<T: bytemuck::Pod> GpuBuffer<T>{
                 // 9. The data is a slice of raw bytes (`&[u8]`). We cast it back to `&[T]`.
                 let downloaded_data: &[T] = bytemuck::cast_slice(&mapped_range);
 
-                // 10. Update our internal `data` vector with the new data from the GPU.
-                self.data.clear();
-                self.data.extend_from_slice(downloaded_data);
+                // 10. Copy the data out of the mapped range.
+                let data = downloaded_data.to_vec();
 
                 // 11. The `mapped_range` is a RAII guard. When it's dropped here,
                 // the buffer is automatically unmapped.
                 drop(mapped_range);
 
-                Ok(&self.data)
+                Ok(data)
             }
             Err(e) => {
                 // The mapping failed. We propagate the error to the caller.
@@ -174,6 +281,49 @@ impl<T: bytemuck::Pod> GpuBuffer<T>{
         }
     }
 
+    /// Same as `read_back`, but waits for the mapping asynchronously.
+    /// On the web `device.poll` cannot block, the browser resolves the mapping from its event loop,
+    /// so this is the only readback that works there.
+    pub async fn read_back_async(&self, wgpu_context: &WgpuContext) -> Result<Vec<T>, wgpu::BufferAsyncError> {
+        let device = wgpu_context.get_device();
+        let size = (self.data.len() * mem::size_of::<T>()) as u64;
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging Buffer (Async download)"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Async Download Encoder"),
+        });
+        encoder.copy_buffer_to_buffer(&self.buffer, 0, &staging_buffer, 0, size);
+        wgpu_context.get_queue().submit(Some(encoder.finish()));
+        wgpu_context.transfers().record_readback(size);
+
+        let buffer_slice = staging_buffer.slice(..);
+        let map_state = Arc::new(Mutex::new(MapState::default()));
+        let callback_state = map_state.clone();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+            let mut state = callback_state.lock().unwrap();
+            state.result = Some(result);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+
+        MapFuture { state: map_state, device: device.clone() }.await?;
+
+        let mapped_range = buffer_slice.get_mapped_range();
+        let data = bytemuck::cast_slice::<u8, T>(&mapped_range).to_vec();
+        drop(mapped_range);
+        staging_buffer.unmap();
+        Ok(data)
+    }
+
     /// Downloads just the last element from the GPU buffer.
     ///
     /// This is much more efficient than `download()` if you only need the last value,
@@ -226,6 +376,7 @@ impl<T: bytemuck::Pod> GpuBuffer<T>{
 
         // 5. Submit the command to the queue.
         queue.submit(Some(encoder.finish()));
+        wgpu_context.transfers().record_readback(element_size);
 
         // 6. Map the staging buffer and wait for the result synchronously.
         let buffer_slice = staging_buffer.slice(..);
@@ -262,16 +413,107 @@ impl<T: bytemuck::Pod> GpuBuffer<T>{
         }
     }
     
+    /// Replaces one element. Only that element is uploaded, so the rest of the GPU contents
+    /// are kept even if the CPU copy is out of date.
     pub fn replace_elem(&mut self, new_data: T, index: usize, wgpu_context: &WgpuContext) {
+        if index >= self.data.len() {
+            panic!("Index out of bounds");
+        }
+        self.write_range(index, std::slice::from_ref(&new_data), wgpu_context);
+    }
+
+    /// Replaces the elements from `offset` with `values`. Only their bytes are uploaded.
+    /// The bytes written must be 4-byte aligned, like every buffer write.
+    pub fn write_range(&mut self, offset: usize, values: &[T], wgpu_context: &WgpuContext) {
+        assert!(offset + values.len() <= self.data.len(), "Range {}..{} out of bounds of {} elements", offset, offset + values.len(), self.data.len());
+        if values.is_empty() {
+            return;
+        }
+        self.data[offset..offset + values.len()].copy_from_slice(values);
+        wgpu_context.get_queue().write_buffer(
+            &self.buffer,
+            (offset * size_of::<T>()) as u64,
+            bytemuck::cast_slice(values),
+        );
+        wgpu_context.transfers().record_upload(size_of_val(values) as u64);
+    }
+
+    /// Replaces one element like `replace_elem`, but records the upload into `encoder` as a copy from a staging
+    /// buffer, so it lands between the commands recorded before and after it: several steps recorded into one
+    /// encoder each see their own value. The element size must be a multiple of 4 bytes.
+    pub fn record_replace_elem(&mut self, encoder: &mut wgpu::CommandEncoder, new_data: T, index: usize, wgpu_context: &WgpuContext) {
         if index >= self.data.len() {
             panic!("Index out of bounds");
         }
         self.data[index] = new_data;
+        let staging_buffer = wgpu_context.get_device().create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("GpuBuffer staged write"),
+            contents: bytemuck::bytes_of(&new_data),
+            usage: wgpu::BufferUsages::COPY_SRC,
+        });
+        encoder.copy_buffer_to_buffer(&staging_buffer, 0, &self.buffer, (index * size_of::<T>()) as u64, size_of::<T>() as u64);
+        wgpu_context.transfers().record_upload(size_of::<T>() as u64);
+    }
+
+    /// Downloads only the elements of `range` into the CPU-side `Vec` and returns them.
+    /// The copy is widened to the 4-byte alignment copies require, the extra bytes are discarded.
+    pub fn download_range(&mut self, wgpu_context: &WgpuContext, range: Range<usize>) -> Result<&[T], wgpu::BufferAsyncError> {
+        assert!(range.start <= range.end && range.end <= self.data.len(), "Range {:?} out of bounds of {} elements", range, self.data.len());
+        let element_size = size_of::<T>() as u64;
+        if range.is_empty() || element_size == 0 {
+            return Ok(&self.data[range]);
+        }
+        let device = wgpu_context.get_device();
+
+        let byte_start = range.start as u64 * element_size;
+        let byte_end = range.end as u64 * element_size;
+        let copy_start = byte_start - byte_start % wgpu::COPY_BUFFER_ALIGNMENT;
+        let copy_end = byte_end.next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT).min(self.buffer.size());
+        let copy_size = (copy_end - copy_start).next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT);
+
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging Buffer (Download range)"),
+            size: copy_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Download Range Encoder"),
+        });
+        encoder.copy_buffer_to_buffer(&self.buffer, copy_start, &staging_buffer, 0, copy_end - copy_start);
+        wgpu_context.get_queue().submit(Some(encoder.finish()));
+        wgpu_context.transfers().record_readback(copy_end - copy_start);
+
+        let buffer_slice = staging_buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+            sender.send(result).unwrap();
+        });
+        device.poll(Wait).unwrap();
+        receiver.recv().unwrap()?;
+
+        {
+            let mapped_range = buffer_slice.get_mapped_range();
+            let skipped = (byte_start - copy_start) as usize;
+            let bytes = &mapped_range[skipped..skipped + (byte_end - byte_start) as usize];
+            // The skipped bytes can leave the elements unaligned
+            for (element, element_bytes) in self.data[range.clone()].iter_mut().zip(bytes.chunks_exact(element_size as usize)) {
+                *element = bytemuck::pod_read_unaligned(element_bytes);
+            }
+        }
+        Ok(&self.data[range])
+    }
+
+    /// Overwrites the whole buffer with `new_data`, which must have the current length.
+    pub fn overwrite(&mut self, new_data: &[T], wgpu_context: &WgpuContext) {
+        assert_eq!(new_data.len(), self.data.len(), "Overwrite must keep the buffer length");
+        self.data.copy_from_slice(new_data);
         wgpu_context.get_queue().write_buffer(
             &self.buffer,
             0,
             bytemuck::cast_slice(&self.data),
         );
+        wgpu_context.transfers().record_upload((self.data.len() * size_of::<T>()) as u64);
     }
 
     pub fn data(&self) -> &Vec<T>{
@@ -282,4 +524,79 @@ impl<T: bytemuck::Pod> GpuBuffer<T>{
         &self.buffer
     }
 
+    /// Follows the GPU buffer when a push grows it, see `Bindings`.
+    pub fn handle(&self) -> BufferHandle {
+        self.handle.clone()
+    }
+
+    /// Elements the GPU buffer can hold without growing, at least `len`.
+    pub fn capacity(&self) -> usize {
+        (self.buffer.size() / size_of::<T>().max(1) as u64) as usize
+    }
+
+    /// Byte alignment the offsets of `binding_range` must have on this device.
+    pub fn offset_alignment(&self) -> u64 {
+        self.offset_alignment
+    }
+
+    /// Binds `len` elements from `start`, e.g. only the active prefix of a buffer. The range may go past
+    /// `len()` up to the capacity. The byte offset must be aligned to `offset_alignment`, 256 on most devices.
+    /// The binding refers to the current GPU buffer, it must be recreated if a push grows it.
+    pub fn binding_range(&self, start: usize, len: usize) -> Result<wgpu::BufferBinding<'_>, BindingRangeError> {
+        if len == 0 || size_of::<T>() == 0 {
+            return Err(BindingRangeError::Empty);
+        }
+        let capacity = self.capacity();
+        if start.checked_add(len).is_none_or(|end| end > capacity) {
+            return Err(BindingRangeError::OutOfBounds { start, len, capacity });
+        }
+        let element_size = size_of::<T>() as u64;
+        let offset = start as u64 * element_size;
+        if !offset.is_multiple_of(self.offset_alignment) {
+            return Err(BindingRangeError::MisalignedOffset { offset, alignment: self.offset_alignment });
+        }
+        let size = len as u64 * element_size;
+        if !size.is_multiple_of(4) {
+            return Err(BindingRangeError::MisalignedSize { size });
+        }
+        Ok(wgpu::BufferBinding {
+            buffer: &self.buffer,
+            offset,
+            size: wgpu::BufferSize::new(size),
+        })
+    }
+
+}
+
+#[derive(Default)]
+struct MapState {
+    result: Option<Result<(), wgpu::BufferAsyncError>>,
+    waker: Option<Waker>,
+}
+
+/// Resolves when the `map_async` callback that owns `state` runs.
+struct MapFuture {
+    state: Arc<Mutex<MapState>>,
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    device: wgpu::Device,
+}
+
+impl Future for MapFuture {
+    type Output = Result<(), wgpu::BufferAsyncError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Natively the map callback only runs from a device poll, nobody else drives the device: wait for the copy
+        #[cfg(not(target_arch = "wasm32"))]
+        let _ = self.device.poll(Wait);
+
+        let mut state = self.state.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                // On the web the browser runs the map callback, which wakes the task
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
 }
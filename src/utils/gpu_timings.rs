@@ -0,0 +1,125 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use crate::utils::profiler::GpuTimerQueryResult;
+
+/// Frames the averages of `GpuTimings` cover by default, a second at 60 fps.
+pub const DEFAULT_WINDOW_FRAMES: usize = 60;
+
+/// GPU time of a profiler scope in one frame. `depth` is 0 for the scopes opened on an encoder,
+/// 1 for the ones nested in them, and so on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScopeTime {
+    pub label: String,
+    pub depth: u32,
+    pub time: Duration,
+}
+
+/// Average GPU time of a scope over the frames it ran in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScopeAverage {
+    pub label: String,
+    pub depth: u32,
+    pub average: Duration,
+    /// Frames of the window the scope ran in, e.g. the particle sort only runs every few seconds.
+    pub frames: usize,
+}
+
+/// Flattens the scopes of a finished profiler frame, parents first. Scopes without a time
+/// (timer queries disabled or unsupported) are skipped, their nested scopes are kept.
+pub fn scope_times(results: &[GpuTimerQueryResult]) -> Vec<ScopeTime> {
+    fn flatten(results: &[GpuTimerQueryResult], depth: u32, scopes: &mut Vec<ScopeTime>) {
+        for result in results {
+            if let Some(time) = &result.time {
+                scopes.push(ScopeTime {
+                    label: result.label.clone(),
+                    depth,
+                    time: Duration::from_secs_f64((time.end - time.start).max(0.0)),
+                });
+            }
+            flatten(&result.nested_queries, depth + 1, scopes);
+        }
+    }
+    let mut scopes = Vec::new();
+    flatten(results, 0, &mut scopes);
+    scopes
+}
+
+/// Rolling per-scope GPU times of the last frames, for the profiler overlay.
+pub struct GpuTimings {
+    window: usize,
+    frames: VecDeque<Vec<ScopeTime>>,
+}
+
+impl GpuTimings {
+    /// Averages over the last `window` frames, at least one.
+    pub fn new(window: usize) -> Self {
+        let window = window.max(1);
+        Self {
+            window,
+            frames: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// Adds a finished frame, dropping the oldest one past the window. A scope that ran several
+    /// times in the frame, e.g. once per step, counts with the sum of its times.
+    pub fn record_frame(&mut self, scopes: Vec<ScopeTime>) {
+        let mut merged: Vec<ScopeTime> = Vec::with_capacity(scopes.len());
+        for scope in scopes {
+            match merged.iter_mut().find(|merged| merged.label == scope.label && merged.depth == scope.depth) {
+                Some(merged) => merged.time += scope.time,
+                None => merged.push(scope),
+            }
+        }
+        if self.frames.len() == self.window {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(merged);
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Average of every scope, in the order of the last frame (the order the GPU ran them),
+    /// followed by the scopes that only ran in older frames.
+    pub fn averages(&self) -> Vec<ScopeAverage> {
+        let mut averages: Vec<ScopeAverage> = Vec::new();
+        for frame in self.frames.iter().rev() {
+            for scope in frame {
+                match averages.iter_mut().find(|average| average.label == scope.label && average.depth == scope.depth) {
+                    Some(average) => {
+                        average.average += scope.time;
+                        average.frames += 1;
+                    }
+                    None => averages.push(ScopeAverage {
+                        label: scope.label.clone(),
+                        depth: scope.depth,
+                        average: scope.time,
+                        frames: 1,
+                    }),
+                }
+            }
+        }
+        for average in &mut averages {
+            average.average /= average.frames as u32;
+        }
+        averages
+    }
+
+    /// Average GPU time of a frame: the outermost scopes, over every frame of the window.
+    pub fn frame_average(&self) -> Duration {
+        if self.frames.is_empty() {
+            return Duration::ZERO;
+        }
+        let total: Duration = self.frames.iter().flatten().filter(|scope| scope.depth == 0).map(|scope| scope.time).sum();
+        total / self.frames.len() as u32
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
@@ -0,0 +1,81 @@
+use std::time::{Duration, Instant};
+
+/// Options of the low power mode.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PowerSavingConfig {
+    /// Throttle the loop while the window is unfocused or the physics is paused.
+    pub enabled: bool,
+    /// Redraws per second while throttled.
+    pub idle_frame_rate: f32,
+    /// Keep stepping the physics while the window is unfocused, one longer step per throttled frame.
+    pub simulate_when_unfocused: bool,
+}
+
+impl Default for PowerSavingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            idle_frame_rate: 10.0,
+            simulate_when_unfocused: false,
+        }
+    }
+}
+
+/// Decides if the loop runs flat out or at the idle frame rate, and if the physics is stepped.
+/// While the window is unfocused or the physics is paused there is nothing worth drawing at full
+/// speed, so frames are only drawn every `1 / idle_frame_rate` seconds (or on input) to save battery.
+pub struct IdleThrottle {
+    config: PowerSavingConfig,
+    focused: bool,
+    last_frame: Instant,
+}
+
+impl IdleThrottle {
+    pub fn new(config: PowerSavingConfig) -> Self {
+        Self {
+            config,
+            focused: true,
+            last_frame: Instant::now(),
+        }
+    }
+
+    pub fn config(&self) -> PowerSavingConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: PowerSavingConfig) {
+        self.config = config;
+    }
+
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// True if frames should only be drawn at the idle frame rate.
+    pub fn is_throttled(&self, paused: bool) -> bool {
+        self.config.enabled && (paused || !self.focused)
+    }
+
+    pub fn should_step_physics(&self, paused: bool) -> bool {
+        !paused && (self.focused || !self.config.enabled || self.config.simulate_when_unfocused)
+    }
+
+    /// Must be called after every drawn frame.
+    pub fn frame_drawn(&mut self, now: Instant) {
+        self.last_frame = now;
+    }
+
+    /// When the next frame is due while throttled.
+    pub fn next_frame_time(&self) -> Instant {
+        self.last_frame + Duration::from_secs_f32(1.0 / self.config.idle_frame_rate.max(0.1))
+    }
+
+    /// True if a throttled loop should draw a frame at `now`.
+    pub fn is_frame_due(&self, now: Instant) -> bool {
+        now >= self.next_frame_time()
+    }
+}
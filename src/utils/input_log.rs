@@ -0,0 +1,344 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use glam::UVec2;
+use winit::dpi::PhysicalPosition;
+use winit::event::{MouseButton, MouseScrollDelta, WindowEvent};
+use winit::keyboard::{KeyCode, PhysicalKey};
+use crate::utils::rng;
+
+const HEADER: &str = "# gpu-physics-engine input log";
+
+/// Keys an input log can hold: the ones `InputManager` binds, and the modifiers. Other keys do nothing and are
+/// not logged.
+const LOGGED_KEYS: &[KeyCode] = &[
+    KeyCode::KeyA, KeyCode::KeyB, KeyCode::KeyC, KeyCode::KeyD, KeyCode::KeyE, KeyCode::KeyF, KeyCode::KeyG,
+    KeyCode::KeyH, KeyCode::KeyI, KeyCode::KeyJ, KeyCode::KeyK, KeyCode::KeyL, KeyCode::KeyM, KeyCode::KeyN,
+    KeyCode::KeyO, KeyCode::KeyP, KeyCode::KeyQ, KeyCode::KeyR, KeyCode::KeyS, KeyCode::KeyT, KeyCode::KeyU,
+    KeyCode::KeyV, KeyCode::KeyW, KeyCode::KeyX, KeyCode::KeyY, KeyCode::KeyZ,
+    KeyCode::Digit0, KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4,
+    KeyCode::Digit5, KeyCode::Digit6, KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+    KeyCode::ArrowUp, KeyCode::ArrowDown, KeyCode::ArrowLeft, KeyCode::ArrowRight,
+    KeyCode::Equal, KeyCode::Minus, KeyCode::NumpadAdd, KeyCode::NumpadSubtract,
+    KeyCode::BracketLeft, KeyCode::BracketRight, KeyCode::Comma, KeyCode::Period,
+    KeyCode::Space, KeyCode::Tab, KeyCode::Delete, KeyCode::Backspace, KeyCode::Escape,
+    KeyCode::ShiftLeft, KeyCode::ShiftRight,
+];
+
+/// An input event of the window, as `InputManager` handles it. Unlike `WindowEvent`, it can be built again
+/// from a log.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InputEvent {
+    Key { code: KeyCode, pressed: bool },
+    Shift { pressed: bool },
+    CursorMoved(PhysicalPosition<f64>),
+    CursorLeft,
+    MouseButton { button: MouseButton, pressed: bool },
+    MouseWheel(MouseScrollDelta),
+}
+
+impl InputEvent {
+    /// The input of `event`, None for the other window events and the keys that are not logged.
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        match event {
+            WindowEvent::KeyboardInput { event, .. } => match event.physical_key {
+                PhysicalKey::Code(code) if LOGGED_KEYS.contains(&code) => Some(Self::Key { code, pressed: event.state.is_pressed() }),
+                _ => None,
+            },
+            WindowEvent::ModifiersChanged(modifiers) => Some(Self::Shift { pressed: modifiers.state().shift_key() }),
+            WindowEvent::CursorMoved { position, .. } => Some(Self::CursorMoved(*position)),
+            WindowEvent::CursorLeft { .. } => Some(Self::CursorLeft),
+            WindowEvent::MouseInput { state, button, .. } => Some(Self::MouseButton { button: *button, pressed: state.is_pressed() }),
+            WindowEvent::MouseWheel { delta, .. } => Some(Self::MouseWheel(*delta)),
+            _ => None,
+        }
+    }
+
+    fn to_line(self) -> String {
+        let state = |pressed: bool| if pressed { "down" } else { "up" };
+        match self {
+            Self::Key { code, pressed } => format!("key {:?} {}", code, state(pressed)),
+            Self::Shift { pressed } => format!("shift {}", state(pressed)),
+            Self::CursorMoved(position) => format!("cursor {} {}", position.x, position.y),
+            Self::CursorLeft => "cursor_left".to_string(),
+            Self::MouseButton { button, pressed } => format!("button {} {}", button_name(button), state(pressed)),
+            Self::MouseWheel(MouseScrollDelta::LineDelta(x, y)) => format!("wheel lines {} {}", x, y),
+            Self::MouseWheel(MouseScrollDelta::PixelDelta(delta)) => format!("wheel pixels {} {}", delta.x, delta.y),
+        }
+    }
+
+    fn parse(words: &[&str]) -> Option<Self> {
+        let pressed = |word: &str| match word {
+            "down" => Some(true),
+            "up" => Some(false),
+            _ => None,
+        };
+        let number = |word: &str| word.parse::<f64>().ok();
+        let line_delta = |word: &str| word.parse::<f32>().ok();
+        match words {
+            ["key", name, state] => {
+                let code = *LOGGED_KEYS.iter().find(|code| format!("{:?}", code) == *name)?;
+                Some(Self::Key { code, pressed: pressed(state)? })
+            }
+            ["shift", state] => Some(Self::Shift { pressed: pressed(state)? }),
+            ["cursor", x, y] => Some(Self::CursorMoved(PhysicalPosition::new(number(x)?, number(y)?))),
+            ["cursor_left"] => Some(Self::CursorLeft),
+            ["button", name, state] => Some(Self::MouseButton { button: parse_button(name)?, pressed: pressed(state)? }),
+            ["wheel", "lines", x, y] => Some(Self::MouseWheel(MouseScrollDelta::LineDelta(line_delta(x)?, line_delta(y)?))),
+            ["wheel", "pixels", x, y] => Some(Self::MouseWheel(MouseScrollDelta::PixelDelta(PhysicalPosition::new(number(x)?, number(y)?)))),
+            _ => None,
+        }
+    }
+}
+
+fn button_name(button: MouseButton) -> String {
+    match button {
+        MouseButton::Left => "left".to_string(),
+        MouseButton::Right => "right".to_string(),
+        MouseButton::Middle => "middle".to_string(),
+        MouseButton::Back => "back".to_string(),
+        MouseButton::Forward => "forward".to_string(),
+        MouseButton::Other(id) => format!("other{}", id),
+    }
+}
+
+fn parse_button(name: &str) -> Option<MouseButton> {
+    match name {
+        "left" => Some(MouseButton::Left),
+        "right" => Some(MouseButton::Right),
+        "middle" => Some(MouseButton::Middle),
+        "back" => Some(MouseButton::Back),
+        "forward" => Some(MouseButton::Forward),
+        _ => name.strip_prefix("other")?.parse().ok().map(MouseButton::Other),
+    }
+}
+
+/// The input events received before a frame, and the frame time it stepped the simulation with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoggedFrame {
+    pub events: Vec<InputEvent>,
+    pub frame_time: Duration,
+}
+
+/// Everything a windowed run depends on besides the config: the seed of `utils::rng`, the window size and, for
+/// every frame, the input events and the frame time. Replaying it steps the same simulation with the same input.
+/// Saved as text, one line per event, each frame ended by its frame time in nanoseconds:
+///
+/// ```text
+/// seed 1234
+/// window 1280 720
+/// cursor 640.5 360
+/// button left down
+/// frame 16666667
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputLog {
+    seed: u64,
+    window_size: UVec2,
+    frames: Vec<LoggedFrame>,
+    /// Events of the frame being recorded
+    pending_events: Vec<InputEvent>,
+}
+
+impl InputLog {
+    pub fn new(seed: u64, window_size: UVec2) -> Self {
+        Self {
+            seed,
+            window_size,
+            frames: Vec::new(),
+            pending_events: Vec::new(),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn window_size(&self) -> UVec2 {
+        self.window_size
+    }
+
+    pub fn set_window_size(&mut self, window_size: UVec2) {
+        self.window_size = window_size;
+    }
+
+    pub fn frames(&self) -> &[LoggedFrame] {
+        &self.frames
+    }
+
+    /// Adds an event to the next frame.
+    pub fn push_event(&mut self, event: InputEvent) {
+        self.pending_events.push(event);
+    }
+
+    /// Ends the frame with the events pushed since the last one.
+    pub fn end_frame(&mut self, frame_time: Duration) {
+        let events = std::mem::take(&mut self.pending_events);
+        self.frames.push(LoggedFrame { events, frame_time });
+    }
+
+    /// The log as text. Events pushed after the last frame are left out.
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\nseed {}\nwindow {} {}\n", HEADER, self.seed, self.window_size.x, self.window_size.y);
+        for frame in &self.frames {
+            for event in &frame.events {
+                text.push_str(&event.to_line());
+                text.push('\n');
+            }
+            let _ = writeln!(text, "frame {}", frame.frame_time.as_nanos());
+        }
+        text
+    }
+
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let invalid = |line: usize, message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line + 1, message));
+        let mut log = Self::new(0, UVec2::ZERO);
+        let (mut has_seed, mut has_window) = (false, false);
+        for (index, line) in text.lines().enumerate() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {}
+                [first, ..] if first.starts_with('#') => {}
+                ["seed", seed] => {
+                    log.seed = seed.parse().map_err(|_| invalid(index, "expected a whole number"))?;
+                    has_seed = true;
+                }
+                ["window", width, height] => {
+                    let size = width.parse().ok().zip(height.parse().ok()).ok_or_else(|| invalid(index, "expected a width and a height"))?;
+                    log.window_size = UVec2::new(size.0, size.1);
+                    has_window = true;
+                }
+                ["frame", nanos] => {
+                    let nanos: u64 = nanos.parse().map_err(|_| invalid(index, "expected nanoseconds"))?;
+                    log.end_frame(Duration::from_nanos(nanos));
+                }
+                _ => log.push_event(InputEvent::parse(&words).ok_or_else(|| invalid(index, &format!("unknown event {}", line.trim())))?),
+            }
+        }
+        if !has_seed || !has_window {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "an input log needs a seed and a window size"));
+        }
+        log.pending_events.clear();
+        Ok(log)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_text())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+    }
+}
+
+/// Where the input of the window comes from, and if it is logged. See `InputLog`.
+#[derive(Default)]
+pub enum InputSession {
+    /// Input from the window, not logged.
+    #[default]
+    Live,
+    /// Input from the window, logged and saved to `path` by `finish`.
+    Recording { log: InputLog, path: PathBuf },
+    /// Input from the log, the window input is ignored. Becomes `Live` after the last frame.
+    Replaying { log: InputLog, next_frame: usize },
+}
+
+impl InputSession {
+    /// Starts logging into `path` with a random seed, the window size is set once known.
+    pub fn recording(path: PathBuf) -> Self {
+        Self::Recording { log: InputLog::new(rng::os_seed(), UVec2::ZERO), path }
+    }
+
+    pub fn replaying(log: InputLog) -> Self {
+        Self::Replaying { log, next_frame: 0 }
+    }
+
+    pub fn is_live(&self) -> bool {
+        matches!(self, Self::Live)
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        matches!(self, Self::Replaying { .. })
+    }
+
+    /// Seeds `utils::rng` with the seed of the log, and records or checks the window size. Call it before any
+    /// particle is created.
+    pub fn begin(&mut self, window_size: UVec2) {
+        match self {
+            Self::Live => {}
+            Self::Recording { log, .. } => {
+                rng::seed(log.seed());
+                log.set_window_size(window_size);
+            }
+            Self::Replaying { log, .. } => {
+                rng::seed(log.seed());
+                if log.window_size() != window_size {
+                    log::warn!("The input log was recorded in a {} window, the replay runs in {}: the mouse will not match", log.window_size(), window_size);
+                }
+            }
+        }
+    }
+
+    /// Logs an event of the window while recording.
+    pub fn record_event(&mut self, event: InputEvent) {
+        if let Self::Recording { log, .. } = self {
+            log.push_event(event);
+        }
+    }
+
+    /// The events to inject before the next frame while replaying, empty otherwise.
+    pub fn replay_events(&self) -> Vec<InputEvent> {
+        match self {
+            Self::Replaying { log, next_frame } => log.frames().get(*next_frame).map_or_else(Vec::new, |frame| frame.events.clone()),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Ends a frame that took `measured`: logs it while recording, or returns the logged frame time while
+    /// replaying. The session goes live after the last logged frame.
+    pub fn end_frame(&mut self, measured: Duration) -> Duration {
+        match self {
+            Self::Live => measured,
+            Self::Recording { log, .. } => {
+                log.end_frame(measured);
+                measured
+            }
+            Self::Replaying { log, next_frame } => {
+                let Some(frame) = log.frames().get(*next_frame) else {
+                    *self = Self::Live;
+                    return measured;
+                };
+                let frame_time = frame.frame_time;
+                *next_frame += 1;
+                if *next_frame == log.frames().len() {
+                    log::info!("Replay finished after {} frames", next_frame);
+                    *self = Self::Live;
+                }
+                frame_time
+            }
+        }
+    }
+
+    /// Saves the log of a recording. Does nothing for the other sessions.
+    pub fn finish(&mut self) -> io::Result<()> {
+        match std::mem::take(self) {
+            Self::Recording { log, path } => {
+                log.save(&path)?;
+                log::info!("Input log of {} frames saved to {}", log.frames().len(), path.display());
+                Ok(())
+            }
+            session => {
+                *self = session;
+                Ok(())
+            }
+        }
+    }
+}
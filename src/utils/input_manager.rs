@@ -1,22 +1,179 @@
 use winit::dpi::PhysicalPosition;
+use glam::{Vec2, Vec4};
 use winit::event::{ElementState, MouseButton, MouseScrollDelta};
 use winit::event_loop::ActiveEventLoop;
 use winit::keyboard::{KeyCode};
+use crate::particles::particle_group::GroupOperation;
+use crate::particles::color_palette::ColorTheme;
+use crate::particles::particle_color_kernel::ParticleColorSettings;
+use crate::particles::particle_interaction::InteractionMode;
 use crate::state::State;
+use crate::utils::command_queue::SimulationCommand;
+use crate::utils::input_log::InputEvent;
+use crate::simulation_config::EARTH_GRAVITY;
+
+/// Color the C key paints the selected particles with.
+const SELECTION_COLOR: Vec4 = Vec4::new(1.0, 0.35, 0.2, 1.0);
+/// Factor the Q/E and R/T keys scale the interaction radius and strength by.
+const INTERACTION_SCALE_STEP: f32 = 1.25;
+/// Particles the +/- keys add or remove at once.
+const PARTICLE_BATCH_SIZE: usize = 100_000;
+/// Angle the J/L keys rotate the gravity by, in radians.
+const GRAVITY_ROTATION_STEP: f32 = std::f32::consts::PI / 12.0;
 
 pub struct InputManager {}
 
 impl InputManager {
-    
+
+    /// Hands an input event, from the window or from an input log, to its handler below
+    pub fn process_input_event(state: &mut State, event_loop: &ActiveEventLoop, event: &InputEvent) {
+        match *event {
+            InputEvent::Key { code, pressed } => {
+                let key_state = if pressed { ElementState::Pressed } else { ElementState::Released };
+                Self::process_keyboard_input(state, event_loop, &code, &key_state);
+            }
+            InputEvent::Shift { pressed } => Self::process_modifiers_changed(state, pressed),
+            InputEvent::CursorMoved(position) => Self::process_cursor_moved(state, &position),
+            InputEvent::CursorLeft => Self::process_cursor_left(state),
+            InputEvent::MouseButton { button, pressed } => {
+                let mouse_state = if pressed { ElementState::Pressed } else { ElementState::Released };
+                Self::process_mouse_input(state, &mouse_state, &button);
+            }
+            InputEvent::MouseWheel(delta) => Self::process_mouse_wheel(state, delta),
+        }
+    }
+
     /// Manages keyboard inputs from the user
     pub fn process_keyboard_input(state: &mut State, event_loop: &ActiveEventLoop, code: &KeyCode, key_state: &ElementState) {
         match (code, key_state.is_pressed()) {
             (KeyCode::Escape, true) => event_loop.exit(),
             (KeyCode::KeyP, true) => {
-                state.add_particles();
+                let position = state.get_mouse_world_position();
+                state.push_command(SimulationCommand::SpawnParticles { position });
+            },
+            (KeyCode::Equal | KeyCode::NumpadAdd, true) => {
+                state.push_command(SimulationCommand::AddParticleBatch(PARTICLE_BATCH_SIZE));
+            },
+            (KeyCode::Minus | KeyCode::NumpadSubtract, true) => {
+                state.push_command(SimulationCommand::RemoveParticleBatch(PARTICLE_BATCH_SIZE));
             },
             (KeyCode::KeyG, true) => {
-                state.toggle_grid_drawing();
+                state.push_command(SimulationCommand::ToggleGrid);
+            },
+            (KeyCode::KeyY, true) => {
+                state.push_command(SimulationCommand::CycleGridDebugView);
+            },
+            (KeyCode::KeyM, true) => {
+                state.push_command(SimulationCommand::ToggleTrails);
+            },
+            (KeyCode::KeyN, true) => {
+                state.push_command(SimulationCommand::ToggleDensityHeatmap);
+            },
+            (KeyCode::KeyK, true) => {
+                state.push_command(SimulationCommand::ToggleVelocityField);
+            },
+            (KeyCode::KeyZ, true) => {
+                state.push_command(SimulationCommand::ToggleSleep);
+            },
+            (KeyCode::BracketLeft, true) => {
+                state.push_command(SimulationCommand::ChangeSolverIterations(-1));
+            },
+            (KeyCode::BracketRight, true) => {
+                state.push_command(SimulationCommand::ChangeSolverIterations(1));
+            },
+            (KeyCode::F10, true) => {
+                state.push_command(SimulationCommand::CompareSolvers);
+            },
+            (KeyCode::F9, true) => {
+                let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs();
+                state.push_command(SimulationCommand::CaptureFrame(std::path::PathBuf::from(format!("captures/frame_{}", timestamp))));
+            },
+            (KeyCode::F3, true) => {
+                state.push_command(SimulationCommand::ToggleProfilerOverlay);
+            },
+            (KeyCode::F8, true) => {
+                let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs();
+                state.push_command(SimulationCommand::ExportHeightmap(std::path::PathBuf::from(format!("exports/heightmap_{}.png", timestamp))));
+            },
+            (KeyCode::F12, true) => {
+                let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs();
+                state.push_command(SimulationCommand::CaptureScreenshot(std::path::PathBuf::from(format!("screenshots/frame_{}.png", timestamp))));
+            },
+            (KeyCode::F7, true) => {
+                state.push_command(SimulationCommand::ToggleRecording);
+            },
+            (KeyCode::KeyC, true) => {
+                state.push_command(SimulationCommand::ApplyToSelection(GroupOperation::Recolor(SELECTION_COLOR)));
+            },
+            (KeyCode::KeyV, true) => {
+                let settings = ParticleColorSettings::cycle(state.particle_color_settings());
+                state.push_command(SimulationCommand::SetParticleColors(settings));
+            },
+            (KeyCode::KeyB, true) => {
+                state.push_command(SimulationCommand::SetColorTheme(ColorTheme::random()));
+            },
+            (KeyCode::KeyH, true) => {
+                let shape = state.particle_shape().next_built_in();
+                state.push_command(SimulationCommand::SetParticleShape(shape));
+            },
+            (KeyCode::KeyF, true) => {
+                state.push_command(SimulationCommand::ApplyToSelection(GroupOperation::Freeze));
+            },
+            (KeyCode::Delete | KeyCode::Backspace, true) => {
+                state.push_command(SimulationCommand::ApplyToSelection(GroupOperation::Remove));
+            },
+            (KeyCode::Digit1, true) => {
+                state.push_command(SimulationCommand::SetInteractionMode(InteractionMode::Attract));
+            },
+            (KeyCode::Digit2, true) => {
+                state.push_command(SimulationCommand::SetInteractionMode(InteractionMode::Repel));
+            },
+            (KeyCode::Digit3, true) => {
+                state.push_command(SimulationCommand::SetInteractionMode(InteractionMode::Vortex));
+            },
+            (KeyCode::Digit4, true) => {
+                state.push_command(SimulationCommand::SetInteractionMode(InteractionMode::Drag));
+            },
+            (KeyCode::Digit5, true) => {
+                state.push_command(SimulationCommand::SetInteractionMode(InteractionMode::Pick));
+            },
+            (KeyCode::Digit6, true) => {
+                state.push_command(SimulationCommand::SetInteractionMode(InteractionMode::Heat));
+            },
+            (KeyCode::KeyQ, true) => {
+                state.push_command(SimulationCommand::ScaleInteractionRadius(1.0 / INTERACTION_SCALE_STEP));
+            },
+            (KeyCode::KeyE, true) => {
+                state.push_command(SimulationCommand::ScaleInteractionRadius(INTERACTION_SCALE_STEP));
+            },
+            (KeyCode::KeyR, true) => {
+                state.push_command(SimulationCommand::ScaleInteractionStrength(1.0 / INTERACTION_SCALE_STEP));
+            },
+            (KeyCode::KeyT, true) => {
+                state.push_command(SimulationCommand::ScaleInteractionStrength(INTERACTION_SCALE_STEP));
+            },
+            (KeyCode::KeyJ, true) => {
+                state.set_gravity(Self::rotated_gravity(state.gravity(), GRAVITY_ROTATION_STEP));
+            },
+            (KeyCode::KeyL, true) => {
+                state.set_gravity(Self::rotated_gravity(state.gravity(), -GRAVITY_ROTATION_STEP));
+            },
+            (KeyCode::KeyO, true) => {
+                state.push_command(SimulationCommand::LoadHourglassDemo);
+            },
+            (KeyCode::Tab, true) => {
+                state.push_command(SimulationCommand::CycleActiveLayer);
+            },
+            (KeyCode::Space, true) => {
+                state.push_command(SimulationCommand::TogglePause);
+            },
+            (KeyCode::Comma, true) => {
+                let time_scale = state.get_time_scale() * 0.5;
+                state.push_command(SimulationCommand::SetTimeScale(time_scale));
+            },
+            (KeyCode::Period, true) => {
+                let time_scale = (state.get_time_scale() * 2.0).min(1.0);
+                state.push_command(SimulationCommand::SetTimeScale(time_scale));
             },
             (KeyCode::KeyW | KeyCode::ArrowUp, true) => {
                 state.move_camera(KeyCode::KeyW, true);
@@ -46,12 +203,27 @@ impl InputManager {
         }
     }
     
+    /// `gravity` rotated counterclockwise by `angle`. Without gravity, rotates the earth gravity.
+    fn rotated_gravity(gravity: Vec2, angle: f32) -> Vec2 {
+        let gravity = if gravity == Vec2::ZERO { EARTH_GRAVITY } else { gravity };
+        Vec2::from_angle(angle).rotate(gravity)
+    }
+
     /// Manages mouse movement
     pub fn process_cursor_moved(state: &mut State, position: &PhysicalPosition<f64>){
         // Update the stored mouse position
         state.set_mouse_position(Some(*position));
     }
+
+    pub fn process_cursor_left(state: &mut State){
+        state.clear_mouse_position();
+    }
     
+    /// Tracks the modifier keys, shift turns left drags into rectangle selections
+    pub fn process_modifiers_changed(state: &mut State, shift_pressed: bool){
+        state.set_shift_pressed(shift_pressed);
+    }
+
     /// Manages mouse button inputs from the user
     pub fn process_mouse_input(state: &mut State, mouse_state: &ElementState, button: &MouseButton){
         state.mouse_click_callback(mouse_state, button);
@@ -0,0 +1,116 @@
+//! Workgroup sizes of the kernels whose speed depends the most on the adapter, and their cache file.
+use std::fmt::Write;
+use std::path::Path;
+use crate::physics::collision_solver;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::config_file::{self, ConfigError};
+use crate::utils::prefix_sum::prefix_sum;
+use crate::utils::radix_sort::{self, RADIX_SORT_BUCKETS};
+
+/// Radix sort workgroups keep a flag word per bucket for every 32 threads, see `GPUSorter::workgroup_size`.
+const MIN_SORT_WORKGROUP_SIZE: u32 = 32;
+
+/// Workgroup sizes the sorter, the prefix sum and the collision solver are created with, read from
+/// `WgpuContext::kernel_tuning`. The defaults suit most desktop GPUs, `kernel_autotune::autotune` measures
+/// the candidates on the current adapter. Every size is still capped by `DeviceCapabilities::workgroup_size`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct KernelTuning {
+    /// Threads of the radix sort kernels, a power of two from 32 to `RADIX_SORT_BUCKETS`.
+    pub sort_workgroup_size: u32,
+    /// Blocks of `sort_workgroup_size` keys each radix sort workgroup processes, see `SortIndirectArgs::new`.
+    pub sort_blocks_per_workgroup: u32,
+    /// Threads of the prefix sum kernels, a power of two. Larger ones need fewer nested scans.
+    pub prefix_sum_workgroup_size: u32,
+    /// Threads of the collision solver kernels, a power of two.
+    pub collision_workgroup_size: u32,
+}
+
+impl Default for KernelTuning {
+    fn default() -> Self {
+        Self {
+            sort_workgroup_size: radix_sort::WORKGROUP_SIZE.0,
+            sort_blocks_per_workgroup: radix_sort::NUM_BLOCKS_PER_WORKGROUP,
+            prefix_sum_workgroup_size: prefix_sum::WORKGROUP_SIZE,
+            collision_workgroup_size: collision_solver::WORKGROUP_SIZE,
+        }
+    }
+}
+
+impl KernelTuning {
+    /// Name, backend and driver of the adapter of `wgpu_context`. A cached tuning is only used on the adapter
+    /// it was measured on.
+    pub fn adapter_key(wgpu_context: &WgpuContext) -> String {
+        let info = wgpu_context.get_adapter().get_info();
+        // The cache file has no escapes in its strings
+        format!("{} ({:?}, {} {})", info.name, info.backend, info.driver, info.driver_info).replace(['"', '\\'], "'")
+    }
+
+    /// Writes the tuning measured on `adapter` in the format of `from_toml_str`.
+    pub fn to_toml_string(&self, adapter: &str) -> String {
+        let mut text = String::from("# Kernel tuning written by --autotune. Delete the file to measure again.\n");
+        let _ = writeln!(text, "adapter = \"{}\"", adapter);
+        let _ = writeln!(text, "sort_workgroup_size = {}", self.sort_workgroup_size);
+        let _ = writeln!(text, "sort_blocks_per_workgroup = {}", self.sort_blocks_per_workgroup);
+        let _ = writeln!(text, "prefix_sum_workgroup_size = {}", self.prefix_sum_workgroup_size);
+        let _ = writeln!(text, "collision_workgroup_size = {}", self.collision_workgroup_size);
+        text
+    }
+
+    /// Parses a cache file, returns the adapter it was measured on and the tuning. Missing sizes keep their default.
+    pub fn from_toml_str(source: &str) -> Result<(String, Self), ConfigError> {
+        let mut adapter = String::new();
+        let mut tuning = Self::default();
+        for entry in &config_file::parse(source)? {
+            let size = || -> Result<u32, ConfigError> {
+                let size = entry.count()?;
+                if !size.is_power_of_two() || size > u32::MAX as u64 {
+                    return Err(entry.invalid("must be a power of two"));
+                }
+                Ok(size as u32)
+            };
+            match entry.key.as_str() {
+                "adapter" => adapter = entry.string()?.to_string(),
+                "sort_workgroup_size" => {
+                    tuning.sort_workgroup_size = size()?;
+                    if !(MIN_SORT_WORKGROUP_SIZE..=RADIX_SORT_BUCKETS).contains(&tuning.sort_workgroup_size) {
+                        return Err(entry.invalid(&format!("must be between {} and {}", MIN_SORT_WORKGROUP_SIZE, RADIX_SORT_BUCKETS)));
+                    }
+                }
+                "sort_blocks_per_workgroup" => {
+                    let blocks = entry.count()?;
+                    if blocks == 0 || blocks > u32::MAX as u64 {
+                        return Err(entry.invalid("must be positive"));
+                    }
+                    tuning.sort_blocks_per_workgroup = blocks as u32;
+                }
+                "prefix_sum_workgroup_size" => tuning.prefix_sum_workgroup_size = size()?,
+                "collision_workgroup_size" => tuning.collision_workgroup_size = size()?,
+                _ => return Err(entry.unknown()),
+            }
+        }
+        Ok((adapter, tuning))
+    }
+
+    /// The tuning cached at `path` for `adapter`, `None` if there is none or it was measured on another adapter.
+    pub fn load(path: impl AsRef<Path>, adapter: &str) -> Result<Option<Self>, ConfigError> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let source = std::fs::read_to_string(path).map_err(|error| ConfigError::Io {
+            path: path.display().to_string(),
+            message: error.to_string(),
+        })?;
+        let (cached_adapter, tuning) = Self::from_toml_str(&source)?;
+        Ok((cached_adapter == adapter).then_some(tuning))
+    }
+
+    /// Caches the tuning measured on `adapter` at `path`, creating its directory.
+    pub fn save(&self, path: impl AsRef<Path>, adapter: &str) -> std::io::Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, self.to_toml_string(adapter))
+    }
+}
@@ -5,10 +5,31 @@ pub mod compute_shader;
 pub mod radix_sort;
 pub mod prefix_sum;
 pub mod render_timer;
+#[cfg(feature = "windowing")]
 pub mod input_manager;
+#[cfg(feature = "windowing")]
+pub mod input_log;
 pub mod bind_resources;
+pub mod profiler;
+pub mod telemetry;
+pub mod buffer_registry;
+pub mod benchmark;
+pub mod command_queue;
+pub mod png;
+pub mod idle_throttle;
+pub mod present_schedule;
+pub mod step_accumulator;
+pub mod readback_queue;
+pub mod config_file;
+pub mod gpu_timings;
+pub mod kernel_tuning;
+pub mod pipeline_cache;
+#[cfg(debug_assertions)]
+pub mod shader_watcher;
+pub mod rng;
 
-/// Returns the maximum subgroup size of the GPU.
+/// Returns the maximum subgroup size of the GPU, `None` if the device has no subgroup operations.
 pub fn get_subgroup_size(wgpu_context: &WgpuContext) -> Option<u32> {
-    Some(wgpu_context.get_adapter().limits().max_subgroup_size)
+    let capabilities = wgpu_context.capabilities();
+    capabilities.subgroups.then_some(capabilities.max_subgroup_size)
 }
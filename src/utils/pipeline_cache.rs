@@ -0,0 +1,64 @@
+//! wgpu's pipeline cache, kept on disk so later runs skip most of the shader compilation.
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// The driver's compiled pipelines, loaded from a file of the cache directory and written back by `save`. The
+/// compute kernels and the render pipelines are created with it, see `WgpuContext::pipeline_cache`. Only Vulkan
+/// has `Features::PIPELINE_CACHE`, the other backends compile every pipeline at startup as before.
+pub struct PipelineCache {
+    cache: wgpu::PipelineCache,
+    /// Named after the adapter and driver by `wgpu::util::pipeline_cache_key`
+    path: PathBuf,
+}
+
+impl PipelineCache {
+    /// Loads the cache of `adapter_info` from `dir`, or starts an empty one. `None` if `device` was created without
+    /// `Features::PIPELINE_CACHE` or wgpu has no cache key for the adapter.
+    pub fn load(device: &wgpu::Device, adapter_info: &wgpu::AdapterInfo, dir: impl AsRef<Path>) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::PIPELINE_CACHE) {
+            return None;
+        }
+        let path = dir.as_ref().join(wgpu::util::pipeline_cache_key(adapter_info)?);
+        let data = match std::fs::read(&path) {
+            Ok(data) => Some(data),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => {
+                log::warn!("Ignoring the pipeline cache {}: {}", path.display(), e);
+                None
+            }
+        };
+        // SAFETY: the data was written by `get_data` for an adapter of the same cache key. wgpu checks its header
+        // and, with `fallback`, starts an empty cache when it belongs to another driver or is corrupted.
+        let cache = unsafe {
+            device.create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+                label: Some("Pipeline cache"),
+                data: data.as_deref(),
+                fallback: true,
+            })
+        };
+        log::info!("Pipeline cache {} ({} KiB loaded)", path.display(), data.map_or(0, |data| data.len() / 1024));
+        Some(Self { cache, path })
+    }
+
+    pub fn cache(&self) -> &wgpu::PipelineCache {
+        &self.cache
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the pipelines compiled so far, creating the directory. The data goes to a temporary file renamed
+    /// over the cache, so an interrupted write leaves the previous cache intact.
+    pub fn save(&self) -> std::io::Result<()> {
+        let Some(data) = self.cache.get_data() else {
+            return Ok(());
+        };
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let temporary_path = self.path.with_extension("tmp");
+        std::fs::write(&temporary_path, data)?;
+        std::fs::rename(&temporary_path, &self.path)
+    }
+}
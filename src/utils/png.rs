@@ -0,0 +1,92 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+/// Largest payload of an uncompressed deflate block.
+const MAX_STORED_BLOCK: usize = u16::MAX as usize;
+
+/// Encodes 8-bit RGBA pixels, row-major from the top left, as a PNG.
+/// The image data is stored without compression, so no extra dependency is needed;
+/// the files are bigger than they could be, but every viewer opens them.
+pub fn encode_rgba8(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+    assert!(width > 0 && height > 0, "PNG images can not be empty");
+    assert_eq!(pixels.len(), width as usize * height as usize * 4, "Expected {}x{} RGBA pixels", width, height);
+
+    // Every scanline starts with its filter type, 0 = none
+    let row_size = width as usize * 4;
+    let mut raw = Vec::with_capacity((row_size + 1) * height as usize);
+    for row in pixels.chunks_exact(row_size) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // Bit depth 8, color type 6 (RGBA), default compression, filter and no interlace
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+/// Encodes the pixels with `encode_rgba8` and writes them to `path`, creating the parent directories.
+pub fn save_rgba8(path: &Path, width: u32, height: u32, pixels: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, encode_rgba8(width, height, pixels))
+}
+
+fn write_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Zlib stream made of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let num_blocks = data.len().div_ceil(MAX_STORED_BLOCK).max(1);
+    let mut zlib = Vec::with_capacity(data.len() + num_blocks * 5 + 6);
+    // Deflate with a 32K window, no preset dictionary
+    zlib.extend_from_slice(&[0x78, 0x01]);
+    for block in 0..num_blocks {
+        let chunk = &data[block * MAX_STORED_BLOCK..((block + 1) * MAX_STORED_BLOCK).min(data.len())];
+        let is_last = block == num_blocks - 1;
+        zlib.push(is_last as u8);
+        let len = chunk.len() as u16;
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(chunk);
+    }
+    zlib.extend_from_slice(&adler32(data).to_be_bytes());
+    zlib
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % MOD_ADLER;
+        b = (b + a) % MOD_ADLER;
+    }
+    (b << 16) | a
+}
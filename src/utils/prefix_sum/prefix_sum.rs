@@ -6,24 +6,68 @@ use crate::utils::compute_shader::ComputeShader;
 use crate::utils::get_subgroup_size;
 use crate::utils::gpu_buffer::GpuBuffer;
 
-const WORKGROUP_SIZE: (u32, u32, u32) = (256, 1, 1);
-const LIMIT: u32 = WORKGROUP_SIZE.0 * WORKGROUP_SIZE.0;
+/// Default workgroup size, see `KernelTuning`. Capped by `DeviceCapabilities::workgroup_size`.
+pub const WORKGROUP_SIZE: u32 = 256;
+
+/// How the words of a scanned buffer are summed, the ELEMENT_KIND constant of prefix_sum.wgsl.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScanElementKind {
+    U32 = 0,
+    F32 = 1,
+    /// Two u32 summed per component, e.g. a count and an offset scanned together.
+    U32Pair = 2,
+}
+
+impl ScanElementKind {
+    fn words(self) -> usize {
+        match self {
+            ScanElementKind::U32 | ScanElementKind::F32 => 1,
+            ScanElementKind::U32Pair => 2,
+        }
+    }
+}
+
+/// Element types `PrefixSum` can scan.
+pub trait ScanElement: bytemuck::Pod {
+    const KIND: ScanElementKind;
+}
+
+impl ScanElement for u32 {
+    const KIND: ScanElementKind = ScanElementKind::U32;
+}
+
+impl ScanElement for f32 {
+    const KIND: ScanElementKind = ScanElementKind::F32;
+}
+
+impl ScanElement for [u32; 2] {
+    const KIND: ScanElementKind = ScanElementKind::U32Pair;
+}
+
 pub struct PrefixSum {
     first_pass: ComputeShader,
+    first_pass_exclusive: ComputeShader,
     second_pass: ComputeShader,
     third_pass: ComputeShader,
+    /// Block sums, `kind.words()` words per block
     intermediate_buffer: GpuBuffer<u32>,
     block_prefix_sum: Option<Box<PrefixSum>>,
     bind_resources: BindResources,
+    kind: ScanElementKind,
+    workgroup_size: u32,
 }
 
 impl PrefixSum {
-    pub fn new(wgpu_context: &WgpuContext, buffer: &GpuBuffer<u32>) -> Self {
-        
+    /// Prefix sum of the elements of `buffer`, in place.
+    pub fn new<T: ScanElement>(wgpu_context: &WgpuContext, buffer: &GpuBuffer<T>) -> Self {
+        Self::with_kind(wgpu_context, buffer.buffer(), buffer.len(), T::KIND)
+    }
 
+    fn with_kind(wgpu_context: &WgpuContext, buffer: &wgpu::Buffer, len: usize, kind: ScanElementKind) -> Self {
+        let workgroup_size = wgpu_context.capabilities().workgroup_size(wgpu_context.kernel_tuning().prefix_sum_workgroup_size);
         let intermediate_buffer = GpuBuffer::new(
             wgpu_context,
-            vec![0u32; PrefixSum::get_max_possible_block_sums(buffer)],
+            vec![0u32; Self::get_max_possible_block_sums(len, workgroup_size) * kind.words()],
             wgpu::BufferUsages::STORAGE,
         );
 
@@ -65,7 +109,7 @@ impl PrefixSum {
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
-                        resource: buffer.buffer().as_entire_binding(),
+                        resource: buffer.as_entire_binding(),
                     },
                     wgpu::BindGroupEntry {
                         binding: 1,
@@ -77,13 +121,19 @@ impl PrefixSum {
         
         let bind_resources = BindResources::new(binding_group_layout, binding_group);
 
-        let max_subgroup_size = get_subgroup_size(wgpu_context).unwrap();
-
-        let constants = vec![
-            ("SUBGROUP_SIZE", max_subgroup_size as f64),
-            ("WORKGROUP_SIZE", WORKGROUP_SIZE.0 as f64),
-            ("SHARED_MEMORY_SIZE", ((WORKGROUP_SIZE.0/max_subgroup_size)*2) as f64),
-        ];
+        // Without subgroups, or with workgroups smaller than a subgroup, the workgroup scan runs in workgroup memory
+        let (shader, constants) = match get_subgroup_size(wgpu_context).filter(|&size| size <= workgroup_size) {
+            Some(max_subgroup_size) => (wgpu::include_wgsl!("prefix_sum.wgsl"), vec![
+                ("SUBGROUP_SIZE", max_subgroup_size as f64),
+                ("WORKGROUP_SIZE", workgroup_size as f64),
+                ("SHARED_MEMORY_SIZE", ((workgroup_size/max_subgroup_size)*2) as f64),
+                ("ELEMENT_KIND", kind as u32 as f64),
+            ]),
+            None => (wgpu::include_wgsl!("prefix_sum_workgroup.wgsl"), vec![
+                ("WORKGROUP_SIZE", workgroup_size as f64),
+                ("ELEMENT_KIND", kind as u32 as f64),
+            ]),
+        };
 
         let push_constants = vec![
             PushConstantRange{
@@ -92,62 +142,69 @@ impl PrefixSum {
             }
         ];
 
-        let first_pass = ComputeShader::new(
+        let create_pass = |entry_point: &str, push_constants: &Vec<PushConstantRange>| ComputeShader::new(
             wgpu_context,
-            wgpu::include_wgsl!("prefix_sum.wgsl"),
-            "prefix_sum_of_each_block",
+            shader.clone(),
+            entry_point,
             &bind_resources.bind_group_layout,
-            WORKGROUP_SIZE,
+            (workgroup_size, 1, 1),
             &constants,
-            &push_constants
-        );
-        
-
-        let second_pass = ComputeShader::new(
-            wgpu_context,
-            wgpu::include_wgsl!("prefix_sum.wgsl"),
-            "prefix_sum_of_the_block_sums",
-            &bind_resources.bind_group_layout,
-            WORKGROUP_SIZE,
-            &constants,
-            &vec![]
-        );
-
-        let third_pass = ComputeShader::new(
-            wgpu_context,
-            wgpu::include_wgsl!("prefix_sum.wgsl"),
-            "add_block_prefix_sums_to_the_buffer",
-            &bind_resources.bind_group_layout,
-            WORKGROUP_SIZE,
-            &constants,
-            &push_constants
+            push_constants
         );
+        let first_pass = create_pass("prefix_sum_of_each_block", &push_constants);
+        let first_pass_exclusive = create_pass("exclusive_prefix_sum_of_each_block", &push_constants);
+        let second_pass = create_pass("prefix_sum_of_the_block_sums", &vec![]);
+        let third_pass = create_pass("add_block_prefix_sums_to_the_buffer", &push_constants);
 
         
         let mut block_prefix_sum = None;
-        if buffer.len() >= LIMIT as usize {
-            block_prefix_sum = Some(Box::new(PrefixSum::new(wgpu_context, &intermediate_buffer)));
+        if len >= Self::limit(workgroup_size) as usize {
+            let num_blocks = intermediate_buffer.len() / kind.words();
+            block_prefix_sum = Some(Box::new(PrefixSum::with_kind(wgpu_context, intermediate_buffer.buffer(), num_blocks, kind)));
         }
         
         Self {
             first_pass,  
+            first_pass_exclusive,
             second_pass,
             third_pass,
             intermediate_buffer,
             block_prefix_sum,
-            bind_resources
+            bind_resources,
+            kind,
+            workgroup_size,
         }
     }
     
-    /// Performs the prefix sum algorithm
-    pub fn execute(&self, wgpu_context: &WgpuContext, encoder: &mut CommandEncoder, num_items: u32) {
-        let num_blocks = (num_items as f32 / WORKGROUP_SIZE.0 as f32).ceil() as u32;
+    /// Performs the prefix sum algorithm: element i becomes the sum of the elements 0..=i.
+    pub fn execute(&self, _wgpu_context: &WgpuContext, encoder: &mut CommandEncoder, num_items: u32) {
+        self.record(encoder, num_items);
+    }
+
+    /// Exclusive prefix sum: element i becomes the sum of the elements 0..i, the first one 0.
+    pub fn execute_exclusive(&self, _wgpu_context: &WgpuContext, encoder: &mut CommandEncoder, num_items: u32) {
+        self.record_exclusive(encoder, num_items);
+    }
+
+    /// Same as `execute`, for callers that only have the encoder, e.g. the grid kernels.
+    pub fn record(&self, encoder: &mut CommandEncoder, num_items: u32) {
+        self.record_scan(encoder, num_items, &self.first_pass);
+    }
+
+    /// Same as `execute_exclusive`, for callers that only have the encoder.
+    pub fn record_exclusive(&self, encoder: &mut CommandEncoder, num_items: u32) {
+        self.record_scan(encoder, num_items, &self.first_pass_exclusive);
+    }
+
+    /// The exclusive scan only differs in the first pass: the block sums are inclusive either way.
+    fn record_scan(&self, encoder: &mut CommandEncoder, num_items: u32, first_pass: &ComputeShader) {
+        let num_blocks = num_items.div_ceil(self.workgroup_size);
 
         // Pass 1: Dispatch one workgroup per data block.
-        self.first_pass.dispatch_by_items(encoder, (num_items, 1, 1), Some(vec![(0, bytes_of(&num_items))]), &self.bind_resources.bind_group);
+        first_pass.dispatch_by_items(encoder, (num_items, 1, 1), Some(vec![(0, bytes_of(&num_items))]), &self.bind_resources.bind_group);
 
-        if num_items >= LIMIT {
-            self.block_prefix_sum.as_ref().unwrap().execute(wgpu_context, encoder, num_blocks);
+        if num_items >= Self::limit(self.workgroup_size) {
+            self.block_prefix_sum.as_ref().unwrap().record(encoder, num_blocks);
         }
         else {
             // Pass 2: Dispatch a single workgroup to scan the block_sums.
@@ -159,8 +216,18 @@ impl PrefixSum {
 
     }
     
-    fn get_max_possible_block_sums(buffer: &GpuBuffer<u32>) -> usize{
-        (buffer.len() as f32 / WORKGROUP_SIZE.0 as f32).ceil() as usize
+    fn get_max_possible_block_sums(len: usize, workgroup_size: u32) -> usize{
+        len.div_ceil(workgroup_size as usize)
+    }
+
+    /// From this many items on, the block sums are more than one workgroup scans and get a nested prefix sum.
+    fn limit(workgroup_size: u32) -> u32 {
+        workgroup_size * workgroup_size
+    }
+
+    /// Workgroup size picked for the device, see `DeviceCapabilities::workgroup_size`.
+    pub fn workgroup_size(&self) -> u32 {
+        self.workgroup_size
     }
     
     pub fn print_buffer(&mut self, wgpu_context: &WgpuContext){
@@ -169,19 +236,27 @@ impl PrefixSum {
     }
 
     /// Update buffers when resizing the buffer
-    pub fn update_buffers(&mut self, wgpu_context: &WgpuContext, buffer: &GpuBuffer<u32>) {
+    pub fn update_buffers<T: ScanElement>(&mut self, wgpu_context: &WgpuContext, buffer: &GpuBuffer<T>) {
+        assert_eq!(T::KIND, self.kind, "the prefix sum was created for {:?} elements", self.kind);
+        self.update_buffers_with_len(wgpu_context, buffer.buffer(), buffer.len());
+    }
+
+    fn update_buffers_with_len(&mut self, wgpu_context: &WgpuContext, buffer: &wgpu::Buffer, len: usize) {
         let binding_group_layout = &self.bind_resources.bind_group_layout;
         
-        let new_len: u32 = buffer.len() as u32;
+        let new_len: u32 = len as u32;
 
-        let num_blocks_to_add = PrefixSum::get_max_possible_block_sums(buffer)-self.intermediate_buffer.len();
-        self.intermediate_buffer.push_all(&vec![0u32; num_blocks_to_add], wgpu_context);
+        let words = self.kind.words();
+        let num_words_to_add = (PrefixSum::get_max_possible_block_sums(len, self.workgroup_size) * words).saturating_sub(self.intermediate_buffer.len());
+        self.intermediate_buffer.push_all(&vec![0u32; num_words_to_add], wgpu_context);
 
-        if new_len >= LIMIT && self.block_prefix_sum.is_none(){
-            self.block_prefix_sum = Some(Box::new(PrefixSum::new(wgpu_context, &self.intermediate_buffer)));
+        let num_blocks = self.intermediate_buffer.len() / words;
+        let limit = Self::limit(self.workgroup_size);
+        if new_len >= limit && self.block_prefix_sum.is_none(){
+            self.block_prefix_sum = Some(Box::new(PrefixSum::with_kind(wgpu_context, self.intermediate_buffer.buffer(), num_blocks, self.kind)));
         }
-        else if new_len >= LIMIT && self.block_prefix_sum.is_some(){
-            self.block_prefix_sum.as_mut().unwrap().update_buffers(wgpu_context, &self.intermediate_buffer);
+        else if new_len >= limit && self.block_prefix_sum.is_some(){
+            self.block_prefix_sum.as_mut().unwrap().update_buffers_with_len(wgpu_context, self.intermediate_buffer.buffer(), num_blocks);
         }
         
         self.bind_resources.bind_group = wgpu_context.get_device().create_bind_group(
@@ -191,7 +266,7 @@ impl PrefixSum {
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
-                        resource: buffer.buffer().as_entire_binding(),
+                        resource: buffer.as_entire_binding(),
                     },
                     wgpu::BindGroupEntry {
                         binding: 1,
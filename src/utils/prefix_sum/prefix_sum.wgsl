@@ -1,35 +1,90 @@
 override WORKGROUP_SIZE: u32 = 256;
 override SUBGROUP_SIZE: u32 = 64;
 override SHARED_MEMORY_SIZE: u32 = 64;
+// What the words of the buffers hold, see ScanElement: one u32, one f32 or a pair of u32 summed per component
+override ELEMENT_KIND: u32 = 0;
 
-var<workgroup> shared_data: array<u32, SHARED_MEMORY_SIZE>;
+const KIND_U32: u32 = 0u;
+const KIND_F32: u32 = 1u;
+const KIND_U32_PAIR: u32 = 2u;
+
+// Every element is held as a pair of words, the second one is 0 unless ELEMENT_KIND is KIND_U32_PAIR.
+// f32 elements are kept as their bits.
+var<workgroup> shared_data: array<vec2<u32>, SHARED_MEMORY_SIZE>;
 
 @group(0) @binding(0) var<storage, read_write> data: array<u32>;
 @group(0) @binding(1) var<storage, read_write> block_sums: array<u32>;
 
 var<push_constant> total_elems: u32;
 
-/// First pass
-@compute @workgroup_size(WORKGROUP_SIZE)
-fn prefix_sum_of_each_block(
-    @builtin(global_invocation_id) global_id: vec3<u32>,
-    @builtin(local_invocation_id) local_id: vec3<u32>,
-    @builtin(workgroup_id) workgroup_id: vec3<u32>,
-    @builtin(subgroup_invocation_id) subgroup_thread_id: u32,
-    @builtin(subgroup_size) subgroup_size: u32,
-    ){
+fn words_per_element() -> u32 {
+    return select(1u, 2u, ELEMENT_KIND == KIND_U32_PAIR);
+}
 
-    if global_id.x >= total_elems {return;}
+fn load_value(idx: u32) -> vec2<u32> {
+    let first_word = idx * words_per_element();
+    var value = vec2<u32>(data[first_word], 0u);
+    if ELEMENT_KIND == KIND_U32_PAIR {
+        value.y = data[first_word + 1u];
+    }
+    return value;
+}
 
-    let subgroup_id = local_id.x / subgroup_size;
-    let num_subgroups = WORKGROUP_SIZE / subgroup_size;
+fn store_value(idx: u32, value: vec2<u32>) {
+    let first_word = idx * words_per_element();
+    data[first_word] = value.x;
+    if ELEMENT_KIND == KIND_U32_PAIR {
+        data[first_word + 1u] = value.y;
+    }
+}
 
-    // Load value
-    let value = get_value(global_id.x);
+fn load_block_sum(idx: u32) -> vec2<u32> {
+    let first_word = idx * words_per_element();
+    var value = vec2<u32>(block_sums[first_word], 0u);
+    if ELEMENT_KIND == KIND_U32_PAIR {
+        value.y = block_sums[first_word + 1u];
+    }
+    return value;
+}
 
+fn store_block_sum(idx: u32, value: vec2<u32>) {
+    let first_word = idx * words_per_element();
+    block_sums[first_word] = value.x;
+    if ELEMENT_KIND == KIND_U32_PAIR {
+        block_sums[first_word + 1u] = value.y;
+    }
+}
+
+fn add(a: vec2<u32>, b: vec2<u32>) -> vec2<u32> {
+    if ELEMENT_KIND == KIND_F32 {
+        return vec2<u32>(bitcast<u32>(bitcast<f32>(a.x) + bitcast<f32>(b.x)), 0u);
+    }
+    return a + b;
+}
+
+fn subgroup_inclusive_add(value: vec2<u32>) -> vec2<u32> {
+    if ELEMENT_KIND == KIND_F32 {
+        return vec2<u32>(bitcast<u32>(subgroupInclusiveAdd(bitcast<f32>(value.x))), 0u);
+    }
+    return subgroupInclusiveAdd(value);
+}
+
+fn subgroup_exclusive_add(value: vec2<u32>) -> vec2<u32> {
+    if ELEMENT_KIND == KIND_F32 {
+        return vec2<u32>(bitcast<u32>(subgroupExclusiveAdd(bitcast<f32>(value.x))), 0u);
+    }
+    return subgroupExclusiveAdd(value);
+}
+
+/// Prefix sum of `value` over the workgroup. Returns the inclusive sum, then the exclusive one.
+/// Every thread of the workgroup must call it.
+fn workgroup_scan(value: vec2<u32>, local_idx: u32, subgroup_thread_id: u32, subgroup_size: u32) -> array<vec2<u32>, 2> {
+    let subgroup_id = local_idx / subgroup_size;
+    let num_subgroups = WORKGROUP_SIZE / subgroup_size;
 
     // Subgroup prefix sum
-    let thread_val = subgroupInclusiveAdd(value);
+    let thread_val = subgroup_inclusive_add(value);
+    let thread_exclusive_val = subgroup_exclusive_add(value);
 
     // The last thread of the subgroup has the total sum of the subgroup prefix sum
     if subgroup_thread_id == subgroup_size - 1u {
@@ -40,37 +95,65 @@ fn prefix_sum_of_each_block(
 
     // The first subgroup does an exclusive prefix sum of the total sums of the subgroups
     if subgroup_id == 0 {
-        let block_val = select(0, shared_data[subgroup_thread_id], subgroup_thread_id < num_subgroups);
-        let prefix_val = subgroupExclusiveAdd(block_val);
+        let block_val = select(vec2<u32>(0u), shared_data[subgroup_thread_id], subgroup_thread_id < num_subgroups);
+        let prefix_val = subgroup_exclusive_add(block_val);
         if subgroup_thread_id < num_subgroups {
             shared_data[subgroup_thread_id] = prefix_val;
         }
     }
     workgroupBarrier();
 
-    // Calculate the final value with the subgroup val and the block val
-    let final_value = thread_val + shared_data[subgroup_id];
+    // Calculate the final values with the subgroup val and the block val
+    let block_val = shared_data[subgroup_id];
+    return array<vec2<u32>, 2>(add(thread_val, block_val), add(thread_exclusive_val, block_val));
+}
+
+/// First pass: the prefix sum of each block, and the total of each block into block_sums.
+fn scan_each_block(global_idx: u32, local_idx: u32, block_idx: u32, subgroup_thread_id: u32, subgroup_size: u32, exclusive: bool) {
+    // The threads past the end take part in the scan with a 0
+    let in_bounds = global_idx < total_elems;
+    var value = vec2<u32>(0u);
+    if in_bounds {
+        value = load_value(global_idx);
+    }
 
+    let sums = workgroup_scan(value, local_idx, subgroup_thread_id, subgroup_size);
 
     // Only the last thread of the workgroup
-    if local_id.x == WORKGROUP_SIZE - 1 {
+    if local_idx == WORKGROUP_SIZE - 1 {
         // Store the total sum of the block to global memory
-        block_sums[workgroup_id.x] = final_value;
+        store_block_sum(block_idx, sums[0]);
     }
 
-
     // Write back to global memory
-    data[global_id.x] = final_value;
+    if in_bounds {
+        store_value(global_idx, select(sums[0], sums[1], exclusive));
+    }
 }
 
-
-
-fn get_value(idx: u32) -> u32 {
-     return data[idx];
+/// First pass, inclusive
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn prefix_sum_of_each_block(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(subgroup_invocation_id) subgroup_thread_id: u32,
+    @builtin(subgroup_size) subgroup_size: u32,
+    ){
+    scan_each_block(global_id.x, local_id.x, workgroup_id.x, subgroup_thread_id, subgroup_size, false);
 }
 
-fn get_block_value(idx: u32) -> u32 {
-    return block_sums[idx];
+/// First pass, exclusive: every element gets the sum of the elements before it in its block.
+/// The block sums stay inclusive, so the second and third passes are shared with the inclusive scan.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn exclusive_prefix_sum_of_each_block(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(subgroup_invocation_id) subgroup_thread_id: u32,
+    @builtin(subgroup_size) subgroup_size: u32,
+    ){
+    scan_each_block(global_id.x, local_id.x, workgroup_id.x, subgroup_thread_id, subgroup_size, true);
 }
 
 
@@ -79,49 +162,20 @@ fn get_block_value(idx: u32) -> u32 {
 fn prefix_sum_of_the_block_sums(
     @builtin(global_invocation_id) global_id: vec3<u32>,
     @builtin(local_invocation_id) local_id: vec3<u32>,
-    @builtin(workgroup_id) workgroup_id: vec3<u32>,
     @builtin(subgroup_invocation_id) subgroup_thread_id: u32,
     @builtin(subgroup_size) subgroup_size: u32,
     ){
 
-    let subgroup_id = local_id.x / subgroup_size;
-    let num_subgroups = WORKGROUP_SIZE / subgroup_size;
-
     // Load value
-    let block_sum = get_block_value(global_id.x);
-
-
-    // Subgroup prefix sum
-    let thread_val = subgroupInclusiveAdd(block_sum);
-
-    // The last thread of the subgroup has the total sum of the subgroup prefix sum
-    if subgroup_thread_id == subgroup_size - 1u {
-        // Store the subgroup total sum in shared memory
-        shared_data[subgroup_id] = thread_val;
-    }
-    workgroupBarrier();
-
-    // The first subgroup does an exclusive prefix sum of the total sums of the subgroups
-    if subgroup_id == 0 {
-        let block_val = select(0, shared_data[subgroup_thread_id], subgroup_thread_id < num_subgroups);
-        let prefix_val = subgroupExclusiveAdd(block_val);
-        if subgroup_thread_id < num_subgroups {
-            shared_data[subgroup_thread_id] = prefix_val;
-        }
-    }
-    workgroupBarrier();
-
-    // Calculate the final value with the subgroup val and the block val
-    let final_value = thread_val + shared_data[subgroup_id];
-
+    let block_sum = load_block_sum(global_id.x);
 
+    let sums = workgroup_scan(block_sum, local_id.x, subgroup_thread_id, subgroup_size);
 
     // Write back to global memory
-    block_sums[global_id.x] = final_value;
-
+    store_block_sum(global_id.x, sums[0]);
 }
 
-var<workgroup> previous_block_sum: u32;
+var<workgroup> previous_block_sum: vec2<u32>;
 
 /// Third pass
 @compute @workgroup_size(WORKGROUP_SIZE)
@@ -130,18 +184,18 @@ fn add_block_prefix_sums_to_the_buffer(
     @builtin(workgroup_id) workgroup_id: vec3<u32>,
     @builtin(local_invocation_id) local_id: vec3<u32>,
 ){
-    if global_id.x >= total_elems {return;} // Out of bounds
-
-    let block_id = global_id.x / WORKGROUP_SIZE;
+    let block_id = workgroup_id.x;
 
     // No need to compute the first block, as it does not have a preceding block
     if block_id == 0 {return;}
 
     // One thread loads the data to be read to shared memory
     if local_id.x == 0 {
-        previous_block_sum = block_sums[block_id - 1];
+        previous_block_sum = load_block_sum(block_id - 1);
     }
     workgroupBarrier();
 
-    data[global_id.x] += previous_block_sum;
-}
\ No newline at end of file
+    if global_id.x >= total_elems {return;} // Out of bounds
+
+    store_value(global_id.x, add(load_value(global_id.x), previous_block_sum));
+}
@@ -0,0 +1,173 @@
+// Variant of prefix_sum.wgsl for devices without subgroup operations, e.g. the WebGPU baseline:
+// the workgroup scan runs in workgroup memory. Same bindings, entry points and passes.
+override WORKGROUP_SIZE: u32 = 256;
+// What the words of the buffers hold, see ScanElement: one u32, one f32 or a pair of u32 summed per component
+override ELEMENT_KIND: u32 = 0;
+
+const KIND_U32: u32 = 0u;
+const KIND_F32: u32 = 1u;
+const KIND_U32_PAIR: u32 = 2u;
+
+// Every element is held as a pair of words, the second one is 0 unless ELEMENT_KIND is KIND_U32_PAIR.
+// f32 elements are kept as their bits.
+var<workgroup> shared_data: array<vec2<u32>, WORKGROUP_SIZE>;
+
+@group(0) @binding(0) var<storage, read_write> data: array<u32>;
+@group(0) @binding(1) var<storage, read_write> block_sums: array<u32>;
+
+var<push_constant> total_elems: u32;
+
+fn words_per_element() -> u32 {
+    return select(1u, 2u, ELEMENT_KIND == KIND_U32_PAIR);
+}
+
+fn load_value(idx: u32) -> vec2<u32> {
+    let first_word = idx * words_per_element();
+    var value = vec2<u32>(data[first_word], 0u);
+    if ELEMENT_KIND == KIND_U32_PAIR {
+        value.y = data[first_word + 1u];
+    }
+    return value;
+}
+
+fn store_value(idx: u32, value: vec2<u32>) {
+    let first_word = idx * words_per_element();
+    data[first_word] = value.x;
+    if ELEMENT_KIND == KIND_U32_PAIR {
+        data[first_word + 1u] = value.y;
+    }
+}
+
+fn load_block_sum(idx: u32) -> vec2<u32> {
+    let first_word = idx * words_per_element();
+    var value = vec2<u32>(block_sums[first_word], 0u);
+    if ELEMENT_KIND == KIND_U32_PAIR {
+        value.y = block_sums[first_word + 1u];
+    }
+    return value;
+}
+
+fn store_block_sum(idx: u32, value: vec2<u32>) {
+    let first_word = idx * words_per_element();
+    block_sums[first_word] = value.x;
+    if ELEMENT_KIND == KIND_U32_PAIR {
+        block_sums[first_word + 1u] = value.y;
+    }
+}
+
+fn add(a: vec2<u32>, b: vec2<u32>) -> vec2<u32> {
+    if ELEMENT_KIND == KIND_F32 {
+        return vec2<u32>(bitcast<u32>(bitcast<f32>(a.x) + bitcast<f32>(b.x)), 0u);
+    }
+    return a + b;
+}
+
+/// Prefix sum of `value` over the workgroup. Returns the inclusive sum, then the exclusive one.
+/// Every thread of the workgroup must call it.
+/// Hillis-Steele scan: at each step, every thread adds the value `offset` threads before it.
+fn workgroup_scan(value: vec2<u32>, local_idx: u32) -> array<vec2<u32>, 2> {
+    shared_data[local_idx] = value;
+    workgroupBarrier();
+
+    for (var offset = 1u; offset < WORKGROUP_SIZE; offset *= 2u) {
+        var sum = shared_data[local_idx];
+        if local_idx >= offset {
+            sum = add(shared_data[local_idx - offset], sum);
+        }
+        workgroupBarrier();
+        shared_data[local_idx] = sum;
+        workgroupBarrier();
+    }
+
+    // The exclusive sum is the inclusive sum of the previous thread
+    var exclusive = vec2<u32>(0u);
+    if local_idx > 0u {
+        exclusive = shared_data[local_idx - 1u];
+    }
+    return array<vec2<u32>, 2>(shared_data[local_idx], exclusive);
+}
+
+/// First pass: the prefix sum of each block, and the total of each block into block_sums.
+fn scan_each_block(global_idx: u32, local_idx: u32, block_idx: u32, exclusive: bool) {
+    // The threads past the end take part in the scan with a 0
+    let in_bounds = global_idx < total_elems;
+    var value = vec2<u32>(0u);
+    if in_bounds {
+        value = load_value(global_idx);
+    }
+
+    let sums = workgroup_scan(value, local_idx);
+
+    // Only the last thread of the workgroup
+    if local_idx == WORKGROUP_SIZE - 1 {
+        // Store the total sum of the block to global memory
+        store_block_sum(block_idx, sums[0]);
+    }
+
+    // Write back to global memory
+    if in_bounds {
+        store_value(global_idx, select(sums[0], sums[1], exclusive));
+    }
+}
+
+/// First pass, inclusive
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn prefix_sum_of_each_block(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    ){
+    scan_each_block(global_id.x, local_id.x, workgroup_id.x, false);
+}
+
+/// First pass, exclusive: every element gets the sum of the elements before it in its block.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn exclusive_prefix_sum_of_each_block(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    ){
+    scan_each_block(global_id.x, local_id.x, workgroup_id.x, true);
+}
+
+
+/// Second pass
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn prefix_sum_of_the_block_sums(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    ){
+
+    // Load value
+    let block_sum = load_block_sum(global_id.x);
+
+    let sums = workgroup_scan(block_sum, local_id.x);
+
+    // Write back to global memory
+    store_block_sum(global_id.x, sums[0]);
+}
+
+var<workgroup> previous_block_sum: vec2<u32>;
+
+/// Third pass
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn add_block_prefix_sums_to_the_buffer(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+){
+    let block_id = workgroup_id.x;
+
+    // No need to compute the first block, as it does not have a preceding block
+    if block_id == 0 {return;}
+
+    // One thread loads the data to be read to shared memory
+    if local_id.x == 0 {
+        previous_block_sum = load_block_sum(block_id - 1);
+    }
+    workgroupBarrier();
+
+    if global_id.x >= total_elems {return;} // Out of bounds
+
+    store_value(global_id.x, add(load_value(global_id.x), previous_block_sum));
+}
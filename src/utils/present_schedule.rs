@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+/// Options of the present skip.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PresentSkipConfig {
+    /// Skip presents while the physics is over the frame budget.
+    pub enabled: bool,
+    /// Time a physics step may take before frames start being skipped.
+    pub frame_budget: Duration,
+    /// Upper bound of the number of physics steps between two presented frames.
+    pub max_steps_per_present: u32,
+}
+
+impl Default for PresentSkipConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            frame_budget: Duration::from_secs_f64(1.0 / 60.0),
+            max_steps_per_present: 8,
+        }
+    }
+}
+
+/// Decides after every physics step if the frame is drawn. When the steps take longer than the
+/// frame budget, drawing every step only slows the simulation down further, so only one step out of
+/// `steps_per_present` is presented. The ratio doubles while the steps are over the budget and halves
+/// once they take less than half of it; throughput-oriented runs (e.g. fast-forwarding) simulate faster.
+pub struct PresentSchedule {
+    config: PresentSkipConfig,
+    steps_per_present: u32,
+    steps_since_present: u32,
+}
+
+impl PresentSchedule {
+    pub fn new(config: PresentSkipConfig) -> Self {
+        Self {
+            config,
+            steps_per_present: 1,
+            steps_since_present: 0,
+        }
+    }
+
+    pub fn config(&self) -> PresentSkipConfig {
+        self.config
+    }
+
+    /// Starts presenting every step again.
+    pub fn set_config(&mut self, config: PresentSkipConfig) {
+        self.config = config;
+        self.steps_per_present = 1;
+        self.steps_since_present = 0;
+    }
+
+    /// Physics steps per presented frame right now, 1 when nothing is skipped.
+    pub fn steps_per_present(&self) -> u32 {
+        self.steps_per_present
+    }
+
+    /// Must be called after every physics step with the time it took. Returns true if the frame should be presented.
+    pub fn step_finished(&mut self, step_time: Duration) -> bool {
+        if !self.config.enabled {
+            return true;
+        }
+        if step_time > self.config.frame_budget {
+            self.steps_per_present = (self.steps_per_present * 2).min(self.config.max_steps_per_present.max(1));
+        }
+        else if step_time < self.config.frame_budget / 2 {
+            self.steps_per_present = (self.steps_per_present / 2).max(1);
+        }
+
+        self.steps_since_present += 1;
+        if self.steps_since_present >= self.steps_per_present {
+            self.steps_since_present = 0;
+            return true;
+        }
+        false
+    }
+}
@@ -0,0 +1,86 @@
+// Re-exports the GPU profiler used by every kernel.
+// With the `profiling` feature this is `wgpu_profiler`; without it, a no-op stand-in with the
+// same surface is used so the physics kernels can be embedded without pulling in the profiler.
+
+#[cfg(feature = "profiling")]
+pub use wgpu_profiler::{GpuProfiler, GpuProfilerSettings, GpuTimerQueryResult};
+
+#[cfg(not(feature = "profiling"))]
+pub use self::noop::{GpuProfiler, GpuProfilerSettings, GpuTimerQueryResult, Scope};
+
+#[cfg(not(feature = "profiling"))]
+mod noop {
+    use std::convert::Infallible;
+    use std::ops::{Deref, DerefMut, Range};
+
+    #[derive(Clone, Debug)]
+    pub struct GpuProfilerSettings {
+        pub enable_timer_queries: bool,
+        pub enable_debug_groups: bool,
+        pub max_num_pending_frames: usize,
+    }
+
+    impl Default for GpuProfilerSettings {
+        fn default() -> Self {
+            Self {
+                enable_timer_queries: false,
+                enable_debug_groups: false,
+                max_num_pending_frames: 1,
+            }
+        }
+    }
+
+    /// Timing of one scope of a finished frame. The no-op profiler never finishes a frame.
+    #[derive(Clone, Debug)]
+    pub struct GpuTimerQueryResult {
+        pub label: String,
+        /// Start and end, in seconds.
+        pub time: Option<Range<f64>>,
+        pub nested_queries: Vec<GpuTimerQueryResult>,
+    }
+
+    /// Profiler that records nothing. Scopes simply hand back the wrapped encoder or pass.
+    pub struct GpuProfiler;
+
+    impl GpuProfiler {
+        pub fn new(_device: &wgpu::Device, _settings: GpuProfilerSettings) -> Result<Self, Infallible> {
+            Ok(Self)
+        }
+
+        pub fn scope<'a, Recorder>(&'a self, _label: impl Into<String>, recorder: &'a mut Recorder) -> Scope<'a, Recorder> {
+            Scope { recorder }
+        }
+
+        pub fn resolve_queries(&mut self, _encoder: &mut wgpu::CommandEncoder) {}
+
+        pub fn end_frame(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        pub fn change_settings(&mut self, _settings: GpuProfilerSettings) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        pub fn process_finished_frame(&mut self, _timestamp_period: f32) -> Option<Vec<GpuTimerQueryResult>> {
+            None
+        }
+    }
+
+    pub struct Scope<'a, Recorder> {
+        recorder: &'a mut Recorder,
+    }
+
+    impl<Recorder> Deref for Scope<'_, Recorder> {
+        type Target = Recorder;
+
+        fn deref(&self) -> &Recorder {
+            self.recorder
+        }
+    }
+
+    impl<Recorder> DerefMut for Scope<'_, Recorder> {
+        fn deref_mut(&mut self) -> &mut Recorder {
+            self.recorder
+        }
+    }
+}
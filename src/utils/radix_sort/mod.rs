@@ -1 +1,733 @@
-pub mod radix_sort;
\ No newline at end of file
+/*
+    This file implements a gpu version of radix sort. 
+
+    It sorts 32-bit keys, each one with a payload of one or more 32-bit words
+
+    All shaders can be found in radix_sort.wgsl, the early exit check is in sort_check.wgsl
+*/
+
+use std::{
+    num::{NonZeroU32},
+};
+
+use bytemuck::bytes_of;
+use wgpu::{include_wgsl, BufferAsyncError, PushConstantRange};
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::bind_resources::BindResources;
+use crate::utils::compute_shader::ComputeShader;
+use crate::utils::gpu_buffer::GpuBuffer;
+
+#[cfg(feature = "onesweep")]
+pub mod onesweep;
+
+/// Default workgroup size, see `GPUSorter::workgroup_size` and `KernelTuning`.
+pub const WORKGROUP_SIZE: (u32, u32, u32) = (256, 1, 1);
+
+/// The scatter keeps a 32-bit flag word per bucket for every 32 threads of the workgroup.
+pub const MIN_WORKGROUP_SIZE: u32 = 32;
+
+
+
+// Number of bits processed in one pass
+pub const RADIX_SORT_BITS_PER_PASS: u32 = 8;
+
+// 2^(bits processed in one pass)
+// In this case 2^8 = 256. Thus, 8 bits are processed in one pass
+// Workgroups with fewer threads than buckets loop over them
+pub const RADIX_SORT_BUCKETS: u32 = 1 << RADIX_SORT_BITS_PER_PASS;
+
+// Number of bits per element
+// u32 -> 32 bits
+// u64 -> 64 bits
+pub const BITS_PER_ELEMENT: u32 = 32;
+pub const RADIX_SORT_TOTAL_ITERATIONS: u32 = BITS_PER_ELEMENT / RADIX_SORT_BITS_PER_PASS;
+
+// Each workgroup processes NUM_BLOCKS_PER_WORKGROUP blocks/histograms by default, see `GPUSorter::blocks_per_workgroup`
+pub const NUM_BLOCKS_PER_WORKGROUP: u32 = 45;
+
+// num_elements of the push constants of an indirect sort: the kernels read the counts from the indirect args
+pub const INDIRECT_NUM_ELEMENTS: u32 = u32::MAX;
+
+
+pub struct GPUSorter {
+    histogram_shader: ComputeShader,
+    scatter_shader: ComputeShader,
+    sorting_buffers: SortBuffers,
+    indirect_args: GpuBuffer<SortIndirectArgs>,
+    payload_words: NonZeroU32,
+    early_exit: Option<SortEarlyExit>, // Set by set_early_exit
+    key_bits: u32, // Set by set_key_bits
+    workgroup_size: u32,
+    blocks_per_workgroup: u32,
+}
+
+/// Dispatch size and counts of `GPUSorter::sort_indirect`, written on the GPU by whoever knows
+/// how many elements to sort (e.g. the grid, from the live particle count).
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SortIndirectArgs {
+    /// Workgroups of both kernels, in the layout of `dispatch_workgroups_indirect`
+    pub workgroups: [u32; 3],
+    pub num_elements: u32,
+    /// Same as `workgroups[0]`, read by the scatter kernel
+    pub num_workgroups: u32,
+}
+
+impl SortIndirectArgs {
+    /// Args of a sort of `num_elements` by a sorter of `workgroup_size` and `blocks_per_workgroup`,
+    /// see `GPUSorter::workgroup_size` and `GPUSorter::blocks_per_workgroup`.
+    pub fn new(num_elements: u32, workgroup_size: u32, blocks_per_workgroup: u32) -> Self {
+        let total_threads = num_elements.div_ceil(blocks_per_workgroup);
+        let num_workgroups = total_threads.div_ceil(workgroup_size);
+        Self {
+            workgroups: [num_workgroups, 1, 1],
+            num_elements,
+            num_workgroups,
+        }
+    }
+}
+
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PushConstants {
+    pub num_elements: u32,
+    pub current_shift: u32, 
+    pub num_workgroups: u32,
+    pub num_blocks_per_workgroup: u32,
+}
+
+impl GPUSorter {
+    pub fn new(wgpu_context: &WgpuContext, length: NonZeroU32, keys: &GpuBuffer<u32>, payload: &GpuBuffer<u32>) -> Self {
+        Self::with_payload_words(wgpu_context, length, keys, payload, NonZeroU32::MIN)
+    }
+
+    /// Sorts keys that carry `payload_words` u32 words of payload each, e.g. a struct cast to words:
+    /// the payload of key `i` is `payload[i * payload_words..(i + 1) * payload_words]`, and every scatter moves
+    /// all its words. Each pass copies the whole payload, so it pays off over gathering the data after the sort
+    /// only for a few words.
+    pub fn with_payload_words(wgpu_context: &WgpuContext, length: NonZeroU32, keys: &GpuBuffer<u32>, payload: &GpuBuffer<u32>, payload_words: NonZeroU32) -> Self {
+        
+        let bind_group_layout = Self::create_bind_group_layout(wgpu_context.get_device());
+        let workgroup_size = Self::pick_workgroup_size(wgpu_context);
+        let blocks_per_workgroup = wgpu_context.kernel_tuning().sort_blocks_per_workgroup;
+
+        let indirect_args = GpuBuffer::new(
+            wgpu_context,
+            vec![SortIndirectArgs::new(length.get(), workgroup_size, blocks_per_workgroup)],
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::COPY_DST
+        );
+        let sorting_buffers = Self::create_sort_buffers(wgpu_context, length, keys, payload, payload_words, workgroup_size, blocks_per_workgroup, &indirect_args);
+        
+        let bind_group = sorting_buffers.bind_group_ping.clone();
+        
+        let bind_resources = BindResources::new(bind_group_layout, bind_group);
+        
+        
+        assert!(workgroup_size <= RADIX_SORT_BUCKETS);
+        
+        let constants = vec![
+            ("WORKGROUP_SIZE", workgroup_size as f64),
+            ("RADIX_SORT_BUCKETS", RADIX_SORT_BUCKETS as f64),
+            ("PAYLOAD_WORDS", payload_words.get() as f64),
+        ];
+
+
+        let push_constants = vec![
+            PushConstantRange{
+                stages: wgpu::ShaderStages::COMPUTE,
+                range: 0..size_of::<PushConstants>() as u32,
+            }
+        ];
+        
+        let histogram_shader = ComputeShader::new(
+            wgpu_context,
+            include_wgsl!("radix_sort.wgsl"),
+            "build_histogram",
+            &bind_resources.bind_group_layout,
+            (workgroup_size, 1, 1),
+            &constants,
+            &push_constants,
+        );
+
+
+        let scatter_shader = ComputeShader::new(
+            wgpu_context,
+            include_wgsl!("radix_sort.wgsl"),
+            "scatter_keys",
+            &bind_resources.bind_group_layout,
+            (workgroup_size, 1, 1),
+            &constants,
+            &push_constants
+        );
+
+
+        Self {
+            histogram_shader,
+            scatter_shader,
+            sorting_buffers,
+            indirect_args,
+            payload_words,
+            early_exit: None,
+            key_bits: BITS_PER_ELEMENT,
+            workgroup_size,
+            blocks_per_workgroup,
+        }
+    }
+
+    /// The sort workgroup size of `KernelTuning`, or less on devices with smaller workgroups.
+    /// The kernels then loop over the buckets.
+    fn pick_workgroup_size(wgpu_context: &WgpuContext) -> u32 {
+        let workgroup_size = wgpu_context.capabilities().workgroup_size(wgpu_context.kernel_tuning().sort_workgroup_size);
+        assert!(workgroup_size >= MIN_WORKGROUP_SIZE, "The radix sort needs workgroups of at least {} threads, the device allows {}", MIN_WORKGROUP_SIZE, workgroup_size);
+        workgroup_size
+    }
+
+    /// Workgroup size of the radix kernels on this device, which the indirect args must be computed with,
+    /// see `SortIndirectArgs::new`.
+    pub fn workgroup_size(&self) -> u32 {
+        self.workgroup_size
+    }
+
+    /// Blocks of `workgroup_size` keys each workgroup processes, from `KernelTuning`. The indirect args must be
+    /// computed with it too.
+    pub fn blocks_per_workgroup(&self) -> u32 {
+        self.blocks_per_workgroup
+    }
+
+    fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        return device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("radix sort bind group layout"),
+            entries: &[
+                // Keys buffer
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Histogram buffer
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Payload a
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Keys b 
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Payload b
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Indirect args
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+    }
+    
+    pub fn build_histogram(&self, encoder: &mut wgpu::CommandEncoder, total_threads: (u32, u32, u32), push_constants: &PushConstants, ping_pong: &bool){
+        let ping_pong_bind_group = if *ping_pong {&self.sorting_buffers.bind_group_ping} else {&self.sorting_buffers.bind_group_pong};
+        self.histogram_shader.dispatch_by_items(
+            encoder,
+            total_threads,
+            Some(vec![(0, bytes_of(push_constants))]),
+            ping_pong_bind_group
+        );
+    }
+
+    pub fn scatter(&self, encoder: &mut wgpu::CommandEncoder, total_threads: (u32, u32, u32), push_constants: &PushConstants, ping_pong: &bool){
+        let ping_pong_bind_group = if *ping_pong {&self.sorting_buffers.bind_group_ping} else {&self.sorting_buffers.bind_group_pong};
+        self.scatter_shader.dispatch_by_items(
+            encoder,
+            total_threads,
+            Some(vec![(0, bytes_of(push_constants))]),
+            ping_pong_bind_group       
+        );
+    }
+    pub fn sort(&self, encoder: &mut wgpu::CommandEncoder, sort_first_n:Option<u32>) {
+        let sort_buffers = &self.sorting_buffers;
+        
+        let num_elements = sort_first_n.unwrap_or(sort_buffers.len());
+        let total_threads = (num_elements.div_ceil(self.blocks_per_workgroup), 1, 1);
+        let num_workgroups = total_threads.0.div_ceil(self.workgroup_size);
+        if let Some(early_exit) = &self.early_exit {
+            early_exit.check(encoder, num_elements, sort_buffers.len(), self.blocks_per_workgroup);
+        }
+        let mut ping_pong: bool = true;
+        for i in 0..self.num_passes(){
+            let push_constants = PushConstants{
+                num_elements,
+                current_shift: i * RADIX_SORT_BITS_PER_PASS,
+                num_workgroups,
+                num_blocks_per_workgroup: self.blocks_per_workgroup,
+            };
+            match &self.early_exit {
+                Some(early_exit) => self.dispatch_pass_indirect(encoder, early_exit.pass_workgroups.buffer(), &push_constants, ping_pong),
+                None => {
+                    self.build_histogram(encoder, total_threads, &push_constants, &ping_pong);
+                    self.scatter(encoder, total_threads, &push_constants, &ping_pong);
+                }
+            }
+            ping_pong = !ping_pong;
+        }
+    }
+
+    /// Runs a check pass before every sort, and skips the radix passes on the GPU when the keys are already
+    /// in order, e.g. cell ids that barely changed since the last sort. The passes are then dispatched indirectly
+    /// with no workgroups, so the CPU records the same commands either way. The payload is left untouched too,
+    /// which is what sorting would have done.
+    pub fn set_early_exit(&mut self, wgpu_context: &WgpuContext, enabled: bool) {
+        self.early_exit = enabled.then(|| SortEarlyExit::new(wgpu_context, &self.sorting_buffers.keys_a, &self.indirect_args, self.workgroup_size));
+    }
+
+    pub fn early_exit(&self) -> bool {
+        self.early_exit.is_some()
+    }
+
+    /// One radix pass, the histogram and the scatter, with the dispatch size read from `dispatch_args`.
+    fn dispatch_pass_indirect(&self, encoder: &mut wgpu::CommandEncoder, dispatch_args: &wgpu::Buffer, push_constants: &PushConstants, ping_pong: bool) {
+        let ping_pong_bind_group = if ping_pong {&self.sorting_buffers.bind_group_ping} else {&self.sorting_buffers.bind_group_pong};
+        for shader in [&self.histogram_shader, &self.scatter_shader] {
+            shader.indirect_dispatch(
+                encoder,
+                dispatch_args,
+                0,
+                Some(vec![(0, bytes_of(push_constants))]),
+                ping_pong_bind_group
+            );
+        }
+    }
+
+    pub fn get_keys_b(&mut self, wgpu_context: &WgpuContext) -> Result<&Vec<u32>, BufferAsyncError> {
+        self.sorting_buffers.keys_b.download(wgpu_context)
+    }
+
+    pub fn get_histogram(&mut self, wgpu_context: &WgpuContext) -> Result<&Vec<u32>, BufferAsyncError> {
+        self.sorting_buffers.histogram.download(wgpu_context)
+    }
+    
+    /// Same as `sort`, but the number of elements and the dispatch size come from `indirect_args`,
+    /// so they can be written by a previous kernel without a CPU round trip.
+    /// Elements after `num_elements` are left untouched.
+    pub fn sort_indirect(&self, encoder: &mut wgpu::CommandEncoder) {
+        if let Some(early_exit) = &self.early_exit {
+            early_exit.check(encoder, INDIRECT_NUM_ELEMENTS, self.sorting_buffers.len(), self.blocks_per_workgroup);
+        }
+        let dispatch_args = match &self.early_exit {
+            Some(early_exit) => early_exit.pass_workgroups.buffer(),
+            None => self.indirect_args.buffer(),
+        };
+        let mut ping_pong: bool = true;
+        for i in 0..self.num_passes(){
+            let push_constants = PushConstants{
+                num_elements: INDIRECT_NUM_ELEMENTS,
+                current_shift: i * RADIX_SORT_BITS_PER_PASS,
+                num_workgroups: 0,
+                num_blocks_per_workgroup: self.blocks_per_workgroup,
+            };
+            self.dispatch_pass_indirect(encoder, dispatch_args, &push_constants, ping_pong);
+            ping_pong = !ping_pong;
+        }
+    }
+
+    /// Sorts on the lowest `key_bits` bits of the keys only, skipping the passes of the digits above them,
+    /// e.g. 2 passes instead of 4 for 16-bit keys. Keys must be below `1 << key_bits`, except keys with all
+    /// those bits set, like `UNUSED_CELL_ID`, which end up after them. The pass count is rounded up to an even
+    /// number, so the last pass still writes into the user buffers.
+    pub fn set_key_bits(&mut self, key_bits: u32) {
+        assert!((1..=BITS_PER_ELEMENT).contains(&key_bits), "key_bits must be in 1..={}, got {}", BITS_PER_ELEMENT, key_bits);
+        self.key_bits = key_bits;
+    }
+
+    pub fn key_bits(&self) -> u32 {
+        self.key_bits
+    }
+
+    /// Radix passes of every sort, see `set_key_bits`.
+    pub fn num_passes(&self) -> u32 {
+        self.key_bits.div_ceil(RADIX_SORT_BITS_PER_PASS).next_multiple_of(2)
+    }
+
+    /// u32 words of payload per key, see `with_payload_words`.
+    pub fn payload_words(&self) -> NonZeroU32 {
+        self.payload_words
+    }
+
+    /// Read by `sort_indirect`. Must never hold more elements than the sorting buffers.
+    pub fn indirect_args(&self) -> &GpuBuffer<SortIndirectArgs> {
+        &self.indirect_args
+    }
+
+    pub fn update_sorting_buffers(&mut self, wgpu_context: &WgpuContext,
+                                  length: NonZeroU32,
+                                  keys_a: &GpuBuffer<u32>,
+                                  payload_a: &GpuBuffer<u32>){
+        self.sorting_buffers = Self::create_sort_buffers(wgpu_context, length, keys_a, payload_a, self.payload_words, self.workgroup_size, self.blocks_per_workgroup, &self.indirect_args);
+        if let Some(early_exit) = self.early_exit.as_mut() {
+            early_exit.refresh(wgpu_context, &self.sorting_buffers.keys_a, &self.indirect_args);
+        }
+    }
+    
+    /// Creates all buffers necessary for sorting, using user-provided buffers for keys and values.
+    ///
+    /// # Arguments
+    ///
+    /// * `wgpu_context` - The wgpu context for creating new buffers.
+    /// * `length` - The number of key-value pairs to be sorted.
+    /// * `keys_a` - Your buffer containing the keys to be sorted.
+    /// * `payload_a` - Your buffer containing the corresponding values (payload).
+    /// * `payload_words` - The u32 words of payload of each key.
+    /// * `workgroup_size` - The workgroup size of the kernels, which the histogram size depends on.
+    /// * `blocks_per_workgroup` - The blocks each workgroup processes, which the histogram size depends on.
+    /// * `indirect_args` - The counts of `sort_indirect`.
+    fn create_sort_buffers(
+        wgpu_context: &WgpuContext,
+        length: NonZeroU32,
+        keys_a: &GpuBuffer<u32>,
+        payload_a: &GpuBuffer<u32>,
+        payload_words: NonZeroU32,
+        workgroup_size: u32,
+        blocks_per_workgroup: u32,
+        indirect_args: &GpuBuffer<SortIndirectArgs>,
+    ) -> SortBuffers {
+        let length = length.get();
+        let payload_len = length as usize * payload_words.get() as usize;
+        
+        let payload_b = GpuBuffer::new(
+            wgpu_context,
+            vec![0; payload_len],
+            wgpu::BufferUsages::STORAGE
+        );
+
+        let keys_b = GpuBuffer::new(
+            wgpu_context,
+            vec![0; length as usize],
+            wgpu::BufferUsages::STORAGE
+        );
+
+        let histogram = GpuBuffer::new(
+            wgpu_context,
+            vec![0; get_histogram_size(length, workgroup_size, blocks_per_workgroup) as usize],   
+            wgpu::BufferUsages::STORAGE
+        );
+        
+        let device = wgpu_context.get_device();
+
+        let bind_group_ping = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("radix sort bind group with user buffers"),
+            layout: &Self::create_bind_group_layout(device), 
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: keys_a.buffer().as_entire_binding(),
+                },
+                // Histogram buffer
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: histogram.buffer().as_entire_binding(),
+                },
+                // Payload a
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: payload_a.buffer().as_entire_binding(),
+                },
+                // Keys b
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: keys_b.buffer().as_entire_binding(),
+                },
+                // Payload b
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: payload_b.buffer().as_entire_binding(),
+                },
+                // Indirect args
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: indirect_args.buffer().as_entire_binding(),
+                },
+            ],
+        });
+
+        let bind_group_pong = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("radix sort bind group pong with user buffers"),
+            layout: &Self::create_bind_group_layout(device),
+            entries: &[
+                // Keys b
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: keys_b.buffer().as_entire_binding(),
+                },
+                // Histogram buffer
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: histogram.buffer().as_entire_binding(),
+                },
+                // Payload b
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: payload_b.buffer().as_entire_binding(),
+                },
+                // Keys a
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: keys_a.buffer().as_entire_binding(),
+                },
+                // Payload a
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: payload_a.buffer().as_entire_binding(),
+                },
+                // Indirect args
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: indirect_args.buffer().as_entire_binding(),
+                },
+            ],
+        });
+
+        SortBuffers {
+            keys_a: keys_a.buffer().clone(),
+            histogram,
+            keys_b,
+            payload_b,
+            bind_group_ping,
+            bind_group_pong,
+            length,
+        }
+    }
+    
+   
+}
+
+fn get_histogram_size(length: u32, workgroup_size: u32, blocks_per_workgroup: u32) -> u32 {
+    let total_threads = (length.div_ceil(blocks_per_workgroup), 1, 1);
+    let num_workgroups = total_threads.0.div_ceil(workgroup_size);
+    RADIX_SORT_BUCKETS * num_workgroups
+}
+
+/// Struct containing all buffers necessary for sorting.
+/// The key and value buffers can be read and written.
+pub struct SortBuffers {
+    /// the user buffer of the keys, read by the early exit check
+    keys_a: wgpu::Buffer,
+
+    #[allow(dead_code)]
+    histogram: GpuBuffer<u32>,
+    
+    /// intermediate key buffer for sorting
+    #[allow(dead_code)]
+    keys_b: GpuBuffer<u32>,
+
+    /// intermediate value buffer for sorting
+    #[allow(dead_code)]
+    payload_b: GpuBuffer<u32>,
+
+
+    /// bind group used for sorting
+    bind_group_ping: wgpu::BindGroup,
+    bind_group_pong: wgpu::BindGroup,
+
+    // number of key-value pairs
+    length: u32,
+}
+
+impl SortBuffers {
+    /// number of key-value pairs that can be stored in this buffer
+    pub fn len(&self) -> u32 {
+        self.length
+    }
+    
+
+    
+}
+
+
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SortCheckPushConstants {
+    num_elements: u32,
+    num_blocks_per_workgroup: u32,
+}
+
+/// Check of `GPUSorter::set_early_exit`. All its shaders can be found in sort_check.wgsl
+struct SortEarlyExit {
+    check_shader: ComputeShader,
+    gate_shader: ComputeShader,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    unsorted: GpuBuffer<u32>,
+    /// Dispatch size of the radix passes, written by the gate
+    pass_workgroups: GpuBuffer<u32>,
+}
+
+impl SortEarlyExit {
+    fn new(wgpu_context: &WgpuContext, keys: &wgpu::Buffer, indirect_args: &GpuBuffer<SortIndirectArgs>, sort_workgroup_size: u32) -> Self {
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = wgpu_context.get_device().create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("radix sort early exit bind group layout"),
+            entries: &[
+                // Keys and indirect args
+                storage_entry(0, true),
+                storage_entry(1, true),
+                // Unsorted flag and dispatch size of the passes
+                storage_entry(2, false),
+                storage_entry(3, false),
+            ],
+        });
+        let unsorted = GpuBuffer::new(wgpu_context, vec![0u32], wgpu::BufferUsages::STORAGE);
+        let pass_workgroups = GpuBuffer::new(wgpu_context, vec![0u32, 1, 1], wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT);
+
+        let workgroup_size = wgpu_context.capabilities().workgroup_size(WORKGROUP_SIZE.0);
+        let constants = vec![
+            ("WORKGROUP_SIZE", workgroup_size as f64),
+            ("SORT_WORKGROUP_SIZE", sort_workgroup_size as f64),
+        ];
+        let push_constants = vec![
+            PushConstantRange{
+                stages: wgpu::ShaderStages::COMPUTE,
+                range: 0..size_of::<SortCheckPushConstants>() as u32,
+            }
+        ];
+        let check_shader = ComputeShader::new(
+            wgpu_context,
+            include_wgsl!("sort_check.wgsl"),
+            "check_sorted",
+            &bind_group_layout,
+            (workgroup_size, 1, 1),
+            &constants,
+            &push_constants,
+        );
+        let gate_shader = ComputeShader::new(
+            wgpu_context,
+            include_wgsl!("sort_check.wgsl"),
+            "gate_radix_passes",
+            &bind_group_layout,
+            (1, 1, 1),
+            &constants,
+            &push_constants,
+        );
+        let bind_group = Self::create_bind_group(wgpu_context, &bind_group_layout, keys, indirect_args, &unsorted, &pass_workgroups);
+
+        Self {
+            check_shader,
+            gate_shader,
+            bind_group_layout,
+            bind_group,
+            unsorted,
+            pass_workgroups,
+        }
+    }
+
+    fn refresh(&mut self, wgpu_context: &WgpuContext, keys: &wgpu::Buffer, indirect_args: &GpuBuffer<SortIndirectArgs>) {
+        self.bind_group = Self::create_bind_group(wgpu_context, &self.bind_group_layout, keys, indirect_args, &self.unsorted, &self.pass_workgroups);
+    }
+
+    /// Writes the dispatch size of the radix passes: none if the first `num_elements` keys are sorted.
+    /// `capacity` threads check the keys, so `INDIRECT_NUM_ELEMENTS` works without knowing the count.
+    fn check(&self, encoder: &mut wgpu::CommandEncoder, num_elements: u32, capacity: u32, num_blocks_per_workgroup: u32) {
+        let push_constants = SortCheckPushConstants {
+            num_elements,
+            num_blocks_per_workgroup,
+        };
+        let checked = if num_elements == INDIRECT_NUM_ELEMENTS { capacity } else { num_elements };
+        self.check_shader.dispatch_by_items(
+            encoder,
+            (checked, 1, 1),
+            Some(vec![(0, bytes_of(&push_constants))]),
+            &self.bind_group
+        );
+        self.gate_shader.dispatch_by_items(
+            encoder,
+            (1, 1, 1),
+            Some(vec![(0, bytes_of(&push_constants))]),
+            &self.bind_group
+        );
+    }
+
+    fn create_bind_group(
+        wgpu_context: &WgpuContext,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        keys: &wgpu::Buffer,
+        indirect_args: &GpuBuffer<SortIndirectArgs>,
+        unsorted: &GpuBuffer<u32>,
+        pass_workgroups: &GpuBuffer<u32>,
+    ) -> wgpu::BindGroup {
+        wgpu_context.get_device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("radix sort early exit bind group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: keys.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: indirect_args.buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: unsorted.buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: pass_workgroups.buffer().as_entire_binding(),
+                },
+            ],
+        })
+    }
+}
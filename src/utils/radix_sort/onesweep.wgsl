@@ -0,0 +1,215 @@
+// Must be equal to RADIX_SORT_BUCKETS: every thread owns a bucket in the lookback
+override WORKGROUP_SIZE: u32 = 256;
+override RADIX_SORT_BUCKETS: u32 = 256;
+// Keys of a partition per thread, a partition (tile) has WORKGROUP_SIZE * KEYS_PER_THREAD keys
+override KEYS_PER_THREAD: u32 = 8;
+override FLAGS_PER_BUCKET: u32 = WORKGROUP_SIZE / 32;
+
+const RADIX_SORT_BITS_PER_PASS: u32 = 8u;
+const RADIX_SORT_TOTAL_ITERATIONS: u32 = 4u;
+
+// Partition status, packed as (count << 2) | flag
+const FLAG_NOT_READY: u32 = 0u;
+// count is the number of keys of the bucket in the partition
+const FLAG_AGGREGATE: u32 = 1u;
+// count is the number of keys of the bucket in the partition and every partition before it
+const FLAG_INCLUSIVE: u32 = 2u;
+const FLAG_MASK: u32 = 3u;
+
+struct PushConstants {
+    num_elements: u32,
+    pass_index: u32,
+}
+
+var<push_constant> push_constants: PushConstants;
+
+@group(0) @binding(0) var<storage, read> keys_in: array<u32>;
+@group(0) @binding(1) var<storage, read> payload_in: array<u32>;
+@group(0) @binding(2) var<storage, read_write> keys_out: array<u32>;
+@group(0) @binding(3) var<storage, read_write> payload_out: array<u32>;
+// Histograms of the 4 digits of every key, the exclusive prefix sums of them after scan_global_histogram
+@group(0) @binding(4) var<storage, read_write> global_histogram: array<atomic<u32>>;
+// One status per bucket of every partition of every pass
+@group(0) @binding(5) var<storage, read_write> partition_status: array<atomic<u32>>;
+// Next partition id of every pass
+@group(0) @binding(6) var<storage, read_write> partition_counters: array<atomic<u32>>;
+
+fn get_num_partitions() -> u32 {
+    let partition_size = WORKGROUP_SIZE * KEYS_PER_THREAD;
+    return (push_constants.num_elements + partition_size - 1u) / partition_size;
+}
+
+fn get_bucket(key: u32, pass_index: u32) -> u32 {
+    return (key >> (pass_index * RADIX_SORT_BITS_PER_PASS)) & (RADIX_SORT_BUCKETS - 1u);
+}
+
+var<workgroup> shared_digit_histograms: array<atomic<u32>, RADIX_SORT_BUCKETS * RADIX_SORT_TOTAL_ITERATIONS>;
+
+/// Upsweep: the histograms of all the digits in a single read of the keys.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn build_global_histogram(
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+) {
+    let local_idx = local_id.x;
+    for (var i = local_idx; i < RADIX_SORT_BUCKETS * RADIX_SORT_TOTAL_ITERATIONS; i += WORKGROUP_SIZE) {
+        atomicStore(&shared_digit_histograms[i], 0u);
+    }
+    workgroupBarrier();
+
+    let partition_start = workgroup_id.x * WORKGROUP_SIZE * KEYS_PER_THREAD;
+    for (var i = 0u; i < KEYS_PER_THREAD; i++) {
+        let index = partition_start + i * WORKGROUP_SIZE + local_idx;
+        if index < push_constants.num_elements {
+            let key = keys_in[index];
+            for (var pass_index = 0u; pass_index < RADIX_SORT_TOTAL_ITERATIONS; pass_index++) {
+                atomicAdd(&shared_digit_histograms[pass_index * RADIX_SORT_BUCKETS + get_bucket(key, pass_index)], 1u);
+            }
+        }
+    }
+    workgroupBarrier();
+
+    for (var i = local_idx; i < RADIX_SORT_BUCKETS * RADIX_SORT_TOTAL_ITERATIONS; i += WORKGROUP_SIZE) {
+        let count = atomicLoad(&shared_digit_histograms[i]);
+        if count > 0u {
+            atomicAdd(&global_histogram[i], count);
+        }
+    }
+}
+
+var<workgroup> shared_subgroup_sums: array<u32, WORKGROUP_SIZE>;
+
+/// Exclusive prefix sum of the histogram of one digit per workgroup: where each bucket starts in the output.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn scan_global_histogram(
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(subgroup_invocation_id) subgroup_thread_id: u32,
+    @builtin(subgroup_size) subgroup_size: u32,
+) {
+    let bucket = local_id.x;
+    let histogram_index = workgroup_id.x * RADIX_SORT_BUCKETS + bucket;
+    let subgroup_id = bucket / subgroup_size;
+    let num_subgroups = WORKGROUP_SIZE / subgroup_size;
+
+    let count = atomicLoad(&global_histogram[histogram_index]);
+    let subgroup_prefix = subgroupExclusiveAdd(count);
+    let subgroup_sum = subgroupAdd(count);
+    if subgroup_thread_id == 0u {
+        shared_subgroup_sums[subgroup_id] = subgroup_sum;
+    }
+    workgroupBarrier();
+
+    if bucket == 0u {
+        var accum = 0u;
+        for (var i = 0u; i < num_subgroups; i++) {
+            let sum = shared_subgroup_sums[i];
+            shared_subgroup_sums[i] = accum;
+            accum += sum;
+        }
+    }
+    workgroupBarrier();
+
+    atomicStore(&global_histogram[histogram_index], shared_subgroup_sums[subgroup_id] + subgroup_prefix);
+}
+
+var<workgroup> shared_partition_id: u32;
+var<workgroup> shared_partition_histogram: array<atomic<u32>, RADIX_SORT_BUCKETS>;
+// Output index of the next key of every bucket in the partition
+var<workgroup> shared_bucket_offsets: array<atomic<u32>, RADIX_SORT_BUCKETS>;
+// Per-bucket binary masks (used for local reordering inside workgroup), see scatter_keys of radix_sort.wgsl
+var<workgroup> shared_bin_flags: array<atomic<u32>, RADIX_SORT_BUCKETS * FLAGS_PER_BUCKET>;
+
+/// One digit pass: ranks the keys of a partition and scatters them, with the offsets of the earlier partitions
+/// found by decoupled lookback. Partition ids are taken in launch order, so a partition only waits for
+/// partitions that already started.
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn onesweep_pass(@builtin(local_invocation_id) local_id: vec3<u32>) {
+    let local_idx = local_id.x;
+    let pass_index = push_constants.pass_index;
+    let num_elements = push_constants.num_elements;
+
+    if local_idx == 0u {
+        shared_partition_id = atomicAdd(&partition_counters[pass_index], 1u);
+    }
+    atomicStore(&shared_partition_histogram[local_idx], 0u);
+    let partition_id = workgroupUniformLoad(&shared_partition_id);
+
+    // Step 1: histogram of the partition
+    let partition_start = partition_id * WORKGROUP_SIZE * KEYS_PER_THREAD;
+    for (var i = 0u; i < KEYS_PER_THREAD; i++) {
+        let index = partition_start + i * WORKGROUP_SIZE + local_idx;
+        if index < num_elements {
+            atomicAdd(&shared_partition_histogram[get_bucket(keys_in[index], pass_index)], 1u);
+        }
+    }
+    workgroupBarrier();
+
+    // Step 2: publish the histogram, then add the counts of the earlier partitions until one has its inclusive count
+    let bucket = local_idx;
+    let count = atomicLoad(&shared_partition_histogram[bucket]);
+    let status_base = pass_index * get_num_partitions() * RADIX_SORT_BUCKETS + bucket;
+    let own_status = status_base + partition_id * RADIX_SORT_BUCKETS;
+    var prefix = 0u;
+    if partition_id == 0u {
+        atomicStore(&partition_status[own_status], (count << 2u) | FLAG_INCLUSIVE);
+    } else {
+        atomicStore(&partition_status[own_status], (count << 2u) | FLAG_AGGREGATE);
+        var lookback_id = partition_id - 1u;
+        loop {
+            let status = atomicLoad(&partition_status[status_base + lookback_id * RADIX_SORT_BUCKETS]);
+            let flag = status & FLAG_MASK;
+            if flag == FLAG_NOT_READY {
+                continue;
+            }
+            prefix += status >> 2u;
+            if flag == FLAG_INCLUSIVE {
+                break;
+            }
+            lookback_id -= 1u;
+        }
+        atomicStore(&partition_status[own_status], ((prefix + count) << 2u) | FLAG_INCLUSIVE);
+    }
+    atomicStore(&shared_bucket_offsets[bucket], atomicLoad(&global_histogram[pass_index * RADIX_SORT_BUCKETS + bucket]) + prefix);
+
+    // Step 3: stable scatter, one block of WORKGROUP_SIZE keys at a time
+    let flag_offset = local_idx / 32u;
+    let flags_bit = 1u << (local_idx % 32u);
+    for (var i = 0u; i < KEYS_PER_THREAD; i++) {
+        let index = partition_start + i * WORKGROUP_SIZE + local_idx;
+
+        for (var j = 0u; j < FLAGS_PER_BUCKET; j++) {
+            atomicStore(&shared_bin_flags[local_idx * FLAGS_PER_BUCKET + j], 0u);
+        }
+        workgroupBarrier();
+
+        var key = 0u;
+        var key_bucket = 0u;
+        var bucket_offset = 0u;
+        if index < num_elements {
+            key = keys_in[index];
+            key_bucket = get_bucket(key, pass_index);
+            bucket_offset = atomicLoad(&shared_bucket_offsets[key_bucket]);
+            atomicOr(&shared_bin_flags[key_bucket * FLAGS_PER_BUCKET + flag_offset], flags_bit);
+        }
+        workgroupBarrier();
+
+        if index < num_elements {
+            var rank = 0u;
+            var block_count = 0u;
+            for (var j = 0u; j < FLAGS_PER_BUCKET; j++) {
+                let bits = atomicLoad(&shared_bin_flags[key_bucket * FLAGS_PER_BUCKET + j]);
+                rank += select(0u, countOneBits(bits), j < flag_offset);
+                rank += select(0u, countOneBits(bits & (flags_bit - 1u)), j == flag_offset);
+                block_count += countOneBits(bits);
+            }
+            let destination = bucket_offset + rank;
+            keys_out[destination] = key;
+            payload_out[destination] = payload_in[index];
+            if rank == block_count - 1u {
+                atomicAdd(&shared_bucket_offsets[key_bucket], block_count);
+            }
+        }
+        workgroupBarrier();
+    }
+}
@@ -1,7 +1,10 @@
+// A power of two of at least 32. Smaller than RADIX_SORT_BUCKETS on devices with smaller workgroups,
+// then every thread owns several buckets, see GPUSorter::workgroup_size
 override WORKGROUP_SIZE: u32 = 256;
 override RADIX_SORT_BUCKETS: u32 = 256;
-override SUBGROUP_SIZE: u32 = 64;
 override FLAGS_PER_BUCKET: u32 = WORKGROUP_SIZE / 32;
+// u32 words of payload per key, the payload of element i is payload[i * PAYLOAD_WORDS..(i + 1) * PAYLOAD_WORDS]
+override PAYLOAD_WORDS: u32 = 1;
 
 struct PushConstants {
     num_elements: u32,
@@ -10,6 +13,18 @@ struct PushConstants {
     num_blocks_per_workgroup: u32,
 }
 
+// Counts of an indirect sort, see SortIndirectArgs
+struct IndirectArgs {
+    x: u32,
+    y: u32,
+    z: u32,
+    num_elements: u32,
+    num_workgroups: u32,
+}
+
+// num_elements value of the push constants that reads the counts from indirect_args
+const INDIRECT_NUM_ELEMENTS: u32 = 0xffffffffu;
+
 var<push_constant> push_constants: PushConstants;
 var<workgroup> shared_histogram: array<atomic<u32>, RADIX_SORT_BUCKETS>;
 
@@ -18,6 +33,15 @@ var<workgroup> shared_histogram: array<atomic<u32>, RADIX_SORT_BUCKETS>;
 @group(0) @binding(2) var<storage, read_write> payload_a: array<u32>;
 @group(0) @binding(3) var<storage, read_write> keys_b: array<u32>;
 @group(0) @binding(4) var<storage, read_write> payload_b: array<u32>;
+@group(0) @binding(5) var<storage, read> indirect_args: IndirectArgs;
+
+fn get_num_elements() -> u32 {
+    return select(push_constants.num_elements, indirect_args.num_elements, push_constants.num_elements == INDIRECT_NUM_ELEMENTS);
+}
+
+fn get_num_workgroups() -> u32 {
+    return select(push_constants.num_workgroups, indirect_args.num_workgroups, push_constants.num_elements == INDIRECT_NUM_ELEMENTS);
+}
 
 @compute @workgroup_size(WORKGROUP_SIZE)
 fn build_histogram(
@@ -31,13 +55,13 @@ fn build_histogram(
     let global_idx = global_id.x;
 
     // Set to 0 the shared histogram values
-    if local_id.x < RADIX_SORT_BUCKETS {
-        atomicStore(&shared_histogram[local_idx], 0u);
+    for (var bucket = local_idx; bucket < RADIX_SORT_BUCKETS; bucket += WORKGROUP_SIZE) {
+        atomicStore(&shared_histogram[bucket], 0u);
     }
     workgroupBarrier();
 
     let num_blocks_per_workgroup = push_constants.num_blocks_per_workgroup;
-    let num_elements = push_constants.num_elements;
+    let num_elements = get_num_elements();
     let current_shift = push_constants.current_shift;
 
     // Each workgroup processes MULTIPLE blocks/histograms
@@ -52,15 +76,13 @@ fn build_histogram(
     }
     workgroupBarrier();
 
-    if local_idx < RADIX_SORT_BUCKETS {
-        histogram[RADIX_SORT_BUCKETS * workgroup_idx + local_idx] = atomicLoad(&shared_histogram[local_idx]);
+    for (var bucket = local_idx; bucket < RADIX_SORT_BUCKETS; bucket += WORKGROUP_SIZE) {
+        histogram[RADIX_SORT_BUCKETS * workgroup_idx + bucket] = atomicLoad(&shared_histogram[bucket]);
     }
 
 }
 
 
-override NUM_SUBGROUPS: u32 = WORKGROUP_SIZE / SUBGROUP_SIZE;
-
 
 // Exclusive prefix sum. Where each bucket starts in the final output
 var<workgroup> shared_global_offsets: array<atomic<u32>, RADIX_SORT_BUCKETS>;
@@ -77,37 +99,33 @@ var<workgroup> shared_bucket_prefix:  array<u32, RADIX_SORT_BUCKETS>;
 fn scatter_keys(    @builtin(global_invocation_id) g_id: vec3<u32>,
                     @builtin(local_invocation_id) l_id: vec3<u32>,
                     @builtin(workgroup_id) w_id: vec3<u32>,
-                    @builtin(subgroup_invocation_id) sg_tid: u32,
                )
 {
 
     let global_id = g_id.x;
     let local_id = l_id.x;
     let workgroup_id = w_id.x;
-    let subgroup_tid = sg_tid;
-    let num_workgroups = push_constants.num_workgroups;
+    let num_workgroups = get_num_workgroups();
     let num_blocks_per_workgroup = push_constants.num_blocks_per_workgroup;
-    let num_elements = push_constants.num_elements;
+    let num_elements = get_num_elements();
     let current_shift = push_constants.current_shift;
 
 
-    var local_histogram = 0u;
-    var histogram_count = 0u;
-
     // STEP 1: Build global bucket counts and workgroup-local exclusive bases (deterministic)
 
-    if (local_id < RADIX_SORT_BUCKETS) {
+    for (var bucket = local_id; bucket < RADIX_SORT_BUCKETS; bucket += WORKGROUP_SIZE) {
         // Exclusive prefix of this bucket over all workgroups (where this WG starts within the bucket)
+        var local_histogram = 0u;
         var accum = 0u;
         for (var i: u32 = 0u; i < num_workgroups; i++) {
-            let bucket_value = histogram[i * RADIX_SORT_BUCKETS + local_id];
+            let bucket_value = histogram[i * RADIX_SORT_BUCKETS + bucket];
             if (i == workgroup_id) {
                 local_histogram = accum; // exclusive: elems of earlier WGs in this bucket
             }
             accum += bucket_value;
         }
-        histogram_count = accum;                         // total count for this bucket
-        shared_bucket_counts[local_id] = histogram_count; // stash for WG-wide scan
+        shared_bucket_counts[bucket] = accum; // total count for this bucket, stashed for WG-wide scan
+        atomicStore(&shared_global_offsets[bucket], local_histogram); // the bucket start is added after the scan
     }
     workgroupBarrier();
 
@@ -122,10 +140,9 @@ fn scatter_keys(    @builtin(global_invocation_id) g_id: vec3<u32>,
     }
     workgroupBarrier();
 
-    if (local_id < RADIX_SORT_BUCKETS) {
+    for (var bucket = local_id; bucket < RADIX_SORT_BUCKETS; bucket += WORKGROUP_SIZE) {
         // Global base for this bucket slice owned by this workgroup
-        let base = shared_bucket_prefix[local_id] + local_histogram;
-        atomicStore(&shared_global_offsets[local_id], base);
+        atomicAdd(&shared_global_offsets[bucket], shared_bucket_prefix[bucket]);
     }
 
 
@@ -139,22 +156,20 @@ fn scatter_keys(    @builtin(global_invocation_id) g_id: vec3<u32>,
         let index = workgroup_id * num_blocks_per_workgroup * WORKGROUP_SIZE + i * WORKGROUP_SIZE + local_id;
 
         // Initialize bin flags to 0
-        if local_id < RADIX_SORT_BUCKETS {
+        for (var bucket = local_id; bucket < RADIX_SORT_BUCKETS; bucket += WORKGROUP_SIZE) {
             // For each flag in the bucket
             for(var j: u32 = 0; j < FLAGS_PER_BUCKET; j++){
-                let bin_flag_id = local_id * FLAGS_PER_BUCKET + j;
+                let bin_flag_id = bucket * FLAGS_PER_BUCKET + j;
                 atomicStore(&shared_bin_flags[bin_flag_id], 0u);
             }
         }
         workgroupBarrier();
 
         var element: u32 = 0;
-        var payload: u32 = 0;
         var bucket_id: u32 = 0;
         var bucket_offset: u32 = 0;
         if index < num_elements {
             element = keys_a[index];
-            payload = payload_a[index];
             bucket_id = (element >> current_shift) & (RADIX_SORT_BUCKETS - 1u);
             bucket_offset = atomicLoad(&shared_global_offsets[bucket_id]);
             let bin_flag_id = bucket_id * FLAGS_PER_BUCKET + flag_offset;
@@ -174,8 +189,11 @@ fn scatter_keys(    @builtin(global_invocation_id) g_id: vec3<u32>,
                 prefix += select(0u, partial_count, j == flag_offset);
                 count += full_count;
             }
-            keys_b[bucket_offset + prefix] = element;
-            payload_b[bucket_offset + prefix] = payload;
+            let destination = bucket_offset + prefix;
+            keys_b[destination] = element;
+            for(var word: u32 = 0; word < PAYLOAD_WORDS; word++){
+                payload_b[destination * PAYLOAD_WORDS + word] = payload_a[index * PAYLOAD_WORDS + word];
+            }
             if prefix == count - 1 {
                 atomicAdd(&shared_global_offsets[bucket_id], count);
             }
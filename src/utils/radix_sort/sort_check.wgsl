@@ -0,0 +1,54 @@
+override WORKGROUP_SIZE: u32 = 256;
+// Workgroup size of the radix sort kernels
+override SORT_WORKGROUP_SIZE: u32 = 256;
+
+// num_elements value of the push constants that reads the count from indirect_args
+const INDIRECT_NUM_ELEMENTS: u32 = 0xffffffffu;
+
+struct PushConstants {
+    num_elements: u32,
+    num_blocks_per_workgroup: u32,
+}
+
+// Must match SortIndirectArgs
+struct IndirectArgs {
+    x: u32,
+    y: u32,
+    z: u32,
+    num_elements: u32,
+    num_workgroups: u32,
+}
+
+var<push_constant> push_constants: PushConstants;
+
+@group(0) @binding(0) var<storage, read> keys: array<u32>;
+@group(0) @binding(1) var<storage, read> indirect_args: IndirectArgs;
+// Non-zero once two neighbouring keys are out of order. Cleared by gate_radix_passes
+@group(0) @binding(2) var<storage, read_write> unsorted: atomic<u32>;
+// Dispatch size of the radix passes, no workgroups when the keys are already sorted
+@group(0) @binding(3) var<storage, read_write> pass_workgroups: array<u32, 3>;
+
+fn get_num_elements() -> u32 {
+    return select(push_constants.num_elements, indirect_args.num_elements, push_constants.num_elements == INDIRECT_NUM_ELEMENTS);
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn check_sorted(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index + 1u >= get_num_elements() {
+        return;
+    }
+    if keys[index] > keys[index + 1u] {
+        atomicStore(&unsorted, 1u);
+    }
+}
+
+@compute @workgroup_size(1)
+fn gate_radix_passes() {
+    let total_threads = (get_num_elements() + push_constants.num_blocks_per_workgroup - 1u) / push_constants.num_blocks_per_workgroup;
+    let num_workgroups = (total_threads + SORT_WORKGROUP_SIZE - 1u) / SORT_WORKGROUP_SIZE;
+    pass_workgroups[0] = select(0u, num_workgroups, atomicLoad(&unsorted) != 0u);
+    pass_workgroups[1] = 1u;
+    pass_workgroups[2] = 1u;
+    atomicStore(&unsorted, 0u);
+}
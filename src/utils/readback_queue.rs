@@ -0,0 +1,143 @@
+use std::ops::Range;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use wgpu::BufferAsyncError;
+use wgpu::wgt::PollType;
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::gpu_buffer::GpuBuffer;
+
+/// Identifies a request of a `ReadbackQueue`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ReadbackId(u64);
+
+/// Data of a finished readback.
+#[derive(Clone, Debug)]
+pub struct CompletedReadback {
+    pub id: ReadbackId,
+    pub label: &'static str,
+    bytes: Vec<u8>,
+}
+
+impl CompletedReadback {
+    /// The elements that were read, with the element type of the buffer.
+    pub fn to_vec<T: bytemuck::Pod>(&self) -> Vec<T> {
+        self.bytes.chunks_exact(size_of::<T>()).map(bytemuck::pod_read_unaligned).collect()
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+struct PendingReadback {
+    id: ReadbackId,
+    label: &'static str,
+    staging_buffer: wgpu::Buffer,
+    /// Bytes of the staging buffer to keep, the copy is widened to the copy alignment
+    kept: Range<usize>,
+    /// `None` until `after_submit` maps the staging buffer
+    receiver: Option<Receiver<Result<(), BufferAsyncError>>>,
+}
+
+/// Reads buffers back without stalling the frame, unlike `GpuBuffer::download`: `request` schedules the copy
+/// after the work already submitted, and `poll` returns the data once the GPU is done, usually a frame or
+/// two later. Meant for profiling overlays and debug views, call `poll` once per frame.
+#[derive(Default)]
+pub struct ReadbackQueue {
+    pending: Vec<PendingReadback>,
+    next_id: u64,
+}
+
+impl ReadbackQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Readbacks requested and not returned by `poll` yet.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Schedules the copy of the elements of `range` of `buffer`. The result reflects every command
+    /// submitted before this call.
+    pub fn request<T: bytemuck::Pod>(&mut self, wgpu_context: &WgpuContext, label: &'static str, buffer: &GpuBuffer<T>, range: Range<usize>) -> ReadbackId {
+        let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Readback queue encoder") });
+        let id = self.record_request(wgpu_context, &mut encoder, label, buffer, range);
+        wgpu_context.get_queue().submit(Some(encoder.finish()));
+        self.after_submit();
+        id
+    }
+
+    /// `request` recorded into `encoder`: the result reflects the commands recorded before it. `after_submit`
+    /// must follow the submit of the encoder, `poll` ignores the readback until then.
+    pub fn record_request<T: bytemuck::Pod>(&mut self, wgpu_context: &WgpuContext, encoder: &mut wgpu::CommandEncoder, label: &'static str, buffer: &GpuBuffer<T>, range: Range<usize>) -> ReadbackId {
+        let id = ReadbackId(self.next_id);
+        self.next_id += 1;
+
+        let element_size = size_of::<T>() as u64;
+        let byte_start = range.start as u64 * element_size;
+        let byte_end = (range.end as u64 * element_size).min(buffer.buffer().size()).max(byte_start);
+        let copy_start = byte_start - byte_start % wgpu::COPY_BUFFER_ALIGNMENT;
+        let copy_end = byte_end.next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT).min(buffer.buffer().size());
+        let kept = (byte_start - copy_start) as usize..(byte_end - copy_start) as usize;
+
+        let device = wgpu_context.get_device();
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: (copy_end - copy_start).next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT).max(wgpu::COPY_BUFFER_ALIGNMENT),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        if copy_end > copy_start {
+            encoder.copy_buffer_to_buffer(buffer.buffer(), copy_start, &staging_buffer, 0, copy_end - copy_start);
+            wgpu_context.transfers().record_readback(copy_end - copy_start);
+        }
+        self.pending.push(PendingReadback { id, label, staging_buffer, kept, receiver: None });
+        id
+    }
+
+    /// Maps the staging buffers of the readbacks recorded since the last call, once their encoder was submitted.
+    pub fn after_submit(&mut self) {
+        for readback in self.pending.iter_mut().filter(|readback| readback.receiver.is_none()) {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            readback.staging_buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+            readback.receiver = Some(receiver);
+        }
+    }
+
+    /// Returns the readbacks that finished since the last call, in request order, without blocking.
+    /// Failed readbacks are logged and dropped.
+    pub fn poll(&mut self, wgpu_context: &WgpuContext) -> Vec<CompletedReadback> {
+        if self.pending.is_empty() {
+            return Vec::new();
+        }
+        let _ = wgpu_context.get_device().poll(PollType::Poll);
+
+        let mut completed = Vec::new();
+        self.pending.retain(|readback| {
+            let Some(receiver) = &readback.receiver else {
+                return true;
+            };
+            match receiver.try_recv() {
+                Ok(Ok(())) => {
+                    let bytes = readback.staging_buffer.slice(..).get_mapped_range()[readback.kept.clone()].to_vec();
+                    readback.staging_buffer.unmap();
+                    completed.push(CompletedReadback { id: readback.id, label: readback.label, bytes });
+                    false
+                }
+                Ok(Err(e)) => {
+                    log::error!("Readback {} failed: {:?}", readback.label, e);
+                    false
+                }
+                Err(TryRecvError::Empty) => true,
+                Err(TryRecvError::Disconnected) => false,
+            }
+        });
+        completed
+    }
+}
@@ -23,6 +23,11 @@ impl RenderTimer {
         delta_time
     }
     
+    /// Starts the next delta now, so time spent without drawing (e.g. unfocused) is not reported as one huge frame.
+    pub fn restart_delta(&mut self) {
+        self.last_render_time = Instant::now();
+    }
+
     fn get_average_render_time(&self) -> f64{
         self.total_render_time.as_secs_f64() / self.frame_count as f64 * 1000.0f64
     }
@@ -0,0 +1,31 @@
+//! Random numbers of the simulation: the initial particles, the spawn batches and the random color themes.
+//! They all come from one generator per thread, seeded by the OS unless `seed` is called, so that a run
+//! can be repeated exactly, see `InputLog`.
+use std::cell::RefCell;
+use rand::distr::uniform::{SampleRange, SampleUniform};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_os_rng());
+}
+
+/// Restarts the generator of this thread from `seed`.
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// A random seed from the OS, for runs that should be repeatable once logged.
+pub fn os_seed() -> u64 {
+    rand::rng().random()
+}
+
+/// A generator seeded from the one of this thread, for loops that draw many numbers.
+pub fn rng() -> StdRng {
+    RNG.with(|rng| StdRng::seed_from_u64(rng.borrow_mut().random()))
+}
+
+/// A number of `range`, drawn from the generator of this thread.
+pub fn random_range<T: SampleUniform, R: SampleRange<T>>(range: R) -> T {
+    RNG.with(|rng| rng.borrow_mut().random_range(range))
+}
@@ -0,0 +1,217 @@
+//! Hot reload of the compute kernels in debug builds: `ShaderWatcher` recompiles the kernels whose WGSL file
+//! changed on disk, so a shader can be edited without restarting the app.
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::panic::Location;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant, SystemTime};
+use crate::renderer::wgpu_context::WgpuContext;
+use crate::utils::compute_shader::create_pipeline;
+
+/// A WGSL file that could not be read or compiled. The kernels of the file keep their pipeline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShaderReloadError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for ShaderReloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Reloading {} failed: {}", self.path.display(), self.message)
+    }
+}
+
+impl std::error::Error for ShaderReloadError {}
+
+/// What `ComputeShader::new` needs to compile a kernel again, shared by the kernel and the registry.
+pub struct ReloadableKernel {
+    /// The WGSL file of the kernel, `None` for sources built at runtime, like the ones of `ForceKernel`.
+    path: Option<PathBuf>,
+    entry_point: String,
+    constants: Vec<(String, f64)>,
+    pipeline_layout: wgpu::PipelineLayout,
+    uniform_constants: bool,
+    /// The pipeline of the last successful reload, used instead of the one of `ComputeShader::new`
+    reloaded: Mutex<Option<wgpu::ComputePipeline>>,
+    /// Modification time of the file when the watcher last reloaded it
+    loaded_version: Mutex<Option<SystemTime>>,
+}
+
+impl ReloadableKernel {
+    /// The file is the `label` of the shader, next to the source file that created the kernel at `location`.
+    pub(crate) fn new(
+        location: &Location<'_>,
+        label: Option<&str>,
+        entry_point: &str,
+        constants: &[(&str, f64)],
+        pipeline_layout: wgpu::PipelineLayout,
+        uniform_constants: bool,
+    ) -> Arc<Self> {
+        let path = label
+            .filter(|label| label.ends_with(".wgsl"))
+            .and_then(|label| {
+                let caller = Path::new(env!("CARGO_MANIFEST_DIR")).join(location.file());
+                Some(caller.parent()?.join(label))
+            })
+            .filter(|path| path.is_file());
+        Arc::new(Self {
+            path,
+            entry_point: entry_point.to_string(),
+            constants: constants.iter().map(|(name, value)| (name.to_string(), *value)).collect(),
+            pipeline_layout,
+            uniform_constants,
+            reloaded: Mutex::new(None),
+            loaded_version: Mutex::new(None),
+        })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub(crate) fn reloaded_pipeline(&self) -> Option<wgpu::ComputePipeline> {
+        self.reloaded.lock().unwrap().clone()
+    }
+
+    /// See `ComputeShader::reload`.
+    pub(crate) fn reload(&self, wgpu_context: &WgpuContext, source: &str) -> Result<(), ShaderReloadError> {
+        let device = wgpu_context.get_device();
+        let constants: Vec<(&str, f64)> = self.constants.iter().map(|(name, value)| (name.as_str(), *value)).collect();
+        let label = self.path.as_ref().map(|path| path.display().to_string());
+        // Errors in the new source must not reach the uncaptured error handler, which panics
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let pipeline = create_pipeline(
+            wgpu_context,
+            wgpu::ShaderModuleDescriptor {
+                label: label.as_deref(),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            },
+            &self.entry_point,
+            &self.pipeline_layout,
+            &constants,
+            self.uniform_constants,
+        );
+        if let Some(error) = pop_error_scope(device) {
+            return Err(ShaderReloadError {
+                path: self.path.clone().unwrap_or_default(),
+                message: error.to_string(),
+            });
+        }
+        *self.reloaded.lock().unwrap() = Some(pipeline);
+        Ok(())
+    }
+}
+
+/// On native, wgpu resolves the error scope as soon as it is popped, so the future is ready on its first poll.
+fn pop_error_scope(device: &wgpu::Device) -> Option<wgpu::Error> {
+    let mut future = std::pin::pin!(device.pop_error_scope());
+    match future.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(error) => error,
+        Poll::Pending => None,
+    }
+}
+
+/// The live kernels of a `WgpuContext`, see `WgpuContext::shaders`. Kernels leave it when dropped.
+#[derive(Default)]
+pub struct ShaderRegistry {
+    kernels: Mutex<Vec<Weak<ReloadableKernel>>>,
+}
+
+impl ShaderRegistry {
+    pub(crate) fn register(&self, kernel: &Arc<ReloadableKernel>) {
+        if kernel.path.is_some() {
+            self.kernels.lock().unwrap().push(Arc::downgrade(kernel));
+        }
+    }
+
+    /// The live kernels that have a WGSL file.
+    pub fn kernels(&self) -> Vec<Arc<ReloadableKernel>> {
+        let mut kernels = self.kernels.lock().unwrap();
+        kernels.retain(|kernel| kernel.strong_count() > 0);
+        kernels.iter().filter_map(Weak::upgrade).collect()
+    }
+}
+
+/// A WGSL file whose kernels were recompiled by `ShaderWatcher::poll`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShaderReload {
+    pub path: PathBuf,
+    /// Kernels of the file
+    pub kernels: usize,
+    pub result: Result<(), ShaderReloadError>,
+}
+
+/// Watches the WGSL files of the live kernels of a `WgpuContext` and recompiles the kernels of the files that
+/// changed, see `ComputeShader::reload`. `poll` is meant to run between frames, so every frame records its passes
+/// with the same pipelines. Kernels created after a file changed start from the source embedded in the binary and
+/// are reloaded by the next poll too. The files are looked up next to the sources of the crate, so the binary
+/// must run on the machine it was built on.
+pub struct ShaderWatcher {
+    poll_interval: Duration,
+    last_poll: Option<Instant>,
+    /// Modification time of every file when the watcher first saw it, the one the binary was built with
+    built_versions: HashMap<PathBuf, SystemTime>,
+}
+
+impl ShaderWatcher {
+    /// Checks the files at most once every `poll_interval`.
+    pub fn new(poll_interval: Duration) -> Self {
+        Self {
+            poll_interval,
+            last_poll: None,
+            built_versions: HashMap::new(),
+        }
+    }
+
+    /// Recompiles the kernels whose file changed since the watcher first saw it, or since their last reload.
+    /// Returns the files that were reloaded. A file that does not compile is reported once per change, its
+    /// kernels keep their pipeline.
+    pub fn poll(&mut self, wgpu_context: &WgpuContext) -> Vec<ShaderReload> {
+        if self.last_poll.is_some_and(|last_poll| last_poll.elapsed() < self.poll_interval) {
+            return Vec::new();
+        }
+        self.last_poll = Some(Instant::now());
+
+        let mut changed: HashMap<PathBuf, (SystemTime, Vec<Arc<ReloadableKernel>>)> = HashMap::new();
+        let mut modified_times: HashMap<PathBuf, Option<SystemTime>> = HashMap::new();
+        for kernel in wgpu_context.shaders().kernels() {
+            let Some(path) = kernel.path() else {
+                continue;
+            };
+            let modified = *modified_times.entry(path.to_path_buf())
+                .or_insert_with(|| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok());
+            let Some(modified) = modified else {
+                continue;
+            };
+            let built_version = *self.built_versions.entry(path.to_path_buf()).or_insert(modified);
+            let loaded_version = *kernel.loaded_version.lock().unwrap();
+            if modified != built_version && loaded_version != Some(modified) {
+                changed.entry(path.to_path_buf()).or_insert_with(|| (modified, Vec::new())).1.push(kernel);
+            }
+        }
+
+        let mut reloads = Vec::new();
+        for (path, (modified, kernels)) in changed {
+            // Every kernel is compiled, an entry point missing from the new source only fails its own kernels
+            let result = std::fs::read_to_string(&path)
+                .map_err(|error| ShaderReloadError { path: path.clone(), message: error.to_string() })
+                .and_then(|source| {
+                    let results: Vec<_> = kernels.iter().map(|kernel| kernel.reload(wgpu_context, &source)).collect();
+                    results.into_iter().try_fold((), |(), result| result)
+                });
+            // A failed reload is not retried until the file changes again
+            for kernel in &kernels {
+                *kernel.loaded_version.lock().unwrap() = Some(modified);
+            }
+            match &result {
+                Ok(()) => log::info!("Reloaded {} ({} kernels)", path.display(), kernels.len()),
+                Err(error) => log::error!("{}", error),
+            }
+            reloads.push(ShaderReload { path, kernels: kernels.len(), result });
+        }
+        reloads
+    }
+}
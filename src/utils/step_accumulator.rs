@@ -0,0 +1,35 @@
+use crate::simulation_config::FixedTimestep;
+
+/// Accumulates the frame times and turns them into fixed steps, see `FixedTimestep`.
+/// The time is kept in f64 so long runs do not drift.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct StepAccumulator {
+    accumulated: f64,
+}
+
+impl StepAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Time carried over to the next frame, less than one step.
+    pub fn accumulated(&self) -> f64 {
+        self.accumulated
+    }
+
+    pub fn reset(&mut self) {
+        self.accumulated = 0.0;
+    }
+
+    /// Adds the time of a frame and returns the number of steps it completes, at most `max_steps_per_frame`.
+    pub fn take_steps(&mut self, frame_time: f32, timestep: &FixedTimestep) -> u32 {
+        let step = timestep.step as f64;
+        if step <= 0.0 {
+            return 0;
+        }
+        self.accumulated += frame_time.max(0.0) as f64;
+        let steps = (self.accumulated / step).floor();
+        self.accumulated -= steps * step;
+        (steps as u32).min(timestep.max_steps_per_frame)
+    }
+}
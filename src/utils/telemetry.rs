@@ -0,0 +1,521 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+use wgpu::wgt::PollType;
+use wgpu::wgt::PollType::WaitForSubmissionIndex;
+use crate::particles::region_energy::RegionEnergyMap;
+use crate::renderer::wgpu_context::WgpuContext;
+
+/// Number of spawn batches kept in the history.
+const MAX_SPAWN_BATCHES: usize = 64;
+
+/// Number of frames of memory transfers kept in the history.
+const MAX_TRANSFER_FRAMES: usize = 120;
+
+/// Number of kinetic energy samples kept in the history.
+const MAX_ENERGY_SAMPLES: usize = 120;
+
+/// Frames moving more bytes than this are logged, see `Telemetry::record_frame_transfers`.
+pub const LARGE_TRANSFER_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Time spent by one refresh operation.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RefreshTiming {
+    /// Time spent on the CPU recording and submitting the work.
+    pub cpu: Duration,
+    /// Wall-clock time the CPU then waited for the queue to go idle, only measured with
+    /// `WgpuContext::set_measure_queue_drain`. It is not a GPU timestamp: it also covers the work submitted
+    /// before the refresh and the submission overhead.
+    pub queue_drain: Option<Duration>,
+    /// GPU time between two timestamps written around the commands of the refresh, e.g. the copies of the
+    /// grown buffers. Measured with the queue drain, on devices with `TIMESTAMP_QUERY_INSIDE_ENCODERS`.
+    pub gpu: Option<Duration>,
+}
+
+impl std::ops::Add for RefreshTiming {
+    type Output = RefreshTiming;
+
+    fn add(self, other: RefreshTiming) -> RefreshTiming {
+        let add = |duration: Option<Duration>, other_duration: Option<Duration>| match (duration, other_duration) {
+            (Some(duration), Some(other_duration)) => Some(duration + other_duration),
+            (duration, other_duration) => duration.or(other_duration),
+        };
+        RefreshTiming {
+            cpu: self.cpu + other.cpu,
+            queue_drain: add(self.queue_drain, other.queue_drain),
+            gpu: add(self.gpu, other.gpu),
+        }
+    }
+}
+
+impl std::fmt::Display for RefreshTiming {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cpu {:.3} ms", self.cpu.as_secs_f64() * 1000.0)?;
+        if let Some(queue_drain) = self.queue_drain {
+            write!(f, ", queue drain {:.3} ms", queue_drain.as_secs_f64() * 1000.0)?;
+        }
+        if let Some(gpu) = self.gpu {
+            write!(f, ", gpu {:.3} ms", gpu.as_secs_f64() * 1000.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// Measures a refresh operation: start it before the operation and finish it right after.
+pub struct RefreshTimer {
+    start: Instant,
+    timestamps: Option<wgpu::QuerySet>,
+}
+
+impl RefreshTimer {
+    /// With `WgpuContext::measures_queue_drain`, also submits a timestamp before the commands of the refresh.
+    pub fn start(wgpu_context: &WgpuContext) -> Self {
+        let required_features = wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS;
+        let device = wgpu_context.get_device();
+        let timestamps = (wgpu_context.measures_queue_drain() && device.features().contains(required_features)).then(|| {
+            let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Refresh timer query set"),
+                ty: wgpu::QueryType::Timestamp,
+                count: 2,
+            });
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Refresh timer encoder") });
+            encoder.write_timestamp(&query_set, 0);
+            wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+            query_set
+        });
+        Self { start: Instant::now(), timestamps }
+    }
+
+    /// Stops the CPU clock. If `WgpuContext::measures_queue_drain`, also blocks until the queue is idle and
+    /// returns the wait; the submit flushes the pending `write_buffer` calls. The end timestamp is submitted
+    /// with it, so the GPU time covers every command submitted since `start`.
+    pub fn finish(self, wgpu_context: &WgpuContext) -> RefreshTiming {
+        let cpu = self.start.elapsed();
+        if !wgpu_context.measures_queue_drain() {
+            return RefreshTiming { cpu, queue_drain: None, gpu: None };
+        }
+
+        let device = wgpu_context.get_device();
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Refresh timer encoder") });
+        let staging_buffer = self.timestamps.as_ref().map(|query_set| {
+            let size = 2 * size_of::<u64>() as u64;
+            let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Refresh timer resolve buffer"),
+                size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Refresh timer staging buffer"),
+                size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            encoder.write_timestamp(query_set, 1);
+            encoder.resolve_query_set(query_set, 0..2, &resolve_buffer, 0);
+            encoder.copy_buffer_to_buffer(&resolve_buffer, 0, &staging_buffer, 0, size);
+            staging_buffer
+        });
+
+        let drain_start = Instant::now();
+        let idx = wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+        device.poll(WaitForSubmissionIndex(idx)).unwrap();
+        let queue_drain = Some(drain_start.elapsed());
+
+        let gpu = staging_buffer.and_then(|staging_buffer| {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            staging_buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+            device.poll(PollType::Wait).unwrap();
+            if let Err(e) = receiver.recv().unwrap() {
+                log::error!("Refresh timer readback failed: {:?}", e);
+                return None;
+            }
+            let timestamps: [u64; 2] = bytemuck::pod_read_unaligned(&staging_buffer.slice(..).get_mapped_range());
+            let ticks = timestamps[1].saturating_sub(timestamps[0]);
+            let nanoseconds = ticks as f64 * wgpu_context.get_queue().get_timestamp_period() as f64;
+            Some(Duration::from_nanos(nanoseconds as u64))
+        });
+        RefreshTiming { cpu, queue_drain, gpu }
+    }
+}
+
+/// Statistics of one spawn batch: every refresh that had to run because particles were added.
+#[derive(Clone, Debug)]
+pub struct SpawnBatchStats {
+    pub label: String,
+    pub particles_added: usize,
+    pub total_particles: usize,
+    pub refreshes: Vec<(&'static str, RefreshTiming)>,
+}
+
+impl SpawnBatchStats {
+    pub fn total(&self) -> RefreshTiming {
+        self.refreshes.iter().fold(RefreshTiming::default(), |total, (_, timing)| total + *timing)
+    }
+}
+
+/// Bytes moved between the CPU and the GPU, or inside the GPU, over some period.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransferStats {
+    /// Written from the CPU with `Queue::write_buffer`.
+    pub uploaded_bytes: u64,
+    /// Copied between two GPU buffers, e.g. when a `GpuBuffer` grows or the particles are compacted.
+    pub copied_bytes: u64,
+    /// Copied into staging buffers to be read on the CPU.
+    pub readback_bytes: u64,
+}
+
+impl TransferStats {
+    pub fn total_bytes(&self) -> u64 {
+        self.uploaded_bytes + self.copied_bytes + self.readback_bytes
+    }
+}
+
+impl std::ops::Add for TransferStats {
+    type Output = TransferStats;
+
+    fn add(self, other: TransferStats) -> TransferStats {
+        TransferStats {
+            uploaded_bytes: self.uploaded_bytes + other.uploaded_bytes,
+            copied_bytes: self.copied_bytes + other.copied_bytes,
+            readback_bytes: self.readback_bytes + other.readback_bytes,
+        }
+    }
+}
+
+/// Kinetic energy of the particles at one point in time, from a `RegionEnergyQuery`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EnergySample {
+    /// Particles inside the regions.
+    pub particles: u32,
+    /// Average energy per unit of mass of those particles.
+    pub mean_energy: f32,
+    /// Index and mean energy of the region with the highest mean energy, `None` if every region is empty.
+    pub hottest_region: Option<(usize, f32)>,
+}
+
+impl EnergySample {
+    pub fn from_map(map: &RegionEnergyMap) -> Self {
+        Self {
+            particles: map.total_particles(),
+            mean_energy: map.mean_energy(),
+            hottest_region: map.hottest_region().map(|(index, region)| (index, region.mean_energy)),
+        }
+    }
+}
+
+/// Counts the bytes of the transfers recorded through a `WgpuContext`, see `WgpuContext::transfers`.
+/// Every `GpuBuffer` write, copy and readback is counted; code that calls the queue directly records its transfers itself.
+/// The copies of the particle sort, which run every few steps inside the physics encoder, are not counted.
+#[derive(Debug, Default)]
+pub struct TransferCounter {
+    uploaded_bytes: AtomicU64,
+    copied_bytes: AtomicU64,
+    readback_bytes: AtomicU64,
+}
+
+impl TransferCounter {
+    pub fn record_upload(&self, bytes: u64) {
+        self.uploaded_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn record_copy(&self, bytes: u64) {
+        self.copied_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn record_readback(&self, bytes: u64) {
+        self.readback_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Transfers counted since the last `take`.
+    pub fn current(&self) -> TransferStats {
+        TransferStats {
+            uploaded_bytes: self.uploaded_bytes.load(Ordering::Relaxed),
+            copied_bytes: self.copied_bytes.load(Ordering::Relaxed),
+            readback_bytes: self.readback_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns the transfers counted since the last call and restarts the count, once per frame.
+    pub fn take(&self) -> TransferStats {
+        TransferStats {
+            uploaded_bytes: self.uploaded_bytes.swap(0, Ordering::Relaxed),
+            copied_bytes: self.copied_bytes.swap(0, Ordering::Relaxed),
+            readback_bytes: self.readback_bytes.swap(0, Ordering::Relaxed),
+        }
+    }
+}
+
+/// Collects the statistics of the interactions that cause frame spikes.
+pub struct Telemetry {
+    spawn_batches: VecDeque<SpawnBatchStats>,
+    num_spawn_batches: u64,
+    pending_frame_label: Option<String>,
+    frame_transfers: VecDeque<TransferStats>,
+    energy_samples: VecDeque<EnergySample>,
+}
+
+impl Telemetry {
+    pub fn new() -> Self {
+        Self {
+            spawn_batches: VecDeque::with_capacity(MAX_SPAWN_BATCHES),
+            num_spawn_batches: 0,
+            pending_frame_label: None,
+            frame_transfers: VecDeque::with_capacity(MAX_TRANSFER_FRAMES),
+            energy_samples: VecDeque::with_capacity(MAX_ENERGY_SAMPLES),
+        }
+    }
+
+    /// Stores the transfers of a frame, taken from `TransferCounter::take`. Frames moving more than
+    /// `LARGE_TRANSFER_BYTES` are logged, to spot the interactions that upload or read back whole buffers.
+    pub fn record_frame_transfers(&mut self, transfers: TransferStats) {
+        if transfers.total_bytes() > LARGE_TRANSFER_BYTES {
+            log::info!(
+                "Large frame transfer: {:.2} MiB uploaded, {:.2} MiB copied, {:.2} MiB read back",
+                transfers.uploaded_bytes as f64 / (1024.0 * 1024.0),
+                transfers.copied_bytes as f64 / (1024.0 * 1024.0),
+                transfers.readback_bytes as f64 / (1024.0 * 1024.0),
+            );
+        }
+        if self.frame_transfers.len() == MAX_TRANSFER_FRAMES {
+            self.frame_transfers.pop_front();
+        }
+        self.frame_transfers.push_back(transfers);
+    }
+
+    /// Transfers of the most recent frames, oldest first.
+    pub fn frame_transfers(&self) -> &VecDeque<TransferStats> {
+        &self.frame_transfers
+    }
+
+    pub fn last_frame_transfers(&self) -> Option<TransferStats> {
+        self.frame_transfers.back().copied()
+    }
+
+    /// Stores a kinetic energy sample, dropping the oldest one past the history.
+    pub fn record_energy_sample(&mut self, sample: EnergySample) {
+        if self.energy_samples.len() == MAX_ENERGY_SAMPLES {
+            self.energy_samples.pop_front();
+        }
+        self.energy_samples.push_back(sample);
+    }
+
+    /// Most recent kinetic energy samples, oldest first.
+    pub fn energy_samples(&self) -> &VecDeque<EnergySample> {
+        &self.energy_samples
+    }
+
+    pub fn last_energy_sample(&self) -> Option<EnergySample> {
+        self.energy_samples.back().copied()
+    }
+
+    /// One line about the spawn batches, the transfers and the kinetic energy, logged when the app exits.
+    pub fn summary(&self) -> String {
+        let spawn_total = self.spawn_batches.iter().fold(RefreshTiming::default(), |total, batch| total + batch.total());
+        let peak = self.peak_frame_transfers();
+        let peak_energy = self.energy_samples.iter().map(|sample| sample.mean_energy).fold(0.0f32, f32::max);
+        format!(
+            "Telemetry: {} spawn batches (last {}: refresh {}), peak frame transfers {} B uploaded, {} B copied, {} B read back over the last {} frames, peak mean kinetic energy {:.3} over the last {} samples",
+            self.num_spawn_batches,
+            self.spawn_batches.len(),
+            spawn_total,
+            peak.uploaded_bytes,
+            peak.copied_bytes,
+            peak.readback_bytes,
+            self.frame_transfers.len(),
+            peak_energy,
+            self.energy_samples.len(),
+        )
+    }
+
+    /// Largest transfer of each kind over the recorded frames.
+    pub fn peak_frame_transfers(&self) -> TransferStats {
+        self.frame_transfers.iter().fold(TransferStats::default(), |peak, frame| TransferStats {
+            uploaded_bytes: peak.uploaded_bytes.max(frame.uploaded_bytes),
+            copied_bytes: peak.copied_bytes.max(frame.copied_bytes),
+            readback_bytes: peak.readback_bytes.max(frame.readback_bytes),
+        })
+    }
+
+    /// Label used when the caller does not provide one.
+    pub fn next_spawn_label(&self) -> String {
+        format!("Spawn batch {}", self.num_spawn_batches + 1)
+    }
+
+    /// Stores the batch and labels the next physics frame with it.
+    pub fn record_spawn_batch(&mut self, stats: SpawnBatchStats) {
+        let total = stats.total();
+        log::info!(
+            "{}: +{} particles ({} total), refresh {}",
+            stats.label,
+            stats.particles_added,
+            stats.total_particles,
+            total,
+        );
+        for (name, timing) in &stats.refreshes {
+            log::debug!("    {}: {}", name, timing);
+        }
+
+        if self.spawn_batches.len() == MAX_SPAWN_BATCHES {
+            self.spawn_batches.pop_front();
+        }
+        self.pending_frame_label = Some(stats.label.clone());
+        self.spawn_batches.push_back(stats);
+        self.num_spawn_batches += 1;
+    }
+
+    /// Returns the label the next frame should carry in GPU captures, if any.
+    pub fn take_frame_label(&mut self) -> Option<String> {
+        self.pending_frame_label.take()
+    }
+
+    /// Most recent spawn batches, oldest first.
+    pub fn spawn_batches(&self) -> &VecDeque<SpawnBatchStats> {
+        &self.spawn_batches
+    }
+
+    pub fn last_spawn_batch(&self) -> Option<&SpawnBatchStats> {
+        self.spawn_batches.back()
+    }
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Measures the GPU time of a whole physics step with two timestamps, one written before the
+/// first command of the step and one after the last submission. Read back asynchronously; while
+/// a measurement is in flight, steps are not timed.
+/// Needs `TIMESTAMP_QUERY` and `TIMESTAMP_QUERY_INSIDE_ENCODERS`.
+pub struct GpuStepTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    staging_buffer: wgpu::Buffer,
+    timing: bool,
+    copy_recorded: bool,
+    pending: Option<Receiver<Result<(), wgpu::BufferAsyncError>>>,
+}
+
+impl GpuStepTimer {
+    const TIMESTAMPS_SIZE: u64 = 2 * size_of::<u64>() as u64;
+
+    /// Returns `None` if the device cannot write timestamps inside encoders.
+    pub fn new(wgpu_context: &WgpuContext) -> Option<Self> {
+        let required_features = wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS;
+        let device = wgpu_context.get_device();
+        if !device.features().contains(required_features) {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Step timer query set"),
+            ty: wgpu::QueryType::Timestamp,
+            count: 2,
+        });
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Step timer resolve buffer"),
+            size: Self::TIMESTAMPS_SIZE,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Step timer staging buffer"),
+            size: Self::TIMESTAMPS_SIZE,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            staging_buffer,
+            timing: false,
+            copy_recorded: false,
+            pending: None,
+        })
+    }
+
+    /// Writes the start timestamp into `encoder`, unless the previous measurement is still in flight. Of the
+    /// steps recorded into one encoder, only the first is timed.
+    pub fn start(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if self.pending.is_some() || self.copy_recorded {
+            return;
+        }
+        encoder.write_timestamp(&self.query_set, 0);
+        self.timing = true;
+    }
+
+    /// Writes the end timestamp after everything submitted so far and starts the readback.
+    pub fn finish(&mut self, wgpu_context: &WgpuContext) {
+        if !self.timing {
+            return;
+        }
+        let mut encoder = wgpu_context.get_device().create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Step timer encoder") }
+        );
+        self.record_finish(&mut encoder);
+        wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+        self.after_submit();
+    }
+
+    /// Writes the end timestamp into `encoder`, after everything recorded in it so far, e.g. at the end of
+    /// a `FrameGraph`. Returns false when the step is not timed. `after_submit` must follow the submit.
+    pub fn record_finish(&mut self, encoder: &mut wgpu::CommandEncoder) -> bool {
+        if !self.timing {
+            return false;
+        }
+        self.timing = false;
+
+        encoder.write_timestamp(&self.query_set, 1);
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.staging_buffer, 0, Self::TIMESTAMPS_SIZE);
+        self.copy_recorded = true;
+        true
+    }
+
+    /// Starts the readback of the timestamps written by `record_finish`, once its encoder is submitted.
+    pub fn after_submit(&mut self) {
+        if !self.copy_recorded {
+            return;
+        }
+        self.copy_recorded = false;
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.staging_buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.pending = Some(receiver);
+    }
+
+    /// Returns the GPU time of the last measured step once it is available, without blocking.
+    pub fn poll(&mut self, wgpu_context: &WgpuContext) -> Option<Duration> {
+        let receiver = self.pending.take()?;
+        let _ = wgpu_context.get_device().poll(PollType::Poll);
+        match receiver.try_recv() {
+            Ok(Ok(())) => {
+                let timestamps: [u64; 2] = {
+                    let mapped_range = self.staging_buffer.slice(..).get_mapped_range();
+                    bytemuck::pod_read_unaligned(&mapped_range)
+                };
+                self.staging_buffer.unmap();
+                let ticks = timestamps[1].saturating_sub(timestamps[0]);
+                let nanoseconds = ticks as f64 * wgpu_context.get_queue().get_timestamp_period() as f64;
+                Some(Duration::from_nanos(nanoseconds as u64))
+            }
+            Ok(Err(e)) => {
+                log::error!("Step timer readback failed: {:?}", e);
+                None
+            }
+            Err(TryRecvError::Empty) => {
+                self.pending = Some(receiver);
+                None
+            }
+            Err(TryRecvError::Disconnected) => None,
+        }
+    }
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::physics::collision_system::SolverConfig;
+use game_engine::physics::contact_stats::{AdaptiveIterations, ContactStats};
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::Simulation;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+use wgpu::wgt::PollType::Wait;
+
+const ADAPTIVE: AdaptiveIterations = AdaptiveIterations {
+    min_iterations: 1,
+    max_iterations: 6,
+    low_overlap: 0.01,
+    high_overlap: 0.1,
+};
+
+fn stats(contacts: u32, mean_overlap: f32, max_overlap: f32) -> ContactStats {
+    ContactStats { contacts, mean_overlap, max_overlap }
+}
+
+#[test]
+fn iterations_follow_the_overlap_test() {
+    // Deep interpenetration doubles, up to the max
+    assert_eq!(ADAPTIVE.next_iterations(1, &stats(10, 0.2, 0.5)), 2);
+    assert_eq!(ADAPTIVE.next_iterations(4, &stats(10, 0.2, 0.5)), 6);
+    // Resting contacts and no contacts step down, down to the min
+    assert_eq!(ADAPTIVE.next_iterations(4, &stats(10, 0.005, 0.05)), 3);
+    assert_eq!(ADAPTIVE.next_iterations(1, &stats(0, 0.0, 0.0)), 1);
+    // In between, unchanged
+    assert_eq!(ADAPTIVE.next_iterations(3, &stats(10, 0.05, 0.08)), 3);
+    // Out of bounds iterations are brought back in
+    assert_eq!(ADAPTIVE.next_iterations(20, &stats(10, 0.05, 0.08)), 6);
+}
+
+fn create_simulation(wgpu_context: &WgpuContext, positions: Vec<Vec2>) -> Simulation {
+    let mut simulation = common::create_test_simulation(wgpu_context, positions, 2.0);
+    simulation.collision_system_mut().set_solver_config(SolverConfig { adaptive_iterations: Some(ADAPTIVE), ..SolverConfig::default() });
+    simulation
+}
+
+/// Steps and waits for the GPU, so the next step reads the contact statistics of this one.
+fn step(wgpu_context: &WgpuContext, simulation: &mut Simulation, gpu_profiler: &mut GpuProfiler) {
+    simulation.step(wgpu_context, gpu_profiler, 0.01, None);
+    gpu_profiler.end_frame().unwrap();
+    wgpu_context.get_device().poll(Wait).unwrap();
+}
+
+#[test]
+fn spawn_burst_raises_the_iterations_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    // Radius 2, one unit apart: 75% overlap. Around the center of a 4.4 units wide cell, the only one they share,
+    // so the contact is recorded once
+    let mut simulation = create_simulation(wgpu_context, vec![Vec2::new(98.5, 99.0), Vec2::new(99.5, 99.0)]);
+    assert_eq!(simulation.collision_system().last_contact_stats(), None);
+
+    step(wgpu_context, &mut simulation, &mut gpu_profiler);
+    step(wgpu_context, &mut simulation, &mut gpu_profiler);
+
+    let contact_stats = simulation.collision_system().last_contact_stats().unwrap();
+    assert!(contact_stats.contacts > 0);
+    assert!((contact_stats.max_overlap - 0.75).abs() < 1e-3, "{contact_stats:?}");
+    assert!((contact_stats.mean_overlap - 0.75).abs() < 1e-2, "{contact_stats:?}");
+    assert_eq!(simulation.collision_system().solver_config().iterations, 2);
+
+    for _ in 0..10 {
+        step(wgpu_context, &mut simulation, &mut gpu_profiler);
+        let iterations = simulation.collision_system().solver_config().iterations;
+        assert!((ADAPTIVE.min_iterations..=ADAPTIVE.max_iterations).contains(&iterations));
+    }
+}
+
+#[test]
+fn separated_particles_stay_at_the_min_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    let mut simulation = create_simulation(wgpu_context, vec![Vec2::new(100.0, 100.0), Vec2::new(200.0, 100.0)]);
+
+    for _ in 0..3 {
+        step(wgpu_context, &mut simulation, &mut gpu_profiler);
+    }
+    assert_eq!(simulation.collision_system().last_contact_stats().unwrap().contacts, 0);
+    assert_eq!(simulation.collision_system().solver_config().iterations, ADAPTIVE.min_iterations);
+}
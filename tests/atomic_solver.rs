@@ -0,0 +1,50 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::grid::grid::Grid;
+use game_engine::physics::collision_system::{CollisionSystem, SolverBatching, SolverConfig};
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::{Simulation, DIMENSION};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+fn positions_after_step(wgpu_context: &WgpuContext, positions: Vec<Vec2>, config: SolverConfig) -> Vec<Vec2> {
+    let radii = vec![2.0; positions.len()];
+    let particles = common::create_test_particle_system(wgpu_context, positions, radii);
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let collision_system = CollisionSystem::new_with_config(wgpu_context, DIMENSION, &particles, &grid, config);
+    let mut simulation = Simulation::from_parts(wgpu_context, particles, grid, collision_system);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    simulation.step(wgpu_context, &mut gpu_profiler, 0.01, None);
+    gpu_profiler.end_frame().unwrap();
+    simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().to_vec()
+}
+
+#[test]
+fn atomics_solve_a_pair_like_the_colors_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    // Both particles straddle a cell boundary, the contact is in two collision cells but solved once
+    let start = vec![Vec2::new(100.0, 100.0), Vec2::new(101.0, 100.0)];
+    let colors = SolverConfig { stiffness: 1.0, ..SolverConfig::default() };
+    let atomics = SolverConfig { batching: SolverBatching::Atomics, ..colors };
+
+    let expected = positions_after_step(wgpu_context, start.clone(), colors);
+    let actual = positions_after_step(wgpu_context, start, atomics);
+    for (actual, expected) in actual.iter().zip(&expected) {
+        assert!((*actual - *expected).length() < 1e-3, "expected {:?}, got {:?}", expected, actual);
+    }
+}
+
+#[test]
+fn atomics_separate_a_pile_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let start: Vec<Vec2> = (0..64).map(|i| Vec2::new(200.0 + (i % 8) as f32 * 2.0, 200.0 + (i / 8) as f32 * 2.0)).collect();
+    let config = SolverConfig { iterations: 4, batching: SolverBatching::Atomics, ..SolverConfig::default() };
+
+    let positions = positions_after_step(wgpu_context, start.clone(), config);
+    assert!(positions.iter().all(|position| position.is_finite()));
+    let spread = |positions: &[Vec2]| positions.iter().map(|position| position.distance(Vec2::new(207.0, 207.0))).sum::<f32>();
+    assert!(spread(&positions) > spread(&start), "the pile should expand");
+}
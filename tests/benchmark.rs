@@ -0,0 +1,65 @@
+use std::time::Duration;
+use game_engine::utils::benchmark::{BenchmarkHarness, BenchmarkPhase};
+
+fn ms(value: f64) -> Duration {
+    Duration::from_secs_f64(value / 1000.0)
+}
+
+#[test]
+fn warm_up_frames_are_discarded_test() {
+    let mut harness = BenchmarkHarness::with_settings(10, 5, 0.05);
+
+    // Start-up spikes
+    for _ in 0..10 {
+        assert!(!harness.record_frame(ms(100.0)));
+    }
+    assert_eq!(harness.phase(), BenchmarkPhase::WaitingForSteadyState);
+
+    for _ in 0..5 {
+        assert!(!harness.record_frame(ms(2.0)));
+    }
+    assert!(harness.is_collecting());
+
+    for _ in 0..20 {
+        assert!(harness.record_frame(ms(2.0)));
+    }
+
+    let report = harness.report().unwrap();
+    assert_eq!(report.discarded_frames, 15);
+    assert_eq!(report.collected_frames, 20);
+    assert!((report.mean_ms - 2.0).abs() < 1e-6);
+    assert!((report.max_ms - 2.0).abs() < 1e-6);
+}
+
+#[test]
+fn unstable_frame_times_never_reach_steady_state_test() {
+    let mut harness = BenchmarkHarness::with_settings(0, 8, 0.05);
+
+    for i in 0..100 {
+        let frame_time = if i % 2 == 0 { 1.0 } else { 4.0 };
+        assert!(!harness.record_frame(ms(frame_time)));
+    }
+    assert_eq!(harness.phase(), BenchmarkPhase::WaitingForSteadyState);
+    assert!(harness.report().is_none());
+}
+
+#[test]
+fn report_percentiles_test() {
+    let mut harness = BenchmarkHarness::with_settings(0, 2, 0.05);
+    harness.record_frame(ms(1.0));
+    harness.record_frame(ms(1.0));
+    assert!(harness.is_collecting());
+
+    for i in 1..=100 {
+        harness.record_frame(ms(i as f64));
+    }
+
+    let report = harness.report().unwrap();
+    assert_eq!(report.collected_frames, 100);
+    assert!((report.min_ms - 1.0).abs() < 1e-6);
+    assert!((report.median_ms - 50.0).abs() < 1e-6);
+    assert!((report.p95_ms - 95.0).abs() < 1e-6);
+    assert!((report.p99_ms - 99.0).abs() < 1e-6);
+    assert!((report.max_ms - 100.0).abs() < 1e-6);
+    assert!((report.mean_ms - 50.5).abs() < 1e-6);
+}
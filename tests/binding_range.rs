@@ -0,0 +1,65 @@
+mod common;
+
+use game_engine::utils::gpu_buffer::{BindingRangeError, GpuBuffer};
+
+#[test]
+fn binding_range_validation_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let buffer = GpuBuffer::new(wgpu_context, vec![0u32; 1024], wgpu::BufferUsages::STORAGE);
+    let alignment = buffer.offset_alignment();
+    assert!(alignment >= 4 && alignment.is_power_of_two());
+    let aligned_start = (alignment / 4) as usize;
+
+    let binding = buffer.binding_range(aligned_start, 10).unwrap();
+    assert_eq!(binding.offset, alignment);
+    assert_eq!(binding.size.unwrap().get(), 40);
+    // Up to the capacity
+    assert!(buffer.binding_range(0, buffer.capacity()).is_ok());
+
+    assert_eq!(buffer.binding_range(0, 0).unwrap_err(), BindingRangeError::Empty);
+    assert_eq!(
+        buffer.binding_range(1000, 100).unwrap_err(),
+        BindingRangeError::OutOfBounds { start: 1000, len: 100, capacity: 1024 }
+    );
+    assert_eq!(
+        buffer.binding_range(aligned_start + 1, 10).unwrap_err(),
+        BindingRangeError::MisalignedOffset { offset: alignment + 4, alignment }
+    );
+
+    let bytes = GpuBuffer::new(wgpu_context, vec![0u8; 1024], wgpu::BufferUsages::STORAGE);
+    assert_eq!(bytes.binding_range(0, 6).unwrap_err(), BindingRangeError::MisalignedSize { size: 6 });
+}
+
+#[test]
+fn sub_range_can_be_bound_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let device = wgpu_context.get_device();
+    let buffer = GpuBuffer::new(wgpu_context, vec![0u32; 1024], wgpu::BufferUsages::STORAGE);
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: None,
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+    });
+
+    // Validation errors of the bind group fail the test through the device error handler
+    let start = (buffer.offset_alignment() / 4) as usize;
+    let _bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::Buffer(buffer.binding_range(start, 100).unwrap()),
+        }],
+    });
+    device.poll(wgpu::wgt::PollType::Wait).unwrap();
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use wgpu::wgt::PollType::WaitForSubmissionIndex;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::utils::bind_resources::{Bindings, BindingsBuilder};
+use game_engine::utils::compute_shader::ComputeShader;
+use game_engine::utils::gpu_buffer::GpuBuffer;
+
+/// Writes `input[i] * params.x` to `output[i]` for every element of the output.
+const SOURCE: &str = "@group(0) @binding(0) var<storage, read> input: array<u32>;
+@group(0) @binding(1) var<storage, read_write> output: array<u32>;
+@group(0) @binding(2) var<uniform> params: vec4<u32>;
+
+@compute @workgroup_size(64)
+fn scale(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x < arrayLength(&output) && id.x < arrayLength(&input)) {
+        output[id.x] = input[id.x] * params.x;
+    }
+}
+";
+
+struct ScaleKernel {
+    shader: ComputeShader,
+    bindings: Bindings,
+}
+
+impl ScaleKernel {
+    fn new(wgpu_context: &WgpuContext, input: &GpuBuffer<u32>, output: &GpuBuffer<u32>, params: &GpuBuffer<[u32; 4]>) -> Self {
+        let bindings = Self::bindings_builder(input, output, params).build(wgpu_context);
+        let shader = ComputeShader::new(
+            wgpu_context,
+            wgpu::ShaderModuleDescriptor { label: Some("scale"), source: wgpu::ShaderSource::Wgsl(SOURCE.into()) },
+            "scale",
+            bindings.layout(),
+            (64, 1, 1),
+            &vec![],
+            &vec![],
+        );
+        Self { shader, bindings }
+    }
+
+    fn bindings_builder(input: &GpuBuffer<u32>, output: &GpuBuffer<u32>, params: &GpuBuffer<[u32; 4]>) -> BindingsBuilder {
+        BindingsBuilder::new("Scale test")
+            .storage_ro(input)
+            .storage_rw(output)
+            .uniform(params)
+    }
+
+    fn run(&self, wgpu_context: &WgpuContext, num_items: u32) {
+        let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Bindings builder test encoder") });
+        self.shader.dispatch(&mut encoder, (num_items.div_ceil(64), 1, 1), None, &self.bindings.bind_group());
+        let idx = wgpu_context.get_queue().submit([encoder.finish()]);
+        wgpu_context.get_device().poll(WaitForSubmissionIndex(idx)).unwrap();
+    }
+}
+
+fn create_buffers(wgpu_context: &WgpuContext, len: u32) -> (GpuBuffer<u32>, GpuBuffer<u32>, GpuBuffer<[u32; 4]>) {
+    let input = GpuBuffer::new(wgpu_context, (0..len).collect(), wgpu::BufferUsages::STORAGE);
+    let output = GpuBuffer::new(wgpu_context, vec![0u32; len as usize], wgpu::BufferUsages::STORAGE);
+    let params = GpuBuffer::new(wgpu_context, vec![[3u32, 0, 0, 0]], wgpu::BufferUsages::UNIFORM);
+    (input, output, params)
+}
+
+#[test]
+fn bindings_are_numbered_in_call_order_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let (input, mut output, params) = create_buffers(wgpu_context, 100);
+    let kernel = ScaleKernel::new(wgpu_context, &input, &output, &params);
+
+    kernel.run(wgpu_context, 100);
+    let expected: Vec<u32> = (0..100).map(|i| i * 3).collect();
+    assert_eq!(output.download(wgpu_context).unwrap().as_slice(), expected);
+
+    // Nothing changed, the bind group is reused
+    assert_eq!(kernel.bindings.bind_group(), kernel.bindings.bind_group());
+}
+
+#[test]
+fn bind_group_follows_grown_buffers_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let (mut input, mut output, params) = create_buffers(wgpu_context, 10);
+    let kernel = ScaleKernel::new(wgpu_context, &input, &output, &params);
+    kernel.run(wgpu_context, 10);
+    let first_bind_group = kernel.bindings.bind_group();
+
+    // Both pushes recreate their buffer, the kernel is not rebound
+    let old_input = input.buffer().clone();
+    input.push_all(&(10..1000).collect::<Vec<u32>>(), wgpu_context);
+    assert_ne!(*input.buffer(), old_input);
+    assert!(output.reserve(wgpu_context, 1000));
+    output.push_all(&vec![0u32; 990], wgpu_context);
+    assert_eq!(input.handle().buffer(), *input.buffer());
+
+    kernel.run(wgpu_context, 1000);
+    assert_ne!(kernel.bindings.bind_group(), first_bind_group);
+    let expected: Vec<u32> = (0..1000).map(|i| i * 3).collect();
+    assert_eq!(output.download(wgpu_context).unwrap().as_slice(), expected);
+}
+
+#[test]
+fn rebind_keeps_the_layout_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let (input, output, params) = create_buffers(wgpu_context, 10);
+    let mut kernel = ScaleKernel::new(wgpu_context, &input, &output, &params);
+
+    // Another output buffer, the kernel compiled for the layout keeps working
+    let mut other_output = GpuBuffer::new(wgpu_context, vec![0u32; 10], wgpu::BufferUsages::STORAGE);
+    kernel.bindings.rebind(ScaleKernel::bindings_builder(&input, &other_output, &params));
+    kernel.run(wgpu_context, 10);
+    let expected: Vec<u32> = (0..10).map(|i| i * 3).collect();
+    assert_eq!(other_output.download(wgpu_context).unwrap().as_slice(), expected);
+}
+
+#[test]
+#[should_panic(expected = "must be rebound with the bindings of its layout")]
+fn rebind_with_other_kinds_panics_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let (input, output, params) = create_buffers(wgpu_context, 10);
+    let mut kernel = ScaleKernel::new(wgpu_context, &input, &output, &params);
+    kernel.bindings.rebind(BindingsBuilder::new("Scale test").storage_rw(&input).storage_rw(&output).uniform(&params));
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::particles::particle_system::{BOUNDARY_MATERIAL_CHANNEL, NO_MATERIAL};
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::Simulation;
+use game_engine::simulation_config::{BoundaryMaterial, SimulationConfig};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const DELTA_TIME: f32 = 0.1;
+
+/// Particles of radius 2 at rest, 5 units right of the left wall.
+fn create_simulation(wgpu_context: &WgpuContext, heights: &[f32]) -> Simulation {
+    let positions: Vec<Vec2> = heights.iter().map(|&y| Vec2::new(5.0, y)).collect();
+    common::create_test_simulation(wgpu_context, positions, 2.0)
+}
+
+/// Steps once with `gravity` and returns the velocity (displacement per step) each particle leaves with,
+/// sorted by height.
+fn velocities_after_step(wgpu_context: &WgpuContext, simulation: &mut Simulation, gravity: Vec2) -> Vec<Vec2> {
+    simulation.particles_mut().set_gravity(gravity);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    simulation.step(wgpu_context, &mut gpu_profiler, DELTA_TIME, None);
+    gpu_profiler.end_frame().unwrap();
+
+    let buffers = simulation.particles_mut().download_particle_buffers(wgpu_context);
+    let mut particles: Vec<(Vec2, Vec2)> = buffers.current_positions.data().iter()
+        .zip(buffers.previous_positions.data())
+        .map(|(position, previous)| (*position, *position - *previous))
+        .collect();
+    particles.sort_by(|a, b| a.0.y.total_cmp(&b.0.y));
+    particles.into_iter().map(|(_, velocity)| velocity).collect()
+}
+
+fn assert_close(actual: Vec2, expected: Vec2) {
+    assert!((actual - expected).length() < 1e-3, "expected {:?}, got {:?}", expected, actual);
+}
+
+// The gravity moves the particles 10 units left in the step, the wall stops them after 3
+
+#[test]
+fn default_material_stops_at_the_wall_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = create_simulation(wgpu_context, &[100.0]);
+
+    let velocities = velocities_after_step(wgpu_context, &mut simulation, Vec2::new(-1000.0, 0.0));
+    assert_close(velocities[0], Vec2::new(-3.0, 0.0));
+}
+
+#[test]
+fn restitution_bounces_off_the_wall_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = create_simulation(wgpu_context, &[100.0]);
+    simulation.particles_mut().set_boundary_material(BoundaryMaterial::new(0.5, 0.0));
+
+    // Halfway between stopping at the wall (-3) and reflecting the step (10)
+    let velocities = velocities_after_step(wgpu_context, &mut simulation, Vec2::new(-1000.0, 0.0));
+    assert_close(velocities[0], Vec2::new(3.5, 0.0));
+}
+
+#[test]
+fn friction_slows_along_the_wall_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = create_simulation(wgpu_context, &[100.0]);
+    simulation.particles_mut().set_boundary_material(BoundaryMaterial::new(0.0, 0.5));
+
+    let velocities = velocities_after_step(wgpu_context, &mut simulation, Vec2::new(-1000.0, 1000.0));
+    assert_close(velocities[0], Vec2::new(-3.0, 5.0));
+}
+
+#[test]
+fn particles_use_their_material_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = create_simulation(wgpu_context, &[100.0, 200.0, 300.0]);
+    simulation.set_boundary_materials(wgpu_context, &[BoundaryMaterial::new(1.0, 0.0)]);
+    let id = simulation.particles().channels().find(BOUNDARY_MATERIAL_CHANNEL).unwrap();
+    // The third index is past the table
+    simulation.particles_mut().write_channel(wgpu_context, id, &[0, NO_MATERIAL, 1]);
+
+    let velocities = velocities_after_step(wgpu_context, &mut simulation, Vec2::new(-1000.0, 0.0));
+    assert_close(velocities[0], Vec2::new(10.0, 0.0));
+    assert_close(velocities[1], Vec2::new(-3.0, 0.0));
+    assert_close(velocities[2], Vec2::new(-3.0, 0.0));
+}
+
+#[test]
+fn boundary_material_config_test() {
+    let config = SimulationConfig::from_toml_str("[boundary_material]\nrestitution = 0.75\nfriction = 0.25\n").unwrap();
+    assert_eq!(config.boundary_material, BoundaryMaterial::new(0.75, 0.25));
+
+    let invalid = SimulationConfig::builder().boundary_material(BoundaryMaterial::new(1.5, 0.0)).build();
+    assert!(invalid.is_err());
+}
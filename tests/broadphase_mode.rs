@@ -0,0 +1,76 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::physics::collision_system::BroadphaseMode;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::Simulation;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const RADIUS: f32 = 2.0;
+
+fn create_simulation(wgpu_context: &WgpuContext, positions: Vec<Vec2>, broadphase_mode: BroadphaseMode) -> Simulation {
+    let mut simulation = common::create_test_simulation(wgpu_context, positions, RADIUS);
+    simulation.set_broadphase_mode(wgpu_context, broadphase_mode);
+    simulation
+}
+
+fn run(wgpu_context: &WgpuContext, positions: Vec<Vec2>, broadphase_mode: BroadphaseMode, steps: usize) -> Vec<Vec2> {
+    let mut simulation = create_simulation(wgpu_context, positions, broadphase_mode);
+    assert_eq!(simulation.collision_system().broadphase_mode(), broadphase_mode);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    for _ in 0..steps {
+        simulation.step(wgpu_context, &mut gpu_profiler, 0.01, None);
+        gpu_profiler.end_frame().unwrap();
+    }
+    simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().to_vec()
+}
+
+fn min_distance(positions: &[Vec2]) -> f32 {
+    let mut min_distance = f32::MAX;
+    for (i, a) in positions.iter().enumerate() {
+        for b in &positions[i + 1..] {
+            min_distance = min_distance.min(a.distance(*b));
+        }
+    }
+    min_distance
+}
+
+#[test]
+fn cell_ranges_push_overlapping_pairs_apart_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    // Pairs inside a cell, across a cell border and across a cell corner (cells are 4.4 units wide)
+    let positions = vec![
+        Vec2::new(100.0, 100.0), Vec2::new(102.0, 100.0),
+        Vec2::new(219.0, 300.0), Vec2::new(221.0, 300.0),
+        Vec2::new(439.5, 439.5), Vec2::new(440.5, 440.5),
+    ];
+
+    let stepped = run(wgpu_context, positions, BroadphaseMode::CellRanges, 1);
+    // Sorting reorders the particles, so every particle is checked against its nearest neighbour.
+    // Pairs that were not found would still be 2 or 1.4 units apart
+    for (i, position) in stepped.iter().enumerate() {
+        let nearest = stepped.iter().enumerate().filter(|&(j, _)| j != i).map(|(_, other)| position.distance(*other)).fold(f32::MAX, f32::min);
+        assert!(nearest > 2.5, "{stepped:?}");
+    }
+}
+
+#[test]
+fn both_broadphases_separate_a_pile_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut positions = Vec::new();
+    for y in 0..10 {
+        for x in 0..10 {
+            positions.push(Vec2::new(500.0 + x as f32 * 3.0, 500.0 + y as f32 * 3.0));
+        }
+    }
+    let initial = min_distance(&positions);
+
+    for broadphase_mode in [BroadphaseMode::CollisionCells, BroadphaseMode::CellRanges, BroadphaseMode::SpatialHash] {
+        let stepped = run(wgpu_context, positions.clone(), broadphase_mode, 30);
+        assert_eq!(stepped.len(), positions.len());
+        assert!(stepped.iter().all(|position| position.is_finite()));
+        assert!(min_distance(&stepped) > initial, "{broadphase_mode:?} left particles at {}", min_distance(&stepped));
+    }
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use game_engine::utils::buffer_registry::{BufferRegistry, SubsystemUsage};
+use game_engine::utils::gpu_buffer::GpuBuffer;
+
+#[test]
+fn gpu_buffers_register_under_the_file_that_created_them_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let registry = wgpu_context.buffers();
+    let before = registry.total_bytes();
+
+    let mut buffer = GpuBuffer::new(wgpu_context, vec![0u32; 100], wgpu::BufferUsages::STORAGE);
+    let other = GpuBuffer::new(wgpu_context, vec![0.0f32; 8], wgpu::BufferUsages::UNIFORM);
+    assert_eq!(registry.total_bytes(), before + 432);
+    let ours = registry.subsystems().into_iter().find(|usage| usage.subsystem == "buffer_registry").unwrap();
+    assert_eq!(ours, SubsystemUsage { subsystem: "buffer_registry".to_string(), bytes: 432, buffers: 2 });
+    let record = registry.records().into_iter().find(|record| record.size == 400).unwrap();
+    assert!(record.label.starts_with("buffer_registry.rs:"), "{}", record.label);
+    assert!(record.usage.contains(wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST));
+
+    // Growing doubles the capacity, the registry follows
+    buffer.push(1, wgpu_context);
+    assert_eq!(registry.total_bytes(), before + 808 + 32);
+    assert!(registry.report().contains("buffer_registry"), "{}", registry.report());
+
+    drop(buffer);
+    drop(other);
+    assert_eq!(registry.total_bytes(), before);
+    assert!(registry.peak_bytes() >= before + 840);
+}
+
+#[test]
+fn registrations_outliving_their_registry_are_harmless_test() {
+    let registry = BufferRegistry::new(&wgpu::Limits::default());
+    registry.set_memory_budget(Some(1000));
+    let first = registry.register(std::panic::Location::caller(), 600, wgpu::BufferUsages::VERTEX);
+    // Crosses 80% of the budget, warns once
+    let second = registry.register(std::panic::Location::caller(), 300, wgpu::BufferUsages::VERTEX);
+    assert_eq!(registry.total_bytes(), 900);
+    registry.resize(&first, 100);
+    assert_eq!(registry.total_bytes(), 400);
+    assert_eq!(registry.subsystems()[0].buffers, 2);
+    drop(second);
+    assert_eq!(registry.total_bytes(), 100);
+    drop(registry);
+    drop(first);
+}
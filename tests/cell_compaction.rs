@@ -0,0 +1,112 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::grid::grid::{Grid, UNUSED_CELL_ID};
+use game_engine::particles::particle_system::ParticleSystem;
+use game_engine::physics::collision_system::CollisionSystem;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::{Simulation, DIMENSION};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+fn build_grid(wgpu_context: &WgpuContext, grid: &mut Grid) -> (Vec<u32>, Vec<u32>) {
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Cell compaction test") });
+    if grid.is_indirect_dispatch() {
+        grid.prepare_indirect_dispatch(&mut encoder);
+    }
+    grid.build_cell_ids(&mut encoder);
+    grid.sort_map(&mut encoder);
+    wgpu_context.get_queue().submit([encoder.finish()]);
+    (grid.download_cell_ids(wgpu_context).unwrap(), grid.download_object_ids(wgpu_context).unwrap())
+}
+
+fn create_particles(wgpu_context: &WgpuContext) -> ParticleSystem {
+    // Cells of 11 units. Two particles in the middle of a cell, one on a cell border and one on a cell corner
+    common::create_test_particle_system(
+        wgpu_context,
+        vec![Vec2::new(698.5, 698.5), Vec2::new(110.0, 104.5), Vec2::new(302.5, 302.5), Vec2::new(110.0, 110.0)],
+        vec![5.0; 4],
+    )
+}
+
+#[test]
+fn compacted_map_matches_the_full_sort_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let particles = create_particles(wgpu_context);
+
+    let mut full_grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let (expected_cell_ids, expected_object_ids) = build_grid(wgpu_context, &mut full_grid);
+    let used_cells = expected_cell_ids.iter().filter(|&&cell_id| cell_id != UNUSED_CELL_ID).count();
+    assert_eq!(used_cells, 1 + 2 + 1 + 4, "{expected_cell_ids:?}");
+
+    let mut grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    grid.set_cell_compaction(wgpu_context, true);
+    assert!(grid.is_cell_compaction());
+    for _ in 0..2 {
+        let (cell_ids, object_ids) = build_grid(wgpu_context, &mut grid);
+        assert_eq!(cell_ids, expected_cell_ids);
+        assert_eq!(object_ids[..used_cells], expected_object_ids[..used_cells]);
+
+        // The count stays on the GPU, the sort only saw the used cells
+        let args = grid.live_count().download(wgpu_context);
+        assert_eq!(args.used_cells, used_cells as u32);
+        assert_eq!(args.previous_used_cells, used_cells as u32);
+    }
+}
+
+#[test]
+fn removed_particles_leave_the_compacted_map_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let particles = create_particles(wgpu_context);
+
+    let mut grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    grid.set_indirect_dispatch(true);
+    grid.set_cell_compaction(wgpu_context, true);
+    let (cell_ids, _) = build_grid(wgpu_context, &mut grid);
+    let all_cells = grid.live_count().download(wgpu_context).used_cells as usize;
+    assert!(cell_ids[..all_cells].iter().all(|&cell_id| cell_id != UNUSED_CELL_ID));
+
+    // Only the particle in its home cell is left, removed without telling the CPU
+    grid.set_live_particles(wgpu_context, 1);
+    let (cell_ids, object_ids) = build_grid(wgpu_context, &mut grid);
+    assert_eq!(grid.live_count().download(wgpu_context).used_cells, 1);
+    assert_eq!(object_ids[0], 0);
+    assert!(cell_ids[1..].iter().all(|&cell_id| cell_id == UNUSED_CELL_ID), "{cell_ids:?}");
+}
+
+fn run(wgpu_context: &WgpuContext, cell_compaction: bool, indirect_dispatch: bool) -> Vec<Vec2> {
+    let particles = common::create_test_particle_system(
+        wgpu_context,
+        vec![Vec2::new(300.0, 300.0), Vec2::new(302.0, 300.0), Vec2::new(100.0, 100.0), Vec2::new(101.0, 101.0)],
+        vec![2.0; 4],
+    );
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let collision_system = CollisionSystem::new(wgpu_context, DIMENSION, &particles, &grid);
+    let mut simulation = Simulation::from_parts(wgpu_context, particles, grid, collision_system);
+    simulation.set_indirect_dispatch(indirect_dispatch);
+    simulation.set_cell_compaction(wgpu_context, cell_compaction);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    for _ in 0..5 {
+        simulation.step(wgpu_context, &mut gpu_profiler, 0.016, None);
+        gpu_profiler.end_frame().unwrap();
+    }
+    let mut positions = simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().to_vec();
+    positions.sort_by(|a, b| a.x.total_cmp(&b.x));
+    positions
+}
+
+#[test]
+fn cell_compaction_matches_the_full_map_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    for indirect_dispatch in [false, true] {
+        let full = run(wgpu_context, false, indirect_dispatch);
+        let compacted = run(wgpu_context, true, indirect_dispatch);
+        for (full_position, compacted_position) in full.iter().zip(&compacted) {
+            assert!(full_position.distance(*compacted_position) < 1e-4, "{:?} != {:?}", full, compacted);
+        }
+    }
+}
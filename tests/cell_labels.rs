@@ -0,0 +1,48 @@
+use glam::Vec2;
+use game_engine::grid::cell_labels::{cell_label_strokes, digit_segments, number_strokes, MIN_LABEL_CELL_PIXELS};
+use game_engine::grid::morton;
+
+#[test]
+fn digit_segments_test() {
+    // One is the two right segments, eight lights all of them
+    assert_eq!(digit_segments(1), 0b0000110);
+    assert_eq!(digit_segments(8).count_ones(), 7);
+    let strokes: usize = (0..10).map(|digit| digit_segments(digit).count_ones() as usize).sum();
+    assert_eq!(strokes, 49);
+}
+
+#[test]
+fn number_strokes_are_centered_test() {
+    let center = Vec2::new(10.0, 20.0);
+    // 8 and 0 light both sides of their digit, so the strokes span the whole number
+    let strokes = number_strokes(80, center, 4.0);
+    assert_eq!(strokes.len(), 7 + 6);
+
+    let (min, max) = strokes.iter()
+        .flat_map(|(start, end)| [*start, *end])
+        .fold((Vec2::MAX, Vec2::MIN), |(min, max), p| (min.min(p), max.max(p)));
+    assert!(((min + max) * 0.5 - center).length() < 1e-4, "{min} {max}");
+    assert!((max.y - min.y - 4.0).abs() < 1e-4);
+}
+
+#[test]
+fn cells_are_labelled_only_when_zoomed_in_test() {
+    let origin = Vec2::ZERO;
+    let world_size = Vec2::new(100.0, 100.0);
+    let cell_size = 10.0;
+    let zoom = MIN_LABEL_CELL_PIXELS / cell_size;
+
+    // Zoomed out, the labels would not be readable
+    assert!(cell_label_strokes(Vec2::ZERO, world_size, origin, world_size, cell_size, zoom * 0.5).is_empty());
+
+    // The view covers cells (1, 1) and (2, 1), morton ids 3 and 6
+    let strokes = cell_label_strokes(Vec2::new(15.0, 12.0), Vec2::new(25.0, 18.0), origin, world_size, cell_size, zoom);
+    assert_eq!(morton::encode(glam::UVec2::new(1, 1)), 3);
+    assert_eq!(morton::encode(glam::UVec2::new(2, 1)), 6);
+    let expected = digit_segments(3).count_ones() + digit_segments(6).count_ones();
+    assert_eq!(strokes.len(), expected as usize);
+    assert!(strokes.iter().all(|(start, end)| start.x > 10.0 && end.x < 30.0 && start.y > 10.0 && end.y < 20.0));
+
+    // A view outside of the world has no labels
+    assert!(cell_label_strokes(Vec2::new(-50.0, -50.0), Vec2::new(-10.0, -10.0), origin, world_size, cell_size, zoom).is_empty());
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use glam::UVec2;
+use game_engine::grid::cell_occupancy_map::{CellOccupancyMap, GridDebugView};
+use game_engine::grid::morton;
+use game_engine::utils::gpu_buffer::GpuBuffer;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const UNUSED_CELL_ID: u32 = 0xffffffff;
+
+#[test]
+fn occupancy_map_counts_the_objects_per_cell_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    // Cell id map of three objects, in no particular order
+    let cell_ids = vec![
+        morton::encode(UVec2::new(1, 0)), morton::encode(UVec2::new(2, 1)), UNUSED_CELL_ID, UNUSED_CELL_ID,
+        morton::encode(UVec2::new(2, 1)), UNUSED_CELL_ID, UNUSED_CELL_ID, UNUSED_CELL_ID,
+        // Past the table, not counted
+        morton::encode(UVec2::new(5, 0)), morton::encode(UVec2::new(1, 0)), morton::encode(UVec2::new(2, 1)), UNUSED_CELL_ID,
+    ];
+    let cell_ids = GpuBuffer::new(wgpu_context, cell_ids, wgpu::BufferUsages::STORAGE);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    let mut occupancy_map = CellOccupancyMap::new(wgpu_context);
+
+    occupancy_map.update(wgpu_context, &mut gpu_profiler, &cell_ids, UVec2::new(3, 2));
+    assert_eq!(occupancy_map.table_size(), UVec2::new(3, 2));
+    assert_eq!(occupancy_map.download_counts(wgpu_context), vec![0, 2, 0, 0, 0, 3]);
+
+    // Every update counts from zero, the table follows the world
+    assert!(occupancy_map.update(wgpu_context, &mut gpu_profiler, &cell_ids, UVec2::new(8, 4)));
+    let counts = occupancy_map.download_counts(wgpu_context);
+    assert_eq!(counts.len(), 32);
+    assert_eq!((counts[1], counts[5], counts[8 + 2]), (2, 1, 3));
+    assert_eq!(counts.iter().sum::<u32>(), 6);
+    gpu_profiler.end_frame().unwrap();
+}
+
+#[test]
+fn grid_debug_views_test() {
+    assert_eq!(GridDebugView::cycle(None), Some(GridDebugView::Occupancy));
+    assert_eq!(GridDebugView::cycle(Some(GridDebugView::Occupancy)), Some(GridDebugView::PairTests));
+    assert_eq!(GridDebugView::cycle(Some(GridDebugView::PairTests)), None);
+
+    assert_eq!(GridDebugView::Occupancy.value(3), 3);
+    assert_eq!(GridDebugView::PairTests.value(0), 0);
+    assert_eq!(GridDebugView::PairTests.value(1), 0);
+    assert_eq!(GridDebugView::PairTests.value(4), 6);
+    assert_eq!(GridDebugView::PairTests.max_value(), 28);
+}
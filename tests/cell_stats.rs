@@ -0,0 +1,54 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::grid::grid::Grid;
+use game_engine::physics::collision_system::{CollisionSystem, SolverConfig};
+use game_engine::physics::contact_stats::CollisionStats;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::{Simulation, DIMENSION};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+use wgpu::wgt::PollType::Wait;
+
+/// Six particles of radius 2 packed into the same cells.
+fn stats_after_steps(wgpu_context: &WgpuContext, config: SolverConfig) -> CollisionStats {
+    let positions = (0..6).map(|i| Vec2::new(101.0 + (i % 3) as f32, 101.0 + (i / 3) as f32)).collect();
+    let particles = common::create_test_particle_system(wgpu_context, positions, vec![2.0; 6]);
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let collision_system = CollisionSystem::new_with_config(wgpu_context, DIMENSION, &particles, &grid, config);
+    let mut simulation = Simulation::from_parts(wgpu_context, particles, grid, collision_system);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    assert_eq!(simulation.collision_system().stats(), None);
+
+    // The second step reads the statistics of the first
+    for _ in 0..2 {
+        simulation.step(wgpu_context, &mut gpu_profiler, 0.01, None);
+        gpu_profiler.end_frame().unwrap();
+        wgpu_context.get_device().poll(Wait).unwrap();
+    }
+    simulation.collision_system().stats().unwrap()
+}
+
+#[test]
+fn cell_stats_count_the_collision_cells_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    let stats = stats_after_steps(wgpu_context, SolverConfig::default());
+    assert!(stats.cells.collision_cells > 0);
+    assert_eq!(stats.cells.max_particles_per_cell, 6);
+    assert_eq!(stats.cells.overflow_cells, 0);
+    assert_eq!(stats.cells.overflow_particles, 0);
+    assert!(stats.contacts.contacts > 0);
+}
+
+#[test]
+fn cell_stats_count_the_overflow_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    let stats = stats_after_steps(wgpu_context, SolverConfig { max_particles_per_cell: 4, ..SolverConfig::default() });
+    assert_eq!(stats.cells.max_particles_per_cell, 6);
+    assert!(stats.cells.overflow_cells > 0);
+    assert!(stats.cells.overflow_particles >= stats.cells.overflow_cells);
+    assert!(stats.cells.overflow_particles <= stats.cells.overflow_cells * 2);
+}
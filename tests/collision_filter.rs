@@ -0,0 +1,83 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::grid::grid::Grid;
+use game_engine::physics::collision_system::{BroadphaseMode, CollisionFilter, CollisionSystem, COLLISION_FILTER_CHANNEL};
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::{Simulation, DIMENSION};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const DELTA_TIME: f32 = 0.1;
+/// In the second set, colliding with every set but its own.
+const GHOST: CollisionFilter = CollisionFilter { group: 2, mask: !2 };
+
+/// Two overlapping pairs of particles of radius 2: the first pair gets `GHOST`, the second one keeps the default filter.
+/// Returns the positions after a step, sorted along x.
+fn step_overlapping_pairs(wgpu_context: &WgpuContext, broadphase_mode: BroadphaseMode) -> Vec<Vec2> {
+    let positions = vec![Vec2::new(100.0, 500.0), Vec2::new(102.0, 500.0), Vec2::new(500.0, 500.0), Vec2::new(502.0, 500.0)];
+    let particles = common::create_test_particle_system(wgpu_context, positions, vec![2.0; 4]);
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let collision_system = CollisionSystem::new(wgpu_context, DIMENSION, &particles, &grid);
+    let mut simulation = Simulation::from_parts(wgpu_context, particles, grid, collision_system);
+    simulation.set_broadphase_mode(wgpu_context, broadphase_mode);
+    simulation.set_collision_filter(wgpu_context, 0..2, GHOST);
+
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    simulation.step(wgpu_context, &mut gpu_profiler, DELTA_TIME, None);
+    gpu_profiler.end_frame().unwrap();
+
+    let buffers = simulation.particles_mut().download_particle_buffers(wgpu_context);
+    let mut positions = buffers.current_positions.data().to_vec();
+    positions.sort_by(|a, b| a.x.total_cmp(&b.x));
+    positions
+}
+
+fn assert_filtered(positions: &[Vec2]) {
+    // The ghosts pass through each other
+    assert!((positions[0] - Vec2::new(100.0, 500.0)).length() < 1e-3, "{positions:?}");
+    assert!((positions[1] - Vec2::new(102.0, 500.0)).length() < 1e-3, "{positions:?}");
+    // The default particles are pushed apart
+    assert!(positions[3].x - positions[2].x > 2.0, "{positions:?}");
+}
+
+#[test]
+fn collision_filter_can_collide_test() {
+    let default = CollisionFilter::default();
+    assert_eq!(default, CollisionFilter::DEFAULT);
+    assert!(default.can_collide(&default));
+    assert!(default.can_collide(&GHOST));
+    assert!(!GHOST.can_collide(&GHOST));
+    // One sided masks are not enough
+    let shy = CollisionFilter::new(4, 1);
+    assert!(!shy.can_collide(&GHOST));
+    assert!(shy.can_collide(&default));
+    assert_eq!(GHOST.to_words(), [2, !2]);
+}
+
+#[test]
+fn filtered_pairs_pass_through_each_other_test() {
+    let setup = pollster::block_on(common::setup());
+    assert_filtered(&step_overlapping_pairs(&setup.wgpu_context, BroadphaseMode::CollisionCells));
+}
+
+#[test]
+fn filtered_pairs_pass_through_each_other_with_cell_ranges_test() {
+    let setup = pollster::block_on(common::setup());
+    assert_filtered(&step_overlapping_pairs(&setup.wgpu_context, BroadphaseMode::CellRanges));
+}
+
+#[test]
+fn spawned_particles_get_the_spawn_filter_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let particles = common::create_test_particle_system(wgpu_context, vec![Vec2::new(100.0, 500.0)], vec![2.0]);
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let collision_system = CollisionSystem::new(wgpu_context, DIMENSION, &particles, &grid);
+    let mut simulation = Simulation::from_parts(wgpu_context, particles, grid, collision_system);
+
+    simulation.set_spawn_collision_filter(wgpu_context, GHOST);
+    simulation.add_particles_at(wgpu_context, &[Vec2::new(300.0, 500.0)]);
+    let id = simulation.particles().channels().find(COLLISION_FILTER_CHANNEL).unwrap();
+    assert_eq!(simulation.particles_mut().read_channel_at(wgpu_context, id, 0), CollisionFilter::DEFAULT.to_words().to_vec());
+    assert_eq!(simulation.particles_mut().read_channel_at(wgpu_context, id, 1), GHOST.to_words().to_vec());
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use glam::{Vec2, Vec4};
+use game_engine::particles::color_palette::{ColorHarmony, ColorPalette, ColorTheme};
+use game_engine::simulation_config::SimulationConfig;
+use game_engine::utils::config_file::ConfigError;
+
+/// True if `color` is `base` made at most 8% brighter or darker.
+fn is_shade_of(color: Vec4, base: Vec4) -> bool {
+    (color.truncate() - base.truncate()).abs().cmple(base.truncate() * 0.08 + 1e-4).all() && color.w == base.w
+}
+
+#[test]
+fn palettes_follow_the_theme_test() {
+    let theme = ColorTheme { seed: 7, harmony: ColorHarmony::Triadic };
+    let palette = ColorPalette::generate(theme);
+    assert_eq!(palette, ColorPalette::generate(theme));
+    assert_ne!(palette, ColorPalette::generate(ColorTheme { seed: 8, ..theme }));
+    assert_eq!(palette.theme(), theme);
+    // Three hues in three shades
+    assert_eq!(palette.colors().len(), 9);
+    assert_eq!(palette.color(9), palette.color(0));
+
+    for harmony in ColorHarmony::ALL {
+        assert_eq!(ColorHarmony::from_name(harmony.name()), Some(harmony));
+        let palette = ColorPalette::generate(ColorTheme { seed: 3, harmony });
+        assert!(palette.colors().iter().all(|color| color.cmpge(Vec4::ZERO).all() && color.cmple(Vec4::ONE).all()), "{palette:?}");
+    }
+    assert_eq!(ColorHarmony::from_name("rainbow"), None);
+}
+
+#[test]
+fn palette_config_test() {
+    let config = SimulationConfig::from_toml_str("[palette]\nseed = 42\nharmony = \"complementary\"\n").unwrap();
+    assert_eq!(config.palette, Some(ColorTheme { seed: 42, harmony: ColorHarmony::Complementary }));
+    let config = SimulationConfig::from_toml_str("[palette]\nseed = 42\n").unwrap();
+    assert_eq!(config.palette, Some(ColorTheme { seed: 42, harmony: ColorHarmony::Analogous }));
+    assert_eq!(SimulationConfig::from_toml_str("").unwrap().palette, None);
+    assert!(matches!(
+        SimulationConfig::from_toml_str("[palette]\nseed = 1\nharmony = \"rainbow\"\n"),
+        Err(ConfigError::InvalidValue { .. })
+    ));
+}
+
+#[test]
+fn spawn_batches_take_the_palette_colors_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut particles = common::create_test_particle_system(wgpu_context, vec![Vec2::new(100.0, 100.0)], vec![2.0]);
+    let palette = ColorPalette::generate(ColorTheme { seed: 11, harmony: ColorHarmony::Analogous });
+    particles.set_palette(Some(palette.clone()));
+
+    let first_batch: Vec<Vec2> = (0..10).map(|i| Vec2::new(200.0 + i as f32 * 10.0, 200.0)).collect();
+    let second_batch: Vec<Vec2> = (0..10).map(|i| Vec2::new(200.0 + i as f32 * 10.0, 400.0)).collect();
+    particles.add_particles_at(&first_batch, wgpu_context);
+    particles.add_particles_at(&second_batch, wgpu_context);
+
+    let colors = particles.download_particle_buffers(wgpu_context).colors.data().clone();
+    assert_eq!(colors.len(), 21);
+    assert!(colors[1..11].iter().all(|&color| is_shade_of(color, palette.color(0))), "{colors:?}");
+    assert!(colors[11..].iter().all(|&color| is_shade_of(color, palette.color(1))), "{colors:?}");
+
+    // Repainting uses every color of the palette at random
+    assert!(particles.recolor_with_palette(wgpu_context));
+    let colors = particles.download_particle_buffers(wgpu_context).colors.data().clone();
+    assert!(colors.iter().all(|&color| palette.colors().iter().any(|&base| is_shade_of(color, base))), "{colors:?}");
+
+    particles.set_palette(None);
+    assert!(!particles.recolor_with_palette(wgpu_context));
+}
@@ -0,0 +1,53 @@
+use glam::Vec2;
+use game_engine::utils::command_queue::{CommandQueue, SimulationCommand, COMMAND_HISTORY_SIZE};
+
+#[test]
+fn drain_keeps_push_order_test() {
+    let mut queue = CommandQueue::new();
+    queue.push(SimulationCommand::ToggleGrid);
+    queue.push(SimulationCommand::SpawnParticles { position: Vec2::new(1.0, 2.0) });
+    queue.push(SimulationCommand::SetTimeScale(0.5));
+    assert_eq!(queue.len(), 3);
+
+    let commands = queue.drain(7);
+    assert_eq!(commands, vec![
+        SimulationCommand::ToggleGrid,
+        SimulationCommand::SpawnParticles { position: Vec2::new(1.0, 2.0) },
+        SimulationCommand::SetTimeScale(0.5),
+    ]);
+    assert!(queue.is_empty());
+    assert!(queue.drain(8).is_empty());
+
+    let history: Vec<_> = queue.history().collect();
+    assert_eq!(history.len(), 3);
+    assert!(history.iter().all(|executed| executed.frame == 7));
+    assert_eq!(history[2].command, SimulationCommand::SetTimeScale(0.5));
+}
+
+#[test]
+fn consecutive_moves_are_merged_test() {
+    let mut queue = CommandQueue::new();
+    queue.push(SimulationCommand::MoveImpulse { position: Vec2::new(1.0, 1.0) });
+    queue.push(SimulationCommand::MoveImpulse { position: Vec2::new(2.0, 2.0) });
+    queue.push(SimulationCommand::ApplyImpulse { position: Vec2::new(2.0, 2.0), active: true });
+    queue.push(SimulationCommand::MoveImpulse { position: Vec2::new(3.0, 3.0) });
+
+    assert_eq!(queue.drain(0), vec![
+        SimulationCommand::MoveImpulse { position: Vec2::new(2.0, 2.0) },
+        SimulationCommand::ApplyImpulse { position: Vec2::new(2.0, 2.0), active: true },
+        SimulationCommand::MoveImpulse { position: Vec2::new(3.0, 3.0) },
+    ]);
+}
+
+#[test]
+fn history_is_bounded_test() {
+    let mut queue = CommandQueue::new();
+    for frame in 0..(COMMAND_HISTORY_SIZE as u64 + 10) {
+        queue.push(SimulationCommand::ChangeSolverIterations(1));
+        queue.drain(frame);
+    }
+
+    let history: Vec<_> = queue.history().collect();
+    assert_eq!(history.len(), COMMAND_HISTORY_SIZE);
+    assert_eq!(history[0].frame, 10);
+}
@@ -1,15 +1,20 @@
 // Not every test file will use every function.
 #![allow(dead_code)]
 
+#[cfg(feature = "windowing")]
 use game_engine::renderer::camera::Camera;
 use glam::{Vec2};
+use game_engine::grid::grid::Grid;
 use game_engine::particles::particle_system::ParticleSystem;
+use game_engine::physics::collision_system::CollisionSystem;
 use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::{Simulation, DIMENSION};
 use game_engine::utils::gpu_buffer::GpuBuffer;
 
 // A struct to hold all the common objects for a test.
 pub struct TestSetup {
     pub wgpu_context: WgpuContext,
+    #[cfg(feature = "windowing")]
     pub camera: Camera,
 }
 
@@ -18,10 +23,12 @@ pub async fn setup() -> TestSetup {
     let wgpu_context = WgpuContext::new_for_test().await.unwrap();
 
 
+    #[cfg(feature = "windowing")]
     let camera = Camera::new(&Vec2{x: 1920.0, y: 1080.0}, &wgpu_context);
 
     TestSetup {
         wgpu_context,
+        #[cfg(feature = "windowing")]
         camera,
     }
 }
@@ -30,4 +37,14 @@ pub fn create_test_particle_system(wgpu_context: &WgpuContext, positions: Vec<Ve
     let p_buffer = GpuBuffer::new(wgpu_context, positions, wgpu::BufferUsages::STORAGE);
     let r_buffer = GpuBuffer::new(wgpu_context, radius, wgpu::BufferUsages::STORAGE);
     ParticleSystem::new_from_buffers(wgpu_context, p_buffer, r_buffer)
-}
\ No newline at end of file
+}
+
+/// Particles of the same `radius` at rest in the 1920 x 1080 world of the test particle systems, with the grid
+/// and the collision system of the default config.
+pub fn create_test_simulation(wgpu_context: &WgpuContext, positions: Vec<Vec2>, radius: f32) -> Simulation {
+    let radii = vec![radius; positions.len()];
+    let particles = create_test_particle_system(wgpu_context, positions, radii);
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let collision_system = CollisionSystem::new(wgpu_context, DIMENSION, &particles, &grid);
+    Simulation::from_parts(wgpu_context, particles, grid, collision_system)
+}
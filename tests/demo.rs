@@ -0,0 +1,22 @@
+use game_engine::scenes::demo::{self, DEMO_SCENE};
+use game_engine::simulation_config::SimulationConfig;
+
+const LARGE_BINDING: u32 = 1 << 30;
+
+#[test]
+fn demo_scene_parses_test() {
+    let config = SimulationConfig::from_toml_str(DEMO_SCENE).unwrap();
+    assert!(config.world_gravity().y < 0.0);
+    assert!(config.palette.is_some());
+}
+
+#[test]
+fn demo_particle_budget_test() {
+    let discrete = demo::particle_budget(wgpu::DeviceType::DiscreteGpu, LARGE_BINDING);
+    let integrated = demo::particle_budget(wgpu::DeviceType::IntegratedGpu, LARGE_BINDING);
+    let cpu = demo::particle_budget(wgpu::DeviceType::Cpu, LARGE_BINDING);
+    assert!(discrete > integrated && integrated > cpu && cpu > 0);
+
+    // The cell ids of 4 cells per particle must fit in a binding
+    assert_eq!(demo::particle_budget(wgpu::DeviceType::DiscreteGpu, 16 * 1000), 1000);
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use glam::{Mat4, UVec2, Vec2};
+use game_engine::renderer::density_heatmap::DensityHeatmap;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+#[test]
+fn density_heatmap_counts_the_particles_per_texel_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let positions = vec![
+        Vec2::new(15.0, 15.0),
+        Vec2::new(15.5, 15.5),
+        Vec2::new(16.0, 16.0),
+        Vec2::new(95.0, 95.0),
+        // Outside the view
+        Vec2::new(150.0, 50.0),
+    ];
+    let particles = common::create_test_particle_system(wgpu_context, positions, vec![1.0; 5]);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    let mut heatmap = DensityHeatmap::new(wgpu_context, wgpu::TextureFormat::Rgba8Unorm);
+    let view_proj = Mat4::orthographic_rh(0.0, 100.0, 0.0, 100.0, -1.0, 1.0);
+    // Half the window resolution: 10 x 10 texels, 10 world units each
+    let screen_size = Vec2::new(20.0, 20.0);
+
+    heatmap.update(wgpu_context, &mut gpu_profiler, particles.positions(), particles.len(), view_proj, screen_size);
+    assert_eq!(heatmap.size(), Some(UVec2::new(10, 10)));
+    let density = heatmap.download_density(wgpu_context);
+    assert_eq!(density.len(), 100);
+    // Rows start at the top of the view
+    assert_eq!(density[8 * 10 + 1], 1.0);
+    let sparse = density[9];
+    assert!((sparse - 2f32.ln() / 4f32.ln()).abs() < 1e-5, "{sparse}");
+    assert_eq!(density.iter().filter(|&&value| value > 0.0).count(), 2);
+
+    // The counts of the previous update are cleared
+    heatmap.update(wgpu_context, &mut gpu_profiler, particles.positions(), 1, view_proj, screen_size);
+    let density = heatmap.download_density(wgpu_context);
+    assert_eq!(density[8 * 10 + 1], 1.0);
+    assert_eq!(density.iter().filter(|&&value| value > 0.0).count(), 1);
+
+    heatmap.update(wgpu_context, &mut gpu_profiler, particles.positions(), 0, view_proj, screen_size);
+    assert!(heatmap.download_density(wgpu_context).iter().all(|&value| value == 0.0));
+    gpu_profiler.end_frame().unwrap();
+}
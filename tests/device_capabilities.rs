@@ -0,0 +1,53 @@
+use game_engine::renderer::wgpu_context::{DeviceCapabilities, WgpuContext};
+
+mod common;
+
+fn capabilities_with_limits(limits: wgpu::Limits) -> DeviceCapabilities {
+    DeviceCapabilities::new(wgpu::Features::PUSH_CONSTANTS, &limits, wgpu::Backend::Vulkan)
+}
+
+#[test]
+fn workgroup_size_is_clamped_to_the_device_test() {
+    let capabilities = capabilities_with_limits(wgpu::Limits::default());
+    assert_eq!(capabilities.workgroup_size(256), 256);
+    assert_eq!(capabilities.workgroup_size(64), 64);
+
+    let small = capabilities_with_limits(wgpu::Limits {
+        max_compute_workgroup_size_x: 96,
+        max_compute_invocations_per_workgroup: 128,
+        ..wgpu::Limits::default()
+    });
+    // The largest power of two below both limits
+    assert_eq!(small.workgroup_size(256), 64);
+    assert_eq!(small.workgroup_size(32), 32);
+}
+
+#[test]
+fn push_constants_need_the_feature_test() {
+    let limits = wgpu::Limits { max_push_constant_size: 128, ..wgpu::Limits::default() };
+    let with_push_constants = DeviceCapabilities::new(wgpu::Features::PUSH_CONSTANTS, &limits, wgpu::Backend::Vulkan);
+    assert!(with_push_constants.fits_push_constants(128));
+    assert!(!with_push_constants.fits_push_constants(132));
+
+    let without_push_constants = DeviceCapabilities::new(wgpu::Features::empty(), &limits, wgpu::Backend::Vulkan);
+    assert_eq!(without_push_constants.max_push_constant_size, 0);
+    assert!(!without_push_constants.fits_push_constants(4));
+    assert!(!without_push_constants.subgroups);
+
+    // GLES rejects the push constant structs of the kernels, even with the feature
+    let gles = DeviceCapabilities::new(wgpu::Features::PUSH_CONSTANTS, &limits, wgpu::Backend::Gl);
+    assert_eq!(gles.max_push_constant_size, 0);
+}
+
+#[test]
+fn context_capabilities_follow_the_requested_features_test() {
+    let wgpu_context = pollster::block_on(WgpuContext::new_for_test_with_features(wgpu::Features::empty())).unwrap();
+    let capabilities = wgpu_context.capabilities();
+    assert!(!capabilities.subgroups);
+    assert_eq!(capabilities.max_push_constant_size, 0);
+    assert_eq!(capabilities.max_buffer_size, wgpu_context.get_device().limits().max_buffer_size);
+
+    let setup = pollster::block_on(common::setup());
+    let capabilities = setup.wgpu_context.capabilities();
+    assert_eq!(capabilities.subgroups, setup.wgpu_context.get_device().features().contains(wgpu::Features::SUBGROUP));
+}
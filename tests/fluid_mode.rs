@@ -0,0 +1,70 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::physics::fluid_solver::{FluidConfig, SimulationMode};
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::Simulation;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const DELTA_TIME: f32 = 0.01;
+
+fn create_simulation(wgpu_context: &WgpuContext, positions: Vec<Vec2>) -> Simulation {
+    let mut simulation = common::create_test_simulation(wgpu_context, positions, 2.0);
+    simulation.particles_mut().set_gravity(Vec2::ZERO);
+    simulation
+}
+
+fn step(wgpu_context: &WgpuContext, simulation: &mut Simulation) -> Vec<Vec2> {
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    simulation.step(wgpu_context, &mut gpu_profiler, DELTA_TIME, None);
+    gpu_profiler.end_frame().unwrap();
+    simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().to_vec()
+}
+
+#[test]
+fn set_mode_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = create_simulation(wgpu_context, vec![Vec2::new(100.0, 100.0)]);
+    assert_eq!(simulation.mode(), SimulationMode::Rigid);
+    assert!(simulation.download_fluid_densities(wgpu_context).is_empty());
+
+    let config = FluidConfig { viscosity: 0.0, ..FluidConfig::default() };
+    simulation.set_mode(wgpu_context, SimulationMode::Fluid(config));
+    assert_eq!(simulation.mode(), SimulationMode::Fluid(config));
+
+    simulation.set_mode(wgpu_context, SimulationMode::Rigid);
+    assert_eq!(simulation.mode(), SimulationMode::Rigid);
+}
+
+#[test]
+fn packed_fluid_has_a_density_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let positions = (0..16).map(|i| Vec2::new(100.0 + (i % 4) as f32 * 3.0, 100.0 + (i / 4) as f32 * 3.0)).collect();
+    let mut simulation = create_simulation(wgpu_context, positions);
+    simulation.set_mode(wgpu_context, SimulationMode::Fluid(FluidConfig::default()));
+    step(wgpu_context, &mut simulation);
+
+    let densities = simulation.download_fluid_densities(wgpu_context);
+    assert_eq!(densities.len(), 16);
+    // Every particle counts itself, and the packed block is above the rest density so it has a pressure
+    assert!(densities.iter().all(|density_pressure| density_pressure.x > 0.0));
+    assert!(densities.iter().any(|density_pressure| density_pressure.y > 0.0));
+}
+
+#[test]
+fn pressure_pushes_compressed_particles_apart_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let start = [Vec2::new(100.0, 100.0), Vec2::new(101.0, 100.0)];
+    let mut simulation = create_simulation(wgpu_context, start.to_vec());
+    simulation.set_mode(wgpu_context, SimulationMode::Fluid(FluidConfig { viscosity: 0.0, ..FluidConfig::default() }));
+
+    step(wgpu_context, &mut simulation);
+    let positions = step(wgpu_context, &mut simulation);
+    let distance = (positions[0] - positions[1]).length();
+    assert!(distance > 1.0, "the particles should move apart, distance {}", distance);
+    // The pressure is symmetric, so the pair does not drift
+    assert!(((positions[0] + positions[1]) * 0.5 - (start[0] + start[1]) * 0.5).length() < 1e-3);
+}
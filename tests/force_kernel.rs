@@ -0,0 +1,104 @@
+mod common;
+
+use glam::{Vec2, Vec4};
+use game_engine::physics::force_kernel::ForceKernelDescriptor;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::Simulation;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const DELTA_TIME: f32 = 0.1;
+
+const CONSTANT_FORCE: &str = r#"
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn constant_force(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= force_params.num_particles {
+        return;
+    }
+    apply_acceleration(index, force_params.params.xy);
+}
+"#;
+
+/// Gives every particle the velocity stored in its channel, then counts the steps in a second channel.
+const CHANNEL_VELOCITY: &str = r#"
+override VELOCITY_CHANNEL: u32;
+override STEPS_CHANNEL: u32;
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn channel_velocity(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= force_params.num_particles || !has_channel(STEPS_CHANNEL) {
+        return;
+    }
+    set_particle_velocity(index, vec2<f32>(channel_f32(index, VELOCITY_CHANNEL, 0u), channel_f32(index, VELOCITY_CHANNEL, 1u)));
+    set_channel_u32(index, STEPS_CHANNEL, 0u, channel_u32(index, STEPS_CHANNEL, 0u) + 1u);
+}
+"#;
+
+fn positions(wgpu_context: &WgpuContext, simulation: &mut Simulation) -> Vec<Vec2> {
+    simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().to_vec()
+}
+
+#[test]
+fn constant_force_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = common::create_test_simulation(wgpu_context, vec![Vec2::new(500.0, 500.0)], 2.0);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    let id = simulation.add_force_kernel(wgpu_context, &ForceKernelDescriptor::new("Constant force", CONSTANT_FORCE, "constant_force"));
+    simulation.force_kernel_mut(id).set_params(Vec4::new(0.0, -100.0, 0.0, 0.0));
+
+    // Same integration as the built-in forces: x += v + a * dt^2
+    simulation.step(wgpu_context, &mut gpu_profiler, DELTA_TIME, None);
+    gpu_profiler.end_frame().unwrap();
+    assert!((positions(wgpu_context, &mut simulation)[0] - Vec2::new(500.0, 499.0)).length() < 1e-3);
+    simulation.step(wgpu_context, &mut gpu_profiler, DELTA_TIME, None);
+    gpu_profiler.end_frame().unwrap();
+    assert!((positions(wgpu_context, &mut simulation)[0] - Vec2::new(500.0, 497.0)).length() < 1e-3);
+
+    // Disabled kernels are skipped, the particle keeps its velocity
+    simulation.force_kernel_mut(id).set_enabled(false);
+    simulation.step(wgpu_context, &mut gpu_profiler, DELTA_TIME, None);
+    gpu_profiler.end_frame().unwrap();
+    assert!((positions(wgpu_context, &mut simulation)[0] - Vec2::new(500.0, 495.0)).length() < 1e-3);
+}
+
+#[test]
+fn channels_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = common::create_test_simulation(wgpu_context, vec![Vec2::new(300.0, 300.0), Vec2::new(600.0, 600.0)], 2.0);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    let velocity = simulation.register_channel(wgpu_context, "velocity", &[0.0f32.to_bits(), 0.0f32.to_bits()]);
+    let descriptor = ForceKernelDescriptor::new("Channel velocity", CHANNEL_VELOCITY, "channel_velocity")
+        .with_constant("VELOCITY_CHANNEL", velocity.0 as f64)
+        .with_constant("STEPS_CHANNEL", 1.0);
+    simulation.add_force_kernel(wgpu_context, &descriptor);
+
+    // The steps channel does not exist yet: the kernel does nothing
+    simulation.step(wgpu_context, &mut gpu_profiler, DELTA_TIME, None);
+    gpu_profiler.end_frame().unwrap();
+    assert_eq!(positions(wgpu_context, &mut simulation), vec![Vec2::new(300.0, 300.0), Vec2::new(600.0, 600.0)]);
+
+    // Registered after the kernel, which is rebound
+    let steps = simulation.register_channel(wgpu_context, "steps", &[0]);
+    assert_eq!(steps.0, 1);
+    let velocities: Vec<u32> = [10.0f32, 0.0, 0.0, -20.0].iter().map(|value| value.to_bits()).collect();
+    simulation.write_channel(wgpu_context, velocity, &velocities);
+    for _ in 0..2 {
+        simulation.step(wgpu_context, &mut gpu_profiler, DELTA_TIME, None);
+        gpu_profiler.end_frame().unwrap();
+    }
+
+    let positions = positions(wgpu_context, &mut simulation);
+    assert!((positions[0] - Vec2::new(302.0, 300.0)).length() < 1e-3);
+    assert!((positions[1] - Vec2::new(600.0, 596.0)).length() < 1e-3);
+
+    let extras = simulation.particles_mut().download_extras(wgpu_context);
+    let stride = simulation.particles().channels().stride() as usize;
+    let offset = simulation.particles().channels().offset(steps) as usize;
+    assert_eq!(extras[offset], 2);
+    assert_eq!(extras[stride + offset], 2);
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::grid::grid::Grid;
+use game_engine::physics::collision_system::CollisionSystem;
+use game_engine::physics::frame_capture::{capture_physics_step, FrameCapture};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+fn gen_particles() -> Vec<Vec2> {
+    let mut positions = Vec::new();
+    for y in 0..20 {
+        for x in 0..20 {
+            positions.push(Vec2::new(15.0 + x as f32 * 9.0, 15.0 + y as f32 * 9.5));
+        }
+    }
+    positions
+}
+
+#[test]
+fn capture_save_and_replay_kernels_test() {
+    // SETUP
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let max_radius = 5.0;
+
+    let positions = gen_particles();
+    let radii = vec![max_radius; positions.len()];
+    let mut particles = common::create_test_particle_system(wgpu_context, positions.clone(), radii.clone());
+    let mut grid = Grid::new_without_camera(wgpu_context, max_radius, &particles);
+    let mut collision_system = CollisionSystem::new(wgpu_context, 2, &particles, &grid);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    // CAPTURE
+    let capture = capture_physics_step(wgpu_context, &mut particles, &mut grid, &mut collision_system, &[], &mut gpu_profiler, 0.016);
+    let dir = std::env::temp_dir().join(format!("frame_capture_test_{}", std::process::id()));
+    capture.save(&dir).unwrap();
+    let loaded = FrameCapture::load(&dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(loaded.metadata::<usize>("num_particles"), Some(positions.len()));
+    assert_eq!(loaded.metadata::<f32>("cell_size"), Some(grid.cell_size()));
+    assert_eq!(loaded.get::<Vec2>("positions_before_solve").unwrap(), positions);
+    for name in capture.buffer_names() {
+        assert_eq!(loaded.get::<u32>(name), capture.get::<u32>(name), "Buffer {} changed on disk", name);
+    }
+
+    // REPLAY: build cell ids from the captured inputs
+    let replay_positions: Vec<Vec2> = loaded.get("positions_before_solve").unwrap();
+    let replay_radii: Vec<f32> = loaded.get("radii").unwrap();
+    let replay_particles = common::create_test_particle_system(wgpu_context, replay_positions, replay_radii);
+    let mut replay_grid = Grid::new_without_camera(wgpu_context, loaded.metadata("max_radius").unwrap(), &replay_particles);
+
+    let mut encoder = wgpu_context.get_device().create_command_encoder(
+        &wgpu::CommandEncoderDescriptor { label: Some("Replay build cell ids") }
+    );
+    replay_grid.build_cell_ids(&mut encoder);
+    wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+    let expected_cell_ids: Vec<u32> = loaded.get("cell_ids_unsorted").unwrap();
+    let expected_object_ids: Vec<u32> = loaded.get("object_ids_unsorted").unwrap();
+    assert_eq!(replay_grid.download_cell_ids(wgpu_context).unwrap(), expected_cell_ids);
+
+    // REPLAY: sort the captured unsorted map in isolation
+    replay_grid.load_cell_ids(wgpu_context, &expected_cell_ids, &expected_object_ids);
+    let mut encoder = wgpu_context.get_device().create_command_encoder(
+        &wgpu::CommandEncoderDescriptor { label: Some("Replay sort map") }
+    );
+    replay_grid.sort_map(&mut encoder);
+    wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+    assert_eq!(replay_grid.download_cell_ids(wgpu_context).unwrap(), loaded.get::<u32>("cell_ids_sorted").unwrap());
+
+    // REPLAY: collision cells from the captured sorted map
+    let sorted_cell_ids: Vec<u32> = loaded.get("cell_ids_sorted").unwrap();
+    let sorted_object_ids: Vec<u32> = loaded.get("object_ids_sorted").unwrap();
+    replay_grid.load_cell_ids(wgpu_context, &sorted_cell_ids, &sorted_object_ids);
+    let mut replay_collision_system = CollisionSystem::new(wgpu_context, 2, &replay_particles, &replay_grid);
+    let encoder = wgpu_context.get_device().create_command_encoder(
+        &wgpu::CommandEncoderDescriptor { label: Some("Replay collision cells") }
+    );
+    replay_collision_system.build_collision_cells(wgpu_context, encoder, &mut gpu_profiler);
+    assert_eq!(replay_collision_system.download_collision_cells(wgpu_context), loaded.get::<u32>("collision_cells").unwrap());
+}
@@ -0,0 +1,34 @@
+mod common;
+
+use game_engine::physics::frame_graph::FrameGraph;
+use game_engine::physics::pass_validation::PhysicsPass;
+use game_engine::utils::gpu_buffer::GpuBuffer;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+#[test]
+fn frame_graph_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    let first = GpuBuffer::new(wgpu_context, vec![7u32; 4], wgpu::BufferUsages::STORAGE);
+    let second = GpuBuffer::new(wgpu_context, vec![7u32; 4], wgpu::BufferUsages::STORAGE);
+
+    let mut frame_graph = FrameGraph::new(wgpu_context, "Frame graph test encoder");
+    frame_graph.add_pass(PhysicsPass::BuildCellIds, &gpu_profiler, |encoder, _| encoder.clear_buffer(first.buffer(), 0, None));
+    frame_graph.add_pass(PhysicsPass::SortMap, &gpu_profiler, |_, _| {});
+    assert_eq!(frame_graph.passes(), &[PhysicsPass::BuildCellIds, PhysicsPass::SortMap]);
+
+    // Nothing runs until the graph is flushed or submitted
+    assert_eq!(first.read_back(wgpu_context).unwrap(), vec![7; 4]);
+    frame_graph.flush(wgpu_context, &mut gpu_profiler);
+    assert_eq!(frame_graph.submissions(), 1);
+    assert_eq!(first.read_back(wgpu_context).unwrap(), vec![0; 4]);
+
+    // Recording goes on after a flush
+    frame_graph.add_pass(PhysicsPass::Integration, &gpu_profiler, |encoder, _| encoder.clear_buffer(second.buffer(), 0, None));
+    assert_eq!(frame_graph.passes().len(), 3);
+    assert_eq!(second.read_back(wgpu_context).unwrap(), vec![7; 4]);
+    frame_graph.submit(wgpu_context, &mut gpu_profiler);
+    assert_eq!(second.read_back(wgpu_context).unwrap(), vec![0; 4]);
+    gpu_profiler.end_frame().unwrap();
+}
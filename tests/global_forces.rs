@@ -0,0 +1,80 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::physics::forces::GlobalForces;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::Simulation;
+use game_engine::simulation_config::{PhysicalUnits, SimulationConfig, EARTH_GRAVITY};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const DELTA_TIME: f32 = 0.1;
+const START: Vec2 = Vec2::new(500.0, 500.0);
+
+fn create_simulation(wgpu_context: &WgpuContext) -> Simulation {
+    common::create_test_simulation(wgpu_context, vec![START], 2.0)
+}
+
+/// Steps once and returns the position of the particle.
+fn step(wgpu_context: &WgpuContext, simulation: &mut Simulation) -> Vec2 {
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    simulation.step(wgpu_context, &mut gpu_profiler, DELTA_TIME, None);
+    gpu_profiler.end_frame().unwrap();
+    simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data()[0]
+}
+
+fn assert_close(actual: Vec2, expected: Vec2) {
+    assert!((actual - expected).length() < 1e-3, "expected {:?}, got {:?}", expected, actual);
+}
+
+#[test]
+fn wind_adds_to_the_gravity_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = create_simulation(wgpu_context);
+    simulation.particles_mut().set_forces(GlobalForces { gravity: Vec2::new(0.0, -100.0), wind: Vec2::new(100.0, 0.0), linear_drag: 0.0 });
+
+    // From rest, the first step moves the particle by a * dt^2
+    assert_close(step(wgpu_context, &mut simulation), START + Vec2::new(1.0, -1.0));
+}
+
+#[test]
+fn linear_drag_slows_the_particles_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = create_simulation(wgpu_context);
+    simulation.particles_mut().set_gravity(Vec2::new(1000.0, 0.0));
+    let moved = step(wgpu_context, &mut simulation);
+    assert_close(moved, START + Vec2::new(10.0, 0.0));
+
+    // Half of the velocity is lost in the step: 5 per second for a tenth of a second
+    simulation.particles_mut().set_forces(GlobalForces { linear_drag: 5.0, ..GlobalForces::default() });
+    assert_close(step(wgpu_context, &mut simulation), moved + Vec2::new(5.0, 0.0));
+}
+
+#[test]
+fn simulation_setters_keep_the_config_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = create_simulation(wgpu_context);
+    simulation.set_config(SimulationConfig { units: PhysicalUnits::new(0.5), ..SimulationConfig::default() });
+
+    simulation.set_gravity(EARTH_GRAVITY);
+    simulation.set_wind(Vec2::new(1.0, 0.0));
+    simulation.set_linear_drag(0.25);
+    assert_eq!(simulation.gravity(), EARTH_GRAVITY);
+    assert_eq!(simulation.config().units, PhysicalUnits::new(0.5));
+    assert_eq!(simulation.particles().forces(), GlobalForces {
+        gravity: Vec2::new(0.0, -19.62),
+        wind: Vec2::new(2.0, 0.0),
+        linear_drag: 0.25,
+    });
+}
+
+#[test]
+fn forces_config_test() {
+    let config = SimulationConfig::from_toml_str("wind = [1.5, 0.0]\nlinear_drag = 0.1\n").unwrap();
+    assert_eq!(config.wind, Vec2::new(1.5, 0.0));
+    assert_eq!(config.linear_drag, 0.1);
+
+    assert!(SimulationConfig::builder().linear_drag(-1.0).build().is_err());
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use game_engine::utils::gpu_buffer::GpuBuffer;
+
+#[test]
+fn write_range_only_touches_the_range_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut buffer = GpuBuffer::new(wgpu_context, (0u32..100).collect(), wgpu::BufferUsages::STORAGE);
+
+    buffer.write_range(10, &[1000, 1001, 1002], wgpu_context);
+    assert_eq!(&buffer.data()[9..14], &[9, 1000, 1001, 1002, 13]);
+
+    let gpu_data = buffer.read_back(wgpu_context).unwrap();
+    let expected: Vec<u32> = (0..100).map(|i| if (10..13).contains(&i) { 1000 + i - 10 } else { i }).collect();
+    assert_eq!(gpu_data, expected);
+}
+
+#[test]
+fn download_range_reads_only_the_range_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut buffer = GpuBuffer::new(wgpu_context, (0u32..100).collect(), wgpu::BufferUsages::STORAGE);
+
+    // Changed on the GPU only, e.g. by a kernel
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    encoder.clear_buffer(buffer.buffer(), 0, None);
+    wgpu_context.get_queue().submit(Some(encoder.finish()));
+
+    assert_eq!(buffer.download_range(wgpu_context, 40..45).unwrap(), &[0; 5]);
+    // The rest of the CPU copy is untouched
+    assert_eq!(buffer.data()[39], 39);
+    assert_eq!(buffer.data()[45], 45);
+    assert_eq!(buffer.download_range(wgpu_context, 50..50).unwrap(), &[] as &[u32]);
+}
+
+#[test]
+fn download_range_of_unaligned_elements_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let bytes: Vec<u8> = (0..64).collect();
+    let mut buffer = GpuBuffer::new(wgpu_context, bytes, wgpu::BufferUsages::STORAGE);
+
+    assert_eq!(buffer.download_range(wgpu_context, 5..11).unwrap(), &[5, 6, 7, 8, 9, 10]);
+    assert_eq!(buffer.download_range(wgpu_context, 61..64).unwrap(), &[61, 62, 63]);
+}
+
+#[test]
+fn read_back_async_resolves_natively_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let buffer = GpuBuffer::new(wgpu_context, (0u32..100).collect(), wgpu::BufferUsages::STORAGE);
+
+    // The future polls the device without blocking until the map callback wakes it
+    let gpu_data = pollster::block_on(buffer.read_back_async(wgpu_context)).unwrap();
+    assert_eq!(gpu_data, (0u32..100).collect::<Vec<u32>>());
+}
@@ -0,0 +1,56 @@
+use std::time::Duration;
+use glam::Vec2;
+use game_engine::renderer::stroke_font::{glyph_segments, text_strokes, text_width, GLYPH_SPACING, GLYPH_WIDTH};
+use game_engine::utils::gpu_timings::{GpuTimings, ScopeAverage, ScopeTime};
+
+fn scope(label: &str, depth: u32, micros: u64) -> ScopeTime {
+    ScopeTime { label: label.to_string(), depth, time: Duration::from_micros(micros) }
+}
+
+#[test]
+fn averages_follow_the_last_frames_test() {
+    let mut timings = GpuTimings::new(2);
+    timings.record_frame(vec![scope("Sort", 0, 900), scope("Integration", 0, 100)]);
+    // Two steps in a frame add up
+    timings.record_frame(vec![scope("Build cell ids", 0, 50), scope("Integration", 0, 100), scope("Integration", 0, 200)]);
+    assert_eq!(timings.num_frames(), 2);
+    assert_eq!(timings.averages(), vec![
+        ScopeAverage { label: "Build cell ids".to_string(), depth: 0, average: Duration::from_micros(50), frames: 1 },
+        ScopeAverage { label: "Integration".to_string(), depth: 0, average: Duration::from_micros(200), frames: 2 },
+        ScopeAverage { label: "Sort".to_string(), depth: 0, average: Duration::from_micros(900), frames: 1 },
+    ]);
+    assert_eq!(timings.frame_average(), Duration::from_micros(675));
+
+    // The first frame leaves the window
+    timings.record_frame(vec![scope("Integration", 0, 400), scope("Solve", 1, 300)]);
+    let labels: Vec<String> = timings.averages().into_iter().map(|average| average.label).collect();
+    assert_eq!(labels, vec!["Integration", "Solve", "Build cell ids"]);
+    // Nested scopes are already counted in their parent
+    assert_eq!(timings.frame_average(), Duration::from_micros(375));
+
+    timings.clear();
+    assert!(timings.averages().is_empty());
+    assert_eq!(timings.frame_average(), Duration::ZERO);
+}
+
+#[test]
+fn text_strokes_test() {
+    assert_eq!(glyph_segments('a'), glyph_segments('A'));
+    assert_eq!(glyph_segments('I'), Some(0b01001000001001));
+    assert_eq!(glyph_segments('?'), None);
+
+    // I: top, bottom and the two center verticals
+    let strokes = text_strokes("I", Vec2::new(10.0, 20.0), 10.0);
+    assert_eq!(strokes.len(), 4);
+    assert!(strokes.contains(&(Vec2::new(10.0, 30.0), Vec2::new(16.0, 30.0))));
+    assert!(strokes.contains(&(Vec2::new(13.0, 25.0), Vec2::new(13.0, 20.0))));
+
+    // Blanks take their place
+    let strokes = text_strokes(" 1", Vec2::ZERO, 10.0);
+    assert_eq!(strokes.len(), 2);
+    assert!(strokes.iter().all(|(start, end)| start.x >= 9.0 && end.x >= 9.0));
+    assert_eq!(text_strokes("1.5", Vec2::ZERO, 10.0).len(), 2 + 1 + 6);
+
+    assert_eq!(text_width("", 10.0), 0.0);
+    assert!((text_width("AB", 10.0) - (2.0 * GLYPH_WIDTH + GLYPH_SPACING) * 10.0).abs() < 1e-4);
+}
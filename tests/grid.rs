@@ -1,8 +1,9 @@
 mod common;
 
 use game_engine::grid::grid::Grid;
+use game_engine::grid::cell_occupancy_query::CellOccupancyQuery;
 use glam::Vec2;
-use wgpu_profiler::{GpuProfiler, GpuProfilerSettings};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
 use game_engine::particles::particle_system::ParticleSystem;
 use game_engine::physics::collision_system::CollisionSystem;
 use game_engine::renderer::wgpu_context::WgpuContext;
@@ -102,6 +103,30 @@ fn unsplit_by_bits(n: u32) -> u32 {
 
 
 
+#[test]
+fn test_cell_occupancy_query() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let (mut grid, _particles) = build_case_1(wgpu_context);
+
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    let mut encoder = wgpu_context.get_device().create_command_encoder(
+        &wgpu::CommandEncoderDescriptor { label: Some("Cell occupancy test encoder") }
+    );
+    grid.update(&mut encoder, &mut gpu_profiler);
+    wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+
+    let mut query = CellOccupancyQuery::new(wgpu_context, &grid);
+    // (cell, objects touching it)
+    for (cell_id, expected) in [(morton_encode(1, 1), 1), (morton_encode(0, 0), 1), (morton_encode(3, 3), 1), (morton_encode(5, 5), 0)] {
+        query.request(wgpu_context, &grid, cell_id);
+        wgpu_context.get_device().poll(wgpu::wgt::PollType::Wait).unwrap();
+        let occupancy = query.poll(wgpu_context).unwrap();
+        assert_eq!(occupancy.cell_id, cell_id);
+        assert_eq!(occupancy.objects, expected, "cell id {}", cell_id);
+    }
+}
+
 fn build_case_1(wgpu_context: &WgpuContext) -> (Grid, ParticleSystem) {
     // ARRANGE
     let max_radius = 10.0; // This implicitly sets cell_size to 22.0
@@ -289,4 +314,74 @@ pub fn test_grid_build_cell_ids_sort_and_build_collision_cells_list(){
 
     assert_eq!(actual_collision_cells, expected_collision_cells);
 
-}
\ No newline at end of file
+}
+#[test]
+pub fn test_collision_cell_colors_are_exclusive(){
+    // SETUP
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    // Dense block of particles touching many cells, including the negative side of the origin
+    let mut positions = gen_case_2_particles();
+    for y in 0..40 {
+        for x in 0..40 {
+            positions.push(Vec2::new(x as f32 * 7.3 - 5.0, y as f32 * 6.1 - 5.0));
+        }
+    }
+    let (mut grid, particles) = build_case_2(wgpu_context, positions);
+
+    let mut collision_system = CollisionSystem::new(wgpu_context, 2, &particles, &grid);
+    collision_system.set_color_validation(wgpu_context, &particles, &grid, true);
+
+    let mut encoder = wgpu_context.get_device().create_command_encoder(
+        &wgpu::CommandEncoderDescriptor { label: Some("Color validation Test Encoder") }
+    );
+
+    grid.build_cell_ids(&mut encoder);
+    grid.sort_map(&mut encoder);
+    collision_system.solve_collisions(wgpu_context, encoder, &mut GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap());
+
+    // The counters are read back asynchronously
+    wgpu_context.get_device().poll(wgpu::wgt::PollType::Wait).unwrap();
+    assert!(collision_system.poll_color_validation(wgpu_context));
+    assert_eq!(collision_system.color_violations(), [0; 4]);
+    assert_eq!(collision_system.neighbor_color_violations(), [0; 4]);
+
+    // No cell id may alias the unused marker
+    let cell_ids = grid.download_cell_ids(wgpu_context).unwrap();
+    let used_slots = cell_ids.iter().filter(|&&cell_id| cell_id != UNUSED_CELL_ID).count();
+    assert!(used_slots >= particles.len());
+}
+
+#[test]
+fn grid_drawer_is_rebuilt_only_when_the_cells_change_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let camera = &setup.camera;
+    let world_size = Vec2::new(1920.0, 1080.0);
+
+    let mut particles = common::create_test_particle_system(wgpu_context, vec![Vec2::new(100.0, 100.0)], vec![2.0]);
+    let mut grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    assert!(grid.refresh_drawer(wgpu_context, camera, world_size));
+    assert!(!grid.refresh_drawer(wgpu_context, camera, world_size));
+
+    // Particles no larger than the largest one keep the cell size
+    particles.set_spawn_radius_range(1.0, 2.0);
+    particles.add_particles_at(&[Vec2::new(300.0, 300.0), Vec2::new(400.0, 300.0)], wgpu_context);
+    grid.refresh_grid(wgpu_context, &particles);
+    assert!(!grid.refresh_drawer(wgpu_context, camera, world_size));
+
+    // A larger particle grows the cells
+    let cell_size = grid.cell_size();
+    particles.set_spawn_radius_range(8.0, 8.0);
+    particles.add_particles_at(&[Vec2::new(500.0, 300.0)], wgpu_context);
+    grid.refresh_grid(wgpu_context, &particles);
+    assert_ne!(grid.cell_size(), cell_size);
+    assert!(grid.refresh_drawer(wgpu_context, camera, world_size));
+
+    // So do a new world size and origin
+    assert!(grid.refresh_drawer(wgpu_context, camera, world_size * 2.0));
+    grid.set_origin(Vec2::new(-10.0, 0.0));
+    assert!(grid.refresh_drawer(wgpu_context, camera, world_size * 2.0));
+    assert!(!grid.refresh_drawer(wgpu_context, camera, world_size * 2.0));
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use game_engine::grid::grid::Grid;
+use game_engine::grid::morton;
+use game_engine::particles::particle_volume::ParticleVolume;
+use glam::{UVec3, Vec3};
+
+const UNUSED_CELL_ID: u32 = 0xffffffff;
+
+#[test]
+fn test_grid_3d_build_cell_ids() {
+    // SETUP
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    let radius = 4.0;
+    let cell_size = Grid::compute_cell_size(radius);
+    let volume = ParticleVolume::new(
+        wgpu_context,
+        &[
+            // Right next to the corner shared by cells (1..=2, 1..=2, 1..=2)
+            Vec3::splat(2.0 * cell_size + 0.01),
+            // Centered in cell (3, 1, 2)
+            Vec3::new(3.5, 1.5, 2.5) * cell_size,
+        ],
+        vec![radius, radius],
+    );
+    let mut grid = Grid::new_3d(wgpu_context, volume.get_max_radius(), &volume);
+    assert_eq!(grid.dim(), 3);
+    assert_eq!(grid.max_cells_per_object(), 8);
+
+    // ACT
+    let mut encoder = wgpu_context.get_device().create_command_encoder(
+        &wgpu::CommandEncoderDescriptor { label: Some("3D Grid Test Encoder") }
+    );
+    grid.build_cell_ids(&mut encoder);
+    wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+
+    // ASSERT
+    let cell_ids = grid.download_cell_ids(wgpu_context).unwrap();
+    let object_ids = grid.download_object_ids(wgpu_context).unwrap();
+
+    let mut corner_cells = cell_ids[0..8].to_vec();
+    corner_cells.sort();
+    let mut expected_corner_cells: Vec<u32> = (0..8u32)
+        .map(|i| morton::encode_3d(UVec3::new(1 + (i & 1), 1 + ((i >> 1) & 1), 1 + ((i >> 2) & 1))))
+        .collect();
+    expected_corner_cells.sort();
+    assert_eq!(corner_cells, expected_corner_cells);
+    // The home cell comes first
+    assert_eq!(cell_ids[0], morton::encode_3d(UVec3::splat(2)));
+    assert_eq!(object_ids[0..8], [0; 8]);
+
+    assert_eq!(cell_ids[8], morton::encode_3d(UVec3::new(3, 1, 2)));
+    assert_eq!(object_ids[8], 1);
+    assert!(cell_ids[9..16].iter().all(|&cell_id| cell_id == UNUSED_CELL_ID));
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::particles::particle_interaction::InteractionMode;
+use game_engine::physics::heat_diffusion::{HeatBrush, HeatConfig};
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::Simulation;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const DELTA_TIME: f32 = 0.1;
+/// Only the exchange between touching particles: no cooling, no buoyancy.
+const CONDUCTION_ONLY: HeatConfig = HeatConfig { conductivity: 1.0, cooling: 0.0, ambient_temperature: 0.0, buoyancy: 0.0 };
+
+/// Particles of radius 2 at rest, without gravity.
+fn create_simulation(wgpu_context: &WgpuContext, positions: Vec<Vec2>) -> Simulation {
+    let mut simulation = common::create_test_simulation(wgpu_context, positions, 2.0);
+    simulation.particles_mut().set_gravity(Vec2::ZERO);
+    simulation
+}
+
+fn step(wgpu_context: &WgpuContext, simulation: &mut Simulation) {
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    simulation.step(wgpu_context, &mut gpu_profiler, DELTA_TIME, None);
+    gpu_profiler.end_frame().unwrap();
+}
+
+/// Positions and temperatures of the particles, sorted along x.
+fn download_particles(wgpu_context: &WgpuContext, simulation: &mut Simulation) -> Vec<(Vec2, f32)> {
+    let temperatures = simulation.download_temperatures(wgpu_context);
+    let positions = simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().to_vec();
+    let mut particles: Vec<(Vec2, f32)> = positions.into_iter().zip(temperatures).collect();
+    particles.sort_by(|a, b| a.0.x.total_cmp(&b.0.x));
+    particles
+}
+
+#[test]
+fn enable_heat_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = create_simulation(wgpu_context, vec![Vec2::new(100.0, 100.0), Vec2::new(200.0, 100.0)]);
+    assert_eq!(simulation.heat_config(), None);
+    assert!(simulation.download_temperatures(wgpu_context).is_empty());
+
+    let config = HeatConfig { ambient_temperature: 20.0, ..HeatConfig::default() };
+    let id = simulation.enable_heat(wgpu_context, config);
+    assert_eq!(simulation.heat_config(), Some(config));
+    assert_eq!(simulation.download_temperatures(wgpu_context), vec![20.0, 20.0]);
+
+    // Enabled again, only the config changes
+    assert_eq!(simulation.enable_heat(wgpu_context, CONDUCTION_ONLY), id);
+    assert_eq!(simulation.heat_config(), Some(CONDUCTION_ONLY));
+    assert_eq!(simulation.download_temperatures(wgpu_context), vec![20.0, 20.0]);
+
+    simulation.disable_heat();
+    assert_eq!(simulation.heat_config(), None);
+    assert_eq!(simulation.download_temperatures(wgpu_context).len(), 2);
+}
+
+#[test]
+fn heat_flows_between_touching_particles_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    // A touching pair, and a hot particle far from it
+    let mut simulation = create_simulation(wgpu_context, vec![Vec2::new(100.0, 500.0), Vec2::new(102.0, 500.0), Vec2::new(500.0, 500.0)]);
+    simulation.enable_heat(wgpu_context, CONDUCTION_ONLY);
+    simulation.set_temperature(wgpu_context, 0..1, 100.0);
+    simulation.set_temperature(wgpu_context, 2..3, 100.0);
+
+    step(wgpu_context, &mut simulation);
+    let particles = download_particles(wgpu_context, &mut simulation);
+    // conductivity * delta time = 0.1 of the difference
+    assert!((particles[0].1 - 90.0).abs() < 1e-3, "{particles:?}");
+    assert!((particles[1].1 - 10.0).abs() < 1e-3, "{particles:?}");
+    assert_eq!(particles[2].1, 100.0);
+}
+
+#[test]
+fn warm_particles_rise_and_cool_down_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = create_simulation(wgpu_context, vec![Vec2::new(100.0, 500.0), Vec2::new(300.0, 500.0)]);
+    simulation.enable_heat(wgpu_context, HeatConfig { conductivity: 0.0, cooling: 1.0, ambient_temperature: 0.0, buoyancy: 2.0 });
+    simulation.set_temperature(wgpu_context, 0..1, 10.0);
+    simulation.set_temperature(wgpu_context, 1..2, -10.0);
+
+    step(wgpu_context, &mut simulation);
+    let particles = download_particles(wgpu_context, &mut simulation);
+    // Up without gravity: 2 * 9 degrees * delta time^2 after cooling by a tenth
+    assert!((particles[0].0 - Vec2::new(100.0, 500.18)).length() < 1e-3, "{particles:?}");
+    assert!((particles[0].1 - 9.0).abs() < 1e-3, "{particles:?}");
+    // Colder than the ambient temperature, it sinks
+    assert!((particles[1].0 - Vec2::new(300.0, 499.82)).length() < 1e-3, "{particles:?}");
+    assert!((particles[1].1 + 9.0).abs() < 1e-3, "{particles:?}");
+}
+
+#[test]
+fn brushes_heat_the_particles_under_them_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = create_simulation(wgpu_context, vec![Vec2::new(100.0, 500.0), Vec2::new(300.0, 500.0)]);
+    simulation.enable_heat(wgpu_context, CONDUCTION_ONLY);
+    simulation.set_heat_brush(Some(HeatBrush { position: Vec2::new(100.0, 500.0), radius: 10.0, rate: 50.0 }));
+
+    step(wgpu_context, &mut simulation);
+    let particles = download_particles(wgpu_context, &mut simulation);
+    assert!((particles[0].1 - 5.0).abs() < 1e-3, "{particles:?}");
+    assert_eq!(particles[1].1, 0.0);
+
+    // The mouse interaction takes over while it is active
+    simulation.particles_mut().interaction_mut().mode = InteractionMode::Heat;
+    simulation.particles_mut().interaction_mut().set_radius(10.0);
+    simulation.particles_mut().interaction_mut().set_strength(100.0);
+    simulation.particles_mut().mouse_click_callback(true, Vec2::new(300.0, 500.0));
+    step(wgpu_context, &mut simulation);
+    let particles = download_particles(wgpu_context, &mut simulation);
+    assert!((particles[0].1 - 5.0).abs() < 1e-3, "{particles:?}");
+    assert!((particles[1].1 - 10.0).abs() < 1e-3, "{particles:?}");
+}
+
+#[test]
+fn spawned_particles_get_the_spawn_temperature_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = create_simulation(wgpu_context, vec![Vec2::new(100.0, 500.0)]);
+    simulation.set_spawn_temperature(wgpu_context, 42.0);
+    assert_eq!(simulation.heat_config(), Some(HeatConfig::default()));
+
+    simulation.add_particles_at(wgpu_context, &[Vec2::new(300.0, 500.0)]);
+    assert_eq!(simulation.download_temperatures(wgpu_context), vec![0.0, 42.0]);
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::particles::heightmap::{Heightmap, HeightmapMode, HeightmapSettings};
+use game_engine::utils::png;
+
+const WORLD_SIZE: Vec2 = Vec2::new(100.0, 50.0);
+
+fn settings(mode: HeightmapMode) -> HeightmapSettings {
+    HeightmapSettings { width: 10, mode }
+}
+
+#[test]
+fn occupancy_test() {
+    let heightmap = Heightmap::from_particles(&[Vec2::new(15.0, 5.0), Vec2::new(95.0, 45.0)], &[2.0, 10.0], WORLD_SIZE, settings(HeightmapMode::Occupancy));
+
+    // 10x10 world units per pixel, row 0 is the top of the world
+    assert_eq!((heightmap.width(), heightmap.height()), (10, 5));
+    assert!((heightmap.value(1, 4) - std::f32::consts::PI * 4.0 / 100.0).abs() < 1e-5);
+    // Bigger than the pixel, capped
+    assert_eq!(heightmap.value(9, 0), 1.0);
+    assert_eq!(heightmap.values().iter().filter(|&&value| value > 0.0).count(), 2);
+}
+
+#[test]
+fn height_test() {
+    let heightmap = Heightmap::from_particles(&[Vec2::new(15.0, 5.0), Vec2::new(55.0, 25.0)], &[2.0, 5.0], WORLD_SIZE, settings(HeightmapMode::Height));
+
+    assert_eq!(heightmap.column_heights(), &[0.0, 7.0, 0.0, 0.0, 0.0, 30.0, 30.0, 0.0, 0.0, 0.0]);
+    let column = |x: u32| (0..heightmap.height()).map(|y| heightmap.value(x, y)).collect::<Vec<_>>();
+    assert_eq!(column(0), vec![0.0; 5]);
+    assert_eq!(column(1), vec![0.0, 0.0, 0.0, 0.0, 1.0]);
+    assert_eq!(column(5), vec![0.0, 0.0, 1.0, 1.0, 1.0]);
+    assert_eq!(column(6), column(5));
+}
+
+#[test]
+fn png_encoding_test() {
+    let heightmap = Heightmap::from_particles(&[Vec2::new(15.0, 5.0)], &[2.0], WORLD_SIZE, settings(HeightmapMode::Height));
+    let pixels = heightmap.to_rgba8();
+    assert_eq!(&pixels[..4], &[0, 0, 0, 255]);
+    let filled = ((4 * 10 + 1) * 4) as usize;
+    assert_eq!(&pixels[filled..filled + 4], &[255, 255, 255, 255]);
+
+    let encoded = png::encode_rgba8(10, 5, &pixels);
+    assert_eq!(&encoded[..8], &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n']);
+    assert_eq!(&encoded[12..16], b"IHDR");
+    assert_eq!(&encoded[16..24], &[0, 0, 0, 10, 0, 0, 0, 5]);
+
+    // One stored deflate block holding the 5 scanlines, each prefixed by its filter byte
+    let raw_size = 5 * (10 * 4 + 1);
+    let idat = 8 + 25;
+    assert_eq!(&encoded[idat + 4..idat + 8], b"IDAT");
+    assert_eq!(u32::from_be_bytes(encoded[idat..idat + 4].try_into().unwrap()) as usize, 2 + 5 + raw_size + 4);
+    let first_scanline = idat + 8 + 2 + 5;
+    assert_eq!(encoded[first_scanline], 0);
+    assert_eq!(&encoded[first_scanline + 1..first_scanline + 41], &pixels[..40]);
+
+    // IEND and its well known CRC
+    assert_eq!(&encoded[encoded.len() - 8..], &[b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]);
+}
+
+#[test]
+fn heightmap_from_particle_system_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut particles = common::create_test_particle_system(
+        wgpu_context,
+        vec![Vec2::new(100.0, 3.0), Vec2::new(106.0, 3.0), Vec2::new(103.0, 8.0)],
+        vec![3.0, 3.0, 3.0],
+    );
+
+    let heightmap = Heightmap::from_particle_system(wgpu_context, &mut particles, HeightmapSettings { width: 192, mode: HeightmapMode::Height });
+
+    // Test systems have a 1920x1080 world
+    assert_eq!((heightmap.width(), heightmap.height()), (192, 108));
+    assert_eq!(heightmap.column_heights()[10], 11.0);
+    assert_eq!(heightmap.value(10, 107), 1.0);
+    assert_eq!(heightmap.value(10, 106), 0.0);
+}
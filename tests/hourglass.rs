@@ -0,0 +1,77 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::grid::grid::Grid;
+use game_engine::particles::particle_emitter::ParticleEmitter;
+use game_engine::physics::collision_system::CollisionSystem;
+use game_engine::physics::kill_volumes::KillVolume;
+use game_engine::scenes::hourglass::HourglassScene;
+use game_engine::simulation::{Simulation, DIMENSION};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const FRAME_TIME: f32 = 1.0 / 60.0;
+
+#[test]
+fn emitter_spaces_the_positions_in_rows_test() {
+    let mut emitter = ParticleEmitter::new(Vec2::new(100.0, 50.0), 20.0, 64.0).with_min_batch(7);
+    // 4 particles owed, not enough for a batch
+    assert!(emitter.take_positions(0.0625, 10.0).is_empty());
+
+    let positions = emitter.take_positions(0.0625, 10.0);
+    assert_eq!(positions, vec![
+        Vec2::new(90.0, 50.0), Vec2::new(100.0, 50.0), Vec2::new(110.0, 50.0),
+        Vec2::new(90.0, 60.0), Vec2::new(100.0, 60.0), Vec2::new(110.0, 60.0),
+        Vec2::new(90.0, 70.0), Vec2::new(100.0, 70.0),
+    ]);
+    assert!(emitter.take_positions(0.0, 10.0).is_empty());
+}
+
+#[test]
+fn hourglass_scene_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let positions = vec![Vec2::new(500.0, 500.0), Vec2::new(900.0, 300.0)];
+    let particles = common::create_test_particle_system(wgpu_context, positions, vec![3.0; 2]);
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let collision_system = CollisionSystem::new(wgpu_context, DIMENSION, &particles, &grid);
+    let mut simulation = Simulation::from_parts(wgpu_context, particles, grid, collision_system);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    simulation.add_static_circle(wgpu_context, Vec2::new(200.0, 200.0), 40.0);
+
+    let mut scene = HourglassScene::load(wgpu_context, &mut gpu_profiler, &mut simulation);
+    gpu_profiler.end_frame().unwrap();
+    assert_eq!(simulation.particles().len(), 0);
+    assert!(simulation.static_circles().is_empty());
+    assert_eq!(simulation.static_segments().len(), 4);
+    assert_eq!(simulation.kill_volumes(), &[KillVolume::BelowLine { y: scene.kill_line() }]);
+    scene.emitter_mut().set_rate(600.0);
+
+    // A few seconds: the first particles fall through the neck and reach the kill line
+    for _ in 0..300 {
+        scene.update(wgpu_context, &mut simulation, FRAME_TIME);
+        simulation.advance(wgpu_context, &mut gpu_profiler, FRAME_TIME, None);
+        gpu_profiler.end_frame().unwrap();
+    }
+    let stats = scene.stats(&simulation);
+    assert!(stats.emitted > 1000, "{}", stats);
+    assert!(stats.removed > 0, "no particle reached the kill line: {}", stats);
+    assert_eq!(stats.emitted, stats.alive + stats.removed);
+
+    simulation.remove_dead_particles(wgpu_context, &mut gpu_profiler);
+    gpu_profiler.end_frame().unwrap();
+    // Both upper walls, from the mouth to the neck
+    let segments = simulation.static_segments().to_vec();
+    let (left, right) = (segments[0], segments[2]);
+    let wall_x = |start: Vec2, end: Vec2, y: f32| start.x + (end.x - start.x) * (y - start.y) / (end.y - start.y);
+    let positions = simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().to_vec();
+    assert_eq!(positions.len(), scene.stats(&simulation).alive);
+    for position in positions {
+        assert!(position.is_finite());
+        assert!(position.y >= scene.kill_line(), "{} is below the kill line", position);
+        // Nothing went through the funnel
+        if position.y > left.end.y && position.y < left.start.y {
+            assert!(position.x > wall_x(left.start, left.end, position.y), "{} went through the left wall", position);
+            assert!(position.x < wall_x(right.start, right.end, position.y), "{} went through the right wall", position);
+        }
+    }
+}
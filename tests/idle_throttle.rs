@@ -0,0 +1,46 @@
+use std::time::{Duration, Instant};
+use game_engine::utils::idle_throttle::{IdleThrottle, PowerSavingConfig};
+
+#[test]
+fn throttles_when_unfocused_or_paused_test() {
+    let mut throttle = IdleThrottle::new(PowerSavingConfig::default());
+    assert!(!throttle.is_throttled(false));
+    assert!(throttle.should_step_physics(false));
+
+    assert!(throttle.is_throttled(true));
+    assert!(!throttle.should_step_physics(true));
+
+    throttle.set_focused(false);
+    assert!(throttle.is_throttled(false));
+    assert!(!throttle.should_step_physics(false));
+
+    throttle.set_focused(true);
+    assert!(!throttle.is_throttled(false));
+    assert!(throttle.should_step_physics(false));
+}
+
+#[test]
+fn config_test() {
+    let mut throttle = IdleThrottle::new(PowerSavingConfig { simulate_when_unfocused: true, ..PowerSavingConfig::default() });
+    throttle.set_focused(false);
+    assert!(throttle.is_throttled(false));
+    assert!(throttle.should_step_physics(false));
+
+    throttle.set_config(PowerSavingConfig { enabled: false, ..PowerSavingConfig::default() });
+    assert!(!throttle.is_throttled(false));
+    assert!(!throttle.is_throttled(true));
+    assert!(throttle.should_step_physics(false));
+    // Pausing still stops the physics
+    assert!(!throttle.should_step_physics(true));
+}
+
+#[test]
+fn idle_frame_rate_test() {
+    let mut throttle = IdleThrottle::new(PowerSavingConfig { idle_frame_rate: 4.0, ..PowerSavingConfig::default() });
+    let now = Instant::now();
+    throttle.frame_drawn(now);
+
+    assert_eq!(throttle.next_frame_time(), now + Duration::from_millis(250));
+    assert!(!throttle.is_frame_due(now + Duration::from_millis(100)));
+    assert!(throttle.is_frame_due(now + Duration::from_millis(250)));
+}
@@ -0,0 +1,84 @@
+use std::time::Duration;
+use glam::UVec2;
+use game_engine::utils::input_log::{InputEvent, InputLog, InputSession};
+use game_engine::utils::rng;
+use winit::dpi::PhysicalPosition;
+use winit::event::{MouseButton, MouseScrollDelta};
+use winit::keyboard::KeyCode;
+
+fn example_log() -> InputLog {
+    let mut log = InputLog::new(1234, UVec2::new(1280, 720));
+    log.push_event(InputEvent::CursorMoved(PhysicalPosition::new(640.5, 360.0)));
+    log.push_event(InputEvent::MouseButton { button: MouseButton::Left, pressed: true });
+    log.end_frame(Duration::from_nanos(16_666_667));
+    log.end_frame(Duration::from_millis(20));
+    log.push_event(InputEvent::Key { code: KeyCode::KeyP, pressed: true });
+    log.push_event(InputEvent::Shift { pressed: false });
+    log.push_event(InputEvent::MouseWheel(MouseScrollDelta::LineDelta(0.0, -1.5)));
+    log.push_event(InputEvent::MouseWheel(MouseScrollDelta::PixelDelta(PhysicalPosition::new(0.25, 12.0))));
+    log.push_event(InputEvent::CursorLeft);
+    log.end_frame(Duration::from_nanos(1));
+    log
+}
+
+#[test]
+fn input_log_text_round_trip_test() {
+    let log = example_log();
+    let text = log.to_text();
+    assert!(text.contains("seed 1234\nwindow 1280 720\ncursor 640.5 360\nbutton left down\nframe 16666667\n"), "{text}");
+    assert!(text.contains("key KeyP down\n"), "{text}");
+
+    let parsed = InputLog::parse(&text).unwrap();
+    assert_eq!(parsed, log);
+    assert_eq!(parsed.frames().len(), 3);
+    assert!(parsed.frames()[1].events.is_empty());
+
+    assert!(InputLog::parse("window 10 10\nframe 1").is_err());
+    assert!(InputLog::parse("seed 1\nwindow 10 10\nkey NotAKey down").is_err());
+    assert!(InputLog::parse("seed 1\nwindow 10 10\nbutton left sideways").is_err());
+}
+
+#[test]
+fn input_session_replays_the_logged_frames_test() {
+    let mut session = InputSession::replaying(example_log());
+    assert!(session.is_replaying());
+    assert_eq!(session.replay_events().len(), 2);
+    // The measured frame time is replaced by the logged one
+    assert_eq!(session.end_frame(Duration::from_secs(1)), Duration::from_nanos(16_666_667));
+    assert!(session.replay_events().is_empty());
+    assert_eq!(session.end_frame(Duration::from_secs(1)), Duration::from_millis(20));
+    assert_eq!(session.replay_events().len(), 5);
+    assert_eq!(session.end_frame(Duration::from_secs(1)), Duration::from_nanos(1));
+
+    // The window takes over after the last frame
+    assert!(session.is_live());
+    assert_eq!(session.end_frame(Duration::from_secs(1)), Duration::from_secs(1));
+}
+
+#[test]
+fn input_session_records_and_saves_the_log_test() {
+    let path = std::env::temp_dir().join(format!("input_log_test_{}.input", std::process::id()));
+    let mut session = InputSession::recording(path.clone());
+    session.begin(UVec2::new(800, 600));
+    session.record_event(InputEvent::Key { code: KeyCode::Space, pressed: true });
+    assert_eq!(session.end_frame(Duration::from_millis(5)), Duration::from_millis(5));
+    // Not ended by a frame, left out
+    session.record_event(InputEvent::Key { code: KeyCode::Escape, pressed: true });
+    session.finish().unwrap();
+    assert!(session.is_live());
+
+    let log = InputLog::load(&path).unwrap();
+    assert_eq!(log.window_size(), UVec2::new(800, 600));
+    assert_eq!(log.frames().len(), 1);
+    assert_eq!(log.frames()[0].events, vec![InputEvent::Key { code: KeyCode::Space, pressed: true }]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn seeded_rng_repeats_test() {
+    let draw = || (rng::random_range(0.0..1.0f32), rng::random_range(0..1000u32));
+    rng::seed(42);
+    let first = (draw(), draw());
+    rng::seed(42);
+    assert_eq!((draw(), draw()), first);
+}
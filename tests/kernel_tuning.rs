@@ -0,0 +1,89 @@
+mod common;
+
+use std::num::NonZeroU32;
+use wgpu::wgt::PollType::WaitForSubmissionIndex;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::utils::gpu_buffer::GpuBuffer;
+use game_engine::utils::kernel_tuning::KernelTuning;
+use game_engine::utils::radix_sort::GPUSorter;
+
+const ADAPTER: &str = "Test adapter (Vulkan, driver 1.0)";
+
+fn tuned() -> KernelTuning {
+    KernelTuning {
+        sort_workgroup_size: 64,
+        sort_blocks_per_workgroup: 15,
+        prefix_sum_workgroup_size: 128,
+        collision_workgroup_size: 32,
+    }
+}
+
+#[test]
+fn toml_round_trip_test() {
+    let tuning = tuned();
+    let (adapter, parsed) = KernelTuning::from_toml_str(&tuning.to_toml_string(ADAPTER)).unwrap();
+    assert_eq!(adapter, ADAPTER);
+    assert_eq!(parsed, tuning);
+
+    // Missing sizes keep their default
+    let (_, parsed) = KernelTuning::from_toml_str("sort_blocks_per_workgroup = 30").unwrap();
+    assert_eq!(parsed, KernelTuning { sort_blocks_per_workgroup: 30, ..KernelTuning::default() });
+}
+
+#[test]
+fn invalid_sizes_are_rejected_test() {
+    for source in [
+        "prefix_sum_workgroup_size = 100",
+        "collision_workgroup_size = 0",
+        "sort_workgroup_size = 16",
+        "sort_workgroup_size = 512",
+        "sort_blocks_per_workgroup = 0",
+        "scan_workgroup_size = 64",
+    ] {
+        assert!(KernelTuning::from_toml_str(source).is_err(), "{} was accepted", source);
+    }
+}
+
+#[test]
+fn cache_is_only_loaded_on_its_adapter_test() {
+    let path = std::env::temp_dir().join(format!("kernel_tuning_test_{}", std::process::id())).join("kernel_tuning.toml");
+    assert_eq!(KernelTuning::load(&path, ADAPTER).unwrap(), None);
+
+    tuned().save(&path, ADAPTER).unwrap();
+    assert_eq!(KernelTuning::load(&path, ADAPTER).unwrap(), Some(tuned()));
+    assert_eq!(KernelTuning::load(&path, "Other adapter").unwrap(), None);
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+/// Sorts the keys `n - 1..=0` with a sorter created with the tuning of `wgpu_context`, checks that the keys and the
+/// payload end up in order.
+fn sort_reversed(wgpu_context: &WgpuContext, n: u32, early_exit: bool) {
+    let data: Vec<u32> = (0..n).rev().collect();
+    let mut keys = GpuBuffer::new(wgpu_context, data.clone(), wgpu::BufferUsages::STORAGE);
+    let mut payload = GpuBuffer::new(wgpu_context, data, wgpu::BufferUsages::STORAGE);
+    let mut sorter = GPUSorter::new(wgpu_context, NonZeroU32::new(n).unwrap(), &keys, &payload);
+    sorter.set_early_exit(wgpu_context, early_exit);
+    assert_eq!(sorter.workgroup_size(), wgpu_context.capabilities().workgroup_size(wgpu_context.kernel_tuning().sort_workgroup_size));
+    assert_eq!(sorter.blocks_per_workgroup(), wgpu_context.kernel_tuning().sort_blocks_per_workgroup);
+
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Kernel tuning test encoder") });
+    sorter.sort(&mut encoder, None);
+    let idx = wgpu_context.get_queue().submit([encoder.finish()]);
+    wgpu_context.get_device().poll(WaitForSubmissionIndex(idx)).unwrap();
+
+    let sorted: Vec<u32> = (0..n).collect();
+    assert_eq!(keys.download(wgpu_context).unwrap().as_slice(), sorted);
+    assert_eq!(payload.download(wgpu_context).unwrap().as_slice(), sorted);
+}
+
+#[test]
+fn sorter_uses_the_tuning_of_the_context_test() {
+    let mut setup = pollster::block_on(common::setup());
+    let wgpu_context = &mut setup.wgpu_context;
+    assert_eq!(wgpu_context.kernel_tuning(), KernelTuning::default());
+
+    wgpu_context.set_kernel_tuning(tuned());
+    // Several workgroups of 64 threads, 15 blocks each
+    sort_reversed(wgpu_context, 5000, false);
+    sort_reversed(wgpu_context, 5000, true);
+}
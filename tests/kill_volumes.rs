@@ -0,0 +1,61 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::physics::kill_volumes::KillVolume;
+use game_engine::simulation::COMPACTION_INTERVAL_STEPS;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+#[test]
+fn kill_volume_contains_test() {
+    let line = KillVolume::BelowLine { y: 50.0 };
+    assert!(line.contains(Vec2::new(1000.0, 49.0)));
+    assert!(!line.contains(Vec2::new(0.0, 50.0)));
+    let circle = KillVolume::OutsideCircle { center: Vec2::new(100.0, 100.0), radius: 10.0 };
+    assert!(!circle.contains(Vec2::new(105.0, 100.0)));
+    assert!(circle.contains(Vec2::new(111.0, 100.0)));
+}
+
+#[test]
+fn particles_inside_kill_volumes_are_removed_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let positions = vec![Vec2::new(100.0, 20.0), Vec2::new(500.0, 500.0), Vec2::new(900.0, 500.0), Vec2::new(520.0, 510.0)];
+    let mut simulation = common::create_test_simulation(wgpu_context, positions, 2.0);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    assert_eq!(simulation.add_kill_volume(wgpu_context, KillVolume::BelowLine { y: 50.0 }), 0);
+    assert_eq!(simulation.add_kill_volume(wgpu_context, KillVolume::OutsideCircle { center: Vec2::new(500.0, 500.0), radius: 100.0 }), 1);
+    assert_eq!(simulation.kill_volumes().len(), 2);
+
+    assert_eq!(simulation.remove_dead_particles(wgpu_context, &mut gpu_profiler), 2);
+    gpu_profiler.end_frame().unwrap();
+    let positions = simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().to_vec();
+    assert_eq!(positions, vec![Vec2::new(500.0, 500.0), Vec2::new(520.0, 510.0)]);
+    assert_eq!(simulation.grid().num_elements(), 2);
+
+    // Without volumes nothing else is removed
+    assert_eq!(simulation.remove_kill_volume(wgpu_context, 1), Some(KillVolume::OutsideCircle { center: Vec2::new(500.0, 500.0), radius: 100.0 }));
+    assert_eq!(simulation.remove_kill_volume(wgpu_context, 5), None);
+    simulation.clear_kill_volumes(wgpu_context);
+    assert!(simulation.kill_volumes().is_empty());
+    assert_eq!(simulation.remove_dead_particles(wgpu_context, &mut gpu_profiler), 0);
+    gpu_profiler.end_frame().unwrap();
+}
+
+#[test]
+fn step_removes_the_killed_particles_periodically_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = common::create_test_simulation(wgpu_context, vec![Vec2::new(100.0, 20.0), Vec2::new(300.0, 500.0)], 2.0);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    simulation.add_kill_volume(wgpu_context, KillVolume::BelowLine { y: 50.0 });
+
+    for _ in 0..COMPACTION_INTERVAL_STEPS - 1 {
+        simulation.step(wgpu_context, &mut gpu_profiler, 0.001, None);
+        gpu_profiler.end_frame().unwrap();
+    }
+    assert_eq!(simulation.particles().len(), 2);
+    simulation.step(wgpu_context, &mut gpu_profiler, 0.001, None);
+    gpu_profiler.end_frame().unwrap();
+    assert_eq!(simulation.particles().len(), 1);
+}
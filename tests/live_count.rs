@@ -0,0 +1,118 @@
+mod common;
+
+use std::num::NonZeroU32;
+use glam::Vec2;
+use game_engine::grid::grid::{Grid, UNUSED_CELL_ID};
+use game_engine::physics::collision_system::CollisionSystem;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::{Simulation, DIMENSION};
+use game_engine::utils::gpu_buffer::GpuBuffer;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+use game_engine::utils::radix_sort::{GPUSorter, SortIndirectArgs};
+
+#[test]
+fn sort_indirect_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    let n = 2000u32;
+    let data: Vec<u32> = (0..n).rev().collect();
+    let keys = GpuBuffer::new(wgpu_context, data.clone(), wgpu::BufferUsages::STORAGE);
+    let payload = GpuBuffer::new(wgpu_context, data.clone(), wgpu::BufferUsages::STORAGE);
+    let sorter = GPUSorter::new(wgpu_context, NonZeroU32::new(n).unwrap(), &keys, &payload);
+
+    // Only the first half is sorted, as if a kernel had written the count
+    let sorted_elements = n / 2;
+    wgpu_context.get_queue().write_buffer(sorter.indirect_args().buffer(), 0, bytemuck::bytes_of(&SortIndirectArgs::new(sorted_elements, sorter.workgroup_size(), sorter.blocks_per_workgroup())));
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Indirect sort test") });
+    sorter.sort_indirect(&mut encoder);
+    wgpu_context.get_queue().submit([encoder.finish()]);
+
+    let mut expected: Vec<u32> = data[..sorted_elements as usize].to_vec();
+    expected.sort();
+    expected.extend_from_slice(&data[sorted_elements as usize..]);
+    assert_eq!(keys.read_back(wgpu_context).unwrap(), expected);
+    assert_eq!(payload.read_back(wgpu_context).unwrap(), expected);
+}
+
+fn build_grid(wgpu_context: &WgpuContext, grid: &mut Grid) -> Vec<u32> {
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Live count test") });
+    grid.prepare_indirect_dispatch(&mut encoder);
+    grid.build_cell_ids(&mut encoder);
+    grid.sort_map(&mut encoder);
+    wgpu_context.get_queue().submit([encoder.finish()]);
+    grid.download_cell_ids(wgpu_context).unwrap()
+}
+
+#[test]
+fn removed_particles_leave_the_grid_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    // Far apart and at the center of their 4.4 units wide cells, every particle is in its home cell only
+    let particles = common::create_test_particle_system(
+        wgpu_context,
+        vec![Vec2::new(99.0, 99.0), Vec2::new(297.0, 297.0), Vec2::new(495.0, 495.0), Vec2::new(693.0, 693.0)],
+        vec![2.0; 4],
+    );
+    let mut grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    grid.set_indirect_dispatch(true);
+
+    let cell_ids = build_grid(wgpu_context, &mut grid);
+    assert!(cell_ids[..4].iter().all(|&cell_id| cell_id != UNUSED_CELL_ID));
+    assert!(cell_ids[4..].iter().all(|&cell_id| cell_id == UNUSED_CELL_ID));
+
+    // The last two particles are removed without telling the CPU
+    grid.set_live_particles(wgpu_context, 2);
+    let cell_ids = build_grid(wgpu_context, &mut grid);
+    assert!(cell_ids[..2].iter().all(|&cell_id| cell_id != UNUSED_CELL_ID));
+    assert!(cell_ids[2..].iter().all(|&cell_id| cell_id == UNUSED_CELL_ID));
+    let object_ids = grid.download_object_ids(wgpu_context).unwrap();
+    let mut live_objects = object_ids[..2].to_vec();
+    live_objects.sort();
+    assert_eq!(live_objects, vec![0, 1]);
+
+    let args = grid.live_count().download(wgpu_context);
+    assert_eq!(args.covered_particles, 4);
+    assert_eq!(args.previous_live_particles, 2);
+    assert_eq!(args.build_cell_ids, [1, 1, 1]);
+
+    build_grid(wgpu_context, &mut grid);
+    assert_eq!(grid.live_count().download(wgpu_context).covered_particles, 2);
+}
+
+fn run(wgpu_context: &WgpuContext, indirect_dispatch: bool) -> Vec<Vec2> {
+    let particles = common::create_test_particle_system(
+        wgpu_context,
+        vec![Vec2::new(300.0, 300.0), Vec2::new(302.0, 300.0), Vec2::new(100.0, 100.0), Vec2::new(101.0, 101.0)],
+        vec![2.0; 4],
+    );
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let collision_system = CollisionSystem::new(wgpu_context, DIMENSION, &particles, &grid);
+    let mut simulation = Simulation::from_parts(wgpu_context, particles, grid, collision_system);
+    simulation.set_indirect_dispatch(indirect_dispatch);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    for _ in 0..5 {
+        simulation.step(wgpu_context, &mut gpu_profiler, 0.016, None);
+        gpu_profiler.end_frame().unwrap();
+    }
+    // The CPU dispatch sorts the particles in the first step, the indirect one skips the sort
+    let mut positions = simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().to_vec();
+    positions.sort_by(|a, b| a.x.total_cmp(&b.x));
+    positions
+}
+
+#[test]
+fn indirect_dispatch_matches_cpu_dispatch_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    let cpu = run(wgpu_context, false);
+    let indirect = run(wgpu_context, true);
+    for (cpu_position, indirect_position) in cpu.iter().zip(&indirect) {
+        assert!(cpu_position.distance(*indirect_position) < 1e-4, "{:?} != {:?}", cpu, indirect);
+    }
+    // The overlapping pairs were pushed apart
+    assert!(cpu[2].distance(cpu[3]) > 2.0);
+}
@@ -0,0 +1,71 @@
+use glam::{UVec2, UVec3, Vec2, Vec3};
+use game_engine::grid::morton;
+
+#[test]
+fn morton_encode_known_values_test() {
+    assert_eq!(morton::encode(UVec2::new(0, 0)), 0);
+    assert_eq!(morton::encode(UVec2::new(1, 0)), 1);
+    assert_eq!(morton::encode(UVec2::new(0, 1)), 2);
+    assert_eq!(morton::encode(UVec2::new(3, 3)), 15);
+    assert_eq!(morton::encode(UVec2::new(0xFFFF, 0xFFFF)), u32::MAX);
+}
+
+#[test]
+fn key_bits_keep_unused_cell_ids_last_test() {
+    assert_eq!(morton::key_bits(UVec2::new(0, 0)), 1);
+    // Cell ids up to 15 need 5 bits: with 4, the largest one would equal the unused id
+    assert_eq!(morton::key_bits(UVec2::new(3, 3)), 5);
+    assert_eq!(morton::key_bits(UVec2::new(3, 2)), 4);
+    assert_eq!(morton::key_bits(UVec2::new(200, 100)), 15);
+    assert_eq!(morton::key_bits(UVec2::new(0xFFFF, 0xFFFF)), 32);
+}
+
+#[test]
+fn morton_round_trip_test() {
+    for x in (0..1024u32).step_by(7) {
+        for y in (0..1024u32).step_by(13) {
+            let cell = UVec2::new(x, y);
+            assert_eq!(morton::decode(morton::encode(cell)), cell);
+        }
+    }
+}
+
+#[test]
+fn cell_coord_clamps_negative_positions_test() {
+    assert_eq!(morton::cell_coord(Vec2::new(45.0, 21.9), 22.0), UVec2::new(2, 0));
+    assert_eq!(morton::cell_coord(Vec2::new(-3.0, 50.0), 22.0), UVec2::new(0, 2));
+}
+
+#[test]
+fn neighbouring_cells_never_share_a_color_test() {
+    for x in 1..64u32 {
+        for y in 1..64u32 {
+            let color = morton::cell_color(UVec2::new(x, y));
+            assert!((1..=morton::NUM_CELL_COLORS).contains(&color));
+            assert_eq!(morton::cell_id_color(morton::encode(UVec2::new(x, y))), color);
+            for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+                let neighbor = UVec2::new(x.wrapping_add_signed(dx), y.wrapping_add_signed(dy));
+                assert_ne!(morton::cell_color(neighbor), color, "cells {:?} and {:?}", (x, y), neighbor);
+            }
+        }
+    }
+}
+
+#[test]
+fn morton_3d_test() {
+    assert_eq!(morton::encode_3d(UVec3::new(1, 0, 0)), 1);
+    assert_eq!(morton::encode_3d(UVec3::new(0, 1, 0)), 2);
+    assert_eq!(morton::encode_3d(UVec3::new(0, 0, 1)), 4);
+    assert_eq!(morton::encode_3d(UVec3::new(3, 3, 3)), 63);
+    assert_eq!(morton::encode_3d(UVec3::splat(morton::MAX_CELL_COORD_3D)), 0x3FFF_FFFF);
+
+    for x in (0..1024u32).step_by(31) {
+        for y in (0..1024u32).step_by(37) {
+            for z in (0..1024u32).step_by(41) {
+                let cell = UVec3::new(x, y, z);
+                assert_eq!(morton::decode_3d(morton::encode_3d(cell)), cell);
+            }
+        }
+    }
+    assert_eq!(morton::cell_coord_3d(Vec3::new(-1.0, 25.0, 1e9), 10.0), UVec3::new(0, 2, morton::MAX_CELL_COORD_3D));
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::particles::nearest_particle_query::{NearestParticleQuery, NearestParticleResult};
+use game_engine::particles::particle_system::ParticleSystem;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use wgpu::wgt::PollType::Wait;
+
+fn pick(wgpu_context: &WgpuContext, query: &mut NearestParticleQuery, particles: &ParticleSystem, position: Vec2) -> NearestParticleResult {
+    query.request(wgpu_context, particles, position);
+    wgpu_context.get_device().poll(Wait).unwrap();
+    query.poll(wgpu_context).unwrap()
+}
+
+fn create_particles(wgpu_context: &WgpuContext) -> ParticleSystem {
+    common::create_test_particle_system(
+        wgpu_context,
+        vec![Vec2::new(10.0, 10.0), Vec2::new(14.0, 10.0), Vec2::new(50.0, 50.0)],
+        vec![3.0, 3.0, 5.0],
+    )
+}
+
+#[test]
+fn picks_the_nearest_overlapping_particle_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let particles = create_particles(wgpu_context);
+    let mut query = NearestParticleQuery::new(wgpu_context, &particles);
+
+    // Inside both particle 0 and 1, closer to 1
+    let result = pick(wgpu_context, &mut query, &particles, Vec2::new(12.5, 10.0));
+    assert_eq!(result.position, Vec2::new(12.5, 10.0));
+    let nearest = result.nearest.unwrap();
+    assert_eq!(nearest.particle, 1);
+    assert!((nearest.distance - 1.5).abs() < 1e-4);
+
+    let flags = particles.highlight_flags().read_back(wgpu_context).unwrap();
+    assert_eq!(flags, vec![0, 1, 0]);
+
+    let result = pick(wgpu_context, &mut query, &particles, Vec2::new(52.0, 51.0));
+    assert_eq!(result.nearest.unwrap().particle, 2);
+    let flags = particles.highlight_flags().read_back(wgpu_context).unwrap();
+    assert_eq!(flags, vec![0, 0, 1]);
+}
+
+#[test]
+fn empty_space_picks_nothing_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let particles = create_particles(wgpu_context);
+    let mut query = NearestParticleQuery::new(wgpu_context, &particles);
+
+    let result = pick(wgpu_context, &mut query, &particles, Vec2::new(30.0, 30.0));
+    assert_eq!(result.nearest, None);
+    let flags = particles.highlight_flags().read_back(wgpu_context).unwrap();
+    assert_eq!(flags, vec![0, 0, 0]);
+
+    // The tolerance lets particles near the cursor be picked
+    query.set_pick_tolerance(25.0);
+    let result = pick(wgpu_context, &mut query, &particles, Vec2::new(30.0, 30.0));
+    assert_eq!(result.nearest.unwrap().particle, 1);
+}
+
+#[test]
+fn clear_highlight_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let particles = create_particles(wgpu_context);
+    let mut query = NearestParticleQuery::new(wgpu_context, &particles);
+
+    pick(wgpu_context, &mut query, &particles, Vec2::new(10.0, 10.0));
+    query.clear_highlight(wgpu_context, &particles);
+
+    let flags = particles.highlight_flags().read_back(wgpu_context).unwrap();
+    assert_eq!(flags, vec![0, 0, 0]);
+    assert_eq!(query.poll(wgpu_context), None);
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use glam::UVec2;
+use game_engine::renderer::offscreen_target::OffscreenTarget;
+use game_engine::renderer::wgpu_context::WgpuContext;
+
+fn clear(wgpu_context: &WgpuContext, target: &OffscreenTarget, color: wgpu::Color) {
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: None,
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target.view(),
+            resolve_target: None,
+            ops: wgpu::Operations { load: wgpu::LoadOp::Clear(color), store: wgpu::StoreOp::Store },
+            depth_slice: None,
+        })],
+        depth_stencil_attachment: None,
+        occlusion_query_set: None,
+        timestamp_writes: None,
+    });
+    wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+}
+
+#[test]
+fn offscreen_target_reads_back_rgba_pixels_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let color = wgpu::Color { r: 1.0, g: 0.0, b: 0.0, a: 1.0 };
+
+    for format in [wgpu::TextureFormat::Rgba8Unorm, wgpu::TextureFormat::Bgra8Unorm] {
+        // 40 bytes per row, padded to 256 in the staging buffer
+        let target = OffscreenTarget::new(wgpu_context, UVec2::new(10, 3), format);
+        assert_eq!(target.padded_row_bytes(), 256);
+        assert_eq!(target.staging_size(), 3 * 256);
+        clear(wgpu_context, &target, color);
+
+        let pixels = target.read_rgba8(wgpu_context);
+        assert_eq!(pixels.len(), 10 * 3 * 4);
+        assert!(pixels.chunks_exact(4).all(|pixel| pixel == [255, 0, 0, 255]), "{:?}", format);
+    }
+}
+
+#[test]
+fn offscreen_target_strips_the_row_padding_test() {
+    let setup = pollster::block_on(common::setup());
+    let target = OffscreenTarget::new(&setup.wgpu_context, UVec2::new(2, 2), wgpu::TextureFormat::Bgra8Unorm);
+    let mut padded = vec![0u8; target.staging_size() as usize];
+    padded[..8].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    padded[256..264].copy_from_slice(&[9, 10, 11, 12, 13, 14, 15, 16]);
+
+    assert_eq!(target.to_rgba8(&padded), vec![3, 2, 1, 4, 7, 6, 5, 8, 11, 10, 9, 12, 15, 14, 13, 16]);
+}
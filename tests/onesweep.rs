@@ -0,0 +1,73 @@
+#![cfg(feature = "onesweep")]
+mod common;
+
+use std::num::NonZeroU32;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use wgpu::wgt::PollType::WaitForSubmissionIndex;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::utils::gpu_buffer::GpuBuffer;
+use game_engine::utils::radix_sort::onesweep::{OnesweepSorter, PARTITION_SIZE};
+use game_engine::utils::radix_sort::GPUSorter;
+
+/// Keys and payload sorted by the onesweep sorter and by `GPUSorter`.
+fn sort_both(wgpu_context: &WgpuContext, keys: &[u32], sort_first_n: Option<u32>) -> [(Vec<u32>, Vec<u32>); 2] {
+    let device = wgpu_context.get_device();
+    let queue = wgpu_context.get_queue();
+    let n = NonZeroU32::new(keys.len() as u32).unwrap();
+    let payload: Vec<u32> = (0..keys.len() as u32).collect();
+
+    let onesweep_keys = GpuBuffer::new(wgpu_context, keys.to_vec(), wgpu::BufferUsages::STORAGE);
+    let onesweep_payload = GpuBuffer::new(wgpu_context, payload.clone(), wgpu::BufferUsages::STORAGE);
+    let onesweep = OnesweepSorter::new(wgpu_context, n, &onesweep_keys, &onesweep_payload);
+    let radix_keys = GpuBuffer::new(wgpu_context, keys.to_vec(), wgpu::BufferUsages::STORAGE);
+    let radix_payload = GpuBuffer::new(wgpu_context, payload, wgpu::BufferUsages::STORAGE);
+    let radix = GPUSorter::new(wgpu_context, n, &radix_keys, &radix_payload);
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Onesweep test"),
+    });
+    onesweep.sort(&mut encoder, sort_first_n);
+    radix.sort(&mut encoder, sort_first_n);
+    let idx = queue.submit([encoder.finish()]);
+    device.poll(WaitForSubmissionIndex(idx)).unwrap();
+
+    [
+        (onesweep_keys.read_back(wgpu_context).unwrap(), onesweep_payload.read_back(wgpu_context).unwrap()),
+        (radix_keys.read_back(wgpu_context).unwrap(), radix_payload.read_back(wgpu_context).unwrap()),
+    ]
+}
+
+#[test]
+fn onesweep_matches_radix_sort_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut rng = StdRng::seed_from_u64(4273);
+
+    // Many partitions with repeated keys, so the order of equal keys is checked too
+    let keys: Vec<u32> = (0..20 * PARTITION_SIZE + 123).map(|_| rng.random_range(0..5000) * 104_729).collect();
+    let [onesweep, radix] = sort_both(wgpu_context, &keys, None);
+    let mut expected = keys.clone();
+    expected.sort();
+    assert_eq!(onesweep.0, expected);
+    assert_eq!(onesweep, radix);
+
+    // A single partition, with keys of every byte
+    let keys: Vec<u32> = (0..100).map(|_| rng.random()).collect();
+    let [onesweep, radix] = sort_both(wgpu_context, &keys, None);
+    assert!(onesweep.0.is_sorted());
+    assert_eq!(onesweep, radix);
+}
+
+#[test]
+fn onesweep_sorts_the_first_n_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    let keys: Vec<u32> = (0..3 * PARTITION_SIZE).rev().collect();
+    let sorted_elements = PARTITION_SIZE + 7;
+    let [onesweep, radix] = sort_both(wgpu_context, &keys, Some(sorted_elements));
+    assert!(onesweep.0[..sorted_elements as usize].is_sorted());
+    assert_eq!(onesweep.0[sorted_elements as usize..], keys[sorted_elements as usize..]);
+    assert_eq!(onesweep, radix);
+}
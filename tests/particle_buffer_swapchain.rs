@@ -0,0 +1,45 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::particles::particle_system::ParticleSystem;
+use game_engine::renderer::wgpu_context::WgpuContext;
+
+fn publish(particles: &mut ParticleSystem, wgpu_context: &WgpuContext) {
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    particles.record_publish_display(wgpu_context, &mut encoder);
+    wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+}
+
+#[test]
+fn double_buffered_display_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let positions = vec![Vec2::new(10.0, 10.0), Vec2::new(20.0, 10.0), Vec2::new(30.0, 10.0)];
+    let mut particles = common::create_test_particle_system(wgpu_context, positions.clone(), vec![1.0; 3]);
+
+    // Without double buffering there is nothing to publish
+    publish(&mut particles, wgpu_context);
+    assert!(particles.display_buffers().is_none());
+
+    particles.set_double_buffered(wgpu_context, true);
+    assert!(particles.is_double_buffered());
+    publish(&mut particles, wgpu_context);
+    let display_buffers = particles.display_buffers().unwrap();
+    let first_set = display_buffers.displayed_index();
+    assert_eq!(display_buffers.displayed_len(), 3);
+    assert_eq!(display_buffers.displayed().current_positions.read_back(wgpu_context).unwrap()[..3], positions[..]);
+
+    // The spawned particles are only displayed once published, into the other set
+    particles.add_particles_at(&[Vec2::new(40.0, 10.0)], wgpu_context);
+    assert_eq!(particles.display_buffers().unwrap().displayed_len(), 3);
+    publish(&mut particles, wgpu_context);
+    let display_buffers = particles.display_buffers().unwrap();
+    assert_ne!(display_buffers.displayed_index(), first_set);
+    assert_eq!(display_buffers.displayed_len(), particles.len());
+    let displayed = display_buffers.displayed().current_positions.read_back(wgpu_context).unwrap();
+    let live = particles.positions().read_back(wgpu_context).unwrap();
+    assert_eq!(displayed[..particles.len()], live[..particles.len()]);
+
+    particles.set_double_buffered(wgpu_context, false);
+    assert!(particles.display_buffers().is_none());
+}
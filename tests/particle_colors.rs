@@ -0,0 +1,69 @@
+mod common;
+
+use glam::{Vec2, Vec4};
+use game_engine::particles::particle_color_kernel::{ColorMap, ColorSource, ParticleColorSettings};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+fn assert_color_eq(actual: Vec4, expected: Vec4) {
+    assert!((actual - expected).abs().max_element() < 0.01, "{actual} != {expected}");
+}
+
+#[test]
+fn color_maps_test() {
+    assert_eq!(ColorMap::Heat.sample(0.0), Vec4::new(0.0, 0.0, 0.0, 1.0));
+    assert_eq!(ColorMap::Heat.sample(2.0), Vec4::ONE);
+    assert_color_eq(ColorMap::Heat.sample(0.5), Vec4::new(1.0, 0.5, 0.0, 1.0));
+    // Viridis goes from dark purple to yellow
+    let low = ColorMap::Viridis.sample(0.0);
+    assert!(low.z > low.x && low.x > low.y);
+    let high = ColorMap::Viridis.sample(1.0);
+    assert!(high.x > 0.9 && high.y > 0.85 && high.z < 0.2, "{high}");
+}
+
+#[test]
+fn color_modes_cycle_test() {
+    let speed = ParticleColorSettings::cycle(None).unwrap();
+    assert_eq!(speed.source, ColorSource::Speed);
+    let density = ParticleColorSettings::cycle(Some(speed)).unwrap();
+    assert_eq!(density.source, ColorSource::Density);
+    assert_eq!(ParticleColorSettings::cycle(Some(density)), None);
+}
+
+#[test]
+fn speed_colors_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    // Test particle systems have a single color, the one of particle 0
+    let mut simulation = common::create_test_simulation(wgpu_context, vec![Vec2::new(100.0, 100.0)], 2.0);
+    simulation.particles_mut().set_gravity(Vec2::new(0.0, -1000.0));
+    let settings = ParticleColorSettings { max_value: 20.0, ..ParticleColorSettings::speed(ColorMap::Heat) };
+    simulation.set_particle_colors(wgpu_context, Some(settings));
+    assert_eq!(simulation.particles().color_settings(), Some(settings));
+
+    // Starting at rest, the first step moves the particle by g * dt^2: 10 units per second
+    simulation.step(wgpu_context, &mut gpu_profiler, 0.01, None);
+    simulation.update_particle_colors(wgpu_context, &mut gpu_profiler, 0.01);
+    gpu_profiler.end_frame().unwrap();
+    let colors = simulation.particles().buffers().colors.read_back(wgpu_context).unwrap();
+    assert_color_eq(colors[0], ColorMap::Heat.sample(0.5));
+
+    simulation.set_particle_colors(wgpu_context, None);
+    assert_eq!(simulation.particles().color_settings(), None);
+}
+
+#[test]
+fn density_colors_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    // Both centers in the same cell
+    let mut simulation = common::create_test_simulation(wgpu_context, vec![Vec2::new(100.0, 100.0), Vec2::new(100.5, 100.0)], 2.0);
+    simulation.set_particle_colors(wgpu_context, Some(ParticleColorSettings::density(ColorMap::Viridis)));
+
+    simulation.update_particle_colors(wgpu_context, &mut gpu_profiler, 0.01);
+    gpu_profiler.end_frame().unwrap();
+    let colors = simulation.particles().buffers().colors.read_back(wgpu_context).unwrap();
+    // 2 particles out of the default maximum of 4
+    assert_color_eq(colors[0], ColorMap::Viridis.sample(0.5));
+}
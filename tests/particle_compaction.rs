@@ -0,0 +1,91 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::grid::grid::Grid;
+use game_engine::physics::collision_system::CollisionSystem;
+use game_engine::simulation::{Simulation, COMPACTION_INTERVAL_STEPS, DIMENSION};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+#[test]
+fn expired_particles_are_removed_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let positions = vec![Vec2::new(100.0, 100.0), Vec2::new(200.0, 100.0), Vec2::new(300.0, 100.0), Vec2::new(400.0, 100.0)];
+    let mut particles = common::create_test_particle_system(wgpu_context, positions.clone(), vec![1.0, 2.0, 3.0, 4.0]);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    let lifetime = particles.enable_lifetime(wgpu_context);
+    let lifetimes = [1.0f32, 0.5, f32::INFINITY, 0.2];
+    particles.write_channel(wgpu_context, lifetime, &lifetimes.map(f32::to_bits));
+
+    assert_eq!(particles.remove_dead_particles(wgpu_context, &mut gpu_profiler, 0.6), 2);
+    gpu_profiler.end_frame().unwrap();
+    assert_eq!(particles.len(), 2);
+
+    // The survivors keep their order, and their lifetimes were aged
+    let buffers = particles.download_particle_buffers(wgpu_context);
+    assert_eq!(buffers.current_positions.data(), &vec![positions[0], positions[2]]);
+    assert_eq!(buffers.radii.data(), &vec![1.0, 3.0]);
+    let extras = particles.download_extras(wgpu_context);
+    let offset = particles.channels().offset(lifetime) as usize;
+    let stride = particles.channels().stride() as usize;
+    assert!((f32::from_bits(extras[offset]) - 0.4).abs() < 1e-6);
+    assert_eq!(f32::from_bits(extras[stride + offset]), f32::INFINITY);
+}
+
+#[test]
+fn particles_outside_the_world_are_removed_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    // The test world is 1920x1080
+    let positions = vec![Vec2::new(10.0, 10.0), Vec2::new(f32::NAN, 5.0), Vec2::new(-50.0, 10.0), Vec2::new(30.0, 30.0), Vec2::new(40.0, 2000.0)];
+    let mut particles = common::create_test_particle_system(wgpu_context, positions, vec![1.0; 5]);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    // Without lifetimes, only the position matters
+    assert_eq!(particles.remove_dead_particles(wgpu_context, &mut gpu_profiler, 100.0), 3);
+    gpu_profiler.end_frame().unwrap();
+
+    let positions = particles.download_particle_buffers(wgpu_context).current_positions.data().to_vec();
+    assert_eq!(positions, vec![Vec2::new(10.0, 10.0), Vec2::new(30.0, 30.0)]);
+}
+
+#[test]
+fn simulation_removes_expired_particles_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let positions: Vec<Vec2> = (0..8).map(|i| Vec2::new(100.0 + 50.0 * i as f32, 500.0)).collect();
+    let particles = common::create_test_particle_system(wgpu_context, positions, vec![2.0; 8]);
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let collision_system = CollisionSystem::new(wgpu_context, DIMENSION, &particles, &grid);
+    let mut simulation = Simulation::from_parts(wgpu_context, particles, grid, collision_system);
+    simulation.set_pass_validation(true);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    // Half of the particles live for one step
+    let lifetime = simulation.enable_lifetime(wgpu_context);
+    let lifetimes: Vec<u32> = (0..8).map(|i| if i % 2 == 0 { 0.01f32 } else { f32::INFINITY }.to_bits()).collect();
+    simulation.write_channel(wgpu_context, lifetime, &lifetimes);
+
+    for _ in 0..COMPACTION_INTERVAL_STEPS - 1 {
+        simulation.step(wgpu_context, &mut gpu_profiler, 0.01, None);
+        gpu_profiler.end_frame().unwrap();
+    }
+    // Only removed every COMPACTION_INTERVAL_STEPS steps
+    assert_eq!(simulation.particles().len(), 8);
+    simulation.step(wgpu_context, &mut gpu_profiler, 0.01, None);
+    gpu_profiler.end_frame().unwrap();
+    assert_eq!(simulation.particles().len(), 4);
+    assert_eq!(simulation.grid().num_elements(), 4);
+
+    // Spawning reuses the freed slots, the spawned particles expire too
+    simulation.set_spawn_lifetime(wgpu_context, 0.05);
+    let (report, _) = simulation.add_particles(wgpu_context, Vec2::new(960.0, 540.0));
+    assert_eq!(simulation.particles().len(), 4 + report.spawned);
+    for _ in 0..COMPACTION_INTERVAL_STEPS {
+        simulation.step(wgpu_context, &mut gpu_profiler, 0.01, None);
+        gpu_profiler.end_frame().unwrap();
+    }
+    assert_eq!(simulation.particles().len(), 4);
+    assert_eq!(simulation.remove_dead_particles(wgpu_context, &mut gpu_profiler), 0);
+}
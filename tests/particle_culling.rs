@@ -0,0 +1,39 @@
+mod common;
+
+use glam::{Mat4, Vec2};
+use game_engine::particles::particle_culling::ParticleCulling;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+/// Orthographic view of the world rectangle [min, max], like `Camera::build_view_projection_matrix`.
+fn view_of(min: Vec2, max: Vec2) -> Mat4 {
+    Mat4::orthographic_rh(min.x, max.x, min.y, max.y, -1.0, 1.0)
+}
+
+#[test]
+fn culling_keeps_the_particles_in_view_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let positions = vec![
+        Vec2::new(50.0, 50.0),
+        Vec2::new(150.0, 50.0),
+        // Outside, but its quad reaches into the view
+        Vec2::new(102.0, 50.0),
+        Vec2::new(-50.0, -50.0),
+    ];
+    let particles = common::create_test_particle_system(wgpu_context, positions, vec![2.0; 4]);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    let mut culling = ParticleCulling::new(wgpu_context);
+
+    culling.cull(wgpu_context, &mut gpu_profiler, particles.positions(), particles.radius(), particles.len(), view_of(Vec2::ZERO, Vec2::splat(100.0)));
+    assert_eq!(culling.download_visible_ids(wgpu_context), vec![0, 2]);
+    // Six indices per quad, one instance per visible particle
+    assert_eq!(culling.draw_args().read_back(wgpu_context).unwrap(), vec![6, 2, 0, 0, 0]);
+
+    // Every pass starts counting from zero
+    culling.cull(wgpu_context, &mut gpu_profiler, particles.positions(), particles.radius(), particles.len(), view_of(Vec2::splat(-100.0), Vec2::splat(60.0)));
+    assert_eq!(culling.download_visible_ids(wgpu_context), vec![0, 3]);
+
+    culling.cull(wgpu_context, &mut gpu_profiler, particles.positions(), particles.radius(), particles.len(), view_of(Vec2::splat(1000.0), Vec2::splat(1100.0)));
+    assert!(culling.download_visible_ids(wgpu_context).is_empty());
+    gpu_profiler.end_frame().unwrap();
+}
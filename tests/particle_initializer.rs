@@ -0,0 +1,56 @@
+use glam::Vec2;
+use game_engine::particles::particle_initializer::{hex_packing, InitialLayout};
+
+fn assert_no_overlaps(positions: &[Vec2], radius: f32) {
+    for i in 0..positions.len() {
+        for j in i + 1..positions.len() {
+            let distance = positions[i].distance(positions[j]);
+            assert!(distance >= 2.0 * radius - 1e-3, "Particles {} and {} overlap: distance {}", i, j, distance);
+        }
+    }
+}
+
+fn assert_inside(positions: &[Vec2], region_min: Vec2, region_max: Vec2, radius: f32) {
+    for position in positions {
+        assert!(position.x - radius >= region_min.x - 1e-3 && position.x + radius <= region_max.x + 1e-3, "{:?} outside the region", position);
+        assert!(position.y - radius >= region_min.y - 1e-3 && position.y + radius <= region_max.y + 1e-3, "{:?} outside the region", position);
+    }
+}
+
+#[test]
+fn hex_packing_spreads_without_overlaps_test() {
+    let region_min = Vec2::new(10.0, 20.0);
+    let region_max = Vec2::new(310.0, 220.0);
+    let radius = 2.0;
+
+    let positions = hex_packing(region_min, region_max, radius, 500);
+
+    assert_eq!(positions.len(), 500);
+    assert_no_overlaps(&positions, radius);
+    assert_inside(&positions, region_min, region_max, radius);
+
+    // Spread over the region instead of packed in a corner
+    let max_y = positions.iter().map(|position| position.y).fold(f32::MIN, f32::max);
+    assert!(max_y > region_max.y - 40.0);
+}
+
+#[test]
+fn hex_packing_densest_case_test() {
+    let region_min = Vec2::ZERO;
+    let region_max = Vec2::new(100.0, 100.0);
+    let radius = 1.0;
+
+    // More circles than fit: the lattice uses the diameter as spacing and returns what fits
+    let positions = InitialLayout::HexPacking.generate(region_min, region_max, radius, 100_000);
+
+    assert!(positions.len() < 100_000);
+    assert!(positions.len() > 2700);
+    assert_no_overlaps(&positions, radius);
+    assert_inside(&positions, region_min, region_max, radius);
+}
+
+#[test]
+fn hex_packing_empty_region_test() {
+    assert!(hex_packing(Vec2::ZERO, Vec2::new(1.0, 1.0), 2.0, 10).is_empty());
+    assert!(hex_packing(Vec2::ZERO, Vec2::new(100.0, 100.0), 2.0, 0).is_empty());
+}
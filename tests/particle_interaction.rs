@@ -0,0 +1,87 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::particles::particle_interaction::InteractionMode;
+use game_engine::particles::particle_system::ParticleSystem;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const DELTA_TIME: f32 = 0.1;
+const PARTICLE: Vec2 = Vec2::new(100.0, 100.0);
+
+fn step(wgpu_context: &WgpuContext, particles: &mut ParticleSystem, gpu_profiler: &mut GpuProfiler) -> Vec2 {
+    particles.update_positions(DELTA_TIME, wgpu_context, gpu_profiler);
+    gpu_profiler.end_frame().unwrap();
+    particles.download_particle_buffers(wgpu_context).current_positions.data()[0]
+}
+
+/// Position of a resting particle at `PARTICLE` after one step with the mouse pressed at `cursor`.
+fn step_with_cursor(wgpu_context: &WgpuContext, mode: InteractionMode, radius: f32, cursor: Vec2) -> Vec2 {
+    let mut particles = common::create_test_particle_system(wgpu_context, vec![PARTICLE], vec![2.0]);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    particles.interaction_mut().mode = mode;
+    particles.interaction_mut().set_radius(radius);
+    particles.mouse_click_callback(true, cursor);
+    step(wgpu_context, &mut particles, &mut gpu_profiler)
+}
+
+fn assert_close(actual: Vec2, expected: Vec2) {
+    assert!((actual - expected).length() < 1e-3, "expected {:?}, got {:?}", expected, actual);
+}
+
+#[test]
+fn attract_repel_and_vortex_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    // strength * dt^2 = 1.5
+    assert_close(step_with_cursor(wgpu_context, InteractionMode::Attract, 1000.0, Vec2::new(200.0, 100.0)), Vec2::new(101.5, 100.0));
+    assert_close(step_with_cursor(wgpu_context, InteractionMode::Repel, 1000.0, Vec2::new(200.0, 100.0)), Vec2::new(98.5, 100.0));
+    // Counterclockwise around a cursor above the particle, 10% weaker at a tenth of the radius
+    assert_close(step_with_cursor(wgpu_context, InteractionMode::Vortex, 1000.0, Vec2::new(100.0, 200.0)), Vec2::new(101.35, 100.0));
+    // Out of reach
+    for mode in InteractionMode::ALL {
+        assert_eq!(step_with_cursor(wgpu_context, mode, 50.0, Vec2::new(200.0, 100.0)), PARTICLE);
+    }
+}
+
+#[test]
+fn dragged_particles_follow_the_cursor_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut particles = common::create_test_particle_system(wgpu_context, vec![PARTICLE], vec![2.0]);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    particles.interaction_mut().mode = InteractionMode::Drag;
+
+    particles.mouse_click_callback(true, Vec2::new(100.0, 110.0));
+    particles.mouse_move_callback(Vec2::new(105.0, 112.0));
+    assert_close(step(wgpu_context, &mut particles, &mut gpu_profiler), Vec2::new(105.0, 102.0));
+
+    // The cursor stopped, so does the particle
+    assert_close(step(wgpu_context, &mut particles, &mut gpu_profiler), Vec2::new(105.0, 102.0));
+
+    // Released, the cursor no longer matters
+    particles.mouse_click_callback(false, Vec2::new(105.0, 112.0));
+    particles.mouse_move_callback(Vec2::new(300.0, 300.0));
+    assert_close(step(wgpu_context, &mut particles, &mut gpu_profiler), Vec2::new(105.0, 102.0));
+    assert!(!particles.interaction().is_active());
+}
+
+#[test]
+fn steps_recorded_into_one_encoder_see_their_own_cursor_motion_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut particles = common::create_test_particle_system(wgpu_context, vec![PARTICLE], vec![2.0]);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    particles.interaction_mut().mode = InteractionMode::Drag;
+    particles.mouse_click_callback(true, Vec2::new(100.0, 110.0));
+    particles.mouse_move_callback(Vec2::new(105.0, 112.0));
+
+    // The first step follows the cursor motion, the second one sees the cursor at rest
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    particles.record_update_positions(DELTA_TIME, wgpu_context, &mut encoder, &gpu_profiler);
+    particles.record_update_positions(DELTA_TIME, wgpu_context, &mut encoder, &gpu_profiler);
+    gpu_profiler.resolve_queries(&mut encoder);
+    wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+    gpu_profiler.end_frame().unwrap();
+    assert_close(particles.download_particle_buffers(wgpu_context).current_positions.data()[0], Vec2::new(105.0, 102.0));
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::particles::particle_system::{ParticleLimit, ParticleSystem, SpawnOverflow, SpawnReport};
+use game_engine::renderer::wgpu_context::WgpuContext;
+
+fn create_particles(wgpu_context: &WgpuContext) -> ParticleSystem {
+    common::create_test_particle_system(
+        wgpu_context,
+        vec![Vec2::new(10.0, 10.0), Vec2::new(20.0, 20.0), Vec2::new(30.0, 30.0)],
+        vec![1.0, 1.0, 1.0],
+    )
+}
+
+#[test]
+fn spawning_beyond_the_limit_is_refused_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut particles = create_particles(wgpu_context);
+    particles.set_limit(wgpu_context, ParticleLimit { max_particles: 50, overflow: SpawnOverflow::Refuse });
+
+    let report = particles.add_particles(&Vec2::new(500.0, 500.0), wgpu_context);
+    assert_eq!(report, SpawnReport { spawned: 47, recycled: 0, refused: 53 });
+    assert_eq!(particles.len(), 50);
+
+    let report = particles.add_particles(&Vec2::new(500.0, 500.0), wgpu_context);
+    assert_eq!(report, SpawnReport { spawned: 0, recycled: 0, refused: 100 });
+    assert_eq!(particles.len(), 50);
+}
+
+#[test]
+fn oldest_particles_are_recycled_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut particles = create_particles(wgpu_context);
+    particles.set_limit(wgpu_context, ParticleLimit { max_particles: 150, overflow: SpawnOverflow::RecycleOldest });
+
+    let report = particles.add_particles(&Vec2::new(500.0, 500.0), wgpu_context);
+    assert_eq!(report, SpawnReport { spawned: 100, recycled: 0, refused: 0 });
+
+    let report = particles.add_particles(&Vec2::new(500.0, 500.0), wgpu_context);
+    assert_eq!(report, SpawnReport { spawned: 47, recycled: 53, refused: 0 });
+    assert_eq!(particles.len(), 150);
+
+    // The 3 initial particles and the 50 oldest of the first batch were respawned
+    let spawn_order = particles.channels().find("spawn_order").unwrap();
+    let stride = particles.channels().stride() as usize;
+    let offset = particles.channels().offset(spawn_order) as usize;
+    let extras = particles.download_extras(wgpu_context);
+    let mut orders: Vec<u32> = (0..particles.len()).map(|i| extras[i * stride + offset]).collect();
+    orders.sort();
+    assert_eq!(orders, (51..=200).collect::<Vec<u32>>());
+
+    let positions = particles.download_particle_buffers(wgpu_context).current_positions.data().clone();
+    assert!(!positions.contains(&Vec2::new(10.0, 10.0)));
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::particles::particle_emitter::ParticleEmitter;
+use game_engine::particles::particle_system::SpawnMass;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::Simulation;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const DELTA_TIME: f32 = 0.1;
+
+fn download_masses(wgpu_context: &WgpuContext, simulation: &mut Simulation) -> Option<Vec<f32>> {
+    let particles = simulation.particles_mut();
+    particles.download_particle_buffers(wgpu_context);
+    particles.buffers().masses(particles.channels())
+}
+
+#[test]
+fn spawn_mass_test() {
+    assert_eq!(SpawnMass::Fixed(3.0).mass(10.0), 3.0);
+    assert!((SpawnMass::Density(2.0).mass(3.0) - 2.0 * std::f32::consts::PI * 9.0).abs() < 1e-4);
+    assert_eq!(SpawnMass::default(), SpawnMass::Density(1.0));
+}
+
+#[test]
+fn existing_particles_get_the_mass_of_their_radius_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = common::create_test_simulation(wgpu_context, vec![Vec2::new(100.0, 100.0), Vec2::new(200.0, 100.0)], 2.0);
+    assert_eq!(download_masses(wgpu_context, &mut simulation), None);
+
+    simulation.set_spawn_mass(wgpu_context, SpawnMass::Density(0.5));
+    let masses = download_masses(wgpu_context, &mut simulation).unwrap();
+    let expected = 0.5 * std::f32::consts::PI * 4.0;
+    assert!(masses.iter().all(|mass| (mass - expected).abs() < 1e-4), "{masses:?}");
+
+    simulation.set_particle_mass(wgpu_context, 1..2, 7.0);
+    assert_eq!(download_masses(wgpu_context, &mut simulation).unwrap()[1], 7.0);
+}
+
+#[test]
+fn light_particle_takes_most_of_the_correction_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    // Same radius, overlapping by 1 unit; the left one is 9 times heavier
+    let mut simulation = common::create_test_simulation(wgpu_context, vec![Vec2::new(100.0, 500.0), Vec2::new(103.0, 500.0)], 2.0);
+    simulation.set_particle_mass(wgpu_context, 0..1, 9.0);
+    simulation.set_particle_mass(wgpu_context, 1..2, 1.0);
+
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    simulation.step(wgpu_context, &mut gpu_profiler, DELTA_TIME, None);
+    gpu_profiler.end_frame().unwrap();
+
+    let buffers = simulation.particles_mut().download_particle_buffers(wgpu_context);
+    let mut positions = buffers.current_positions.data().to_vec();
+    positions.sort_by(|a, b| a.x.total_cmp(&b.x));
+    let heavy_displacement = 100.0 - positions[0].x;
+    let light_displacement = positions[1].x - 103.0;
+    assert!(heavy_displacement > 0.0, "{positions:?}");
+    assert!((light_displacement / heavy_displacement - 9.0).abs() < 1e-2, "{positions:?}");
+}
+
+#[test]
+fn emitter_spawns_particles_of_its_mass_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = common::create_test_simulation(wgpu_context, vec![Vec2::new(100.0, 100.0)], 2.0);
+    let mut emitter = ParticleEmitter::new(Vec2::new(500.0, 500.0), 20.0, 10.0).with_min_batch(4).with_mass(SpawnMass::Fixed(5.0));
+    assert_eq!(emitter.mass(), Some(SpawnMass::Fixed(5.0)));
+
+    let (report, _) = emitter.update(wgpu_context, &mut simulation, 0.5);
+    assert_eq!(report.spawned, 5);
+    let masses = download_masses(wgpu_context, &mut simulation).unwrap();
+    assert_eq!(masses, vec![5.0; 6]);
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::particles::particle_interaction::InteractionMode;
+use game_engine::particles::particle_system::{PARTICLE_FLAGS_CHANNEL, PINNED_FLAG};
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::Simulation;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const DELTA_TIME: f32 = 0.1;
+const LEFT: Vec2 = Vec2::new(100.0, 100.0);
+const RIGHT: Vec2 = Vec2::new(200.0, 100.0);
+
+/// Two resting particles of radius 2, stepped once so the grid is built.
+fn create_simulation(wgpu_context: &WgpuContext) -> Simulation {
+    let mut simulation = common::create_test_simulation(wgpu_context, vec![LEFT, RIGHT], 2.0);
+    step(wgpu_context, &mut simulation);
+    simulation
+}
+
+/// Steps once and returns the positions, sorted by x.
+fn step(wgpu_context: &WgpuContext, simulation: &mut Simulation) -> Vec<Vec2> {
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    simulation.step(wgpu_context, &mut gpu_profiler, DELTA_TIME, None);
+    gpu_profiler.end_frame().unwrap();
+    let mut positions = simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().clone();
+    positions.sort_by(|a, b| a.x.total_cmp(&b.x));
+    positions
+}
+
+fn assert_close(actual: Vec2, expected: Vec2) {
+    assert!((actual - expected).length() < 1e-3, "expected {:?}, got {:?}", expected, actual);
+}
+
+#[test]
+fn picked_particle_follows_the_cursor_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = create_simulation(wgpu_context);
+    simulation.particles_mut().interaction_mut().mode = InteractionMode::Pick;
+
+    assert_eq!(simulation.pick_particle(wgpu_context, Vec2::new(150.0, 150.0)), None);
+    let picked = simulation.pick_particle(wgpu_context, Vec2::new(101.0, 100.0)).unwrap();
+    let positions = simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().clone();
+    assert_close(positions[picked as usize], LEFT);
+
+    // The particle keeps its offset from the cursor, the other one is left alone
+    simulation.particles_mut().mouse_click_callback(true, Vec2::new(101.0, 100.0));
+    simulation.particles_mut().mouse_move_callback(Vec2::new(151.0, 120.0));
+    let positions = step(wgpu_context, &mut simulation);
+    assert_close(positions[0], Vec2::new(150.0, 120.0));
+    assert_close(positions[1], RIGHT);
+
+    // Released, it is thrown with the velocity of the cursor and no longer held
+    simulation.particles_mut().mouse_click_callback(false, Vec2::new(151.0, 120.0));
+    let positions = step(wgpu_context, &mut simulation);
+    assert_close(positions[0], Vec2::new(200.0, 140.0));
+    let flags_channel = simulation.particles().channels().find(PARTICLE_FLAGS_CHANNEL).unwrap();
+    for particle in 0..2 {
+        assert_eq!(simulation.particles_mut().read_channel_at(wgpu_context, flags_channel, particle), vec![0]);
+    }
+}
+
+#[test]
+fn pinned_particles_stay_in_place_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = create_simulation(wgpu_context);
+    simulation.particles_mut().set_gravity(Vec2::new(0.0, -100.0));
+    let flags_channel = simulation.enable_particle_flags(wgpu_context);
+    let left = simulation.query_circle(wgpu_context, LEFT, 1.0)[0];
+    simulation.particles_mut().write_channel_from(wgpu_context, flags_channel, left as usize, &[PINNED_FLAG]);
+
+    step(wgpu_context, &mut simulation);
+    let positions = step(wgpu_context, &mut simulation);
+    assert_close(positions[0], LEFT);
+    // a * dt^2 in the first step, twice that in the second
+    assert_close(positions[1], RIGHT - Vec2::new(0.0, 3.0));
+}
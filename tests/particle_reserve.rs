@@ -0,0 +1,60 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::grid::grid::Grid;
+use game_engine::physics::collision_system::CollisionSystem;
+use game_engine::simulation::{Simulation, DIMENSION};
+use game_engine::utils::gpu_buffer::GpuBuffer;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+#[test]
+fn gpu_buffer_reserve_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut buffer = GpuBuffer::new(wgpu_context, vec![1u32, 2, 3, 4], wgpu::BufferUsages::STORAGE);
+
+    assert!(!buffer.reserve(wgpu_context, 2));
+    assert!(buffer.reserve(wgpu_context, 64));
+    assert_eq!(buffer.capacity(), 64);
+    assert_eq!(buffer.read_back(wgpu_context).unwrap(), vec![1, 2, 3, 4]);
+
+    // Pushes within the reserved capacity write into the same GPU buffer
+    let reserved = buffer.buffer().clone();
+    buffer.push_all(&(5..=64).collect::<Vec<u32>>(), wgpu_context);
+    assert_eq!(buffer.buffer(), &reserved);
+    assert_eq!(buffer.read_back(wgpu_context).unwrap(), (1..=64).collect::<Vec<u32>>());
+}
+
+#[test]
+fn particle_batch_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let particles = common::create_test_particle_system(wgpu_context, vec![Vec2::new(100.0, 100.0), Vec2::new(200.0, 100.0)], vec![2.0, 2.0]);
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let collision_system = CollisionSystem::new(wgpu_context, DIMENSION, &particles, &grid);
+    let mut simulation = Simulation::from_parts(wgpu_context, particles, grid, collision_system);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    // The batch reserves room for the next one
+    let (report, _) = simulation.add_particle_batch(wgpu_context, 1000);
+    assert_eq!(report.spawned, 1000);
+    assert_eq!(simulation.particles().len(), 1002);
+    assert!(simulation.particles().capacity() >= 2002);
+    let positions = simulation.particles().positions().buffer().clone();
+    simulation.add_particle_batch(wgpu_context, 1000);
+    assert_eq!(simulation.particles().positions().buffer(), &positions);
+
+    let world_min = simulation.particles().get_world_origin();
+    let world_max = world_min + simulation.particles().get_world_size();
+    let spawned = simulation.particles().positions().read_back(wgpu_context).unwrap();
+    assert!(spawned[2..].iter().all(|position| position.cmpge(world_min).all() && position.cmple(world_max).all()));
+
+    simulation.step(wgpu_context, &mut gpu_profiler, 0.01, None);
+    gpu_profiler.end_frame().unwrap();
+
+    assert_eq!(simulation.remove_particle_batch(wgpu_context, &mut gpu_profiler, 1500), 1500);
+    assert_eq!(simulation.particles().len(), 502);
+    assert_eq!(simulation.remove_particle_batch(wgpu_context, &mut gpu_profiler, 1000), 502);
+    assert_eq!(simulation.particles().len(), 0);
+    assert_eq!(simulation.remove_particle_batch(wgpu_context, &mut gpu_profiler, 1000), 0);
+}
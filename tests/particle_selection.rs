@@ -0,0 +1,69 @@
+mod common;
+
+use glam::{Mat4, Vec2, Vec4};
+use game_engine::particles::particle_group::GroupOperation;
+use game_engine::particles::particle_selection::{ParticleSelectionQuery, SelectionRect};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const VIEWPORT: Vec2 = Vec2::new(200.0, 100.0);
+
+// Camera showing the world [0, 200] x [0, 100] one pixel per unit: (x, y) is drawn at pixel (x, 100 - y)
+fn view_proj() -> Mat4 {
+    Mat4::orthographic_rh(0.0, VIEWPORT.x, 0.0, VIEWPORT.y, -1.0, 1.0)
+}
+
+#[test]
+fn selects_the_particles_inside_the_rectangle_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    // Drawn at pixels (10, 10), (50, 50), (150, 80) and (40, 55)
+    let positions = vec![Vec2::new(10.0, 90.0), Vec2::new(50.0, 50.0), Vec2::new(150.0, 20.0), Vec2::new(40.0, 45.0)];
+    let particles = common::create_test_particle_system(wgpu_context, positions, vec![1.0; 4]);
+    let mut query = ParticleSelectionQuery::new(wgpu_context);
+
+    // Dragged from the bottom right to the top left corner
+    let rect = SelectionRect::from_corners(view_proj(), VIEWPORT, Vec2::new(60.0, 52.0), Vec2::ZERO);
+    assert_eq!(rect.min, Vec2::ZERO);
+    assert_eq!(rect.max, Vec2::new(60.0, 52.0));
+    assert_eq!(query.select(wgpu_context, &particles, &rect), vec![0, 1]);
+
+    let rect = SelectionRect::from_corners(view_proj(), VIEWPORT, Vec2::new(30.0, 40.0), Vec2::new(200.0, 100.0));
+    assert_eq!(query.select(wgpu_context, &particles, &rect), vec![1, 2, 3]);
+
+    let rect = SelectionRect::from_corners(view_proj(), VIEWPORT, Vec2::new(100.0, 0.0), Vec2::new(140.0, 40.0));
+    assert!(query.select(wgpu_context, &particles, &rect).is_empty());
+}
+
+#[test]
+fn group_operations_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    // Particles 0 and 1 overlap, so the collisions set them moving
+    let positions = vec![Vec2::new(50.0, 50.0), Vec2::new(52.0, 50.0), Vec2::new(150.0, 20.0)];
+    let mut simulation = common::create_test_simulation(wgpu_context, positions, 2.0);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    simulation.step(wgpu_context, &mut gpu_profiler, 0.01, None);
+    gpu_profiler.end_frame().unwrap();
+
+    // Test particle systems have a single color
+    let color = Vec4::new(1.0, 0.0, 0.5, 1.0);
+    assert_eq!(simulation.apply_group_operation(wgpu_context, &mut gpu_profiler, &[0], GroupOperation::Recolor(color)), 0);
+    assert_eq!(simulation.particles().buffers().colors.read_back(wgpu_context).unwrap(), vec![color]);
+
+    assert_eq!(simulation.apply_group_operation(wgpu_context, &mut gpu_profiler, &[0, 1], GroupOperation::Freeze), 0);
+    let buffers = simulation.particles_mut().download_particle_buffers(wgpu_context);
+    assert_eq!(buffers.current_positions.data()[..2], buffers.previous_positions.data()[..2]);
+    let frozen = buffers.current_positions.data().clone();
+
+    // The selection feeds the removal, the survivors keep their order
+    let mut query = ParticleSelectionQuery::new(wgpu_context);
+    let rect = SelectionRect::from_corners(view_proj(), VIEWPORT, Vec2::new(100.0, 0.0), VIEWPORT);
+    let selected = query.select(wgpu_context, simulation.particles(), &rect);
+    assert_eq!(selected, vec![2]);
+    assert_eq!(simulation.apply_group_operation(wgpu_context, &mut gpu_profiler, &selected, GroupOperation::Remove), 1);
+    gpu_profiler.end_frame().unwrap();
+    assert_eq!(simulation.particles().len(), 2);
+    assert_eq!(simulation.grid().num_elements(), 2);
+    let positions = simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().clone();
+    assert_eq!(positions, frozen[..2].to_vec());
+}
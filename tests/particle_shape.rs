@@ -0,0 +1,142 @@
+mod common;
+
+use glam::{UVec2, Vec2};
+use game_engine::grid::grid::Grid;
+use game_engine::particles::particle_shape::{ParticleShape, SpriteAtlas, SpriteAtlasDescriptor, SpriteAtlasError, SpriteClip, SPRITE_FRAME_CHANNEL};
+use game_engine::particles::sprite_animation::{SpriteAnimation, SPRITE_AGE_CHANNEL};
+use game_engine::physics::collision_system::CollisionSystem;
+use game_engine::simulation::{Simulation, DIMENSION};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+#[test]
+fn sprite_atlas_frames_test() {
+    // 4x2 frames of 8x8 pixels
+    let atlas = SpriteAtlas::new(32, 16, vec![0; 32 * 16 * 4], 4, 2).unwrap();
+    assert_eq!(atlas.num_frames(), 8);
+    assert_eq!(atlas.frame_uv(0), (Vec2::ZERO, Vec2::new(0.25, 0.5)));
+    assert_eq!(atlas.frame_uv(5), (Vec2::new(0.25, 0.5), Vec2::new(0.25, 0.5)));
+    // Frames past the last one wrap around
+    assert_eq!(atlas.frame_uv(9), atlas.frame_uv(1));
+}
+
+#[test]
+fn invalid_sprite_atlases_are_refused_test() {
+    assert_eq!(SpriteAtlas::new(0, 16, Vec::new(), 1, 1), Err(SpriteAtlasError::Empty));
+    assert_eq!(SpriteAtlas::new(4, 4, vec![0; 4], 1, 1), Err(SpriteAtlasError::WrongPixelCount { expected: 64, actual: 4 }));
+    assert_eq!(SpriteAtlas::new(10, 4, vec![0; 160], 3, 1), Err(SpriteAtlasError::UnevenFrames { size: UVec2::new(10, 4), columns: 3, rows: 1 }));
+    assert_eq!(SpriteAtlas::white().size(), UVec2::ONE);
+}
+
+#[test]
+fn sprite_atlas_descriptor_clips_test() {
+    let descriptor = SpriteAtlasDescriptor::grid(4, 2)
+        .with_clip("idle", SpriteClip::new(0, 2, true))
+        .with_clip("explode", SpriteClip::new(2, 6, false));
+    let atlas = SpriteAtlas::from_descriptor(32, 16, vec![0; 32 * 16 * 4], &descriptor).unwrap();
+    assert_eq!(atlas.clip("explode"), Some(SpriteClip::new(2, 6, false)));
+    assert_eq!(atlas.clip("missing"), None);
+    assert_eq!(atlas.descriptor(), descriptor);
+    assert_eq!(atlas.all_frames(true), SpriteClip::new(0, 8, true));
+
+    let too_long = SpriteClip::new(6, 3, false);
+    assert_eq!(
+        SpriteAtlas::from_descriptor(32, 16, vec![0; 32 * 16 * 4], &SpriteAtlasDescriptor::grid(4, 2).with_clip("long", too_long)),
+        Err(SpriteAtlasError::ClipOutOfRange { name: "long".to_string(), clip: too_long, num_frames: 8 })
+    );
+    let twice = SpriteAtlasDescriptor::grid(4, 2).with_clip("a", SpriteClip::new(0, 1, false)).with_clip("a", SpriteClip::new(1, 1, false));
+    assert_eq!(SpriteAtlas::from_descriptor(32, 16, vec![0; 32 * 16 * 4], &twice), Err(SpriteAtlasError::DuplicateClip("a".to_string())));
+}
+
+#[test]
+fn sprite_animation_frames_test() {
+    // 10 frames per second over frames 2, 3 and 4
+    let looping = SpriteAnimation::by_age(SpriteClip::new(2, 3, true), 10.0);
+    assert_eq!(looping.frame(0.0), 2);
+    assert_eq!(looping.frame(0.25), 4);
+    assert_eq!(looping.frame(0.35), 2);
+    let once = SpriteAnimation::by_age(SpriteClip::new(2, 3, false), 10.0);
+    assert_eq!(once.frame(10.0), 4);
+
+    // The speed spreads the clip over 0..max_speed and holds the last frame above it, even if the clip loops
+    let by_speed = SpriteAnimation::by_speed(SpriteClip::new(0, 4, true), 20.0);
+    assert_eq!(by_speed.frame(0.0), 0);
+    assert_eq!(by_speed.frame(9.0), 1);
+    assert_eq!(by_speed.frame(100.0), 3);
+}
+
+#[test]
+fn sprite_animation_advances_the_frame_channel_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let particles = common::create_test_particle_system(wgpu_context, vec![Vec2::new(100.0, 100.0), Vec2::new(200.0, 100.0)], vec![2.0, 2.0]);
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let collision_system = CollisionSystem::new(wgpu_context, DIMENSION, &particles, &grid);
+    let mut simulation = Simulation::from_parts(wgpu_context, particles, grid, collision_system);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    let animation = SpriteAnimation::by_age(SpriteClip::new(2, 3, true), 10.0);
+    simulation.set_sprite_animation(wgpu_context, Some(animation));
+    assert_eq!(simulation.particles().sprite_animation(), Some(animation));
+    let channels = simulation.particles().channels();
+    let stride = channels.stride() as usize;
+    let frame_offset = channels.offset(channels.find(SPRITE_FRAME_CHANNEL).unwrap()) as usize;
+    let age_offset = channels.offset(channels.find(SPRITE_AGE_CHANNEL).unwrap()) as usize;
+
+    let read = |simulation: &mut Simulation| {
+        let extras = simulation.particles_mut().download_extras(wgpu_context);
+        (0..2).map(|i| (extras[i * stride + frame_offset], f32::from_bits(extras[i * stride + age_offset]))).collect::<Vec<_>>()
+    };
+    simulation.update_sprite_animation(wgpu_context, &mut gpu_profiler, 0.25, 0.01);
+    gpu_profiler.end_frame().unwrap();
+    assert_eq!(read(&mut simulation), vec![(4, 0.25), (4, 0.25)]);
+    // Loops back to the first frame of the clip
+    simulation.update_sprite_animation(wgpu_context, &mut gpu_profiler, 0.1, 0.01);
+    gpu_profiler.end_frame().unwrap();
+    assert!(read(&mut simulation).iter().all(|&(frame, age)| frame == 2 && (age - 0.35).abs() < 1e-6));
+
+    // The particles are at rest: the first frame of the speed clip
+    simulation.set_sprite_animation(wgpu_context, Some(SpriteAnimation::by_speed(SpriteClip::new(1, 4, false), 50.0)));
+    simulation.update_sprite_animation(wgpu_context, &mut gpu_profiler, 0.1, 0.01);
+    gpu_profiler.end_frame().unwrap();
+    assert!(read(&mut simulation).iter().all(|&(frame, _)| frame == 1));
+}
+
+#[test]
+fn shape_toggle_cycles_the_built_in_shapes_test() {
+    assert_eq!(ParticleShape::default(), ParticleShape::Circle);
+    assert_eq!(ParticleShape::Circle.next_built_in(), ParticleShape::Square);
+    assert_eq!(ParticleShape::Square.next_built_in(), ParticleShape::Hexagon);
+    assert_eq!(ParticleShape::Hexagon.next_built_in(), ParticleShape::Circle);
+    assert_eq!(ParticleShape::Sprite.next_built_in(), ParticleShape::Circle);
+    // Ids are distinct, the drawer switches on them
+    let mut ids: Vec<u32> = [ParticleShape::Circle, ParticleShape::Square, ParticleShape::Hexagon, ParticleShape::Sprite].iter().map(ParticleShape::id).collect();
+    ids.dedup();
+    assert_eq!(ids.len(), 4);
+}
+
+#[test]
+fn sprite_shape_registers_the_frame_channel_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let particles = common::create_test_particle_system(wgpu_context, vec![Vec2::new(100.0, 100.0), Vec2::new(101.0, 100.0)], vec![2.0, 2.0]);
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let collision_system = CollisionSystem::new(wgpu_context, DIMENSION, &particles, &grid);
+    let mut simulation = Simulation::from_parts(wgpu_context, particles, grid, collision_system);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    simulation.set_particle_shape(wgpu_context, ParticleShape::Hexagon);
+    assert_eq!(simulation.particles().shape(), ParticleShape::Hexagon);
+    assert!(simulation.particles().channels().find(SPRITE_FRAME_CHANNEL).is_none());
+
+    simulation.set_particle_shape(wgpu_context, ParticleShape::Sprite);
+    let frames = simulation.particles().channels().find(SPRITE_FRAME_CHANNEL).unwrap();
+    simulation.write_channel(wgpu_context, frames, &[3, 5]);
+    simulation.particles_mut().set_sprite_atlas(wgpu_context, SpriteAtlas::new(2, 2, vec![255; 16], 2, 2).unwrap());
+    assert_eq!(simulation.particles().sprite_atlas().unwrap().num_frames(), 4);
+
+    // The collisions still see the particles after the channel was added
+    simulation.step(wgpu_context, &mut gpu_profiler, 0.01, None);
+    gpu_profiler.end_frame().unwrap();
+    let positions = simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().to_vec();
+    assert!(positions[0].distance(positions[1]) > 1.0, "{positions:?}");
+}
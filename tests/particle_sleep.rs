@@ -0,0 +1,98 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::particles::particle_system::SLEEPING_FLAG;
+use game_engine::physics::particle_sleep::{SleepConfig, SLEEP_CHANNEL};
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::Simulation;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const DELTA_TIME: f32 = 0.1;
+/// Asleep after two steps slower than 5 units per second, half a unit per step.
+const QUICK_SLEEP: SleepConfig = SleepConfig { sleep_speed: 5.0, sleep_steps: 2 };
+
+/// Particles of radius 2 at rest, without gravity.
+fn create_simulation(wgpu_context: &WgpuContext, positions: Vec<Vec2>) -> Simulation {
+    let mut simulation = common::create_test_simulation(wgpu_context, positions, 2.0);
+    simulation.particles_mut().set_gravity(Vec2::ZERO);
+    simulation
+}
+
+fn step(wgpu_context: &WgpuContext, simulation: &mut Simulation, steps: u32) {
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    for _ in 0..steps {
+        simulation.step(wgpu_context, &mut gpu_profiler, DELTA_TIME, None);
+    }
+    gpu_profiler.end_frame().unwrap();
+}
+
+fn download_positions(wgpu_context: &WgpuContext, simulation: &mut Simulation) -> Vec<Vec2> {
+    simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().to_vec()
+}
+
+#[test]
+fn enable_sleep_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = create_simulation(wgpu_context, vec![Vec2::new(100.0, 100.0)]);
+    assert_eq!(simulation.sleep_config(), None);
+    assert_eq!(simulation.count_sleeping_particles(wgpu_context), 0);
+
+    let id = simulation.enable_sleep(wgpu_context, SleepConfig::default());
+    assert_eq!(simulation.particles().channels().find(SLEEP_CHANNEL), Some(id));
+    assert_eq!(simulation.sleep_config(), Some(SleepConfig::default()));
+
+    // Enabled again, only the config changes
+    assert_eq!(simulation.enable_sleep(wgpu_context, QUICK_SLEEP), id);
+    assert_eq!(simulation.sleep_config(), Some(QUICK_SLEEP));
+
+    simulation.disable_sleep(wgpu_context);
+    assert_eq!(simulation.sleep_config(), None);
+}
+
+#[test]
+fn still_particles_fall_asleep_and_ignore_gravity_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let start = Vec2::new(100.0, 500.0);
+    let mut simulation = create_simulation(wgpu_context, vec![start]);
+    simulation.enable_sleep(wgpu_context, QUICK_SLEEP);
+
+    step(wgpu_context, &mut simulation, 1);
+    assert_eq!(simulation.count_sleeping_particles(wgpu_context), 0);
+    step(wgpu_context, &mut simulation, 1);
+    assert_eq!(simulation.count_sleeping_particles(wgpu_context), 1);
+
+    // Asleep, the integration leaves it in place
+    simulation.particles_mut().set_gravity(Vec2::new(0.0, -100.0));
+    step(wgpu_context, &mut simulation, 2);
+    assert_eq!(download_positions(wgpu_context, &mut simulation)[0], start);
+
+    simulation.wake_particles(wgpu_context);
+    assert_eq!(simulation.count_sleeping_particles(wgpu_context), 0);
+    step(wgpu_context, &mut simulation, 1);
+    assert!(download_positions(wgpu_context, &mut simulation)[0].y < start.y);
+}
+
+#[test]
+fn moving_particle_wakes_its_sleeping_neighbour_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    // Two sleeping particles, 2.5 units apart; the upper one is woken up and falls onto the lower one
+    let mut simulation = create_simulation(wgpu_context, vec![Vec2::new(100.0, 500.0), Vec2::new(100.0, 506.5)]);
+    let id = simulation.enable_sleep(wgpu_context, QUICK_SLEEP);
+    step(wgpu_context, &mut simulation, 2);
+    assert_eq!(simulation.count_sleeping_particles(wgpu_context), 2);
+
+    simulation.particles_mut().set_gravity(Vec2::new(0.0, -100.0));
+    let positions = download_positions(wgpu_context, &mut simulation);
+    let upper = if positions[0].y > positions[1].y { 0 } else { 1 };
+    simulation.particles_mut().set_particle_flag(wgpu_context, upper..upper + 1, SLEEPING_FLAG, false);
+    simulation.particles_mut().write_channel_from(wgpu_context, id, upper, &[0]);
+
+    // One unit per step squared: 505.5, then 503.5, close enough to wake the lower one
+    step(wgpu_context, &mut simulation, 2);
+    assert_eq!(simulation.count_sleeping_particles(wgpu_context), 1);
+    step(wgpu_context, &mut simulation, 1);
+    assert_eq!(simulation.count_sleeping_particles(wgpu_context), 0);
+}
@@ -1,6 +1,6 @@
 use glam::Vec2;
 use wgpu::wgt::PollType::WaitForSubmissionIndex;
-use wgpu_profiler::{GpuProfiler, GpuProfilerSettings};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
 use game_engine::grid::grid::Grid;
 
 mod common;
@@ -68,4 +68,46 @@ fn sort_particles_test(){
     assert_eq!(expected_particle_positions, *actual_particle_positions);
     assert_eq!(expected_previous_positions, *actual_previous_positions);
     assert_eq!(expected_radii, *actual_radii);
-}
\ No newline at end of file
+}
+#[test]
+fn sort_particles_moves_channels_test(){
+
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    let device = wgpu_context.get_device();
+    let queue = wgpu_context.get_queue();
+
+    let max_radius = 10.0;
+
+    // Same case as sort_particles_test: sorted order is [2, 0, 1]
+    let particle_positions = vec![
+        Vec2::new(20.0, 42.0),
+        Vec2::new(77.0, 77.0),
+        Vec2::new(5.0, 5.0),
+    ];
+    let particle_radii = vec![10.0, 8.0, 1.0];
+
+    let mut particle_system = common::create_test_particle_system(
+        wgpu_context,
+        particle_positions,
+        particle_radii,
+    );
+
+    let tag = particle_system.register_channel(wgpu_context, "tag", &[0]);
+    let pair = particle_system.register_channel(wgpu_context, "pair", &[7, 8]);
+    particle_system.write_channel(wgpu_context, tag, &[100, 101, 102]);
+    assert_eq!(particle_system.channels().stride(), 3);
+    assert_eq!(particle_system.download_extras(wgpu_context), vec![100, 7, 8, 101, 7, 8, 102, 7, 8]);
+
+    let mut encoder = wgpu_context.get_device().create_command_encoder(
+        &wgpu::CommandEncoderDescriptor { label: Some("Particle sort channels test Encoder") }
+    );
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    particle_system.sort_by_cell_id(&mut encoder, &mut gpu_profiler, Grid::compute_cell_size(max_radius));
+    let idx = queue.submit([encoder.finish()]);
+    device.poll(WaitForSubmissionIndex(idx)).unwrap();
+
+    assert_eq!(particle_system.channels().find("pair"), Some(pair));
+    assert_eq!(particle_system.download_extras(wgpu_context), vec![102, 7, 8, 100, 7, 8, 101, 7, 8]);
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::Simulation;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const DELTA_TIME: f32 = 0.1;
+
+fn step(wgpu_context: &WgpuContext, simulation: &mut Simulation) {
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    simulation.step(wgpu_context, &mut gpu_profiler, DELTA_TIME, None);
+    gpu_profiler.end_frame().unwrap();
+}
+
+fn update_trails(wgpu_context: &WgpuContext, simulation: &mut Simulation) {
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    simulation.update_trails(wgpu_context, &mut gpu_profiler);
+    gpu_profiler.end_frame().unwrap();
+}
+
+fn positions(wgpu_context: &WgpuContext, simulation: &mut Simulation) -> Vec<Vec2> {
+    simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().clone()
+}
+
+#[test]
+fn trail_keeps_the_last_positions_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let start = Vec2::new(500.0, 500.0);
+    let mut simulation = common::create_test_simulation(wgpu_context, vec![start], 2.0);
+    simulation.particles_mut().set_gravity(Vec2::new(0.0, -100.0));
+    simulation.set_trails(wgpu_context, Some(4));
+    assert_eq!(simulation.trail_length(), Some(4));
+
+    // The first update fills the whole ring with the current position
+    update_trails(wgpu_context, &mut simulation);
+    assert_eq!(simulation.particles_mut().download_trail(wgpu_context, 0), Some(vec![start; 4]));
+
+    let mut expected = vec![start; 4];
+    for _ in 0..5 {
+        step(wgpu_context, &mut simulation);
+        update_trails(wgpu_context, &mut simulation);
+        expected.insert(0, positions(wgpu_context, &mut simulation)[0]);
+        expected.truncate(4);
+    }
+    assert_eq!(simulation.particles_mut().download_trail(wgpu_context, 0), Some(expected));
+
+    simulation.set_trails(wgpu_context, None);
+    assert_eq!(simulation.trail_length(), None);
+    assert_eq!(simulation.particles_mut().download_trail(wgpu_context, 0), None);
+}
+
+#[test]
+fn trails_follow_the_particles_through_the_sort_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    // The first step sorts the particles by cell, which swaps them
+    let initial = vec![Vec2::new(500.0, 500.0), Vec2::new(100.0, 100.0)];
+    let mut simulation = common::create_test_simulation(wgpu_context, initial.clone(), 2.0);
+    simulation.set_trails(wgpu_context, Some(3));
+    update_trails(wgpu_context, &mut simulation);
+
+    step(wgpu_context, &mut simulation);
+    update_trails(wgpu_context, &mut simulation);
+    let sorted = positions(wgpu_context, &mut simulation);
+    assert_eq!(sorted, vec![initial[1], initial[0]]);
+    for (particle, position) in sorted.into_iter().enumerate() {
+        assert_eq!(simulation.particles_mut().download_trail(wgpu_context, particle), Some(vec![position; 3]));
+    }
+
+    // Spawning restarts the trails, every particle gets a slot
+    simulation.add_particles_at(wgpu_context, &[Vec2::new(300.0, 300.0)]);
+    update_trails(wgpu_context, &mut simulation);
+    let current = positions(wgpu_context, &mut simulation);
+    for (particle, position) in current.into_iter().enumerate() {
+        assert_eq!(simulation.particles_mut().download_trail(wgpu_context, particle), Some(vec![position; 3]));
+    }
+}
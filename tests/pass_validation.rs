@@ -0,0 +1,75 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::grid::grid::Grid;
+use game_engine::physics::collision_system::CollisionSystem;
+use game_engine::physics::pass_validation::{PassOrderError, PassValidator, PhysicsPass};
+use game_engine::simulation::{Simulation, DIMENSION};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const REQUIRED: [PhysicsPass; 4] = [PhysicsPass::BuildCellIds, PhysicsPass::SortMap, PhysicsPass::BuildCollisionCells, PhysicsPass::SolveCollisions];
+
+#[test]
+fn pass_order_test() {
+    let mut validator = PassValidator::new();
+    for pass in [PhysicsPass::ParticleSort, PhysicsPass::BuildCellIds, PhysicsPass::SortMap, PhysicsPass::BuildCollisionCells, PhysicsPass::SolveCollisions, PhysicsPass::Integration] {
+        assert_eq!(validator.record(pass), Ok(()));
+    }
+    assert_eq!(validator.trace().len(), 6);
+    assert_eq!(validator.finish_step(&REQUIRED), Ok(()));
+    assert!(validator.trace().is_empty());
+
+    // Solving before the collision cells of this step are built
+    validator.record(PhysicsPass::BuildCellIds).unwrap();
+    validator.record(PhysicsPass::SortMap).unwrap();
+    assert!(matches!(
+        validator.record(PhysicsPass::SolveCollisions),
+        Err(PassOrderError::MissingDependency { pass: PhysicsPass::SolveCollisions, dependency: PhysicsPass::BuildCollisionCells, .. })
+    ));
+    validator.finish_step(&[]).unwrap();
+
+    // Building the cell ids after the map was sorted
+    validator.record(PhysicsPass::BuildCellIds).unwrap();
+    validator.record(PhysicsPass::SortMap).unwrap();
+    assert!(matches!(
+        validator.record(PhysicsPass::BuildCellIds),
+        Err(PassOrderError::OutOfOrder { pass: PhysicsPass::BuildCellIds, after: PhysicsPass::SortMap, .. })
+    ));
+    validator.finish_step(&[]).unwrap();
+
+    // A step that forgot the solve
+    for pass in [PhysicsPass::BuildCellIds, PhysicsPass::SortMap, PhysicsPass::BuildCollisionCells, PhysicsPass::Integration] {
+        validator.record(pass).unwrap();
+    }
+    assert!(matches!(
+        validator.finish_step(&REQUIRED),
+        Err(PassOrderError::MissingPass { pass: PhysicsPass::SolveCollisions, .. })
+    ));
+}
+
+#[test]
+fn simulation_pass_validation_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let particles = common::create_test_particle_system(wgpu_context, vec![Vec2::new(100.0, 100.0), Vec2::new(101.0, 100.0)], vec![2.0, 2.0]);
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let collision_system = CollisionSystem::new(wgpu_context, DIMENSION, &particles, &grid);
+    let mut simulation = Simulation::from_parts(wgpu_context, particles, grid, collision_system);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    assert!(!simulation.is_pass_validation_enabled());
+    simulation.set_pass_validation(true);
+    assert!(simulation.is_pass_validation_enabled());
+
+    // Panics if the step encodes its passes out of order, with the first step sorting the particles
+    for indirect in [false, true] {
+        simulation.set_indirect_dispatch(indirect);
+        for _ in 0..2 {
+            simulation.step(wgpu_context, &mut gpu_profiler, 0.01, None);
+            gpu_profiler.end_frame().unwrap();
+        }
+    }
+    simulation.capture_step(wgpu_context, &mut gpu_profiler, 0.01, None);
+    gpu_profiler.end_frame().unwrap();
+    assert_eq!(simulation.stats(wgpu_context).step_count, 5);
+}
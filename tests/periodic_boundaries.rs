@@ -0,0 +1,129 @@
+mod common;
+
+use glam::{UVec2, Vec2};
+use game_engine::grid::grid::Grid;
+use game_engine::grid::morton;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::{Simulation, COMPACTION_INTERVAL_STEPS};
+use game_engine::simulation_config::BoundaryMode;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const UNUSED_CELL_ID: u32 = 0xffffffff;
+const DELTA_TIME: f32 = 0.1;
+
+fn m(x: u32, y: u32) -> u32 {
+    morton::encode(UVec2::new(x, y))
+}
+
+fn step(wgpu_context: &WgpuContext, simulation: &mut Simulation) {
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    simulation.step(wgpu_context, &mut gpu_profiler, DELTA_TIME, None);
+    gpu_profiler.end_frame().unwrap();
+}
+
+/// Positions and velocities (displacement per step) of the particles.
+fn download_particles(wgpu_context: &WgpuContext, simulation: &mut Simulation) -> Vec<(Vec2, Vec2)> {
+    let buffers = simulation.particles_mut().download_particle_buffers(wgpu_context);
+    buffers.current_positions.data().iter()
+        .zip(buffers.previous_positions.data())
+        .map(|(position, previous)| (*position, *position - *previous))
+        .collect()
+}
+
+#[test]
+fn periodic_cells_test() {
+    assert_eq!(Grid::periodic_cells(Vec2::new(88.0, 88.0), 22.0), UVec2::new(4, 4));
+    // Rounded down to an even count, so the colors alternate across the seam
+    assert_eq!(Grid::periodic_cells(Vec2::new(70.0, 100.0), 22.0), UVec2::new(2, 4));
+    assert_eq!(Grid::periodic_cells(Vec2::new(10.0, 10.0), 22.0), UVec2::new(2, 2));
+}
+
+#[test]
+fn periodic_grid_wraps_the_neighbour_cells_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let positions = vec![
+        // Particle 0: Crosses the right edge and the top of its cell
+        Vec2::new(85.0, 40.0),
+        // Particle 1: Slightly left of the world, in the last column
+        Vec2::new(-2.0, 5.0),
+    ];
+    let particle_system = common::create_test_particle_system(wgpu_context, positions, vec![10.0, 1.0]);
+    // Cells are 22 units wide, 4 of them fit in the period
+    let mut grid = Grid::new_without_camera(wgpu_context, 10.0, &particle_system);
+    grid.set_period(Some(Vec2::new(88.0, 88.0)));
+    assert_eq!(grid.period(), Some(Vec2::new(88.0, 88.0)));
+
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    grid.build_cell_ids(&mut encoder);
+    wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+
+    let expected_cell_ids = vec![
+        m(3, 1), m(0, 1), m(3, 2), m(0, 2),
+        m(3, 0), UNUSED_CELL_ID, UNUSED_CELL_ID, UNUSED_CELL_ID,
+    ];
+    assert_eq!(grid.download_cell_ids(wgpu_context).unwrap(), expected_cell_ids);
+    assert_eq!(grid.download_object_ids(wgpu_context).unwrap(), vec![0, 0, 0, 0, 1, 0, 0, 0]);
+
+    grid.set_period(None);
+    assert_eq!(grid.period(), None);
+}
+
+#[test]
+fn wrapping_particle_keeps_its_velocity_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = common::create_test_simulation(wgpu_context, vec![Vec2::new(1915.0, 500.0)], 2.0);
+    simulation.set_boundary_mode(BoundaryMode::Wrap);
+    assert_eq!(simulation.particles().boundary_mode(), BoundaryMode::Wrap);
+    assert_eq!(simulation.grid().period(), Some(Vec2::new(1920.0, 1080.0)));
+
+    // 10 units to the right in the step, 5 past the edge
+    simulation.particles_mut().set_gravity(Vec2::new(1000.0, 0.0));
+    step(wgpu_context, &mut simulation);
+    let (position, velocity) = download_particles(wgpu_context, &mut simulation)[0];
+    assert!((position - Vec2::new(5.0, 500.0)).length() < 1e-2, "{position:?}");
+    assert!((velocity - Vec2::new(10.0, 0.0)).length() < 1e-2, "{velocity:?}");
+}
+
+#[test]
+fn particles_collide_across_the_seam_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    // 2 units apart through the left and right edges, overlapping by 2
+    let mut simulation = common::create_test_simulation(wgpu_context, vec![Vec2::new(1.0, 500.0), Vec2::new(1919.0, 500.0)], 2.0);
+    simulation.set_boundary_mode(BoundaryMode::Wrap);
+
+    step(wgpu_context, &mut simulation);
+    let mut positions: Vec<Vec2> = download_particles(wgpu_context, &mut simulation).into_iter().map(|(position, _)| position).collect();
+    positions.sort_by(|a, b| a.x.total_cmp(&b.x));
+    let (left, right) = (positions[0], positions[1]);
+    // Pushed apart across the seam, not through the world
+    assert!(left.x > 1.0 && left.x < 960.0, "{left:?}");
+    assert!(right.x < 1919.0 && right.x > 960.0, "{right:?}");
+    let distance = left.x + 1920.0 - right.x;
+    assert!(distance > 3.0, "{distance}");
+}
+
+#[test]
+fn open_boundary_deletes_the_leaving_particles_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = common::create_test_simulation(wgpu_context, vec![Vec2::new(1915.0, 500.0), Vec2::new(100.0, 500.0)], 2.0);
+    simulation.set_boundary_mode(BoundaryMode::Open);
+
+    // The first particle leaves through the right edge, the second one keeps drifting inside
+    simulation.particles_mut().set_gravity(Vec2::new(1000.0, 0.0));
+    step(wgpu_context, &mut simulation);
+    simulation.particles_mut().set_gravity(Vec2::ZERO);
+    for _ in 1..COMPACTION_INTERVAL_STEPS - 1 {
+        step(wgpu_context, &mut simulation);
+    }
+    assert_eq!(simulation.particles().len(), 2);
+    step(wgpu_context, &mut simulation);
+    assert_eq!(simulation.particles().len(), 1);
+
+    let (position, velocity) = download_particles(wgpu_context, &mut simulation)[0];
+    assert!((position - Vec2::new(100.0 + 10.0 * COMPACTION_INTERVAL_STEPS as f32, 500.0)).length() < 1e-1, "{position:?}");
+    assert!((velocity - Vec2::new(10.0, 0.0)).length() < 1e-2, "{velocity:?}");
+}
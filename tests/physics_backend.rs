@@ -0,0 +1,44 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::grid::grid::Grid;
+use game_engine::physics::collision_system::CollisionSystem;
+use game_engine::physics_backend::{GpuBackend, PhysicsBackend};
+use game_engine::simulation::{Simulation, DIMENSION};
+
+/// Runs against any backend: spawns a batch into the 1920x1080 test world and steps it.
+fn spawn_and_settle_scenario(backend: &mut dyn PhysicsBackend) {
+    let initial_count = backend.stats().particle_count;
+    let report = backend.spawn(Vec2::new(960.0, 540.0));
+    assert!(report.spawned > 0, "{}: {report:?}", backend.name());
+
+    for _ in 0..10 {
+        backend.step(0.01);
+    }
+
+    let stats = backend.stats();
+    assert_eq!(stats.step_count, 10, "{}", backend.name());
+    assert!((stats.simulated_time - 0.1).abs() < 1e-6, "{}: {stats:?}", backend.name());
+    assert_eq!(stats.particle_count, initial_count + report.spawned, "{}", backend.name());
+
+    let positions = backend.positions();
+    assert_eq!(positions.len(), stats.particle_count);
+    assert!(
+        positions.iter().all(|position| position.is_finite() && position.x >= 0.0 && position.y >= 0.0 && position.x <= 1920.0 && position.y <= 1080.0),
+        "{}: particles left the world", backend.name()
+    );
+}
+
+#[test]
+fn gpu_backend_scenario_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let particles = common::create_test_particle_system(wgpu_context, vec![Vec2::new(100.0, 100.0)], vec![2.0]);
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let collision_system = CollisionSystem::new(wgpu_context, DIMENSION, &particles, &grid);
+    let simulation = Simulation::from_parts(wgpu_context, particles, grid, collision_system);
+
+    let mut backend = GpuBackend::new(wgpu_context, simulation);
+    assert_eq!(backend.name(), "gpu");
+    spawn_and_settle_scenario(&mut backend);
+}
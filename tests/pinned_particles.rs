@@ -0,0 +1,64 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::particles::particle_system::{PARTICLE_FLAGS_CHANNEL, PINNED_FLAG};
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::Simulation;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const DELTA_TIME: f32 = 0.1;
+
+/// Steps once and returns the positions, sorted by x.
+fn step(wgpu_context: &WgpuContext, simulation: &mut Simulation) -> Vec<Vec2> {
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    simulation.step(wgpu_context, &mut gpu_profiler, DELTA_TIME, None);
+    gpu_profiler.end_frame().unwrap();
+    let mut positions = simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().clone();
+    positions.sort_by(|a, b| a.x.total_cmp(&b.x));
+    positions
+}
+
+#[test]
+fn pinned_particles_are_not_pushed_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let left = Vec2::new(100.0, 100.0);
+    let right = Vec2::new(103.0, 100.0);
+
+    let mut free = common::create_test_simulation(wgpu_context, vec![left, right], 2.0);
+    let free_positions = step(wgpu_context, &mut free);
+
+    let mut pinned = common::create_test_simulation(wgpu_context, vec![left, right], 2.0);
+    pinned.pin_particles(wgpu_context, 0..1);
+    let pinned_positions = step(wgpu_context, &mut pinned);
+
+    // The free particle takes the whole correction
+    assert_eq!(pinned_positions[0], left);
+    assert!(free_positions[0].x < left.x);
+    let free_push = free_positions[1].x - right.x;
+    let pinned_push = pinned_positions[1].x - right.x;
+    assert!((pinned_push - 2.0 * free_push).abs() < 1e-3, "pushed {} pinned, {} free", pinned_push, free_push);
+}
+
+#[test]
+fn pin_and_unpin_ranges_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let positions = vec![Vec2::new(100.0, 100.0), Vec2::new(200.0, 100.0), Vec2::new(300.0, 100.0)];
+    let mut simulation = common::create_test_simulation(wgpu_context, positions.clone(), 2.0);
+    simulation.particles_mut().set_gravity(Vec2::new(0.0, -100.0));
+
+    simulation.pin_particles(wgpu_context, 0..3);
+    assert_eq!(step(wgpu_context, &mut simulation), positions);
+
+    // Sorting by cell keeps the order of particles on the same row, so index 1 is still the middle one
+    simulation.unpin_particles(wgpu_context, 1..2);
+    let moved = step(wgpu_context, &mut simulation);
+    assert_eq!(moved[0], positions[0]);
+    assert!((moved[1] - Vec2::new(200.0, 99.0)).length() < 1e-3, "got {:?}", moved[1]);
+    assert_eq!(moved[2], positions[2]);
+
+    let flags_channel = simulation.particles().channels().find(PARTICLE_FLAGS_CHANNEL).unwrap();
+    let flags: Vec<u32> = (0..3).map(|particle| simulation.particles_mut().read_channel_at(wgpu_context, flags_channel, particle)[0]).collect();
+    assert_eq!(flags.iter().filter(|&&flags| flags == PINNED_FLAG).count(), 2);
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use game_engine::renderer::wgpu_context::{WgpuContext, TEST_FEATURES};
+
+fn cache_dir(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("pipeline_cache_test_{}_{}", name, std::process::id()))
+}
+
+/// A context that requests `Features::PIPELINE_CACHE`, `None` on adapters without it (every backend but Vulkan).
+fn context_with_pipeline_cache() -> Option<WgpuContext> {
+    let wgpu_context = pollster::block_on(WgpuContext::new_for_test_with_features(TEST_FEATURES | wgpu::Features::PIPELINE_CACHE)).unwrap();
+    wgpu_context.get_device().features().contains(wgpu::Features::PIPELINE_CACHE).then_some(wgpu_context)
+}
+
+#[test]
+fn without_the_feature_nothing_is_cached_test() {
+    let mut setup = pollster::block_on(common::setup());
+    let wgpu_context = &mut setup.wgpu_context;
+    let dir = cache_dir("disabled");
+    assert!(!wgpu_context.load_pipeline_cache(&dir));
+    assert!(wgpu_context.pipeline_cache().is_none());
+
+    wgpu_context.save_pipeline_cache().unwrap();
+    assert!(!dir.exists());
+}
+
+#[test]
+fn cache_is_saved_and_loaded_by_the_next_run_test() {
+    let Some(mut wgpu_context) = context_with_pipeline_cache() else {
+        return;
+    };
+    let dir = cache_dir("enabled");
+    assert!(wgpu_context.load_pipeline_cache(&dir));
+    assert!(wgpu_context.pipeline_cache().is_some());
+
+    // Compiles the kernels of the particles through the cache
+    let particles = common::create_test_particle_system(&wgpu_context, vec![glam::Vec2::new(1.0, 1.0)], vec![1.0]);
+    wgpu_context.save_pipeline_cache().unwrap();
+    let files: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+    assert_eq!(files.len(), 1, "{:?}", files);
+    assert!(std::fs::metadata(&files[0]).unwrap().len() > 0);
+    drop(particles);
+
+    // A new context starts from the saved file and creates the same pipelines
+    let mut next_run = context_with_pipeline_cache().unwrap();
+    assert!(next_run.load_pipeline_cache(&dir));
+    let _particles = common::create_test_particle_system(&next_run, vec![glam::Vec2::new(1.0, 1.0)], vec![1.0]);
+    next_run.save_pipeline_cache().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
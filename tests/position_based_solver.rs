@@ -0,0 +1,71 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::grid::grid::Grid;
+use game_engine::physics::collision_system::{BroadphaseMode, CollisionSystem, SolverConfig, SolverMethod};
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::{Simulation, DIMENSION};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const START: [Vec2; 2] = [Vec2::new(100.0, 100.0), Vec2::new(101.0, 100.0)];
+
+/// Steps once two overlapping particles one unit apart and returns their positions.
+fn positions_after_step(wgpu_context: &WgpuContext, broadphase_mode: BroadphaseMode, radii: Vec<f32>, config: SolverConfig) -> Vec<Vec2> {
+    let particles = common::create_test_particle_system(wgpu_context, START.to_vec(), radii);
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let collision_system = CollisionSystem::new_with_config(wgpu_context, DIMENSION, &particles, &grid, config);
+    let mut simulation = Simulation::from_parts(wgpu_context, particles, grid, collision_system);
+    simulation.set_broadphase_mode(wgpu_context, broadphase_mode);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    simulation.step(wgpu_context, &mut gpu_profiler, 0.01, None);
+    gpu_profiler.end_frame().unwrap();
+    simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().to_vec()
+}
+
+#[test]
+fn iteration_stiffness_test() {
+    let relaxation = SolverConfig { stiffness: 0.5, iterations: 4, ..SolverConfig::default() };
+    assert_eq!(relaxation.iteration_stiffness(), 0.5);
+
+    let position_based = SolverConfig { method: SolverMethod::PositionBased, ..relaxation };
+    let remaining = (1.0 - position_based.iteration_stiffness()).powi(4);
+    assert!((remaining - 0.5).abs() < 1e-5, "{}", remaining);
+}
+
+#[test]
+fn position_based_correction_does_not_depend_on_the_iterations_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    for broadphase_mode in [BroadphaseMode::CollisionCells, BroadphaseMode::CellRanges] {
+        let config = |method, iterations| SolverConfig { stiffness: 0.5, iterations, method, ..SolverConfig::default() };
+        let distance = |config| {
+            let positions = positions_after_step(wgpu_context, broadphase_mode, vec![2.0, 2.0], config);
+            positions[0].distance(positions[1])
+        };
+
+        let one_iteration = distance(config(SolverMethod::PositionBased, 1));
+        let four_iterations = distance(config(SolverMethod::PositionBased, 4));
+        assert!((one_iteration - four_iterations).abs() < 1e-3, "{:?}: {} {}", broadphase_mode, one_iteration, four_iterations);
+
+        // Relaxing four times corrects more than once
+        let relaxed = distance(config(SolverMethod::Relaxation, 4));
+        assert!(relaxed > four_iterations + 0.1, "{:?}: {} {}", broadphase_mode, relaxed, four_iterations);
+    }
+}
+
+#[test]
+fn position_based_weights_by_area_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let config = SolverConfig { stiffness: 1.0, method: SolverMethod::PositionBased, ..SolverConfig::default() };
+
+    for broadphase_mode in [BroadphaseMode::CollisionCells, BroadphaseMode::CellRanges] {
+        let positions = positions_after_step(wgpu_context, broadphase_mode, vec![1.0, 2.0], config);
+        let small_moved = positions[0].distance(START[0]);
+        let large_moved = positions[1].distance(START[1]);
+        // The small particle has a quarter of the mass of the large one
+        assert!((small_moved / large_moved - 4.0).abs() < 1e-2, "{:?}: {} {}", broadphase_mode, small_moved, large_moved);
+    }
+}
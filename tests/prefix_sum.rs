@@ -1,5 +1,6 @@
 use rand::{random_range};
 use wgpu::wgt::PollType::WaitForSubmissionIndex;
+use game_engine::renderer::wgpu_context::WgpuContext;
 use game_engine::utils::gpu_buffer::GpuBuffer;
 use game_engine::utils::prefix_sum::prefix_sum::PrefixSum;
 
@@ -240,4 +241,103 @@ fn inclusive_prefix_sum_resize_test() {
     assert_eq!(result.len(), expected_data.len());
     assert_eq!(*result, expected_data);
     
-}
\ No newline at end of file
+}
+#[test]
+fn exclusive_prefix_sum_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    // Over LIMIT, so the block sums are scanned by a nested prefix sum
+    let n = 70_001;
+    let original_values: Vec<u32> = (0u32..n).map(|_| random_range(0u32..=9u32)).collect();
+    let buffer_data = GpuBuffer::new(wgpu_context, original_values.clone(), wgpu::BufferUsages::STORAGE);
+    let prefix_sum = PrefixSum::new(wgpu_context, &buffer_data);
+
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Testing exclusive prefix sum"),
+    });
+    prefix_sum.execute_exclusive(wgpu_context, &mut encoder, n);
+    let idx = wgpu_context.get_queue().submit([encoder.finish()]);
+    wgpu_context.get_device().poll(WaitForSubmissionIndex(idx)).unwrap();
+
+    let expected_data: Vec<u32> = original_values.iter().scan(0, |sum, i| {
+        let exclusive = *sum;
+        *sum += *i;
+        Some(exclusive)
+    }).collect();
+    assert_eq!(buffer_data.read_back(wgpu_context).unwrap(), expected_data);
+}
+
+#[test]
+fn f32_prefix_sum_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    // Powers of two below 1, whose sums are exact in f32
+    let n = 5_000;
+    let original_values: Vec<f32> = (0..n).map(|i| 1.0 / (1 << (i % 4)) as f32).collect();
+    let buffer_data = GpuBuffer::new(wgpu_context, original_values.clone(), wgpu::BufferUsages::STORAGE);
+    let prefix_sum = PrefixSum::new(wgpu_context, &buffer_data);
+
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Testing f32 prefix sum"),
+    });
+    prefix_sum.execute(wgpu_context, &mut encoder, n);
+    let idx = wgpu_context.get_queue().submit([encoder.finish()]);
+    wgpu_context.get_device().poll(WaitForSubmissionIndex(idx)).unwrap();
+
+    let expected_data: Vec<f32> = original_values.iter().scan(0.0, |sum, i| {
+        *sum += *i;
+        Some(*sum)
+    }).collect();
+    assert_eq!(buffer_data.read_back(wgpu_context).unwrap(), expected_data);
+}
+
+#[test]
+fn pair_exclusive_prefix_sum_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    let n = 3_000;
+    let original_values: Vec<[u32; 2]> = (0u32..n).map(|i| [i % 3, 1]).collect();
+    let buffer_data = GpuBuffer::new(wgpu_context, original_values.clone(), wgpu::BufferUsages::STORAGE);
+    let prefix_sum = PrefixSum::new(wgpu_context, &buffer_data);
+
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Testing pair prefix sum"),
+    });
+    prefix_sum.execute_exclusive(wgpu_context, &mut encoder, n);
+    let idx = wgpu_context.get_queue().submit([encoder.finish()]);
+    wgpu_context.get_device().poll(WaitForSubmissionIndex(idx)).unwrap();
+
+    let expected_data: Vec<[u32; 2]> = original_values.iter().scan([0, 0], |sum, value| {
+        let exclusive = *sum;
+        *sum = [sum[0] + value[0], sum[1] + value[1]];
+        Some(exclusive)
+    }).collect();
+    assert_eq!(buffer_data.read_back(wgpu_context).unwrap(), expected_data);
+}
+
+#[test]
+fn baseline_features_prefix_sum_test() {
+    // No subgroups and no push constants, like the WebGPU baseline: the workgroup memory scan with uniform constants
+    let wgpu_context = &pollster::block_on(WgpuContext::new_for_test_with_features(wgpu::Features::empty())).unwrap();
+
+    let n = 70_001;
+    let original_values: Vec<u32> = (0u32..n).map(|_| random_range(0u32..=9u32)).collect();
+    let buffer_data = GpuBuffer::new(wgpu_context, original_values.clone(), wgpu::BufferUsages::STORAGE);
+    let prefix_sum = PrefixSum::new(wgpu_context, &buffer_data);
+
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Testing baseline prefix sum"),
+    });
+    prefix_sum.execute(wgpu_context, &mut encoder, n);
+    let idx = wgpu_context.get_queue().submit([encoder.finish()]);
+    wgpu_context.get_device().poll(WaitForSubmissionIndex(idx)).unwrap();
+
+    let expected_data: Vec<u32> = original_values.iter().scan(0, |sum, i| {
+        *sum += *i;
+        Some(*sum)
+    }).collect();
+    assert_eq!(buffer_data.read_back(wgpu_context).unwrap(), expected_data);
+}
@@ -0,0 +1,38 @@
+use std::time::Duration;
+use game_engine::utils::present_schedule::{PresentSchedule, PresentSkipConfig};
+
+const BUDGET: Duration = Duration::from_millis(16);
+
+fn presented(schedule: &mut PresentSchedule, step_time: Duration, steps: usize) -> Vec<bool> {
+    (0..steps).map(|_| schedule.step_finished(step_time)).collect()
+}
+
+#[test]
+fn presents_every_step_when_disabled_test() {
+    let mut schedule = PresentSchedule::new(PresentSkipConfig::default());
+    assert!(presented(&mut schedule, Duration::from_millis(100), 10).into_iter().all(|present| present));
+    assert_eq!(schedule.steps_per_present(), 1);
+}
+
+#[test]
+fn skips_presents_while_over_budget_test() {
+    let mut schedule = PresentSchedule::new(PresentSkipConfig { enabled: true, frame_budget: BUDGET, max_steps_per_present: 4 });
+    // Within the budget
+    assert_eq!(presented(&mut schedule, Duration::from_millis(10), 3), vec![true, true, true]);
+
+    // Over it, the ratio doubles every step up to the maximum
+    assert_eq!(presented(&mut schedule, Duration::from_millis(30), 2), vec![false, false]);
+    assert_eq!(schedule.steps_per_present(), 4);
+    assert_eq!(presented(&mut schedule, Duration::from_millis(30), 6), vec![false, true, false, false, false, true]);
+
+    // Between half the budget and the budget, the ratio is kept
+    assert_eq!(presented(&mut schedule, Duration::from_millis(12), 4), vec![false, false, false, true]);
+
+    // Well under it, every step is presented again
+    presented(&mut schedule, Duration::from_millis(2), 2);
+    assert_eq!(schedule.steps_per_present(), 1);
+    assert_eq!(presented(&mut schedule, Duration::from_millis(2), 2), vec![true, true]);
+
+    schedule.set_config(PresentSkipConfig::default());
+    assert!(schedule.step_finished(Duration::from_millis(30)));
+}
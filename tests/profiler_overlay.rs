@@ -0,0 +1,26 @@
+#![cfg(feature = "windowing")]
+use std::time::Duration;
+use glam::Vec2;
+use game_engine::renderer::profiler_overlay::panel_lines;
+use game_engine::renderer::stroke_font::text_strokes;
+use game_engine::utils::gpu_timings::{GpuTimings, ScopeTime};
+
+fn timings_with_scope(label: &str) -> GpuTimings {
+    let mut timings = GpuTimings::new(2);
+    timings.record_frame(vec![ScopeTime { label: label.to_string(), depth: 0, time: Duration::from_micros(500) }]);
+    timings
+}
+
+#[test]
+fn panel_draws_the_punctuation_of_the_labels_test() {
+    // The headline "GPU 0.500 MS PER FRAME (1 FRAMES)" and the times have punctuation too
+    let (positions, colors) = panel_lines(&timings_with_scope("SOLVE (COLOR 1/2)"), Vec2::ZERO, 1.0);
+    assert_eq!(positions.len(), colors.len());
+    assert!(positions.len() % 2 == 0);
+
+    // Same label with blanks in place of the punctuation: only the strokes of "()/" are missing
+    let (blank_positions, _) = panel_lines(&timings_with_scope("SOLVE  COLOR 1 2 "), Vec2::ZERO, 1.0);
+    let punctuation_strokes = text_strokes("()/", Vec2::ZERO, 10.0).len();
+    assert_eq!(punctuation_strokes, 5);
+    assert_eq!(positions.len() - blank_positions.len(), 2 * punctuation_strokes);
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::Simulation;
+use game_engine::simulation_config::{GravityMode, PhysicalUnits, RadialFalloff, SimulationConfig, WorldBoundary};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const CENTER: Vec2 = Vec2::new(300.0, 300.0);
+
+/// Steps once from rest and returns the positions.
+fn step(wgpu_context: &WgpuContext, simulation: &mut Simulation, delta_time: f32) -> Vec<Vec2> {
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    simulation.step(wgpu_context, &mut gpu_profiler, delta_time, None);
+    gpu_profiler.end_frame().unwrap();
+    simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().clone()
+}
+
+#[test]
+fn world_units_conversion_test() {
+    let config = SimulationConfig {
+        units: PhysicalUnits::new(0.5),
+        gravity_mode: GravityMode::Radial { center: Vec2::new(1.0, 2.0), strength: 8.0, falloff: RadialFalloff::InverseSquare },
+        boundary: WorldBoundary::Circle { center: Vec2::new(1.0, 2.0), radius: 3.0 },
+        ..SimulationConfig::default()
+    };
+    // GM scales with the length cubed
+    assert_eq!(config.world_gravity_mode(), GravityMode::Radial { center: Vec2::new(2.0, 4.0), strength: 64.0, falloff: RadialFalloff::InverseSquare });
+    assert_eq!(config.world_boundary(), WorldBoundary::Circle { center: Vec2::new(2.0, 4.0), radius: 6.0 });
+    assert_eq!(SimulationConfig::default().world_gravity_mode(), GravityMode::Uniform);
+}
+
+#[test]
+fn radial_gravity_pulls_towards_the_center_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    // 100 units right of and 100 units above the center
+    let mut simulation = common::create_test_simulation(wgpu_context, vec![CENTER + Vec2::new(100.0, 0.0), CENTER + Vec2::new(0.0, 100.0)], 2.0);
+    simulation.set_config(SimulationConfig {
+        units: PhysicalUnits::new(1.0),
+        gravity_mode: GravityMode::Radial { center: CENTER, strength: 1000.0, falloff: RadialFalloff::Constant },
+        ..SimulationConfig::default()
+    });
+
+    // From rest, the first step moves the particles by a * dt^2 = 0.1 towards the center
+    let mut positions = step(wgpu_context, &mut simulation, 0.01);
+    positions.sort_by(|a, b| a.x.total_cmp(&b.x));
+    assert!((positions[0] - (CENTER + Vec2::new(0.0, 99.9))).length() < 1e-3, "{positions:?}");
+    assert!((positions[1] - (CENTER + Vec2::new(99.9, 0.0))).length() < 1e-3, "{positions:?}");
+}
+
+#[test]
+fn inverse_square_gravity_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = common::create_test_simulation(wgpu_context, vec![CENTER + Vec2::new(100.0, 0.0)], 2.0);
+    simulation.set_config(SimulationConfig {
+        units: PhysicalUnits::new(1.0),
+        // 100 units away: 1e6 / 100^2 = 100 units per second squared
+        gravity_mode: GravityMode::Radial { center: CENTER, strength: 1.0e6, falloff: RadialFalloff::InverseSquare },
+        ..SimulationConfig::default()
+    });
+
+    let positions = step(wgpu_context, &mut simulation, 0.1);
+    assert!((positions[0] - (CENTER + Vec2::new(99.0, 0.0))).length() < 1e-3, "{positions:?}");
+}
+
+#[test]
+fn circular_boundary_keeps_the_particles_inside_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = common::create_test_simulation(wgpu_context, vec![CENTER + Vec2::new(500.0, 0.0), CENTER + Vec2::new(10.0, 0.0)], 2.0);
+    simulation.set_config(SimulationConfig {
+        units: PhysicalUnits::new(1.0),
+        boundary: WorldBoundary::Circle { center: CENTER, radius: 100.0 },
+        ..SimulationConfig::default()
+    });
+
+    let mut positions = step(wgpu_context, &mut simulation, 0.01);
+    positions.sort_by(|a, b| a.x.total_cmp(&b.x));
+    // The particle inside does not move, the one outside is brought back to the edge minus its radius
+    assert!((positions[0] - (CENTER + Vec2::new(10.0, 0.0))).length() < 1e-3, "{positions:?}");
+    assert!((positions[1] - (CENTER + Vec2::new(98.0, 0.0))).length() < 1e-3, "{positions:?}");
+}
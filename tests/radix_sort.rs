@@ -1,7 +1,8 @@
 use std::num::NonZeroU32;
 use wgpu::wgt::PollType::WaitForSubmissionIndex;
+use game_engine::renderer::wgpu_context::WgpuContext;
 use game_engine::utils::gpu_buffer::GpuBuffer;
-use game_engine::utils::radix_sort::radix_sort::{GPUSorter, PushConstants, NUM_BLOCKS_PER_WORKGROUP, RADIX_SORT_BUCKETS, WORKGROUP_SIZE};
+use game_engine::utils::radix_sort::{GPUSorter, PushConstants, NUM_BLOCKS_PER_WORKGROUP, RADIX_SORT_BUCKETS, WORKGROUP_SIZE};
 mod common;
 #[test]
 fn sort_test() {
@@ -123,4 +124,152 @@ fn sort_test_small_sized_array() {
     assert_eq!(*sorter.get_keys_b(wgpu_context).unwrap(), vec![20_000_000, 257, 1, 2, 30_000, 357_000_000, 65611, 90_000]);
 
 
-}
\ No newline at end of file
+}
+/// A payload of several words, e.g. the state of a particle.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct Particle {
+    position: [f32; 2],
+    radius: f32,
+    id: u32,
+}
+
+#[test]
+fn sort_multi_word_payload_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    let device = wgpu_context.get_device();
+    let queue = wgpu_context.get_queue();
+
+    // Repeated keys over 2 workgroups, the particles of a key keep their order
+    let n = 25006u32;
+    let keys: Vec<u32> = (0..n).map(|i| (n - i) / 3).collect();
+    let particles: Vec<Particle> = (0..n).map(|i| Particle { position: [i as f32, -(i as f32)], radius: 0.5 * i as f32, id: i }).collect();
+    let mut keys_buffer = GpuBuffer::new(wgpu_context, keys.clone(), wgpu::BufferUsages::STORAGE);
+    let mut payload_buffer = GpuBuffer::new(wgpu_context, bytemuck::cast_slice::<Particle, u32>(&particles).to_vec(), wgpu::BufferUsages::STORAGE);
+
+    let payload_words = NonZeroU32::new((size_of::<Particle>() / size_of::<u32>()) as u32).unwrap();
+    let sorter = GPUSorter::with_payload_words(wgpu_context, NonZeroU32::new(n).unwrap(), &keys_buffer, &payload_buffer, payload_words);
+    assert_eq!(sorter.payload_words().get(), 4);
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("GPURSSorter test_sort_multi_word_payload"),
+    });
+    sorter.sort(&mut encoder, None);
+    let idx = queue.submit([encoder.finish()]);
+    device.poll(WaitForSubmissionIndex(idx)).unwrap();
+
+    let mut expected: Vec<(u32, Particle)> = keys.into_iter().zip(particles).collect();
+    expected.sort_by_key(|(key, _)| *key);
+    let sorted_keys = keys_buffer.download(wgpu_context).unwrap().clone();
+    let sorted_particles: Vec<Particle> = bytemuck::cast_slice(payload_buffer.download(wgpu_context).unwrap()).to_vec();
+    assert_eq!(sorted_keys, expected.iter().map(|(key, _)| *key).collect::<Vec<u32>>());
+    assert_eq!(sorted_particles, expected.iter().map(|(_, particle)| *particle).collect::<Vec<Particle>>());
+}
+
+#[test]
+fn sort_early_exit_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    let device = wgpu_context.get_device();
+    let queue = wgpu_context.get_queue();
+
+    let n = 25006u32;
+    let sort = |sorter: &GPUSorter| {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("GPURSSorter test_sort_early_exit"),
+        });
+        sorter.sort(&mut encoder, None);
+        let idx = queue.submit([encoder.finish()]);
+        device.poll(WaitForSubmissionIndex(idx)).unwrap();
+    };
+
+    // Unsorted keys still go through the radix passes
+    let scrambled_data: Vec<u32> = (0..n).rev().collect();
+    let mut keys_buffer = GpuBuffer::new(wgpu_context, scrambled_data.clone(), wgpu::BufferUsages::STORAGE);
+    let mut payload_buffer = GpuBuffer::new(wgpu_context, scrambled_data, wgpu::BufferUsages::STORAGE);
+    let mut sorter = GPUSorter::new(wgpu_context, NonZeroU32::new(n).unwrap(), &keys_buffer, &payload_buffer);
+    sorter.set_early_exit(wgpu_context, true);
+    assert!(sorter.early_exit());
+    sort(&sorter);
+    let sorted_data: Vec<u32> = (0..n).collect();
+    assert_eq!(*keys_buffer.download(wgpu_context).unwrap(), sorted_data);
+    assert_eq!(*payload_buffer.download(wgpu_context).unwrap(), sorted_data);
+
+    // Sorted keys skip them, the ping pong buffers are never written
+    let payload: Vec<u32> = (0..n).map(|i| n - i).collect();
+    let mut keys_buffer = GpuBuffer::new(wgpu_context, sorted_data.clone(), wgpu::BufferUsages::STORAGE);
+    let mut payload_buffer = GpuBuffer::new(wgpu_context, payload.clone(), wgpu::BufferUsages::STORAGE);
+    let mut sorter = GPUSorter::new(wgpu_context, NonZeroU32::new(n).unwrap(), &keys_buffer, &payload_buffer);
+    sorter.set_early_exit(wgpu_context, true);
+    sort(&sorter);
+    assert!(sorter.get_keys_b(wgpu_context).unwrap().iter().all(|&key| key == 0));
+    assert_eq!(*keys_buffer.download(wgpu_context).unwrap(), sorted_data);
+    assert_eq!(*payload_buffer.download(wgpu_context).unwrap(), payload);
+}
+
+#[test]
+fn sort_key_bits_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    let device = wgpu_context.get_device();
+    let queue = wgpu_context.get_queue();
+
+    // 16-bit keys with unused ids in between, like the cell ids of a small world
+    let n = 25006u32;
+    let unused = u32::MAX;
+    let keys: Vec<u32> = (0..n).map(|i| if i % 5 == 0 { unused } else { (i * 7919) % 0xFFFF }).collect();
+    let payload: Vec<u32> = (0..n).collect();
+    let keys_buffer = GpuBuffer::new(wgpu_context, keys.clone(), wgpu::BufferUsages::STORAGE);
+    let payload_buffer = GpuBuffer::new(wgpu_context, payload, wgpu::BufferUsages::STORAGE);
+    let mut sorter = GPUSorter::new(wgpu_context, NonZeroU32::new(n).unwrap(), &keys_buffer, &payload_buffer);
+    assert_eq!(sorter.num_passes(), 4);
+    sorter.set_key_bits(16);
+    assert_eq!(sorter.num_passes(), 2);
+    sorter.set_key_bits(17);
+    assert_eq!(sorter.num_passes(), 4);
+    sorter.set_key_bits(9);
+    assert_eq!(sorter.num_passes(), 2);
+    sorter.set_key_bits(16);
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("GPURSSorter test_sort_key_bits"),
+    });
+    sorter.sort(&mut encoder, None);
+    let idx = queue.submit([encoder.finish()]);
+    device.poll(WaitForSubmissionIndex(idx)).unwrap();
+
+    // Stable: the payloads of equal keys keep their order
+    let mut expected: Vec<(u32, u32)> = keys.iter().copied().zip(0..n).collect();
+    expected.sort_by_key(|&(key, _)| key);
+    let sorted_keys = keys_buffer.read_back(wgpu_context).unwrap();
+    let sorted_payload = payload_buffer.read_back(wgpu_context).unwrap();
+    assert_eq!(sorted_keys, expected.iter().map(|&(key, _)| key).collect::<Vec<_>>());
+    assert_eq!(sorted_payload, expected.iter().map(|&(_, index)| index).collect::<Vec<_>>());
+}
+
+#[test]
+fn sort_baseline_features_test() {
+    // No subgroups and no push constants, like the WebGPU baseline
+    let wgpu_context = &pollster::block_on(WgpuContext::new_for_test_with_features(wgpu::Features::empty())).unwrap();
+
+    let n = 25006;
+    let scrambled_data: Vec<u32> = (0..n).rev().collect();
+    let keys = GpuBuffer::new(wgpu_context, scrambled_data.clone(), wgpu::BufferUsages::STORAGE);
+    let payload = GpuBuffer::new(wgpu_context, scrambled_data, wgpu::BufferUsages::STORAGE);
+    let sorter = GPUSorter::new(wgpu_context, NonZeroU32::new(n).unwrap(), &keys, &payload);
+
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Baseline sort test"),
+    });
+    sorter.sort(&mut encoder, None);
+    let idx = wgpu_context.get_queue().submit([encoder.finish()]);
+    wgpu_context.get_device().poll(WaitForSubmissionIndex(idx)).unwrap();
+
+    let sorted_data: Vec<u32> = (0..n).collect();
+    assert_eq!(keys.read_back(wgpu_context).unwrap(), sorted_data);
+    assert_eq!(payload.read_back(wgpu_context).unwrap(), sorted_data);
+}
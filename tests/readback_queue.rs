@@ -0,0 +1,70 @@
+mod common;
+
+use game_engine::utils::gpu_buffer::GpuBuffer;
+use game_engine::utils::readback_queue::ReadbackQueue;
+use wgpu::wgt::PollType::Wait;
+
+#[test]
+fn readbacks_arrive_after_the_gpu_is_done_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut buffer = GpuBuffer::new(wgpu_context, (0u32..64).collect(), wgpu::BufferUsages::STORAGE);
+    let mut queue = ReadbackQueue::new();
+
+    let whole = queue.request(wgpu_context, "whole", &buffer, 0..64);
+    // Written after the first request, only seen by the second one
+    buffer.write_range(10, &[100, 101], wgpu_context);
+    let part = queue.request(wgpu_context, "part", &buffer, 9..13);
+    assert_eq!(queue.len(), 2);
+
+    wgpu_context.get_device().poll(Wait).unwrap();
+    let completed = queue.poll(wgpu_context);
+    assert!(queue.is_empty());
+    assert_eq!(completed.len(), 2);
+
+    assert_eq!(completed[0].id, whole);
+    assert_eq!(completed[0].label, "whole");
+    assert_eq!(completed[0].to_vec::<u32>(), (0u32..64).collect::<Vec<_>>());
+    assert_eq!(completed[1].id, part);
+    assert_eq!(completed[1].to_vec::<u32>(), vec![9, 100, 101, 12]);
+
+    // Nothing left
+    assert!(queue.poll(wgpu_context).is_empty());
+}
+
+#[test]
+fn unaligned_ranges_are_read_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let buffer = GpuBuffer::new(wgpu_context, (0u8..32).collect(), wgpu::BufferUsages::STORAGE);
+    let mut queue = ReadbackQueue::new();
+
+    queue.request(wgpu_context, "bytes", &buffer, 3..9);
+    wgpu_context.get_device().poll(Wait).unwrap();
+    assert_eq!(queue.poll(wgpu_context)[0].bytes(), &[3, 4, 5, 6, 7, 8]);
+}
+
+#[test]
+fn recorded_readbacks_wait_for_after_submit_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let buffer = GpuBuffer::new(wgpu_context, (0u32..16).collect(), wgpu::BufferUsages::STORAGE);
+    let mut queue = ReadbackQueue::new();
+
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Readback test encoder") });
+    // Recorded after a clear of the same encoder, so it reads the cleared buffer
+    encoder.clear_buffer(buffer.buffer(), 0, None);
+    let id = queue.record_request(wgpu_context, &mut encoder, "recorded", &buffer, 4..8);
+    wgpu_context.get_queue().submit(Some(encoder.finish()));
+    wgpu_context.get_device().poll(Wait).unwrap();
+    // Not mapped yet
+    assert!(queue.poll(wgpu_context).is_empty());
+    assert_eq!(queue.len(), 1);
+
+    queue.after_submit();
+    wgpu_context.get_device().poll(Wait).unwrap();
+    let completed = queue.poll(wgpu_context);
+    assert_eq!(completed.len(), 1);
+    assert_eq!(completed[0].id, id);
+    assert_eq!(completed[0].to_vec::<u32>(), vec![0; 4]);
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use glam::UVec2;
+use game_engine::renderer::recorder::{Recorder, RecordingSettings, STAGING_BUFFERS};
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::utils::png;
+
+const SIZE: UVec2 = UVec2::new(10, 4);
+
+/// Draws a frame whose red channel is `frame`, so the recorded files tell the frames apart.
+fn draw_frame(wgpu_context: &WgpuContext, recorder: &mut Recorder, frame: u8) {
+    let target = recorder.target(wgpu_context, SIZE, wgpu::TextureFormat::Rgba8Unorm);
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: None,
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target.view(),
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color { r: frame as f64 / 255.0, g: 0.0, b: 0.0, a: 1.0 }),
+                store: wgpu::StoreOp::Store,
+            },
+            depth_slice: None,
+        })],
+        depth_stencil_attachment: None,
+        occlusion_query_set: None,
+        timestamp_writes: None,
+    });
+    wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+}
+
+#[test]
+fn recorder_writes_every_nth_frame_in_order_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let dir = std::env::temp_dir().join(format!("recorder_test_{}", std::process::id()));
+    let mut recorder = Recorder::new(RecordingSettings { frame_interval: 2, ..RecordingSettings::default() });
+    assert!(!recorder.advance_frame());
+
+    recorder.start(&dir).unwrap();
+    assert!(recorder.is_recording());
+    assert!(recorder.start(&dir).is_err());
+    // More recorded frames than staging buffers, without polling: the oldest ones are waited for
+    let num_frames = 4 * STAGING_BUFFERS as u8;
+    for frame in 0..num_frames {
+        if recorder.advance_frame() {
+            draw_frame(wgpu_context, &mut recorder, frame * 10);
+            recorder.capture(wgpu_context);
+        }
+    }
+    assert_eq!(recorder.frames_recorded(), num_frames as u64 / 2);
+    recorder.stop(wgpu_context);
+    assert!(!recorder.is_recording());
+
+    for index in 0..num_frames / 2 {
+        let red = index * 2 * 10;
+        let pixels: Vec<u8> = [red, 0, 0, 255].repeat((SIZE.x * SIZE.y) as usize);
+        let file = std::fs::read(dir.join(format!("frame_{:05}.png", index))).unwrap();
+        assert_eq!(file, png::encode_rgba8(SIZE.x, SIZE.y, &pixels), "frame {index}");
+    }
+    assert!(!dir.join(format!("frame_{:05}.png", num_frames / 2)).exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::particles::region_energy::{RegionEnergyQuery, RegionGridLayout};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+use game_engine::utils::telemetry::{EnergySample, Telemetry};
+use wgpu::wgt::PollType::Wait;
+
+#[test]
+fn region_grid_layout_test() {
+    let layout = RegionGridLayout::covering(Vec2::new(-100.0, 0.0), Vec2::new(400.0, 200.0), 4, 2);
+    assert_eq!(layout.region_size, Vec2::new(100.0, 100.0));
+    assert_eq!(layout.num_regions(), 8);
+    assert_eq!(layout.region_of(Vec2::new(-100.0, 0.0)), Some(0));
+    assert_eq!(layout.region_of(Vec2::new(250.0, 150.0)), Some(7));
+    assert_eq!(layout.region_of(Vec2::new(-100.1, 50.0)), None);
+    assert_eq!(layout.region_of(Vec2::new(0.0, 200.0)), None);
+    assert_eq!(layout.region_center(5), Vec2::new(50.0, 150.0));
+}
+
+#[test]
+fn region_energy_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    let positions = vec![Vec2::new(100.0, 100.0), Vec2::new(200.0, 100.0), Vec2::new(1500.0, 700.0)];
+    let mut simulation = common::create_test_simulation(wgpu_context, positions, 2.0);
+    simulation.particles_mut().set_gravity(Vec2::new(0.0, -1000.0));
+
+    // Regions of 480 x 540
+    let layout = RegionGridLayout::covering(Vec2::ZERO, simulation.particles().world_size(), 4, 2);
+    let mut query = RegionEnergyQuery::new(wgpu_context, layout);
+
+    // Starting at rest, the first step moves every particle by g * dt^2: 10 units per second
+    simulation.step(wgpu_context, &mut gpu_profiler, 0.01, None);
+    query.measure(wgpu_context, &mut gpu_profiler, simulation.particles(), 0.01);
+    gpu_profiler.end_frame().unwrap();
+
+    let map = query.read(wgpu_context).unwrap();
+    assert_eq!(map.total_particles(), 3);
+    let bottom_left = map.region(0, 0).unwrap();
+    assert_eq!(bottom_left.particles, 2);
+    assert!((bottom_left.mean_speed - 10.0).abs() < 1e-2, "{}", bottom_left.mean_speed);
+    assert!((bottom_left.mean_energy - 50.0).abs() < 0.2, "{}", bottom_left.mean_energy);
+    assert_eq!(map.region(3, 1).unwrap().particles, 1);
+    assert_eq!(map.region(1, 0).unwrap().particles, 0);
+    assert_eq!(map.region(1, 0).unwrap().mean_energy, 0.0);
+    assert!(map.region(4, 0).is_none());
+    assert!((map.mean_energy() - 50.0).abs() < 0.2);
+    assert!(map.hottest_region().is_some());
+
+    // The readback delivers the same map
+    query.request(wgpu_context);
+    wgpu_context.get_device().poll(Wait).unwrap();
+    assert_eq!(query.poll(wgpu_context).as_ref(), Some(&map));
+    assert_eq!(query.poll(wgpu_context), None);
+    assert_eq!(query.last_map(), Some(&map));
+
+    let mut telemetry = Telemetry::new();
+    telemetry.record_energy_sample(EnergySample::from_map(&map));
+    let sample = telemetry.last_energy_sample().unwrap();
+    assert_eq!(sample.particles, 3);
+    assert!(sample.hottest_region.is_some());
+    assert!(telemetry.summary().contains("over the last 1 samples"), "{}", telemetry.summary());
+
+    // Only the particles inside the regions count
+    query.set_layout(wgpu_context, RegionGridLayout::covering(Vec2::ZERO, Vec2::new(480.0, 540.0), 1, 1));
+    query.measure(wgpu_context, &mut gpu_profiler, simulation.particles(), 0.01);
+    gpu_profiler.end_frame().unwrap();
+    let map = query.read(wgpu_context).unwrap();
+    assert_eq!(map.regions.len(), 1);
+    assert_eq!(map.total_particles(), 2);
+}
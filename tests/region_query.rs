@@ -0,0 +1,53 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::particles::region_query::QueryShape;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::Simulation;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+/// A 20 x 20 block of separated particles, stepped once so the grid is sorted.
+fn create_simulation(wgpu_context: &WgpuContext) -> Simulation {
+    let positions = (0..400).map(|i| Vec2::new(50.0 + (i % 20) as f32 * 5.0, 50.0 + (i / 20) as f32 * 5.0)).collect();
+    let mut simulation = common::create_test_simulation(wgpu_context, positions, 2.0);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    simulation.step(wgpu_context, &mut gpu_profiler, 0.01, None);
+    gpu_profiler.end_frame().unwrap();
+    simulation
+}
+
+/// The particles inside `shape`, found on the CPU.
+fn expected_ids(wgpu_context: &WgpuContext, simulation: &mut Simulation, shape: &QueryShape) -> Vec<u32> {
+    let buffers = simulation.particles_mut().download_particle_buffers(wgpu_context);
+    buffers.current_positions.data().iter().enumerate()
+        .filter(|(_, position)| shape.contains(**position))
+        .map(|(index, _)| index as u32)
+        .collect()
+}
+
+#[test]
+fn query_circle_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = create_simulation(wgpu_context);
+
+    let shape = QueryShape::Circle { center: Vec2::new(80.0, 90.0), radius: 17.0 };
+    let expected = expected_ids(wgpu_context, &mut simulation, &shape);
+    assert!(!expected.is_empty());
+    assert_eq!(simulation.query_circle(wgpu_context, Vec2::new(80.0, 90.0), 17.0), expected);
+}
+
+#[test]
+fn query_rect_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = create_simulation(wgpu_context);
+
+    let shape = QueryShape::rect(Vec2::new(120.0, 60.0), Vec2::new(62.0, 71.0));
+    let expected = expected_ids(wgpu_context, &mut simulation, &shape);
+    assert!(!expected.is_empty());
+    assert_eq!(simulation.query_rect(wgpu_context, Vec2::new(120.0, 60.0), Vec2::new(62.0, 71.0)), expected);
+
+    // Outside of the block, and a second query reuses the buffers
+    assert!(simulation.query_rect(wgpu_context, Vec2::new(300.0, 300.0), Vec2::new(400.0, 400.0)).is_empty());
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::grid::grid::Grid;
+use game_engine::physics::collision_system::{CollisionSystem, RESTITUTION_CHANNEL};
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::{Simulation, DIMENSION};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const DELTA_TIME: f32 = 0.1;
+
+/// Pulls two particles against each other with the mouse attraction, then releases them
+/// during the step of the impact. Returns the velocity (displacement per step) of the left and right particle.
+fn head_on_collision(wgpu_context: &WgpuContext, restitution: Option<f32>) -> (Vec2, Vec2) {
+    let particles = common::create_test_particle_system(
+        wgpu_context,
+        vec![Vec2::new(100.0, 100.0), Vec2::new(110.0, 100.0)],
+        vec![3.0, 3.0],
+    );
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let collision_system = CollisionSystem::new(wgpu_context, DIMENSION, &particles, &grid);
+    let mut simulation = Simulation::from_parts(wgpu_context, particles, grid, collision_system);
+    if let Some(restitution) = restitution {
+        simulation.enable_restitution(wgpu_context, restitution);
+    }
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    // Two steps of attraction: the particles approach at 3 units per step each and overlap
+    simulation.particles_mut().mouse_click_callback(true, Vec2::new(105.0, 100.0));
+    for _ in 0..2 {
+        simulation.step(wgpu_context, &mut gpu_profiler, DELTA_TIME, None);
+        gpu_profiler.end_frame().unwrap();
+    }
+    simulation.particles_mut().mouse_click_callback(false, Vec2::new(105.0, 100.0));
+    simulation.step(wgpu_context, &mut gpu_profiler, DELTA_TIME, None);
+    gpu_profiler.end_frame().unwrap();
+
+    let buffers = simulation.particles_mut().download_particle_buffers(wgpu_context);
+    let mut particles: Vec<(Vec2, Vec2)> = buffers.current_positions.data().iter()
+        .zip(buffers.previous_positions.data())
+        .map(|(position, previous)| (*position, *position - *previous))
+        .collect();
+    particles.sort_by(|a, b| a.0.x.total_cmp(&b.0.x));
+    (particles[0].1, particles[1].1)
+}
+
+fn assert_close(actual: Vec2, expected: Vec2) {
+    assert!((actual - expected).length() < 1e-3, "expected {:?}, got {:?}", expected, actual);
+}
+
+#[test]
+fn elastic_particles_bounce_back_test() {
+    let setup = pollster::block_on(common::setup());
+    let (left, right) = head_on_collision(&setup.wgpu_context, Some(1.0));
+
+    assert_close(left, Vec2::new(-3.0, 0.0));
+    assert_close(right, Vec2::new(3.0, 0.0));
+}
+
+#[test]
+fn inelastic_particles_stop_test() {
+    let setup = pollster::block_on(common::setup());
+    let (left, right) = head_on_collision(&setup.wgpu_context, Some(0.0));
+
+    assert_close(left, Vec2::ZERO);
+    assert_close(right, Vec2::ZERO);
+}
+
+#[test]
+fn spawned_particles_get_the_spawn_channel_value_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut particles = common::create_test_particle_system(
+        wgpu_context,
+        vec![Vec2::new(10.0, 10.0), Vec2::new(20.0, 20.0)],
+        vec![1.0, 1.0],
+    );
+    let restitution = particles.register_channel(wgpu_context, RESTITUTION_CHANNEL, &[1.0f32.to_bits()]);
+    particles.set_spawn_channel_value(restitution, &[0.25f32.to_bits()]);
+    particles.add_particles(&Vec2::new(500.0, 500.0), wgpu_context);
+
+    let values: Vec<f32> = particles.download_extras(wgpu_context).iter().map(|word| f32::from_bits(*word)).collect();
+    assert_eq!(values.len(), 102);
+    assert_eq!(values[..2], [1.0, 1.0]);
+    assert!(values[2..].iter().all(|&value| value == 0.25));
+}
@@ -0,0 +1,500 @@
+//! Validates every WGSL shader with naga, with the override constants the kernels pass at runtime,
+//! so syntax errors, type errors and bad override values fail `cargo test` instead of the first
+//! pipeline creation. No GPU needed.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use naga::valid::{Capabilities, ShaderStages, SubgroupOperationSet, ValidationFlags, Validator};
+use naga::ShaderStage;
+use game_engine::grid::{cell_occupancy_map, grid, velocity_field};
+use game_engine::grid::grid::Grid;
+use game_engine::particles::{nearest_particle_query, particle_color_kernel, particle_compaction, particle_culling, particle_group, particle_selection, particle_sort, particle_trails, region_energy, region_query, sprite_animation};
+use game_engine::physics::{cell_range_solver, collision_cell_builder, collision_color_validator, fluid_solver, heat_diffusion, kill_volumes, particle_sleep, spring_constraints, stability_watchdog, static_colliders};
+#[cfg(feature = "windowing")]
+use game_engine::renderer::density_heatmap;
+use game_engine::utils::compute_shader::uniform_constants_source;
+use game_engine::utils::kernel_tuning::KernelTuning;
+use game_engine::utils::prefix_sum::prefix_sum::ScanElementKind;
+#[cfg(feature = "onesweep")]
+use game_engine::utils::radix_sort::onesweep::KEYS_PER_THREAD;
+use game_engine::utils::radix_sort::{self, RADIX_SORT_BUCKETS};
+
+/// Subgroup sizes the prefix sum may be created with, see `get_subgroup_size`.
+const SUBGROUP_SIZES: [u32; 5] = [8, 16, 32, 64, 128];
+
+struct EntryPoint {
+    stage: ShaderStage,
+    name: &'static str,
+    constants: Vec<(&'static str, f64)>,
+}
+
+struct Shader {
+    path: &'static str,
+    source: &'static str,
+    entry_points: Vec<EntryPoint>,
+}
+
+fn compute(name: &'static str, constants: Vec<(&'static str, f64)>) -> EntryPoint {
+    EntryPoint { stage: ShaderStage::Compute, name, constants }
+}
+
+fn workgroup_size(size: u32) -> Vec<(&'static str, f64)> {
+    vec![("WORKGROUP_SIZE", size as f64)]
+}
+
+fn render_entry_points() -> Vec<EntryPoint> {
+    vec![
+        EntryPoint { stage: ShaderStage::Vertex, name: "vs_main", constants: vec![] },
+        EntryPoint { stage: ShaderStage::Fragment, name: "fs_main", constants: vec![] },
+    ]
+}
+
+/// Every shader of the crate with the constants its kernel passes, taken from the kernels and the default `KernelTuning`.
+fn shaders() -> Vec<Shader> {
+    let tuning = KernelTuning::default();
+    let grid_constants = |max_cells_per_object: u32| vec![
+        ("WORKGROUP_SIZE", grid::WORKGROUP_SIZE.0 as f64),
+        ("MAX_CELLS_PER_OBJECT", max_cells_per_object as f64),
+    ];
+    let cell_builder_constants = || [
+        grid_constants(grid::MAX_CELLS_PER_OBJECT),
+        vec![("CHUNK_SIZE", collision_cell_builder::COUNTING_CHUNK_SIZE as f64)],
+    ].concat();
+    // The grid dispatch of cell_compaction and live_count, see `CellCompaction::new`
+    let cell_compaction_constants = || [
+        cell_builder_constants(),
+        vec![
+            ("CHUNK_WORKGROUP_SIZE", collision_cell_builder::WORKGROUP_SIZE.0 as f64),
+            ("SORT_WORKGROUP_SIZE", tuning.sort_workgroup_size as f64),
+            ("SORT_BLOCKS_PER_WORKGROUP", tuning.sort_blocks_per_workgroup as f64),
+        ],
+    ].concat();
+
+    let radix_sort_constants_of = |workgroup_size: u32| vec![
+        ("WORKGROUP_SIZE", workgroup_size as f64),
+        ("RADIX_SORT_BUCKETS", RADIX_SORT_BUCKETS as f64),
+    ];
+    let radix_sort_constants = radix_sort_constants_of(tuning.sort_workgroup_size);
+    let radix_sort_entry_points = vec![
+        compute("build_histogram", radix_sort_constants.clone()),
+        compute("scatter_keys", radix_sort_constants.clone()),
+        // Payload of several words per key, see GPUSorter::with_payload_words
+        compute("scatter_keys", [radix_sort_constants, vec![("PAYLOAD_WORDS", 4.0)]].concat()),
+        // Fewer threads than buckets, see GPUSorter::workgroup_size
+        compute("build_histogram", radix_sort_constants_of(radix_sort::MIN_WORKGROUP_SIZE)),
+        compute("scatter_keys", radix_sort_constants_of(radix_sort::MIN_WORKGROUP_SIZE)),
+    ];
+    let sort_check_constants = || [
+        workgroup_size(radix_sort::WORKGROUP_SIZE.0),
+        vec![("SORT_WORKGROUP_SIZE", tuning.sort_workgroup_size as f64)],
+    ].concat();
+
+    let prefix_sum_entry_points_of = |constants: Vec<(&'static str, f64)>| {
+        let mut entry_points = Vec::new();
+        for element_kind in [ScanElementKind::U32, ScanElementKind::F32, ScanElementKind::U32Pair] {
+            for entry_point in ["prefix_sum_of_each_block", "exclusive_prefix_sum_of_each_block", "prefix_sum_of_the_block_sums", "add_block_prefix_sums_to_the_buffer"] {
+                entry_points.push(compute(entry_point, [constants.clone(), vec![("ELEMENT_KIND", element_kind as u32 as f64)]].concat()));
+            }
+        }
+        entry_points
+    };
+    let prefix_sum_workgroup_size = tuning.prefix_sum_workgroup_size;
+    let mut prefix_sum_entry_points = Vec::new();
+    for subgroup_size in SUBGROUP_SIZES {
+        let prefix_sum_constants = vec![
+            ("SUBGROUP_SIZE", subgroup_size as f64),
+            ("WORKGROUP_SIZE", prefix_sum_workgroup_size as f64),
+            ("SHARED_MEMORY_SIZE", ((prefix_sum_workgroup_size / subgroup_size) * 2) as f64),
+        ];
+        prefix_sum_entry_points.extend(prefix_sum_entry_points_of(prefix_sum_constants));
+    }
+
+    // The onesweep kernels only exist with the onesweep feature
+    #[cfg(feature = "onesweep")]
+    let onesweep_entry_points = ["build_global_histogram", "scan_global_histogram", "onesweep_pass"].into_iter()
+        .map(|entry_point| compute(entry_point, [radix_sort_constants_of(radix_sort::WORKGROUP_SIZE.0), vec![("KEYS_PER_THREAD", KEYS_PER_THREAD as f64)]].concat()))
+        .collect();
+    #[cfg(not(feature = "onesweep"))]
+    let onesweep_entry_points = vec![];
+
+    // The heatmap kernel only exists with the window
+    #[cfg(feature = "windowing")]
+    let density_heatmap_entry_points = vec![
+        compute("scatter_density", workgroup_size(density_heatmap::WORKGROUP_SIZE)),
+        compute("resolve_density", workgroup_size(density_heatmap::WORKGROUP_SIZE)),
+    ];
+    #[cfg(not(feature = "windowing"))]
+    let density_heatmap_entry_points = vec![];
+
+    vec![
+        Shader {
+            path: "grid/cell_compaction.wgsl",
+            source: include_str!("../src/grid/cell_compaction.wgsl"),
+            entry_points: ["count_used_cells", "scatter_used_cells", "prepare_used_cells_dispatch"].into_iter()
+                .map(|entry_point| compute(entry_point, cell_compaction_constants()))
+                .collect(),
+        },
+        Shader {
+            path: "grid/cell_occupancy_map.wgsl",
+            source: include_str!("../src/grid/cell_occupancy_map.wgsl"),
+            entry_points: vec![compute("count_cell_objects", workgroup_size(cell_occupancy_map::WORKGROUP_SIZE))],
+        },
+        Shader {
+            path: "grid/cell_occupancy_query.wgsl",
+            source: include_str!("../src/grid/cell_occupancy_query.wgsl"),
+            entry_points: vec![compute("count_cell_occupancy", vec![])],
+        },
+        Shader {
+            path: "grid/grid.wgsl",
+            source: include_str!("../src/grid/grid.wgsl"),
+            entry_points: vec![compute("build_cell_ids_array", grid_constants(grid::MAX_CELLS_PER_OBJECT))],
+        },
+        Shader {
+            path: "grid/grid_3d.wgsl",
+            source: include_str!("../src/grid/grid_3d.wgsl"),
+            entry_points: vec![compute("build_cell_ids_array", grid_constants(Grid::max_cells_per_object_of(3)))],
+        },
+        Shader {
+            path: "grid/live_count.wgsl",
+            source: include_str!("../src/grid/live_count.wgsl"),
+            entry_points: vec![compute("prepare_grid_dispatch", cell_compaction_constants())],
+        },
+        Shader {
+            path: "grid/occupancy_drawer.wgsl",
+            source: include_str!("../src/grid/occupancy_drawer.wgsl"),
+            entry_points: render_entry_points(),
+        },
+        Shader {
+            path: "grid/velocity_field.wgsl",
+            source: include_str!("../src/grid/velocity_field.wgsl"),
+            entry_points: vec![
+                compute("accumulate_velocities", workgroup_size(velocity_field::WORKGROUP_SIZE)),
+                compute("build_arrows", workgroup_size(velocity_field::WORKGROUP_SIZE)),
+            ],
+        },
+        Shader {
+            path: "lines/line.wgsl",
+            source: include_str!("../src/lines/line.wgsl"),
+            entry_points: render_entry_points(),
+        },
+        Shader {
+            path: "particles/home_cell_ids.wgsl",
+            source: include_str!("../src/particles/home_cell_ids.wgsl"),
+            entry_points: vec![compute("create_home_cell_ids", workgroup_size(particle_sort::WORKGROUP_SIZE.0))],
+        },
+        Shader {
+            path: "particles/sort_disorder.wgsl",
+            source: include_str!("../src/particles/sort_disorder.wgsl"),
+            entry_points: vec![compute("count_descending_pairs", workgroup_size(particle_sort::WORKGROUP_SIZE.0))],
+        },
+        Shader {
+            path: "particles/particle_compaction.wgsl",
+            source: include_str!("../src/particles/particle_compaction.wgsl"),
+            entry_points: vec![
+                compute("mark_alive", workgroup_size(particle_compaction::WORKGROUP_SIZE.0)),
+                compute("scatter_alive", workgroup_size(particle_compaction::WORKGROUP_SIZE.0)),
+            ],
+        },
+        Shader {
+            path: "particles/nearest_particle_query.wgsl",
+            source: include_str!("../src/particles/nearest_particle_query.wgsl"),
+            entry_points: vec![
+                compute("find_nearest_distance", workgroup_size(nearest_particle_query::WORKGROUP_SIZE)),
+                compute("find_nearest_particle", workgroup_size(nearest_particle_query::WORKGROUP_SIZE)),
+                compute("mark_highlight", workgroup_size(nearest_particle_query::WORKGROUP_SIZE)),
+            ],
+        },
+        Shader {
+            path: "particles/particle_selection.wgsl",
+            source: include_str!("../src/particles/particle_selection.wgsl"),
+            entry_points: vec![compute("select_in_rect", workgroup_size(particle_selection::WORKGROUP_SIZE))],
+        },
+        Shader {
+            path: "particles/particle_group.wgsl",
+            source: include_str!("../src/particles/particle_group.wgsl"),
+            entry_points: vec![
+                compute("recolor", workgroup_size(particle_group::WORKGROUP_SIZE)),
+                compute("freeze", workgroup_size(particle_group::WORKGROUP_SIZE)),
+                compute("mark_removed", workgroup_size(particle_group::WORKGROUP_SIZE)),
+            ],
+        },
+        Shader {
+            path: "particles/particle_color_kernel.wgsl",
+            source: include_str!("../src/particles/particle_color_kernel.wgsl"),
+            entry_points: vec![
+                compute("count_particles", workgroup_size(particle_color_kernel::WORKGROUP_SIZE)),
+                compute("color_particles", workgroup_size(particle_color_kernel::WORKGROUP_SIZE)),
+            ],
+        },
+        Shader {
+            path: "physics/kill_volumes.wgsl",
+            source: include_str!("../src/physics/kill_volumes.wgsl"),
+            entry_points: vec![compute("mark_killed", workgroup_size(kill_volumes::WORKGROUP_SIZE))],
+        },
+        Shader {
+            path: "physics/spring_constraints.wgsl",
+            source: include_str!("../src/physics/spring_constraints.wgsl"),
+            entry_points: vec![
+                compute("map_spring_handles", workgroup_size(spring_constraints::WORKGROUP_SIZE)),
+                compute("solve_springs", workgroup_size(spring_constraints::WORKGROUP_SIZE)),
+            ],
+        },
+        Shader {
+            path: "particles/sprite_animation.wgsl",
+            source: include_str!("../src/particles/sprite_animation.wgsl"),
+            entry_points: vec![compute("animate_sprites", workgroup_size(sprite_animation::WORKGROUP_SIZE))],
+        },
+        Shader {
+            path: "particles/region_energy.wgsl",
+            source: include_str!("../src/particles/region_energy.wgsl"),
+            entry_points: vec![
+                compute("accumulate_region_energy", workgroup_size(region_energy::WORKGROUP_SIZE)),
+                compute("finalize_region_energy", workgroup_size(region_energy::WORKGROUP_SIZE)),
+            ],
+        },
+        Shader {
+            path: "particles/particle_drawer.wgsl",
+            source: include_str!("../src/particles/particle_drawer.wgsl"),
+            entry_points: render_entry_points(),
+        },
+        Shader {
+            path: "particles/particle_culling.wgsl",
+            source: include_str!("../src/particles/particle_culling.wgsl"),
+            entry_points: vec![compute("cull_particles", workgroup_size(particle_culling::WORKGROUP_SIZE))],
+        },
+        Shader {
+            path: "particles/particle_trails.wgsl",
+            source: include_str!("../src/particles/particle_trails.wgsl"),
+            entry_points: vec![compute("record_trails", workgroup_size(particle_trails::WORKGROUP_SIZE))],
+        },
+        Shader {
+            path: "particles/trail_drawer.wgsl",
+            source: include_str!("../src/particles/trail_drawer.wgsl"),
+            entry_points: render_entry_points(),
+        },
+        Shader {
+            path: "renderer/density_heatmap.wgsl",
+            source: include_str!("../src/renderer/density_heatmap.wgsl"),
+            entry_points: density_heatmap_entry_points,
+        },
+        Shader {
+            path: "renderer/density_heatmap_draw.wgsl",
+            source: include_str!("../src/renderer/density_heatmap_draw.wgsl"),
+            entry_points: render_entry_points(),
+        },
+        Shader {
+            path: "particles/particle_integration.wgsl",
+            source: include_str!("../src/particles/particle_integration.wgsl"),
+            entry_points: vec![compute("verlet_integration", vec![])],
+        },
+        Shader {
+            path: "particles/rearrange.wgsl",
+            source: include_str!("../src/particles/rearrange.wgsl"),
+            entry_points: vec![compute("rearrange", workgroup_size(particle_sort::WORKGROUP_SIZE.0))],
+        },
+        Shader {
+            path: "physics/collision_cell_builder.wgsl",
+            source: include_str!("../src/physics/collision_cell_builder.wgsl"),
+            entry_points: vec![
+                compute("count_objects_for_each_chunk", cell_builder_constants()),
+                compute("build_collision_cells_array", cell_builder_constants()),
+            ],
+        },
+        Shader {
+            path: "physics/collision_color_validator.wgsl",
+            source: include_str!("../src/physics/collision_color_validator.wgsl"),
+            entry_points: vec![
+                compute("validate_cell_colors", workgroup_size(collision_color_validator::WORKGROUP_SIZE)),
+                compute("validate_neighbor_colors", workgroup_size(collision_color_validator::WORKGROUP_SIZE)),
+            ],
+        },
+        Shader {
+            path: "physics/collision_solver.wgsl",
+            source: include_str!("../src/physics/collision_solver.wgsl"),
+            entry_points: vec![
+                compute("solve_collisions", workgroup_size(tuning.collision_workgroup_size)),
+                compute("solve_segment_collisions", workgroup_size(tuning.collision_workgroup_size)),
+                compute("accumulate_collisions", workgroup_size(tuning.collision_workgroup_size)),
+                compute("apply_deltas", workgroup_size(tuning.collision_workgroup_size)),
+                compute("record_cell_stats", workgroup_size(tuning.collision_workgroup_size)),
+            ],
+        },
+        Shader {
+            path: "physics/static_colliders.wgsl",
+            source: include_str!("../src/physics/static_colliders.wgsl"),
+            entry_points: vec![
+                compute("solve_circle_collisions", workgroup_size(static_colliders::WORKGROUP_SIZE)),
+            ],
+        },
+        Shader {
+            path: "physics/cell_range_solver.wgsl",
+            source: include_str!("../src/physics/cell_range_solver.wgsl"),
+            entry_points: vec![
+                compute("find_cell_boundaries", workgroup_size(cell_range_solver::WORKGROUP_SIZE)),
+                compute("solve_cell_ranges", workgroup_size(cell_range_solver::WORKGROUP_SIZE)),
+                compute("apply_corrections", workgroup_size(cell_range_solver::WORKGROUP_SIZE)),
+            ],
+        },
+        Shader {
+            path: "particles/region_query.wgsl",
+            source: include_str!("../src/particles/region_query.wgsl"),
+            entry_points: vec![
+                compute("query_region", workgroup_size(region_query::WORKGROUP_SIZE)),
+            ],
+        },
+        Shader {
+            path: "physics/fluid_solver.wgsl",
+            source: include_str!("../src/physics/fluid_solver.wgsl"),
+            entry_points: vec![
+                compute("compute_densities", workgroup_size(fluid_solver::WORKGROUP_SIZE)),
+                compute("compute_accelerations", workgroup_size(fluid_solver::WORKGROUP_SIZE)),
+                compute("apply_accelerations", workgroup_size(fluid_solver::WORKGROUP_SIZE)),
+            ],
+        },
+        Shader {
+            path: "physics/heat_diffusion.wgsl",
+            source: include_str!("../src/physics/heat_diffusion.wgsl"),
+            entry_points: vec![
+                compute("diffuse_heat", workgroup_size(heat_diffusion::WORKGROUP_SIZE)),
+                compute("apply_heat", workgroup_size(heat_diffusion::WORKGROUP_SIZE)),
+            ],
+        },
+        Shader {
+            path: "physics/particle_sleep.wgsl",
+            source: include_str!("../src/physics/particle_sleep.wgsl"),
+            entry_points: vec![
+                compute("update_sleep", workgroup_size(particle_sleep::WORKGROUP_SIZE)),
+                compute("apply_sleep", workgroup_size(particle_sleep::WORKGROUP_SIZE)),
+            ],
+        },
+        Shader {
+            // Entry points come from the user kernels, see tests/force_kernel.rs
+            path: "physics/force_kernel_prelude.wgsl",
+            source: include_str!("../src/physics/force_kernel_prelude.wgsl"),
+            entry_points: vec![],
+        },
+        Shader {
+            path: "physics/stability_watchdog.wgsl",
+            source: include_str!("../src/physics/stability_watchdog.wgsl"),
+            entry_points: vec![compute("check_particles", workgroup_size(stability_watchdog::WORKGROUP_SIZE))],
+        },
+        Shader {
+            path: "utils/prefix_sum/prefix_sum.wgsl",
+            source: include_str!("../src/utils/prefix_sum/prefix_sum.wgsl"),
+            entry_points: prefix_sum_entry_points,
+        },
+        Shader {
+            path: "utils/prefix_sum/prefix_sum_workgroup.wgsl",
+            source: include_str!("../src/utils/prefix_sum/prefix_sum_workgroup.wgsl"),
+            entry_points: prefix_sum_entry_points_of(workgroup_size(prefix_sum_workgroup_size)),
+        },
+        Shader {
+            path: "utils/radix_sort/radix_sort.wgsl",
+            source: include_str!("../src/utils/radix_sort/radix_sort.wgsl"),
+            entry_points: radix_sort_entry_points,
+        },
+        Shader {
+            path: "utils/radix_sort/onesweep.wgsl",
+            source: include_str!("../src/utils/radix_sort/onesweep.wgsl"),
+            entry_points: onesweep_entry_points,
+        },
+        Shader {
+            path: "utils/radix_sort/sort_check.wgsl",
+            source: include_str!("../src/utils/radix_sort/sort_check.wgsl"),
+            entry_points: vec![
+                compute("check_sorted", sort_check_constants()),
+                compute("gate_radix_passes", sort_check_constants()),
+            ],
+        },
+    ]
+}
+
+/// Same capabilities as the features requested in `WgpuContext`.
+fn create_validator() -> Validator {
+    let mut validator = Validator::new(
+        ValidationFlags::all(),
+        Capabilities::PUSH_CONSTANT | Capabilities::SUBGROUP | Capabilities::SUBGROUP_BARRIER,
+    );
+    validator
+        .subgroup_stages(ShaderStages::all())
+        .subgroup_operations(SubgroupOperationSet::all());
+    validator
+}
+
+/// Capabilities of the WebGPU baseline, see `DeviceCapabilities`.
+fn create_baseline_validator() -> Validator {
+    Validator::new(ValidationFlags::all(), Capabilities::empty())
+}
+
+fn validate(shader: &Shader) -> Result<(), String> {
+    validate_source(shader, shader.source, create_validator())
+}
+
+fn validate_source(shader: &Shader, source: &str, mut validator: Validator) -> Result<(), String> {
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|e| e.emit_to_string(source))?;
+    let info = validator.validate(&module)
+        .map_err(|e| e.emit_to_string(source))?;
+
+    for entry_point in &shader.entry_points {
+        if !module.entry_points.iter().any(|ep| ep.name == entry_point.name && ep.stage == entry_point.stage) {
+            return Err(format!("missing {:?} entry point {}", entry_point.stage, entry_point.name));
+        }
+        let constants: naga::back::PipelineConstants = entry_point.constants.iter()
+            .map(|(name, value)| (name.to_string(), *value))
+            .collect();
+        naga::back::pipeline_constants::process_overrides(&module, &info, Some((entry_point.stage, entry_point.name)), &constants)
+            .map_err(|e| format!("{} with {:?}: {}", entry_point.name, entry_point.constants, e))?;
+    }
+    Ok(())
+}
+
+fn collect_wgsl_files(dir: &Path, files: &mut BTreeSet<PathBuf>) {
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_wgsl_files(&path, files);
+        }
+        else if path.extension().is_some_and(|extension| extension == "wgsl") {
+            files.insert(path);
+        }
+    }
+}
+
+#[test]
+fn all_shaders_validate_test() {
+    let errors: Vec<String> = shaders().iter()
+        .filter_map(|shader| validate(shader).err().map(|e| format!("{}:\n{}", shader.path, e)))
+        .collect();
+
+    assert!(errors.is_empty(), "Invalid shaders:\n{}", errors.join("\n"));
+}
+
+/// The compute shaders as `ComputeShader` creates them on devices without push constants and subgroups.
+/// The subgroup prefix sum and the onesweep sort are not created there.
+#[test]
+fn compute_shaders_validate_without_push_constants_test() {
+    let subgroup_only = ["utils/prefix_sum/prefix_sum.wgsl", "utils/radix_sort/onesweep.wgsl"];
+    let errors: Vec<String> = shaders().iter()
+        .filter(|shader| !subgroup_only.contains(&shader.path))
+        .filter(|shader| shader.entry_points.iter().all(|entry_point| entry_point.stage == ShaderStage::Compute))
+        .filter_map(|shader| {
+            validate_source(shader, &uniform_constants_source(shader.source), create_baseline_validator())
+                .err()
+                .map(|e| format!("{}:\n{}", shader.path, e))
+        })
+        .collect();
+
+    assert!(errors.is_empty(), "Invalid shaders without push constants:\n{}", errors.join("\n"));
+}
+
+#[test]
+fn every_shader_is_validated_test() {
+    let src_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+    let mut files = BTreeSet::new();
+    collect_wgsl_files(&src_dir, &mut files);
+
+    let validated: BTreeSet<PathBuf> = shaders().iter().map(|shader| src_dir.join(shader.path)).collect();
+    let missing: Vec<_> = files.difference(&validated).collect();
+    assert!(missing.is_empty(), "Shaders missing from the validation list: {:?}", missing);
+}
@@ -0,0 +1,130 @@
+// Hot reload only exists in debug builds
+#![cfg(debug_assertions)]
+mod common;
+
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use wgpu::wgt::PollType::WaitForSubmissionIndex;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::utils::compute_shader::ComputeShader;
+use game_engine::utils::gpu_buffer::GpuBuffer;
+use game_engine::utils::shader_watcher::ShaderWatcher;
+
+/// A kernel writing `value` to the first word of its buffer.
+fn source(value: u32) -> String {
+    format!("@group(0) @binding(0) var<storage, read_write> output: array<u32>;\n\n@compute @workgroup_size(1)\nfn write_value() {{\n    output[0] = {}u;\n}}\n", value)
+}
+
+struct TestKernel {
+    shader: ComputeShader,
+    output: GpuBuffer<u32>,
+    bind_group: wgpu::BindGroup,
+}
+
+impl TestKernel {
+    fn new(wgpu_context: &WgpuContext, label: &str, source: &str) -> Self {
+        let device = wgpu_context.get_device();
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Shader watcher test bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let output = GpuBuffer::new(wgpu_context, vec![0u32], wgpu::BufferUsages::STORAGE);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Shader watcher test bind group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: output.buffer().as_entire_binding() }],
+        });
+        let shader = ComputeShader::new(
+            wgpu_context,
+            wgpu::ShaderModuleDescriptor { label: Some(label), source: wgpu::ShaderSource::Wgsl(source.into()) },
+            "write_value",
+            &bind_group_layout,
+            (1, 1, 1),
+            &vec![],
+            &vec![],
+        );
+        Self { shader, output, bind_group }
+    }
+
+    fn run(&mut self, wgpu_context: &WgpuContext) -> u32 {
+        let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Shader watcher test encoder") });
+        self.shader.dispatch(&mut encoder, (1, 1, 1), None, &self.bind_group);
+        let idx = wgpu_context.get_queue().submit([encoder.finish()]);
+        wgpu_context.get_device().poll(WaitForSubmissionIndex(idx)).unwrap();
+        self.output.download(wgpu_context).unwrap()[0]
+    }
+}
+
+/// Writes `source` to `path` with a modification time `seconds` after the epoch, so every write is seen as a change.
+fn write_source(path: &Path, source: &str, seconds: u64) {
+    std::fs::write(path, source).unwrap();
+    let file = std::fs::File::options().write(true).open(path).unwrap();
+    file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)).unwrap();
+}
+
+#[test]
+fn reload_swaps_the_pipeline_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut kernel = TestKernel::new(wgpu_context, "write_value", &source(1));
+    assert_eq!(kernel.run(wgpu_context), 1);
+
+    kernel.shader.reload(wgpu_context, &source(2)).unwrap();
+    assert_eq!(kernel.run(wgpu_context), 2);
+
+    // A source that does not compile keeps the last pipeline
+    assert!(kernel.shader.reload(wgpu_context, "fn write_value( {").is_err());
+    assert!(kernel.shader.reload(wgpu_context, &source(3).replace("write_value", "other_entry_point")).is_err());
+    assert_eq!(kernel.run(wgpu_context), 2);
+}
+
+#[test]
+fn watcher_reloads_the_changed_files_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let path = std::env::temp_dir().join(format!("shader_watcher_test_{}.wgsl", std::process::id()));
+    write_source(&path, &source(1), 1000);
+    let mut kernel = TestKernel::new(wgpu_context, path.to_str().unwrap(), &source(1));
+    // Built at runtime, not watched
+    let mut inline_kernel = TestKernel::new(wgpu_context, "write_value", &source(1));
+    assert_eq!(wgpu_context.shaders().kernels().len(), 1);
+
+    let mut watcher = ShaderWatcher::new(Duration::ZERO);
+    assert!(watcher.poll(wgpu_context).is_empty());
+
+    write_source(&path, &source(2), 1001);
+    let reloads = watcher.poll(wgpu_context);
+    assert_eq!(reloads.len(), 1);
+    assert_eq!(reloads[0].kernels, 1);
+    assert_eq!(reloads[0].result, Ok(()));
+    assert_eq!(kernel.run(wgpu_context), 2);
+    assert_eq!(inline_kernel.run(wgpu_context), 1);
+    assert!(watcher.poll(wgpu_context).is_empty());
+
+    // Created after the change, from the old source: the next poll brings it up to date
+    let mut late_kernel = TestKernel::new(wgpu_context, path.to_str().unwrap(), &source(1));
+    assert_eq!(watcher.poll(wgpu_context).len(), 1);
+    assert_eq!(late_kernel.run(wgpu_context), 2);
+
+    // Reported once, the kernels keep running the last source that compiled
+    write_source(&path, "fn write_value( {", 1002);
+    let reloads = watcher.poll(wgpu_context);
+    assert_eq!(reloads.len(), 1);
+    assert_eq!(reloads[0].kernels, 2);
+    assert!(reloads[0].result.is_err());
+    assert!(watcher.poll(wgpu_context).is_empty());
+    assert_eq!(kernel.run(wgpu_context), 2);
+
+    drop(late_kernel);
+    assert_eq!(wgpu_context.shaders().kernels().len(), 1);
+    std::fs::remove_file(&path).unwrap();
+}
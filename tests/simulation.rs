@@ -0,0 +1,72 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::grid::grid::Grid;
+use game_engine::particles::particle_initializer::InitialLayout;
+use game_engine::physics::collision_system::CollisionSystem;
+use game_engine::simulation::{Simulation, DIMENSION};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+#[test]
+fn simulation_stats_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    // Two overlapping pairs, far from each other, and one lonely particle
+    let particles = common::create_test_particle_system(
+        wgpu_context,
+        vec![Vec2::new(100.0, 100.0), Vec2::new(101.0, 100.0), Vec2::new(500.0, 500.0), Vec2::new(500.0, 501.0), Vec2::new(900.0, 300.0)],
+        vec![2.0, 2.0, 2.0, 2.0, 2.0],
+    );
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let collision_system = CollisionSystem::new(wgpu_context, DIMENSION, &particles, &grid);
+    let mut simulation = Simulation::from_parts(wgpu_context, particles, grid, collision_system);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    let stats = simulation.stats(wgpu_context);
+    assert_eq!(stats.step_count, 0);
+    assert_eq!(stats.simulated_time, 0.0);
+    assert_eq!(stats.particle_count, 5);
+
+    simulation.step(wgpu_context, &mut gpu_profiler, 0.5, None);
+    gpu_profiler.end_frame().unwrap();
+    // The collision cells of the step, built before the pairs were pushed apart
+    assert!(simulation.stats(wgpu_context).active_collision_cells >= 1);
+
+    for _ in 0..2 {
+        simulation.step(wgpu_context, &mut gpu_profiler, 0.5, None);
+        gpu_profiler.end_frame().unwrap();
+    }
+
+    let stats = simulation.stats(wgpu_context);
+    assert_eq!(stats.step_count, 3);
+    assert!((stats.simulated_time - 1.5).abs() < 1e-9);
+    assert_eq!(stats.particle_count, 5);
+}
+
+#[test]
+fn headless_simulation_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    // No window nor camera, a small world holds a couple hundred particles
+    let mut simulation = Simulation::new(wgpu_context, Vec2::new(20.0, 10.0), InitialLayout::HexPacking);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    let initial_particles = simulation.particles().len();
+    assert!(initial_particles > 0);
+
+    for _ in 0..3 {
+        simulation.step(wgpu_context, &mut gpu_profiler, 0.01, None);
+        gpu_profiler.end_frame().unwrap();
+    }
+    let (report, _) = simulation.add_particles(wgpu_context, Vec2::new(10.0, 5.0));
+    for _ in 0..2 {
+        simulation.step(wgpu_context, &mut gpu_profiler, 0.01, None);
+        gpu_profiler.end_frame().unwrap();
+    }
+
+    let stats = simulation.stats(wgpu_context);
+    assert_eq!(stats.step_count, 5);
+    assert_eq!(stats.particle_count, initial_particles + report.spawned);
+    assert_eq!(simulation.grid().num_elements(), stats.particle_count);
+}
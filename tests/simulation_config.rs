@@ -0,0 +1,209 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::grid::grid::Grid;
+use game_engine::physics::collision_system::CollisionSystem;
+use game_engine::simulation::{Simulation, DIMENSION};
+use std::time::Duration;
+use game_engine::particles::particle_initializer::InitialLayout;
+use game_engine::simulation_config::{BoundaryMode, FixedTimestep, GravityMode, PhysicalUnits, RadialFalloff, SimulationConfig, WorldBoundary, EARTH_GRAVITY};
+use game_engine::renderer::recorder::{RecordingFormat, RecordingSettings};
+use game_engine::utils::config_file::ConfigError;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+#[test]
+fn unit_conversions_test() {
+    let units = PhysicalUnits::new(0.5);
+    assert_eq!(units.length_to_world(2.0), 4.0);
+    assert_eq!(units.length_to_meters(4.0), 2.0);
+    assert_eq!(units.position_to_world(Vec2::new(1.0, -1.0)), Vec2::new(2.0, -2.0));
+    assert_eq!(units.velocity_to_world(Vec2::new(3.0, 0.0)), Vec2::new(6.0, 0.0));
+    assert_eq!(units.acceleration_to_world(EARTH_GRAVITY), Vec2::new(0.0, -19.62));
+    assert_eq!(units.acceleration_to_meters(Vec2::new(0.0, -19.62)), EARTH_GRAVITY);
+
+    let config = SimulationConfig { gravity: EARTH_GRAVITY, spawn_radius_range: (0.02, 0.05), ..SimulationConfig::default() };
+    // A world unit is a centimeter by default
+    assert!((config.world_gravity() - Vec2::new(0.0, -981.0)).length() < 1e-3);
+    let (min_radius, max_radius) = config.world_spawn_radius_range();
+    assert!((min_radius - 2.0).abs() < 1e-5 && (max_radius - 5.0).abs() < 1e-5);
+}
+
+#[test]
+fn gravity_and_spawn_radii_follow_the_config_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let particles = common::create_test_particle_system(wgpu_context, vec![Vec2::new(500.0, 500.0)], vec![2.0]);
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let collision_system = CollisionSystem::new(wgpu_context, DIMENSION, &particles, &grid);
+    let mut simulation = Simulation::from_parts(wgpu_context, particles, grid, collision_system);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    // 1 world unit = 1 meter, so the particle falls g * dt^2 from rest in the first step
+    let config = SimulationConfig {
+        units: PhysicalUnits::new(1.0),
+        gravity: EARTH_GRAVITY,
+        spawn_radius_range: (2.0, 2.5),
+        ..SimulationConfig::default()
+    };
+    simulation.set_config(config);
+    assert_eq!(simulation.config(), &config);
+    assert_eq!(simulation.particles().gravity(), EARTH_GRAVITY);
+    simulation.step(wgpu_context, &mut gpu_profiler, 0.1, None);
+    gpu_profiler.end_frame().unwrap();
+    let position = simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data()[0];
+    assert!((position - Vec2::new(500.0, 500.0 - 0.0981)).length() < 1e-3, "{position:?}");
+
+    let (report, _) = simulation.add_particles(wgpu_context, Vec2::new(960.0, 540.0));
+    let radii = simulation.particles_mut().download_particle_buffers(wgpu_context).radii.data().clone();
+    assert_eq!(radii.len(), 1 + report.spawned);
+    assert!(radii[1..].iter().all(|radius| (2.0..=2.5).contains(radius)), "{radii:?}");
+}
+
+#[test]
+fn advance_runs_fixed_substeps_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let particles = common::create_test_particle_system(wgpu_context, vec![Vec2::new(500.0, 500.0)], vec![2.0]);
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let collision_system = CollisionSystem::new(wgpu_context, DIMENSION, &particles, &grid);
+    let mut simulation = Simulation::from_parts(wgpu_context, particles, grid, collision_system);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    let timestep = FixedTimestep { step: 0.25, substeps: 4, max_steps_per_frame: 2 };
+    simulation.set_config(SimulationConfig { timestep: Some(timestep), ..SimulationConfig::default() });
+
+    // Shorter than a step: nothing runs
+    assert_eq!(simulation.advance(wgpu_context, &mut gpu_profiler, 0.125, None), 0);
+    // Completes the first step, plus one
+    assert_eq!(simulation.advance(wgpu_context, &mut gpu_profiler, 0.375, None), 8);
+    // Capped at two steps per frame
+    assert_eq!(simulation.advance(wgpu_context, &mut gpu_profiler, 2.0, None), 8);
+    gpu_profiler.end_frame().unwrap();
+    let stats = simulation.stats(wgpu_context);
+    assert_eq!(stats.step_count, 16);
+    assert_eq!(stats.simulated_time, 1.0);
+
+    // Without a fixed timestep, one step of the frame time
+    simulation.set_config(SimulationConfig { timestep: None, ..SimulationConfig::default() });
+    assert_eq!(simulation.advance(wgpu_context, &mut gpu_profiler, 0.125, None), 1);
+    gpu_profiler.end_frame().unwrap();
+    assert_eq!(simulation.stats(wgpu_context).simulated_time, 1.125);
+}
+
+#[test]
+fn builder_checks_the_values_test() {
+    let config = SimulationConfig::builder()
+        .num_particles(5000)
+        .world_size(Vec2::new(4.0, 2.0))
+        .gravity(EARTH_GRAVITY)
+        .initial_radius_range(0.01, 0.02)
+        .sort_interval(Duration::from_millis(500))
+        .build()
+        .unwrap();
+    assert_eq!(config.num_particles, 5000);
+    assert_eq!(config.gravity, EARTH_GRAVITY);
+    assert_eq!(config.sort_interval, Duration::from_millis(500));
+    assert!((config.world_size_in_world_units() - Vec2::new(400.0, 200.0)).length() < 1e-3);
+    let (min_radius, max_radius) = config.world_initial_radius_range();
+    assert!((min_radius - 1.0).abs() < 1e-5 && (max_radius - 2.0).abs() < 1e-5);
+    // Untouched settings keep their defaults
+    assert_eq!(config.spawn_radius_range, SimulationConfig::default().spawn_radius_range);
+
+    let invalid_key = |result: Result<SimulationConfig, ConfigError>| match result {
+        Err(ConfigError::InvalidValue { key, .. }) => key,
+        other => panic!("{other:?}"),
+    };
+    assert_eq!(invalid_key(SimulationConfig::builder().num_particles(0).build()), "num_particles");
+    assert_eq!(invalid_key(SimulationConfig::builder().world_size(Vec2::new(1.0, -1.0)).build()), "world_size");
+    assert_eq!(invalid_key(SimulationConfig::builder().initial_radius_range(0.02, 0.01).build()), "initial_radius_range");
+    assert_eq!(invalid_key(SimulationConfig::builder().sort_disorder_threshold(Some(1.5)).build()), "sort_disorder_threshold");
+    assert!(SimulationConfig::default().validate().is_ok());
+}
+
+#[test]
+fn config_file_test() {
+    let source = r#"
+        # Small world with a planet
+        num_particles = 20_000
+        world_size = [10.0, 10.0]
+        meters_per_world_unit = 0.02
+        initial_radius_range = [0.01, 0.03]
+        sort_interval = 0.5
+        sort_disorder_threshold = 0.25
+        boundary_mode = "wrap"
+
+        [timestep]
+        step = 0.01
+        substeps = 2
+
+        [radial_gravity]
+        center = [5.0, 5.0]
+        strength = 3.0
+        falloff = "inverse_square"
+
+        [circle_boundary]
+        center = [5.0, 5.0]
+        radius = 4.5
+
+        [recording]
+        format = "ffmpeg"
+        frame_interval = 2
+        start = true
+    "#;
+    let config = SimulationConfig::from_toml_str(source).unwrap();
+    assert_eq!(config.num_particles, 20_000);
+    assert_eq!(config.world_size, Vec2::new(10.0, 10.0));
+    assert_eq!(config.units, PhysicalUnits::new(0.02));
+    assert_eq!(config.initial_radius_range, (0.01, 0.03));
+    assert_eq!(config.sort_interval, Duration::from_millis(500));
+    assert_eq!(config.sort_disorder_threshold, Some(0.25));
+    assert_eq!(config.timestep, Some(FixedTimestep { step: 0.01, substeps: 2, ..FixedTimestep::default() }));
+    assert_eq!(config.gravity_mode, GravityMode::Radial { center: Vec2::new(5.0, 5.0), strength: 3.0, falloff: RadialFalloff::InverseSquare });
+    assert_eq!(config.boundary, WorldBoundary::Circle { center: Vec2::new(5.0, 5.0), radius: 4.5 });
+    assert_eq!(config.boundary_mode, BoundaryMode::Wrap);
+    assert_eq!(config.recording, RecordingSettings { format: RecordingFormat::Ffmpeg, frame_interval: 2, record_on_start: true, ..RecordingSettings::default() });
+
+    // Everything is optional
+    assert_eq!(SimulationConfig::from_toml_str("").unwrap(), SimulationConfig::default());
+    assert_eq!(SimulationConfig::from_toml_str("[timestep]\nenabled = false").unwrap().timestep, None);
+
+    assert_eq!(
+        SimulationConfig::from_toml_str("num_particles = 10\nnum_partcles = 20"),
+        Err(ConfigError::UnknownKey { line: 2, key: "num_partcles".to_string() })
+    );
+    assert!(matches!(SimulationConfig::from_toml_str("num_particles = 1.5"), Err(ConfigError::InvalidValue { .. })));
+    assert!(matches!(SimulationConfig::from_toml_str("gravity = [0.0]"), Err(ConfigError::InvalidValue { .. })));
+    assert!(matches!(SimulationConfig::from_toml_str("[radial_gravity]\nstrength = 2.0"), Err(ConfigError::InvalidValue { .. })));
+    assert!(matches!(SimulationConfig::from_toml_str("boundary_mode = \"periodic\""), Err(ConfigError::InvalidValue { .. })));
+    assert!(matches!(SimulationConfig::from_toml_str("[recording]\nformat = \"gif\""), Err(ConfigError::InvalidValue { .. })));
+    assert!(matches!(SimulationConfig::from_toml_str("[recording]\nframe_interval = 0"), Err(ConfigError::InvalidValue { .. })));
+    assert!(matches!(SimulationConfig::load("no/such/config.toml"), Err(ConfigError::Io { .. })));
+}
+
+#[test]
+fn simulation_from_config_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let config = SimulationConfig::builder()
+        .units(PhysicalUnits::new(1.0))
+        .num_particles(50)
+        .world_size(Vec2::new(40.0, 20.0))
+        .initial_radius_range(0.5, 1.0)
+        .gravity(EARTH_GRAVITY)
+        .sort_interval(Duration::from_secs(10))
+        .build()
+        .unwrap();
+    let mut simulation = Simulation::with_config(wgpu_context, config, InitialLayout::HexPacking);
+
+    assert_eq!(simulation.particles().len(), 50);
+    assert_eq!(simulation.particles().get_world_size(), Vec2::new(40.0, 20.0));
+    assert_eq!(simulation.particles().gravity(), EARTH_GRAVITY);
+    assert_eq!(simulation.particles().sort_interval(), Duration::from_secs(10));
+    assert_eq!(simulation.config(), &config);
+    let buffers = simulation.particles_mut().download_particle_buffers(wgpu_context);
+    assert!(buffers.radii.data().iter().all(|radius| (0.5..=1.0).contains(radius)), "{:?}", buffers.radii.data());
+    assert!(buffers.current_positions.data().iter().all(|position| position.cmpge(Vec2::ZERO).all() && position.cmple(Vec2::new(40.0, 20.0)).all()));
+
+    simulation.set_config(SimulationConfig { sort_interval: Duration::from_secs(1), sort_disorder_threshold: Some(0.1), ..config });
+    assert_eq!(simulation.particles().sort_interval(), Duration::from_secs(1));
+    assert_eq!(simulation.particles().sort_disorder_threshold(), Some(0.1));
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::Simulation;
+use game_engine::simulation_layers::SimulationLayers;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const DELTA_TIME: f32 = 0.1;
+
+fn download_positions(wgpu_context: &WgpuContext, simulation: &mut Simulation) -> Vec<Vec2> {
+    simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().to_vec()
+}
+
+#[test]
+fn layers_add_remove_and_activate_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut layers = SimulationLayers::new("water", common::create_test_simulation(wgpu_context, vec![Vec2::new(100.0, 100.0)], 2.0));
+    let oil = layers.add("oil", common::create_test_simulation(wgpu_context, vec![Vec2::new(200.0, 100.0), Vec2::new(300.0, 100.0)], 2.0));
+    assert_eq!(oil, 1);
+    assert_eq!(layers.len(), 2);
+    assert_eq!(layers.find("oil"), Some(1));
+    assert_eq!(layers.particle_count(), 3);
+    assert_eq!(layers.active_index(), 0);
+
+    layers.set_active(oil);
+    assert_eq!(layers.active().particles().len(), 2);
+    assert_eq!(layers.cycle_active(), 0);
+    // Out of range, ignored
+    layers.set_active(5);
+    assert_eq!(layers.active_index(), 0);
+
+    // The active layer is removed, the one below it takes over
+    layers.set_active(oil);
+    assert_eq!(layers.remove(oil).unwrap().name(), "oil");
+    assert_eq!(layers.active_index(), 0);
+    assert_eq!(layers.active_layer().name(), "water");
+    // The last layer stays
+    assert!(layers.remove(0).is_none());
+    assert_eq!(layers.len(), 1);
+}
+
+#[test]
+fn layers_do_not_collide_with_each_other_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    // Overlapping pairs: one within the first layer, one across the two layers
+    let mut layers = SimulationLayers::new("first", common::create_test_simulation(wgpu_context, vec![Vec2::new(100.0, 500.0), Vec2::new(102.0, 500.0), Vec2::new(500.0, 500.0)], 2.0));
+    layers.add("second", common::create_test_simulation(wgpu_context, vec![Vec2::new(502.0, 500.0)], 2.0));
+    layers.add("disabled", common::create_test_simulation(wgpu_context, vec![Vec2::new(800.0, 500.0), Vec2::new(801.0, 500.0)], 2.0));
+    layers.set_enabled(2, false);
+
+    let steps = layers.advance(wgpu_context, &mut gpu_profiler, DELTA_TIME, None);
+    gpu_profiler.end_frame().unwrap();
+    // The steps of the fixed timestep of the default config, none for the disabled layer
+    assert!(steps[0] > 0);
+    assert_eq!(steps, vec![steps[0], steps[0], 0]);
+    assert_eq!(layers.get(2).unwrap().simulation().stats(wgpu_context).step_count, 0);
+
+    let first = download_positions(wgpu_context, layers.get_mut(0).unwrap().simulation_mut());
+    assert!(first[1].x - first[0].x > 2.0, "{first:?}");
+    // Not pushed by the particle of the second layer
+    assert!((first[2] - Vec2::new(500.0, 500.0)).length() < 1e-3, "{first:?}");
+    let second = download_positions(wgpu_context, layers.get_mut(1).unwrap().simulation_mut());
+    assert!((second[0] - Vec2::new(502.0, 500.0)).length() < 1e-3, "{second:?}");
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::physics::collision_system::SolverConfig;
+use game_engine::physics::solver_comparison::compare_solvers;
+
+/// Overlapping particles, so the solver has work to do from the first frame.
+fn gen_overlapping_particles() -> Vec<Vec2> {
+    let mut positions = Vec::new();
+    for y in 0..16 {
+        for x in 0..16 {
+            positions.push(Vec2::new(100.0 + x as f32 * 6.0, 100.0 + y as f32 * 6.0));
+        }
+    }
+    positions
+}
+
+#[test]
+fn same_solver_config_does_not_diverge_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    let positions = gen_overlapping_particles();
+    let radii = vec![5.0; positions.len()];
+    let mut particles = common::create_test_particle_system(wgpu_context, positions.clone(), radii);
+
+    let config = SolverConfig::default();
+    let comparison = compare_solvers(wgpu_context, &mut particles, 2, config, config, 30, 1.0 / 60.0);
+
+    assert_eq!(comparison.frames, 30);
+    assert_eq!(comparison.divergence.len(), 3);
+    let divergence = comparison.final_divergence().unwrap();
+    assert_eq!(divergence.frame, 30);
+    assert_eq!(divergence.max_distance, 0.0);
+
+    // The source particles are left untouched
+    assert_eq!(*particles.download_particle_buffers(wgpu_context).current_positions.data(), positions);
+}
+
+#[test]
+fn different_solver_configs_diverge_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    let positions = gen_overlapping_particles();
+    let radii = vec![5.0; positions.len()];
+    let mut particles = common::create_test_particle_system(wgpu_context, positions, radii);
+
+    let config_a = SolverConfig::default();
+    let config_b = SolverConfig { iterations: 3, ..config_a };
+    let comparison = compare_solvers(wgpu_context, &mut particles, 2, config_a, config_b, 20, 1.0 / 60.0);
+
+    assert_eq!(comparison.a.config, config_a);
+    assert_eq!(comparison.b.config, config_b);
+    assert!(comparison.final_divergence().unwrap().max_distance > 0.0);
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::grid::grid::Grid;
+use game_engine::physics::collision_system::{BroadphaseMode, CollisionSystem, SolverConfig};
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::{Simulation, DIMENSION};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+/// Steps once two particles of radius 2, one unit apart (75% overlap), and returns by how much the collisions
+/// separated them. The integration after the collisions carries the correction on as velocity, so the step moves
+/// them twice the correction apart.
+fn correction_of_step(wgpu_context: &WgpuContext, broadphase_mode: BroadphaseMode, slop: f32) -> f32 {
+    let particles = common::create_test_particle_system(
+        wgpu_context,
+        vec![Vec2::new(100.0, 100.0), Vec2::new(101.0, 100.0)],
+        vec![2.0, 2.0],
+    );
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let config = SolverConfig { stiffness: 1.0, slop, ..SolverConfig::default() };
+    let collision_system = CollisionSystem::new_with_config(wgpu_context, DIMENSION, &particles, &grid, config);
+    let mut simulation = Simulation::from_parts(wgpu_context, particles, grid, collision_system);
+    simulation.set_broadphase_mode(wgpu_context, broadphase_mode);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    simulation.step(wgpu_context, &mut gpu_profiler, 0.01, None);
+    gpu_profiler.end_frame().unwrap();
+    let positions = simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().to_vec();
+    (positions[0].distance(positions[1]) - 1.0) / 2.0
+}
+
+#[test]
+fn slop_leaves_the_allowed_penetration_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    for broadphase_mode in [BroadphaseMode::CollisionCells, BroadphaseMode::CellRanges] {
+        // Without slop, a full correction separates them
+        let correction = correction_of_step(wgpu_context, broadphase_mode, 0.0);
+        assert!((correction - 3.0).abs() < 1e-3, "{:?}: {}", broadphase_mode, correction);
+
+        // 10% of the radius sum is left overlapping
+        let correction = correction_of_step(wgpu_context, broadphase_mode, 0.1);
+        assert!((correction - 2.6).abs() < 1e-3, "{:?}: {}", broadphase_mode, correction);
+    }
+}
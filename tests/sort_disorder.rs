@@ -0,0 +1,72 @@
+mod common;
+
+use glam::Vec2;
+use wgpu::wgt::PollType::Wait;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::Simulation;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+/// Ten particles of radius 2 at rest along a row, in descending cell order: every neighbouring pair is out of order.
+fn create_simulation(wgpu_context: &WgpuContext) -> Simulation {
+    let positions: Vec<Vec2> = (0..10).map(|i| Vec2::new(500.0 - 40.0 * i as f32, 100.0)).collect();
+    let mut simulation = common::create_test_simulation(wgpu_context, positions, 2.0);
+    simulation.particles_mut().set_gravity(Vec2::ZERO);
+    simulation
+}
+
+/// Steps once and waits for the GPU, so the measure of the step can be polled.
+fn step(wgpu_context: &WgpuContext, simulation: &mut Simulation) {
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    simulation.step(wgpu_context, &mut gpu_profiler, 0.1, None);
+    gpu_profiler.end_frame().unwrap();
+    wgpu_context.get_device().poll(Wait).unwrap();
+}
+
+fn download_x(wgpu_context: &WgpuContext, simulation: &mut Simulation) -> Vec<f32> {
+    simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().iter().map(|position| position.x).collect()
+}
+
+#[test]
+fn disorder_above_the_threshold_sorts_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = create_simulation(wgpu_context);
+    simulation.particles_mut().set_sort_disorder_threshold(Some(0.5));
+    assert_eq!(simulation.particles().sort_disorder(), None);
+
+    // The first step only measures, the interval would have sorted
+    step(wgpu_context, &mut simulation);
+    assert_eq!(simulation.particles().sort_disorder(), None);
+    simulation.particles_mut().poll_sort_disorder(wgpu_context);
+    assert_eq!(simulation.particles().sort_disorder(), Some(1.0));
+    assert!(download_x(wgpu_context, &mut simulation).is_sorted_by(|a, b| a > b));
+
+    // Out of order, the next step sorts
+    step(wgpu_context, &mut simulation);
+    assert_eq!(simulation.particles().sort_disorder(), Some(0.0));
+    assert!(download_x(wgpu_context, &mut simulation).is_sorted());
+
+    // And measures again after it
+    step(wgpu_context, &mut simulation);
+    simulation.particles_mut().poll_sort_disorder(wgpu_context);
+    assert_eq!(simulation.particles().sort_disorder(), Some(0.0));
+}
+
+#[test]
+fn disorder_below_the_threshold_keeps_the_order_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = create_simulation(wgpu_context);
+    simulation.particles_mut().set_sort_disorder_threshold(Some(1.0));
+
+    for _ in 0..4 {
+        step(wgpu_context, &mut simulation);
+    }
+    assert_eq!(simulation.particles().sort_disorder(), Some(1.0));
+    assert!(download_x(wgpu_context, &mut simulation).is_sorted_by(|a, b| a > b));
+
+    // Back to the sort interval, which never sorted yet
+    simulation.particles_mut().set_sort_disorder_threshold(None);
+    step(wgpu_context, &mut simulation);
+    assert!(download_x(wgpu_context, &mut simulation).is_sorted());
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use glam::{IVec2, UVec2, Vec2};
+use game_engine::grid::grid::Grid;
+use game_engine::grid::{morton, spatial_hash};
+use game_engine::particles::particle_system::ParticleSystem;
+use game_engine::physics::collision_system::CollisionSystem;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const UNUSED_CELL_ID: u32 = 0xffffffff;
+const TABLE_BITS: u32 = spatial_hash::DEFAULT_TABLE_BITS;
+
+fn hash(x: i32, y: i32) -> u32 {
+    spatial_hash::encode(IVec2::new(x, y), TABLE_BITS)
+}
+
+/// The particles of the morton grid tests, and one below the origin
+fn build_hashed_grid(wgpu_context: &WgpuContext) -> (Grid, ParticleSystem) {
+    let positions = vec![
+        // Particle 0: Crosses into 3 neighbors.
+        Vec2::new(20.0, 42.0),
+        // Particle 1: Fully contained within one cell.
+        Vec2::new(77.0, 77.0),
+        // Particle 2: Edge case at the origin, fully contained.
+        Vec2::new(5.0, 5.0),
+        // Particle 3: In cell (-2, -3), which the morton ids clamp to (0, 0)
+        Vec2::new(-30.0, -50.0),
+    ];
+    let particle_system = common::create_test_particle_system(wgpu_context, positions, vec![10.0, 8.0, 1.0, 1.0]);
+    // Cells are 22 units wide
+    let mut grid = Grid::new_without_camera(wgpu_context, 10.0, &particle_system);
+    grid.set_spatial_hash(Some(TABLE_BITS));
+    (grid, particle_system)
+}
+
+fn expected_map() -> (Vec<u32>, Vec<u32>) {
+    let cell_ids = vec![
+        hash(0, 1), hash(1, 1), hash(0, 2), hash(1, 2),
+        hash(3, 3), UNUSED_CELL_ID, UNUSED_CELL_ID, UNUSED_CELL_ID,
+        hash(0, 0), UNUSED_CELL_ID, UNUSED_CELL_ID, UNUSED_CELL_ID,
+        hash(-2, -3), UNUSED_CELL_ID, UNUSED_CELL_ID, UNUSED_CELL_ID,
+    ];
+    let object_ids = vec![0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0];
+    (cell_ids, object_ids)
+}
+
+#[test]
+fn spatial_hash_matches_the_morton_colors_test() {
+    for y in -5..5 {
+        for x in -5..5 {
+            let cell_id = hash(x, y);
+            assert!(cell_id < (1 << spatial_hash::key_bits(TABLE_BITS)) - 1);
+            if x >= 0 && y >= 0 {
+                assert_eq!(spatial_hash::cell_id_color(cell_id), morton::cell_color(UVec2::new(x as u32, y as u32)));
+            }
+            // Neighbouring cells never share an id
+            for (dx, dy) in [(1, 0), (0, 1), (1, 1), (1, -1)] {
+                assert_ne!(spatial_hash::cell_id_color(cell_id), spatial_hash::cell_id_color(hash(x + dx, y + dy)));
+            }
+        }
+    }
+    assert_eq!(spatial_hash::cell_coord(Vec2::new(-30.0, -50.0), 22.0), IVec2::new(-2, -3));
+}
+
+#[test]
+fn hashed_grid_build_cell_ids_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let (mut grid, _) = build_hashed_grid(wgpu_context);
+    assert_eq!(grid.spatial_hash_bits(), Some(TABLE_BITS));
+
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    grid.build_cell_ids(&mut encoder);
+    wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+
+    let (expected_cell_ids, expected_object_ids) = expected_map();
+    assert_eq!(grid.download_cell_ids(wgpu_context).unwrap(), expected_cell_ids);
+    assert_eq!(grid.download_object_ids(wgpu_context).unwrap(), expected_object_ids);
+}
+
+#[test]
+fn hashed_grid_build_cell_ids_and_sort_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let (mut grid, _) = build_hashed_grid(wgpu_context);
+
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    grid.build_cell_ids(&mut encoder);
+    grid.sort_map(&mut encoder);
+    wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+
+    let (expected_cell_ids, expected_object_ids) = expected_map();
+    let mut expected: Vec<(u32, u32)> = expected_cell_ids.into_iter().zip(expected_object_ids).collect();
+    expected.sort();
+    let cell_ids = grid.download_cell_ids(wgpu_context).unwrap();
+    let object_ids = grid.download_object_ids(wgpu_context).unwrap();
+    let sorted: Vec<(u32, u32)> = cell_ids.into_iter().zip(object_ids).collect();
+    assert_eq!(sorted, expected);
+
+    // Back to the morton ids
+    grid.set_spatial_hash(None);
+    assert_eq!(grid.spatial_hash_bits(), None);
+}
+
+#[test]
+fn colliding_slots_only_solve_touching_pairs_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    // Two overlapping pairs and two lone particles, far apart and below the origin
+    let positions = vec![
+        Vec2::new(100.0, 100.0), Vec2::new(103.0, 100.0),
+        Vec2::new(-5000.0, -800.0), Vec2::new(-5000.0, -797.0),
+        Vec2::new(900.0, -3000.0),
+        Vec2::new(-2000.0, 4000.0),
+    ];
+    let mut particles = common::create_test_particle_system(wgpu_context, positions.clone(), vec![2.0; positions.len()]);
+    let mut grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    // A table of 2 slots: the cells of the same color all share an id
+    grid.set_spatial_hash(Some(1));
+    let mut collision_system = CollisionSystem::new(wgpu_context, 2, &particles, &grid);
+
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    grid.build_cell_ids(&mut encoder);
+    grid.sort_map(&mut encoder);
+    collision_system.solve_collisions(wgpu_context, encoder, &mut gpu_profiler);
+
+    let stepped = particles.download_particle_buffers(wgpu_context).current_positions.data().to_vec();
+    assert!(stepped[0].distance(stepped[1]) > 3.5, "{stepped:?}");
+    assert!(stepped[2].distance(stepped[3]) > 3.5, "{stepped:?}");
+    assert_eq!(&stepped[4..], &positions[4..]);
+}
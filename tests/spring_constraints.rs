@@ -0,0 +1,97 @@
+mod common;
+
+use std::collections::HashSet;
+use glam::Vec2;
+use game_engine::particles::particle_system::{ParticleLimit, SpawnOverflow};
+use game_engine::physics::spring_constraints::{chain_layout, cloth_layout, color_springs, ParticleHandle, Spring};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+#[test]
+fn spring_layouts_test() {
+    let (positions, links) = chain_layout(Vec2::ZERO, Vec2::new(0.0, 10.0), 2.5);
+    assert_eq!(positions.len(), 5);
+    assert_eq!(positions[4], Vec2::new(0.0, 10.0));
+    assert_eq!(links, vec![(0, 1), (1, 2), (2, 3), (3, 4)]);
+
+    let (positions, links) = cloth_layout(Vec2::new(1.0, 1.0), 4, 3, 2.0);
+    assert_eq!(positions.len(), 12);
+    assert_eq!(positions[11], Vec2::new(7.0, 5.0));
+    // Horizontal, vertical and both diagonals
+    assert_eq!(links.len(), 3 * 3 + 4 * 2 + 2 * 3 * 2);
+
+    // No two springs of a group share a particle
+    let springs: Vec<Spring> = links.iter().map(|&(a, b)| Spring {
+        a: ParticleHandle(a as u32),
+        b: ParticleHandle(b as u32),
+        rest_length: 1.0,
+        stiffness: 1.0,
+    }).collect();
+    let (ordered, groups) = color_springs(&springs);
+    assert_eq!(ordered.len(), springs.len());
+    assert_eq!(groups.last().unwrap().end, springs.len());
+    for group in groups {
+        let mut handles = HashSet::new();
+        for spring in &ordered[group] {
+            assert!(handles.insert(spring.a) && handles.insert(spring.b));
+        }
+    }
+}
+
+#[test]
+fn springs_pull_the_particles_to_their_rest_length_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    let mut simulation = common::create_test_simulation(wgpu_context, vec![Vec2::new(100.0, 100.0)], 2.0);
+    simulation.particles_mut().set_gravity(Vec2::ZERO);
+
+    let body = simulation.add_spring_body(wgpu_context, &[Vec2::new(500.0, 500.0), Vec2::new(520.0, 500.0)], &[], 1.0).unwrap();
+    assert_eq!(body.num_particles(), 2);
+    assert_eq!(simulation.particles().len(), 3);
+    assert!(simulation.springs().is_empty());
+    simulation.add_springs(wgpu_context, &[Spring { a: body.handle(0), b: body.handle(1), rest_length: 10.0, stiffness: 1.0 }]);
+
+    simulation.step(wgpu_context, &mut gpu_profiler, 0.01, None);
+    gpu_profiler.end_frame().unwrap();
+    let positions = simulation.spring_handle_positions(wgpu_context);
+    let (a, b) = (positions[0].unwrap(), positions[1].unwrap());
+    assert!((a.distance(b) - 10.0).abs() < 1e-3, "{} {}", a, b);
+    assert!(((a + b) / 2.0).distance(Vec2::new(510.0, 500.0)) < 1e-3);
+
+    simulation.clear_springs(wgpu_context);
+    assert!(simulation.springs().is_empty());
+}
+
+#[test]
+fn chains_and_cloths_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    let mut simulation = common::create_test_simulation(wgpu_context, vec![Vec2::new(100.0, 100.0)], 2.0);
+    simulation.particles_mut().set_spawn_radius_range(2.0, 2.0);
+
+    let chain = simulation.add_chain(wgpu_context, Vec2::new(500.0, 800.0), Vec2::new(540.0, 800.0), 1.0).unwrap();
+    assert_eq!(chain.num_particles(), 11);
+    assert_eq!(chain.num_springs, 10);
+    let cloth = simulation.add_cloth(wgpu_context, Vec2::new(1000.0, 600.0), 5, 4, 1.0).unwrap();
+    assert_eq!(cloth.num_particles(), 20);
+    assert_eq!(cloth.handle(0), ParticleHandle(11));
+    assert_eq!(simulation.springs().len(), 10 + cloth.num_springs);
+    assert_eq!(simulation.particles().len(), 1 + 11 + 20);
+
+    // Falling together, the links keep their length
+    for _ in 0..10 {
+        simulation.step(wgpu_context, &mut gpu_profiler, 0.005, None);
+        gpu_profiler.end_frame().unwrap();
+    }
+    let positions = simulation.spring_handle_positions(wgpu_context);
+    for spring in simulation.springs() {
+        let distance = positions[spring.a.0 as usize].unwrap().distance(positions[spring.b.0 as usize].unwrap());
+        assert!((distance - spring.rest_length).abs() < 0.1, "{:?} at {}", spring, distance);
+    }
+
+    // Nothing is spawned when the body does not fit
+    simulation.particles_mut().set_limit(wgpu_context, ParticleLimit { max_particles: 40, overflow: SpawnOverflow::Refuse });
+    assert!(simulation.add_cloth(wgpu_context, Vec2::new(300.0, 300.0), 3, 3, 1.0).is_none());
+    assert_eq!(simulation.particles().len(), 32);
+}
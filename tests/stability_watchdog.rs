@@ -0,0 +1,72 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::physics::stability_watchdog::StabilityWatchdog;
+use game_engine::particles::particle_system::ParticleSystem;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use wgpu::wgt::PollType::Wait;
+
+fn check(wgpu_context: &WgpuContext, watchdog: &mut StabilityWatchdog, particles: &ParticleSystem) -> game_engine::physics::stability_watchdog::StabilityReport {
+    watchdog.request(wgpu_context, particles);
+    wgpu_context.get_device().poll(Wait).unwrap();
+    watchdog.poll(wgpu_context).unwrap()
+}
+
+#[test]
+fn stable_particles_are_not_reported_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let particles = common::create_test_particle_system(
+        wgpu_context,
+        vec![Vec2::new(10.0, 10.0), Vec2::new(20.0, 20.0), Vec2::new(30.0, 30.0)],
+        vec![1.0, 1.0, 1.0],
+    );
+
+    let mut watchdog = StabilityWatchdog::new(wgpu_context, &particles);
+    let report = check(wgpu_context, &mut watchdog, &particles);
+
+    assert!(report.is_stable());
+    assert_eq!(report.first_invalid_particle, None);
+}
+
+#[test]
+fn nan_positions_are_reported_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let particles = common::create_test_particle_system(
+        wgpu_context,
+        vec![Vec2::new(10.0, 10.0), Vec2::new(f32::NAN, 20.0), Vec2::new(30.0, 30.0), Vec2::new(40.0, f32::INFINITY)],
+        vec![1.0, 1.0, 1.0, 1.0],
+    );
+
+    let mut watchdog = StabilityWatchdog::new(wgpu_context, &particles);
+    let report = check(wgpu_context, &mut watchdog, &particles);
+
+    assert_eq!(report.invalid_particles, 2);
+    assert_eq!(report.first_invalid_particle, Some(1));
+}
+
+#[test]
+fn exploding_particles_are_reported_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let particles = common::create_test_particle_system(
+        wgpu_context,
+        vec![Vec2::new(10.0, 10.0), Vec2::new(20.0, 20.0), Vec2::new(30.0, 30.0)],
+        vec![1.0, 1.0, 1.0],
+    );
+    // Particle 2 moved 500 units in the last step
+    let previous_positions = vec![Vec2::new(10.0, 10.0), Vec2::new(21.0, 20.0), Vec2::new(530.0, 30.0)];
+    wgpu_context.get_queue().write_buffer(particles.buffers().previous_positions.buffer(), 0, bytemuck::cast_slice(&previous_positions));
+
+    let mut watchdog = StabilityWatchdog::new(wgpu_context, &particles);
+    watchdog.set_max_step_displacement(100.0);
+    let report = check(wgpu_context, &mut watchdog, &particles);
+
+    assert_eq!(report.invalid_particles, 1);
+    assert_eq!(report.first_invalid_particle, Some(2));
+
+    // Checks can be repeated, the result is cleared every time
+    let report = check(wgpu_context, &mut watchdog, &particles);
+    assert_eq!(report.invalid_particles, 1);
+}
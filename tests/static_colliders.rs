@@ -0,0 +1,65 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::physics::static_colliders::StaticCircle;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::Simulation;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const RADIUS: f32 = 2.0;
+
+fn step(wgpu_context: &WgpuContext, simulation: &mut Simulation, gpu_profiler: &mut GpuProfiler) -> Vec<Vec2> {
+    simulation.step(wgpu_context, gpu_profiler, 0.01, None);
+    gpu_profiler.end_frame().unwrap();
+    simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().to_vec()
+}
+
+#[test]
+fn particles_are_pushed_out_of_circles_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let center = Vec2::new(400.0, 400.0);
+    let positions = vec![
+        // Inside the circle, on its right and above it
+        Vec2::new(415.0, 400.0),
+        Vec2::new(400.0, 420.0),
+        // Away from it
+        Vec2::new(400.0, 600.0),
+    ];
+    let mut simulation = common::create_test_simulation(wgpu_context, positions.clone(), RADIUS);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    // Without circles nothing moves, but the first step sorts the particles
+    let sorted = step(wgpu_context, &mut simulation, &mut gpu_profiler);
+    let [right, above, away] = [0, 1, 2].map(|i| sorted.iter().position(|&position| position == positions[i]).unwrap());
+
+    assert_eq!(simulation.add_static_circle(wgpu_context, center, 30.0), 0);
+    assert_eq!(simulation.static_circles(), &[StaticCircle::new(center, 30.0)]);
+    let stepped = step(wgpu_context, &mut simulation, &mut gpu_profiler);
+
+    // Pushed out radially to the surface, then moved on by the velocity the push gave them
+    assert!(stepped[right].distance(center) >= 30.0 + RADIUS - 1e-3, "{stepped:?}");
+    assert!(stepped[above].distance(center) >= 30.0 + RADIUS - 1e-3, "{stepped:?}");
+    assert!((stepped[right].y - center.y).abs() < 1e-4, "{stepped:?}");
+    assert!((stepped[above].x - center.x).abs() < 1e-4, "{stepped:?}");
+    assert_eq!(stepped[away], positions[2]);
+
+    simulation.clear_static_circles(wgpu_context);
+    assert!(simulation.static_circles().is_empty());
+}
+
+#[test]
+fn circles_are_removed_under_the_cursor_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = common::create_test_simulation(wgpu_context, vec![Vec2::new(100.0, 100.0)], RADIUS);
+    simulation.add_static_circle(wgpu_context, Vec2::new(500.0, 500.0), 40.0);
+    simulation.add_static_circle(wgpu_context, Vec2::new(520.0, 500.0), 40.0);
+
+    assert_eq!(simulation.remove_static_circle_at(wgpu_context, Vec2::new(800.0, 800.0)), None);
+    // The last placed circle is on top
+    assert_eq!(simulation.remove_static_circle_at(wgpu_context, Vec2::new(510.0, 500.0)), Some(StaticCircle::new(Vec2::new(520.0, 500.0), 40.0)));
+    assert_eq!(simulation.static_circles(), &[StaticCircle::new(Vec2::new(500.0, 500.0), 40.0)]);
+    assert!(StaticCircle::new(Vec2::ZERO, 1.0).contains(Vec2::new(0.5, 0.5)));
+    assert!(!StaticCircle::new(Vec2::ZERO, 1.0).contains(Vec2::new(1.0, 1.0)));
+}
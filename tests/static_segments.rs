@@ -0,0 +1,74 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::physics::collision_system::StaticSegment;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::Simulation;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const RADIUS: f32 = 2.0;
+
+fn step(wgpu_context: &WgpuContext, simulation: &mut Simulation, gpu_profiler: &mut GpuProfiler) -> Vec<Vec2> {
+    simulation.step(wgpu_context, gpu_profiler, 0.01, None);
+    gpu_profiler.end_frame().unwrap();
+    simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().to_vec()
+}
+
+#[test]
+fn particles_are_pushed_out_of_segments_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let positions = vec![
+        // Above, below and past the end of the segment, all overlapping it
+        Vec2::new(100.0, 101.0),
+        Vec2::new(80.0, 98.5),
+        Vec2::new(151.0, 100.0),
+        // Away from it
+        Vec2::new(100.0, 200.0),
+    ];
+    let mut simulation = common::create_test_simulation(wgpu_context, positions.clone(), RADIUS);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    // Without segments nothing moves, but the first step sorts the particles
+    let sorted = step(wgpu_context, &mut simulation, &mut gpu_profiler);
+    let [above, below, past_the_end, away] = [0, 1, 2, 3].map(|i| sorted.iter().position(|&position| position == positions[i]).unwrap());
+
+    assert_eq!(simulation.add_static_segment(wgpu_context, Vec2::new(50.0, 100.0), Vec2::new(150.0, 100.0)), 0);
+    assert_eq!(simulation.static_segments(), &[StaticSegment { start: Vec2::new(50.0, 100.0), end: Vec2::new(150.0, 100.0) }]);
+    let stepped = step(wgpu_context, &mut simulation, &mut gpu_profiler);
+
+    // Pushed out by the radius, then moved on by the velocity the push gave them
+    assert!(stepped[above].y >= 100.0 + RADIUS - 1e-4, "{stepped:?}");
+    assert!(stepped[below].y <= 100.0 - RADIUS + 1e-4, "{stepped:?}");
+    assert!(stepped[past_the_end].x >= 150.0 + RADIUS - 1e-4, "{stepped:?}");
+    assert_eq!(stepped[away], positions[3]);
+    // Along the segment normal only
+    assert!((stepped[above].x - positions[0].x).abs() < 1e-4, "{stepped:?}");
+    assert!((stepped[below].x - positions[1].x).abs() < 1e-4, "{stepped:?}");
+
+    simulation.clear_static_segments(wgpu_context);
+    assert!(simulation.static_segments().is_empty());
+}
+
+#[test]
+fn funnel_keeps_the_particles_inside_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    // A box around a small pile
+    let positions: Vec<Vec2> = (0..20).map(|i| Vec2::new(300.0 + (i % 5) as f32 * 3.0, 300.0 + (i / 5) as f32 * 3.0)).collect();
+    let mut simulation = common::create_test_simulation(wgpu_context, positions, RADIUS);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    let corners = [Vec2::new(290.0, 290.0), Vec2::new(330.0, 290.0), Vec2::new(330.0, 330.0), Vec2::new(290.0, 330.0)];
+    for i in 0..4 {
+        simulation.add_static_segment(wgpu_context, corners[i], corners[(i + 1) % 4]);
+    }
+
+    let mut positions = Vec::new();
+    for _ in 0..100 {
+        positions = step(wgpu_context, &mut simulation, &mut gpu_profiler);
+    }
+    // The overlapping particles spread out, but stay in the box
+    for position in positions {
+        assert!(position.cmpge(corners[0]).all() && position.cmple(corners[2]).all(), "{position:?}");
+    }
+}
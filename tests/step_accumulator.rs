@@ -0,0 +1,27 @@
+use game_engine::simulation_config::FixedTimestep;
+use game_engine::utils::step_accumulator::StepAccumulator;
+
+// Powers of two, so the accumulated times are exact
+const TIMESTEP: FixedTimestep = FixedTimestep { step: 0.25, substeps: 4, max_steps_per_frame: 3 };
+
+#[test]
+fn frame_times_are_consumed_in_fixed_steps_test() {
+    let mut accumulator = StepAccumulator::new();
+    assert_eq!(accumulator.take_steps(0.125, &TIMESTEP), 0);
+    assert_eq!(accumulator.accumulated(), 0.125);
+    assert_eq!(accumulator.take_steps(0.625, &TIMESTEP), 3);
+    assert_eq!(accumulator.accumulated(), 0.0);
+    assert_eq!(accumulator.take_steps(0.375, &TIMESTEP), 1);
+    assert_eq!(accumulator.accumulated(), 0.125);
+    assert_eq!(TIMESTEP.substep_time(), 0.0625);
+}
+
+#[test]
+fn slow_frames_drop_the_steps_over_the_max_test() {
+    let mut accumulator = StepAccumulator::new();
+    assert_eq!(accumulator.take_steps(10.125, &TIMESTEP), TIMESTEP.max_steps_per_frame);
+    // Only the fraction of a step is carried over
+    assert_eq!(accumulator.accumulated(), 0.125);
+    accumulator.reset();
+    assert_eq!(accumulator.accumulated(), 0.0);
+}
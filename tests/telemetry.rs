@@ -0,0 +1,47 @@
+mod common;
+
+use std::time::Duration;
+use game_engine::renderer::wgpu_context::{WgpuContext, TEST_FEATURES};
+use game_engine::utils::gpu_buffer::GpuBuffer;
+use game_engine::utils::telemetry::{RefreshTimer, RefreshTiming};
+
+#[test]
+fn queue_drain_is_only_measured_on_request_test() {
+    let mut setup = pollster::block_on(common::setup());
+    assert!(!setup.wgpu_context.measures_queue_drain());
+    let timing = RefreshTimer::start(&setup.wgpu_context).finish(&setup.wgpu_context);
+    assert_eq!(timing.queue_drain, None);
+    assert!(!timing.to_string().contains("queue drain"));
+    assert_eq!(timing.gpu, None);
+
+    setup.wgpu_context.set_measure_queue_drain(true);
+    let timing = RefreshTimer::start(&setup.wgpu_context).finish(&setup.wgpu_context);
+    assert!(timing.queue_drain.is_some());
+    assert!(timing.to_string().contains("queue drain"));
+
+    // A refresh timed without the drain does not hide the drain of the others
+    let drained = RefreshTiming { cpu: Duration::from_millis(1), queue_drain: Some(Duration::from_millis(2)), gpu: Some(Duration::from_millis(1)) };
+    let undrained = RefreshTiming { cpu: Duration::from_millis(3), queue_drain: None, gpu: None };
+    let total = drained + undrained;
+    assert_eq!(total.cpu, Duration::from_millis(4));
+    assert_eq!(total.queue_drain, Some(Duration::from_millis(2)));
+    assert_eq!(total.gpu, Some(Duration::from_millis(1)));
+}
+
+#[test]
+fn gpu_time_of_a_refresh_is_measured_with_timestamps_test() {
+    let timestamps = wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS;
+    let mut wgpu_context = pollster::block_on(WgpuContext::new_for_test_with_features(TEST_FEATURES | timestamps)).unwrap();
+    wgpu_context.set_measure_queue_drain(true);
+
+    let mut buffer = GpuBuffer::new(&wgpu_context, vec![0u32; 1024], wgpu::BufferUsages::STORAGE);
+    let timer = RefreshTimer::start(&wgpu_context);
+    // Grows with a copy of the old contents
+    buffer.push_all(&vec![1u32; 1 << 20], &wgpu_context);
+    let timing = timer.finish(&wgpu_context);
+
+    assert!(timing.queue_drain.is_some());
+    // Adapters without timestamps inside encoders only measure the drain
+    assert_eq!(timing.gpu.is_some(), wgpu_context.get_device().features().contains(timestamps));
+    assert_eq!(timing.to_string().contains("gpu"), timing.gpu.is_some());
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use glam::Vec2;
+use game_engine::grid::grid::Grid;
+use game_engine::physics::collision_system::CollisionSystem;
+use game_engine::physics::trajectory::{compare_trajectories, Trajectory};
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::{Simulation, DIMENSION};
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const FRAMES: u32 = 6;
+
+fn trajectory(frames: &[(u32, [Vec2; 2])]) -> Trajectory {
+    let mut trajectory = Trajectory::new(2);
+    for (frame, positions) in frames {
+        trajectory.push(*frame, positions.to_vec());
+    }
+    trajectory
+}
+
+#[test]
+fn save_and_load_test() {
+    let recording = trajectory(&[(0, [Vec2::new(1.0, 2.0), Vec2::new(3.0, 4.0)]), (5, [Vec2::new(1.5, 2.0), Vec2::NAN])]);
+    let path = std::env::temp_dir().join(format!("trajectory_test_{}.traj", std::process::id()));
+    recording.save(&path).unwrap();
+    let loaded = Trajectory::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.num_particles(), 2);
+    assert_eq!(loaded.frames().len(), 2);
+    assert_eq!(loaded.frames()[0], recording.frames()[0]);
+    assert_eq!(loaded.frames()[1].frame, 5);
+    assert!(loaded.frames()[1].positions[1].is_nan());
+}
+
+#[test]
+fn first_divergence_test() {
+    let a = trajectory(&[
+        (0, [Vec2::ZERO, Vec2::ONE]),
+        (1, [Vec2::ZERO, Vec2::ONE]),
+        (2, [Vec2::ZERO, Vec2::ONE]),
+        (3, [Vec2::ZERO, Vec2::ONE]),
+    ]);
+    let b = trajectory(&[
+        (0, [Vec2::ZERO, Vec2::ONE]),
+        (1, [Vec2::new(0.001, 0.0), Vec2::ONE]),
+        (2, [Vec2::new(0.5, 0.0), Vec2::ONE]),
+        (3, [Vec2::new(2.0, 0.0), Vec2::ONE]),
+    ]);
+
+    let divergence = compare_trajectories(&a, &b, 0.0);
+    assert_eq!(divergence.first_divergent_frame, Some(1));
+    assert_eq!(divergence.max_divergence(), 2.0);
+    assert_eq!(divergence.samples.len(), 4);
+    assert!((divergence.samples[2].mean_distance - 0.25).abs() < 1e-6);
+
+    let divergence = compare_trajectories(&a, &b, 0.1);
+    assert_eq!(divergence.first_divergent_frame, Some(2));
+    assert!(!divergence.is_identical());
+
+    assert!(compare_trajectories(&a, &a, 0.0).is_identical());
+}
+
+#[test]
+fn untracked_particles_test() {
+    let a = trajectory(&[(0, [Vec2::ZERO, Vec2::NAN]), (1, [Vec2::ZERO, Vec2::NAN])]);
+    let b = trajectory(&[(0, [Vec2::ZERO, Vec2::NAN]), (1, [Vec2::ZERO, Vec2::ONE]), (2, [Vec2::ZERO, Vec2::ONE])]);
+
+    let divergence = compare_trajectories(&a, &b, 0.0);
+    // Missing in both runs at frame 0, only in one at frame 1
+    assert_eq!(divergence.first_divergent_frame, Some(1));
+    assert_eq!(divergence.samples[0].max_distance, 0.0);
+    assert_eq!(divergence.unmatched_frames, 1);
+}
+
+/// Two overlapping pairs that push each other apart. The mouse starts pulling the particles at `pull_from_frame`.
+fn record_run(wgpu_context: &WgpuContext, pull_from_frame: Option<u32>) -> Trajectory {
+    let particles = common::create_test_particle_system(
+        wgpu_context,
+        vec![Vec2::new(300.0, 300.0), Vec2::new(100.0, 100.0), Vec2::new(302.0, 300.0), Vec2::new(101.0, 101.0)],
+        vec![2.0, 2.0, 2.0, 2.0],
+    );
+    let grid = Grid::new_without_camera(wgpu_context, particles.get_max_radius(), &particles);
+    let collision_system = CollisionSystem::new(wgpu_context, DIMENSION, &particles, &grid);
+    let mut simulation = Simulation::from_parts(wgpu_context, particles, grid, collision_system);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    let mut recorder = simulation.start_trajectory_recording(wgpu_context);
+    simulation.record_trajectory(wgpu_context, &mut recorder);
+    for frame in 1..=FRAMES {
+        if pull_from_frame == Some(frame) {
+            simulation.particles_mut().mouse_click_callback(true, Vec2::new(1000.0, 500.0));
+        }
+        simulation.step(wgpu_context, &mut gpu_profiler, 0.016, None);
+        gpu_profiler.end_frame().unwrap();
+        simulation.record_trajectory(wgpu_context, &mut recorder);
+    }
+    recorder.finish()
+}
+
+#[test]
+fn recorded_runs_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+
+    let a = record_run(wgpu_context, None);
+    let b = record_run(wgpu_context, None);
+    let pulled = record_run(wgpu_context, Some(4));
+
+    assert_eq!(a.frames().len(), FRAMES as usize + 1);
+    // The particles are followed through the sort of the first step
+    assert_eq!(a.frames()[0].positions[0], Vec2::new(300.0, 300.0));
+    // Pushed apart by the first step, then drifting at the speed of that correction, less than a radius per frame
+    assert!(a.frames()[FRAMES as usize].positions[0].distance(Vec2::new(300.0, 300.0)) < 2.0 * FRAMES as f32);
+    assert!(a.frames().iter().all(|frame| frame.positions.iter().all(|position| !position.is_nan())));
+
+    assert!(compare_trajectories(&a, &b, 0.0).is_identical());
+    let divergence = compare_trajectories(&a, &pulled, 0.0);
+    assert_eq!(divergence.first_divergent_frame, Some(4));
+    assert!(divergence.max_divergence() > 0.0);
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use game_engine::utils::gpu_buffer::GpuBuffer;
+use game_engine::utils::telemetry::{Telemetry, TransferStats, LARGE_TRANSFER_BYTES};
+
+#[test]
+fn gpu_buffer_transfers_are_counted_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let transfers = wgpu_context.transfers();
+    transfers.take();
+
+    let mut buffer = GpuBuffer::new(wgpu_context, vec![0u32; 100], wgpu::BufferUsages::STORAGE);
+    assert_eq!(transfers.take(), TransferStats { uploaded_bytes: 400, copied_bytes: 0, readback_bytes: 0 });
+
+    // Only the element is uploaded
+    buffer.replace_elem(7, 10, wgpu_context);
+    assert_eq!(transfers.take(), TransferStats { uploaded_bytes: 4, copied_bytes: 0, readback_bytes: 0 });
+
+    // Growing copies the old contents into the new buffer
+    buffer.push(1, wgpu_context);
+    assert_eq!(transfers.take(), TransferStats { uploaded_bytes: 4, copied_bytes: 400, readback_bytes: 0 });
+
+    buffer.read_back(wgpu_context).unwrap();
+    buffer.download_range(wgpu_context, 0..2).unwrap();
+    assert_eq!(transfers.current(), TransferStats { uploaded_bytes: 0, copied_bytes: 0, readback_bytes: 404 + 8 });
+
+    buffer.overwrite(&vec![3u32; 101], wgpu_context);
+    assert_eq!(transfers.take().uploaded_bytes, 404);
+    assert_eq!(transfers.take(), TransferStats::default());
+}
+
+#[test]
+fn telemetry_keeps_the_frame_transfers_test() {
+    let mut telemetry = Telemetry::new();
+    assert_eq!(telemetry.last_frame_transfers(), None);
+
+    let small = TransferStats { uploaded_bytes: 64, copied_bytes: 0, readback_bytes: 8 };
+    let large = TransferStats { uploaded_bytes: LARGE_TRANSFER_BYTES, copied_bytes: 16, readback_bytes: 0 };
+    telemetry.record_frame_transfers(small);
+    telemetry.record_frame_transfers(large);
+    telemetry.record_frame_transfers(small);
+
+    assert_eq!(telemetry.last_frame_transfers(), Some(small));
+    assert_eq!(telemetry.frame_transfers().len(), 3);
+    assert_eq!(telemetry.peak_frame_transfers(), TransferStats { uploaded_bytes: LARGE_TRANSFER_BYTES, copied_bytes: 16, readback_bytes: 8 });
+    assert_eq!((small + large).total_bytes(), LARGE_TRANSFER_BYTES + 88);
+
+    // Only the most recent frames are kept
+    for _ in 0..1000 {
+        telemetry.record_frame_transfers(small);
+    }
+    assert!(telemetry.frame_transfers().len() < 1000);
+    assert_eq!(telemetry.peak_frame_transfers(), small);
+    assert!(telemetry.summary().contains("0 spawn batches"), "{}", telemetry.summary());
+    assert!(telemetry.summary().contains("64 B uploaded"), "{}", telemetry.summary());
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use glam::{UVec2, Vec2};
+use game_engine::grid::velocity_field::{VelocityField, VelocityFieldParams, ARROW_VERTICES, MAX_ARROWS_PER_SIDE};
+use game_engine::utils::gpu_buffer::GpuBuffer;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+fn assert_close(actual: Vec2, expected: Vec2) {
+    assert!((actual - expected).length() < 1e-2, "{actual} != {expected}");
+}
+
+#[test]
+fn velocity_field_averages_the_velocities_per_cell_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let delta_time = 0.5;
+    // Displacements of the last step
+    let particles = [
+        (Vec2::new(5.0, 5.0), Vec2::new(1.0, 0.0)),
+        (Vec2::new(6.0, 4.0), Vec2::new(0.0, 1.0)),
+        (Vec2::new(15.0, 5.0), Vec2::new(-2.0, 0.0)),
+        (Vec2::new(35.0, 15.0), Vec2::new(0.0, 0.0)),
+    ];
+    let positions = GpuBuffer::new(wgpu_context, particles.iter().map(|(position, _)| *position).collect(), wgpu::BufferUsages::STORAGE);
+    let previous_positions = GpuBuffer::new(wgpu_context, particles.iter().map(|(position, displacement)| *position - *displacement).collect(), wgpu::BufferUsages::STORAGE);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+    let mut field = VelocityField::new(wgpu_context);
+    let params = VelocityFieldParams {
+        num_particles: particles.len(),
+        delta_time,
+        origin: Vec2::ZERO,
+        world_size: Vec2::new(40.0, 20.0),
+        cell_size: 10.0,
+    };
+
+    field.update(wgpu_context, &mut gpu_profiler, &positions, &previous_positions, params);
+    assert_eq!(field.table_size(), UVec2::new(4, 2));
+    let velocities = field.download_velocities(wgpu_context);
+    assert_eq!(velocities.len(), 8);
+    assert_close(velocities[0], Vec2::new(1.0, 1.0));
+    assert_close(velocities[1], Vec2::new(-4.0, 0.0));
+    assert_eq!(velocities.iter().filter(|velocity| **velocity != Vec2::ZERO).count(), 2);
+    assert_eq!(field.arrow_vertices().len(), 8 * ARROW_VERTICES);
+
+    // The arrow of cell 1 points along -x from its center
+    let vertices = field.arrow_vertices().read_back(wgpu_context).unwrap();
+    let (tail, tip) = (vertices[ARROW_VERTICES], vertices[ARROW_VERTICES + 1]);
+    assert_close((tail + tip) * 0.5, Vec2::new(15.0, 5.0));
+    assert!(tip.x < tail.x && (tip.y - tail.y).abs() < 1e-4);
+    // Still cells collapse to transparent points at their center
+    let colors = field.arrow_colors().read_back(wgpu_context).unwrap();
+    assert_eq!(vertices[7 * ARROW_VERTICES], Vec2::new(35.0, 15.0));
+    assert_eq!(colors[7 * ARROW_VERTICES].w, 0.0);
+
+    // The sums of the previous update are cleared
+    field.update(wgpu_context, &mut gpu_profiler, &positions, &previous_positions, VelocityFieldParams { num_particles: 1, ..params });
+    let velocities = field.download_velocities(wgpu_context);
+    assert_close(velocities[0], Vec2::new(2.0, 0.0));
+    assert_eq!(velocities[1], Vec2::ZERO);
+    gpu_profiler.end_frame().unwrap();
+}
+
+#[test]
+fn velocity_field_merges_grid_cells_test() {
+    assert_eq!(VelocityField::cell_size_for(10.0, Vec2::new(100.0, 50.0)), 10.0);
+    let cell_size = VelocityField::cell_size_for(2.0, Vec2::new(1000.0, 500.0));
+    assert_eq!(cell_size % 2.0, 0.0);
+    assert!(1000.0 / cell_size <= MAX_ARROWS_PER_SIDE);
+}
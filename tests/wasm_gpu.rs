@@ -0,0 +1,91 @@
+//! Sorting and scanning primitives on WebGPU, run in a headless browser:
+//! `wasm-pack test --headless --chrome -- --test wasm_gpu`
+#![cfg(target_arch = "wasm32")]
+
+use std::num::NonZeroU32;
+use wasm_bindgen_test::*;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::utils::gpu_buffer::GpuBuffer;
+use game_engine::utils::prefix_sum::prefix_sum::PrefixSum;
+use game_engine::utils::radix_sort::GPUSorter;
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+/// The kernels fall back to the WebGPU baseline when the browser has no push constants or subgroups,
+/// see `DeviceCapabilities`. A browser without WebGPU fails the tests, run them where it is enabled.
+async fn setup() -> WgpuContext {
+    WgpuContext::new_for_test().await
+        .unwrap_or_else(|e| panic!("No suitable WebGPU device, enable WebGPU in the test browser: {:?}", e))
+}
+
+#[wasm_bindgen_test]
+async fn wasm_sort_test() {
+    let wgpu_context = setup().await;
+    let wgpu_context = &wgpu_context;
+
+    let n = 25006;
+    let scrambled_data: Vec<u32> = (0..n).rev().collect();
+    let keys = GpuBuffer::new(wgpu_context, scrambled_data.clone(), wgpu::BufferUsages::STORAGE);
+    let payload = GpuBuffer::new(wgpu_context, scrambled_data, wgpu::BufferUsages::STORAGE);
+    let sorter = GPUSorter::new(wgpu_context, NonZeroU32::new(n).unwrap(), &keys, &payload);
+
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Wasm sort test"),
+    });
+    sorter.sort(&mut encoder, None);
+    wgpu_context.get_queue().submit([encoder.finish()]);
+
+    let sorted_data: Vec<u32> = (0..n).collect();
+    assert_eq!(keys.read_back_async(wgpu_context).await.unwrap(), sorted_data);
+    assert_eq!(payload.read_back_async(wgpu_context).await.unwrap(), sorted_data);
+}
+
+#[wasm_bindgen_test]
+async fn wasm_sort_small_sized_array_test() {
+    let wgpu_context = setup().await;
+    let wgpu_context = &wgpu_context;
+
+    let data: Vec<u32> = vec![357_000_000, 90_000, 257, 2, 20_000_000, 1, 30_000, 65611];
+    let keys = GpuBuffer::new(wgpu_context, data.clone(), wgpu::BufferUsages::STORAGE);
+    let payload = GpuBuffer::new(wgpu_context, (0..data.len() as u32).collect(), wgpu::BufferUsages::STORAGE);
+    let sorter = GPUSorter::new(wgpu_context, NonZeroU32::new(data.len() as u32).unwrap(), &keys, &payload);
+
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Wasm small sort test"),
+    });
+    sorter.sort(&mut encoder, None);
+    wgpu_context.get_queue().submit([encoder.finish()]);
+
+    let mut expected_keys = data.clone();
+    expected_keys.sort();
+    let sorted_keys = keys.read_back_async(wgpu_context).await.unwrap();
+    let sorted_payload = payload.read_back_async(wgpu_context).await.unwrap();
+    assert_eq!(sorted_keys, expected_keys);
+    // The payload follows its key
+    for (key, original_index) in sorted_keys.iter().zip(&sorted_payload) {
+        assert_eq!(data[*original_index as usize], *key);
+    }
+}
+
+#[wasm_bindgen_test]
+async fn wasm_inclusive_prefix_sum_test() {
+    let wgpu_context = setup().await;
+    let wgpu_context = &wgpu_context;
+
+    let n = 81_920;
+    let original_values: Vec<u32> = (0..n).rev().collect();
+    let buffer = GpuBuffer::new(wgpu_context, original_values.clone(), wgpu::BufferUsages::STORAGE);
+    let prefix_sum = PrefixSum::new(wgpu_context, &buffer);
+
+    let mut encoder = wgpu_context.get_device().create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Wasm prefix sum test"),
+    });
+    prefix_sum.execute(wgpu_context, &mut encoder, n);
+    wgpu_context.get_queue().submit([encoder.finish()]);
+
+    let expected: Vec<u32> = original_values.iter().scan(0u32, |sum, value| {
+        *sum = sum.wrapping_add(*value);
+        Some(*sum)
+    }).collect();
+    assert_eq!(buffer.read_back_async(wgpu_context).await.unwrap(), expected);
+}
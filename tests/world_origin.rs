@@ -0,0 +1,83 @@
+mod common;
+
+use glam::{UVec2, Vec2};
+use game_engine::grid::grid::Grid;
+use game_engine::grid::morton;
+use game_engine::renderer::wgpu_context::WgpuContext;
+use game_engine::simulation::Simulation;
+use game_engine::utils::profiler::{GpuProfiler, GpuProfilerSettings};
+
+const UNUSED_CELL_ID: u32 = 0xffffffff;
+const RADIUS: f32 = 2.0;
+// The test world is 1920x1080, centered on (0, 0)
+const WORLD_ORIGIN: Vec2 = Vec2::new(-960.0, -540.0);
+
+fn create_simulation(wgpu_context: &WgpuContext, positions: Vec<Vec2>) -> Simulation {
+    let mut simulation = common::create_test_simulation(wgpu_context, positions, RADIUS);
+    // Moves the grid and the collision cells with the particles
+    simulation.set_world_origin(WORLD_ORIGIN);
+    simulation
+}
+
+#[test]
+fn negative_positions_cell_ids_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let cell_size = Grid::compute_cell_size(RADIUS);
+    // Centered in their cells, all of them at negative x or y
+    let cells = [UVec2::new(10, 20), UVec2::new(200, 100), UVec2::new(300, 50)];
+    let positions: Vec<Vec2> = cells.iter().map(|cell| WORLD_ORIGIN + (cell.as_vec2() + 0.5) * cell_size).collect();
+    let mut simulation = create_simulation(wgpu_context, positions.clone());
+    assert_eq!(simulation.grid().origin(), WORLD_ORIGIN);
+
+    let mut encoder = wgpu_context.get_device().create_command_encoder(
+        &wgpu::CommandEncoderDescriptor { label: Some("World Origin Test Encoder") }
+    );
+    simulation.grid().build_cell_ids(&mut encoder);
+    wgpu_context.get_queue().submit(std::iter::once(encoder.finish()));
+
+    let cell_ids = simulation.grid_mut().download_cell_ids(wgpu_context).unwrap();
+    for (i, position) in positions.iter().enumerate() {
+        assert_eq!(morton::cell_coord(*position - WORLD_ORIGIN, cell_size), cells[i]);
+        // Far from the cell borders: the home cell only, relative to the origin
+        assert_eq!(cell_ids[i * 4], morton::encode(cells[i]), "particle {i}");
+        assert!(cell_ids[i * 4 + 1..i * 4 + 4].iter().all(|&cell_id| cell_id == UNUSED_CELL_ID), "particle {i}");
+    }
+}
+
+#[test]
+fn negative_positions_collide_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    // Overlapping pair on both sides of (0, 0), the corner of four cells without an origin
+    let mut simulation = create_simulation(wgpu_context, vec![Vec2::new(-1.0, -0.5), Vec2::new(1.0, 0.5)]);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    for _ in 0..10 {
+        simulation.step(wgpu_context, &mut gpu_profiler, 0.01, None);
+        gpu_profiler.end_frame().unwrap();
+    }
+
+    let positions = simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().to_vec();
+    // Pushed apart
+    assert!(positions[0].distance(positions[1]) > Vec2::new(2.0, 1.0).length() + 0.5, "{positions:?}");
+}
+
+#[test]
+fn integration_bounds_follow_the_origin_test() {
+    let setup = pollster::block_on(common::setup());
+    let wgpu_context = &setup.wgpu_context;
+    let mut simulation = create_simulation(wgpu_context, vec![Vec2::new(-2000.0, 1000.0), Vec2::new(-900.0, -500.0)]);
+    let mut gpu_profiler = GpuProfiler::new(wgpu_context.get_device(), GpuProfilerSettings::default()).unwrap();
+
+    simulation.step(wgpu_context, &mut gpu_profiler, 0.01, None);
+    gpu_profiler.end_frame().unwrap();
+
+    let positions = simulation.particles_mut().download_particle_buffers(wgpu_context).current_positions.data().to_vec();
+    let mut positions_by_x = positions.clone();
+    positions_by_x.sort_by(|a, b| a.x.total_cmp(&b.x));
+    // Clamped into [origin, origin + world_size] instead of [0, world_size]
+    assert!((positions_by_x[0] - Vec2::new(-960.0 + RADIUS, 540.0 - RADIUS)).length() < 1e-3, "{positions:?}");
+    // Inside the world, left where it was
+    assert!((positions_by_x[1] - Vec2::new(-900.0, -500.0)).length() < 1e-3, "{positions:?}");
+}